@@ -1,5 +1,5 @@
-use crate::models::{ContentStatus, ContentType};
-use crate::services::{content, settings, tags};
+use crate::models::{ContentType, ContentWithTags};
+use crate::services::{content, content_types, i18n, seo, settings, social_card, tags};
 use crate::web::AppState;
 use crate::Config;
 use anyhow::Result;
@@ -30,12 +30,14 @@ pub async fn run(config_path: &Path, output_dir: &Path, base_url: Option<String>
     tracing::info!("Building static site to {}", output_dir.display());
 
     build_index(&state, output_dir, &site_url)?;
-    build_posts(&state, output_dir)?;
-    build_pages(&state, output_dir)?;
+    build_posts(&state, output_dir, &site_url)?;
+    build_pages(&state, output_dir, &site_url)?;
+    build_entries(&state, output_dir, &site_url)?;
     build_tags(&state, output_dir)?;
     build_search(&state, output_dir)?;
     build_feeds(&state, output_dir, &site_url)?;
     copy_media(&config, output_dir)?;
+    write_assets(&state, output_dir)?;
 
     tracing::info!("Static site build complete");
     Ok(())
@@ -52,26 +54,81 @@ fn make_context(state: &AppState) -> Context {
     if config.theme.custom.has_customizations() {
         ctx.insert("theme_custom_css", &config.theme.custom.to_css_variables());
     }
+    let default_language = config.site.language.clone();
+    drop(config);
+    state.localize(&mut ctx, &default_language, &[]);
     ctx
 }
 
+/// Context for a page in `lang` (`None` for the default language).
+fn make_localized_context(
+    state: &AppState,
+    lang: Option<&str>,
+    alternates: &[(String, String)],
+) -> Context {
+    let mut ctx = make_context(state);
+    let lang = lang
+        .map(str::to_string)
+        .unwrap_or_else(|| state.config().site.language.clone());
+    state.localize(&mut ctx, &lang, alternates);
+    ctx
+}
+
+/// Every language the site is built in: `None` for the default, then each
+/// additional language.
+fn build_languages(state: &AppState) -> Vec<Option<String>> {
+    std::iter::once(None)
+        .chain(state.config().i18n.languages.iter().cloned().map(Some))
+        .collect()
+}
+
+/// Home page path in each language, when the site has more than one.
+fn home_alternates(state: &AppState) -> Vec<(String, String)> {
+    let config = state.config();
+    if config.i18n.languages.is_empty() {
+        return Vec::new();
+    }
+    i18n::site_languages(&config.site.language, &config.i18n)
+        .into_iter()
+        .map(|code| {
+            let prefix = i18n::language_prefix(Some(&code), &config.site.language);
+            let path = if prefix.is_empty() { "/".to_string() } else { prefix };
+            (code, path)
+        })
+        .collect()
+}
+
 fn build_index(state: &AppState, output_dir: &Path, _site_url: &str) -> Result<()> {
+    for lang in build_languages(state) {
+        let root = match &lang {
+            Some(code) => output_dir.join(code),
+            None => output_dir.to_path_buf(),
+        };
+        build_language_index(state, &root, lang.as_deref())?;
+    }
+    Ok(())
+}
+
+fn build_language_index(state: &AppState, output_dir: &Path, lang: Option<&str>) -> Result<()> {
     let posts_per_page = state.config().content.posts_per_page;
-    let total = content::count_content(
-        &state.db,
-        Some(ContentType::Post),
-        Some(ContentStatus::Published),
-    )?;
+    let total = content::count_published_content_in_language(&state.db, ContentType::Post, lang)?;
     let total_pages = ((total as usize) + posts_per_page - 1) / posts_per_page;
     let homepage_settings = settings::get_homepage_settings(&state.db).unwrap_or_default();
-    let pages = content::list_published_content(&state.db, ContentType::Page, 100, 0)?;
+    let pages =
+        content::list_published_content_in_language(&state.db, ContentType::Page, lang, 100, 0)?;
+    let alternates = home_alternates(state);
 
     for page_num in 1..=total_pages.max(1) {
         let offset = (page_num - 1) * posts_per_page;
-        let posts =
-            content::list_published_content(&state.db, ContentType::Post, posts_per_page, offset)?;
-
-        let mut ctx = make_context(state);
+        let posts = content::list_published_content_in_language(
+            &state.db,
+            ContentType::Post,
+            lang,
+            posts_per_page,
+            offset,
+        )?;
+
+        let mut ctx = make_localized_context(state, lang, &alternates);
         ctx.insert("posts", &posts);
         ctx.insert("pages", &pages);
         ctx.insert("homepage", &homepage_settings);
@@ -85,6 +142,7 @@ fn build_index(state: &AppState, output_dir: &Path, _site_url: &str) -> Result<(
         let html = state.templates.render("public/index.html", &ctx)?;
 
         if page_num == 1 {
+            fs::create_dir_all(output_dir)?;
             fs::write(output_dir.join("index.html"), &html)?;
             fs::create_dir_all(output_dir.join("posts"))?;
             fs::write(output_dir.join("posts").join("index.html"), &html)?;
@@ -100,42 +158,79 @@ fn build_index(state: &AppState, output_dir: &Path, _site_url: &str) -> Result<(
         }
     }
 
-    tracing::info!("Built index with {} page(s)", total_pages.max(1));
+    tracing::info!(
+        "Built {} index with {} page(s)",
+        lang.unwrap_or("default language"),
+        total_pages.max(1)
+    );
     Ok(())
 }
 
-fn build_posts(state: &AppState, output_dir: &Path) -> Result<()> {
+fn build_posts(state: &AppState, output_dir: &Path, site_url: &str) -> Result<()> {
     let posts =
         content::list_published_content(&state.db, ContentType::Post, MAX_BUILD_CONTENT, 0)?;
-    let posts_dir = output_dir.join("posts");
-    fs::create_dir_all(&posts_dir)?;
+    let config = state.config().clone();
 
     for post in &posts {
-        let mut ctx = make_context(state);
+        let alternates = i18n::content_alternates(
+            &state.db,
+            &post.content,
+            &config.site.language,
+            &config.i18n,
+        );
+        let mut ctx = make_localized_context(state, Some(&alternates[0].0), &alternates);
+        ctx.insert("seo", &state.content_seo(post, site_url, &alternates[0].1));
         ctx.insert("content", &post);
 
         let html = state.templates.render("public/post.html", &ctx)?;
 
-        let post_dir = posts_dir.join(&post.content.slug);
+        // Posts in additional languages live under their prefix, e.g. fr/posts/<slug>
+        let post_dir = output_dir.join(alternates[0].1.trim_start_matches('/'));
         fs::create_dir_all(&post_dir)?;
         fs::write(post_dir.join("index.html"), html)?;
+
+        if config.social_cards.enabled && social_card::needs_card(post) {
+            let card = social_card::cached_card(
+                Path::new(&config.media.upload_dir),
+                &config.theme,
+                &config.site.title,
+                post,
+            )?;
+            let dest = output_dir.join(social_card::card_path(&post.content.slug).trim_start_matches('/'));
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(card, dest)?;
+        }
     }
 
     tracing::info!("Built {} posts", posts.len());
     Ok(())
 }
 
-fn build_pages(state: &AppState, output_dir: &Path) -> Result<()> {
+fn build_pages(state: &AppState, output_dir: &Path, site_url: &str) -> Result<()> {
     let pages =
         content::list_published_content(&state.db, ContentType::Page, MAX_BUILD_CONTENT, 0)?;
+    let config = state.config().clone();
 
     for page in &pages {
-        let mut ctx = make_context(state);
+        let alternates = i18n::content_alternates(
+            &state.db,
+            &page.content,
+            &config.site.language,
+            &config.i18n,
+        );
+        let prefix = i18n::language_prefix(Some(&alternates[0].0), &config.site.language);
+        let path = format!("{}/{}", prefix, page.content.slug);
+        let mut ctx = make_localized_context(state, Some(&alternates[0].0), &alternates);
+        ctx.insert("seo", &state.content_seo(page, site_url, &path));
         ctx.insert("content", &page);
 
         let html = state.templates.render("public/page.html", &ctx)?;
 
-        let page_dir = output_dir.join(&page.content.slug);
+        let page_dir = output_dir
+            .join(prefix.trim_start_matches('/'))
+            .join(&page.content.slug);
         fs::create_dir_all(&page_dir)?;
         fs::write(page_dir.join("index.html"), html)?;
     }
@@ -144,6 +239,67 @@ fn build_pages(state: &AppState, output_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Custom content types: a listing of every entry at <prefix>/, the entries
+/// at <prefix>/<slug>/ and an RSS feed at <prefix>/feed.xml.
+fn build_entries(state: &AppState, output_dir: &Path, site_url: &str) -> Result<()> {
+    let config = state.config().clone();
+
+    for def in &config.content_types {
+        let items =
+            content::list_published_entries(&state.db, &def.name, MAX_BUILD_CONTENT, 0)?;
+        let type_dir = output_dir.join(def.prefix());
+        fs::create_dir_all(&type_dir)?;
+
+        let type_ctx = serde_json::json!({
+            "name": def.name,
+            "label": def.label(),
+            "prefix": def.prefix(),
+            "fields": def.fields,
+        });
+        let views: Vec<_> = items
+            .iter()
+            .map(|item| {
+                content_types::entry_view(&state.db, &config.content_types, def, item.clone(), |c| {
+                    i18n::localized_content_path(c, &config.site.language, &config.i18n)
+                })
+            })
+            .collect();
+
+        for (item, view) in items.iter().zip(&views) {
+            let mut ctx = make_context(state);
+            ctx.insert(
+                "seo",
+                &state.content_seo(item, site_url, &def.entry_path(&item.content.slug)),
+            );
+            ctx.insert("content_type", &type_ctx);
+            ctx.insert("content", item);
+            ctx.insert("entry", view);
+            let html = state
+                .templates
+                .render(&content_types::detail_template_name(def), &ctx)?;
+            let entry_dir = type_dir.join(&item.content.slug);
+            fs::create_dir_all(&entry_dir)?;
+            fs::write(entry_dir.join("index.html"), html)?;
+        }
+
+        let mut ctx = make_context(state);
+        ctx.insert("content_type", &type_ctx);
+        ctx.insert("entries", &views);
+        ctx.insert("page", &1);
+        ctx.insert("total_pages", &1);
+        let html = state
+            .templates
+            .render(&content_types::list_template_name(def), &ctx)?;
+        fs::write(type_dir.join("index.html"), html)?;
+
+        let rss = generate_entries_rss(&config.site, site_url, def, &items);
+        fs::write(type_dir.join("feed.xml"), rss)?;
+
+        tracing::info!("Built {} {} entries", items.len(), def.name);
+    }
+    Ok(())
+}
+
 fn build_tags(state: &AppState, output_dir: &Path) -> Result<()> {
     let tags_dir = output_dir.join("tags");
     fs::create_dir_all(&tags_dir)?;
@@ -278,14 +434,28 @@ fn generate_static_search_page(state: &AppState) -> Result<String> {
 }
 
 fn build_feeds(state: &AppState, output_dir: &Path, site_url: &str) -> Result<()> {
-    let posts = content::list_published_content(&state.db, ContentType::Post, 20, 0)?;
-    let config = state.config();
-
-    let rss = generate_rss(&config.site, site_url, &posts);
-    fs::write(output_dir.join("feed.xml"), rss)?;
-
-    let json_feed = generate_json_feed(&config.site, site_url, &posts);
-    fs::write(output_dir.join("feed.json"), json_feed)?;
+    let config = state.config().clone();
+
+    for lang in build_languages(state) {
+        let posts = content::list_published_content_in_language(
+            &state.db,
+            ContentType::Post,
+            lang.as_deref(),
+            20,
+            0,
+        )?;
+        let root = match &lang {
+            Some(code) => output_dir.join(code),
+            None => output_dir.to_path_buf(),
+        };
+        fs::create_dir_all(&root)?;
+
+        let rss = generate_rss(&config.site, site_url, lang.as_deref(), &posts);
+        fs::write(root.join("feed.xml"), rss)?;
+
+        let json_feed = generate_json_feed(&config.site, site_url, lang.as_deref(), &posts);
+        fs::write(root.join("feed.json"), json_feed)?;
+    }
 
     let sitemap = generate_sitemap(state, site_url)?;
     fs::write(output_dir.join("sitemap.xml"), sitemap)?;
@@ -297,8 +467,10 @@ fn build_feeds(state: &AppState, output_dir: &Path, site_url: &str) -> Result<()
 fn generate_rss(
     site: &crate::config::SiteConfig,
     site_url: &str,
+    lang: Option<&str>,
     posts: &[crate::models::ContentWithTags],
 ) -> String {
+    let prefix = i18n::language_prefix(lang, &site.language);
     let mut items = String::new();
     for post in posts {
         let pub_date = post
@@ -310,16 +482,18 @@ fn generate_rss(
         items.push_str(&format!(
             r#"<item>
 <title>{}</title>
-<link>{}/posts/{}</link>
-<guid>{}/posts/{}</guid>
+<link>{}{}/posts/{}</link>
+<guid>{}{}/posts/{}</guid>
 <pubDate>{}</pubDate>
 <description><![CDATA[{}]]></description>
 </item>
 "#,
             xml_escape(&post.content.title),
             site_url,
+            prefix,
             post.content.slug,
             site_url,
+            prefix,
             post.content.slug,
             pub_date,
             excerpt
@@ -331,18 +505,78 @@ fn generate_rss(
 <rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
 <channel>
 <title>{}</title>
-<link>{}</link>
+<link>{}{}</link>
 <description>{}</description>
 <language>{}</language>
-<atom:link href="{}/feed.xml" rel="self" type="application/rss+xml"/>
+<atom:link href="{}{}/feed.xml" rel="self" type="application/rss+xml"/>
 {}
 </channel>
 </rss>"#,
         xml_escape(&site.title),
         site_url,
+        prefix,
         xml_escape(&site.description),
+        lang.unwrap_or(&site.language),
+        site_url,
+        prefix,
+        items
+    )
+}
+
+fn generate_entries_rss(
+    site: &crate::config::SiteConfig,
+    site_url: &str,
+    def: &crate::config::ContentTypeConfig,
+    entries: &[crate::models::ContentWithTags],
+) -> String {
+    let mut items = String::new();
+    for entry in entries.iter().take(20) {
+        let pub_date = entry
+            .content
+            .published_at
+            .as_ref()
+            .unwrap_or(&entry.content.created_at);
+        let path = def.entry_path(&entry.content.slug);
+        items.push_str(&format!(
+            r#"<item>
+<title>{}</title>
+<link>{}{}</link>
+<guid>{}{}</guid>
+<pubDate>{}</pubDate>
+<description><![CDATA[{}]]></description>
+</item>
+"#,
+            xml_escape(&entry.content.title),
+            site_url,
+            path,
+            site_url,
+            path,
+            pub_date,
+            entry.content.excerpt.as_deref().unwrap_or("")
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>{} — {}</title>
+<link>{}/{}</link>
+<description>{} on {}</description>
+<language>{}</language>
+<atom:link href="{}/{}/feed.xml" rel="self" type="application/rss+xml"/>
+{}
+</channel>
+</rss>"#,
+        xml_escape(def.label()),
+        xml_escape(&site.title),
+        site_url,
+        def.prefix(),
+        xml_escape(def.label()),
+        xml_escape(&site.title),
         site.language,
         site_url,
+        def.prefix(),
         items
     )
 }
@@ -350,14 +584,16 @@ fn generate_rss(
 fn generate_json_feed(
     site: &crate::config::SiteConfig,
     site_url: &str,
+    lang: Option<&str>,
     posts: &[crate::models::ContentWithTags],
 ) -> String {
+    let prefix = i18n::language_prefix(lang, &site.language);
     let items: Vec<serde_json::Value> = posts
         .iter()
         .map(|post| {
             serde_json::json!({
-                "id": format!("{}/posts/{}", site_url, post.content.slug),
-                "url": format!("{}/posts/{}", site_url, post.content.slug),
+                "id": format!("{}{}/posts/{}", site_url, prefix, post.content.slug),
+                "url": format!("{}{}/posts/{}", site_url, prefix, post.content.slug),
                 "title": post.content.title,
                 "content_html": post.content.body_html,
                 "summary": post.content.excerpt,
@@ -370,40 +606,62 @@ fn generate_json_feed(
     serde_json::json!({
         "version": "https://jsonfeed.org/version/1.1",
         "title": site.title,
-        "home_page_url": site_url,
-        "feed_url": format!("{}/feed.json", site_url),
+        "home_page_url": format!("{}{}", site_url, prefix),
+        "feed_url": format!("{}{}/feed.json", site_url, prefix),
         "description": site.description,
-        "language": site.language,
+        "language": lang.unwrap_or(&site.language),
         "items": items
     })
     .to_string()
 }
 
 fn generate_sitemap(state: &AppState, site_url: &str) -> Result<String> {
+    let config = state.config().clone();
+    let default_language = config.site.language.as_str();
+    let indexable = |item: &ContentWithTags| !seo::is_noindex(&item.content.metadata);
     let mut urls = String::new();
 
-    urls.push_str(&format!(
-        "<url><loc>{}</loc><changefreq>daily</changefreq><priority>1.0</priority></url>\n",
-        site_url
-    ));
+    let homes = home_alternates(state);
+    if homes.is_empty() {
+        urls.push_str(&format!(
+            "<url><loc>{}</loc><changefreq>daily</changefreq><priority>1.0</priority></url>\n",
+            site_url
+        ));
+    }
+    for (_, path) in &homes {
+        urls.push_str(&format!(
+            "<url><loc>{}{}</loc>{}<changefreq>daily</changefreq><priority>1.0</priority></url>\n",
+            site_url,
+            path.trim_end_matches('/'),
+            sitemap_alternates(site_url, &homes)
+        ));
+    }
 
     let posts =
         content::list_published_content(&state.db, ContentType::Post, MAX_BUILD_CONTENT, 0)?;
-    for post in posts {
+    for post in posts.iter().filter(|p| indexable(p)) {
+        let alternates =
+            i18n::content_alternates(&state.db, &post.content, default_language, &config.i18n);
         urls.push_str(&format!(
-            "<url><loc>{}/posts/{}</loc><lastmod>{}</lastmod><changefreq>weekly</changefreq></url>\n",
+            "<url><loc>{}{}</loc>{}<lastmod>{}</lastmod><changefreq>weekly</changefreq></url>\n",
             site_url,
-            post.content.slug,
+            alternates[0].1,
+            sitemap_alternates(site_url, &alternates),
             post.content.updated_at.split('T').next().unwrap_or(&post.content.updated_at)
         ));
     }
 
     let pages =
         content::list_published_content(&state.db, ContentType::Page, MAX_BUILD_CONTENT, 0)?;
-    for page in pages {
+    for page in pages.iter().filter(|p| indexable(p)) {
+        let prefix = i18n::language_prefix(
+            i18n::route_language(page.content.language.as_deref(), &config.i18n),
+            default_language,
+        );
         urls.push_str(&format!(
-            "<url><loc>{}/{}</loc><lastmod>{}</lastmod><changefreq>monthly</changefreq></url>\n",
+            "<url><loc>{}{}/{}</loc><lastmod>{}</lastmod><changefreq>monthly</changefreq></url>\n",
             site_url,
+            prefix,
             page.content.slug,
             page.content
                 .updated_at
@@ -413,6 +671,29 @@ fn generate_sitemap(state: &AppState, site_url: &str) -> Result<String> {
         ));
     }
 
+    for def in &config.content_types {
+        urls.push_str(&format!(
+            "<url><loc>{}/{}</loc><changefreq>weekly</changefreq></url>\n",
+            site_url,
+            def.prefix()
+        ));
+        let entries =
+            content::list_published_entries(&state.db, &def.name, MAX_BUILD_CONTENT, 0)?;
+        for entry in entries.iter().filter(|e| indexable(e)) {
+            urls.push_str(&format!(
+                "<url><loc>{}{}</loc><lastmod>{}</lastmod><changefreq>monthly</changefreq></url>\n",
+                site_url,
+                def.entry_path(&entry.content.slug),
+                entry
+                    .content
+                    .updated_at
+                    .split('T')
+                    .next()
+                    .unwrap_or(&entry.content.updated_at)
+            ));
+        }
+    }
+
     let all_tags = tags::list_tags_with_counts(&state.db)?;
     urls.push_str(&format!(
         "<url><loc>{}/tags</loc><changefreq>weekly</changefreq></url>\n",
@@ -427,13 +708,31 @@ fn generate_sitemap(state: &AppState, site_url: &str) -> Result<String> {
 
     Ok(format!(
         r#"<?xml version="1.0" encoding="UTF-8"?>
-<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" xmlns:xhtml="http://www.w3.org/1999/xhtml">
 {}
 </urlset>"#,
         urls
     ))
 }
 
+/// `xhtml:link` alternates for a sitemap entry that exists in several languages.
+fn sitemap_alternates(site_url: &str, alternates: &[(String, String)]) -> String {
+    if alternates.len() < 2 {
+        return String::new();
+    }
+    alternates
+        .iter()
+        .map(|(code, path)| {
+            format!(
+                "<xhtml:link rel=\"alternate\" hreflang=\"{}\" href=\"{}{}\"/>",
+                code,
+                site_url,
+                path.trim_end_matches('/')
+            )
+        })
+        .collect()
+}
+
 fn copy_media(config: &Config, output_dir: &Path) -> Result<()> {
     let media_src = Path::new(&config.media.upload_dir);
     if !media_src.exists() {
@@ -459,6 +758,21 @@ fn copy_media(config: &Config, output_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Scripts and stylesheets referenced by the public base template.
+fn write_assets(state: &AppState, output_dir: &Path) -> Result<()> {
+    let js_dir = output_dir.join("js");
+    fs::create_dir_all(&js_dir)?;
+    for name in ["theme.js", "embeds.js"] {
+        if let Some(source) = state.static_assets.get(name) {
+            fs::write(js_dir.join(name), source)?;
+        }
+    }
+    let css_dir = output_dir.join("css");
+    fs::create_dir_all(&css_dir)?;
+    fs::write(css_dir.join("highlight.css"), &state.highlight_css)?;
+    Ok(())
+}
+
 fn xml_escape(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::services::lint::{self, CheckCounts, Severity};
+use crate::{Config, Database};
+
+pub async fn run(config_path: &Path) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let db = Database::open(&config.database.path)?;
+    db.migrate()?;
+
+    println!("Checking all content...");
+    let reports = lint::check_site(&db, &config)?;
+
+    let mut total = CheckCounts::default();
+    for report in &reports {
+        println!(
+            "\n  {} \x1b[1m{}\x1b[0m ({})",
+            report.content_type, report.slug, report.title
+        );
+        for issue in &report.issues {
+            let label = match issue.severity {
+                Severity::Error => "\x1b[31merror\x1b[0m  ",
+                Severity::Warning => "\x1b[33mwarning\x1b[0m",
+            };
+            let line = issue
+                .line
+                .map(|l| format!("line {}: ", l))
+                .unwrap_or_default();
+            println!("    {}  {}{}  [{}]", label, line, issue.message, issue.rule);
+        }
+        let counts = CheckCounts::of(&report.issues);
+        total.errors += counts.errors;
+        total.warnings += counts.warnings;
+    }
+
+    println!(
+        "\n{} error(s), {} warning(s) in {} content item(s).",
+        total.errors,
+        total.warnings,
+        reports.len()
+    );
+    if total.errors > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
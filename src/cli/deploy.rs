@@ -1,54 +1,65 @@
-use crate::services::search;
+use super::serve::{open_site_database, spawn_site_tasks};
+use crate::services::metrics::Metrics;
 use crate::web;
-use crate::{Config, Database};
+use crate::web::listener::ListenAddr;
+use crate::Config;
 use anyhow::Result;
 use std::path::Path;
-use std::time::Duration;
+use std::sync::Arc;
 
-pub async fn run(config_path: &Path, host: &str, port: u16) -> Result<()> {
+pub async fn run(
+    config_path: &Path,
+    host: &str,
+    port: u16,
+    listen: Option<String>,
+    admin: Option<String>,
+) -> Result<()> {
     let config = Config::load(config_path)?;
-    let db = Database::open(&config.database.path)?;
+    let listen = ListenAddr::resolve(
+        listen.as_deref(),
+        config.server.listen.as_deref(),
+        host,
+        port,
+    )?;
+    let admin = admin
+        .or_else(|| config.server.admin_listen.clone())
+        .map(|addr| ListenAddr::parse(&addr))
+        .transpose()?;
+    let db = open_site_database(&config)?;
 
-    if let Ok(count) = search::rebuild_fts_index(&db) {
-        tracing::info!("Search index rebuilt: {} documents indexed", count);
-    }
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let metrics = Arc::new(Metrics::new());
+    let bg_handles = spawn_site_tasks(&config, &db, &metrics, shutdown_rx);
 
-    // Auto-backup scheduler
-    if config.backup.auto_enabled {
-        let backup_config = config.backup.clone();
-        let backup_site_config = config.clone();
-        tokio::spawn(async move {
-            let interval_secs = backup_config.interval_hours.max(1) * 3600;
-            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
-            // Skip the first immediate tick
-            interval.tick().await;
-            loop {
-                interval.tick().await;
-                let backup_dir = std::path::Path::new(&backup_config.directory);
-                match crate::cli::backup::create_backup(&backup_site_config, backup_dir) {
-                    Ok(()) => {
-                        tracing::info!("Auto-backup completed successfully");
-                        let _ = crate::cli::backup::enforce_retention(
-                            backup_dir,
-                            backup_config.retention_count,
-                        );
-                    }
-                    Err(e) => {
-                        tracing::error!("Auto-backup failed: {}", e);
-                    }
-                }
-            }
-        });
-        tracing::info!(
-            "Auto-backup enabled: every {} hours, keeping {} backups in {}",
-            config.backup.interval_hours,
-            config.backup.retention_count,
-            config.backup.directory
-        );
+    match &listen {
+        ListenAddr::Tcp(addr) if config.tls.enabled => {
+            tracing::info!("Deploying in production mode at https://{}", addr)
+        }
+        listen => tracing::info!("Deploying in production mode at {}", listen),
+    }
+    match &admin {
+        Some(admin) => tracing::info!(
+            "Public routes read-only, admin served separately at {}",
+            admin
+        ),
+        None => tracing::info!("Admin routes disabled, read-only mode active"),
     }
 
-    tracing::info!("Deploying in production mode at http://{}:{}", host, port);
-    tracing::info!("Admin routes disabled, read-only mode active");
+    let result = web::serve_production(
+        &config,
+        config_path.to_path_buf(),
+        db,
+        metrics,
+        &listen,
+        admin.as_ref(),
+    )
+    .await;
+
+    // Signal all background tasks to stop
+    let _ = shutdown_tx.send(true);
+    for handle in bg_handles {
+        handle.abort();
+    }
 
-    web::serve_production(&config, config_path.to_path_buf(), host, port).await
+    result
 }
@@ -0,0 +1,78 @@
+use crate::services::deploy_config::{self, DeploySite, DeployTarget};
+use crate::services::slug;
+use crate::Config;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+pub fn run(
+    config_path: &Path,
+    target: &str,
+    output: Option<PathBuf>,
+    name: Option<String>,
+    user: String,
+    binary: Option<PathBuf>,
+) -> Result<()> {
+    let target: DeployTarget = target.parse()?;
+    let config = Config::load(config_path)?;
+    let local = url::Url::parse(&config.site.url)
+        .is_ok_and(|url| matches!(url.host_str(), Some("localhost" | "127.0.0.1")));
+    if local && target != DeployTarget::Systemd {
+        eprintln!(
+            "Warning: site.url is {}. Set it to the public URL first so the files use the right domain.",
+            config.site.url
+        );
+    }
+
+    let config_path = config_path
+        .canonicalize()
+        .with_context(|| format!("Config file not found: {}", config_path.display()))?;
+    let site_dir = config_path
+        .parent()
+        .context("Config file has no parent directory")?
+        .to_path_buf();
+    let name = match name {
+        Some(name) => slug::generate_slug(&name),
+        None => site_dir
+            .file_name()
+            .map(|n| slug::generate_slug(&n.to_string_lossy()))
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| "site".to_string()),
+    };
+    let binary = match binary {
+        Some(binary) => binary,
+        None => std::env::current_exe()?,
+    };
+
+    let site = DeploySite {
+        name,
+        site_dir,
+        binary,
+        user,
+    };
+    let files = deploy_config::generate(target, &config, &site)?;
+
+    match output {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)?;
+            for file in &files {
+                let path = dir.join(&file.name);
+                std::fs::write(&path, &file.contents)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                println!("Wrote {}", path.display());
+            }
+        }
+        None => {
+            for (i, file) in files.iter().enumerate() {
+                if files.len() > 1 {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("# ==> {} <==", file.name);
+                }
+                print!("{}", file.contents);
+            }
+        }
+    }
+
+    Ok(())
+}
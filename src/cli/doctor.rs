@@ -1,4 +1,5 @@
 use crate::services::database::{analyze_database, get_database_stats, run_integrity_check};
+use crate::web::listener::ListenAddr;
 use crate::Config;
 use crate::Database;
 use anyhow::Result;
@@ -328,28 +329,130 @@ pub async fn run(config_path: &Path) -> Result<()> {
         });
     }
 
-    // 9. Port availability (uses configured host:port)
-    {
-        let test_addr = format!("{}:{}", config.server.host, config.server.port);
-        match std::net::TcpListener::bind(&test_addr) {
-            Ok(_listener) => {
-                results.push(CheckResult {
-                    name: format!("Port ({})", config.server.port),
-                    status: CheckStatus::Ok,
-                    detail: format!("{} is available", test_addr),
-                });
-            }
-            Err(_) => {
-                results.push(CheckResult {
-                    name: format!("Port ({})", config.server.port),
-                    status: CheckStatus::Warn,
-                    detail: format!("{} is in use. Use --port to specify an alternative", test_addr),
-                });
+    // 9. Port availability (uses server.listen, else configured host:port)
+    match config.server.listen.as_deref().map(ListenAddr::parse) {
+        #[cfg(unix)]
+        Some(Ok(ListenAddr::Unix(path))) => {
+            let in_use = std::os::unix::net::UnixStream::connect(&path).is_ok();
+            results.push(CheckResult {
+                name: "Listen".into(),
+                status: if in_use {
+                    CheckStatus::Warn
+                } else {
+                    CheckStatus::Ok
+                },
+                detail: if in_use {
+                    format!("unix:{} is in use by a running server", path.display())
+                } else {
+                    format!("unix:{} is available", path.display())
+                },
+            });
+        }
+        Some(Ok(ListenAddr::Systemd(_))) => {
+            results.push(CheckResult {
+                name: "Listen".into(),
+                status: CheckStatus::Ok,
+                detail: "Socket is passed in by systemd socket activation".into(),
+            });
+        }
+        Some(Err(e)) => {
+            results.push(CheckResult {
+                name: "Listen".into(),
+                status: CheckStatus::Fail,
+                detail: e.to_string(),
+            });
+        }
+        listen => {
+            let test_addr = match listen {
+                Some(Ok(ListenAddr::Tcp(addr))) => addr,
+                _ => format!("{}:{}", config.server.host, config.server.port),
+            };
+            match std::net::TcpListener::bind(&test_addr) {
+                Ok(_listener) => {
+                    results.push(CheckResult {
+                        name: format!("Port ({})", test_addr),
+                        status: CheckStatus::Ok,
+                        detail: format!("{} is available", test_addr),
+                    });
+                }
+                Err(_) => {
+                    results.push(CheckResult {
+                        name: format!("Port ({})", test_addr),
+                        status: CheckStatus::Warn,
+                        detail: format!(
+                            "{} is in use. Use --port to specify an alternative",
+                            test_addr
+                        ),
+                    });
+                }
             }
         }
     }
 
-    // 10. Database stats (INFO only)
+    // 10. TLS certificate expiry (when pebble deploy serves HTTPS itself)
+    #[cfg(feature = "tls")]
+    if config.tls.enabled {
+        let tls = &config.tls;
+        let cert_path = match &tls.cert_file {
+            Some(cert_file) => std::path::PathBuf::from(cert_file),
+            None => crate::services::acme::Storage::new(&tls.storage_dir).cert_path(),
+        };
+        let warn_days = i64::from(tls.renew_days).max(14);
+        let (status, detail) = match std::fs::read(&cert_path) {
+            Err(_) if tls.uses_acme() => (
+                CheckStatus::Warn,
+                format!(
+                    "No certificate yet; one is requested from {} on startup",
+                    tls.acme_directory
+                ),
+            ),
+            Err(e) => (
+                CheckStatus::Fail,
+                format!("Cannot read {}: {}", cert_path.display(), e),
+            ),
+            Ok(pem) => match crate::web::tls::certificate_expiry(&pem) {
+                Err(e) => (
+                    CheckStatus::Fail,
+                    format!("{}: {}", cert_path.display(), e),
+                ),
+                Ok(expires) => {
+                    let days = (expires - chrono::Utc::now()).num_days();
+                    let date = expires.format("%Y-%m-%d");
+                    if expires <= chrono::Utc::now() {
+                        (CheckStatus::Fail, format!("Expired on {}", date))
+                    } else if days < warn_days && !tls.uses_acme() {
+                        (
+                            CheckStatus::Warn,
+                            format!("Expires on {} ({} days left); renew it soon", date, days),
+                        )
+                    } else if days < warn_days {
+                        (
+                            CheckStatus::Warn,
+                            format!(
+                                "Expires on {} ({} days left); renewal is overdue, check the server log",
+                                date, days
+                            ),
+                        )
+                    } else {
+                        (
+                            CheckStatus::Ok,
+                            format!("Valid until {} ({} days left)", date, days),
+                        )
+                    }
+                }
+            },
+        };
+        if matches!(status, CheckStatus::Fail) {
+            has_failure = true;
+        }
+        results.push(CheckResult {
+            name: "TLS certificate".into(),
+            status,
+            detail,
+        });
+    }
+
+    // 11. Database stats (INFO only)
     match get_database_stats(&db, &config.database.path) {
         Ok(stats) => {
             let total_rows: i64 = stats.tables.iter().map(|t| t.row_count).sum();
@@ -1,7 +1,8 @@
-use crate::models::{ContentStatus, ContentType};
-use crate::services::content;
+use crate::models::{Content, ContentStatus, ContentType};
+use crate::services::{comments, content};
 use crate::Config;
 use anyhow::Result;
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 
@@ -44,6 +45,8 @@ fn export_pebble(
 
     tracing::info!("Exporting {} posts and {} pages", posts.len(), pages.len());
 
+    let exported_slugs: HashSet<String> = posts.iter().map(|p| p.slug.clone()).collect();
+
     for post in posts {
         let full_content = content::get_content_by_id(db, post.id)?;
         if let Some(c) = full_content {
@@ -57,7 +60,7 @@ slug: "{}"
 status: "{}"
 published_at: {}
 created_at: "{}"
----
+{}---
 
 "#,
                 c.content.title.replace('"', r#"\""#),
@@ -69,6 +72,7 @@ created_at: "{}"
                     .map(|d| format!("\"{}\"", d))
                     .unwrap_or_else(|| "null".to_string()),
                 c.content.created_at,
+                translation_frontmatter(&c.content),
             );
 
             let content_str = format!("{}{}", frontmatter, c.content.body_markdown);
@@ -89,13 +93,14 @@ title: "{}"
 slug: "{}"
 status: "{}"
 created_at: "{}"
----
+{}---
 
 "#,
                 c.content.title.replace('"', r#"\""#),
                 c.content.slug,
                 c.content.status,
                 c.content.created_at,
+                translation_frontmatter(&c.content),
             );
 
             let content_str = format!("{}{}", frontmatter, c.content.body_markdown);
@@ -104,6 +109,50 @@ created_at: "{}"
         }
     }
 
+    for def in &config.content_types {
+        let entries = content::list_entries(db, &def.name, status, 10000, 0)?;
+        if entries.is_empty() {
+            continue;
+        }
+        let type_dir = output_dir.join("entries").join(&def.name);
+        fs::create_dir_all(&type_dir)?;
+        tracing::info!("Exporting {} {} entries", entries.len(), def.name);
+
+        for entry in entries {
+            let full_content = content::get_content_by_id(db, entry.id)?;
+            if let Some(c) = full_content {
+                let filepath = type_dir.join(format!("{}.md", c.content.slug));
+
+                // Field data is written as one line of JSON so the flat
+                // frontmatter parser on import can read it back.
+                let frontmatter = format!(
+                    r#"---
+title: "{}"
+slug: "{}"
+type: "{}"
+status: "{}"
+created_at: "{}"
+fields: {}
+---
+
+"#,
+                    c.content.title.replace('"', r#"\""#),
+                    c.content.slug,
+                    def.name,
+                    c.content.status,
+                    c.content.created_at,
+                    serde_json::to_string(&c.content.fields)?,
+                );
+
+                let content_str = format!("{}{}", frontmatter, c.content.body_markdown);
+                fs::write(&filepath, content_str)?;
+                tracing::info!("Exported: {}", filepath.display());
+            }
+        }
+    }
+
+    export_comments(db, output_dir, &exported_slugs)?;
+
     if include_media {
         copy_media(config, output_dir, "media")?;
     }
@@ -112,6 +161,47 @@ created_at: "{}"
     Ok(())
 }
 
+/// Frontmatter lines carrying the language and translation group, so that
+/// translation links survive a round trip through export and import.
+fn translation_frontmatter(content: &Content) -> String {
+    let mut lines = String::new();
+    if let Some(language) = &content.language {
+        lines.push_str(&format!("language: \"{}\"\n", language));
+    }
+    if let Some(group) = &content.translation_group {
+        lines.push_str(&format!("translation_group: \"{}\"\n", group));
+    }
+    lines
+}
+
+/// Write comments for the exported content to `comments.json`. Each entry
+/// references its post by slug so it can be re-attached on import.
+fn export_comments(
+    db: &crate::Database,
+    output_dir: &Path,
+    exported_slugs: &HashSet<String>,
+) -> Result<()> {
+    let all_comments = comments::list_all_comments(db)?;
+    let entries: Vec<serde_json::Value> = all_comments
+        .into_iter()
+        .filter(|c| exported_slugs.contains(&c.content_slug))
+        .map(|c| {
+            let mut entry = serde_json::to_value(&c.comment).unwrap_or_default();
+            entry["content_slug"] = serde_json::json!(c.content_slug);
+            entry
+        })
+        .collect();
+
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let filepath = output_dir.join("comments.json");
+    fs::write(&filepath, serde_json::to_string_pretty(&entries)?)?;
+    tracing::info!("Exported {} comments to {}", entries.len(), filepath.display());
+    Ok(())
+}
+
 fn export_hugo(
     db: &crate::Database,
     config: &Config,
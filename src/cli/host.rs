@@ -0,0 +1,205 @@
+//! `pebble registry serve-all`: every registry site served from one process
+//! and one port, routed by `Host` header.
+
+use super::serve;
+use crate::global::{normalize_domain, PebbleHome, Registry, RegistrySite, SiteStatus};
+use crate::services::metrics::Metrics;
+use crate::web::AppState;
+use crate::web::vhost::{self, HostedSite, VirtualHosts};
+use crate::Config;
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// How often the registry file is checked for added, removed or changed sites.
+const REGISTRY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+struct Host {
+    home: PebbleHome,
+    port: u16,
+    production: bool,
+    hosts: Arc<VirtualHosts>,
+    /// Loaded sites by name, with the domains they were loaded with
+    sites: BTreeMap<String, (Arc<HostedSite>, Vec<String>)>,
+    registry_modified: Option<SystemTime>,
+}
+
+pub async fn serve_all(home: PebbleHome, host: &str, port: u16, production: bool) -> Result<()> {
+    let mut registry = Registry::load(&home.registry_path)?;
+    registry.cleanup_dead_processes();
+    if let Some(other) = registry
+        .list_sites()
+        .into_iter()
+        .find(|s| s.status == SiteStatus::Hosted)
+    {
+        bail!(
+            "Sites are already hosted by another process (PID: {})",
+            other.pid.unwrap_or(0)
+        );
+    }
+
+    let mut state = Host {
+        home,
+        port,
+        production,
+        hosts: Arc::new(VirtualHosts::default()),
+        sites: BTreeMap::new(),
+        registry_modified: None,
+    };
+    state.sync(registry)?;
+
+    let addr = format!("{}:{}", host, port);
+    println!("Hosting {} site(s) on http://{}", state.sites.len(), addr);
+    for (site, _) in state.sites.values() {
+        println!("  {:<20} {}", site.name, site.hosts.join(", "));
+    }
+
+    let server = vhost::serve(state.hosts.clone(), &addr);
+    tokio::pin!(server);
+    let mut poll = tokio::time::interval(REGISTRY_POLL_INTERVAL);
+    let result = loop {
+        tokio::select! {
+            result = &mut server => break result,
+            _ = poll.tick() => {
+                if state.registry_modified != modified(&state.home) {
+                    if let Err(e) = Registry::load(&state.home.registry_path).and_then(|r| state.sync(r)) {
+                        tracing::warn!("Failed to reload the site registry: {}", e);
+                    }
+                }
+            }
+        }
+    };
+
+    state.shutdown()?;
+    result
+}
+
+impl Host {
+    /// Bring the loaded sites in line with the registry: load new sites,
+    /// drop removed ones and reload sites whose domains changed. Sites that
+    /// run as their own process (`pebble registry serve/deploy`) are skipped.
+    fn sync(&mut self, mut registry: Registry) -> Result<()> {
+        let pid = std::process::id();
+        let wanted: BTreeMap<String, RegistrySite> = registry
+            .list_sites()
+            .into_iter()
+            .filter(|s| match s.status {
+                SiteStatus::Running | SiteStatus::Deploying => false,
+                SiteStatus::Hosted => s.pid == Some(pid),
+                SiteStatus::Stopped => true,
+            })
+            .map(|s| (s.name.clone(), s.clone()))
+            .collect();
+
+        self.sites.retain(|name, (_, domains)| {
+            let keep = wanted.get(name).is_some_and(|s| &s.domains == domains);
+            if !keep {
+                tracing::info!("Unloading site '{}'", name);
+            }
+            keep
+        });
+
+        for (name, site) in &wanted {
+            if self.sites.contains_key(name) {
+                continue;
+            }
+            match self.load_site(site) {
+                Ok(hosted) => {
+                    tracing::info!("Hosting site '{}' at {}", name, hosted.hosts.join(", "));
+                    self.sites
+                        .insert(name.clone(), (hosted, site.domains.clone()));
+                }
+                Err(e) => tracing::error!("Failed to load site '{}': {:#}", name, e),
+            }
+        }
+
+        let loaded: Vec<Arc<HostedSite>> = self.sites.values().map(|(s, _)| s.clone()).collect();
+        self.hosts.set_sites(&loaded);
+
+        let mut changed = false;
+        for site in registry.list_sites() {
+            let hosted = self.sites.contains_key(&site.name);
+            let marked = site.status == SiteStatus::Hosted && site.pid == Some(pid);
+            changed |= hosted != marked;
+        }
+        if changed {
+            for name in wanted.keys() {
+                if self.sites.contains_key(name) {
+                    registry.update_site_status(
+                        name,
+                        SiteStatus::Hosted,
+                        Some(self.port),
+                        Some(pid),
+                    );
+                } else {
+                    registry.update_site_status(name, SiteStatus::Stopped, None, None);
+                }
+            }
+            registry.save(&self.home.registry_path)?;
+        }
+        self.registry_modified = modified(&self.home);
+        Ok(())
+    }
+
+    fn load_site(&self, site: &RegistrySite) -> Result<Arc<HostedSite>> {
+        let site_path = self.home.site_path(&site.name);
+        let config_path = site_path.join("pebble.toml");
+        let mut config = Config::load(&config_path)?;
+        config.resolve_paths(&site_path);
+
+        let db = serve::open_site_database(&config)?;
+        let hosts = site_hosts(site, &config);
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let metrics = Arc::new(Metrics::new());
+        let state = AppState::new(config.clone(), config_path, db.clone(), self.production)?
+            .with_metrics(metrics.clone());
+        let tasks = serve::spawn_site_tasks(&config, &db, &metrics, shutdown_rx);
+
+        Ok(Arc::new(HostedSite::new(
+            site.name.clone(),
+            hosts,
+            state,
+            shutdown_tx,
+            tasks,
+        )))
+    }
+
+    /// Stop every site and mark them stopped in the registry.
+    fn shutdown(mut self) -> Result<()> {
+        self.sites.clear();
+        self.hosts.set_sites(&[]);
+
+        let pid = std::process::id();
+        let mut registry = Registry::load(&self.home.registry_path)?;
+        let hosted: Vec<String> = registry
+            .list_sites()
+            .into_iter()
+            .filter(|s| s.status == SiteStatus::Hosted && s.pid == Some(pid))
+            .map(|s| s.name.clone())
+            .collect();
+        for name in hosted {
+            registry.update_site_status(&name, SiteStatus::Stopped, None, None);
+        }
+        registry.save(&self.home.registry_path)
+    }
+}
+
+/// Host names routed to a site: `<name>.localhost`, its registry domains and
+/// the host of its `site.url` unless that is a loopback address.
+fn site_hosts(site: &RegistrySite, config: &Config) -> Vec<String> {
+    let mut hosts = vec![format!("{}.localhost", site.name)];
+    hosts.extend(site.domains.iter().cloned());
+    if let Ok(host) = normalize_domain(&config.site.url) {
+        if !matches!(host.as_str(), "localhost" | "127.0.0.1") && !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    hosts
+}
+
+fn modified(home: &PebbleHome) -> Option<SystemTime> {
+    std::fs::metadata(&home.registry_path)
+        .and_then(|m| m.modified())
+        .ok()
+}
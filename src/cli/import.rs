@@ -1,12 +1,16 @@
-use crate::models::{ContentStatus, ContentType, CreateContent};
-use crate::services::content;
+use crate::models::{Comment, ContentStatus, ContentType, CreateContent};
+use crate::config::ContentTypeConfig;
+use crate::services::markdown::MarkdownRenderer;
+use crate::services::{comments, content, content_types, i18n};
 use crate::Config;
 use anyhow::Result;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
 pub async fn run(config_path: &Path, import_dir: &Path, overwrite: bool) -> Result<()> {
     let config = Config::load(config_path)?;
+    let renderer = MarkdownRenderer::for_site(&config)?;
     let db = crate::Database::open(&config.database.path)?;
 
     if !import_dir.exists() {
@@ -23,8 +27,10 @@ pub async fn run(config_path: &Path, import_dir: &Path, overwrite: bool) -> Resu
     if posts_dir.exists() {
         let (i, s) = import_content_dir(
             &db,
+            &renderer,
             &posts_dir,
             ContentType::Post,
+            None,
             overwrite,
             config.content.excerpt_length,
         )?;
@@ -35,8 +41,10 @@ pub async fn run(config_path: &Path, import_dir: &Path, overwrite: bool) -> Resu
     if pages_dir.exists() {
         let (i, s) = import_content_dir(
             &db,
+            &renderer,
             &pages_dir,
             ContentType::Page,
+            None,
             overwrite,
             config.content.excerpt_length,
         )?;
@@ -44,6 +52,43 @@ pub async fn run(config_path: &Path, import_dir: &Path, overwrite: bool) -> Resu
         skipped += s;
     }
 
+    let entries_dir = import_dir.join("entries");
+    if entries_dir.exists() {
+        for def in &config.content_types {
+            let type_dir = entries_dir.join(&def.name);
+            if !type_dir.exists() {
+                continue;
+            }
+            let (i, s) = import_content_dir(
+                &db,
+                &renderer,
+                &type_dir,
+                ContentType::Entry,
+                Some(def),
+                overwrite,
+                config.content.excerpt_length,
+            )?;
+            imported += i;
+            skipped += s;
+        }
+        for entry in fs::read_dir(&entries_dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if content_types::find(&config.content_types, &name).is_none() {
+                tracing::warn!(
+                    "Skipping entries/{}: no content type named '{}' in the config",
+                    name,
+                    name
+                );
+            }
+        }
+    }
+
+    let comments_file = import_dir.join("comments.json");
+    if comments_file.exists() {
+        let count = import_comments(&db, &comments_file)?;
+        tracing::info!("Imported {} comments", count);
+    }
+
     if media_dir.exists() {
         let dest_media = Path::new(&config.media.upload_dir);
         fs::create_dir_all(dest_media)?;
@@ -71,10 +116,55 @@ pub async fn run(config_path: &Path, import_dir: &Path, overwrite: bool) -> Resu
     Ok(())
 }
 
+/// Re-attach exported comments to their posts by slug. Posts that already
+/// have comments are skipped so re-running an import doesn't duplicate them.
+fn import_comments(db: &crate::Database, path: &Path) -> Result<usize> {
+    let entries: Vec<serde_json::Value> = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+    let mut content_ids: HashMap<String, Option<i64>> = HashMap::new();
+    let mut id_map: HashMap<i64, i64> = HashMap::new();
+    let mut imported = 0;
+
+    for entry in entries {
+        let slug = match entry.get("content_slug").and_then(|v| v.as_str()) {
+            Some(s) => s.to_string(),
+            None => continue,
+        };
+        let comment: Comment = match serde_json::from_value(entry) {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!("Skipping malformed comment: {}", e);
+                continue;
+            }
+        };
+
+        let content_id = *content_ids.entry(slug.clone()).or_insert_with(|| {
+            content::get_content_by_slug(db, &slug)
+                .ok()
+                .flatten()
+                .map(|c| c.content.id)
+                .filter(|id| comments::count_content_comments(db, *id).unwrap_or(0) == 0)
+        });
+        let content_id = match content_id {
+            Some(id) => id,
+            None => continue,
+        };
+
+        let parent_id = comment.parent_id.and_then(|old| id_map.get(&old).copied());
+        let new_id = comments::import_comment(db, content_id, parent_id, &comment)?;
+        id_map.insert(comment.id, new_id);
+        imported += 1;
+    }
+
+    Ok(imported)
+}
+
 fn import_content_dir(
     db: &crate::Database,
+    renderer: &MarkdownRenderer,
     dir: &Path,
     content_type: ContentType,
+    definition: Option<&ContentTypeConfig>,
     overwrite: bool,
     excerpt_length: usize,
 ) -> Result<(usize, usize)> {
@@ -86,7 +176,15 @@ fn import_content_dir(
         let path = entry.path();
 
         if path.extension().map(|e| e == "md").unwrap_or(false) {
-            match import_markdown_file(db, &path, content_type.clone(), overwrite, excerpt_length) {
+            match import_markdown_file(
+                db,
+                renderer,
+                &path,
+                content_type,
+                definition,
+                overwrite,
+                excerpt_length,
+            ) {
                 Ok(true) => imported += 1,
                 Ok(false) => skipped += 1,
                 Err(e) => {
@@ -102,8 +200,10 @@ fn import_content_dir(
 
 fn import_markdown_file(
     db: &crate::Database,
+    renderer: &MarkdownRenderer,
     path: &Path,
     content_type: ContentType,
+    definition: Option<&ContentTypeConfig>,
     overwrite: bool,
     excerpt_length: usize,
 ) -> Result<bool> {
@@ -138,6 +238,8 @@ fn import_markdown_file(
         _ => ContentStatus::Draft,
     };
 
+    let fields = definition.map(|_| entry_fields(&frontmatter));
+
     let input = CreateContent {
         title,
         slug: Some(slug.clone()),
@@ -149,6 +251,8 @@ fn import_markdown_file(
         featured_image: None,
         tags: vec![],
         metadata: None,
+        entry_type: definition.map(|def| def.name.clone()),
+        fields,
     };
 
     // Atomically check for existing content and handle overwrite
@@ -170,8 +274,10 @@ fn import_markdown_file(
     }
 
     // The unique constraint on slug will catch any race condition
-    match content::create_content(db, input, None, excerpt_length) {
-        Ok(_) => {
+    let content_types = definition.map(std::slice::from_ref).unwrap_or_default();
+    match content::create_content(db, renderer, content_types, input, None, excerpt_length) {
+        Ok(id) => {
+            apply_translation_fields(db, id, &frontmatter);
             tracing::info!("Imported: {} ({})", slug, content_type);
             Ok(true)
         }
@@ -186,6 +292,41 @@ fn import_markdown_file(
     }
 }
 
+/// Read the `fields:` JSON written by `pebble export`. `create_content` checks
+/// the values against the type's schema, so entries that no longer validate
+/// (a removed select option, a reference to missing content) are skipped.
+fn entry_fields(frontmatter: &serde_json::Map<String, serde_json::Value>) -> serde_json::Value {
+    let raw = frontmatter
+        .get("fields")
+        .and_then(|v| v.as_str())
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_default();
+    serde_json::Value::Object(raw)
+}
+
+/// Restore the language and translation group recorded by `pebble export`.
+/// Failures are logged rather than aborting the import of the content itself.
+fn apply_translation_fields(
+    db: &crate::Database,
+    id: i64,
+    frontmatter: &serde_json::Map<String, serde_json::Value>,
+) {
+    if let Some(language) = frontmatter.get("language").and_then(|v| v.as_str()) {
+        let language = language.trim().to_lowercase();
+        if !i18n::is_valid_language_code(&language) {
+            tracing::warn!("Ignoring invalid language '{}' for content {}", language, id);
+        } else if let Err(e) = i18n::set_content_language(db, id, Some(&language)) {
+            tracing::warn!("Failed to set language for content {}: {}", id, e);
+        }
+    }
+    if let Some(group) = frontmatter.get("translation_group").and_then(|v| v.as_str()) {
+        if let Err(e) = i18n::set_translation_group(db, id, group) {
+            tracing::warn!("Failed to restore translation link for content {}: {}", id, e);
+        }
+    }
+}
+
 fn parse_frontmatter(content: &str) -> Result<(serde_json::Map<String, serde_json::Value>, &str)> {
     let content = content.trim_start();
 
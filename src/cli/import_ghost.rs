@@ -1,4 +1,5 @@
 use crate::models::{ContentStatus, ContentType, CreateContent};
+use crate::services::markdown::MarkdownRenderer;
 use crate::services::{content, html_to_markdown};
 use crate::Config;
 use anyhow::Result;
@@ -8,6 +9,7 @@ use std::path::Path;
 
 pub async fn run(config_path: &Path, file: &Path, overwrite: bool) -> Result<()> {
     let config = Config::load(config_path)?;
+    let renderer = MarkdownRenderer::for_site(&config)?;
     let db = crate::Database::open(&config.database.path)?;
     db.migrate()?;
 
@@ -136,9 +138,18 @@ pub async fn run(config_path: &Path, file: &Path, overwrite: bool) -> Result<()>
             featured_image: post.get("feature_image").and_then(|v| v.as_str()).map(|s| s.to_string()),
             tags,
             metadata: None,
+            entry_type: None,
+            fields: None,
         };
 
-        match content::create_content(&db, input, None, config.content.excerpt_length) {
+        match content::create_content(
+            &db,
+            &renderer,
+            &config.content_types,
+            input,
+            None,
+            config.content.excerpt_length,
+        ) {
             Ok(_) => {
                 match content_type {
                     ContentType::Post => posts_imported += 1,
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use std::path::Path;
+
+use super::LinksCommand;
+use crate::services::link_checker;
+use crate::{Config, Database};
+
+pub async fn run(config_path: &Path, command: LinksCommand) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let db = Database::open(&config.database.path)?;
+    db.migrate()?;
+
+    match command {
+        LinksCommand::Check { stale, concurrency } => {
+            let mut checker = config.link_checker.clone();
+            if let Some(concurrency) = concurrency {
+                checker.concurrency = concurrency.max(1);
+            }
+
+            println!("Checking external links...");
+            let run = link_checker::run_check(&db, &config.site.url, &checker, stale).await?;
+            let reports = link_checker::broken_report(&db, &config.site.url)?;
+
+            for report in &reports {
+                println!(
+                    "\n  {} \x1b[1m{}\x1b[0m ({})",
+                    report.content_type, report.slug, report.title
+                );
+                for link in &report.links {
+                    let reason = match (link.status_code, &link.error) {
+                        (Some(status), _) => format!("HTTP {}", status),
+                        (None, Some(error)) => error.clone(),
+                        (None, None) => "failed".to_string(),
+                    };
+                    println!("    \x1b[31mbroken\x1b[0m  {}  [{}]", link.url, reason);
+                }
+            }
+
+            let summary = link_checker::summary(&db, &config.site.url)?;
+            println!(
+                "\n{} link(s) checked, {} skipped by robots.txt. {} broken link(s) in {} content item(s).",
+                run.checked,
+                summary.skipped,
+                summary.broken,
+                reports.len()
+            );
+            if summary.broken > 0 {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
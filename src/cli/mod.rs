@@ -1,17 +1,24 @@
 pub mod backup;
 pub mod build;
+pub mod check;
 pub mod config;
 pub mod deploy;
+pub mod deploy_config;
 pub mod doctor;
 pub mod export;
+pub mod host;
 pub mod import;
 pub mod import_ghost;
 pub mod import_wordpress;
 pub mod init;
+pub mod links;
 pub mod migrate;
 pub mod registry;
 pub mod rerender;
 pub mod serve;
+pub mod supervisor;
+pub mod sync;
+pub mod transfer;
 pub mod user;
 
 use clap::{Parser, Subcommand};
@@ -48,6 +55,10 @@ pub enum Commands {
         /// Port to listen on
         #[arg(short, long, default_value = "3000")]
         port: u16,
+        /// Listen on host:port, unix:/path/to.sock or systemd instead
+        /// (overrides server.listen, --host and --port)
+        #[arg(long)]
+        listen: Option<String>,
     },
     /// Start the production server
     Deploy {
@@ -57,6 +68,32 @@ pub enum Commands {
         /// Port to listen on
         #[arg(short, long, default_value = "8080")]
         port: u16,
+        /// Listen on host:port, unix:/path/to.sock or systemd instead
+        /// (overrides server.listen, --host and --port)
+        #[arg(long)]
+        listen: Option<String>,
+        /// Also serve the admin on this address, e.g. 127.0.0.1:8081 or
+        /// unix:/run/pebble/admin.sock (overrides server.admin_listen)
+        #[arg(long)]
+        admin: Option<String>,
+    },
+    /// Generate systemd, reverse proxy or Docker files for this site
+    DeployConfig {
+        /// What to generate: systemd, caddy, nginx or docker
+        #[arg(short, long)]
+        target: String,
+        /// Write the files into this directory instead of printing them
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Short name for the service (defaults to the site directory name)
+        #[arg(long)]
+        name: Option<String>,
+        /// System user the service runs as
+        #[arg(long, default_value = "pebble")]
+        user: String,
+        /// Path of the pebble binary (defaults to this executable)
+        #[arg(long)]
+        binary: Option<PathBuf>,
     },
     /// Generate a static site
     Build {
@@ -121,6 +158,18 @@ pub enum Commands {
     Doctor,
     /// Re-render all content HTML from markdown
     Rerender,
+    /// Check content for broken links, missing media and other problems
+    Check,
+    /// Check external links in published content
+    Links {
+        #[command(subcommand)]
+        command: LinksCommand,
+    },
+    /// Sync content, media and settings with another site
+    Sync {
+        #[command(subcommand)]
+        command: SyncCommand,
+    },
     /// Manage users
     User {
         #[command(subcommand)]
@@ -189,6 +238,58 @@ pub enum MigrateCommand {
     },
 }
 
+#[derive(Subcommand)]
+pub enum LinksCommand {
+    /// Request every external link and report the broken ones
+    Check {
+        /// Only check links not checked within link_checker.recheck_after_hours
+        #[arg(long)]
+        stale: bool,
+        /// Number of hosts to check at the same time
+        #[arg(long)]
+        concurrency: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SyncCommand {
+    /// Send this site's changes to another site
+    Push {
+        /// Site to update: a site directory, its pebble.toml or a URL
+        target: String,
+        #[command(flatten)]
+        args: SyncArgs,
+    },
+    /// Bring another site's changes into this site
+    Pull {
+        /// Site to copy from: a site directory, its pebble.toml or a URL
+        source: String,
+        #[command(flatten)]
+        args: SyncArgs,
+    },
+    /// Give this site a new sync id, e.g. after copying it by hand
+    ResetId,
+}
+
+#[derive(clap::Args)]
+pub struct SyncArgs {
+    /// API token with the sync permission, for a remote site
+    #[arg(long, env = "PEBBLE_SYNC_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+    /// Show the plan without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Apply the plan without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+    /// Also delete items that exist only on the receiving site
+    #[arg(long)]
+    pub delete: bool,
+    /// Overwrite conflicting changes made on the receiving site
+    #[arg(long)]
+    pub force: bool,
+}
+
 #[derive(Subcommand)]
 pub enum ConfigCommand {
     Get { key: String },
@@ -208,6 +309,49 @@ pub enum RegistryCommand {
         #[arg(long)]
         title: Option<String>,
     },
+    /// Copy an existing site directory into the registry
+    Adopt {
+        /// Directory containing the site's pebble.toml
+        path: PathBuf,
+        /// Name for the site (defaults to the directory name)
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Copy a registry site, e.g. as a staging copy
+    Clone {
+        /// Name of the site to copy
+        source: String,
+        /// Name for the copy
+        target: String,
+        /// site.url for the copy (defaults to http://localhost:<port>)
+        #[arg(long)]
+        url: Option<String>,
+        /// Port for the copy (defaults to a free port)
+        #[arg(short, long)]
+        port: Option<u16>,
+    },
+    /// Write a site's config, database and media to a single archive
+    Pack {
+        /// Name of the site to pack
+        name: String,
+        /// Archive to write (defaults to ./<name>.pebble.zip)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Import a site from an archive written by `pack`
+    Unpack {
+        /// Archive to import
+        archive: PathBuf,
+        /// Name for the site (defaults to the name it was packed under)
+        #[arg(long)]
+        name: Option<String>,
+        /// Replace the site's site.url
+        #[arg(long)]
+        url: Option<String>,
+        /// Replace the site's port
+        #[arg(short, long)]
+        port: Option<u16>,
+    },
     /// List all registered sites
     List,
     /// Start a site's development server
@@ -226,6 +370,41 @@ pub enum RegistryCommand {
         #[arg(short, long)]
         port: Option<u16>,
     },
+    /// Serve every site from one process, routed by Host header
+    ServeAll {
+        /// Host address to bind to
+        #[arg(short = 'H', long, default_value = "127.0.0.1")]
+        host: String,
+        /// Port to listen on
+        #[arg(short, long, default_value = "8080")]
+        port: u16,
+        /// Serve sites read-only, without admin routes, as `deploy` does
+        #[arg(long)]
+        production: bool,
+    },
+    /// Manage the domains a site is served at by serve-all
+    Domain {
+        #[command(subcommand)]
+        command: DomainCommand,
+    },
+    /// Stop a site if it is running and start it again
+    Restart {
+        /// Name of the site to restart
+        name: String,
+    },
+    /// Show a site's server log
+    Logs {
+        /// Name of the site
+        name: String,
+        /// Keep printing new log lines
+        #[arg(short, long)]
+        follow: bool,
+        /// Number of lines to show
+        #[arg(short = 'n', long, default_value = "100")]
+        lines: usize,
+    },
+    /// Keep started sites running: health checks, restarts and log rotation
+    Supervise,
     /// Stop a running site
     Stop {
         /// Name of the site to stop
@@ -265,6 +444,29 @@ pub enum RegistryCommand {
     },
 }
 
+#[derive(Subcommand)]
+pub enum DomainCommand {
+    /// Serve a site at a domain
+    Add {
+        /// Site name
+        name: String,
+        /// Domain name (e.g., blog.example.com)
+        domain: String,
+    },
+    /// Stop serving a site at a domain
+    Remove {
+        /// Site name
+        name: String,
+        /// Domain name
+        domain: String,
+    },
+    /// List the domains of one or all sites
+    List {
+        /// Site name (omit to list every site)
+        name: Option<String>,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum SiteConfigCommand {
     /// Get a config value (e.g., theme.name, site.title)
@@ -1,8 +1,10 @@
 use crate::config::Config;
-use crate::global::{GlobalConfig, PebbleHome, Registry, RegistrySite, SiteStatus};
+use crate::global::{normalize_domain, GlobalConfig, PebbleHome, Registry, RegistrySite, SiteStatus};
+use crate::services::markdown::MarkdownRenderer;
 use anyhow::{bail, Context, Result};
-use std::fs::{self, File};
-use std::process::{Command, Stdio};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
 
 pub async fn run(command: super::RegistryCommand) -> Result<()> {
     let home = PebbleHome::init()?;
@@ -16,6 +18,39 @@ pub async fn run(command: super::RegistryCommand) -> Result<()> {
             init_site(&home, &global_config, &mut registry, &name, title)?;
             registry.save(&home.registry_path)?;
         }
+        super::RegistryCommand::Adopt { path, name } => {
+            super::transfer::adopt(&home, &mut registry, &path, name)?;
+            registry.save(&home.registry_path)?;
+        }
+        super::RegistryCommand::Clone {
+            source,
+            target,
+            url,
+            port,
+        } => {
+            super::transfer::clone(
+                &home,
+                &global_config,
+                &mut registry,
+                &source,
+                &target,
+                url,
+                port,
+            )?;
+            registry.save(&home.registry_path)?;
+        }
+        super::RegistryCommand::Pack { name, output } => {
+            super::transfer::pack(&home, &registry, &name, output)?;
+        }
+        super::RegistryCommand::Unpack {
+            archive,
+            name,
+            url,
+            port,
+        } => {
+            super::transfer::unpack(&home, &mut registry, &archive, name, url, port)?;
+            registry.save(&home.registry_path)?;
+        }
         super::RegistryCommand::List => {
             list_sites(&registry);
         }
@@ -27,6 +62,35 @@ pub async fn run(command: super::RegistryCommand) -> Result<()> {
             serve_site(&home, &global_config, &mut registry, &name, port, true).await?;
             registry.save(&home.registry_path)?;
         }
+        super::RegistryCommand::ServeAll {
+            host,
+            port,
+            production,
+        } => {
+            super::host::serve_all(home, &host, port, production).await?;
+        }
+        super::RegistryCommand::Domain { command } => {
+            site_domains(&mut registry, command)?;
+            registry.save(&home.registry_path)?;
+        }
+        super::RegistryCommand::Restart { name } => {
+            restart_site(&home, &global_config, &mut registry, &name).await?;
+            registry.save(&home.registry_path)?;
+        }
+        super::RegistryCommand::Logs {
+            name,
+            follow,
+            lines,
+        } => {
+            registry
+                .get_site(&name)
+                .context(format!("Site '{}' not found in registry", name))?;
+            let path = super::supervisor::log_path(&home, &name);
+            super::supervisor::show_logs(&path, lines, follow).await?;
+        }
+        super::RegistryCommand::Supervise => {
+            super::supervisor::run(&home).await?;
+        }
         super::RegistryCommand::Stop { name } => {
             stop_site(&mut registry, &name)?;
             registry.save(&home.registry_path)?;
@@ -103,6 +167,8 @@ fn init_site(
         port: None,
         pid: None,
         last_started: None,
+        domains: Vec::new(),
+        supervision: Default::default(),
     };
     registry.add_site(site)?;
 
@@ -188,14 +254,16 @@ fn list_sites(registry: &Registry) {
     }
 }
 
-async fn serve_site(
+/// Start a site as its own `pebble serve/deploy` process, returning the
+/// child unless the site was already running.
+pub(super) async fn serve_site(
     home: &PebbleHome,
     global_config: &GlobalConfig,
     registry: &mut Registry,
     name: &str,
     port: Option<u16>,
     production: bool,
-) -> Result<()> {
+) -> Result<Option<Child>> {
     let site = registry
         .get_site(name)
         .context(format!("Site '{}' not found in registry", name))?;
@@ -206,7 +274,16 @@ async fn serve_site(
             name,
             site.port.unwrap_or(0)
         );
-        return Ok(());
+        return Ok(None);
+    }
+
+    if site.status == SiteStatus::Hosted {
+        println!(
+            "Site '{}' is served by `pebble registry serve-all` on port {}",
+            name,
+            site.port.unwrap_or(0)
+        );
+        return Ok(None);
     }
 
     let site_path = home.site_path(name);
@@ -243,7 +320,25 @@ async fn serve_site(
     let logs_dir = site_path.join("logs");
     fs::create_dir_all(&logs_dir)?;
     let log_file = logs_dir.join(format!("{}.log", name));
-    let stdout_file = File::create(&log_file).context("Failed to create log file")?;
+    let supervisor = &global_config.supervisor;
+    super::supervisor::rotate_log(
+        &log_file,
+        supervisor.log_max_size_mb * 1024 * 1024,
+        supervisor.log_keep,
+    )?;
+    let mut stdout_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_file)
+        .context("Failed to open log file")?;
+    writeln!(
+        stdout_file,
+        "--- {} starting '{}' ({}) on port {} ---",
+        chrono::Utc::now().to_rfc3339(),
+        name,
+        mode,
+        port
+    )?;
     let stderr_file = stdout_file
         .try_clone()
         .context("Failed to clone log file handle")?;
@@ -253,10 +348,10 @@ async fn serve_site(
             "--config",
             config_path.to_str().ok_or_else(|| anyhow::anyhow!("Config path contains invalid UTF-8"))?,
             mode,
-            "-H",
-            host,
-            "-p",
-            &port.to_string(),
+            // --listen rather than -H/-p so the registry's port also wins
+            // over a server.listen in the site's config
+            "--listen",
+            &format!("{}:{}", host, port),
         ])
         .current_dir(&site_path)
         .stdin(Stdio::null())
@@ -273,12 +368,16 @@ async fn serve_site(
     };
 
     registry.update_site_status(name, status, Some(port), Some(pid));
+    if let Some(site) = registry.get_site_mut(name) {
+        site.supervision.keep_running = true;
+        site.supervision.production = production;
+    }
 
     println!("Started '{}' ({}) on http://{}:{}", name, mode, host, port);
     println!("PID: {}", pid);
     println!("Logs: {}", log_file.display());
 
-    Ok(())
+    Ok(Some(child))
 }
 
 fn stop_site(registry: &mut Registry, name: &str) -> Result<()> {
@@ -291,12 +390,67 @@ fn stop_site(registry: &mut Registry, name: &str) -> Result<()> {
         return Ok(());
     }
 
+    if site.status == SiteStatus::Hosted {
+        bail!(
+            "Site '{}' is served by `pebble registry serve-all` (PID: {}). Stop that process, or remove the site from the registry",
+            name,
+            site.pid.unwrap_or(0)
+        );
+    }
+
     if let Some(pid) = site.pid {
         kill_process(pid)?;
         println!("Stopped site '{}' (PID: {})", name, pid);
     }
 
     registry.update_site_status(name, SiteStatus::Stopped, None, None);
+    if let Some(site) = registry.get_site_mut(name) {
+        site.supervision.keep_running = false;
+    }
+    Ok(())
+}
+
+async fn restart_site(
+    home: &PebbleHome,
+    global_config: &GlobalConfig,
+    registry: &mut Registry,
+    name: &str,
+) -> Result<()> {
+    let site = registry
+        .get_site(name)
+        .context(format!("Site '{}' not found in registry", name))?
+        .clone();
+
+    if site.status == SiteStatus::Hosted {
+        bail!(
+            "Site '{}' is served by `pebble registry serve-all`; restart that process instead",
+            name
+        );
+    }
+
+    let production = site.status == SiteStatus::Deploying
+        || (site.status == SiteStatus::Stopped && site.supervision.production);
+    if site.status != SiteStatus::Stopped {
+        // Keep the supervisor from restarting the site while it is down
+        if let Some(site) = registry.get_site_mut(name) {
+            site.supervision.keep_running = false;
+        }
+        registry.save(&home.registry_path)?;
+
+        if let Some(pid) = site.pid {
+            kill_process(pid)?;
+            for _ in 0..50 {
+                if !crate::global::is_process_running(pid) {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            println!("Stopped '{}' (PID: {})", name, pid);
+        }
+        registry.update_site_status(name, SiteStatus::Stopped, site.port, None);
+    }
+
+    serve_site(home, global_config, registry, name, site.port, production).await?;
     Ok(())
 }
 
@@ -312,14 +466,21 @@ fn stop_all_sites(registry: &mut Registry) -> Result<()> {
         return Ok(());
     }
 
+    // Hosted sites share the serve-all process, which is stopped only once
+    let mut stopped = std::collections::HashSet::new();
     for name in running {
         if let Some(site) = registry.get_site(&name) {
             if let Some(pid) = site.pid {
-                kill_process(pid)?;
+                if stopped.insert(pid) {
+                    kill_process(pid)?;
+                }
                 println!("Stopped '{}' (PID: {})", name, pid);
             }
         }
         registry.update_site_status(&name, SiteStatus::Stopped, None, None);
+        if let Some(site) = registry.get_site_mut(&name) {
+            site.supervision.keep_running = false;
+        }
     }
 
     Ok(())
@@ -334,12 +495,20 @@ fn remove_site(home: &PebbleHome, registry: &mut Registry, name: &str, force: bo
         bail!("Site '{}' is running. Stop it first or use --force", name);
     }
 
+    if site.status == SiteStatus::Hosted && !force {
+        bail!(
+            "Site '{}' is served by `pebble registry serve-all`. Use --force to remove it anyway",
+            name
+        );
+    }
+
     if site.status == SiteStatus::Running {
         if let Some(pid) = site.pid {
             let _ = kill_process(pid);
         }
     }
 
+    // A serve-all host unloads the site once it is gone from the registry
     let site_path = home.site_path(name);
     if site_path.exists() {
         fs::remove_dir_all(&site_path)
@@ -362,7 +531,14 @@ fn show_status(registry: &Registry, name: &str) -> Result<()> {
     println!("Status:      {}", site.status);
     if let Some(port) = site.port {
         println!("Port:        {}", port);
-        println!("URL:         http://localhost:{}", port);
+        if site.status == SiteStatus::Hosted {
+            println!("URL:         http://{}.localhost:{}", site.name, port);
+        } else {
+            println!("URL:         http://localhost:{}", port);
+        }
+    }
+    if !site.domains.is_empty() {
+        println!("Domains:     {}", site.domains.join(", "));
     }
     if let Some(pid) = site.pid {
         println!("PID:         {}", pid);
@@ -371,6 +547,24 @@ fn show_status(registry: &Registry, name: &str) -> Result<()> {
     if let Some(ref started) = site.last_started {
         println!("Last Start:  {}", started);
     }
+    let supervision = &site.supervision;
+    if supervision.keep_running {
+        let supervisor = match registry.active_supervisor() {
+            Some(s) => format!("yes (supervisor PID: {})", s.pid),
+            None => "yes (supervisor not running)".to_string(),
+        };
+        println!("Supervised:  {}", supervisor);
+        println!("Restarts:    {}", supervision.restarts);
+        if let Some(ref checked) = supervision.last_check {
+            println!("Last Check:  {}", checked);
+        }
+        if let Some(ref restarted) = supervision.last_restart {
+            println!("Last Restart: {}", restarted);
+        }
+        if let Some(ref error) = supervision.last_error {
+            println!("Last Error:  {}", error);
+        }
+    }
 
     Ok(())
 }
@@ -403,16 +597,46 @@ fn rerender_site(home: &PebbleHome, registry: &Registry, name: &str) -> Result<(
     }
 
     let config = Config::load(&config_path)?;
+    let renderer = MarkdownRenderer::for_site(&config)?;
     let db = crate::Database::open(&config.database.path)?;
 
     println!("Re-rendering all content for '{}'...", name);
-    let count = crate::services::content::rerender_all_content(&db)?;
+    let count = crate::services::content::rerender_all_content(&db, &renderer)?;
     println!("Successfully re-rendered {} content items.", count);
 
     Ok(())
 }
 
-fn is_valid_site_name(name: &str) -> bool {
+fn site_domains(registry: &mut Registry, command: super::DomainCommand) -> Result<()> {
+    match command {
+        super::DomainCommand::Add { name, domain } => {
+            registry.add_domain(&name, &domain)?;
+            println!("Added domain '{}' to site '{}'", normalize_domain(&domain)?, name);
+        }
+        super::DomainCommand::Remove { name, domain } => {
+            registry.remove_domain(&name, &normalize_domain(&domain)?)?;
+            println!("Removed domain '{}' from site '{}'", domain, name);
+        }
+        super::DomainCommand::List { name } => {
+            let sites = match name {
+                Some(n) => vec![registry
+                    .get_site(&n)
+                    .context(format!("Site '{}' not found in registry", n))?],
+                None => registry.list_sites(),
+            };
+            println!("{:<20} DOMAINS", "NAME");
+            println!("{}", "-".repeat(72));
+            for site in sites {
+                let mut domains = vec![format!("{}.localhost", site.name)];
+                domains.extend(site.domains.iter().cloned());
+                println!("{:<20} {}", site.name, domains.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn is_valid_site_name(name: &str) -> bool {
     !name.is_empty()
         && name.len() <= 64
         && name
@@ -423,7 +647,7 @@ fn is_valid_site_name(name: &str) -> bool {
 }
 
 #[cfg(unix)]
-fn kill_process(pid: u32) -> Result<()> {
+pub(super) fn kill_process(pid: u32) -> Result<()> {
     unsafe {
         if libc::kill(pid as i32, libc::SIGTERM) != 0 {
             bail!("Failed to kill process {}", pid);
@@ -433,7 +657,7 @@ fn kill_process(pid: u32) -> Result<()> {
 }
 
 #[cfg(windows)]
-fn kill_process(pid: u32) -> Result<()> {
+pub(super) fn kill_process(pid: u32) -> Result<()> {
     Command::new("taskkill")
         .args(["/PID", &pid.to_string(), "/F"])
         .output()
@@ -453,6 +677,7 @@ async fn site_config(
         .context(format!("Site '{}' not found in registry", name))?;
 
     let was_running = site.status == SiteStatus::Running;
+    let was_hosted = site.status == SiteStatus::Hosted;
 
     let site_path = home.site_path(name);
     let config_path = site_path.join("pebble.toml");
@@ -491,6 +716,9 @@ async fn site_config(
         // Use None for port to let it read from the updated config
         serve_site(home, global_config, registry, name, None, false).await?;
     }
+    if needs_restart && was_hosted {
+        println!("Restart `pebble registry serve-all` to apply the changes.");
+    }
 
     Ok(())
 }
@@ -508,6 +736,23 @@ fn show_site_config(config_path: &std::path::Path) -> Result<()> {
     println!("# Server");
     println!("{:<30}  {}", "server.host", config.server.host);
     println!("{:<30}  {}", "server.port", config.server.port);
+    if let Some(ref v) = config.server.listen {
+        println!("{:<30}  {}", "server.listen", v);
+    }
+    if let Some(ref v) = config.server.admin_listen {
+        println!("{:<30}  {}", "server.admin_listen", v);
+    }
+    if let Some(ref v) = config.server.socket_mode {
+        println!("{:<30}  {}", "server.socket_mode", v);
+    }
+    println!(
+        "{:<30}  {}",
+        "server.trusted_proxies",
+        config.server.trusted_proxies.join(", ")
+    );
+    println!("{:<30}  {}", "server.client_ip_header", config.server.client_ip_header);
+    println!("{:<30}  {}", "tls.enabled", config.tls.enabled);
+    println!("{:<30}  {}", "metrics.enabled", config.metrics.enabled);
     println!();
 
     println!("# Content");
@@ -587,6 +832,17 @@ fn get_config_value(config: &Config, key: &str) -> Result<String> {
         // Server
         ["server", "host"] => Ok(config.server.host.clone()),
         ["server", "port"] => Ok(config.server.port.to_string()),
+        ["server", "listen"] => Ok(config.server.listen.clone().unwrap_or_default()),
+        ["server", "admin_listen"] => Ok(config.server.admin_listen.clone().unwrap_or_default()),
+        ["server", "socket_mode"] => Ok(config.server.socket_mode.clone().unwrap_or_default()),
+        ["server", "trusted_proxies"] => Ok(config.server.trusted_proxies.join(", ")),
+        ["server", "client_ip_header"] => Ok(config.server.client_ip_header.clone()),
+        // TLS
+        ["tls", "enabled"] => Ok(config.tls.enabled.to_string()),
+        ["tls", "domains"] => Ok(config.tls.domains(&config.site.url).join(", ")),
+        // Metrics
+        ["metrics", "enabled"] => Ok(config.metrics.enabled.to_string()),
+        ["metrics", "listen"] => Ok(config.metrics.listen.clone().unwrap_or_default()),
         // Content
         ["content", "posts_per_page"] => Ok(config.content.posts_per_page.to_string()),
         ["content", "excerpt_length"] => Ok(config.content.excerpt_length.to_string()),
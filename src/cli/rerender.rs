@@ -2,15 +2,24 @@ use anyhow::Result;
 use std::path::Path;
 
 use crate::services::content::rerender_all_content;
+use crate::services::embeds;
+use crate::services::markdown::MarkdownRenderer;
 use crate::{Config, Database};
 
 pub async fn run(config_path: &Path) -> Result<()> {
     let config = Config::load(config_path)?;
+    let renderer = MarkdownRenderer::for_site(&config)?;
     let db = Database::open(&config.database.path)?;
 
     println!("Re-rendering all content...");
-    let count = rerender_all_content(&db)?;
+    let count = rerender_all_content(&db, &renderer)?;
     println!("Successfully re-rendered {} content items.", count);
 
+    let cached =
+        embeds::cache_all_thumbnails(&db, Path::new(&config.media.upload_dir)).await?;
+    if cached > 0 {
+        println!("Cached {} embed thumbnail(s).", cached);
+    }
+
     Ok(())
 }
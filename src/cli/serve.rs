@@ -1,33 +1,104 @@
-use crate::services::{content, search};
+use crate::services::{content, embeds, link_checker, search, social_card, webmention};
+use crate::web::listener::ListenAddr;
+use crate::services::metrics::Metrics;
 use crate::{web, Config, Database};
 use anyhow::Result;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::JoinHandle;
 
-pub async fn run(config_path: &Path, host: &str, port: u16) -> Result<()> {
+pub async fn run(config_path: &Path, host: &str, port: u16, listen: Option<String>) -> Result<()> {
     let config = Config::load(config_path)?;
-    let db = Database::open(&config.database.path)?;
+    let listen = ListenAddr::resolve(
+        listen.as_deref(),
+        config.server.listen.as_deref(),
+        host,
+        port,
+    )?;
+    let db = open_site_database(&config)?;
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let metrics = Arc::new(Metrics::new());
+    let bg_handles = spawn_site_tasks(&config, &db, &metrics, shutdown_rx.clone());
+
+    tracing::info!("Starting server at {}", listen);
+
+    web::serve(
+        config,
+        config_path.to_path_buf(),
+        db,
+        metrics,
+        &listen,
+        Some(shutdown_rx),
+    )
+    .await?;
+
+    // Signal all background tasks to stop
+    let _ = shutdown_tx.send(true);
+    for handle in bg_handles {
+        handle.abort();
+    }
 
+    Ok(())
+}
+
+/// Open and migrate a site's database and rebuild its search index.
+pub fn open_site_database(config: &Config) -> Result<Database> {
+    let db = Database::open(&config.database.path)?;
     db.migrate()?;
 
     if let Ok(count) = search::rebuild_fts_index(&db) {
         tracing::info!("Search index rebuilt: {} documents indexed", count);
     }
 
-    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    Ok(db)
+}
+
+/// Start a site's background work: embed thumbnail caching, scheduled
+/// publishing, and auto-backups and link checks when enabled. The returned
+/// tasks stop when `shutdown_rx` changes.
+pub fn spawn_site_tasks(
+    config: &Config,
+    db: &Database,
+    metrics: &Arc<Metrics>,
+    shutdown_rx: tokio::sync::watch::Receiver<bool>,
+) -> Vec<JoinHandle<()>> {
+    // Fetch thumbnails for embeds added by imports or while the server was down
+    let embed_db = db.clone();
+    let embed_media_dir = std::path::PathBuf::from(&config.media.upload_dir);
+    tokio::spawn(async move {
+        match embeds::cache_all_thumbnails(&embed_db, &embed_media_dir).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("Cached {} embed thumbnail(s)", count),
+            Err(e) => tracing::warn!("Failed to cache embed thumbnails: {}", e),
+        }
+    });
+
     let mut bg_handles: Vec<JoinHandle<()>> = Vec::new();
 
     let scheduler_db = db.clone();
+    let scheduler_config = config.clone();
+    let scheduler_media_dir = std::path::PathBuf::from(&config.media.upload_dir);
+    let scheduler_metrics = metrics.clone();
     let mut scheduler_rx = shutdown_rx.clone();
     bg_handles.push(tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(60));
         loop {
             tokio::select! {
                 _ = interval.tick() => {
-                    if let Ok(count) = content::publish_scheduled(&scheduler_db) {
-                        if count > 0 {
-                            tracing::info!("Scheduled publisher: {} post(s) published", count);
+                    let published = content::publish_scheduled(&scheduler_db);
+                    scheduler_metrics.record_publish_run(published.as_ref().ok().map(Vec::len));
+                    if let Err(e) = &published {
+                        tracing::error!("Scheduled publisher failed: {}", e);
+                    }
+                    if let Ok(ids) = published {
+                        if !ids.is_empty() {
+                            tracing::info!("Scheduled publisher: {} post(s) published", ids.len());
+                        }
+                        for id in ids {
+                            webmention::notify_published(&scheduler_db, &scheduler_config, id);
+                            social_card::spawn_render(&scheduler_db, &scheduler_config, &scheduler_media_dir, id);
                         }
                     }
                 }
@@ -81,16 +152,41 @@ pub async fn run(config_path: &Path, host: &str, port: u16) -> Result<()> {
         );
     }
 
-    let addr = format!("{}:{}", host, port);
-    tracing::info!("Starting server at http://{}", addr);
-
-    web::serve(config, config_path.to_path_buf(), db, &addr, Some(shutdown_rx)).await?;
-
-    // Signal all background tasks to stop
-    let _ = shutdown_tx.send(true);
-    for handle in bg_handles {
-        handle.abort();
+    // External link checker
+    if config.link_checker.enabled {
+        let checker_db = db.clone();
+        let checker_config = config.link_checker.clone();
+        let site_url = config.site.url.clone();
+        let mut checker_rx = shutdown_rx.clone();
+        bg_handles.push(tokio::spawn(async move {
+            let interval_secs = checker_config.interval_hours.max(1) * 3600;
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        match link_checker::run_check(&checker_db, &site_url, &checker_config, true).await {
+                            Ok(run) if run.checked > 0 => tracing::info!(
+                                "Link checker: {} link(s) checked, {} broken",
+                                run.checked,
+                                run.broken
+                            ),
+                            Ok(_) => {}
+                            Err(e) => tracing::warn!("Link checker failed: {}", e),
+                        }
+                    }
+                    _ = checker_rx.changed() => {
+                        tracing::info!("Link checker stopping...");
+                        break;
+                    }
+                }
+            }
+        }));
+        tracing::info!(
+            "Link checker enabled: every {} hours, rechecking links after {} hours",
+            config.link_checker.interval_hours,
+            config.link_checker.recheck_after_hours
+        );
     }
 
-    Ok(())
+    bg_handles
 }
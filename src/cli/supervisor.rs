@@ -0,0 +1,567 @@
+//! `pebble registry supervise`: keeps registry sites started with `serve` or
+//! `deploy` running, and the log handling shared with the registry commands.
+
+use super::registry::{kill_process, serve_site};
+use crate::config::Config;
+use crate::global::{
+    is_process_running, GlobalConfig, PebbleHome, Registry, RegistrySite, SiteStatus,
+    SupervisorProcess,
+};
+use anyhow::{bail, Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// How long a health check may take before it counts as failed.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Per-site state that is not worth persisting.
+#[derive(Default)]
+struct Watch {
+    /// Failed health checks in a row while the process is alive
+    failed_checks: u32,
+    next_check: Option<Instant>,
+    /// Earliest time the site may be restarted again
+    next_restart: Option<Instant>,
+}
+
+pub async fn run(home: &PebbleHome) -> Result<()> {
+    let mut registry = Registry::load(&home.registry_path)?;
+    let pid = std::process::id();
+    if let Some(other) = registry.active_supervisor() {
+        if other.pid != pid {
+            bail!("A supervisor is already running (PID: {})", other.pid);
+        }
+    }
+    registry.supervisor = Some(SupervisorProcess {
+        pid,
+        started_at: chrono::Utc::now().to_rfc3339(),
+    });
+    registry.save(&home.registry_path)?;
+
+    let supervised = registry
+        .list_sites()
+        .iter()
+        .filter(|s| s.supervision.keep_running)
+        .count();
+    println!("Supervising {} site(s) (PID: {})", supervised, pid);
+    println!("Sites started with `pebble registry serve` or `deploy` are kept running.");
+
+    let mut children: HashMap<String, Child> = HashMap::new();
+    let mut watches: HashMap<String, Watch> = HashMap::new();
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let shutdown = crate::web::shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {
+                // Reap sites that exited so they do not linger as zombies
+                children.retain(|_, child| matches!(child.try_wait(), Ok(None)));
+                if let Err(e) = supervise_once(home, &mut children, &mut watches).await {
+                    tracing::warn!("Supervisor pass failed: {:#}", e);
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    let mut registry = Registry::load(&home.registry_path)?;
+    if registry.supervisor.as_ref().is_some_and(|s| s.pid == pid) {
+        registry.supervisor = None;
+        registry.save(&home.registry_path)?;
+    }
+    println!("Supervisor stopped; supervised sites keep running.");
+    Ok(())
+}
+
+async fn supervise_once(
+    home: &PebbleHome,
+    children: &mut HashMap<String, Child>,
+    watches: &mut HashMap<String, Watch>,
+) -> Result<()> {
+    // Reloaded each pass so `pebble config set supervisor.*` applies at once
+    let global_config = GlobalConfig::load(&home.config_path)?;
+    let config = &global_config.supervisor;
+    let mut registry = Registry::load(&home.registry_path)?;
+    let now = Instant::now();
+    let mut changed: Vec<String> = Vec::new();
+
+    let names: Vec<String> = registry
+        .list_sites()
+        .into_iter()
+        .filter(|s| s.supervision.keep_running && s.status != SiteStatus::Hosted)
+        .map(|s| s.name.clone())
+        .collect();
+    watches.retain(|name, _| names.contains(name));
+
+    for name in names {
+        let Some(site) = registry.get_site(&name).cloned() else {
+            continue;
+        };
+        let watch = watches.entry(name.clone()).or_default();
+        let alive = site.pid.is_some_and(is_process_running);
+        let mut restart = !alive;
+        let mut error = (!alive).then(|| "Process exited".to_string());
+
+        if alive {
+            let log = log_path(home, &name);
+            if let Err(e) = rotate_log(&log, config.log_max_size_mb * 1024 * 1024, config.log_keep)
+            {
+                tracing::warn!("Failed to rotate log for '{}': {}", name, e);
+            }
+
+            if watch.next_check.map_or(true, |t| now >= t) {
+                watch.next_check =
+                    Some(now + Duration::from_secs(config.health_interval_secs.max(1)));
+                let port = site.port.unwrap_or(0);
+                let https = serves_https(&home.site_path(&name), site.supervision.production);
+                let site_mut = registry.get_site_mut(&name).expect("site listed above");
+                site_mut.supervision.last_check = Some(chrono::Utc::now().to_rfc3339());
+                match check_health(port, https).await {
+                    Ok(()) => {
+                        watch.failed_checks = 0;
+                        site_mut.supervision.failures = 0;
+                        site_mut.supervision.last_error = None;
+                    }
+                    Err(e) => {
+                        watch.failed_checks += 1;
+                        tracing::warn!(
+                            "Health check {}/{} failed for '{}': {}",
+                            watch.failed_checks,
+                            config.failed_checks,
+                            name,
+                            e
+                        );
+                        site_mut.supervision.last_error =
+                            Some(format!("Health check failed: {}", e));
+                        if watch.failed_checks >= config.failed_checks.max(1) {
+                            restart = true;
+                            error = Some(format!(
+                                "Unhealthy after {} checks: {}",
+                                watch.failed_checks, e
+                            ));
+                        }
+                    }
+                }
+                changed.push(name.clone());
+            }
+        }
+
+        if !restart || watch.next_restart.is_some_and(|t| now < t) {
+            continue;
+        }
+
+        // Leave the site alone if a command stopped or restarted it meanwhile
+        let current = Registry::load(&home.registry_path)?;
+        if !current
+            .get_site(&name)
+            .is_some_and(|s| s.supervision.keep_running && s.pid == site.pid)
+        {
+            continue;
+        }
+
+        if alive {
+            if let Some(pid) = site.pid {
+                let _ = kill_process(pid);
+                wait_for_exit(pid).await;
+            }
+        }
+        children.remove(&name);
+        registry.update_site_status(&name, SiteStatus::Stopped, site.port, None);
+
+        tracing::warn!(
+            "Restarting '{}': {}",
+            name,
+            error.as_deref().unwrap_or("unknown error")
+        );
+        let production = site.supervision.production;
+        let started = serve_site(
+            home,
+            &global_config,
+            &mut registry,
+            &name,
+            site.port,
+            production,
+        )
+        .await;
+
+        let site_mut = registry.get_site_mut(&name).expect("site listed above");
+        let supervision = &mut site_mut.supervision;
+        supervision.restarts += 1;
+        supervision.failures += 1;
+        supervision.last_restart = Some(chrono::Utc::now().to_rfc3339());
+        supervision.last_error = error;
+        match started {
+            Ok(Some(child)) => {
+                children.insert(name.clone(), child);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("Failed to restart '{}': {:#}", name, e);
+                supervision.last_error = Some(format!("Restart failed: {:#}", e));
+            }
+        }
+
+        // Back off exponentially while the site keeps failing
+        let backoff = 2u64
+            .saturating_pow(supervision.failures.min(16))
+            .min(config.max_backoff_secs.max(1));
+        watch.next_restart = Some(now + Duration::from_secs(backoff));
+        watch.failed_checks = 0;
+        // Give the new process time to start before checking it
+        watch.next_check = Some(now + Duration::from_secs(config.health_interval_secs.max(1)));
+        changed.push(name);
+    }
+
+    if !changed.is_empty() {
+        save_supervised(home, &registry, &changed)?;
+    }
+    Ok(())
+}
+
+/// Write the supervisor's changes to `names` into the registry as it is on
+/// disk now, so commands run meanwhile (e.g. `stop`) are not overwritten.
+fn save_supervised(home: &PebbleHome, updated: &Registry, names: &[String]) -> Result<()> {
+    let mut registry = Registry::load(&home.registry_path)?;
+    for name in names {
+        let (Some(current), Some(site)) = (registry.get_site_mut(name), updated.get_site(name))
+        else {
+            continue;
+        };
+        if !current.supervision.keep_running {
+            continue;
+        }
+        let RegistrySite {
+            status,
+            port,
+            pid,
+            last_started,
+            supervision,
+            ..
+        } = site.clone();
+        current.status = status;
+        current.port = port;
+        current.pid = pid;
+        current.last_started = last_started;
+        current.supervision = supervision;
+    }
+    registry.save(&home.registry_path)
+}
+
+/// GET `/health` on a site's port, over HTTPS when the site serves it.
+async fn check_health(port: u16, https: bool) -> Result<()> {
+    if port == 0 {
+        bail!("no port recorded");
+    }
+    let request = async {
+        let stream = tokio::net::TcpStream::connect(("127.0.0.1", port)).await?;
+        if https {
+            health_request(health_tls::connect(stream).await?).await
+        } else {
+            health_request(stream).await
+        }
+    };
+    let head = tokio::time::timeout(HEALTH_TIMEOUT, request)
+        .await
+        .context("timed out")??;
+    let status = head.split_whitespace().nth(1).unwrap_or("");
+    if status != "200" {
+        bail!(
+            "HTTP status {}",
+            if status.is_empty() { "missing" } else { status }
+        );
+    }
+    Ok(())
+}
+
+/// Send the health request on `stream` and return the start of the response.
+async fn health_request<S>(mut stream: S) -> std::io::Result<String>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    stream
+        .write_all(b"GET /health HTTP/1.0\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await?;
+    let mut head = [0u8; 32];
+    let read = stream.read(&mut head).await?;
+    Ok(String::from_utf8_lossy(&head[..read]).into_owned())
+}
+
+/// Whether the site in `site_path` serves HTTPS on its port, which it only
+/// does when started with `deploy`.
+fn serves_https(site_path: &Path, production: bool) -> bool {
+    production
+        && Config::load(&site_path.join("pebble.toml")).is_ok_and(|config| config.tls.enabled)
+}
+
+/// The TLS client for health checks. The site's certificate is issued for its
+/// public domain, not the loopback address checked here, so it is not
+/// verified; the check only needs the site to answer.
+#[cfg(feature = "tls")]
+mod health_tls {
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{self, CryptoProvider};
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+    use std::sync::Arc;
+    use tokio::net::TcpStream;
+    use tokio_rustls::client::TlsStream;
+    use tokio_rustls::TlsConnector;
+
+    #[derive(Debug)]
+    struct AnyCertificate(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for AnyCertificate {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            crypto::verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            crypto::verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    pub async fn connect(stream: TcpStream) -> std::io::Result<TlsStream<TcpStream>> {
+        let provider = Arc::new(crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(std::io::Error::other)?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider)))
+            .with_no_client_auth();
+        let name = ServerName::try_from("localhost").expect("valid server name");
+        TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+mod health_tls {
+    use tokio::net::TcpStream;
+
+    pub async fn connect(_stream: TcpStream) -> std::io::Result<TcpStream> {
+        Err(std::io::Error::other(
+            "HTTPS requires Pebble built with the 'tls' feature",
+        ))
+    }
+}
+
+async fn wait_for_exit(pid: u32) {
+    for _ in 0..50 {
+        if !is_process_running(pid) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+pub fn log_path(home: &PebbleHome, name: &str) -> PathBuf {
+    home.site_path(name)
+        .join("logs")
+        .join(format!("{}.log", name))
+}
+
+/// Rotate `path` once it is larger than `max_bytes`: its contents move to
+/// `<path>.1.gz`, older archives shift up by one and those beyond `keep`
+/// are deleted. The file is truncated rather than replaced, so a running
+/// site keeps writing to it. Returns whether the log was rotated.
+pub fn rotate_log(path: &Path, max_bytes: u64, keep: usize) -> Result<bool> {
+    let size = match fs::metadata(path) {
+        Ok(meta) => meta.len(),
+        Err(_) => return Ok(false),
+    };
+    if max_bytes == 0 || size <= max_bytes {
+        return Ok(false);
+    }
+
+    let archive = |n: usize| PathBuf::from(format!("{}.{}.gz", path.display(), n));
+    let _ = fs::remove_file(archive(keep.max(1)));
+    for n in (1..keep.max(1)).rev() {
+        let from = archive(n);
+        if from.exists() {
+            fs::rename(&from, archive(n + 1))?;
+        }
+    }
+
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut encoder = GzEncoder::new(File::create(archive(1))?, Compression::default());
+    std::io::copy(&mut (&mut file).take(size), &mut encoder)?;
+    encoder.finish()?;
+    file.set_len(0)?;
+
+    if keep == 0 {
+        fs::remove_file(archive(1))?;
+    }
+    Ok(true)
+}
+
+/// Print the last `lines` lines of a site's log, then with `follow` keep
+/// printing what is appended until interrupted.
+pub async fn show_logs(path: &Path, lines: usize, follow: bool) -> Result<()> {
+    let mut file =
+        File::open(path).with_context(|| format!("No log file at {}", path.display()))?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    let skip = content.lines().count().saturating_sub(lines);
+    let mut out = std::io::stdout();
+    for line in content.lines().skip(skip) {
+        writeln!(out, "{}", line)?;
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let mut position = content.len() as u64;
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let len = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        if len < position {
+            // Rotated or restarted: read the new contents from the start
+            position = 0;
+        }
+        if len > position {
+            file.seek(SeekFrom::Start(position))?;
+            let mut buf = Vec::new();
+            (&mut file).take(len - position).read_to_end(&mut buf)?;
+            out.write_all(&buf)?;
+            out.flush()?;
+            position = len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+
+    #[test]
+    fn test_rotate_log() {
+        let dir = std::env::temp_dir().join(format!("pebble-rotate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("site.log");
+
+        fs::write(&log, "small\n").unwrap();
+        assert!(!rotate_log(&log, 100, 2).unwrap());
+
+        for round in 1..=3 {
+            fs::write(&log, format!("round {}\n", round).repeat(20)).unwrap();
+            assert!(rotate_log(&log, 100, 2).unwrap());
+            assert_eq!(fs::metadata(&log).unwrap().len(), 0);
+        }
+
+        let mut newest = String::new();
+        GzDecoder::new(File::open(dir.join("site.log.1.gz")).unwrap())
+            .read_to_string(&mut newest)
+            .unwrap();
+        assert!(newest.starts_with("round 3"));
+        assert!(dir.join("site.log.2.gz").exists());
+        assert!(!dir.join("site.log.3.gz").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A site deployed with `tls.enabled` answers health checks over HTTPS
+    /// only, with a certificate for its public name.
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_check_health_over_tls() {
+        use rustls::pki_types::PrivateKeyDer;
+        use std::sync::Arc;
+
+        let site = std::env::temp_dir().join(format!("pebble-health-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&site).unwrap();
+        fs::write(
+            site.join("pebble.toml"),
+            "[site]\ntitle = \"Test\"\ndescription = \"\"\nurl = \"https://example.test\"\n\
+             language = \"en\"\n[server]\n[database]\npath = \"data/pebble.db\"\n[content]\n\
+             [media]\nupload_dir = \"data/media\"\n[theme]\nname = \"default\"\n[auth]\n\
+             [tls]\nenabled = true\ncert_file = \"cert.pem\"\nkey_file = \"key.pem\"\n",
+        )
+        .unwrap();
+        assert!(serves_https(&site, true));
+        assert!(!serves_https(&site, false));
+        fs::remove_dir_all(&site).unwrap();
+
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["example.test".to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let server_config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert.der().clone()],
+            PrivateKeyDer::try_from(key.serialize_der()).unwrap(),
+        )
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                // Plain HTTP fails the handshake and gets no response
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    continue;
+                };
+                let mut request = [0u8; 256];
+                let _ = stream.read(&mut request).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK")
+                    .await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        check_health(port, true).await.unwrap();
+        assert!(check_health(port, false).await.is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! `pebble sync push` and `pebble sync pull`: promote content between two
+//! sites, each either a local site directory or a remote Pebble reached over
+//! its API with a token created with the `sync` permission.
+
+use anyhow::{bail, Context, Result};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{SyncArgs, SyncCommand};
+use crate::services::audit::AuditContext;
+use crate::services::markdown::MarkdownRenderer;
+use crate::services::sync::{self, SiteSnapshot, SyncAction, SyncOptions, SyncPlan, SyncReport};
+use crate::{Config, Database};
+
+pub async fn run(config_path: &Path, command: SyncCommand) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let db = Database::open(&config.database.path)?;
+    db.migrate()?;
+    let this = Site::Local {
+        db,
+        config: Box::new(config),
+    };
+
+    match command {
+        SyncCommand::Push { target, args } => {
+            let target = Site::open(&target, args.token.as_deref())?;
+            sync_sites(&this, &target, &args).await
+        }
+        SyncCommand::Pull { source, args } => {
+            let source = Site::open(&source, args.token.as_deref())?;
+            sync_sites(&source, &this, &args).await
+        }
+        SyncCommand::ResetId => {
+            let Site::Local { db, .. } = &this else {
+                unreachable!()
+            };
+            sync::reset_site_id(db)?;
+            println!("This site now has a new sync id: {}", sync::site_id(db)?);
+            println!("Sites it synced with before will treat it as a new site.");
+            Ok(())
+        }
+    }
+}
+
+async fn sync_sites(source: &Site, target: &Site, args: &SyncArgs) -> Result<()> {
+    let options = SyncOptions {
+        delete: args.delete,
+        force: args.force,
+    };
+    let snapshot = source.snapshot().await?;
+    let plan = target.plan(&snapshot, &options).await?;
+
+    println!("Sync {} -> {}", source.describe(), target.describe());
+    print_plan(&plan);
+
+    if plan.changes.iter().all(|c| !plan.applies(c)) {
+        println!("\nNothing to apply.");
+        return Ok(());
+    }
+    if args.dry_run {
+        println!("\nDry run: nothing was changed.");
+        return Ok(());
+    }
+    if !args.yes {
+        print!("\nApply these changes to {}? [y/N] ", target.describe());
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        if !input.trim().eq_ignore_ascii_case("y") {
+            println!("Sync cancelled.");
+            return Ok(());
+        }
+    }
+
+    let files = plan.media_files(&snapshot);
+    if !files.is_empty() {
+        println!("\nCopying {} media file(s)...", files.len());
+        for file in files {
+            let data = source.read_media_file(&file.name).await?;
+            target.stage_media_file(&file.name, data).await?;
+        }
+    }
+
+    let report = target.apply(&snapshot, &options).await?;
+    print_report(&report);
+    if !report.failed.is_empty() {
+        bail!("{} change(s) failed", report.failed.len());
+    }
+    Ok(())
+}
+
+fn print_plan(plan: &SyncPlan) {
+    if plan.changes.is_empty() {
+        println!(
+            "\nBoth sites are in sync ({} item(s) unchanged).",
+            plan.unchanged
+        );
+        return;
+    }
+    println!();
+    for change in &plan.changes {
+        let symbol = match change.action {
+            SyncAction::Insert => "\x1b[32m+\x1b[0m",
+            SyncAction::Update => "\x1b[33m~\x1b[0m",
+            SyncAction::Delete => "\x1b[31m-\x1b[0m",
+        };
+        print!("  {} {:<8} {}", symbol, change.kind.as_str(), change.key);
+        if change.title != change.key {
+            print!(" ({})", change.title);
+        }
+        match &change.conflict {
+            Some(conflict) if plan.options.force => {
+                println!("  \x1b[31mconflict, forced:\x1b[0m {}", conflict)
+            }
+            Some(conflict) => println!("  \x1b[31mconflict, skipped:\x1b[0m {}", conflict),
+            None => println!(),
+        }
+    }
+    let count = |action| plan.changes.iter().filter(|c| c.action == action).count();
+    println!(
+        "\n{} to insert, {} to update, {} to delete, {} conflict(s), {} unchanged",
+        count(SyncAction::Insert),
+        count(SyncAction::Update),
+        count(SyncAction::Delete),
+        plan.conflicts(),
+        plan.unchanged
+    );
+    if plan.kept > 0 {
+        println!(
+            "{} item(s) exist only on the receiving site and are kept (use --delete to remove them)",
+            plan.kept
+        );
+    }
+    if plan.conflicts() > 0 && !plan.options.force {
+        println!("Conflicts are skipped; use --force to overwrite them.");
+    }
+}
+
+fn print_report(report: &SyncReport) {
+    println!(
+        "\nSynced: {} inserted, {} updated, {} deleted, {} skipped",
+        report.count(SyncAction::Insert),
+        report.count(SyncAction::Update),
+        report.count(SyncAction::Delete),
+        report.skipped.len()
+    );
+    for failed in &report.failed {
+        println!(
+            "  \x1b[31mfailed\x1b[0m {} {} {}: {}",
+            failed.change.action.as_str(),
+            failed.change.kind.as_str(),
+            failed.change.key,
+            failed.error
+        );
+    }
+}
+
+/// One side of a sync.
+enum Site {
+    Local { db: Database, config: Box<Config> },
+    Remote { url: String, token: String },
+}
+
+impl Site {
+    /// A peer is remote when given as an http(s) URL, otherwise it is a site
+    /// directory or the path of its pebble.toml.
+    fn open(peer: &str, token: Option<&str>) -> Result<Self> {
+        if peer.starts_with("http://") || peer.starts_with("https://") {
+            let token = token.context(
+                "A remote site needs an API token with the sync permission: pass --token or set PEBBLE_SYNC_TOKEN",
+            )?;
+            return Ok(Site::Remote {
+                url: peer.trim_end_matches('/').to_string(),
+                token: token.to_string(),
+            });
+        }
+
+        let path = PathBuf::from(peer);
+        let config_path = if path.is_dir() {
+            path.join("pebble.toml")
+        } else {
+            path
+        };
+        if !config_path.is_file() {
+            bail!(
+                "No Pebble site at {} (expected a site directory or its pebble.toml)",
+                peer
+            );
+        }
+        let mut config = Config::load(&config_path)?;
+        let root = config_path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .canonicalize()?;
+        config.resolve_paths(&root);
+        let db = Database::open(&config.database.path)?;
+        db.migrate()?;
+        Ok(Site::Local {
+            db,
+            config: Box::new(config),
+        })
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Site::Local { config, .. } => format!("{} ({})", config.site.title, config.site.url),
+            Site::Remote { url, .. } => url.clone(),
+        }
+    }
+
+    async fn snapshot(&self) -> Result<SiteSnapshot> {
+        match self {
+            Site::Local { db, config } => sync::snapshot(db, config),
+            Site::Remote { .. } => self.request("GET", "snapshot", None).await,
+        }
+    }
+
+    async fn plan(&self, source: &SiteSnapshot, options: &SyncOptions) -> Result<SyncPlan> {
+        match self {
+            Site::Local { db, config } => sync::plan(db, config, source, options),
+            Site::Remote { .. } => {
+                self.request("POST", "plan", Some(sync_request(source, options)))
+                    .await
+            }
+        }
+    }
+
+    async fn apply(&self, source: &SiteSnapshot, options: &SyncOptions) -> Result<SyncReport> {
+        match self {
+            Site::Local { db, config } => {
+                let mut ctx = AuditContext::new();
+                ctx.username = Some("cli".to_string());
+                let renderer = MarkdownRenderer::for_site(config)?;
+                sync::apply(db, config, &renderer, source, options, &ctx)
+            }
+            Site::Remote { .. } => {
+                self.request("POST", "apply", Some(sync_request(source, options)))
+                    .await
+            }
+        }
+    }
+
+    async fn read_media_file(&self, name: &str) -> Result<Vec<u8>> {
+        match self {
+            Site::Local { config, .. } => {
+                sync::read_media_file(Path::new(&config.media.upload_dir), name)
+            }
+            Site::Remote { .. } => self.transfer_media(name, None).await,
+        }
+    }
+
+    async fn stage_media_file(&self, name: &str, data: Vec<u8>) -> Result<()> {
+        match self {
+            Site::Local { config, .. } => {
+                sync::stage_media_file(Path::new(&config.media.upload_dir), name, &data)
+            }
+            Site::Remote { .. } => self.transfer_media(name, Some(data)).await.map(|_| ()),
+        }
+    }
+
+    #[cfg(feature = "webhooks")]
+    async fn request<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        endpoint: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T> {
+        let Site::Remote { url, token } = self else {
+            unreachable!()
+        };
+        let endpoint = format!("{}/api/v1/sync/{}", url, endpoint);
+        let client = crate::services::webhook::http_client();
+        let request = match method {
+            "POST" => client.post(&endpoint).json(&body),
+            _ => client.get(&endpoint),
+        };
+        let response = request
+            .bearer_auth(token)
+            .timeout(std::time::Duration::from_secs(300))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", url))?;
+        let status = response.status();
+        let json: serde_json::Value = response.json().await.unwrap_or_default();
+        if !status.is_success() {
+            let message = json["message"]
+                .as_str()
+                .or(json["error"].as_str())
+                .unwrap_or("");
+            bail!("{} answered {}: {}", url, status, message);
+        }
+        Ok(serde_json::from_value(json["data"].clone())?)
+    }
+
+    #[cfg(feature = "webhooks")]
+    async fn transfer_media(&self, name: &str, upload: Option<Vec<u8>>) -> Result<Vec<u8>> {
+        let Site::Remote { url, token } = self else {
+            unreachable!()
+        };
+        let endpoint = format!("{}/api/v1/sync/media/{}", url, name);
+        let client = crate::services::webhook::http_client();
+        let request = match upload {
+            Some(data) => client.put(&endpoint).body(data),
+            None => client.get(&endpoint),
+        };
+        let response = request
+            .bearer_auth(token)
+            .timeout(std::time::Duration::from_secs(300))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", url))?;
+        if !response.status().is_success() {
+            bail!(
+                "{} answered {} for media file {}",
+                url,
+                response.status(),
+                name
+            );
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    #[cfg(not(feature = "webhooks"))]
+    async fn request<T>(
+        &self,
+        _method: &str,
+        _endpoint: &str,
+        _body: Option<serde_json::Value>,
+    ) -> Result<T> {
+        bail!("Syncing with a remote site requires the 'webhooks' feature")
+    }
+
+    #[cfg(not(feature = "webhooks"))]
+    async fn transfer_media(&self, _name: &str, _upload: Option<Vec<u8>>) -> Result<Vec<u8>> {
+        bail!("Syncing with a remote site requires the 'webhooks' feature")
+    }
+}
+
+fn sync_request(source: &SiteSnapshot, options: &SyncOptions) -> serde_json::Value {
+    serde_json::json!({
+        "source": source,
+        "options": options,
+    })
+}
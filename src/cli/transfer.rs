@@ -0,0 +1,685 @@
+//! Moving sites into, within and out of the registry: `pebble registry adopt`,
+//! `clone`, `pack` and `unpack`.
+//!
+//! Every transfer copies a site the same way: the site directory without its
+//! logs and backups, a consistent snapshot of the database taken with the
+//! SQLite backup API (so running sites can be copied) and the media
+//! directory. The copy always uses the registry layout, `data/pebble.db` and
+//! `data/media`, and its `pebble.toml` is rewritten to match.
+
+use super::registry::is_valid_site_name;
+use crate::global::{GlobalConfig, PebbleHome, Registry, RegistrySite, SiteStatus};
+use crate::services::{slug, sync};
+use crate::{Config, Database};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// Version of the archive layout written by `pack`.
+const ARCHIVE_FORMAT: u32 = 1;
+
+/// Where site files live inside a packed archive.
+const ARCHIVE_SITE_DIR: &str = "site";
+
+/// `manifest.json` at the root of a packed archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct PackManifest {
+    format: u32,
+    pebble_version: String,
+    name: String,
+    title: String,
+    #[serde(default)]
+    description: String,
+    created_at: String,
+    packed_at: String,
+}
+
+/// Settings rewritten in a copied site's `pebble.toml`.
+#[derive(Debug, Default)]
+struct ConfigChanges {
+    url: Option<String>,
+    port: Option<u16>,
+    /// Point `backup.directory` at `./backups` because the original was
+    /// outside the site
+    reset_backup_dir: bool,
+}
+
+/// Copy an existing site directory into the registry.
+pub fn adopt(
+    home: &PebbleHome,
+    registry: &mut Registry,
+    path: &Path,
+    name: Option<String>,
+) -> Result<()> {
+    let source = path
+        .canonicalize()
+        .with_context(|| format!("Site directory not found: {}", path.display()))?;
+    if !source.join("pebble.toml").exists() {
+        bail!("No pebble.toml found in {}", source.display());
+    }
+    if source.starts_with(&home.registry_dir) {
+        bail!("{} is already inside the registry", source.display());
+    }
+
+    let name = match name {
+        Some(name) => name,
+        None => slug::generate_slug(
+            &source
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        ),
+    };
+    let site_path = new_site_path(home, registry, &name)?;
+
+    let changes = copy_site(&source, &site_path)?;
+    let config = finish_copy(&site_path, &changes)?;
+    register(registry, &name, &config, None)?;
+
+    println!("Adopted '{}' from {}", name, source.display());
+    println!("Site directory: {}", site_path.display());
+    println!("The original directory was left in place.");
+    Ok(())
+}
+
+/// Copy a registry site to a new name, on its own port and URL.
+pub fn clone(
+    home: &PebbleHome,
+    global_config: &GlobalConfig,
+    registry: &mut Registry,
+    source: &str,
+    target: &str,
+    url: Option<String>,
+    port: Option<u16>,
+) -> Result<()> {
+    let source_site = registry
+        .get_site(source)
+        .context(format!("Site '{}' not found in registry", source))?
+        .clone();
+    let site_path = new_site_path(home, registry, target)?;
+
+    let port = match port {
+        Some(port) => port,
+        None => free_site_port(home, global_config, registry)?,
+    };
+    let url = url.unwrap_or_else(|| format!("http://localhost:{}", port));
+
+    let mut changes = copy_site(&home.site_path(source), &site_path)?;
+    changes.url = Some(url.clone());
+    changes.port = Some(port);
+    let config = finish_copy(&site_path, &changes)?;
+    // A clone is a site of its own to the sites the original syncs with
+    let mut resolved = config.clone();
+    resolved.resolve_paths(&site_path);
+    sync::reset_site_id(&Database::open(&resolved.database.path)?)?;
+    register(registry, target, &config, Some(&source_site.description))?;
+
+    println!(
+        "Cloned '{}' to '{}' at {}",
+        source,
+        target,
+        site_path.display()
+    );
+    println!("URL: {}", url);
+    println!("Run: pebble registry serve {} to start it", target);
+    Ok(())
+}
+
+/// Write a registry site to a single archive that `unpack` can import.
+pub fn pack(
+    home: &PebbleHome,
+    registry: &Registry,
+    name: &str,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let site = registry
+        .get_site(name)
+        .context(format!("Site '{}' not found in registry", name))?;
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.pebble.zip", name)));
+    if output.exists() {
+        bail!("Output file already exists: {}", output.display());
+    }
+
+    // Copy to a staging directory first so the archive gets the same
+    // rewritten layout as a clone
+    let staging = home
+        .root
+        .join("tmp")
+        .join(format!("pack-{}-{}", name, std::process::id()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    let result = copy_site(&home.site_path(name), &staging)
+        .and_then(|changes| finish_copy(&staging, &changes))
+        .and_then(|_| {
+            let manifest = PackManifest {
+                format: ARCHIVE_FORMAT,
+                pebble_version: env!("CARGO_PKG_VERSION").to_string(),
+                name: site.name.clone(),
+                title: site.title.clone(),
+                description: site.description.clone(),
+                created_at: site.created_at.clone(),
+                packed_at: chrono::Utc::now().to_rfc3339(),
+            };
+            write_archive(&staging, &manifest, &output)
+        });
+    let _ = fs::remove_dir_all(&staging);
+    if result.is_err() {
+        let _ = fs::remove_file(&output);
+    }
+    let files = result?;
+
+    let size_mb = fs::metadata(&output)?.len() as f64 / (1024.0 * 1024.0);
+    println!(
+        "Packed '{}' into {} ({} files, {:.2} MB)",
+        name,
+        output.display(),
+        files,
+        size_mb
+    );
+    Ok(())
+}
+
+/// Import a site from an archive written by `pack`.
+pub fn unpack(
+    home: &PebbleHome,
+    registry: &mut Registry,
+    archive_path: &Path,
+    name: Option<String>,
+    url: Option<String>,
+    port: Option<u16>,
+) -> Result<()> {
+    let file = File::open(archive_path)
+        .with_context(|| format!("Archive not found: {}", archive_path.display()))?;
+    let mut archive = ZipArchive::new(file).context("Not a Pebble site archive")?;
+    let manifest = read_manifest(&mut archive)?;
+
+    let name = name.unwrap_or_else(|| manifest.name.clone());
+    let site_path = new_site_path(home, registry, &name)?;
+
+    let result = extract_archive(&mut archive, &site_path).and_then(|_| {
+        let changes = ConfigChanges {
+            url,
+            port,
+            ..Default::default()
+        };
+        finish_copy(&site_path, &changes)
+    });
+    let config = match result {
+        Ok(config) => config,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&site_path);
+            return Err(e);
+        }
+    };
+
+    register(registry, &name, &config, Some(&manifest.description))?;
+    if let Some(site) = registry.get_site_mut(&name) {
+        site.created_at = manifest.created_at;
+    }
+
+    println!(
+        "Unpacked '{}' from {} (packed with Pebble {} at {})",
+        name,
+        archive_path.display(),
+        manifest.pebble_version,
+        manifest.packed_at
+    );
+    println!("Site directory: {}", site_path.display());
+    println!("URL: {}", config.site.url);
+    Ok(())
+}
+
+/// The directory for a new registry site, after checking the name is valid
+/// and free.
+fn new_site_path(home: &PebbleHome, registry: &Registry, name: &str) -> Result<PathBuf> {
+    if !is_valid_site_name(name) {
+        bail!(
+            "Invalid site name '{}': must be lowercase alphanumeric with hyphens only",
+            name
+        );
+    }
+    if registry.get_site(name).is_some() {
+        bail!("Site '{}' already registered", name);
+    }
+    let site_path = home.site_path(name);
+    if site_path.exists() {
+        bail!("Site directory already exists: {}", site_path.display());
+    }
+    Ok(site_path)
+}
+
+fn register(
+    registry: &mut Registry,
+    name: &str,
+    config: &Config,
+    description: Option<&str>,
+) -> Result<()> {
+    registry.add_site(RegistrySite {
+        name: name.to_string(),
+        title: config.site.title.clone(),
+        description: description
+            .map(str::to_string)
+            .unwrap_or_else(|| config.site.description.clone()),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        status: SiteStatus::Stopped,
+        port: None,
+        pid: None,
+        last_started: None,
+        domains: Vec::new(),
+        supervision: Default::default(),
+    })
+}
+
+/// Copy the site in `source` to `target` in the registry layout. On failure
+/// the partial copy is removed.
+fn copy_site(source: &Path, target: &Path) -> Result<ConfigChanges> {
+    let result = copy_site_files(source, target);
+    if result.is_err() {
+        let _ = fs::remove_dir_all(target);
+    }
+    result
+}
+
+fn copy_site_files(source: &Path, target: &Path) -> Result<ConfigChanges> {
+    let source = source.canonicalize()?;
+    let config_path = source.join("pebble.toml");
+    let raw = Config::load(&config_path)?;
+    let mut config = raw.clone();
+    config.resolve_paths(&source);
+
+    let database = PathBuf::from(&config.database.path);
+    let media = PathBuf::from(&config.media.upload_dir);
+    let backups = PathBuf::from(&config.backup.directory);
+
+    let mut skip: HashSet<PathBuf> = ["", "-wal", "-shm", "-journal"]
+        .iter()
+        .map(|suffix| PathBuf::from(format!("{}{}", config.database.path, suffix)))
+        .collect();
+    skip.insert(media.clone());
+    skip.insert(backups);
+    skip.insert(source.join("logs"));
+
+    fs::create_dir_all(target).with_context(|| format!("Failed to create {}", target.display()))?;
+    copy_tree(&source, target, &skip)?;
+
+    let data = target.join("data");
+    fs::create_dir_all(&data)?;
+    if database.exists() {
+        snapshot_database(&database, &data.join("pebble.db"))?;
+    }
+    fs::create_dir_all(data.join("media"))?;
+    if media.is_dir() {
+        copy_tree(&media, &data.join("media"), &HashSet::new())?;
+    }
+
+    Ok(ConfigChanges {
+        reset_backup_dir: Path::new(&raw.backup.directory).is_absolute(),
+        ..Default::default()
+    })
+}
+
+/// Recursively copy `source` into `target`, leaving out the paths in `skip`.
+/// Symlinks are followed; other special files are ignored.
+fn copy_tree(source: &Path, target: &Path, skip: &HashSet<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(source)? {
+        let path = entry?.path();
+        if skip.contains(&path) {
+            continue;
+        }
+        let Some(file_name) = path.file_name() else {
+            continue;
+        };
+        let dest = target.join(file_name);
+        if path.is_dir() {
+            fs::create_dir_all(&dest)?;
+            copy_tree(&path, &dest, skip)?;
+        } else if path.is_file() {
+            fs::copy(&path, &dest).with_context(|| format!("Failed to copy {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy a database with the SQLite backup API, which gives a consistent
+/// snapshot even while a server is writing to it.
+fn snapshot_database(source: &Path, target: &Path) -> Result<()> {
+    let conn = rusqlite::Connection::open_with_flags(
+        source,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .with_context(|| format!("Failed to open database {}", source.display()))?;
+    conn.backup(rusqlite::DatabaseName::Main, target, None)
+        .with_context(|| format!("Failed to copy database {}", source.display()))?;
+    Ok(())
+}
+
+/// Point a copied site's config at the registry layout, apply `changes` and
+/// return the resulting config.
+fn finish_copy(site_path: &Path, changes: &ConfigChanges) -> Result<Config> {
+    let config_path = site_path.join("pebble.toml");
+    let content = fs::read_to_string(&config_path)?;
+    let mut doc = content
+        .parse::<toml_edit::DocumentMut>()
+        .context("Failed to parse config file")?;
+
+    doc["database"]["path"] = toml_edit::value("./data/pebble.db");
+    doc["media"]["upload_dir"] = toml_edit::value("./data/media");
+    if changes.reset_backup_dir {
+        doc["backup"]["directory"] = toml_edit::value("./backups");
+    }
+    if let Some(ref url) = changes.url {
+        doc["site"]["url"] = toml_edit::value(url.as_str());
+    }
+    if let Some(port) = changes.port {
+        doc["server"]["port"] = toml_edit::value(i64::from(port));
+    }
+
+    fs::write(&config_path, doc.to_string())?;
+    Config::load(&config_path)
+}
+
+/// A port in the registry's auto-assign range that no other site is
+/// configured to use and nothing is listening on.
+fn free_site_port(
+    home: &PebbleHome,
+    global_config: &GlobalConfig,
+    registry: &Registry,
+) -> Result<u16> {
+    let configured: HashSet<u16> = registry
+        .list_sites()
+        .iter()
+        .filter_map(|site| Config::load(&home.site_path(&site.name).join("pebble.toml")).ok())
+        .map(|config| config.server.port)
+        .collect();
+    let range = &global_config.registry;
+    (range.auto_port_range_start..=range.auto_port_range_end)
+        .filter(|port| !configured.contains(port))
+        .find(|port| registry.find_available_port(*port, *port).is_some())
+        .context("No free port left in the registry's auto-assign range; pass --port")
+}
+
+/// Zip `staging` under `site/` with the manifest at the root, returning the
+/// number of site files written.
+fn write_archive(staging: &Path, manifest: &PackManifest, output: &Path) -> Result<usize> {
+    if let Some(parent) = output.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let file =
+        File::create(output).with_context(|| format!("Failed to create {}", output.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(true);
+
+    zip.start_file("manifest.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(manifest)?.as_bytes())?;
+
+    let mut files = 0;
+    let mut pending = vec![staging.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(staging)?;
+            let name = std::iter::once(ARCHIVE_SITE_DIR.to_string())
+                .chain(
+                    relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+                )
+                .collect::<Vec<_>>()
+                .join("/");
+            zip.start_file(name, options)?;
+            let mut source = File::open(&path)?;
+            std::io::copy(&mut source, &mut zip)?;
+            files += 1;
+        }
+    }
+
+    zip.finish()?;
+    Ok(files)
+}
+
+fn read_manifest(archive: &mut ZipArchive<File>) -> Result<PackManifest> {
+    let mut content = String::new();
+    archive
+        .by_name("manifest.json")
+        .context("Not a Pebble site archive: manifest.json is missing")?
+        .read_to_string(&mut content)?;
+    let manifest: PackManifest =
+        serde_json::from_str(&content).context("Invalid manifest.json in site archive")?;
+    if manifest.format > ARCHIVE_FORMAT {
+        bail!(
+            "The archive was packed by a newer Pebble ({}); upgrade to unpack it",
+            manifest.pebble_version
+        );
+    }
+    Ok(manifest)
+}
+
+/// Extract the `site/` entries of an archive into `site_path`, refusing
+/// entries that would land outside it.
+fn extract_archive(archive: &mut ZipArchive<File>, site_path: &Path) -> Result<()> {
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let Some(path) = entry.enclosed_name() else {
+            tracing::warn!("Skipping suspicious path in archive: {}", entry.name());
+            continue;
+        };
+        let Ok(relative) = path.strip_prefix(ARCHIVE_SITE_DIR) else {
+            continue;
+        };
+        // `enclosed_name` allows `site/../x`, which stays inside the archive
+        // root but not inside `site/`
+        if !relative
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            bail!("Refusing path outside site/ in archive: {}", entry.name());
+        }
+        let outpath = site_path.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&outpath)?;
+            continue;
+        }
+        if let Some(parent) = outpath.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut outfile = File::create(&outpath)?;
+        std::io::copy(&mut entry, &mut outfile)?;
+    }
+    if !site_path.join("pebble.toml").exists() {
+        bail!("The archive does not contain a pebble.toml");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_site(dir: &Path, config: &str) {
+        fs::create_dir_all(dir.join("data/media/2024")).unwrap();
+        fs::create_dir_all(dir.join("logs")).unwrap();
+        fs::create_dir_all(dir.join("templates")).unwrap();
+        fs::write(dir.join("pebble.toml"), config).unwrap();
+        fs::write(dir.join("data/media/2024/cover.png"), b"png").unwrap();
+        fs::write(dir.join("logs/site.log"), b"log").unwrap();
+        fs::write(dir.join("templates/post.html"), b"<article>").unwrap();
+        let conn = rusqlite::Connection::open(dir.join("data/pebble.db")).unwrap();
+        conn.execute_batch("CREATE TABLE notes (body TEXT); INSERT INTO notes VALUES ('hello');")
+            .unwrap();
+    }
+
+    fn site_config(backups: &Path) -> String {
+        format!(
+            "[site]\ntitle = \"Blog\"\ndescription = \"\"\nurl = \"https://blog.example.com\"\nlanguage = \"en\"\n\n\
+             [server]\nhost = \"127.0.0.1\"\nport = 3000\n\n\
+             [database]\npath = \"data/pebble.db\"\n\n\
+             [content]\nposts_per_page = 10\nexcerpt_length = 200\nauto_excerpt = true\n\n\
+             [media]\nupload_dir = \"data/media\"\nmax_upload_size = \"10MB\"\n\n\
+             [theme]\nname = \"default\"\n\n\
+             [auth]\nsession_lifetime = \"7d\"\n\n\
+             [backup]\ndirectory = \"{}\"\n",
+            backups.display()
+        )
+    }
+
+    fn test_home(root: &Path) -> PebbleHome {
+        PebbleHome {
+            root: root.to_path_buf(),
+            config_path: root.join("config.toml"),
+            registry_dir: root.join("registry"),
+            registry_path: root.join("registry.toml"),
+        }
+    }
+
+    #[test]
+    fn test_copy_site_uses_registry_layout() {
+        let tmp = std::env::temp_dir().join(format!("pebble-transfer-{}", std::process::id()));
+        let source = tmp.join("blog");
+        let backups = tmp.join("shared-backups");
+        write_site(&source, &site_config(&backups));
+
+        let target = tmp.join("copy");
+        let mut changes = copy_site(&source, &target).unwrap();
+        assert!(changes.reset_backup_dir);
+        changes.url = Some("http://localhost:3005".to_string());
+        changes.port = Some(3005);
+        let config = finish_copy(&target, &changes).unwrap();
+
+        assert_eq!(config.database.path, "./data/pebble.db");
+        assert_eq!(config.media.upload_dir, "./data/media");
+        assert_eq!(config.backup.directory, "./backups");
+        assert_eq!(config.site.url, "http://localhost:3005");
+        assert_eq!(config.server.port, 3005);
+        assert!(target.join("templates/post.html").exists());
+        assert!(target.join("data/media/2024/cover.png").exists());
+        assert!(!target.join("logs").exists());
+
+        let conn = rusqlite::Connection::open(target.join("data/pebble.db")).unwrap();
+        let body: String = conn
+            .query_row("SELECT body FROM notes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(body, "hello");
+
+        drop(conn);
+        fs::remove_dir_all(&tmp).unwrap();
+    }
+
+    #[test]
+    fn test_pack_unpack_round_trip() {
+        let tmp = std::env::temp_dir().join(format!("pebble-pack-{}", uuid::Uuid::new_v4()));
+        let home = test_home(&tmp);
+        let mut registry = Registry::default();
+        write_site(
+            &home.site_path("blog"),
+            &site_config(&tmp.join("shared-backups")),
+        );
+        let config = Config::load(&home.site_path("blog").join("pebble.toml")).unwrap();
+        register(&mut registry, "blog", &config, Some("Notes and photos")).unwrap();
+        registry.get_site_mut("blog").unwrap().created_at = "2024-01-01T00:00:00+00:00".to_string();
+
+        let archive = tmp.join("out/blog.pebble.zip");
+        pack(&home, &registry, "blog", Some(archive.clone())).unwrap();
+        assert!(pack(&home, &registry, "blog", Some(archive.clone())).is_err());
+        assert!(!home.root.join("tmp").read_dir().unwrap().any(|_| true));
+
+        unpack(
+            &home,
+            &mut registry,
+            &archive,
+            Some("copy".to_string()),
+            Some("http://localhost:3005".to_string()),
+            Some(3005),
+        )
+        .unwrap();
+
+        let target = home.site_path("copy");
+        let config = Config::load(&target.join("pebble.toml")).unwrap();
+        assert_eq!(config.database.path, "./data/pebble.db");
+        assert_eq!(config.media.upload_dir, "./data/media");
+        assert_eq!(config.backup.directory, "./backups");
+        assert_eq!(config.site.url, "http://localhost:3005");
+        assert_eq!(config.server.port, 3005);
+        assert_eq!(
+            fs::read(target.join("data/media/2024/cover.png")).unwrap(),
+            b"png"
+        );
+        assert!(target.join("templates/post.html").exists());
+        assert!(!target.join("logs").exists());
+
+        let conn = rusqlite::Connection::open(target.join("data/pebble.db")).unwrap();
+        let body: String = conn
+            .query_row("SELECT body FROM notes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(body, "hello");
+        drop(conn);
+
+        let site = registry.get_site("copy").unwrap();
+        assert_eq!(site.description, "Notes and photos");
+        assert_eq!(site.created_at, "2024-01-01T00:00:00+00:00");
+        // The name is taken now
+        assert!(unpack(
+            &home,
+            &mut registry,
+            &archive,
+            Some("copy".to_string()),
+            None,
+            None
+        )
+        .is_err());
+
+        fs::remove_dir_all(&tmp).unwrap();
+    }
+
+    #[test]
+    fn test_extract_archive_refuses_paths_outside_site() {
+        let tmp = std::env::temp_dir().join(format!("pebble-unpack-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&tmp).unwrap();
+        let archive_path = tmp.join("evil.zip");
+        let write_zip = |entries: &[(&str, &str)]| {
+            let mut zip = ZipWriter::new(File::create(&archive_path).unwrap());
+            for (name, contents) in entries {
+                zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(contents.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        };
+
+        // Entries outside site/ are skipped
+        write_zip(&[
+            ("site/pebble.toml", "[site]"),
+            ("other/notes.txt", "skipped"),
+        ]);
+        let site_path = tmp.join("registry/site");
+        let mut archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        extract_archive(&mut archive, &site_path).unwrap();
+        assert!(site_path.join("pebble.toml").exists());
+        assert!(!site_path.join("other").exists());
+        assert!(!site_path.join("notes.txt").exists());
+        assert!(!tmp.join("registry/other").exists());
+
+        // An entry that climbs out of site/ is refused
+        fs::remove_dir_all(&site_path).unwrap();
+        write_zip(&[("site/pebble.toml", "[site]"), ("site/../evil", "escaped")]);
+        let mut archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let err = extract_archive(&mut archive, &site_path).unwrap_err();
+        assert!(err.to_string().contains("site/../evil"), "{}", err);
+        assert!(!tmp.join("registry/evil").exists());
+        assert!(!site_path.join("evil").exists());
+
+        fs::remove_dir_all(&tmp).unwrap();
+    }
+}
@@ -19,6 +19,24 @@ pub struct Config {
     pub api: ApiConfig,
     #[serde(default)]
     pub backup: BackupConfig,
+    #[serde(default)]
+    pub comments: CommentsConfig,
+    #[serde(default)]
+    pub webmentions: WebmentionsConfig,
+    #[serde(default)]
+    pub newsletter: NewsletterConfig,
+    #[serde(default)]
+    pub link_checker: LinkCheckerConfig,
+    #[serde(default)]
+    pub social_cards: SocialCardsConfig,
+    #[serde(default)]
+    pub i18n: I18nConfig,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub content_types: Vec<ContentTypeConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -36,6 +54,25 @@ pub struct ServerConfig {
     pub host: String,
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Where to listen instead of host and port: `host:port`,
+    /// `unix:/path/to.sock` or `systemd` for socket activation
+    #[serde(default)]
+    pub listen: Option<String>,
+    /// Address for a second listener serving the admin in production
+    /// (`pebble deploy`), e.g. `127.0.0.1:8081`
+    #[serde(default)]
+    pub admin_listen: Option<String>,
+    /// Octal permission mode for Unix sockets Pebble creates, e.g. `660`
+    #[serde(default)]
+    pub socket_mode: Option<String>,
+    /// Addresses or CIDR ranges of reverse proxies whose forwarding header
+    /// is believed
+    #[serde(default = "default_trusted_proxies")]
+    pub trusted_proxies: Vec<String>,
+    /// The one header trusted proxies record the client address in, e.g.
+    /// `x-forwarded-for`, `x-real-ip` or `forwarded`
+    #[serde(default = "default_client_ip_header")]
+    pub client_ip_header: String,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -56,6 +93,21 @@ pub struct ContentConfig {
     /// Number of versions to keep per content item (0 = unlimited)
     #[serde(default = "default_version_retention")]
     pub version_retention: usize,
+    /// Directory of `<name>.html` Tera templates defining site shortcodes
+    #[serde(default = "default_shortcodes_dir")]
+    pub shortcodes_dir: String,
+    /// Code highlighting theme: a bundled syntect theme name or a `.tmTheme` file
+    #[serde(default = "default_highlight_theme")]
+    pub highlight_theme: String,
+    /// Code highlighting theme used with the dark colour scheme
+    #[serde(default = "default_highlight_theme_dark")]
+    pub highlight_theme_dark: String,
+    /// Number the lines of every code block (blocks can opt out with `{nolinenos}`)
+    #[serde(default)]
+    pub line_numbers: bool,
+    /// Add a permalink anchor, shown on hover, to every heading
+    #[serde(default)]
+    pub heading_anchors: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -319,6 +371,339 @@ impl Default for BackupConfig {
     }
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CommentsConfig {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// Hold new comments in the moderation queue until approved
+    #[serde(default = "default_true")]
+    pub require_approval: bool,
+    /// Maximum reply nesting below a top-level comment (0 = flat)
+    #[serde(default = "default_comment_max_depth")]
+    pub max_depth: usize,
+}
+
+impl Default for CommentsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            require_approval: true,
+            max_depth: default_comment_max_depth(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebmentionsConfig {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// Notify linked sites when a post or page is published or updated
+    #[serde(default = "default_true")]
+    pub send_on_publish: bool,
+    /// Also accept Pingbacks via XML-RPC at /xmlrpc
+    #[serde(default = "default_true")]
+    pub pingback: bool,
+    /// Fetch mention sources, and the targets and endpoints of outgoing
+    /// mentions, on loopback, private and link-local addresses. Off by
+    /// default so neither can reach the server's own network.
+    #[serde(default = "default_false")]
+    pub allow_private_sources: bool,
+}
+
+impl Default for WebmentionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            send_on_publish: true,
+            pingback: true,
+            allow_private_sources: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewsletterConfig {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// Sender shown to subscribers, e.g. "My Blog <news@example.com>"
+    #[serde(default)]
+    pub from_address: String,
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    #[serde(default = "default_smtp_host")]
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub smtp_username: Option<String>,
+    #[serde(default)]
+    pub smtp_password: Option<String>,
+    /// "starttls", "tls" (implicit TLS, usually port 465) or "none"
+    #[serde(default = "default_smtp_security")]
+    pub smtp_security: String,
+    /// Messages sent per batch before pausing
+    #[serde(default = "default_newsletter_batch_size")]
+    pub batch_size: usize,
+    /// Pause between batches, in milliseconds
+    #[serde(default = "default_newsletter_batch_delay")]
+    pub batch_delay_ms: u64,
+}
+
+impl Default for NewsletterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            from_address: String::new(),
+            reply_to: None,
+            smtp_host: default_smtp_host(),
+            smtp_port: default_smtp_port(),
+            smtp_username: None,
+            smtp_password: None,
+            smtp_security: default_smtp_security(),
+            batch_size: default_newsletter_batch_size(),
+            batch_delay_ms: default_newsletter_batch_delay(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LinkCheckerConfig {
+    /// Check external links in the background while the server runs
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// How often the background checker runs
+    #[serde(default = "default_link_check_interval")]
+    pub interval_hours: u64,
+    /// Links checked more recently than this are skipped by background runs
+    #[serde(default = "default_link_recheck_after")]
+    pub recheck_after_hours: u64,
+    /// Number of hosts checked at the same time
+    #[serde(default = "default_link_check_concurrency")]
+    pub concurrency: usize,
+    /// Pause between requests to the same host, in milliseconds
+    #[serde(default = "default_link_check_host_delay")]
+    pub host_delay_ms: u64,
+    #[serde(default = "default_link_check_timeout")]
+    pub timeout_secs: u64,
+    /// Skip URLs that the linked site's robots.txt disallows
+    #[serde(default = "default_true")]
+    pub respect_robots: bool,
+}
+
+impl Default for LinkCheckerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: default_link_check_interval(),
+            recheck_after_hours: default_link_recheck_after(),
+            concurrency: default_link_check_concurrency(),
+            host_delay_ms: default_link_check_host_delay(),
+            timeout_secs: default_link_check_timeout(),
+            respect_robots: true,
+        }
+    }
+}
+
+/// Generated Open Graph images for posts that have no featured or social image.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SocialCardsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl Default for SocialCardsConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Additional content languages. `site.language` is the default language and
+/// is served without a prefix; each language listed here gets `/<code>/` routes.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct I18nConfig {
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// Directory of `<code>.toml` message catalogs overriding the built-in UI strings
+    #[serde(default = "default_catalog_dir")]
+    pub catalog_dir: String,
+}
+
+impl Default for I18nConfig {
+    fn default() -> Self {
+        Self {
+            languages: Vec::new(),
+            catalog_dir: default_catalog_dir(),
+        }
+    }
+}
+
+/// HTTPS served by `pebble deploy` itself, with a certificate from files or
+/// obtained and renewed over ACME (Let's Encrypt by default).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TlsConfig {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// PEM certificate chain and private key; without them ACME is used
+    #[serde(default)]
+    pub cert_file: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+    /// Names on the certificate; the host of `site.url` when empty
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// Contact address for the ACME account, told about expiry problems
+    #[serde(default)]
+    pub acme_email: Option<String>,
+    #[serde(default = "default_acme_directory")]
+    pub acme_directory: String,
+    /// Extra root certificate (PEM) to trust for the ACME server, for test servers
+    #[serde(default)]
+    pub acme_ca_file: Option<String>,
+    /// Where the ACME account key and the obtained certificate are kept
+    #[serde(default = "default_tls_storage_dir")]
+    pub storage_dir: String,
+    /// Renew an ACME certificate this many days before it expires
+    #[serde(default = "default_tls_renew_days")]
+    pub renew_days: u32,
+    /// Plain HTTP listener answering ACME challenges and redirecting
+    /// everything else to HTTPS; empty to disable
+    #[serde(default = "default_tls_redirect_listen")]
+    pub redirect_listen: String,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cert_file: None,
+            key_file: None,
+            domains: Vec::new(),
+            acme_email: None,
+            acme_directory: default_acme_directory(),
+            acme_ca_file: None,
+            storage_dir: default_tls_storage_dir(),
+            renew_days: default_tls_renew_days(),
+            redirect_listen: default_tls_redirect_listen(),
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Whether the certificate comes from ACME rather than from files.
+    pub fn uses_acme(&self) -> bool {
+        self.cert_file.is_none()
+    }
+
+    /// The names on the certificate.
+    pub fn domains(&self, site_url: &str) -> Vec<String> {
+        if !self.domains.is_empty() {
+            return self.domains.clone();
+        }
+        url::Url::parse(site_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Prometheus metrics at `/metrics`, for a bearer token on the site's own
+/// address or served only on a separate, private `listen` address.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// Required as `Authorization: Bearer <token>` when set
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Serve `/metrics` on this address only, instead of on the site's
+    #[serde(default)]
+    pub listen: Option<String>,
+}
+
+impl I18nConfig {
+    /// Whether `code` is one of the additional (prefixed) languages.
+    pub fn is_additional(&self, code: &str) -> bool {
+        self.languages.iter().any(|l| l == code)
+    }
+}
+
+/// A user-defined content type, declared as a `[[content_types]]` table.
+/// Entries of the type are listed at `/<prefix>` and served at `/<prefix>/<slug>`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ContentTypeConfig {
+    /// Identifier stored with each entry, e.g. "project"
+    pub name: String,
+    /// Plural display name used in the admin and on the listing page
+    #[serde(default)]
+    pub label: Option<String>,
+    /// URL prefix; defaults to the name
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Template file for the listing page, relative to the site root
+    #[serde(default)]
+    pub list_template: Option<String>,
+    /// Template file for a single entry, relative to the site root
+    #[serde(default)]
+    pub detail_template: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<FieldConfig>,
+}
+
+impl ContentTypeConfig {
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Public path of an entry of this type.
+    pub fn entry_path(&self, slug: &str) -> String {
+        format!("/{}/{}", self.prefix(), slug)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Text,
+    Number,
+    Date,
+    Media,
+    Select,
+    Reference,
+}
+
+/// One typed field of a custom content type.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FieldConfig {
+    /// Key the value is stored under, e.g. "repo_url"
+    pub name: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    /// Allowed values of a `select` field
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// Content type a `reference` field points to: "post", "page" or a custom type name
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Hint shown under the control in the admin editor
+    #[serde(default)]
+    pub help: Option<String>,
+}
+
+impl FieldConfig {
+    pub fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+}
+
 fn default_hero_layout() -> String {
     "centered".to_string()
 }
@@ -355,6 +740,14 @@ fn default_port() -> u16 {
     3000
 }
 
+fn default_trusted_proxies() -> Vec<String> {
+    vec!["127.0.0.0/8".to_string(), "::1/128".to_string()]
+}
+
+fn default_client_ip_header() -> String {
+    "x-forwarded-for".to_string()
+}
+
 fn default_posts_per_page() -> usize {
     10
 }
@@ -420,6 +813,18 @@ fn default_version_retention() -> usize {
     50
 }
 
+fn default_shortcodes_dir() -> String {
+    "./shortcodes".to_string()
+}
+
+fn default_highlight_theme() -> String {
+    "InspiredGitHub".to_string()
+}
+
+fn default_highlight_theme_dark() -> String {
+    "base16-ocean.dark".to_string()
+}
+
 fn default_audit_enabled() -> bool {
     true
 }
@@ -456,6 +861,70 @@ fn default_backup_dir() -> String {
     "./backups".to_string()
 }
 
+fn default_acme_directory() -> String {
+    "https://acme-v02.api.letsencrypt.org/directory".to_string()
+}
+
+fn default_tls_storage_dir() -> String {
+    "./data/tls".to_string()
+}
+
+fn default_tls_renew_days() -> u32 {
+    30
+}
+
+fn default_tls_redirect_listen() -> String {
+    "0.0.0.0:80".to_string()
+}
+
+fn default_comment_max_depth() -> usize {
+    3
+}
+
+fn default_smtp_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_security() -> String {
+    "starttls".to_string()
+}
+
+fn default_newsletter_batch_size() -> usize {
+    50
+}
+
+fn default_newsletter_batch_delay() -> u64 {
+    1000
+}
+
+fn default_link_check_interval() -> u64 {
+    24
+}
+
+fn default_link_recheck_after() -> u64 {
+    168
+}
+
+fn default_link_check_concurrency() -> usize {
+    4
+}
+
+fn default_link_check_host_delay() -> u64 {
+    1000
+}
+
+fn default_link_check_timeout() -> u64 {
+    10
+}
+
+fn default_catalog_dir() -> String {
+    "./i18n".to_string()
+}
+
 impl Config {
     pub fn load(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path).map_err(|e| {
@@ -470,6 +939,51 @@ impl Config {
         Ok(config)
     }
 
+    /// Resolve the relative paths in the config (database, media, shortcodes,
+    /// catalogs, backups and content type templates) against `root` instead
+    /// of the working directory, for processes hosting several sites.
+    pub fn resolve_paths(&mut self, root: &Path) {
+        let resolve = |path: &mut String| {
+            let relative = Path::new(path.as_str());
+            if relative.is_relative() {
+                // Drop "./" so "./data" becomes "<root>/data"
+                let joined: std::path::PathBuf = std::iter::once(root.as_os_str())
+                    .chain(
+                        relative
+                            .components()
+                            .filter(|c| *c != std::path::Component::CurDir)
+                            .map(|c| c.as_os_str()),
+                    )
+                    .collect();
+                *path = joined.to_string_lossy().into_owned();
+            }
+        };
+        resolve(&mut self.database.path);
+        resolve(&mut self.media.upload_dir);
+        resolve(&mut self.content.shortcodes_dir);
+        resolve(&mut self.i18n.catalog_dir);
+        resolve(&mut self.backup.directory);
+        resolve(&mut self.tls.storage_dir);
+        for path in [
+            &mut self.tls.cert_file,
+            &mut self.tls.key_file,
+            &mut self.tls.acme_ca_file,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            resolve(path);
+        }
+        for def in &mut self.content_types {
+            if let Some(ref mut path) = def.list_template {
+                resolve(path);
+            }
+            if let Some(ref mut path) = def.detail_template {
+                resolve(path);
+            }
+        }
+    }
+
     pub fn validate(&self) -> Result<()> {
         if self.content.posts_per_page == 0 {
             anyhow::bail!("content.posts_per_page must be greater than 0");
@@ -484,6 +998,77 @@ impl Config {
             anyhow::bail!("content.excerpt_length must be 10000 or less");
         }
         self.theme.validate()?;
+        for (key, addr) in [
+            ("server.listen", &self.server.listen),
+            ("server.admin_listen", &self.server.admin_listen),
+        ] {
+            if let Some(addr) = addr {
+                crate::web::listener::ListenAddr::parse(addr)
+                    .map_err(|e| anyhow::anyhow!("{}: {}", key, e))?;
+            }
+        }
+        if let Some(mode) = &self.server.socket_mode {
+            crate::web::listener::parse_socket_mode(mode)
+                .map_err(|e| anyhow::anyhow!("server.socket_mode: {}", e))?;
+        }
+        crate::web::client_ip::ClientIpResolver::from_config(&self.server)
+            .map_err(|e| anyhow::anyhow!("{:#}", e))?;
+        if self.tls.enabled {
+            if self.tls.cert_file.is_some() != self.tls.key_file.is_some() {
+                anyhow::bail!("tls.cert_file and tls.key_file must be set together");
+            }
+            if self.tls.uses_acme() {
+                if self.tls.domains(&self.site.url).is_empty() {
+                    anyhow::bail!("tls.domains is required when site.url has no host name");
+                }
+                if self.tls.redirect_listen.is_empty() {
+                    anyhow::bail!(
+                        "tls.redirect_listen is required for ACME: the HTTP-01 challenge is answered on port 80"
+                    );
+                }
+            }
+            if !self.tls.redirect_listen.is_empty() && !self.tls.redirect_listen.contains(':') {
+                anyhow::bail!("tls.redirect_listen must be host:port, e.g. 0.0.0.0:80");
+            }
+        }
+        if self.metrics.enabled {
+            if self.metrics.token.as_deref().map_or(true, |t| t.trim().is_empty())
+                && self.metrics.listen.is_none()
+            {
+                anyhow::bail!(
+                    "metrics.token or metrics.listen is required: /metrics must not be public"
+                );
+            }
+            if let Some(addr) = &self.metrics.listen {
+                crate::web::listener::ListenAddr::parse(addr)
+                    .map_err(|e| anyhow::anyhow!("metrics.listen: {}", e))?;
+            }
+        }
+        if self.newsletter.enabled && self.newsletter.from_address.trim().is_empty() {
+            anyhow::bail!("newsletter.from_address is required when the newsletter is enabled");
+        }
+        if !["starttls", "tls", "none"].contains(&self.newsletter.smtp_security.as_str()) {
+            anyhow::bail!("newsletter.smtp_security must be one of: starttls, tls, none");
+        }
+        if self.link_checker.concurrency == 0 {
+            anyhow::bail!("link_checker.concurrency must be greater than 0");
+        }
+        crate::services::content_types::validate_definitions(&self.content_types, &self.i18n)?;
+        for code in &self.i18n.languages {
+            if !crate::services::i18n::is_valid_language_code(code) {
+                anyhow::bail!(
+                    "i18n.languages: '{}' is not a valid language code (e.g. \"fr\" or \"pt-br\")",
+                    code
+                );
+            }
+            if code == &self.site.language {
+                anyhow::bail!(
+                    "i18n.languages must not include the default language '{}'",
+                    code
+                );
+            }
+        }
         Ok(())
     }
 }
+
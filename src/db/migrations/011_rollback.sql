@@ -0,0 +1,7 @@
+-- Rollback migration 011: Remove comments table
+-- Note: Loses all reader comments
+
+DROP INDEX IF EXISTS idx_comments_status;
+DROP INDEX IF EXISTS idx_comments_parent;
+DROP INDEX IF EXISTS idx_comments_content;
+DROP TABLE IF EXISTS comments;
@@ -0,0 +1,8 @@
+-- Rollback migration 012: Remove webmention tables
+-- Note: Loses all received mentions and the send log
+
+DROP INDEX IF EXISTS idx_webmention_sends_content;
+DROP TABLE IF EXISTS webmention_sends;
+DROP INDEX IF EXISTS idx_webmentions_status;
+DROP INDEX IF EXISTS idx_webmentions_content;
+DROP TABLE IF EXISTS webmentions;
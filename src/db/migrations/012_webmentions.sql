@@ -0,0 +1,36 @@
+-- Incoming Webmentions and Pingbacks (verified asynchronously by fetching the source)
+CREATE TABLE IF NOT EXISTS webmentions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    content_id INTEGER NOT NULL REFERENCES content(id) ON DELETE CASCADE,
+    source TEXT NOT NULL,
+    target TEXT NOT NULL,
+    protocol TEXT NOT NULL DEFAULT 'webmention' CHECK (protocol IN ('webmention', 'pingback')),
+    mention_type TEXT NOT NULL DEFAULT 'mention' CHECK (mention_type IN ('mention', 'reply', 'like', 'repost', 'bookmark')),
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'verified', 'rejected')),
+    author_name TEXT,
+    author_url TEXT,
+    author_photo TEXT,
+    summary TEXT,
+    error TEXT,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    verified_at TEXT,
+    UNIQUE(source, target)
+);
+
+CREATE INDEX IF NOT EXISTS idx_webmentions_content ON webmentions(content_id, status);
+CREATE INDEX IF NOT EXISTS idx_webmentions_status ON webmentions(status, created_at);
+
+-- Outgoing Webmentions/Pingbacks sent when content is published
+CREATE TABLE IF NOT EXISTS webmention_sends (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    content_id INTEGER NOT NULL REFERENCES content(id) ON DELETE CASCADE,
+    target TEXT NOT NULL,
+    endpoint TEXT,
+    protocol TEXT,
+    response_status INTEGER,
+    success INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    sent_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_webmention_sends_content ON webmention_sends(content_id, sent_at);
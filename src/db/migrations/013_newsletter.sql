@@ -0,0 +1,42 @@
+-- Newsletter subscribers (double opt-in) and per-post email sends
+CREATE TABLE IF NOT EXISTS subscribers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    email TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    name TEXT,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'confirmed', 'unsubscribed')),
+    confirm_token TEXT UNIQUE,
+    unsubscribe_token TEXT NOT NULL UNIQUE,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    confirmed_at TEXT,
+    unsubscribed_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscribers_status ON subscribers(status, created_at);
+
+CREATE TABLE IF NOT EXISTS newsletter_issues (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    content_id INTEGER REFERENCES content(id) ON DELETE SET NULL,
+    subject TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'sending' CHECK (status IN ('sending', 'sent', 'failed')),
+    recipient_count INTEGER NOT NULL DEFAULT 0,
+    sent_count INTEGER NOT NULL DEFAULT 0,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    completed_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_newsletter_issues_content ON newsletter_issues(content_id);
+
+-- One row per delivery attempt
+CREATE TABLE IF NOT EXISTS newsletter_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    issue_id INTEGER NOT NULL REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    subscriber_id INTEGER REFERENCES subscribers(id) ON DELETE SET NULL,
+    email TEXT NOT NULL,
+    success INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    sent_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_newsletter_deliveries_issue ON newsletter_deliveries(issue_id, success);
@@ -0,0 +1,9 @@
+-- Rollback migration 013: Remove newsletter tables
+-- Note: Loses all subscribers and the newsletter send log
+
+DROP INDEX IF EXISTS idx_newsletter_deliveries_issue;
+DROP TABLE IF EXISTS newsletter_deliveries;
+DROP INDEX IF EXISTS idx_newsletter_issues_content;
+DROP TABLE IF EXISTS newsletter_issues;
+DROP INDEX IF EXISTS idx_subscribers_status;
+DROP TABLE IF EXISTS subscribers;
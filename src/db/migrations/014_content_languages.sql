@@ -0,0 +1,8 @@
+-- Per-item language and translation groups.
+-- A NULL language means the site's default language (site.language).
+-- Items that are translations of each other share a translation_group key.
+ALTER TABLE content ADD COLUMN language TEXT;
+ALTER TABLE content ADD COLUMN translation_group TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_content_language ON content(language);
+CREATE INDEX IF NOT EXISTS idx_content_translation_group ON content(translation_group);
@@ -0,0 +1,7 @@
+-- Rollback migration 014: Remove content language columns
+-- Note: Loses every item's language and its translation links
+
+DROP INDEX IF EXISTS idx_content_translation_group;
+DROP INDEX IF EXISTS idx_content_language;
+ALTER TABLE content DROP COLUMN translation_group;
+ALTER TABLE content DROP COLUMN language;
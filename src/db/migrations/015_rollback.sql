@@ -0,0 +1,48 @@
+-- Rollback migration 015: Remove custom content types
+-- Note: Loses field data. Entries of custom types are kept as draft pages.
+
+CREATE TABLE content_rollback (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    slug TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    content_type TEXT NOT NULL DEFAULT 'post' CHECK (content_type IN ('post', 'page', 'snippet')),
+    body_markdown TEXT NOT NULL DEFAULT '',
+    body_html TEXT NOT NULL DEFAULT '',
+    excerpt TEXT,
+    featured_image TEXT,
+    status TEXT NOT NULL DEFAULT 'draft' CHECK (status IN ('draft', 'scheduled', 'published', 'archived')),
+    scheduled_at TEXT,
+    published_at TEXT,
+    author_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    metadata TEXT DEFAULT '{}',
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    language TEXT,
+    translation_group TEXT
+);
+
+INSERT INTO content_rollback (id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at, language, translation_group)
+SELECT id, slug, title,
+       CASE WHEN content_type = 'entry' THEN 'page' ELSE content_type END,
+       body_markdown, body_html, excerpt, featured_image,
+       CASE WHEN content_type = 'entry' THEN 'draft' ELSE status END,
+       CASE WHEN content_type = 'entry' THEN NULL ELSE scheduled_at END,
+       published_at, author_id, metadata, created_at, updated_at, language, translation_group
+FROM content;
+
+DROP TABLE content;
+ALTER TABLE content_rollback RENAME TO content;
+
+CREATE INDEX idx_content_status ON content(status);
+CREATE INDEX idx_content_type ON content(content_type);
+CREATE INDEX idx_content_published ON content(published_at DESC);
+CREATE INDEX idx_content_slug ON content(slug);
+CREATE INDEX idx_content_scheduled ON content(scheduled_at) WHERE scheduled_at IS NOT NULL AND status = 'scheduled';
+CREATE INDEX idx_content_language ON content(language);
+CREATE INDEX idx_content_translation_group ON content(translation_group);
+
+CREATE TRIGGER update_content_timestamp
+AFTER UPDATE ON content
+BEGIN
+    UPDATE content SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
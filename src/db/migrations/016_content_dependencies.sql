@@ -0,0 +1,27 @@
+-- Snippets each content item draws on, recorded when its body is rendered.
+-- snippet_slug is a slug rather than an id so that content referencing a
+-- snippet that doesn't exist yet is re-rendered once it is created.
+CREATE TABLE IF NOT EXISTS content_dependencies (
+    content_id INTEGER NOT NULL REFERENCES content(id) ON DELETE CASCADE,
+    snippet_slug TEXT NOT NULL,
+    PRIMARY KEY (content_id, snippet_slug)
+);
+
+CREATE INDEX IF NOT EXISTS idx_content_dependencies_snippet ON content_dependencies(snippet_slug);
+
+-- Backfill from existing content. Snippets weren't expanded recursively
+-- before this migration, so direct references are the full set.
+INSERT OR IGNORE INTO content_dependencies (content_id, snippet_slug)
+WITH RECURSIVE refs(content_id, rest, slug) AS (
+    SELECT id, body_markdown, NULL FROM content
+    WHERE body_markdown LIKE '%[snippet slug="%'
+    UNION ALL
+    SELECT
+        content_id,
+        substr(rest, instr(rest, '[snippet slug="') + 15 + instr(substr(rest, instr(rest, '[snippet slug="') + 15), '"')),
+        substr(rest, instr(rest, '[snippet slug="') + 15, instr(substr(rest, instr(rest, '[snippet slug="') + 15), '"') - 1)
+    FROM refs
+    WHERE instr(rest, '[snippet slug="') > 0
+      AND instr(substr(rest, instr(rest, '[snippet slug="') + 15), '"') > 0
+)
+SELECT content_id, slug FROM refs WHERE slug IS NOT NULL AND slug != '';
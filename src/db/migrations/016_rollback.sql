@@ -0,0 +1,5 @@
+-- Rollback migration 016: Remove snippet dependency tracking
+-- Note: Recorded dependencies are rebuilt by `pebble rerender` after re-applying
+
+DROP INDEX IF EXISTS idx_content_dependencies_snippet;
+DROP TABLE IF EXISTS content_dependencies;
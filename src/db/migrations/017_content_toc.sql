@@ -0,0 +1,4 @@
+-- Table of contents of each item's rendered body, as JSON.
+-- Existing content gets its table of contents on the next save or
+-- `pebble rerender`.
+ALTER TABLE content ADD COLUMN toc TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,4 @@
+-- Rollback migration 017: Remove stored tables of contents
+-- Note: Tables of contents are rebuilt by `pebble rerender` after re-applying
+
+ALTER TABLE content DROP COLUMN toc;
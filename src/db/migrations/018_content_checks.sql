@@ -0,0 +1,10 @@
+-- Latest content check results for each content item, recorded when it is
+-- saved. issues holds the individual findings as JSON; the counts are kept
+-- alongside so listings can show them without parsing.
+CREATE TABLE IF NOT EXISTS content_checks (
+    content_id INTEGER PRIMARY KEY REFERENCES content(id) ON DELETE CASCADE,
+    errors INTEGER NOT NULL DEFAULT 0,
+    warnings INTEGER NOT NULL DEFAULT 0,
+    issues TEXT NOT NULL DEFAULT '[]',
+    checked_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,4 @@
+-- Rollback migration 018: Remove stored content check results
+-- Note: Results are recorded again by `pebble check` after re-applying
+
+DROP TABLE IF EXISTS content_checks;
@@ -0,0 +1,14 @@
+-- Results of checking external links found in published content. One row per
+-- URL; the content linking to it is found from body_html when reporting, so
+-- edits never leave stale rows pointing at the wrong posts.
+CREATE TABLE IF NOT EXISTS link_checks (
+    url TEXT PRIMARY KEY,
+    status_code INTEGER,
+    broken INTEGER NOT NULL DEFAULT 0,
+    skipped INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    checked_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_link_checks_broken ON link_checks(broken);
+CREATE INDEX IF NOT EXISTS idx_link_checks_checked ON link_checks(checked_at);
@@ -0,0 +1,6 @@
+-- Rollback migration 019: Remove external link check results
+-- Note: Results are recorded again by the next `pebble links check`
+
+DROP INDEX IF EXISTS idx_link_checks_checked;
+DROP INDEX IF EXISTS idx_link_checks_broken;
+DROP TABLE IF EXISTS link_checks;
@@ -0,0 +1,4 @@
+-- Rollback migration 020: Remove sync state
+-- Note: The next `pebble sync` treats every differing item as a conflict
+
+DROP TABLE IF EXISTS sync_state;
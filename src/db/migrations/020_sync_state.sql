@@ -0,0 +1,11 @@
+-- What this site last received from each site it syncs with (`pebble sync`).
+-- One row per item: the hash of the item as applied, so the next sync can
+-- tell edits made here since then (conflicts) from changes made at the source.
+CREATE TABLE IF NOT EXISTS sync_state (
+    peer_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    item_key TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    synced_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (peer_id, kind, item_key)
+);
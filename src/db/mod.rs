@@ -6,7 +6,7 @@ use std::path::Path;
 
 pub type DbPool = Pool<SqliteConnectionManager>;
 
-pub const MIGRATION_COUNT: i32 = 10;
+pub const MIGRATION_COUNT: i32 = 20;
 
 pub struct Database {
     pool: DbPool,
@@ -84,13 +84,22 @@ impl Database {
         Ok(self.pool.get()?)
     }
 
+    /// Connections currently open in the pool and how many of them are idle.
+    pub fn pool_state(&self) -> r2d2::State {
+        self.pool.state()
+    }
+
+    pub fn pool_max_size(&self) -> u32 {
+        self.pool.max_size()
+    }
+
     pub fn migrate(&self) -> Result<()> {
         let conn = self.get()?;
         run_migrations(&conn)?;
         Ok(())
     }
 
-    /// Returns the status of all 10 migrations as (version, Option<applied_at>).
+    /// Returns the status of all migrations as (version, Option<applied_at>).
     /// Pending migrations have `None` for applied_at.
     pub fn get_migration_status(&self) -> Result<Vec<(i32, Option<String>)>> {
         let conn = self.get()?;
@@ -103,10 +112,9 @@ impl Database {
             );",
         )?;
 
-        let total_migrations = 10;
-        let mut result = Vec::with_capacity(total_migrations);
+        let mut result = Vec::with_capacity(MIGRATION_COUNT as usize);
 
-        for version in 1..=total_migrations as i32 {
+        for version in 1..=MIGRATION_COUNT {
             let applied_at: Option<String> = conn
                 .query_row(
                     "SELECT applied_at FROM schema_migrations WHERE version = ?1",
@@ -172,6 +180,16 @@ fn run_migrations(conn: &Connection) -> Result<()> {
         (8, include_str!("migrations/008_preview_tokens.sql")),
         (9, include_str!("migrations/009_content_series.sql")),
         (10, include_str!("migrations/010_api_and_webhooks.sql")),
+        (11, include_str!("migrations/011_comments.sql")),
+        (12, include_str!("migrations/012_webmentions.sql")),
+        (13, include_str!("migrations/013_newsletter.sql")),
+        (14, include_str!("migrations/014_content_languages.sql")),
+        (15, include_str!("migrations/015_custom_content_types.sql")),
+        (16, include_str!("migrations/016_content_dependencies.sql")),
+        (17, include_str!("migrations/017_content_toc.sql")),
+        (18, include_str!("migrations/018_content_checks.sql")),
+        (19, include_str!("migrations/019_link_checks.sql")),
+        (20, include_str!("migrations/020_sync_state.sql")),
     ];
 
     for (version, sql) in migrations {
@@ -200,6 +218,16 @@ fn get_rollback_sql(version: i32) -> Result<&'static str> {
         8 => Ok(include_str!("migrations/008_rollback.sql")),
         9 => Ok(include_str!("migrations/009_rollback.sql")),
         10 => Ok(include_str!("migrations/010_rollback.sql")),
+        11 => Ok(include_str!("migrations/011_rollback.sql")),
+        12 => Ok(include_str!("migrations/012_rollback.sql")),
+        13 => Ok(include_str!("migrations/013_rollback.sql")),
+        14 => Ok(include_str!("migrations/014_rollback.sql")),
+        15 => Ok(include_str!("migrations/015_rollback.sql")),
+        16 => Ok(include_str!("migrations/016_rollback.sql")),
+        17 => Ok(include_str!("migrations/017_rollback.sql")),
+        18 => Ok(include_str!("migrations/018_rollback.sql")),
+        19 => Ok(include_str!("migrations/019_rollback.sql")),
+        20 => Ok(include_str!("migrations/020_rollback.sql")),
         _ => anyhow::bail!("No rollback SQL for migration version {}", version),
     }
 }
@@ -10,6 +10,8 @@ pub struct GlobalConfig {
     #[serde(default)]
     pub registry: RegistryConfig,
     #[serde(default)]
+    pub supervisor: SupervisorConfig,
+    #[serde(default)]
     pub custom: BTreeMap<String, String>,
 }
 
@@ -62,6 +64,37 @@ impl Default for RegistryConfig {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorConfig {
+    /// Seconds between `/health` checks of each supervised site
+    #[serde(default = "default_health_interval_secs")]
+    pub health_interval_secs: u64,
+    /// Consecutive failed health checks before a live site is restarted
+    #[serde(default = "default_failed_checks")]
+    pub failed_checks: u32,
+    /// Longest wait between restarts of a site that keeps failing
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    /// Rotate a site's log once it grows past this size
+    #[serde(default = "default_log_max_size_mb")]
+    pub log_max_size_mb: u64,
+    /// Compressed rotated logs to keep per site
+    #[serde(default = "default_log_keep")]
+    pub log_keep: usize,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            health_interval_secs: default_health_interval_secs(),
+            failed_checks: default_failed_checks(),
+            max_backoff_secs: default_max_backoff_secs(),
+            log_max_size_mb: default_log_max_size_mb(),
+            log_keep: default_log_keep(),
+        }
+    }
+}
+
 fn default_author() -> String {
     whoami::username()
 }
@@ -98,6 +131,26 @@ fn default_auto_port_range_end() -> u16 {
     3100
 }
 
+fn default_health_interval_secs() -> u64 {
+    15
+}
+
+fn default_failed_checks() -> u32 {
+    3
+}
+
+fn default_max_backoff_secs() -> u64 {
+    300
+}
+
+fn default_log_max_size_mb() -> u64 {
+    10
+}
+
+fn default_log_keep() -> usize {
+    5
+}
+
 impl GlobalConfig {
     pub fn load(path: &Path) -> Result<Self> {
         if !path.exists() {
@@ -137,6 +190,17 @@ impl GlobalConfig {
             ["registry", "auto_port_range_end"] => {
                 Some(self.registry.auto_port_range_end.to_string())
             }
+            ["supervisor", "health_interval_secs"] => {
+                Some(self.supervisor.health_interval_secs.to_string())
+            }
+            ["supervisor", "failed_checks"] => Some(self.supervisor.failed_checks.to_string()),
+            ["supervisor", "max_backoff_secs"] => {
+                Some(self.supervisor.max_backoff_secs.to_string())
+            }
+            ["supervisor", "log_max_size_mb"] => {
+                Some(self.supervisor.log_max_size_mb.to_string())
+            }
+            ["supervisor", "log_keep"] => Some(self.supervisor.log_keep.to_string()),
             ["custom", k] => self.custom.get(*k).cloned(),
             _ => None,
         }
@@ -166,6 +230,21 @@ impl GlobalConfig {
             ["registry", "auto_port_range_end"] => {
                 self.registry.auto_port_range_end = value.parse().context("Invalid port")?
             }
+            ["supervisor", "health_interval_secs"] => {
+                self.supervisor.health_interval_secs = value.parse().context("Invalid number")?
+            }
+            ["supervisor", "failed_checks"] => {
+                self.supervisor.failed_checks = value.parse().context("Invalid number")?
+            }
+            ["supervisor", "max_backoff_secs"] => {
+                self.supervisor.max_backoff_secs = value.parse().context("Invalid number")?
+            }
+            ["supervisor", "log_max_size_mb"] => {
+                self.supervisor.log_max_size_mb = value.parse().context("Invalid number")?
+            }
+            ["supervisor", "log_keep"] => {
+                self.supervisor.log_keep = value.parse().context("Invalid number")?
+            }
             ["custom", k] => {
                 self.custom.insert(k.to_string(), value.to_string());
             }
@@ -206,6 +285,26 @@ impl GlobalConfig {
                 "registry.auto_port_range_end".to_string(),
                 self.registry.auto_port_range_end.to_string(),
             ),
+            (
+                "supervisor.health_interval_secs".to_string(),
+                self.supervisor.health_interval_secs.to_string(),
+            ),
+            (
+                "supervisor.failed_checks".to_string(),
+                self.supervisor.failed_checks.to_string(),
+            ),
+            (
+                "supervisor.max_backoff_secs".to_string(),
+                self.supervisor.max_backoff_secs.to_string(),
+            ),
+            (
+                "supervisor.log_max_size_mb".to_string(),
+                self.supervisor.log_max_size_mb.to_string(),
+            ),
+            (
+                "supervisor.log_keep".to_string(),
+                self.supervisor.log_keep.to_string(),
+            ),
         ];
 
         for (k, v) in &self.custom {
@@ -4,4 +4,7 @@ pub mod registry;
 
 pub use config::GlobalConfig;
 pub use home::PebbleHome;
-pub use registry::{Registry, RegistrySite, SiteStatus};
+pub use registry::{
+    is_process_running, normalize_domain, Registry, RegistrySite, SiteStatus, Supervision,
+    SupervisorProcess,
+};
@@ -9,6 +9,8 @@ pub enum SiteStatus {
     Stopped,
     Running,
     Deploying,
+    /// Served with other sites by `pebble registry serve-all`
+    Hosted,
 }
 
 impl Default for SiteStatus {
@@ -23,6 +25,7 @@ impl std::fmt::Display for SiteStatus {
             SiteStatus::Stopped => write!(f, "stopped"),
             SiteStatus::Running => write!(f, "running"),
             SiteStatus::Deploying => write!(f, "deploying"),
+            SiteStatus::Hosted => write!(f, "hosted"),
         }
     }
 }
@@ -39,10 +42,47 @@ pub struct RegistrySite {
     pub pid: Option<u32>,
     #[serde(default)]
     pub last_started: Option<String>,
+    /// Host names `pebble registry serve-all` routes to this site, in
+    /// addition to the host of its `site.url`
+    #[serde(default)]
+    pub domains: Vec<String>,
+    #[serde(default)]
+    pub supervision: Supervision,
+}
+
+/// What `pebble registry supervise` knows about a site.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Supervision {
+    /// Restart the site when it dies; set by `serve`/`deploy`, cleared by `stop`
+    #[serde(default)]
+    pub keep_running: bool,
+    /// Restart with `deploy` rather than `serve`
+    #[serde(default)]
+    pub production: bool,
+    #[serde(default)]
+    pub restarts: u32,
+    /// Restarts since the site last passed a health check
+    #[serde(default)]
+    pub failures: u32,
+    #[serde(default)]
+    pub last_check: Option<String>,
+    #[serde(default)]
+    pub last_restart: Option<String>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// The running `pebble registry supervise` process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorProcess {
+    pub pid: u32,
+    pub started_at: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Registry {
+    #[serde(default)]
+    pub supervisor: Option<SupervisorProcess>,
     #[serde(default)]
     pub sites: HashMap<String, RegistrySite>,
 }
@@ -99,16 +139,49 @@ impl Registry {
     pub fn running_sites(&self) -> Vec<&RegistrySite> {
         self.sites
             .values()
-            .filter(|s| s.status == SiteStatus::Running)
+            .filter(|s| matches!(s.status, SiteStatus::Running | SiteStatus::Hosted))
             .collect()
     }
 
+    /// The site a domain is assigned to, if any.
+    pub fn site_for_domain(&self, domain: &str) -> Option<&RegistrySite> {
+        self.sites
+            .values()
+            .find(|s| s.domains.iter().any(|d| d.eq_ignore_ascii_case(domain)))
+    }
+
+    pub fn add_domain(&mut self, name: &str, domain: &str) -> Result<()> {
+        let domain = normalize_domain(domain)?;
+        if let Some(owner) = self.site_for_domain(&domain) {
+            bail!("Domain '{}' is already assigned to site '{}'", domain, owner.name);
+        }
+        let site = self
+            .sites
+            .get_mut(name)
+            .with_context(|| format!("Site '{}' not found in registry", name))?;
+        site.domains.push(domain);
+        Ok(())
+    }
+
+    pub fn remove_domain(&mut self, name: &str, domain: &str) -> Result<()> {
+        let site = self
+            .sites
+            .get_mut(name)
+            .with_context(|| format!("Site '{}' not found in registry", name))?;
+        let before = site.domains.len();
+        site.domains.retain(|d| !d.eq_ignore_ascii_case(domain));
+        if site.domains.len() == before {
+            bail!("Domain '{}' is not assigned to site '{}'", domain, name);
+        }
+        Ok(())
+    }
+
     pub fn find_available_port(&self, start: u16, end: u16) -> Option<u16> {
         let used_ports: std::collections::HashSet<u16> = self
             .sites
             .values()
             .filter_map(|s| {
-                if s.status == SiteStatus::Running {
+                if matches!(s.status, SiteStatus::Running | SiteStatus::Hosted) {
                     s.port
                 } else {
                     None
@@ -130,15 +203,22 @@ impl Registry {
             site.status = status;
             site.port = port;
             site.pid = pid;
-            if status == SiteStatus::Running {
+            if matches!(status, SiteStatus::Running | SiteStatus::Hosted) {
                 site.last_started = Some(chrono::Utc::now().to_rfc3339());
             }
         }
     }
 
+    /// The supervisor process, if it is still alive.
+    pub fn active_supervisor(&self) -> Option<&SupervisorProcess> {
+        self.supervisor
+            .as_ref()
+            .filter(|s| is_process_running(s.pid))
+    }
+
     pub fn cleanup_dead_processes(&mut self) {
         for site in self.sites.values_mut() {
-            if site.status == SiteStatus::Running {
+            if site.status != SiteStatus::Stopped {
                 if let Some(pid) = site.pid {
                     if !is_process_running(pid) {
                         site.status = SiteStatus::Stopped;
@@ -151,17 +231,42 @@ impl Registry {
     }
 }
 
+/// Lowercase host name without scheme, port or path, e.g. "Blog.Example.com:443"
+/// becomes "blog.example.com".
+pub fn normalize_domain(domain: &str) -> Result<String> {
+    let domain = domain.trim();
+    let domain = domain
+        .split_once("://")
+        .map_or(domain, |(_, rest)| rest)
+        .split(['/', ':'])
+        .next()
+        .unwrap_or("")
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+    let valid = !domain.is_empty()
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !valid {
+        bail!("'{}' is not a valid domain name", domain);
+    }
+    Ok(domain)
+}
+
 fn is_port_available(port: u16) -> bool {
     std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
 }
 
 #[cfg(unix)]
-fn is_process_running(pid: u32) -> bool {
+pub fn is_process_running(pid: u32) -> bool {
     unsafe { libc::kill(pid as i32, 0) == 0 }
 }
 
 #[cfg(windows)]
-fn is_process_running(pid: u32) -> bool {
+pub fn is_process_running(pid: u32) -> bool {
     use std::process::Command;
     Command::new("tasklist")
         .args(["/FI", &format!("PID eq {}", pid), "/NH"])
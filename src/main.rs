@@ -21,11 +21,25 @@ async fn main() -> anyhow::Result<()> {
         Some(Commands::Init { path, name }) => {
             pebble_cms::cli::init::run(path, name).await?;
         }
-        Some(Commands::Serve { host, port }) => {
-            pebble_cms::cli::serve::run(&cli.config, &host, port).await?;
+        Some(Commands::Serve { host, port, listen }) => {
+            pebble_cms::cli::serve::run(&cli.config, &host, port, listen).await?;
+        }
+        Some(Commands::Deploy {
+            host,
+            port,
+            listen,
+            admin,
+        }) => {
+            pebble_cms::cli::deploy::run(&cli.config, &host, port, listen, admin).await?;
         }
-        Some(Commands::Deploy { host, port }) => {
-            pebble_cms::cli::deploy::run(&cli.config, &host, port).await?;
+        Some(Commands::DeployConfig {
+            target,
+            output,
+            name,
+            user,
+            binary,
+        }) => {
+            pebble_cms::cli::deploy_config::run(&cli.config, &target, output, name, user, binary)?;
         }
         Some(Commands::Build { output, base_url }) => {
             pebble_cms::cli::build::run(&cli.config, &output, base_url).await?;
@@ -59,6 +73,15 @@ async fn main() -> anyhow::Result<()> {
         Some(Commands::Rerender) => {
             pebble_cms::cli::rerender::run(&cli.config).await?;
         }
+        Some(Commands::Check) => {
+            pebble_cms::cli::check::run(&cli.config).await?;
+        }
+        Some(Commands::Links { command }) => {
+            pebble_cms::cli::links::run(&cli.config, command).await?;
+        }
+        Some(Commands::Sync { command }) => {
+            pebble_cms::cli::sync::run(&cli.config, command).await?;
+        }
         Some(Commands::User { command }) => {
             pebble_cms::cli::user::run(&cli.config, command).await?;
         }
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentStatus {
+    #[default]
+    Pending,
+    Approved,
+    Spam,
+}
+
+impl FromStr for CommentStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(Self::Pending),
+            "approved" => Ok(Self::Approved),
+            "spam" => Ok(Self::Spam),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for CommentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Approved => write!(f, "approved"),
+            Self::Spam => write!(f, "spam"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: i64,
+    pub content_id: i64,
+    pub parent_id: Option<i64>,
+    pub author_name: String,
+    pub author_url: Option<String>,
+    pub body_markdown: String,
+    pub body_html: String,
+    pub status: CommentStatus,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A comment positioned within a rendered thread. `depth` is 0 for top-level
+/// comments and increases by one per reply level.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommentThreadItem {
+    #[serde(flatten)]
+    pub comment: Comment,
+    pub depth: usize,
+}
+
+/// A comment joined with the post it belongs to, for the moderation queue.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommentWithContent {
+    #[serde(flatten)]
+    pub comment: Comment,
+    pub content_title: String,
+    pub content_slug: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateComment {
+    pub content_id: i64,
+    pub parent_id: Option<i64>,
+    pub author_name: String,
+    pub author_url: Option<String>,
+    pub body_markdown: String,
+}
@@ -9,6 +9,8 @@ pub enum ContentType {
     Post,
     Page,
     Snippet,
+    /// An item of a custom content type declared in `[[content_types]]`
+    Entry,
 }
 
 impl FromStr for ContentType {
@@ -19,6 +21,7 @@ impl FromStr for ContentType {
             "post" => Ok(Self::Post),
             "page" => Ok(Self::Page),
             "snippet" => Ok(Self::Snippet),
+            "entry" => Ok(Self::Entry),
             _ => Err(()),
         }
     }
@@ -30,6 +33,7 @@ impl std::fmt::Display for ContentType {
             Self::Post => write!(f, "post"),
             Self::Page => write!(f, "page"),
             Self::Snippet => write!(f, "snippet"),
+            Self::Entry => write!(f, "entry"),
         }
     }
 }
@@ -86,6 +90,28 @@ pub struct Content {
     pub metadata: serde_json::Value,
     pub created_at: String,
     pub updated_at: String,
+    /// Language code, or `None` for the site's default language.
+    pub language: Option<String>,
+    /// Shared token linking translations of the same piece of content.
+    pub translation_group: Option<String>,
+    /// Name of the custom content type, for `ContentType::Entry` items.
+    pub entry_type: Option<String>,
+    /// Field values of a custom content type entry, keyed by field name.
+    pub fields: serde_json::Value,
+    /// Headings of the rendered body, nested by level.
+    pub toc: Vec<TocEntry>,
+}
+
+/// A heading in a content item's table of contents.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    /// Anchor id of the heading in the rendered HTML.
+    pub id: String,
+    /// Lower-level headings up to the next heading at this level or above.
+    #[serde(default)]
+    pub children: Vec<TocEntry>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -112,6 +138,12 @@ pub struct CreateContent {
     #[serde(default)]
     pub tags: Vec<String>,
     pub metadata: Option<serde_json::Value>,
+    /// Custom content type name; required when `content_type` is `entry`
+    #[serde(default)]
+    pub entry_type: Option<String>,
+    /// Field values for an entry, checked against its type when saved
+    #[serde(default)]
+    pub fields: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -125,6 +157,21 @@ pub struct UpdateContent {
     pub scheduled_at: Option<String>,
     pub tags: Option<Vec<String>>,
     pub metadata: Option<serde_json::Value>,
+    pub fields: Option<serde_json::Value>,
+}
+
+/// Another language version of a content item, linked through its translation group.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentTranslation {
+    pub id: i64,
+    pub slug: String,
+    pub title: String,
+    pub content_type: ContentType,
+    pub status: ContentStatus,
+    /// Effective language code (the site default when the item has none set)
+    pub language: String,
+    /// Public path, including the language prefix where one applies
+    pub path: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
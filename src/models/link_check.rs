@@ -0,0 +1,34 @@
+use serde::Serialize;
+
+/// The latest result of checking one external URL.
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkCheck {
+    pub url: String,
+    pub status_code: Option<i32>,
+    pub broken: bool,
+    /// Not requested because the site's robots.txt disallows it
+    pub skipped: bool,
+    pub error: Option<String>,
+    pub checked_at: String,
+}
+
+/// A published content item with the broken links found in it, for the
+/// admin report and `pebble links check`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BrokenLinkReport {
+    pub content_id: i64,
+    pub title: String,
+    pub slug: String,
+    pub content_type: String,
+    pub links: Vec<LinkCheck>,
+}
+
+/// Totals across every external link currently in published content.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkCheckSummary {
+    pub total: usize,
+    pub checked: usize,
+    pub broken: usize,
+    pub skipped: usize,
+    pub last_checked: Option<String>,
+}
@@ -1,15 +1,23 @@
 mod api_token;
+mod comment;
 mod content;
+mod link_check;
 mod media;
+mod newsletter;
 mod series;
 mod tag;
 mod user;
 mod webhook;
+mod webmention;
 
 pub use api_token::*;
+pub use comment::*;
 pub use content::*;
+pub use link_check::*;
 pub use media::*;
+pub use newsletter::*;
 pub use series::*;
 pub use tag::*;
 pub use user::*;
 pub use webhook::*;
+pub use webmention::*;
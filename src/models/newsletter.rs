@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriberStatus {
+    #[default]
+    Pending,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl FromStr for SubscriberStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(Self::Pending),
+            "confirmed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriberStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Confirmed => write!(f, "confirmed"),
+            Self::Unsubscribed => write!(f, "unsubscribed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Subscriber {
+    pub id: i64,
+    pub email: String,
+    pub name: Option<String>,
+    pub status: SubscriberStatus,
+    #[serde(skip_serializing)]
+    pub confirm_token: Option<String>,
+    #[serde(skip_serializing)]
+    pub unsubscribe_token: String,
+    pub created_at: String,
+    pub confirmed_at: Option<String>,
+    pub unsubscribed_at: Option<String>,
+}
+
+/// A newsletter send of one post to every confirmed subscriber.
+#[derive(Debug, Clone, Serialize)]
+pub struct NewsletterIssue {
+    pub id: i64,
+    pub content_id: Option<i64>,
+    pub subject: String,
+    pub status: String,
+    pub recipient_count: i64,
+    pub sent_count: i64,
+    pub failed_count: i64,
+    pub created_by: Option<i64>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewsletterDelivery {
+    pub id: i64,
+    pub issue_id: i64,
+    pub subscriber_id: Option<i64>,
+    pub email: String,
+    pub success: bool,
+    pub error: Option<String>,
+    pub sent_at: String,
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebmentionStatus {
+    #[default]
+    Pending,
+    Verified,
+    Rejected,
+}
+
+impl FromStr for WebmentionStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(Self::Pending),
+            "verified" => Ok(Self::Verified),
+            "rejected" => Ok(Self::Rejected),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for WebmentionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Verified => write!(f, "verified"),
+            Self::Rejected => write!(f, "rejected"),
+        }
+    }
+}
+
+/// How the source page relates to the target, taken from its microformats
+/// (`u-in-reply-to`, `u-like-of`, `u-repost-of`, `u-bookmark-of`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionType {
+    #[default]
+    Mention,
+    Reply,
+    Like,
+    Repost,
+    Bookmark,
+}
+
+impl FromStr for MentionType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mention" => Ok(Self::Mention),
+            "reply" => Ok(Self::Reply),
+            "like" => Ok(Self::Like),
+            "repost" => Ok(Self::Repost),
+            "bookmark" => Ok(Self::Bookmark),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for MentionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mention => write!(f, "mention"),
+            Self::Reply => write!(f, "reply"),
+            Self::Like => write!(f, "like"),
+            Self::Repost => write!(f, "repost"),
+            Self::Bookmark => write!(f, "bookmark"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Webmention {
+    pub id: i64,
+    pub content_id: i64,
+    pub source: String,
+    pub target: String,
+    pub protocol: String,
+    pub mention_type: MentionType,
+    pub status: WebmentionStatus,
+    pub author_name: Option<String>,
+    pub author_url: Option<String>,
+    pub author_photo: Option<String>,
+    pub summary: Option<String>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub verified_at: Option<String>,
+}
+
+/// Verified mentions of a single content item, grouped for display.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WebmentionGroups {
+    pub likes: Vec<Webmention>,
+    pub reposts: Vec<Webmention>,
+    pub replies: Vec<Webmention>,
+    pub mentions: Vec<Webmention>,
+    pub total: usize,
+}
+
+/// A webmention joined with the content it targets, for the admin list.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebmentionWithContent {
+    #[serde(flatten)]
+    pub webmention: Webmention,
+    pub content_title: String,
+    pub content_slug: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebmentionSend {
+    pub id: i64,
+    pub content_id: i64,
+    pub content_title: String,
+    pub target: String,
+    pub endpoint: Option<String>,
+    pub protocol: Option<String>,
+    pub response_status: Option<i32>,
+    pub success: bool,
+    pub error: Option<String>,
+    pub sent_at: String,
+}
@@ -0,0 +1,865 @@
+//! A small ACME (RFC 8555) client obtaining certificates with the HTTP-01
+//! challenge, for `pebble deploy` serving HTTPS itself. The challenge
+//! responses are put in a [`Challenges`] map that the plain HTTP listener
+//! answers from.
+//!
+//! The account key is kept in `<storage_dir>/account.pem`; certificates in
+//! `cert.pem` and `key.pem`, with the domains they cover in `domains`.
+
+use crate::config::TlsConfig;
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Key authorizations to serve at `/.well-known/acme-challenge/<token>`.
+pub type Challenges = Arc<RwLock<HashMap<String, String>>>;
+
+/// A certificate chain and its private key, both PEM.
+pub struct Certificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+/// Files under `tls.storage_dir`.
+pub struct Storage {
+    dir: PathBuf,
+}
+
+impl Storage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn cert_path(&self) -> PathBuf {
+        self.dir.join("cert.pem")
+    }
+
+    pub fn key_path(&self) -> PathBuf {
+        self.dir.join("key.pem")
+    }
+
+    /// The stored certificate, if there is one for exactly `domains`.
+    pub fn load(&self, domains: &[String]) -> Option<Certificate> {
+        let stored = std::fs::read_to_string(self.dir.join("domains")).ok()?;
+        if stored.lines().collect::<Vec<_>>() != domains {
+            return None;
+        }
+        Some(Certificate {
+            cert_pem: std::fs::read_to_string(self.cert_path()).ok()?,
+            key_pem: std::fs::read_to_string(self.key_path()).ok()?,
+        })
+    }
+
+    pub fn save(&self, domains: &[String], cert: &Certificate) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        write_private(&self.key_path(), &cert.key_pem)?;
+        std::fs::write(self.cert_path(), &cert.cert_pem)?;
+        std::fs::write(self.dir.join("domains"), domains.join("\n"))?;
+        Ok(())
+    }
+
+    fn account_key(&self) -> Result<rcgen::KeyPair> {
+        let path = self.dir.join("account.pem");
+        if let Ok(pem) = std::fs::read_to_string(&path) {
+            return rcgen::KeyPair::from_pem(&pem)
+                .with_context(|| format!("Invalid ACME account key in {}", path.display()));
+        }
+        let key = rcgen::KeyPair::generate()?;
+        std::fs::create_dir_all(&self.dir)?;
+        write_private(&path, &key.serialize_pem())?;
+        Ok(key)
+    }
+}
+
+/// Write a file only its owner can read.
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    std::io::Write::write_all(&mut file, contents.as_bytes())?;
+    Ok(())
+}
+
+/// Obtain a certificate for `domains`, answering the HTTP-01 challenges
+/// through `challenges`.
+pub async fn obtain(
+    config: &TlsConfig,
+    domains: &[String],
+    challenges: &Challenges,
+) -> Result<Certificate> {
+    let storage = Storage::new(&config.storage_dir);
+    let mut client = Client::new(config, storage.account_key()?).await?;
+    client.register(config.acme_email.as_deref()).await?;
+
+    let identifiers: Vec<Value> = domains
+        .iter()
+        .map(|d| json!({ "type": "dns", "value": d }))
+        .collect();
+    let (order_url, order) = client
+        .post(
+            &client.directory.new_order.clone(),
+            Some(json!({ "identifiers": identifiers })),
+        )
+        .await?;
+    let order_url = order_url.context("The ACME server returned no order URL")?;
+    let order: Order = serde_json::from_value(order)?;
+
+    let mut tokens = Vec::new();
+    let result = async {
+        for authz_url in &order.authorizations {
+            let token = client.authorize(authz_url, challenges).await?;
+            tokens.extend(token);
+        }
+
+        let key = rcgen::KeyPair::generate()?;
+        let csr = rcgen::CertificateParams::new(domains.to_vec())?.serialize_request(&key)?;
+        client
+            .post(
+                &order.finalize,
+                Some(json!({ "csr": URL_SAFE_NO_PAD.encode(csr.der()) })),
+            )
+            .await?;
+        let order: Order = client
+            .poll(&order_url, |o: &Order| {
+                o.status != "processing" && o.status != "ready"
+            })
+            .await?;
+        if order.status != "valid" {
+            bail!("The ACME order ended as '{}'", order.status);
+        }
+        let cert_url = order
+            .certificate
+            .context("The ACME server returned no certificate URL")?;
+        let cert_pem = client.download(&cert_url).await?;
+        Ok(Certificate {
+            cert_pem,
+            key_pem: key.serialize_pem(),
+        })
+    }
+    .await;
+
+    let mut map = challenges.write().unwrap_or_else(|e| e.into_inner());
+    for token in tokens {
+        map.remove(&token);
+    }
+    result
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+    status: String,
+    #[serde(default)]
+    authorizations: Vec<String>,
+    finalize: String,
+    #[serde(default)]
+    certificate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+    status: String,
+    identifier: Identifier,
+    #[serde(default)]
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    error: Option<Value>,
+}
+
+struct Client {
+    http: reqwest::Client,
+    directory: Directory,
+    key: EcdsaKeyPair,
+    jwk: Value,
+    /// The account URL, used as the key id once registered
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl Client {
+    async fn new(config: &TlsConfig, account_key: rcgen::KeyPair) -> Result<Self> {
+        let mut http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(30))
+            .user_agent("Pebble-CMS/1.0");
+        if let Some(ca_file) = &config.acme_ca_file {
+            let pem = std::fs::read(ca_file)
+                .with_context(|| format!("Failed to read tls.acme_ca_file {}", ca_file))?;
+            http = http.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
+        let http = http.build()?;
+
+        let directory: Directory = http
+            .get(&config.acme_directory)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", config.acme_directory))?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Invalid ACME directory")?;
+
+        let key = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            &account_key.serialize_der(),
+            &SystemRandom::new(),
+        )
+        .map_err(|e| anyhow::anyhow!("Unusable ACME account key: {}", e))?;
+        let jwk = jwk(account_key.public_key_raw())?;
+
+        Ok(Self {
+            http,
+            directory,
+            key,
+            jwk,
+            kid: None,
+            nonce: None,
+        })
+    }
+
+    /// Find or create the account for the key.
+    async fn register(&mut self, email: Option<&str>) -> Result<()> {
+        let mut account = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = email {
+            account["contact"] = json!([format!("mailto:{}", email)]);
+        }
+        let (location, _) = self
+            .post(&self.directory.new_account.clone(), Some(account))
+            .await?;
+        self.kid = Some(location.context("The ACME server returned no account URL")?);
+        Ok(())
+    }
+
+    /// Complete the HTTP-01 challenge of an authorization. Returns the token
+    /// to clear from `challenges` afterwards.
+    async fn authorize(&mut self, url: &str, challenges: &Challenges) -> Result<Option<String>> {
+        let (_, authz) = self.post(url, None).await?;
+        let authz: Authorization = serde_json::from_value(authz)?;
+        if authz.status == "valid" {
+            return Ok(None);
+        }
+        let challenge = authz
+            .challenges
+            .iter()
+            .find(|c| c.kind == "http-01")
+            .with_context(|| {
+                format!(
+                    "The ACME server offers no http-01 challenge for {}",
+                    authz.identifier.value
+                )
+            })?;
+
+        challenges
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                challenge.token.clone(),
+                key_authorization(&challenge.token, &self.jwk),
+            );
+        self.post(&challenge.url, Some(json!({}))).await?;
+
+        let authz: Authorization = self
+            .poll(url, |a: &Authorization| a.status != "pending")
+            .await?;
+        if authz.status != "valid" {
+            let error = authz
+                .challenges
+                .iter()
+                .find_map(|c| c.error.as_ref())
+                .and_then(|e| e["detail"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            bail!(
+                "Validation of {} failed ({}): {}",
+                authz.identifier.value,
+                authz.status,
+                error
+            );
+        }
+        Ok(Some(challenge.token.clone()))
+    }
+
+    /// POST-as-GET `url` until `done` holds for the resource.
+    async fn poll<T: serde::de::DeserializeOwned>(
+        &mut self,
+        url: &str,
+        done: impl Fn(&T) -> bool,
+    ) -> Result<T> {
+        for _ in 0..30 {
+            let (_, value) = self.post(url, None).await?;
+            let resource: T = serde_json::from_value(value)?;
+            if done(&resource) {
+                return Ok(resource);
+            }
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+        bail!("Timed out waiting for the ACME server at {}", url)
+    }
+
+    async fn download(&mut self, url: &str) -> Result<String> {
+        let response = self.send(url, None).await?;
+        Ok(response.text().await?)
+    }
+
+    /// A signed POST with a JSON payload, or a POST-as-GET without one.
+    /// Returns the `Location` header and the response body.
+    async fn post(&mut self, url: &str, payload: Option<Value>) -> Result<(Option<String>, Value)> {
+        let response = self.send(url, payload).await?;
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = response.json().await.unwrap_or(Value::Null);
+        Ok((location, body))
+    }
+
+    async fn send(&mut self, url: &str, payload: Option<Value>) -> Result<reqwest::Response> {
+        // A nonce the server rejected as stale is retried once with a fresh one
+        for attempt in 0..2 {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.new_nonce().await?,
+            };
+            let body = self.sign(url, &nonce, payload.as_ref())?;
+            let response = self
+                .http
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/jose+json")
+                .body(body)
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach {}", url))?;
+            self.nonce = replay_nonce(&response);
+
+            if response.status().is_success() {
+                return Ok(response);
+            }
+            let status = response.status();
+            let problem: Value = response.json().await.unwrap_or(Value::Null);
+            let kind = problem["type"].as_str().unwrap_or_default();
+            if kind.ends_with(":badNonce") && attempt == 0 {
+                continue;
+            }
+            bail!(
+                "The ACME server answered {}: {}",
+                status,
+                problem["detail"].as_str().unwrap_or(kind)
+            );
+        }
+        bail!("The ACME server kept rejecting nonces for {}", url)
+    }
+
+    async fn new_nonce(&self) -> Result<String> {
+        let response = self
+            .http
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .context("Failed to get an ACME nonce")?;
+        replay_nonce(&response).context("The ACME server returned no nonce")
+    }
+
+    /// The JWS (flattened JSON serialization) for a request to `url`.
+    fn sign(&self, url: &str, nonce: &str, payload: Option<&Value>) -> Result<String> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match &self.kid {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk.clone(),
+        }
+        let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+        let payload = match payload {
+            Some(payload) => URL_SAFE_NO_PAD.encode(payload.to_string()),
+            None => String::new(),
+        };
+        let signature = self
+            .key
+            .sign(
+                &SystemRandom::new(),
+                format!("{}.{}", protected, payload).as_bytes(),
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to sign the ACME request"))?;
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        })
+        .to_string())
+    }
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get("replay-nonce")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// The JWK of a P-256 public key given as an uncompressed point.
+fn jwk(public_key: &[u8]) -> Result<Value> {
+    if public_key.len() != 65 || public_key[0] != 4 {
+        bail!("The ACME account key is not a P-256 key");
+    }
+    Ok(json!({
+        "crv": "P-256",
+        "kty": "EC",
+        "x": URL_SAFE_NO_PAD.encode(&public_key[1..33]),
+        "y": URL_SAFE_NO_PAD.encode(&public_key[33..]),
+    }))
+}
+
+/// `<token>.<JWK thumbprint>` (RFC 8555 section 8.1). The thumbprint hashes
+/// the required members in lexicographic order without whitespace, which is
+/// how `jwk` builds them.
+fn key_authorization(token: &str, jwk: &Value) -> String {
+    let canonical = format!(
+        r#"{{"crv":"{}","kty":"{}","x":"{}","y":"{}"}}"#,
+        jwk["crv"].as_str().unwrap_or_default(),
+        jwk["kty"].as_str().unwrap_or_default(),
+        jwk["x"].as_str().unwrap_or_default(),
+        jwk["y"].as_str().unwrap_or_default(),
+    );
+    let thumbprint = URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()));
+    format!("{}.{}", token, thumbprint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pebble_acme_{}_{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_key_authorization() {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let jwk = jwk(key.public_key_raw()).unwrap();
+        assert_eq!(jwk["kty"], "EC");
+        assert_eq!(jwk["x"].as_str().unwrap().len(), 43);
+
+        let auth = key_authorization("tok3n", &jwk);
+        let (token, thumbprint) = auth.split_once('.').unwrap();
+        assert_eq!(token, "tok3n");
+        assert_eq!(thumbprint.len(), 43);
+        assert_eq!(auth, key_authorization("tok3n", &jwk));
+        assert!(super::jwk(&[4; 33]).is_err());
+    }
+
+    #[test]
+    fn test_jws_signature_verifies() {
+        let account_key = rcgen::KeyPair::generate().unwrap();
+        let client = Client {
+            http: reqwest::Client::new(),
+            directory: Directory {
+                new_nonce: String::new(),
+                new_account: String::new(),
+                new_order: String::new(),
+            },
+            key: EcdsaKeyPair::from_pkcs8(
+                &ECDSA_P256_SHA256_FIXED_SIGNING,
+                &account_key.serialize_der(),
+                &SystemRandom::new(),
+            )
+            .unwrap(),
+            jwk: jwk(account_key.public_key_raw()).unwrap(),
+            kid: None,
+            nonce: None,
+        };
+
+        let jws: Value = serde_json::from_str(
+            &client
+                .sign(
+                    "https://acme.test/new-order",
+                    "n0nce",
+                    Some(&json!({"a": 1})),
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        let protected = jws["protected"].as_str().unwrap();
+        let header: Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(protected).unwrap()).unwrap();
+        assert_eq!(header["alg"], "ES256");
+        assert_eq!(header["nonce"], "n0nce");
+        assert_eq!(header["jwk"]["crv"], "P-256");
+
+        let signed = format!("{}.{}", protected, jws["payload"].as_str().unwrap());
+        let signature = URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, account_key.public_key_raw())
+            .verify(signed.as_bytes(), &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_storage() {
+        let dir = temp_dir("storage");
+        let storage = Storage::new(&dir);
+        let domains = vec!["example.com".to_string(), "www.example.com".to_string()];
+        assert!(storage.load(&domains).is_none());
+
+        let cert = Certificate {
+            cert_pem: "CERT".to_string(),
+            key_pem: "KEY".to_string(),
+        };
+        storage.save(&domains, &cert).unwrap();
+        assert_eq!(storage.load(&domains).unwrap().cert_pem, "CERT");
+        // A changed domain list needs a new certificate
+        assert!(storage.load(&domains[..1]).is_none());
+
+        let key = storage.account_key().unwrap();
+        assert_eq!(
+            storage.account_key().unwrap().public_key_raw(),
+            key.public_key_raw()
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// An in-process ACME server for one order of `example.test`. It checks
+    /// each request's nonce, URL and signature, rejects the first account
+    /// request with `badNonce` and marks the challenge valid once `obtain`
+    /// serves the right key authorization.
+    struct AcmeStub {
+        base: String,
+        challenges: Challenges,
+        cert_pem: String,
+        nonces: std::collections::HashSet<String>,
+        next_nonce: u32,
+        jwk: Option<Value>,
+        rejected_nonce: bool,
+        answered: bool,
+        csr: Option<Vec<u8>>,
+        log: Vec<String>,
+    }
+
+    type Stub = Arc<std::sync::Mutex<AcmeStub>>;
+
+    impl AcmeStub {
+        fn nonce(&mut self) -> String {
+            self.next_nonce += 1;
+            let nonce = format!("nonce-{}", self.next_nonce);
+            self.nonces.insert(nonce.clone());
+            nonce
+        }
+
+        fn order(&self) -> Value {
+            json!({
+                "status": if self.csr.is_some() { "valid" } else { "pending" },
+                "authorizations": [format!("{}/authz/1", self.base)],
+                "finalize": format!("{}/finalize/1", self.base),
+                "certificate": self.csr.as_ref().map(|_| format!("{}/cert/1", self.base)),
+            })
+        }
+
+        /// Check a JWS request to `path`, returning its payload.
+        fn verify(&mut self, path: &str, body: &str) -> std::result::Result<Option<Value>, String> {
+            let jws: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+            let field = |name: &str| jws[name].as_str().unwrap_or_default().to_string();
+            let (protected, payload) = (field("protected"), field("payload"));
+            let header: Value =
+                serde_json::from_slice(&URL_SAFE_NO_PAD.decode(&protected).unwrap()).unwrap();
+            if header["url"] != format!("{}{}", self.base, path) {
+                return Err(format!("wrong url {}", header["url"]));
+            }
+            if !self
+                .nonces
+                .remove(header["nonce"].as_str().unwrap_or_default())
+            {
+                return Err("badNonce".to_string());
+            }
+            if path == "/new-account" {
+                self.jwk = Some(header["jwk"].clone());
+            } else if header["kid"] != format!("{}/account/1", self.base) {
+                return Err("missing kid".to_string());
+            }
+            let jwk = self.jwk.as_ref().ok_or("no account")?;
+            let mut public_key = vec![4];
+            for coordinate in ["x", "y"] {
+                public_key.extend(
+                    URL_SAFE_NO_PAD
+                        .decode(jwk[coordinate].as_str().unwrap())
+                        .unwrap(),
+                );
+            }
+            UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, public_key)
+                .verify(
+                    format!("{}.{}", protected, payload).as_bytes(),
+                    &URL_SAFE_NO_PAD.decode(field("signature")).unwrap(),
+                )
+                .map_err(|_| "bad signature".to_string())?;
+            Ok((!payload.is_empty()).then(|| {
+                serde_json::from_slice(&URL_SAFE_NO_PAD.decode(&payload).unwrap()).unwrap()
+            }))
+        }
+
+        /// Answer a POST to `path`: status, Location header and body.
+        fn handle(&mut self, path: &str, body: &str) -> (u16, Option<String>, String) {
+            self.log.push(path.to_string());
+            let payload = match self.verify(path, body) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    let kind = if e == "badNonce" {
+                        "badNonce"
+                    } else {
+                        "malformed"
+                    };
+                    let problem = json!({
+                        "type": format!("urn:ietf:params:acme:error:{}", kind),
+                        "detail": e,
+                    });
+                    return (400, None, problem.to_string());
+                }
+            };
+            match path {
+                "/new-account" if !self.rejected_nonce => {
+                    self.rejected_nonce = true;
+                    let problem = json!({ "type": "urn:ietf:params:acme:error:badNonce" });
+                    (400, None, problem.to_string())
+                }
+                "/new-account" => (
+                    201,
+                    Some(format!("{}/account/1", self.base)),
+                    json!({ "status": "valid" }).to_string(),
+                ),
+                "/new-order" => {
+                    let payload = payload.unwrap_or_default();
+                    assert_eq!(payload["identifiers"][0]["value"], "example.test");
+                    (
+                        201,
+                        Some(format!("{}/order/1", self.base)),
+                        self.order().to_string(),
+                    )
+                }
+                "/authz/1" => {
+                    let status = if self.answered { "valid" } else { "pending" };
+                    let authz = json!({
+                        "status": status,
+                        "identifier": { "type": "dns", "value": "example.test" },
+                        "challenges": [
+                            { "type": "dns-01", "url": format!("{}/chall/2", self.base), "token": "dns" },
+                            { "type": "http-01", "url": format!("{}/chall/1", self.base), "token": "tok3n" },
+                        ],
+                    });
+                    (200, None, authz.to_string())
+                }
+                "/chall/1" => {
+                    let expected = key_authorization("tok3n", self.jwk.as_ref().unwrap());
+                    let served = self.challenges.read().unwrap().get("tok3n").cloned();
+                    self.answered = served.as_deref() == Some(expected.as_str());
+                    (
+                        200,
+                        None,
+                        json!({ "type": "http-01", "status": "processing" }).to_string(),
+                    )
+                }
+                "/finalize/1" => {
+                    let csr = payload.unwrap_or_default()["csr"]
+                        .as_str()
+                        .unwrap()
+                        .to_string();
+                    self.csr = Some(URL_SAFE_NO_PAD.decode(csr).unwrap());
+                    let mut order = self.order();
+                    order["status"] = json!("processing");
+                    (200, None, order.to_string())
+                }
+                "/order/1" => (200, None, self.order().to_string()),
+                "/cert/1" => (200, None, self.cert_pem.clone()),
+                _ => (404, None, String::new()),
+            }
+        }
+    }
+
+    async fn spawn_acme_stub(challenges: Challenges, cert_pem: String) -> (String, Stub) {
+        use axum::extract::State;
+        use axum::routing::get;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let stub: Stub = Arc::new(std::sync::Mutex::new(AcmeStub {
+            base: base.clone(),
+            challenges,
+            cert_pem,
+            nonces: Default::default(),
+            next_nonce: 0,
+            jwk: None,
+            rejected_nonce: false,
+            answered: false,
+            csr: None,
+            log: Vec::new(),
+        }));
+
+        let app = axum::Router::new()
+            .route(
+                "/dir",
+                get(|State(stub): State<Stub>| async move {
+                    let base = stub.lock().unwrap().base.clone();
+                    axum::Json(json!({
+                        "newNonce": format!("{}/nonce", base),
+                        "newAccount": format!("{}/new-account", base),
+                        "newOrder": format!("{}/new-order", base),
+                    }))
+                }),
+            )
+            .route(
+                "/nonce",
+                get(|State(stub): State<Stub>| async move {
+                    [("replay-nonce", stub.lock().unwrap().nonce())]
+                }),
+            )
+            .fallback(
+                |State(stub): State<Stub>, uri: axum::http::Uri, body: String| async move {
+                    let mut stub = stub.lock().unwrap();
+                    let (status, location, body) = stub.handle(uri.path(), &body);
+                    let mut response = axum::response::Response::builder()
+                        .status(status)
+                        .header("replay-nonce", stub.nonce());
+                    if let Some(location) = location {
+                        response = response.header("location", location);
+                    }
+                    response.body(axum::body::Body::from(body)).unwrap()
+                },
+            )
+            .with_state(stub.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (base, stub)
+    }
+
+    #[tokio::test]
+    async fn test_obtain_from_stub_server() {
+        let dir = temp_dir("stub");
+        let issued = {
+            let key = rcgen::KeyPair::generate().unwrap();
+            let params = rcgen::CertificateParams::new(vec!["example.test".to_string()]).unwrap();
+            params.self_signed(&key).unwrap().pem()
+        };
+        let challenges = Challenges::default();
+        let (base, stub) = spawn_acme_stub(challenges.clone(), issued.clone()).await;
+        let config = TlsConfig {
+            enabled: true,
+            acme_email: Some("admin@example.test".to_string()),
+            acme_directory: format!("{}/dir", base),
+            storage_dir: dir.display().to_string(),
+            ..Default::default()
+        };
+
+        let domains = vec!["example.test".to_string()];
+        let cert = obtain(&config, &domains, &challenges).await.unwrap();
+        assert_eq!(cert.cert_pem, issued);
+        assert!(challenges.read().unwrap().is_empty());
+
+        let stub = stub.lock().unwrap();
+        assert_eq!(
+            stub.log,
+            [
+                "/new-account",
+                "/new-account",
+                "/new-order",
+                "/authz/1",
+                "/chall/1",
+                "/authz/1",
+                "/finalize/1",
+                "/order/1",
+                "/cert/1",
+            ]
+        );
+        assert!(stub.answered);
+        // The CSR names the domain and carries the returned key
+        let csr = stub.csr.as_ref().unwrap();
+        let key = rcgen::KeyPair::from_pem(&cert.key_pem).unwrap();
+        let contains = |needle: &[u8]| csr.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"example.test"));
+        assert!(contains(key.public_key_raw()));
+        assert!(dir.join("account.pem").exists());
+        drop(stub);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Runs against a local ACME test server such as Let's Encrypt's
+    /// `pebble`, started with `PEBBLE_VA_ALWAYS_VALID=1`:
+    ///
+    ///   ACME_TEST_DIRECTORY=https://localhost:14000/dir \
+    ///   ACME_TEST_CA=test/certs/pebble.minica.pem \
+    ///   cargo test acme -- --ignored
+    #[tokio::test]
+    #[ignore]
+    async fn test_obtain_from_test_server() {
+        let directory = std::env::var("ACME_TEST_DIRECTORY").expect("ACME_TEST_DIRECTORY");
+        let dir = temp_dir("obtain");
+        let config: crate::Config = toml::from_str(&format!(
+            r#"
+            [site]
+            title = "Test"
+            description = ""
+            url = "https://example.test"
+            [server]
+            [database]
+            path = ":memory:"
+            [content]
+            [media]
+            upload_dir = "media"
+            [theme]
+            name = "default"
+            [auth]
+            [tls]
+            enabled = true
+            acme_directory = "{}"
+            acme_ca_file = "{}"
+            storage_dir = "{}"
+            "#,
+            directory,
+            std::env::var("ACME_TEST_CA").expect("ACME_TEST_CA"),
+            dir.display()
+        ))
+        .unwrap();
+
+        let domains = vec!["example.test".to_string()];
+        let challenges = Challenges::default();
+        let cert = obtain(&config.tls, &domains, &challenges).await.unwrap();
+        assert!(cert.cert_pem.contains("BEGIN CERTIFICATE"));
+        assert!(challenges.read().unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
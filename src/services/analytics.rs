@@ -41,6 +41,7 @@ impl Default for AnalyticsConfig {
             session_timeout_minutes: 30,
             excluded_paths: vec![
                 "/health".into(),
+                "/metrics".into(),
                 "/robots.txt".into(),
                 "/favicon.ico".into(),
             ],
@@ -28,6 +28,7 @@ pub enum AuditAction {
     SettingsUpdate,
     Cleanup,
     Export,
+    Sync,
 }
 
 impl AuditAction {
@@ -55,6 +56,7 @@ impl AuditAction {
             Self::SettingsUpdate => "settings_update",
             Self::Cleanup => "cleanup",
             Self::Export => "export",
+            Self::Sync => "sync",
         }
     }
 
@@ -82,6 +84,7 @@ impl AuditAction {
             "settings_update" => Some(Self::SettingsUpdate),
             "cleanup" => Some(Self::Cleanup),
             "export" => Some(Self::Export),
+            "sync" => Some(Self::Sync),
             _ => None,
         }
     }
@@ -110,6 +113,7 @@ impl AuditAction {
             Self::SettingsUpdate => "Settings Update",
             Self::Cleanup => "Cleanup",
             Self::Export => "Export",
+            Self::Sync => "Sync",
         }
     }
 }
@@ -736,6 +740,7 @@ pub fn get_all_actions() -> Vec<(&'static str, &'static str)> {
         ("settings_update", "Settings Update"),
         ("cleanup", "Cleanup"),
         ("export", "Export"),
+        ("sync", "Sync"),
     ]
 }
 
@@ -0,0 +1,440 @@
+//! Reader comments — threaded replies on posts with a moderation queue.
+
+use crate::models::{
+    Comment, CommentStatus, CommentThreadItem, CommentWithContent, CreateComment,
+};
+use crate::Database;
+use ammonia::Builder;
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use pulldown_cmark::{html, Event, Options, Parser};
+use std::collections::HashMap;
+
+const MAX_AUTHOR_NAME_LENGTH: usize = 100;
+const MAX_AUTHOR_URL_LENGTH: usize = 500;
+const MAX_COMMENT_LENGTH: usize = 5000;
+
+// Comments are untrusted input: only basic inline/block formatting survives,
+// and every link is marked so search engines don't credit it.
+static COMMENT_SANITIZER: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut sanitizer = Builder::default();
+    sanitizer
+        .tags(
+            [
+                "p", "br", "a", "em", "strong", "code", "pre", "blockquote", "ul", "ol", "li",
+                "del",
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .link_rel(Some("nofollow ugc noopener noreferrer"));
+    sanitizer
+});
+
+const COMMENT_COLUMNS: &str = "c.id, c.content_id, c.parent_id, c.author_name, c.author_url, c.body_markdown, c.body_html, c.status, c.created_at, c.updated_at";
+
+/// Render comment markdown to safe HTML. Raw HTML in the source is shown as
+/// text rather than interpreted, and shortcodes are not processed.
+pub fn render_comment_body(markdown: &str) -> String {
+    let parser = Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        other => other,
+    });
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, parser);
+    COMMENT_SANITIZER.clean(&html_output).to_string()
+}
+
+/// Whether comments are open on a content item. The per-item
+/// `comments_enabled` metadata flag overrides the site-wide default.
+pub fn comments_enabled_for(metadata: &serde_json::Value, site_enabled: bool) -> bool {
+    if !site_enabled {
+        return false;
+    }
+    metadata
+        .get("comments_enabled")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
+
+fn validate_comment_input(input: &CreateComment) -> Result<()> {
+    let name = input.author_name.trim();
+    if name.is_empty() {
+        bail!("Name is required");
+    }
+    if name.chars().count() > MAX_AUTHOR_NAME_LENGTH {
+        bail!("Name must be {} characters or less", MAX_AUTHOR_NAME_LENGTH);
+    }
+    if input.body_markdown.trim().is_empty() {
+        bail!("Comment cannot be empty");
+    }
+    if input.body_markdown.chars().count() > MAX_COMMENT_LENGTH {
+        bail!("Comment must be {} characters or less", MAX_COMMENT_LENGTH);
+    }
+    if let Some(ref url) = input.author_url {
+        let url = url.trim();
+        if url.len() > MAX_AUTHOR_URL_LENGTH {
+            bail!("Website must be {} characters or less", MAX_AUTHOR_URL_LENGTH);
+        }
+        if !url.is_empty() && !url.starts_with("http://") && !url.starts_with("https://") {
+            bail!("Website must start with http:// or https://");
+        }
+    }
+    Ok(())
+}
+
+/// Create a comment. Replies that would nest more than `max_depth` levels
+/// below a top-level comment are attached to the deepest allowed ancestor
+/// instead, so threads stay readable.
+pub fn create_comment(
+    db: &Database,
+    input: CreateComment,
+    status: CommentStatus,
+    max_depth: usize,
+) -> Result<i64> {
+    validate_comment_input(&input)?;
+
+    let parent_id = match input.parent_id {
+        Some(pid) => {
+            let parent = get_comment(db, pid)?
+                .ok_or_else(|| anyhow::anyhow!("The comment you replied to no longer exists"))?;
+            if parent.content_id != input.content_id || parent.status != CommentStatus::Approved {
+                bail!("The comment you replied to no longer exists");
+            }
+            resolve_parent(db, parent, max_depth)?
+        }
+        None => None,
+    };
+
+    let author_url = input
+        .author_url
+        .as_deref()
+        .map(str::trim)
+        .filter(|u| !u.is_empty());
+    let body_html = render_comment_body(&input.body_markdown);
+
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO comments (content_id, parent_id, author_name, author_url, body_markdown, body_html, status) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        rusqlite::params![
+            input.content_id,
+            parent_id,
+            input.author_name.trim(),
+            author_url,
+            input.body_markdown,
+            body_html,
+            status.to_string(),
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn resolve_parent(db: &Database, parent: Comment, max_depth: usize) -> Result<Option<i64>> {
+    if max_depth == 0 {
+        return Ok(None);
+    }
+
+    // Ancestor chain from the parent up to its top-level comment
+    let mut chain = vec![parent];
+    while let Some(pid) = chain.last().and_then(|c| c.parent_id) {
+        match get_comment(db, pid)? {
+            Some(c) => chain.push(c),
+            None => break,
+        }
+    }
+
+    // chain[i] sits at depth (len - 1 - i); the new reply needs its parent at depth < max_depth
+    let excess = chain.len().saturating_sub(max_depth);
+    Ok(Some(chain[excess].id))
+}
+
+pub fn get_comment(db: &Database, id: i64) -> Result<Option<Comment>> {
+    let conn = db.get()?;
+    let comment = conn
+        .query_row(
+            &format!("SELECT {} FROM comments c WHERE c.id = ?", COMMENT_COLUMNS),
+            [id],
+            row_to_comment,
+        )
+        .ok();
+    Ok(comment)
+}
+
+/// Approved comments for a content item, flattened in thread order
+/// (each reply directly follows its parent).
+pub fn list_thread(db: &Database, content_id: i64) -> Result<Vec<CommentThreadItem>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM comments c WHERE c.content_id = ? AND c.status = 'approved' ORDER BY c.created_at ASC, c.id ASC",
+        COMMENT_COLUMNS
+    ))?;
+    let comments: Vec<Comment> = stmt
+        .query_map([content_id], row_to_comment)?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    let mut children: HashMap<Option<i64>, Vec<Comment>> = HashMap::new();
+    for c in comments {
+        children.entry(c.parent_id).or_default().push(c);
+    }
+
+    let mut thread = Vec::new();
+    let mut stack: Vec<(Comment, usize)> = children
+        .remove(&None)
+        .unwrap_or_default()
+        .into_iter()
+        .rev()
+        .map(|c| (c, 0))
+        .collect();
+    while let Some((comment, depth)) = stack.pop() {
+        if let Some(replies) = children.remove(&Some(comment.id)) {
+            stack.extend(replies.into_iter().rev().map(|c| (c, depth + 1)));
+        }
+        thread.push(CommentThreadItem { comment, depth });
+    }
+    Ok(thread)
+}
+
+/// List comments for moderation, newest first, optionally filtered by status.
+pub fn list_comments(
+    db: &Database,
+    status: Option<CommentStatus>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<CommentWithContent>> {
+    let conn = db.get()?;
+    let sql = format!(
+        r#"
+        SELECT {}, content.title, content.slug
+        FROM comments c
+        JOIN content ON c.content_id = content.id
+        WHERE (?1 IS NULL OR c.status = ?1)
+        ORDER BY c.created_at DESC, c.id DESC
+        LIMIT ?2 OFFSET ?3
+        "#,
+        COMMENT_COLUMNS
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let comments = stmt
+        .query_map(
+            rusqlite::params![status.map(|s| s.to_string()), limit, offset],
+            |row| {
+                Ok(CommentWithContent {
+                    comment: row_to_comment(row)?,
+                    content_title: row.get(10)?,
+                    content_slug: row.get(11)?,
+                })
+            },
+        )?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(comments)
+}
+
+pub fn count_comments(db: &Database, status: Option<CommentStatus>) -> Result<i64> {
+    let conn = db.get()?;
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM comments WHERE (?1 IS NULL OR status = ?1)",
+        [status.map(|s| s.to_string())],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// Count every comment on a content item, regardless of status.
+pub fn count_content_comments(db: &Database, content_id: i64) -> Result<i64> {
+    let conn = db.get()?;
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM comments WHERE content_id = ?",
+        [content_id],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+pub fn set_comment_status(db: &Database, id: i64, status: CommentStatus) -> Result<()> {
+    let conn = db.get()?;
+    let updated = conn.execute(
+        "UPDATE comments SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        rusqlite::params![status.to_string(), id],
+    )?;
+    if updated == 0 {
+        bail!("Comment not found");
+    }
+    Ok(())
+}
+
+/// Delete a comment and all replies beneath it.
+pub fn delete_comment(db: &Database, id: i64) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute("DELETE FROM comments WHERE id = ?", [id])?;
+    Ok(())
+}
+
+/// Every comment with its post, oldest first (parents before replies), for export.
+pub fn list_all_comments(db: &Database) -> Result<Vec<CommentWithContent>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, content.title, content.slug FROM comments c JOIN content ON c.content_id = content.id ORDER BY c.id ASC",
+        COMMENT_COLUMNS
+    ))?;
+    let comments = stmt
+        .query_map([], |row| {
+            Ok(CommentWithContent {
+                comment: row_to_comment(row)?,
+                content_title: row.get(10)?,
+                content_slug: row.get(11)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(comments)
+}
+
+/// Insert a comment verbatim (status and timestamp preserved), used by import.
+pub fn import_comment(
+    db: &Database,
+    content_id: i64,
+    parent_id: Option<i64>,
+    comment: &Comment,
+) -> Result<i64> {
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO comments (content_id, parent_id, author_name, author_url, body_markdown, body_html, status, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        rusqlite::params![
+            content_id,
+            parent_id,
+            comment.author_name,
+            comment.author_url,
+            comment.body_markdown,
+            render_comment_body(&comment.body_markdown),
+            comment.status.to_string(),
+            comment.created_at,
+            comment.updated_at,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn row_to_comment(row: &rusqlite::Row) -> rusqlite::Result<Comment> {
+    let status: String = row.get(7)?;
+    Ok(Comment {
+        id: row.get(0)?,
+        content_id: row.get(1)?,
+        parent_id: row.get(2)?,
+        author_name: row.get(3)?,
+        author_url: row.get(4)?,
+        body_markdown: row.get(5)?,
+        body_html: row.get(6)?,
+        status: status.parse().unwrap_or_default(),
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ContentStatus, ContentType, CreateContent};
+    use crate::services::markdown::MarkdownRenderer;
+    use crate::services::content;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    fn setup_test_db() -> Database {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let db = Database::open_memory(&format!("comments_test_{}", id)).unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    fn create_post(db: &Database) -> i64 {
+        content::create_content(
+            db,
+            &MarkdownRenderer::new(),
+            &[],
+            CreateContent {
+                title: "Commented Post".to_string(),
+                slug: Some("commented-post".to_string()),
+                content_type: ContentType::Post,
+                body_markdown: "Hello".to_string(),
+                status: ContentStatus::Published,
+                scheduled_at: None,
+                excerpt: None,
+                featured_image: None,
+                tags: vec![],
+                metadata: None,
+                entry_type: None,
+                fields: None,
+            },
+            None,
+            200,
+        )
+        .unwrap()
+    }
+
+    fn comment(content_id: i64, parent_id: Option<i64>, body: &str) -> CreateComment {
+        CreateComment {
+            content_id,
+            parent_id,
+            author_name: "Reader".to_string(),
+            author_url: None,
+            body_markdown: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_thread_order_and_depth_limit() {
+        let db = setup_test_db();
+        let post_id = create_post(&db);
+
+        let first = create_comment(&db, comment(post_id, None, "first"), CommentStatus::Approved, 1).unwrap();
+        let second = create_comment(&db, comment(post_id, None, "second"), CommentStatus::Approved, 1).unwrap();
+        let reply = create_comment(&db, comment(post_id, Some(first), "reply"), CommentStatus::Approved, 1).unwrap();
+        // Depth 2 would exceed max_depth, so this attaches to `reply`'s parent
+        let deep = create_comment(&db, comment(post_id, Some(reply), "deep"), CommentStatus::Approved, 1).unwrap();
+        assert_eq!(get_comment(&db, deep).unwrap().unwrap().parent_id, Some(first));
+
+        let thread = list_thread(&db, post_id).unwrap();
+        let order: Vec<(i64, usize)> = thread.iter().map(|t| (t.comment.id, t.depth)).collect();
+        assert_eq!(order, vec![(first, 0), (reply, 1), (deep, 1), (second, 0)]);
+    }
+
+    #[test]
+    fn test_moderation_and_visibility() {
+        let db = setup_test_db();
+        let post_id = create_post(&db);
+
+        let id = create_comment(&db, comment(post_id, None, "needs review"), CommentStatus::Pending, 3).unwrap();
+        assert!(list_thread(&db, post_id).unwrap().is_empty());
+        assert_eq!(count_comments(&db, Some(CommentStatus::Pending)).unwrap(), 1);
+
+        // Replies to unapproved comments are rejected
+        assert!(create_comment(&db, comment(post_id, Some(id), "reply"), CommentStatus::Pending, 3).is_err());
+
+        set_comment_status(&db, id, CommentStatus::Approved).unwrap();
+        assert_eq!(list_thread(&db, post_id).unwrap().len(), 1);
+
+        set_comment_status(&db, id, CommentStatus::Spam).unwrap();
+        assert!(list_thread(&db, post_id).unwrap().is_empty());
+        assert_eq!(list_comments(&db, Some(CommentStatus::Spam), 10, 0).unwrap().len(), 1);
+
+        delete_comment(&db, id).unwrap();
+        assert_eq!(count_comments(&db, None).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_comment_body_is_sanitized() {
+        let html = render_comment_body("**hi** <script>alert(1)</script> [link](https://example.com)\n\n[media src=\"x.png\"]");
+        assert!(html.contains("<strong>hi</strong>"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("rel=\"nofollow ugc noopener noreferrer\""));
+        assert!(html.contains("[media src=\"x.png\"]") || html.contains("[media src=&quot;x.png&quot;]"));
+
+        assert!(!comments_enabled_for(&serde_json::json!({}), false));
+        assert!(comments_enabled_for(&serde_json::json!({}), true));
+        assert!(!comments_enabled_for(&serde_json::json!({ "comments_enabled": false }), true));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::{ContentTypeConfig, FieldType};
 use crate::models::{
     Content, ContentStatus, ContentSummary, ContentType, ContentWithTags, CreateContent, Tag,
     UpdateContent, UserSummary,
@@ -7,6 +8,7 @@ use crate::services::slug::{generate_slug, validate_slug};
 use crate::Database;
 use anyhow::{bail, Result};
 use once_cell::sync::Lazy;
+use serde::Serialize;
 use regex::Regex;
 
 static SNIPPET_REGEX: Lazy<Regex> = Lazy::new(|| {
@@ -16,6 +18,9 @@ static SNIPPET_REGEX: Lazy<Regex> = Lazy::new(|| {
 const MAX_TITLE_LENGTH: usize = 500;
 const MAX_BODY_LENGTH: usize = 500_000;
 const MAX_EXCERPT_LENGTH: usize = 2000;
+const MAX_FIELD_LENGTH: usize = 10_000;
+/// Deepest level of snippets-within-snippets that is expanded
+const MAX_SNIPPET_DEPTH: usize = 10;
 
 fn validate_content_input(title: &str, body: &str, excerpt: Option<&str>) -> Result<()> {
     if title.is_empty() {
@@ -40,13 +45,14 @@ fn validate_content_input(title: &str, body: &str, excerpt: Option<&str>) -> Res
 
 pub fn create_content(
     db: &Database,
+    renderer: &MarkdownRenderer,
+    content_types: &[ContentTypeConfig],
     input: CreateContent,
     author_id: Option<i64>,
     excerpt_length: usize,
 ) -> Result<i64> {
     validate_content_input(&input.title, &input.body_markdown, input.excerpt.as_deref())?;
 
-    let renderer = MarkdownRenderer::new();
     let slug = input.slug.unwrap_or_else(|| generate_slug(&input.title));
 
     if !validate_slug(&slug) {
@@ -55,6 +61,18 @@ pub fn create_content(
         );
     }
 
+    let entry_type = match input.content_type {
+        ContentType::Entry => match input.entry_type {
+            Some(name) if !name.is_empty() => Some(name),
+            _ => bail!("Entries must name their content type"),
+        },
+        _ => None,
+    };
+    let fields = match &entry_type {
+        Some(name) => checked_entry_fields(db, content_types, name, input.fields)?,
+        None => input.fields.unwrap_or(serde_json::json!({})),
+    };
+
     let mut conn = db.get()?;
     let tx = conn.transaction()?;
 
@@ -69,8 +87,10 @@ pub fn create_content(
     }
 
     // Process snippet shortcodes before rendering
-    let processed_markdown = process_snippet_shortcodes(db, &input.body_markdown);
-    let body_html = renderer.render(&processed_markdown);
+    let is_snippet = input.content_type == ContentType::Snippet;
+    let expansion = expand_snippets(db, &input.body_markdown, is_snippet.then_some(slug.as_str()));
+    check_snippet_cycle(&expansion, is_snippet)?;
+    let rendered = renderer.render_with_toc(&expansion.markdown);
     let excerpt = input.excerpt.or_else(|| {
         if input.body_markdown.is_empty() {
             None
@@ -120,15 +140,15 @@ pub fn create_content(
 
     tx.execute(
         r#"
-        INSERT INTO content (slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO content (slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, entry_type, fields, toc)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
-        (
+        rusqlite::params![
             &slug,
             &input.title,
             input.content_type.to_string(),
             &input.body_markdown,
-            &body_html,
+            &rendered.html,
             &excerpt,
             &input.featured_image,
             input.status.to_string(),
@@ -136,10 +156,14 @@ pub fn create_content(
             &published_at,
             author_id,
             serde_json::to_string(&metadata)?,
-        ),
+            &entry_type,
+            serde_json::to_string(&fields)?,
+            serde_json::to_string(&rendered.toc)?,
+        ],
     )?;
 
     let content_id = tx.last_insert_rowid();
+    record_dependencies(&tx, content_id, &expansion.dependencies)?;
 
     for tag_name in input.tags {
         let tag_slug = generate_slug(&tag_name);
@@ -154,14 +178,23 @@ pub fn create_content(
     }
 
     tx.commit()?;
+
+    // Content that referenced this slug before the snippet existed
+    if is_snippet {
+        if let Err(e) = rerender_dependents(db, renderer, &[slug.as_str()]) {
+            tracing::warn!("Failed to re-render content using snippet '{}': {}", slug, e);
+        }
+    }
+
     Ok(content_id)
 }
 
 pub fn update_content(
     db: &Database,
+    renderer: &MarkdownRenderer,
+    content_types: &[ContentTypeConfig],
     id: i64,
     input: UpdateContent,
-    _excerpt_length: usize, // Preserved for API compatibility; excerpt is now only updated when explicitly provided
     user_id: Option<i64>,
     version_retention: usize,
 ) -> Result<()> {
@@ -171,11 +204,10 @@ pub fn update_content(
         // Continue with update even if versioning fails
     }
 
-    let renderer = MarkdownRenderer::new();
     let mut conn = db.get()?;
 
     let current: Content = conn.query_row(
-        "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at FROM content WHERE id = ?",
+        "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at, language, translation_group, entry_type, fields, toc FROM content WHERE id = ?",
         [id],
         row_to_content,
     )?;
@@ -206,8 +238,10 @@ pub fn update_content(
     }
 
     // Process snippet shortcodes before rendering
-    let processed_markdown = process_snippet_shortcodes(db, &body_markdown);
-    let body_html = renderer.render(&processed_markdown);
+    let is_snippet = current.content_type == ContentType::Snippet;
+    let expansion = expand_snippets(db, &body_markdown, is_snippet.then_some(slug.as_str()));
+    check_snippet_cycle(&expansion, is_snippet)?;
+    let rendered = renderer.render_with_toc(&expansion.markdown);
     // Only regenerate excerpt if explicitly provided in input, otherwise keep current
     let excerpt = match input.excerpt {
         Some(new_excerpt) => Some(new_excerpt),
@@ -215,6 +249,11 @@ pub fn update_content(
     };
     let featured_image = input.featured_image.or(current.featured_image);
     let status = input.status.unwrap_or(current.status);
+    let fields = match (input.fields, &current.entry_type) {
+        (Some(fields), Some(name)) => checked_entry_fields(db, content_types, name, Some(fields))?,
+        (Some(fields), None) => fields,
+        (None, _) => current.fields,
+    };
 
     // Calculate reading time and merge with provided metadata
     let reading_time = renderer.calculate_reading_time(&body_markdown);
@@ -266,23 +305,26 @@ pub fn update_content(
 
     tx.execute(
         r#"
-        UPDATE content SET slug = ?, title = ?, body_markdown = ?, body_html = ?, excerpt = ?, featured_image = ?, status = ?, scheduled_at = ?, published_at = ?, metadata = ?
+        UPDATE content SET slug = ?, title = ?, body_markdown = ?, body_html = ?, excerpt = ?, featured_image = ?, status = ?, scheduled_at = ?, published_at = ?, metadata = ?, fields = ?, toc = ?
         WHERE id = ?
         "#,
-        (
+        rusqlite::params![
             &slug,
             &title,
             &body_markdown,
-            &body_html,
+            &rendered.html,
             &excerpt,
             &featured_image,
             status.to_string(),
             &scheduled_at,
             &published_at,
             serde_json::to_string(&metadata)?,
+            serde_json::to_string(&fields)?,
+            serde_json::to_string(&rendered.toc)?,
             id,
-        ),
+        ],
     )?;
+    record_dependencies(&tx, id, &expansion.dependencies)?;
 
     if let Some(tags) = input.tags {
         tx.execute("DELETE FROM content_tags WHERE content_id = ?", [id])?;
@@ -301,6 +343,13 @@ pub fn update_content(
 
     tx.commit()?;
 
+    if is_snippet {
+        let slugs = [original_slug.as_str(), slug.as_str()];
+        if let Err(e) = rerender_dependents(db, renderer, &slugs) {
+            tracing::warn!("Failed to re-render content using snippet '{}': {}", slug, e);
+        }
+    }
+
     // Cleanup old versions based on retention policy
     if version_retention > 0 {
         if let Err(e) = super::versions::cleanup_old_versions(db, id, version_retention) {
@@ -311,10 +360,25 @@ pub fn update_content(
     Ok(())
 }
 
-pub fn delete_content(db: &Database, id: i64) -> Result<()> {
+pub fn delete_content(db: &Database, renderer: &MarkdownRenderer, id: i64) -> Result<()> {
     let conn = db.get()?;
+    let snippet_slug: Option<String> = conn
+        .query_row(
+            "SELECT slug FROM content WHERE id = ? AND content_type = 'snippet'",
+            [id],
+            |row| row.get(0),
+        )
+        .ok();
+    conn.execute("DELETE FROM content_dependencies WHERE content_id = ?", [id])?;
     conn.execute("DELETE FROM content WHERE id = ?", [id])?;
     let _ = crate::services::tags::cleanup_orphaned_tags(db);
+
+    // Content that embedded the snippet now shows the bare shortcode
+    if let Some(slug) = snippet_slug {
+        if let Err(e) = rerender_dependents(db, renderer, &[slug.as_str()]) {
+            tracing::warn!("Failed to re-render content using snippet '{}': {}", slug, e);
+        }
+    }
     Ok(())
 }
 
@@ -322,7 +386,7 @@ pub fn get_content_by_id(db: &Database, id: i64) -> Result<Option<ContentWithTag
     let conn = db.get()?;
     let content: Option<Content> = conn
         .query_row(
-            "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at FROM content WHERE id = ?",
+            "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at, language, translation_group, entry_type, fields, toc FROM content WHERE id = ?",
             [id],
             row_to_content,
         )
@@ -338,7 +402,7 @@ pub fn get_content_by_slug(db: &Database, slug: &str) -> Result<Option<ContentWi
     let conn = db.get()?;
     let content: Option<Content> = conn
         .query_row(
-            "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at FROM content WHERE slug = ?",
+            "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at, language, translation_group, entry_type, fields, toc FROM content WHERE slug = ?",
             [slug],
             row_to_content,
         )
@@ -417,7 +481,7 @@ pub fn list_published_content(
 ) -> Result<Vec<ContentWithTags>> {
     let conn = db.get()?;
     let mut stmt = conn.prepare(
-        "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at
+        "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at, language, translation_group, entry_type, fields, toc
          FROM content WHERE content_type = ? AND status = 'published' ORDER BY published_at DESC LIMIT ? OFFSET ?",
     )?;
 
@@ -428,6 +492,236 @@ pub fn list_published_content(
     enrich_content_batch(db, content)
 }
 
+/// Published content in one language. `None` selects the site's default
+/// language, i.e. items with no language set.
+pub fn list_published_content_in_language(
+    db: &Database,
+    content_type: ContentType,
+    language: Option<&str>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<ContentWithTags>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at, language, translation_group, entry_type, fields, toc
+         FROM content WHERE content_type = ? AND status = 'published' AND language IS ? ORDER BY published_at DESC LIMIT ? OFFSET ?",
+    )?;
+
+    let content = stmt
+        .query_map(
+            rusqlite::params![content_type.to_string(), language, limit, offset],
+            row_to_content,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    enrich_content_batch(db, content)
+}
+
+pub fn count_published_content_in_language(
+    db: &Database,
+    content_type: ContentType,
+    language: Option<&str>,
+) -> Result<i64> {
+    let conn = db.get()?;
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM content WHERE content_type = ? AND status = 'published' AND language IS ?",
+        rusqlite::params![content_type.to_string(), language],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// Entries of a custom content type, newest first.
+pub fn list_entries(
+    db: &Database,
+    entry_type: &str,
+    status: Option<ContentStatus>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<ContentSummary>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, slug, title, content_type, excerpt, status, scheduled_at, published_at, created_at
+         FROM content WHERE content_type = 'entry' AND entry_type = ?1 AND (?2 IS NULL OR status = ?2)
+         ORDER BY created_at DESC LIMIT ?3 OFFSET ?4",
+    )?;
+
+    let content = stmt
+        .query_map(
+            rusqlite::params![entry_type, status.map(|s| s.to_string()), limit, offset],
+            |row| {
+                Ok(ContentSummary {
+                    id: row.get(0)?,
+                    slug: row.get(1)?,
+                    title: row.get(2)?,
+                    content_type: ContentType::Entry,
+                    excerpt: row.get(4)?,
+                    status: row
+                        .get::<_, String>(5)?
+                        .parse()
+                        .unwrap_or(ContentStatus::Draft),
+                    scheduled_at: row.get(6)?,
+                    published_at: row.get(7)?,
+                    created_at: row.get(8)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(content)
+}
+
+pub fn count_entries(db: &Database, entry_type: &str, status: Option<ContentStatus>) -> Result<i64> {
+    let conn = db.get()?;
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM content WHERE content_type = 'entry' AND entry_type = ?1 AND (?2 IS NULL OR status = ?2)",
+        rusqlite::params![entry_type, status.map(|s| s.to_string())],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// Published entries of a custom content type, newest first.
+pub fn list_published_entries(
+    db: &Database,
+    entry_type: &str,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<ContentWithTags>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, slug, title, content_type, body_markdown, body_html, excerpt, featured_image, status, scheduled_at, published_at, author_id, metadata, created_at, updated_at, language, translation_group, entry_type, fields, toc
+         FROM content WHERE content_type = 'entry' AND entry_type = ? AND status = 'published' ORDER BY published_at DESC LIMIT ? OFFSET ?",
+    )?;
+
+    let content = stmt
+        .query_map((entry_type, limit, offset), row_to_content)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    enrich_content_batch(db, content)
+}
+
+/// Check an entry's field values against the schema of its type, `entry_type`.
+fn checked_entry_fields(
+    db: &Database,
+    content_types: &[ContentTypeConfig],
+    entry_type: &str,
+    fields: Option<serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let Some(definition) = super::content_types::find(content_types, entry_type) else {
+        bail!("No content type named '{}'", entry_type);
+    };
+    let input = match fields {
+        Some(serde_json::Value::Object(input)) => input,
+        Some(serde_json::Value::Null) | None => serde_json::Map::new(),
+        Some(_) => bail!("Entry fields must be an object"),
+    };
+    validate_entry_fields(db, definition, &input)
+}
+
+/// Check submitted field values against a custom type's schema and return
+/// the values to store. Numbers are stored as JSON numbers, empty optional
+/// fields are dropped, and keys the schema doesn't declare are discarded.
+pub fn validate_entry_fields(
+    db: &Database,
+    definition: &ContentTypeConfig,
+    input: &serde_json::Map<String, serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let mut fields = serde_json::Map::new();
+
+    for field in &definition.fields {
+        let raw = match input.get(&field.name) {
+            Some(serde_json::Value::String(s)) => s.trim().to_string(),
+            Some(serde_json::Value::Number(n)) => n.to_string(),
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        };
+        if raw.is_empty() {
+            if field.required {
+                bail!("{} is required", field.label());
+            }
+            continue;
+        }
+
+        let value = match field.field_type {
+            FieldType::Text => {
+                if raw.len() > MAX_FIELD_LENGTH {
+                    bail!(
+                        "{} must be {} characters or less",
+                        field.label(),
+                        MAX_FIELD_LENGTH
+                    );
+                }
+                serde_json::json!(raw)
+            }
+            FieldType::Number => {
+                let number: f64 = raw
+                    .parse()
+                    .ok()
+                    .filter(|n: &f64| n.is_finite())
+                    .ok_or_else(|| anyhow::anyhow!("{} must be a number", field.label()))?;
+                if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+                    serde_json::json!(number as i64)
+                } else {
+                    serde_json::json!(number)
+                }
+            }
+            FieldType::Date => {
+                if chrono::NaiveDate::parse_from_str(&raw, "%Y-%m-%d").is_err() {
+                    bail!("{} must be a date (YYYY-MM-DD)", field.label());
+                }
+                serde_json::json!(raw)
+            }
+            FieldType::Media => {
+                let filename = raw.trim_start_matches("/media/").to_string();
+                let conn = db.get()?;
+                let exists: bool = conn.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM media WHERE filename = ?)",
+                    [&filename],
+                    |row| row.get(0),
+                )?;
+                if !exists {
+                    bail!("{}: media file '{}' not found", field.label(), filename);
+                }
+                serde_json::json!(filename)
+            }
+            FieldType::Select => {
+                if !field.options.contains(&raw) {
+                    bail!(
+                        "{} must be one of: {}",
+                        field.label(),
+                        field.options.join(", ")
+                    );
+                }
+                serde_json::json!(raw)
+            }
+            FieldType::Reference => {
+                let target = field.to.as_deref().unwrap_or("post");
+                let conn = db.get()?;
+                let exists: bool = match target.parse::<ContentType>() {
+                    Ok(content_type) => conn.query_row(
+                        "SELECT EXISTS(SELECT 1 FROM content WHERE slug = ? AND content_type = ?)",
+                        [&raw, &content_type.to_string()],
+                        |row| row.get(0),
+                    )?,
+                    Err(()) => conn.query_row(
+                        "SELECT EXISTS(SELECT 1 FROM content WHERE slug = ? AND content_type = 'entry' AND entry_type = ?)",
+                        [&raw, target],
+                        |row| row.get(0),
+                    )?,
+                };
+                if !exists {
+                    bail!("{}: no {} with the slug '{}'", field.label(), target, raw);
+                }
+                serde_json::json!(raw)
+            }
+        };
+        fields.insert(field.name.clone(), value);
+    }
+
+    Ok(serde_json::Value::Object(fields))
+}
+
 pub fn count_content(
     db: &Database,
     content_type: Option<ContentType>,
@@ -502,6 +796,11 @@ fn row_to_content(row: &rusqlite::Row) -> rusqlite::Result<Content> {
         metadata,
         created_at: row.get(13)?,
         updated_at: row.get(14)?,
+        language: row.get(15)?,
+        translation_group: row.get(16)?,
+        entry_type: row.get(17)?,
+        fields: serde_json::from_str(&row.get::<_, String>(18)?).unwrap_or(serde_json::json!({})),
+        toc: serde_json::from_str(&row.get::<_, String>(19)?).unwrap_or_default(),
     })
 }
 
@@ -651,7 +950,8 @@ fn enrich_content_batch(db: &Database, contents: Vec<Content>) -> Result<Vec<Con
     Ok(result)
 }
 
-pub fn publish_scheduled(db: &Database) -> Result<usize> {
+/// Publish scheduled content whose time has come. Returns the ids published.
+pub fn publish_scheduled(db: &Database) -> Result<Vec<i64>> {
     let mut conn = db.get()?;
     let now = chrono::Utc::now().to_rfc3339();
 
@@ -666,7 +966,7 @@ pub fn publish_scheduled(db: &Database) -> Result<usize> {
     drop(stmt);
 
     if ids.is_empty() {
-        return Ok(0);
+        return Ok(ids);
     }
 
     for id in &ids {
@@ -678,51 +978,262 @@ pub fn publish_scheduled(db: &Database) -> Result<usize> {
     }
 
     tx.commit()?;
-    Ok(ids.len())
+    Ok(ids)
 }
 
 /// Re-render all content HTML from markdown.
 /// Useful after updating the markdown renderer to apply changes to existing content.
-pub fn rerender_all_content(db: &Database) -> Result<usize> {
-    let renderer = super::markdown::MarkdownRenderer::new();
+/// Also rebuilds the snippet dependencies of every item.
+pub fn rerender_all_content(db: &Database, renderer: &MarkdownRenderer) -> Result<usize> {
     let mut conn = db.get()?;
 
     // Get all content IDs and markdown
-    let items: Vec<(i64, String)> = {
-        let mut stmt = conn.prepare("SELECT id, body_markdown FROM content")?;
-        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let items: Vec<(i64, String, String, String)> = {
+        let mut stmt = conn.prepare("SELECT id, slug, content_type, body_markdown FROM content")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
         rows.collect::<Result<Vec<_>, _>>()?
     };
 
     let count = items.len();
 
     let tx = conn.transaction()?;
-    for (id, markdown) in items {
-        let processed = process_snippet_shortcodes(db, &markdown);
-        let html = renderer.render(&processed);
-        tx.execute("UPDATE content SET body_html = ? WHERE id = ?", (&html, id))?;
+    for (id, slug, content_type, markdown) in items {
+        let own_slug = (content_type == "snippet").then_some(slug.as_str());
+        let expansion = expand_snippets(db, &markdown, own_slug);
+        let rendered = renderer.render_with_toc(&expansion.markdown);
+        tx.execute(
+            "UPDATE content SET body_html = ?, toc = ? WHERE id = ?",
+            (&rendered.html, serde_json::to_string(&rendered.toc)?, id),
+        )?;
+        record_dependencies(&tx, id, &expansion.dependencies)?;
     }
     tx.commit()?;
 
     Ok(count)
 }
 
-/// Process `[snippet slug="..."]` shortcodes by replacing them with the snippet's rendered HTML.
-/// Snippets are resolved from the database. Unknown slugs are left as-is.
-/// To prevent infinite recursion, snippet content is not recursively processed for nested snippets.
-pub fn process_snippet_shortcodes(db: &Database, markdown: &str) -> String {
+/// Markdown with its snippet shortcodes expanded.
+#[derive(Debug, Clone)]
+pub struct SnippetExpansion {
+    pub markdown: String,
+    /// Slugs of every snippet referenced, directly or through other snippets,
+    /// including ones that don't exist (yet)
+    pub dependencies: Vec<String>,
+    /// The first include cycle found, as the chain of slugs that leads back
+    /// to an earlier one, e.g. `["a", "b", "a"]`
+    pub cycle: Option<Vec<String>>,
+}
+
+/// Expand `[snippet slug="..."]` shortcodes with the snippets' markdown,
+/// including snippets used inside snippets. `own_slug` is the slug of the
+/// snippet being rendered, if it is one, so that it can't include itself.
+/// Unknown slugs, shortcodes that would close a cycle, and nesting deeper
+/// than `MAX_SNIPPET_DEPTH` are left as-is.
+pub fn expand_snippets(db: &Database, markdown: &str, own_slug: Option<&str>) -> SnippetExpansion {
+    let mut stack: Vec<String> = own_slug.map(|s| vec![s.to_string()]).unwrap_or_default();
+    let mut dependencies = std::collections::BTreeSet::new();
+    let mut cycle = None;
+    let markdown = expand_snippets_in(db, markdown, &mut stack, &mut dependencies, &mut cycle);
+    SnippetExpansion {
+        markdown,
+        dependencies: dependencies.into_iter().collect(),
+        cycle,
+    }
+}
+
+fn expand_snippets_in(
+    db: &Database,
+    markdown: &str,
+    stack: &mut Vec<String>,
+    dependencies: &mut std::collections::BTreeSet<String>,
+    cycle: &mut Option<Vec<String>>,
+) -> String {
     SNIPPET_REGEX
         .replace_all(markdown, |caps: &regex::Captures| {
             let slug = &caps[1];
+            dependencies.insert(slug.to_string());
+
+            if stack.iter().any(|s| s == slug) {
+                if cycle.is_none() {
+                    let mut chain = stack.clone();
+                    chain.push(slug.to_string());
+                    *cycle = Some(chain);
+                }
+                return caps[0].to_string();
+            }
+            if stack.len() >= MAX_SNIPPET_DEPTH {
+                return caps[0].to_string();
+            }
+
             match get_content_by_slug(db, slug) {
                 Ok(Some(content_with_tags))
                     if content_with_tags.content.content_type == ContentType::Snippet =>
                 {
-                    // Return the snippet's markdown body so it gets rendered inline
-                    content_with_tags.content.body_markdown
+                    stack.push(slug.to_string());
+                    let body = expand_snippets_in(
+                        db,
+                        &content_with_tags.content.body_markdown,
+                        stack,
+                        dependencies,
+                        cycle,
+                    );
+                    stack.pop();
+                    body
                 }
                 _ => caps[0].to_string(), // Leave unresolved shortcodes as-is
             }
         })
         .to_string()
 }
+
+/// Process `[snippet slug="..."]` shortcodes by replacing them with the snippets' markdown.
+/// See [`expand_snippets`].
+pub fn process_snippet_shortcodes(db: &Database, markdown: &str) -> String {
+    expand_snippets(db, markdown, None).markdown
+}
+
+/// A snippet that would include itself is rejected. Other content that runs
+/// into an existing cycle renders with the offending shortcode left as-is.
+fn check_snippet_cycle(expansion: &SnippetExpansion, is_snippet: bool) -> Result<()> {
+    if let Some(chain) = &expansion.cycle {
+        if is_snippet {
+            bail!("Snippet would include itself: {}", chain.join(" → "));
+        }
+        tracing::warn!("Snippet include cycle left unexpanded: {}", chain.join(" → "));
+    }
+    Ok(())
+}
+
+fn record_dependencies(
+    conn: &rusqlite::Connection,
+    content_id: i64,
+    dependencies: &[String],
+) -> Result<()> {
+    conn.execute(
+        "DELETE FROM content_dependencies WHERE content_id = ?",
+        [content_id],
+    )?;
+    for slug in dependencies {
+        conn.execute(
+            "INSERT OR IGNORE INTO content_dependencies (content_id, snippet_slug) VALUES (?, ?)",
+            rusqlite::params![content_id, slug],
+        )?;
+    }
+    Ok(())
+}
+
+/// Re-render the content that uses any of the given snippets, directly or
+/// through another snippet. Returns the number of items re-rendered.
+pub fn rerender_dependents(
+    db: &Database,
+    renderer: &MarkdownRenderer,
+    snippet_slugs: &[&str],
+) -> Result<usize> {
+    let mut ids = std::collections::BTreeSet::new();
+    {
+        let conn = db.get()?;
+        let mut stmt = conn
+            .prepare("SELECT content_id FROM content_dependencies WHERE snippet_slug = ?")?;
+        for slug in snippet_slugs {
+            let rows = stmt.query_map([slug], |row| row.get::<_, i64>(0))?;
+            for id in rows {
+                ids.insert(id?);
+            }
+        }
+    }
+
+    let mut count = 0;
+    for id in ids {
+        if rerender_content(db, renderer, id)? {
+            count += 1;
+        }
+    }
+
+    if count > 0 {
+        tracing::info!("Re-rendered {} item(s) using snippet(s) {}", count, snippet_slugs.join(", "));
+    }
+    Ok(count)
+}
+
+/// Re-render one item's HTML from its markdown and refresh its snippet
+/// dependencies. Returns false if the item doesn't exist.
+pub fn rerender_content(db: &Database, renderer: &MarkdownRenderer, id: i64) -> Result<bool> {
+    let item: Option<(String, String, String)> = db
+        .get()?
+        .query_row(
+            "SELECT slug, content_type, body_markdown FROM content WHERE id = ?",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .ok();
+    let Some((slug, content_type, markdown)) = item else {
+        return Ok(false);
+    };
+
+    let own_slug = (content_type == "snippet").then_some(slug.as_str());
+    let expansion = expand_snippets(db, &markdown, own_slug);
+    let rendered = renderer.render_with_toc(&expansion.markdown);
+    let conn = db.get()?;
+    conn.execute(
+        "UPDATE content SET body_html = ?, toc = ? WHERE id = ?",
+        (&rendered.html, serde_json::to_string(&rendered.toc)?, id),
+    )?;
+    record_dependencies(&conn, id, &expansion.dependencies)?;
+    Ok(true)
+}
+
+/// An item that embeds a snippet, for the snippet's "used by" list.
+#[derive(Debug, Clone, Serialize)]
+pub struct SnippetUse {
+    pub id: i64,
+    pub slug: String,
+    pub title: String,
+    pub content_type: ContentType,
+    pub entry_type: Option<String>,
+    pub status: ContentStatus,
+}
+
+/// Content that uses a snippet, directly or through another snippet.
+pub fn list_snippet_uses(db: &Database, snippet_slug: &str) -> Result<Vec<SnippetUse>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT c.id, c.slug, c.title, c.content_type, c.entry_type, c.status
+        FROM content_dependencies d
+        JOIN content c ON c.id = d.content_id
+        WHERE d.snippet_slug = ? AND c.slug != d.snippet_slug
+        ORDER BY c.title COLLATE NOCASE
+        "#,
+    )?;
+    let rows = stmt.query_map([snippet_slug], |row| {
+        let content_type: String = row.get(3)?;
+        let status: String = row.get(5)?;
+        Ok(SnippetUse {
+            id: row.get(0)?,
+            slug: row.get(1)?,
+            title: row.get(2)?,
+            content_type: content_type.parse().unwrap_or(ContentType::Post),
+            entry_type: row.get(4)?,
+            status: status.parse().unwrap_or(ContentStatus::Draft),
+        })
+    })?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Number of items using each snippet, keyed by snippet slug.
+pub fn snippet_use_counts(db: &Database) -> Result<std::collections::HashMap<String, i64>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT d.snippet_slug, COUNT(*)
+        FROM content_dependencies d
+        JOIN content c ON c.id = d.content_id
+        WHERE c.slug != d.snippet_slug
+        GROUP BY d.snippet_slug
+        "#,
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
@@ -0,0 +1,484 @@
+use crate::config::{ContentTypeConfig, FieldType, I18nConfig};
+use crate::models::{Content, ContentStatus, ContentType, ContentWithTags};
+use crate::services::content;
+use crate::Database;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Top-level path segments used by built-in routes, which a type prefix would shadow.
+const RESERVED_PREFIXES: &[&str] = &[
+    "admin",
+    "api",
+    "feed.json",
+    "feed.xml",
+    "health",
+    "htmx",
+    "js",
+    "media",
+    "metrics",
+    "newsletter",
+    "og",
+    "pages",
+    "posts",
+    "preview",
+    "robots.txt",
+    "search",
+    "series",
+    "sitemap.xml",
+    "tags",
+    "webmention",
+    "xmlrpc",
+];
+
+/// Names of the built-in content types, which custom types can't reuse.
+const RESERVED_NAMES: &[&str] = &["post", "page", "snippet", "entry"];
+
+const MAX_NAME_LENGTH: usize = 50;
+
+fn is_identifier(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_NAME_LENGTH
+        && value.starts_with(|c: char| c.is_ascii_lowercase())
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// Check the `[[content_types]]` declarations: unique names and prefixes that
+/// don't collide with built-in routes or language prefixes, and well-formed fields.
+pub fn validate_definitions(types: &[ContentTypeConfig], i18n: &I18nConfig) -> Result<()> {
+    let mut names = HashSet::new();
+    let mut prefixes = HashSet::new();
+
+    for def in types {
+        if !is_identifier(&def.name) {
+            bail!(
+                "content_types: '{}' is not a valid name (lowercase letters, digits, '-' and '_')",
+                def.name
+            );
+        }
+        if RESERVED_NAMES.contains(&def.name.as_str()) {
+            bail!("content_types: '{}' is a built-in content type", def.name);
+        }
+        if !names.insert(def.name.as_str()) {
+            bail!("content_types: '{}' is declared more than once", def.name);
+        }
+
+        let prefix = def.prefix();
+        if !is_identifier(prefix) {
+            bail!(
+                "content_types.{}: prefix '{}' must be lowercase letters, digits, '-' and '_'",
+                def.name,
+                prefix
+            );
+        }
+        if RESERVED_PREFIXES.contains(&prefix) || i18n.is_additional(prefix) {
+            bail!(
+                "content_types.{}: prefix '{}' is already used by another route",
+                def.name,
+                prefix
+            );
+        }
+        if !prefixes.insert(prefix) {
+            bail!(
+                "content_types.{}: prefix '{}' is used by another content type",
+                def.name,
+                prefix
+            );
+        }
+
+        let mut field_names = HashSet::new();
+        for field in &def.fields {
+            if !is_identifier(&field.name) {
+                bail!(
+                    "content_types.{}: '{}' is not a valid field name",
+                    def.name,
+                    field.name
+                );
+            }
+            if !field_names.insert(field.name.as_str()) {
+                bail!(
+                    "content_types.{}: field '{}' is declared more than once",
+                    def.name,
+                    field.name
+                );
+            }
+            match field.field_type {
+                FieldType::Select if field.options.is_empty() => bail!(
+                    "content_types.{}: select field '{}' needs a list of options",
+                    def.name,
+                    field.name
+                ),
+                FieldType::Reference => {
+                    let target = field.to.as_deref().unwrap_or("post");
+                    let known = ["post", "page"].contains(&target)
+                        || types.iter().any(|t| t.name == target);
+                    if !known {
+                        bail!(
+                            "content_types.{}: field '{}' refers to unknown content type '{}'",
+                            def.name,
+                            field.name,
+                            target
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn find<'a>(types: &'a [ContentTypeConfig], name: &str) -> Option<&'a ContentTypeConfig> {
+    types.iter().find(|t| t.name == name)
+}
+
+pub fn find_by_prefix<'a>(
+    types: &'a [ContentTypeConfig],
+    prefix: &str,
+) -> Option<&'a ContentTypeConfig> {
+    types.iter().find(|t| t.prefix() == prefix)
+}
+
+/// The declared type of an entry, if it is one and its type still exists.
+pub fn definition_for<'a>(
+    types: &'a [ContentTypeConfig],
+    content: &Content,
+) -> Option<&'a ContentTypeConfig> {
+    if content.content_type != ContentType::Entry {
+        return None;
+    }
+    content.entry_type.as_deref().and_then(|name| find(types, name))
+}
+
+/// Template names registered for a type's listing and detail pages.
+pub fn list_template_name(def: &ContentTypeConfig) -> String {
+    match def.list_template {
+        Some(_) => format!("types/{}/list.html", def.name),
+        None => "public/entries.html".to_string(),
+    }
+}
+
+pub fn detail_template_name(def: &ContentTypeConfig) -> String {
+    match def.detail_template {
+        Some(_) => format!("types/{}/entry.html", def.name),
+        None => "public/entry.html".to_string(),
+    }
+}
+
+/// A field value prepared for templates.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldView {
+    pub name: String,
+    pub label: String,
+    pub field_type: FieldType,
+    pub value: serde_json::Value,
+    /// Text to show for the value: a referenced item's title, the media filename, ...
+    pub display: String,
+    /// Link for media and reference fields
+    pub url: Option<String>,
+}
+
+/// Resolve an entry's stored field values against its type, in declaration order.
+/// Fields without a value are skipped, and references only link to published content.
+pub fn field_views(
+    db: &Database,
+    types: &[ContentTypeConfig],
+    def: &ContentTypeConfig,
+    fields: &serde_json::Value,
+    path_for: impl Fn(&Content) -> String,
+) -> Vec<FieldView> {
+    def.fields
+        .iter()
+        .filter_map(|field| {
+            let value = fields.get(&field.name)?.clone();
+            let text = match &value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            let (display, url) = match field.field_type {
+                FieldType::Media => (text.clone(), Some(format!("/media/{}", text))),
+                FieldType::Reference => match content::get_content_by_slug(db, &text) {
+                    Ok(Some(target)) if target.content.status == ContentStatus::Published => {
+                        let url = match definition_for(types, &target.content) {
+                            Some(target_def) => target_def.entry_path(&target.content.slug),
+                            None => path_for(&target.content),
+                        };
+                        (target.content.title, Some(url))
+                    }
+                    _ => (text.clone(), None),
+                },
+                _ => (text, None),
+            };
+            Some(FieldView {
+                name: field.name.clone(),
+                label: field.label().to_string(),
+                field_type: field.field_type,
+                value,
+                display,
+                url,
+            })
+        })
+        .collect()
+}
+
+/// An entry with its public path and resolved fields, as given to templates.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryView {
+    #[serde(flatten)]
+    pub entry: ContentWithTags,
+    pub path: String,
+    pub field_values: Vec<FieldView>,
+}
+
+pub fn entry_view(
+    db: &Database,
+    types: &[ContentTypeConfig],
+    def: &ContentTypeConfig,
+    entry: ContentWithTags,
+    path_for: impl Fn(&Content) -> String,
+) -> EntryView {
+    let field_values = field_views(db, types, def, &entry.content.fields, path_for);
+    EntryView {
+        path: def.entry_path(&entry.content.slug),
+        entry,
+        field_values,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FieldConfig;
+    use crate::models::{CreateContent, UpdateContent};
+    use crate::services::markdown::MarkdownRenderer;
+
+    fn field(name: &str, field_type: FieldType) -> FieldConfig {
+        FieldConfig {
+            name: name.to_string(),
+            label: None,
+            field_type,
+            required: false,
+            options: Vec::new(),
+            to: None,
+            help: None,
+        }
+    }
+
+    fn project_type() -> ContentTypeConfig {
+        let mut status = field("stage", FieldType::Select);
+        status.options = vec!["active".to_string(), "archived".to_string()];
+        let mut repo = field("repo", FieldType::Text);
+        repo.required = true;
+        let mut post = field("writeup", FieldType::Reference);
+        post.to = Some("post".to_string());
+        ContentTypeConfig {
+            name: "project".to_string(),
+            label: Some("Projects".to_string()),
+            prefix: Some("projects".to_string()),
+            list_template: None,
+            detail_template: None,
+            fields: vec![
+                repo,
+                field("year", FieldType::Number),
+                field("started", FieldType::Date),
+                status,
+                post,
+            ],
+        }
+    }
+
+    fn test_db() -> Database {
+        let name = format!("content_types_{}", rand::random::<u64>());
+        let db = Database::open_memory(&name).unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    #[test]
+    fn test_validate_definitions() {
+        let i18n = I18nConfig {
+            languages: vec!["fr".to_string()],
+            ..Default::default()
+        };
+        assert!(validate_definitions(&[project_type()], &i18n).is_ok());
+
+        let mut reserved = project_type();
+        reserved.prefix = Some("posts".to_string());
+        assert!(validate_definitions(&[reserved], &i18n).is_err());
+
+        let mut language = project_type();
+        language.prefix = Some("fr".to_string());
+        assert!(validate_definitions(&[language], &i18n).is_err());
+
+        let mut unknown = project_type();
+        unknown.fields[4].to = Some("recipe".to_string());
+        assert!(validate_definitions(&[unknown], &i18n).is_err());
+
+        assert!(validate_definitions(&[project_type(), project_type()], &i18n).is_err());
+    }
+
+    #[test]
+    fn test_validate_entry_fields() {
+        let db = test_db();
+        let def = project_type();
+        content::create_content(
+            &db,
+            &MarkdownRenderer::new(),
+            &[],
+            CreateContent {
+                title: "Launch".to_string(),
+                slug: Some("launch".to_string()),
+                content_type: ContentType::Post,
+                body_markdown: "Hello".to_string(),
+                excerpt: None,
+                featured_image: None,
+                status: ContentStatus::Published,
+                scheduled_at: None,
+                tags: vec![],
+                metadata: None,
+                entry_type: None,
+                fields: None,
+            },
+            None,
+            200,
+        )
+        .unwrap();
+
+        let input = serde_json::json!({
+            "repo": " https://example.com/repo ",
+            "year": "2024",
+            "started": "2024-03-01",
+            "stage": "active",
+            "writeup": "launch",
+            "unknown": "dropped",
+        });
+        let fields = content::validate_entry_fields(&db, &def, input.as_object().unwrap()).unwrap();
+        assert_eq!(fields["repo"], "https://example.com/repo");
+        assert_eq!(fields["year"], 2024);
+        assert!(fields.get("unknown").is_none());
+
+        let bad = |key: &str, value: &str| {
+            let mut input = input.as_object().unwrap().clone();
+            input.insert(key.to_string(), serde_json::json!(value));
+            content::validate_entry_fields(&db, &def, &input).is_err()
+        };
+        assert!(bad("repo", ""));
+        assert!(bad("year", "soon"));
+        assert!(bad("started", "March"));
+        assert!(bad("stage", "paused"));
+        assert!(bad("writeup", "missing"));
+        assert!(!bad("year", ""));
+    }
+
+    fn create(db: &Database, types: &[ContentTypeConfig], input: CreateContent) -> Result<i64> {
+        content::create_content(db, &MarkdownRenderer::new(), types, input, None, 200)
+    }
+
+    fn post(slug: &str, status: ContentStatus) -> CreateContent {
+        CreateContent {
+            title: slug.to_string(),
+            slug: Some(slug.to_string()),
+            content_type: ContentType::Post,
+            body_markdown: "Hello".to_string(),
+            excerpt: None,
+            featured_image: None,
+            status,
+            scheduled_at: None,
+            tags: vec![],
+            metadata: None,
+            entry_type: None,
+            fields: None,
+        }
+    }
+
+    fn project(slug: &str, fields: serde_json::Value) -> CreateContent {
+        CreateContent {
+            title: slug.to_string(),
+            slug: Some(slug.to_string()),
+            content_type: ContentType::Entry,
+            body_markdown: "A CMS".to_string(),
+            excerpt: None,
+            featured_image: None,
+            status: ContentStatus::Published,
+            scheduled_at: None,
+            tags: vec![],
+            metadata: None,
+            entry_type: Some("project".to_string()),
+            fields: Some(fields),
+        }
+    }
+
+    #[test]
+    fn test_entries_and_field_views() {
+        let db = test_db();
+        let def = project_type();
+        let types = vec![def.clone()];
+        let fields = serde_json::json!({ "repo": "https://example.com", "year": 2024 });
+        let id = create(&db, &types, project("pebble", fields)).unwrap();
+
+        let entries = content::list_published_entries(&db, "project", 10, 0).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content.id, id);
+        assert_eq!(content::count_entries(&db, "project", None).unwrap(), 1);
+        assert_eq!(content::count_entries(&db, "talk", None).unwrap(), 0);
+
+        let entry = &entries[0].content;
+        assert_eq!(definition_for(&types, entry).unwrap().name, "project");
+        let views = field_views(&db, &types, &def, &entry.fields, |c| c.slug.clone());
+        assert_eq!(views.len(), 2);
+        assert_eq!(views[1].display, "2024");
+        assert_eq!(def.entry_path("pebble"), "/projects/pebble");
+    }
+
+    #[test]
+    fn test_entry_fields_checked_on_save() {
+        let db = test_db();
+        let types = vec![project_type()];
+        let valid = serde_json::json!({ "repo": "https://example.com" });
+
+        let missing_repo = project("bad", serde_json::json!({ "year": 2024 }));
+        assert!(create(&db, &types, missing_repo).is_err());
+        assert!(create(&db, &[], project("untyped", valid.clone())).is_err());
+        assert!(content::get_content_by_slug(&db, "bad").unwrap().is_none());
+
+        let id = create(&db, &types, project("pebble", valid)).unwrap();
+        let update = |fields: serde_json::Value| {
+            let input = UpdateContent {
+                fields: Some(fields),
+                ..Default::default()
+            };
+            content::update_content(&db, &MarkdownRenderer::new(), &types, id, input, None, 0)
+        };
+        assert!(update(serde_json::json!({ "repo": "" })).is_err());
+        assert!(update(serde_json::json!({ "repo": "x", "stage": "paused" })).is_err());
+        update(serde_json::json!({ "repo": " https://pebble.dev ", "extra": 1 })).unwrap();
+
+        let stored = content::get_content_by_id(&db, id).unwrap().unwrap();
+        assert_eq!(
+            stored.content.fields,
+            serde_json::json!({ "repo": "https://pebble.dev" })
+        );
+    }
+
+    #[test]
+    fn test_references_resolve_only_to_published_content() {
+        let db = test_db();
+        let def = project_type();
+        let types = vec![def.clone()];
+        create(&db, &types, post("launch", ContentStatus::Published)).unwrap();
+        create(&db, &types, post("upcoming", ContentStatus::Draft)).unwrap();
+
+        let writeup = |slug: &str| {
+            let fields = serde_json::json!({ "repo": "x", "writeup": slug });
+            let views = field_views(&db, &types, &def, &fields, |c| format!("/posts/{}", c.slug));
+            (views[1].display.clone(), views[1].url.clone())
+        };
+        assert_eq!(
+            writeup("launch"),
+            ("launch".to_string(), Some("/posts/launch".to_string()))
+        );
+        assert_eq!(writeup("upcoming"), ("upcoming".to_string(), None));
+    }
+}
@@ -239,9 +239,9 @@ pub fn get_database_stats(db: &Database, db_path: &str) -> Result<DatabaseStats>
     // Cache stats from sqlite3_db_status
     let (cache_hit, cache_miss, cache_write, cache_spill) = get_cache_stats(&conn);
 
-    // Pool stats - we can get rough estimates
-    let pool_size = 10u32; // Default from Database::open
-    let pool_idle = 0u32; // Not easily accessible through r2d2
+    // Pool stats
+    let pool_size = db.pool_max_size();
+    let pool_idle = db.pool_state().idle_connections;
 
     Ok(DatabaseStats {
         file_size_bytes,
@@ -0,0 +1,592 @@
+//! Deployment files for a site: a systemd unit, Caddy and nginx reverse
+//! proxy configs, and a Dockerfile with a Compose file, filled in from the
+//! site's configuration.
+
+use crate::web::listener::ListenAddr;
+use crate::Config;
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeployTarget {
+    Systemd,
+    Caddy,
+    Nginx,
+    Docker,
+}
+
+impl std::str::FromStr for DeployTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "systemd" => Ok(Self::Systemd),
+            "caddy" => Ok(Self::Caddy),
+            "nginx" => Ok(Self::Nginx),
+            "docker" => Ok(Self::Docker),
+            _ => bail!(
+                "Unknown deploy target '{}'. Use systemd, caddy, nginx or docker",
+                s
+            ),
+        }
+    }
+}
+
+/// Where and how the site is installed, beyond what its config says.
+#[derive(Debug, Clone)]
+pub struct DeploySite {
+    /// Short name used for the unit, upstream and service names
+    pub name: String,
+    /// Directory holding `pebble.toml`; relative config paths resolve here
+    pub site_dir: PathBuf,
+    /// The `pebble` binary the service runs
+    pub binary: PathBuf,
+    /// System user the service runs as
+    pub user: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedFile {
+    pub name: String,
+    pub contents: String,
+}
+
+/// The public address of a site, from `site.url`.
+struct PublicUrl {
+    https: bool,
+    host: String,
+    port: Option<u16>,
+}
+
+impl PublicUrl {
+    fn parse(site_url: &str) -> Result<Self> {
+        let url = url::Url::parse(site_url)
+            .with_context(|| format!("site.url '{}' is not a valid URL", site_url))?;
+        let host = url
+            .host_str()
+            .with_context(|| format!("site.url '{}' has no host name", site_url))?
+            .to_string();
+        Ok(Self {
+            https: url.scheme() == "https",
+            host,
+            port: url.port(),
+        })
+    }
+}
+
+pub fn generate(
+    target: DeployTarget,
+    config: &Config,
+    site: &DeploySite,
+) -> Result<Vec<GeneratedFile>> {
+    let raw = config;
+    let mut config = config.clone();
+    config.resolve_paths(&site.site_dir);
+    let url = PublicUrl::parse(&config.site.url)?;
+
+    Ok(match target {
+        DeployTarget::Systemd => {
+            let mut files = vec![GeneratedFile {
+                name: format!("pebble-{}.service", site.name),
+                contents: systemd_unit(&config, site),
+            }];
+            if let Some(ListenAddr::Systemd(name)) = listen_addr(&config) {
+                files.push(GeneratedFile {
+                    name: format!("pebble-{}.socket", site.name),
+                    contents: systemd_socket(&config, site, name.as_deref()),
+                });
+            }
+            files
+        }
+        DeployTarget::Caddy => vec![GeneratedFile {
+            name: "Caddyfile".to_string(),
+            contents: caddyfile(&config, site, &url),
+        }],
+        DeployTarget::Nginx => vec![GeneratedFile {
+            name: format!("pebble-{}.conf", site.name),
+            contents: nginx_conf(&config, site, &url),
+        }],
+        DeployTarget::Docker => vec![
+            GeneratedFile {
+                name: "Dockerfile".to_string(),
+                contents: dockerfile(&config, site),
+            },
+            GeneratedFile {
+                name: "docker-compose.yml".to_string(),
+                contents: compose_file(raw, site),
+            },
+        ],
+    })
+}
+
+fn header(target: &str, config: &Config) -> String {
+    format!(
+        "# Generated by `pebble deploy-config --target {}` for {}\n",
+        target, config.site.title
+    )
+}
+
+/// Directories the server writes to: the database's (SQLite keeps its WAL
+/// next to it), media uploads, backups and the directory of a Unix socket.
+fn writable_dirs(config: &Config) -> Vec<String> {
+    let parent = |path: &Path| {
+        path.parent()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let socket_dir = match listen_addr(config) {
+        Some(ListenAddr::Unix(path)) => parent(&path),
+        _ => String::new(),
+    };
+    let dirs: BTreeSet<String> = [
+        parent(Path::new(&config.database.path)),
+        config.media.upload_dir.clone(),
+        config.backup.directory.clone(),
+        socket_dir,
+    ]
+    .into_iter()
+    .filter(|d| !d.is_empty())
+    .map(|d| d.trim_end_matches('/').to_string())
+    .collect();
+
+    // Media usually lives inside the database's directory
+    let mut outermost: Vec<String> = Vec::new();
+    for dir in dirs {
+        if !outermost.iter().any(|o| Path::new(&dir).starts_with(o)) {
+            outermost.push(dir);
+        }
+    }
+    outermost
+}
+
+/// `server.listen`, when set and valid.
+fn listen_addr(config: &Config) -> Option<ListenAddr> {
+    config
+        .server
+        .listen
+        .as_deref()
+        .and_then(|addr| ListenAddr::parse(addr).ok())
+}
+
+/// The TCP address the server listens on.
+fn tcp_addr(config: &Config) -> String {
+    match listen_addr(config) {
+        Some(ListenAddr::Tcp(addr)) => addr,
+        _ => format!("{}:{}", config.server.host, config.server.port),
+    }
+}
+
+/// Where a reverse proxy reaches the server; `unix_prefix` is how the proxy
+/// spells a Unix socket path (`unix:` for nginx, `unix/` for Caddy).
+fn upstream(config: &Config, unix_prefix: &str) -> String {
+    if let Some(ListenAddr::Unix(path)) = listen_addr(config) {
+        return format!("{}{}", unix_prefix, path.display());
+    }
+    let addr = tcp_addr(config);
+    for any in ["0.0.0.0:", "[::]:", ":::"] {
+        if let Some(port) = addr.strip_prefix(any) {
+            return format!("127.0.0.1:{}", port);
+        }
+    }
+    addr
+}
+
+/// Upload limit for proxies, rounded up to whole megabytes.
+fn upload_limit_mb(config: &Config) -> usize {
+    config.media.max_upload_bytes().div_ceil(1024 * 1024).max(1)
+}
+
+fn systemd_unit(config: &Config, site: &DeploySite) -> String {
+    let site_dir = site.site_dir.display();
+    let config_path = site.site_dir.join("pebble.toml");
+    let under_home = writable_dirs(config)
+        .iter()
+        .chain(std::iter::once(&site_dir.to_string()))
+        .any(|d| d.starts_with("/home/") || d.starts_with("/root"));
+
+    let mut out = header("systemd", config);
+    let _ = write!(
+        out,
+        r#"# Install: sudo cp pebble-{name}.service /etc/systemd/system/
+#          sudo systemctl daemon-reload && sudo systemctl enable --now pebble-{name}
+
+[Unit]
+Description=Pebble site: {title}
+Documentation=https://github.com/ooyeku/pebble-cms
+After=network-online.target
+Wants=network-online.target
+{socket_deps}
+[Service]
+Type=simple
+User={user}
+Group={user}
+WorkingDirectory={site_dir}
+ExecStart={binary} --config {config_path} deploy{listen_args}
+Environment=RUST_LOG=info
+Restart=on-failure
+RestartSec=5
+KillSignal=SIGTERM
+TimeoutStopSec=30
+
+# Sandboxing: the server may only write to its data directories
+NoNewPrivileges=true
+ProtectSystem=strict
+ProtectHome={protect_home}
+"#,
+        name = site.name,
+        title = config.site.title,
+        user = site.user,
+        site_dir = site_dir,
+        binary = site.binary.display(),
+        config_path = config_path.display(),
+        socket_deps = match listen_addr(config) {
+            Some(ListenAddr::Systemd(_)) => format!(
+                "Requires=pebble-{name}.socket\nAfter=pebble-{name}.socket\n",
+                name = site.name
+            ),
+            _ => String::new(),
+        },
+        listen_args = if config.server.listen.is_some() {
+            String::new()
+        } else {
+            format!(
+                " --host {} --port {}",
+                config.server.host, config.server.port
+            )
+        },
+        protect_home = if under_home { "read-only" } else { "true" },
+    );
+    for dir in writable_dirs(config) {
+        let _ = writeln!(out, "ReadWritePaths=-{}", dir);
+    }
+    out.push_str(
+        r#"PrivateTmp=true
+PrivateDevices=true
+ProtectKernelTunables=true
+ProtectKernelModules=true
+ProtectKernelLogs=true
+ProtectControlGroups=true
+ProtectClock=true
+ProtectHostname=true
+RestrictAddressFamilies=AF_INET AF_INET6 AF_UNIX
+RestrictNamespaces=true
+RestrictRealtime=true
+RestrictSUIDSGID=true
+LockPersonality=true
+MemoryDenyWriteExecute=true
+SystemCallArchitectures=native
+SystemCallFilter=@system-service
+SystemCallFilter=~@privileged @resources
+CapabilityBoundingSet=
+AmbientCapabilities=
+UMask=0027
+
+[Install]
+WantedBy=multi-user.target
+"#,
+    );
+    out
+}
+
+/// The socket unit for `server.listen = "systemd"`: systemd holds the
+/// listening socket, so connections wait in its queue while the service
+/// restarts instead of being refused.
+fn systemd_socket(config: &Config, site: &DeploySite, name: Option<&str>) -> String {
+    let mut out = header("systemd", config);
+    let _ = write!(
+        out,
+        r#"# Install next to pebble-{site}.service:
+#          sudo cp pebble-{site}.socket /etc/systemd/system/
+#          sudo systemctl daemon-reload && sudo systemctl enable --now pebble-{site}.socket
+
+[Unit]
+Description=Pebble site socket: {title}
+
+[Socket]
+ListenStream={listen}
+{fd_name}NoDelay=true
+
+[Install]
+WantedBy=sockets.target
+"#,
+        site = site.name,
+        title = config.site.title,
+        listen = upstream(config, ""),
+        fd_name = match name {
+            Some(name) if name.parse::<usize>().is_err() => {
+                format!("FileDescriptorName={}\n", name)
+            }
+            _ => String::new(),
+        },
+    );
+    out
+}
+
+fn caddyfile(config: &Config, site: &DeploySite, url: &PublicUrl) -> String {
+    let scheme = if url.https { "https" } else { "http" };
+    let address = match url.port {
+        Some(port) => format!("{}://{}:{}", scheme, url.host, port),
+        None => format!("{}://{}", scheme, url.host),
+    };
+    let hsts = if url.https {
+        "\t\tStrict-Transport-Security \"max-age=31536000; includeSubDomains\"\n"
+    } else {
+        ""
+    };
+
+    let mut out = header("caddy", config);
+    let _ = write!(
+        out,
+        r#"{tls_note}
+{address} {{
+	encode zstd gzip
+
+	request_body {{
+		max_size {upload}MB
+	}}
+
+	header {{
+{hsts}		-Server
+	}}
+
+	# Uploads get unique file names, so browsers may cache them for good
+	handle_path /media/* {{
+		root * {media}
+		header Cache-Control "public, max-age=31536000, immutable"
+		file_server
+	}}
+
+	# Caddy sets X-Forwarded-For, X-Forwarded-Proto and X-Forwarded-Host;
+	# a Forwarded header from the client is dropped so it cannot be trusted
+	handle {{
+		reverse_proxy {upstream} {{
+			header_up X-Real-IP {{remote_host}}
+			header_up -Forwarded
+		}}
+	}}
+
+	log {{
+		output file /var/log/caddy/pebble-{name}.log
+	}}
+}}
+"#,
+        tls_note = if url.https {
+            format!(
+                "# Caddy obtains and renews the HTTPS certificate for {} itself.\n",
+                url.host
+            )
+        } else {
+            String::new()
+        },
+        address = address,
+        upload = upload_limit_mb(config),
+        hsts = hsts,
+        media = config.media.upload_dir,
+        upstream = upstream(config, "unix/"),
+        name = site.name,
+    );
+    out
+}
+
+fn nginx_conf(config: &Config, site: &DeploySite, url: &PublicUrl) -> String {
+    let upstream_name = format!("pebble_{}", site.name.replace('-', "_"));
+    let media = format!("{}/", config.media.upload_dir.trim_end_matches('/'));
+
+    let mut out = header("nginx", config);
+    let _ = write!(
+        out,
+        r#"# Install: sudo cp pebble-{name}.conf /etc/nginx/conf.d/ && sudo nginx -t && sudo systemctl reload nginx
+
+upstream {upstream_name} {{
+    server {upstream};
+    keepalive 16;
+}}
+"#,
+        name = site.name,
+        upstream_name = upstream_name,
+        upstream = upstream(config, "unix:"),
+    );
+
+    let (listen, tls, hsts) = if url.https {
+        let port = url.port.unwrap_or(443);
+        let _ = write!(
+            out,
+            r#"
+server {{
+    listen 80;
+    listen [::]:80;
+    server_name {host};
+
+    location /.well-known/acme-challenge/ {{
+        root /var/www/letsencrypt;
+    }}
+
+    location / {{
+        return 301 https://$host$request_uri;
+    }}
+}}
+"#,
+            host = url.host,
+        );
+        (
+            format!(
+                "    listen {port} ssl http2;\n    listen [::]:{port} ssl http2;\n",
+                port = port
+            ),
+            format!(
+                r#"
+    # Certificates from e.g. `certbot certonly --webroot -w /var/www/letsencrypt -d {host}`
+    ssl_certificate /etc/letsencrypt/live/{host}/fullchain.pem;
+    ssl_certificate_key /etc/letsencrypt/live/{host}/privkey.pem;
+    ssl_protocols TLSv1.2 TLSv1.3;
+    ssl_prefer_server_ciphers off;
+    ssl_session_cache shared:SSL:10m;
+    ssl_session_timeout 1d;
+"#,
+                host = url.host
+            ),
+            "    add_header Strict-Transport-Security \"max-age=31536000; includeSubDomains\" always;\n",
+        )
+    } else {
+        let port = url.port.unwrap_or(80);
+        (
+            format!("    listen {port};\n    listen [::]:{port};\n", port = port),
+            String::new(),
+            "",
+        )
+    };
+
+    let _ = write!(
+        out,
+        r#"
+server {{
+{listen}    server_name {host};
+{tls}
+    server_tokens off;
+    client_max_body_size {upload}m;
+{hsts}
+    # Uploads get unique file names, so browsers may cache them for good.
+    # add_header here replaces the server-level headers, hence the repeat.
+    location /media/ {{
+        alias {media};
+        add_header Cache-Control "public, max-age=31536000, immutable";
+        add_header X-Content-Type-Options "nosniff";
+{hsts_media}        access_log off;
+    }}
+
+    location / {{
+        proxy_pass http://{upstream_name};
+        proxy_http_version 1.1;
+        proxy_set_header Connection "";
+        proxy_set_header Host $host;
+        proxy_set_header X-Real-IP $remote_addr;
+        proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+        proxy_set_header X-Forwarded-Proto $scheme;
+        proxy_set_header X-Forwarded-Host $host;
+        # Drop any Forwarded header the client sent
+        proxy_set_header Forwarded "";
+        proxy_read_timeout 60s;
+    }}
+}}
+"#,
+        listen = listen,
+        host = url.host,
+        tls = tls,
+        upload = upload_limit_mb(config),
+        hsts = hsts,
+        media = media,
+        hsts_media = hsts.replace("    add_header", "        add_header"),
+        upstream_name = upstream_name,
+    );
+    out
+}
+
+fn dockerfile(config: &Config, _site: &DeploySite) -> String {
+    let mut out = header("docker", config);
+    let _ = write!(
+        out,
+        r#"# Build: docker compose build
+
+FROM rust:1.75-bookworm AS builder
+RUN cargo install pebble-cms --version {version} --locked --root /out
+
+FROM debian:bookworm-slim
+
+RUN apt-get update && \
+    apt-get install -y --no-install-recommends ca-certificates && \
+    rm -rf /var/lib/apt/lists/*
+
+# Fixed IDs so the mounted site directory can be owned by this user:
+#   sudo chown -R 10001:10001 <site directory>
+RUN groupadd -r -g 10001 pebble && \
+    useradd -r -u 10001 -g pebble -s /usr/sbin/nologin -d /site pebble
+
+COPY --from=builder /out/bin/pebble /usr/local/bin/pebble
+
+USER pebble
+WORKDIR /site
+
+EXPOSE {port}
+
+HEALTHCHECK --interval=30s --timeout=5s --start-period=10s --retries=3 \
+    CMD pebble --config /site/pebble.toml doctor || exit 1
+
+ENTRYPOINT ["pebble", "--config", "/site/pebble.toml"]
+CMD ["deploy", "--listen", "0.0.0.0:{port}"]
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+        port = config.server.port,
+    );
+    out
+}
+
+/// `config` is unresolved: relative paths stay relative to `/site`, where the
+/// site directory is mounted, and absolute ones are mounted at the same path.
+fn compose_file(config: &Config, site: &DeploySite) -> String {
+    let site_dir = site.site_dir.display().to_string();
+    // Publish only on loopback unless the site is configured to listen publicly
+    let publish = match config.server.host.as_str() {
+        "0.0.0.0" | "::" | "[::]" => format!("{}:{}", config.server.port, config.server.port),
+        _ => format!("127.0.0.1:{}:{}", config.server.port, config.server.port),
+    };
+
+    let mut out = header("docker", config);
+    let _ = write!(
+        out,
+        r#"# Run: docker compose up -d
+
+services:
+  {name}:
+    build: .
+    image: pebble-{name}
+    restart: unless-stopped
+    ports:
+      - "{publish}"
+    volumes:
+      - "{site_dir}:/site"
+"#,
+        name = site.name,
+        publish = publish,
+        site_dir = site_dir,
+    );
+    for dir in writable_dirs(config) {
+        if Path::new(&dir).is_absolute() {
+            let _ = writeln!(out, "      - \"{}:{}\"", dir, dir);
+        }
+    }
+    out.push_str(
+        r#"    environment:
+      RUST_LOG: info
+    read_only: true
+    tmpfs:
+      - /tmp
+    cap_drop:
+      - ALL
+    security_opt:
+      - no-new-privileges:true
+"#,
+    );
+    out
+}
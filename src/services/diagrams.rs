@@ -0,0 +1,862 @@
+//! Diagrams from fenced code blocks, rendered to inline SVG when content is
+//! saved.
+//!
+//! ```` ```dot ```` (or ```` ```graphviz ````) blocks are laid out with
+//! layout-rs. ```` ```mermaid ```` flowcharts (`graph`/`flowchart`) are
+//! translated to DOT and laid out the same way; other Mermaid diagram types
+//! have no pure-Rust renderer and are shown as code.
+//!
+//! The SVG is drawn by our own backend so it needs no ids, `<style>` or
+//! `<defs>`, and uses `currentColor` for default strokes and text so
+//! diagrams follow the site's colour scheme.
+
+use layout::core::color::Color;
+use layout::core::format::{ClipHandle, RenderBackend};
+use layout::core::geometry::Point;
+use layout::core::style::StyleAttr;
+use layout::gv::parser::ast::{
+    ArrowKind, AttrStmt, AttrStmtTarget, AttributeList, EdgeStmt, Graph, NodeId, NodeStmt, Stmt,
+    StmtList,
+};
+use layout::gv::{GraphBuilder, Lexer, Token};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Largest diagram source accepted, to bound layout time on save.
+const MAX_SOURCE_LEN: usize = 20_000;
+/// Most node and edge statements a diagram may have. Layout cost grows
+/// quickly with crossings, so this keeps a save under a second.
+const MAX_ELEMENTS: usize = 150;
+/// Deepest nesting of subgraphs, so parsing cannot exhaust the stack.
+const MAX_DEPTH: usize = 32;
+const PADDING: f64 = 8.0;
+
+/// Whether a fenced code block language is a diagram.
+pub fn is_diagram(lang: &str) -> bool {
+    matches!(lang, "dot" | "graphviz" | "mermaid")
+}
+
+/// Render a diagram block to an SVG figure.
+pub fn render(lang: &str, source: &str) -> Result<String, String> {
+    if source.len() > MAX_SOURCE_LEN {
+        return Err(format!(
+            "Diagram is larger than {} characters",
+            MAX_SOURCE_LEN
+        ));
+    }
+    let dot = match lang {
+        "mermaid" => mermaid_to_dot(source)?,
+        _ => source.to_string(),
+    };
+    let svg = dot_to_svg(&dot)?;
+    Ok(format!(
+        r#"<figure class="diagram diagram-{}">{}</figure>"#,
+        if lang == "graphviz" { "dot" } else { lang },
+        svg
+    ))
+}
+
+fn dot_to_svg(dot: &str) -> Result<String, String> {
+    let graph = DotParser::parse(dot)?;
+
+    if count_elements(&graph) > MAX_ELEMENTS {
+        return Err(format!(
+            "Diagram has more than {} nodes and edges",
+            MAX_ELEMENTS
+        ));
+    }
+
+    let mut builder = GraphBuilder::new();
+    builder.visit_graph(&graph);
+    let mut visual = builder.get();
+    if visual.num_nodes() == 0 {
+        return Err("Diagram has no nodes".to_string());
+    }
+
+    let mut svg = SvgBackend::default();
+    visual.do_it(false, false, false, &mut svg);
+    Ok(svg.finish())
+}
+
+fn count_elements(graph: &Graph) -> usize {
+    graph
+        .list
+        .list
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::Edge(edge) => edge.to.len(),
+            Stmt::Node(_) => 1,
+            Stmt::SubGraph(subgraph) => count_elements(subgraph),
+            Stmt::Attribute(_) => 0,
+        })
+        .sum()
+}
+
+/// A DOT parser producing layout-rs's syntax tree. It accepts the same
+/// grammar as layout-rs's own `DotParser`, which prints the offending token
+/// to stdout on malformed input.
+struct DotParser {
+    lexer: Lexer,
+    tok: Token,
+    depth: usize,
+}
+
+impl DotParser {
+    // graph : [ strict ] (graph | digraph) [ ID ] '{' stmt_list '}'
+    fn parse(source: &str) -> Result<Graph, String> {
+        let mut lexer = Lexer::from_string(source);
+        let tok = lexer.next_token();
+        let mut parser = Self {
+            lexer,
+            tok,
+            depth: 0,
+        };
+        if let Token::StrictKW = parser.tok {
+            parser.next();
+        }
+        match parser.tok {
+            Token::GraphKW | Token::DigraphKW | Token::SubgraphKW => parser.next(),
+            _ => return Err("Expected 'graph' or 'digraph'".to_string()),
+        }
+        let graph = parser.graph_body("")?;
+        match parser.tok {
+            Token::EOF => Ok(graph),
+            _ => Err("Unexpected content after the graph".to_string()),
+        }
+    }
+
+    fn next(&mut self) {
+        self.tok = self.lexer.next_token();
+    }
+
+    fn unexpected(&self) -> String {
+        match &self.tok {
+            Token::EOF => "Unexpected end of diagram".to_string(),
+            Token::Error(pos) => format!("Invalid character at position {}", pos),
+            tok => format!("Unexpected {:?}", tok),
+        }
+    }
+
+    // [ ID ] '{' stmt_list '}'
+    fn graph_body(&mut self, default_name: &str) -> Result<Graph, String> {
+        let mut graph = Graph::new(default_name);
+        if let Token::Identifier(name) = &self.tok {
+            graph.name = name.clone();
+            self.next();
+        }
+        match self.tok {
+            Token::OpenBrace => self.next(),
+            _ => return Err(format!("Expected '{{': {}", self.unexpected())),
+        }
+        graph.list = self.stmt_list()?;
+        Ok(graph)
+    }
+
+    // stmt_list : [ stmt [ ';' ] stmt_list ] '}'
+    fn stmt_list(&mut self) -> Result<StmtList, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("Diagram nests more than {} levels deep", MAX_DEPTH));
+        }
+        let mut list = StmtList::new();
+        loop {
+            if let Token::Semicolon = self.tok {
+                self.next();
+            }
+            if let Token::CloseBrace = self.tok {
+                self.next();
+                self.depth -= 1;
+                return Ok(list);
+            }
+            list.list.push(self.stmt()?);
+        }
+    }
+
+    // stmt : node_stmt | edge_stmt | attr_stmt | ID '=' ID | subgraph
+    fn stmt(&mut self) -> Result<Stmt, String> {
+        let target = match self.tok {
+            Token::Identifier(_) => return self.id_stmt(),
+            Token::SubgraphKW => {
+                self.next();
+                return Ok(Stmt::SubGraph(self.graph_body("")?));
+            }
+            Token::OpenBrace => {
+                return Ok(Stmt::SubGraph(self.graph_body("anonymous")?));
+            }
+            Token::GraphKW => AttrStmtTarget::Graph,
+            Token::NodeKW => AttrStmtTarget::Node,
+            Token::EdgeKW => AttrStmtTarget::Edge,
+            _ => return Err(self.unexpected()),
+        };
+        self.next();
+        Ok(Stmt::Attribute(AttrStmt::new(target, self.attr_list()?)))
+    }
+
+    /// A statement starting with a node id: a node, an edge or `ID = ID`.
+    fn id_stmt(&mut self) -> Result<Stmt, String> {
+        let id = self.node_id()?;
+        match self.tok {
+            Token::ArrowLine | Token::ArrowRight => {
+                let mut edge = EdgeStmt::new(id);
+                while let Some(kind) = match self.tok {
+                    Token::ArrowLine => Some(ArrowKind::Line),
+                    Token::ArrowRight => Some(ArrowKind::Arrow),
+                    _ => None,
+                } {
+                    self.next();
+                    edge.insert(self.node_id()?, kind);
+                }
+                if let Token::OpenBracket = self.tok {
+                    edge.list = self.attr_list()?;
+                }
+                Ok(Stmt::Edge(edge))
+            }
+            Token::Equal => {
+                if id.port.is_some() {
+                    return Err("Can't assign into a port".to_string());
+                }
+                self.next();
+                let Token::Identifier(value) = &self.tok else {
+                    return Err(format!("Expected a value for '{}'", id.name));
+                };
+                let mut list = AttributeList::new();
+                list.add_attr(&id.name, value);
+                self.next();
+                Ok(Stmt::Attribute(AttrStmt::new(AttrStmtTarget::Graph, list)))
+            }
+            Token::OpenBracket => Ok(Stmt::Node(NodeStmt::new_with_list(id, self.attr_list()?))),
+            Token::Semicolon => {
+                self.next();
+                Ok(Stmt::Node(NodeStmt::new(id)))
+            }
+            Token::Identifier(_) | Token::CloseBrace => Ok(Stmt::Node(NodeStmt::new(id))),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    // node_id : ID [ ':' ID ]
+    fn node_id(&mut self) -> Result<NodeId, String> {
+        let Token::Identifier(name) = &self.tok else {
+            return Err(format!("Expected a node name: {}", self.unexpected()));
+        };
+        let name = name.clone();
+        self.next();
+        if let Token::Colon = self.tok {
+            self.next();
+            let Token::Identifier(port) = &self.tok else {
+                return Err("Expected a port name".to_string());
+            };
+            let port = Some(port.clone());
+            self.next();
+            return Ok(NodeId::new(&name, &port));
+        }
+        Ok(NodeId::new(&name, &None))
+    }
+
+    // attr_list : '[' [ ID '=' ID [ (';' | ',') ] ]* ']'
+    fn attr_list(&mut self) -> Result<AttributeList, String> {
+        let mut list = AttributeList::new();
+        match self.tok {
+            Token::OpenBracket => self.next(),
+            _ => return Err(format!("Expected '[': {}", self.unexpected())),
+        }
+        loop {
+            let name = match &self.tok {
+                Token::CloseBracket => break,
+                Token::Identifier(name) => name.clone(),
+                _ => return Err(format!("Expected an attribute name: {}", self.unexpected())),
+            };
+            self.next();
+            match self.tok {
+                Token::Equal => self.next(),
+                _ => return Err(format!("Expected '=' after '{}'", name)),
+            }
+            let Token::Identifier(value) = &self.tok else {
+                return Err(format!("Expected a value for '{}'", name));
+            };
+            list.add_attr(&name, value);
+            self.next();
+            if let Token::Semicolon = self.tok {
+                self.next();
+            }
+            if let Token::Comma = self.tok {
+                self.next();
+            }
+        }
+        self.next();
+        Ok(list)
+    }
+}
+
+/// SVG output without ids or stylesheets, so it passes the sanitizer and
+/// several diagrams can share a page.
+#[derive(Default)]
+struct SvgBackend {
+    content: String,
+    min: Option<(f64, f64)>,
+    max: (f64, f64),
+}
+
+impl SvgBackend {
+    fn grow(&mut self, xy: Point, size: Point) {
+        let (min_x, min_y) = self.min.unwrap_or((xy.x, xy.y));
+        self.min = Some((min_x.min(xy.x), min_y.min(xy.y)));
+        self.max = (self.max.0.max(xy.x + size.x), self.max.1.max(xy.y + size.y));
+    }
+
+    fn finish(&self) -> String {
+        let (min_x, min_y) = self.min.unwrap_or((0.0, 0.0));
+        let x = min_x - PADDING;
+        let y = min_y - PADDING;
+        let width = self.max.0 - min_x + 2.0 * PADDING;
+        let height = self.max.1 - min_y + 2.0 * PADDING;
+        format!(
+            r#"<svg viewBox="{:.1} {:.1} {:.1} {:.1}" width="{:.0}" height="{:.0}" role="img">{}</svg>"#,
+            x, y, width, height, width, height, self.content
+        )
+    }
+}
+
+/// A colour as an SVG paint: black becomes `currentColor` and white fills
+/// become transparent, so default diagrams work on light and dark pages.
+fn paint(color: Option<Color>, is_fill: bool) -> String {
+    let web = match color {
+        Some(color) => color.to_web_color(),
+        None => return "none".to_string(),
+    };
+    match web.as_str() {
+        "#000000ff" => "currentColor".to_string(),
+        "#ffffffff" if is_fill => "none".to_string(),
+        _ if web.ends_with("00") => "none".to_string(),
+        _ => web[..7].to_string(),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl RenderBackend for SvgBackend {
+    fn draw_rect(&mut self, xy: Point, size: Point, look: &StyleAttr, _clip: Option<ClipHandle>) {
+        self.grow(xy, size);
+        let _ = writeln!(
+            self.content,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" rx="{}" fill="{}" stroke="{}" stroke-width="{}"></rect>"#,
+            xy.x,
+            xy.y,
+            size.x,
+            size.y,
+            look.rounded,
+            paint(look.fill_color, true),
+            paint(Some(look.line_color), false),
+            look.line_width
+        );
+    }
+
+    fn draw_line(&mut self, start: Point, stop: Point, look: &StyleAttr) {
+        let _ = writeln!(
+            self.content,
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="{}"></line>"#,
+            start.x,
+            start.y,
+            stop.x,
+            stop.y,
+            paint(Some(look.line_color), false),
+            look.line_width
+        );
+    }
+
+    fn draw_circle(&mut self, xy: Point, size: Point, look: &StyleAttr) {
+        self.grow(Point::new(xy.x - size.x / 2.0, xy.y - size.y / 2.0), size);
+        let _ = writeln!(
+            self.content,
+            r#"<ellipse cx="{:.1}" cy="{:.1}" rx="{:.1}" ry="{:.1}" fill="{}" stroke="{}" stroke-width="{}"></ellipse>"#,
+            xy.x,
+            xy.y,
+            size.x / 2.0,
+            size.y / 2.0,
+            paint(look.fill_color, true),
+            paint(Some(look.line_color), false),
+            look.line_width
+        );
+    }
+
+    fn draw_text(&mut self, xy: Point, text: &str, look: &StyleAttr) {
+        let lines: Vec<&str> = text.lines().collect();
+        let line_height = look.font_size as f64 * 1.2;
+        let top = xy.y - line_height * (lines.len() as f64 - 1.0) / 2.0;
+        for (i, line) in lines.iter().enumerate() {
+            let width = line.chars().count() as f64 * look.font_size as f64 * 0.6;
+            let y = top + line_height * i as f64;
+            self.grow(
+                Point::new(xy.x - width / 2.0, y - line_height / 2.0),
+                Point::new(width, line_height),
+            );
+            let _ = writeln!(
+                self.content,
+                r#"<text x="{:.1}" y="{:.1}" font-size="{}" text-anchor="middle" dominant-baseline="middle" fill="currentColor">{}</text>"#,
+                xy.x,
+                y,
+                look.font_size,
+                escape(line)
+            );
+        }
+    }
+
+    fn draw_arrow(
+        &mut self,
+        path: &[(Point, Point)],
+        dashed: bool,
+        head: (bool, bool),
+        look: &StyleAttr,
+        text: &str,
+    ) {
+        if path.len() < 2 {
+            return;
+        }
+        for (a, b) in path {
+            self.grow(*a, Point::new(0.0, 0.0));
+            self.grow(*b, Point::new(0.0, 0.0));
+        }
+
+        let mut d = format!(
+            "M {:.1} {:.1} C {:.1} {:.1}, {:.1} {:.1}, {:.1} {:.1}",
+            path[0].0.x,
+            path[0].0.y,
+            path[0].1.x,
+            path[0].1.y,
+            path[1].0.x,
+            path[1].0.y,
+            path[1].1.x,
+            path[1].1.y
+        );
+        for (control, point) in path.iter().skip(2) {
+            let _ = write!(
+                d,
+                " S {:.1} {:.1}, {:.1} {:.1}",
+                control.x, control.y, point.x, point.y
+            );
+        }
+        let stroke = paint(Some(look.line_color), false);
+        let _ = writeln!(
+            self.content,
+            r#"<path d="{}" fill="none" stroke="{}" stroke-width="{}"{}></path>"#,
+            d,
+            stroke,
+            look.line_width,
+            if dashed {
+                r#" stroke-dasharray="5,5""#
+            } else {
+                ""
+            }
+        );
+
+        // Arrowheads are drawn as polygons rather than markers, which would
+        // need ids
+        let last = path.len() - 1;
+        if head.1 {
+            self.arrowhead(path[last].0, path[last].1, &stroke);
+        }
+        if head.0 {
+            self.arrowhead(path[0].1, path[0].0, &stroke);
+        }
+
+        if !text.is_empty() {
+            let (_, mid) = path[path.len() / 2];
+            let _ = writeln!(
+                self.content,
+                r#"<text x="{:.1}" y="{:.1}" font-size="{}" text-anchor="middle" dominant-baseline="middle" fill="currentColor">{}</text>"#,
+                mid.x,
+                mid.y - look.font_size as f64 * 0.6,
+                look.font_size,
+                escape(text)
+            );
+        }
+    }
+
+    fn create_clip(&mut self, _xy: Point, _size: Point, _rounded_px: usize) -> ClipHandle {
+        // Clipping only trims record corners; shapes are drawn unclipped
+        0
+    }
+}
+
+impl SvgBackend {
+    /// A triangle at `tip` pointing away from `from`.
+    fn arrowhead(&mut self, from: Point, tip: Point, fill: &str) {
+        let (dx, dy) = (tip.x - from.x, tip.y - from.y);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length < f64::EPSILON {
+            return;
+        }
+        let (ux, uy) = (dx / length, dy / length);
+        let (base_x, base_y) = (tip.x - ux * 10.0, tip.y - uy * 10.0);
+        let _ = writeln!(
+            self.content,
+            r#"<polygon points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="{}"></polygon>"#,
+            tip.x,
+            tip.y,
+            base_x - uy * 4.0,
+            base_y + ux * 4.0,
+            base_x + uy * 4.0,
+            base_y - ux * 4.0,
+            fill
+        );
+    }
+}
+
+/// Translate a Mermaid flowchart to DOT.
+///
+/// Supports node shapes (`A[box]`, `A(round)`, `A((circle))`, `A{decision}`),
+/// edges (`-->`, `---`, `-.->`, `==>`) with `|labels|` or `-- label -->`,
+/// chains (`A --> B --> C`) and `&` lists. Styling statements are ignored.
+fn mermaid_to_dot(source: &str) -> Result<String, String> {
+    let mut lines = source
+        .lines()
+        .map(|line| line.split("%%").next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty());
+
+    let header = lines.next().unwrap_or("");
+    let mut words = header.split_whitespace();
+    let kind = words.next().unwrap_or("");
+    if kind != "graph" && kind != "flowchart" {
+        return Err(format!(
+            "Only Mermaid flowcharts can be rendered, not '{}'",
+            kind
+        ));
+    }
+    let direction = words.next().unwrap_or("TD").trim_end_matches(';');
+
+    let mut flowchart = Flowchart::default();
+    for line in lines {
+        for statement in line.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let first = statement.split_whitespace().next().unwrap_or("");
+            if matches!(
+                first,
+                "classDef"
+                    | "class"
+                    | "style"
+                    | "linkStyle"
+                    | "click"
+                    | "subgraph"
+                    | "end"
+                    | "direction"
+            ) {
+                continue;
+            }
+            flowchart.statement(statement)?;
+        }
+    }
+
+    let mut dot = String::from("digraph {\n");
+    if matches!(direction, "LR" | "RL") {
+        dot.push_str("rankdir=LR;\n");
+    }
+    for id in &flowchart.order {
+        let (label, shape) = &flowchart.nodes[id];
+        let _ = writeln!(
+            dot,
+            "\"{}\" [label=\"{}\" shape={}];",
+            dot_escape(id),
+            dot_escape(label),
+            shape
+        );
+    }
+    for edge in &flowchart.edges {
+        let mut attrs = vec![];
+        if !edge.label.is_empty() {
+            attrs.push(format!("label=\"{}\"", dot_escape(&edge.label)));
+        }
+        if edge.dashed {
+            attrs.push("style=dashed".to_string());
+        }
+        if edge.thick {
+            attrs.push("penwidth=2".to_string());
+        }
+        let _ = writeln!(
+            dot,
+            "\"{}\" {} \"{}\" [{}];",
+            dot_escape(&edge.from),
+            if edge.directed { "->" } else { "--" },
+            dot_escape(&edge.to),
+            attrs.join(" ")
+        );
+    }
+    dot.push('}');
+    Ok(dot)
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    label: String,
+    directed: bool,
+    dashed: bool,
+    thick: bool,
+}
+
+#[derive(Default)]
+struct Flowchart {
+    /// Node id to (label, DOT shape)
+    nodes: HashMap<String, (String, &'static str)>,
+    order: Vec<String>,
+    edges: Vec<Edge>,
+}
+
+impl Flowchart {
+    /// Parse `A[x] & B --> C -- label --> D`.
+    fn statement(&mut self, statement: &str) -> Result<(), String> {
+        let mut rest = statement;
+        let mut previous = self.node_group(&mut rest)?;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                return Ok(());
+            }
+            let link =
+                parse_link(&mut rest).ok_or_else(|| format!("Could not parse '{}'", statement))?;
+            let next = self.node_group(&mut rest)?;
+            for from in &previous {
+                for to in &next {
+                    self.edges.push(Edge {
+                        from: from.clone(),
+                        to: to.clone(),
+                        label: link.label.clone(),
+                        directed: link.directed,
+                        dashed: link.dashed,
+                        thick: link.thick,
+                    });
+                }
+            }
+            previous = next;
+        }
+    }
+
+    fn node_group(&mut self, rest: &mut &str) -> Result<Vec<String>, String> {
+        let mut ids = vec![self.node(rest)?];
+        loop {
+            let trimmed = rest.trim_start();
+            match trimmed.strip_prefix('&') {
+                Some(after) => {
+                    *rest = after;
+                    ids.push(self.node(rest)?);
+                }
+                None => return Ok(ids),
+            }
+        }
+    }
+
+    /// Parse a node id with an optional shaped label, registering the node.
+    fn node(&mut self, rest: &mut &str) -> Result<String, String> {
+        let trimmed = rest.trim_start();
+        let id_len = trimmed
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(trimmed.len());
+        if id_len == 0 {
+            return Err(format!("Expected a node id at '{}'", trimmed));
+        }
+        let id = trimmed[..id_len].to_string();
+        let after = &trimmed[id_len..];
+
+        const SHAPES: &[(&str, &str, &str)] = &[
+            ("(((", ")))", "doublecircle"),
+            ("((", "))", "circle"),
+            ("([", "])", "box"),
+            ("[[", "]]", "box"),
+            ("[(", ")]", "box"),
+            ("{{", "}}", "box"),
+            ("[", "]", "box"),
+            ("(", ")", "box"),
+            ("{", "}", "box"),
+            (">", "]", "box"),
+        ];
+        let mut label = None;
+        *rest = after;
+        for (open, close, shape) in SHAPES {
+            if let Some(inner) = after.strip_prefix(open) {
+                let end = inner
+                    .find(close)
+                    .ok_or_else(|| format!("Unclosed '{}' in node {}", open, id))?;
+                let text = inner[..end].trim().trim_matches('"');
+                label = Some((text.replace("<br>", "\n").replace("<br/>", "\n"), *shape));
+                *rest = &inner[end + close.len()..];
+                break;
+            }
+        }
+
+        match self.nodes.get_mut(&id) {
+            Some(existing) => {
+                if let Some(label) = label {
+                    *existing = label;
+                }
+            }
+            None => {
+                self.order.push(id.clone());
+                self.nodes
+                    .insert(id.clone(), label.unwrap_or_else(|| (id.clone(), "box")));
+            }
+        }
+        Ok(id)
+    }
+}
+
+struct Link {
+    label: String,
+    directed: bool,
+    dashed: bool,
+    thick: bool,
+}
+
+/// Parse an edge such as `-->`, `-.->|label|`, `==>` or `-- label -->`.
+fn parse_link(rest: &mut &str) -> Option<Link> {
+    let s = rest.trim_start();
+    let arrow_len = s
+        .find(|c: char| !matches!(c, '-' | '=' | '.' | '>' | '<' | 'o' | 'x'))
+        .unwrap_or(s.len());
+    // `o` and `x` end a link only when directly after dashes (`--o`), not as
+    // the start of a node id
+    let mut arrow = &s[..arrow_len];
+    while arrow.len() > 2 && (arrow.ends_with('o') || arrow.ends_with('x')) {
+        let trimmed = &arrow[..arrow.len() - 1];
+        if trimmed.ends_with(['-', '=']) {
+            break;
+        }
+        arrow = trimmed;
+    }
+    if arrow.len() < 2 || !arrow.starts_with(['-', '=', '<']) {
+        return None;
+    }
+    let mut after = &s[arrow.len()..];
+    let mut label = String::new();
+
+    // `A -- text --> B`: the first token opens the link, the text runs to
+    // the closing arrow
+    let mut full = arrow.to_string();
+    if matches!(arrow, "--" | "==" | "-.") {
+        let close = ["-->", "---", "==>", "===", ".->", ".-"]
+            .iter()
+            .filter_map(|token| after.find(token).map(|i| (i, *token)))
+            .min_by_key(|(i, _)| *i)?;
+        label = after[..close.0].trim().to_string();
+        full.push_str(close.1);
+        after = &after[close.0 + close.1.len()..];
+    }
+
+    let trimmed = after.trim_start();
+    if let Some(inner) = trimmed.strip_prefix('|') {
+        let end = inner.find('|')?;
+        label = inner[..end].trim().trim_matches('"').to_string();
+        after = &inner[end + 1..];
+    }
+
+    *rest = after;
+    Some(Link {
+        label,
+        directed: full.ends_with(['>', 'o', 'x']),
+        dashed: full.contains('.'),
+        thick: full.contains('='),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_renders_svg_without_ids() {
+        let html = render("dot", "digraph { a -> b [label=\"go\"]; b -> c; }").unwrap();
+        assert!(html.starts_with(r#"<figure class="diagram diagram-dot"><svg viewBox="#));
+        assert_eq!(html.matches("<ellipse").count(), 3);
+        assert!(html.contains(">go</text>"));
+        assert!(html.contains("<polygon"));
+        assert!(!html.contains("id="));
+        assert!(!html.contains("<style"));
+        assert!(render("dot", "digraph { a -> }").is_err());
+    }
+
+    const MALFORMED_DOT: &[&str] = &[
+        "digraph {",
+        "digraph { a -> }",
+        "digraph { a -> b [label] }",
+        "digraph { a [label=] }",
+        "digraph { a: }",
+        "digraph { a = }",
+        "digraph { a ] }",
+        "digraph { \"open }",
+        "digraph { a } b",
+        "digraph { a -x b }",
+        "flowchart { a }",
+    ];
+
+    #[test]
+    fn test_malformed_dot_is_an_error() {
+        for dot in MALFORMED_DOT {
+            assert!(render("dot", dot).is_err(), "{} was accepted", dot);
+        }
+        let nested = format!("digraph {}a{}", "{".repeat(100), "}".repeat(101));
+        assert!(render("dot", &nested).is_err());
+
+        let html = render(
+            "dot",
+            "strict digraph G {\n  rankdir=LR;\n  node [shape=box, color=red];\n  a:n -> b -- c;\n  subgraph s { d }\n  { e; f }\n}",
+        )
+        .unwrap();
+        assert_eq!(html.matches("<rect").count(), 6);
+    }
+
+    /// layout-rs's parser prints the token it stopped at; ours must not, so
+    /// saving bad diagrams doesn't write to the server's stdout.
+    #[test]
+    fn test_malformed_dot_prints_nothing() {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "services::diagrams::tests::test_malformed_dot_is_an_error",
+                "--exact",
+                "--nocapture",
+                "--test-threads=1",
+            ])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        let printed: Vec<&str> = stdout
+            .lines()
+            .filter(|line| {
+                !line.is_empty() && !line.starts_with("running ") && !line.starts_with("test ")
+            })
+            .collect();
+        assert!(printed.is_empty(), "printed {:?}", printed);
+    }
+
+    #[test]
+    fn test_mermaid_flowchart_to_dot() {
+        let dot = mermaid_to_dot(
+            "graph LR\n  A[Start] --> B{\"Is it?\"}\n  B -->|Yes| C((Done))\n  B -. no .-> A\n  %% comment\n  style A fill:#f9f",
+        )
+        .unwrap();
+        assert!(dot.contains("rankdir=LR;"));
+        assert!(dot.contains(r#""A" [label="Start" shape=box];"#));
+        assert!(dot.contains(r#""B" [label="Is it?" shape=box];"#));
+        assert!(dot.contains(r#""C" [label="Done" shape=circle];"#));
+        assert!(dot.contains(r#""B" -> "C" [label="Yes"];"#));
+        assert!(dot.contains(r#""B" -> "A" [label="no" style=dashed];"#));
+
+        let chained = mermaid_to_dot("flowchart TD\nA & B --> C --- D").unwrap();
+        assert!(chained.contains(r#""A" -> "C" [];"#));
+        assert!(chained.contains(r#""B" -> "C" [];"#));
+        assert!(chained.contains(r#""C" -- "D" [];"#));
+    }
+
+    #[test]
+    fn test_unsupported_mermaid_and_limits() {
+        assert!(render("mermaid", "sequenceDiagram\nA->>B: hi").is_err());
+        assert!(render("mermaid", "graph TD\nA --> B").is_ok());
+        assert!(render("dot", &"x".repeat(MAX_SOURCE_LEN + 1)).is_err());
+    }
+}
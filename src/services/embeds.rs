@@ -0,0 +1,501 @@
+//! Privacy-preserving embeds for a curated list of providers.
+//!
+//! Embeds render as click-to-load facades: a locally cached thumbnail and a
+//! link to the original. Nothing is requested from the provider until the
+//! reader clicks, at which point `embeds.js` swaps in the provider's iframe.
+//! Pages containing a facade get the provider's origin added to `frame-src`.
+
+use crate::Database;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::path::Path;
+use url::Url;
+
+static FRAME_SRC_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"data-embed-src="([^"]+)""#).expect("Invalid embed frame source regex")
+});
+static EMBED_URL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"<a href="([^"]+)" class="embed-facade""#).expect("Invalid embed url regex")
+});
+
+/// Largest thumbnail accepted from a provider.
+#[cfg(feature = "webhooks")]
+const MAX_THUMBNAIL_BYTES: usize = 2 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    YouTube,
+    Vimeo,
+    Mastodon,
+    Gist,
+    CodePen,
+}
+
+impl Provider {
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::YouTube => "youtube",
+            Provider::Vimeo => "vimeo",
+            Provider::Mastodon => "mastodon",
+            Provider::Gist => "gist",
+            Provider::CodePen => "codepen",
+        }
+    }
+
+    /// What the reader is about to load, e.g. "YouTube video".
+    pub fn label(self) -> &'static str {
+        match self {
+            Provider::YouTube => "YouTube video",
+            Provider::Vimeo => "Vimeo video",
+            Provider::Mastodon => "Mastodon post",
+            Provider::Gist => "GitHub Gist",
+            Provider::CodePen => "CodePen",
+        }
+    }
+
+    fn has_thumbnail(self) -> bool {
+        matches!(
+            self,
+            Provider::YouTube | Provider::Vimeo | Provider::CodePen
+        )
+    }
+}
+
+/// A recognised embed URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Embed {
+    pub provider: Provider,
+    pub id: String,
+    /// Canonical link to the content on the provider's site
+    pub url: String,
+    /// Iframe URL loaded when the reader clicks
+    pub frame_src: String,
+}
+
+fn is_token(s: &str, extra: &[char]) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || extra.contains(&c))
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+impl Embed {
+    /// Recognise a URL from one of the supported providers.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let url = Url::parse(raw.trim()).ok()?;
+        if !matches!(url.scheme(), "https" | "http") {
+            return None;
+        }
+        let host = url.host_str()?.to_ascii_lowercase();
+        let host = host.strip_prefix("www.").unwrap_or(&host);
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|s| s.filter(|p| !p.is_empty()).collect())
+            .unwrap_or_default();
+
+        match (host, segments.as_slice()) {
+            ("youtube.com" | "m.youtube.com" | "youtube-nocookie.com", _) | ("youtu.be", [_]) => {
+                let id = match segments.as_slice() {
+                    ["watch"] => url
+                        .query_pairs()
+                        .find(|(k, _)| k == "v")
+                        .map(|(_, v)| v.into_owned())?,
+                    [id] if host == "youtu.be" => id.to_string(),
+                    ["shorts" | "embed" | "live", id] => id.to_string(),
+                    _ => return None,
+                };
+                if id.len() != 11 || !is_token(&id, &['-', '_']) {
+                    return None;
+                }
+                let start = url
+                    .query_pairs()
+                    .find(|(k, _)| k == "t" || k == "start")
+                    .map(|(_, v)| v.trim_end_matches('s').to_string())
+                    .filter(|t| is_digits(t))
+                    .map(|t| format!("&start={}", t))
+                    .unwrap_or_default();
+                Some(Self {
+                    provider: Provider::YouTube,
+                    url: format!("https://www.youtube.com/watch?v={}", id),
+                    frame_src: format!(
+                        "https://www.youtube-nocookie.com/embed/{}?autoplay=1{}",
+                        id, start
+                    ),
+                    id,
+                })
+            }
+            ("vimeo.com", [.., id]) | ("player.vimeo.com", ["video", id]) if is_digits(id) => {
+                Some(Self {
+                    provider: Provider::Vimeo,
+                    id: id.to_string(),
+                    url: format!("https://vimeo.com/{}", id),
+                    frame_src: format!("https://player.vimeo.com/video/{}?autoplay=1&dnt=1", id),
+                })
+            }
+            ("gist.github.com", [user, id])
+                if is_token(user, &['-']) && id.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                Some(Self {
+                    provider: Provider::Gist,
+                    id: id.to_string(),
+                    url: format!("https://gist.github.com/{}/{}", user, id),
+                    frame_src: format!("https://gist.github.com/{}/{}.pibb", user, id),
+                })
+            }
+            ("codepen.io", [user, "pen" | "full" | "embed", id])
+                if is_token(user, &['-', '_']) && is_token(id, &[]) =>
+            {
+                Some(Self {
+                    provider: Provider::CodePen,
+                    id: id.to_string(),
+                    url: format!("https://codepen.io/{}/pen/{}", user, id),
+                    frame_src: format!(
+                        "https://codepen.io/{}/embed/{}?default-tab=result",
+                        user, id
+                    ),
+                })
+            }
+            // Mastodon has no single host: any https://<instance>/@user/<numeric id>
+            (_, [user, id])
+                if url.scheme() == "https"
+                    && user.len() > 1
+                    && user.starts_with('@')
+                    && is_token(&user[1..], &['_', '.'])
+                    && is_digits(id) =>
+            {
+                Some(Self {
+                    provider: Provider::Mastodon,
+                    id: id.to_string(),
+                    url: format!("https://{}/{}/{}", host, user, id),
+                    frame_src: format!("https://{}/{}/{}/embed", host, user, id),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// File name of the cached thumbnail inside the media directory.
+    pub fn thumbnail_filename(&self) -> Option<String> {
+        self.provider
+            .has_thumbnail()
+            .then(|| format!("embed-{}-{}.jpg", self.provider.name(), self.id))
+    }
+
+    fn frame_host(&self) -> String {
+        Url::parse(&self.frame_src)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    /// Facade markup. Kept free of blank lines so Markdown treats it as one HTML block.
+    pub fn render(&self, title: Option<&str>) -> String {
+        let label = self.provider.label();
+        let title = title.filter(|t| !t.trim().is_empty()).unwrap_or(label);
+        let thumbnail = self
+            .thumbnail_filename()
+            .map(|file| {
+                format!(
+                    "<img src=\"/media/{}\" alt=\"\" loading=\"lazy\" class=\"embed-thumbnail\">\n",
+                    file
+                )
+            })
+            .unwrap_or_default();
+
+        format!(
+            r#"<figure class="embed embed-{name}" data-embed-src="{src}" data-embed-title="{title}">
+<a href="{url}" class="embed-facade">
+{thumbnail}<span class="embed-play">Load {label}</span>
+</a>
+<figcaption class="embed-notice">{caption}Loading connects to {host}.</figcaption>
+</figure>"#,
+            name = self.provider.name(),
+            src = html_escape(&self.frame_src),
+            title = html_escape(title),
+            url = html_escape(&self.url),
+            thumbnail = thumbnail,
+            label = label,
+            caption = if title == label {
+                String::new()
+            } else {
+                format!("{} &middot; ", html_escape(title))
+            },
+            host = html_escape(&self.frame_host()),
+        )
+    }
+}
+
+/// Whether an iframe URL is one this module would have produced.
+fn allowed_frame_src(src: &str) -> Option<String> {
+    let url = Url::parse(src).ok()?;
+    if url.scheme() != "https" {
+        return None;
+    }
+    let host = url.host_str()?.to_ascii_lowercase();
+    let fixed = matches!(
+        host.as_str(),
+        "www.youtube-nocookie.com" | "player.vimeo.com" | "gist.github.com" | "codepen.io"
+    );
+    let segments: Vec<&str> = url.path_segments()?.collect();
+    let mastodon = matches!(segments.as_slice(), [user, id, "embed"]
+        if user.starts_with('@') && is_digits(id));
+    (fixed || mastodon).then(|| format!("https://{}", host))
+}
+
+/// Origins that must be allowed in `frame-src` for the facades in `html`.
+pub fn frame_sources(html: &str) -> Vec<String> {
+    let mut origins: Vec<String> = FRAME_SRC_REGEX
+        .captures_iter(html)
+        .filter_map(|caps| allowed_frame_src(&caps[1].replace("&amp;", "&")))
+        .collect();
+    origins.sort();
+    origins.dedup();
+    origins
+}
+
+/// Embeds whose facades appear in rendered `html`.
+pub fn embeds_in(html: &str) -> Vec<Embed> {
+    EMBED_URL_REGEX
+        .captures_iter(html)
+        .filter_map(|caps| Embed::parse(&caps[1].replace("&amp;", "&")))
+        .collect()
+}
+
+/// Replace supported URLs that stand alone as a paragraph with an embed.
+/// Fenced and indented code is left untouched.
+pub fn auto_embed(markdown: &str) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut output = Vec::with_capacity(lines.len());
+    let mut fence: Option<&str> = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            output.push(line.to_string());
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            output.push(line.to_string());
+            continue;
+        }
+
+        let standalone = !line.starts_with("    ")
+            && !line.starts_with('\t')
+            && (i == 0 || lines[i - 1].trim().is_empty())
+            && lines.get(i + 1).map_or(true, |next| next.trim().is_empty());
+        let candidate = trimmed
+            .strip_prefix('<')
+            .and_then(|s| s.strip_suffix('>'))
+            .unwrap_or(trimmed);
+        let embed = (standalone && candidate.starts_with("http") && !candidate.contains(' '))
+            .then(|| Embed::parse(candidate))
+            .flatten();
+
+        match embed {
+            Some(embed) => output.push(embed.render(None)),
+            None => output.push(line.to_string()),
+        }
+    }
+
+    let mut result = output.join("\n");
+    if markdown.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Where to download a provider's thumbnail from.
+#[cfg(feature = "webhooks")]
+async fn thumbnail_source(embed: &Embed) -> anyhow::Result<Option<String>> {
+    let oembed = match embed.provider {
+        Provider::YouTube => {
+            return Ok(Some(format!(
+                "https://i.ytimg.com/vi/{}/hqdefault.jpg",
+                embed.id
+            )))
+        }
+        Provider::Vimeo => "https://vimeo.com/api/oembed.json",
+        Provider::CodePen => "https://codepen.io/api/oembed",
+        Provider::Mastodon | Provider::Gist => return Ok(None),
+    };
+    let response: serde_json::Value = super::webhook::http_client()
+        .get(oembed)
+        .query(&[("format", "json"), ("url", embed.url.as_str())])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response["thumbnail_url"].as_str().map(str::to_string))
+}
+
+/// Download the thumbnail of one embed into `media_dir` unless it is cached.
+#[cfg(feature = "webhooks")]
+async fn cache_thumbnail(media_dir: &Path, embed: &Embed) -> anyhow::Result<bool> {
+    let Some(filename) = embed.thumbnail_filename() else {
+        return Ok(false);
+    };
+    let path = media_dir.join(&filename);
+    if path.exists() {
+        return Ok(false);
+    }
+    let Some(source) = thumbnail_source(embed).await? else {
+        return Ok(false);
+    };
+
+    let bytes = super::webhook::http_client()
+        .get(&source)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    if bytes.len() > MAX_THUMBNAIL_BYTES {
+        anyhow::bail!("thumbnail is larger than {} bytes", MAX_THUMBNAIL_BYTES);
+    }
+    image::guess_format(&bytes).map_err(|_| anyhow::anyhow!("thumbnail is not an image"))?;
+
+    tokio::fs::create_dir_all(media_dir).await?;
+    tokio::fs::write(&path, &bytes).await?;
+    Ok(true)
+}
+
+/// Cache the thumbnails of every embed in `html`. Returns how many were downloaded.
+#[cfg(feature = "webhooks")]
+pub async fn cache_thumbnails(media_dir: &Path, html: &str) -> usize {
+    let mut cached = 0;
+    for embed in embeds_in(html) {
+        match cache_thumbnail(media_dir, &embed).await {
+            Ok(true) => cached += 1,
+            Ok(false) => {}
+            Err(e) => tracing::warn!("Failed to cache thumbnail for {}: {}", embed.url, e),
+        }
+    }
+    cached
+}
+
+#[cfg(not(feature = "webhooks"))]
+pub async fn cache_thumbnails(_media_dir: &Path, _html: &str) -> usize {
+    0
+}
+
+/// Cache missing thumbnails for all content. Used at startup and by `pebble rerender`.
+pub async fn cache_all_thumbnails(db: &Database, media_dir: &Path) -> anyhow::Result<usize> {
+    let bodies: Vec<String> = {
+        let conn = db.get()?;
+        let mut stmt =
+            conn.prepare("SELECT body_html FROM content WHERE body_html LIKE '%embed-facade%'")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.filter_map(|r| r.ok()).collect()
+    };
+
+    let mut cached = 0;
+    for html in bodies {
+        cached += cache_thumbnails(media_dir, &html).await;
+    }
+    Ok(cached)
+}
+
+/// Cache the thumbnails of a content item in the background after it is saved.
+pub fn spawn_cache_thumbnails(db: &Database, media_dir: &Path, content_id: i64) {
+    let html = match super::content::get_content_by_id(db, content_id) {
+        Ok(Some(item)) if item.content.body_html.contains("embed-facade") => item.content.body_html,
+        _ => return,
+    };
+    let media_dir = media_dir.to_path_buf();
+    tokio::spawn(async move {
+        cache_thumbnails(&media_dir, &html).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_providers() {
+        let yt = Embed::parse("https://youtu.be/dQw4w9WgXcQ?t=42").unwrap();
+        assert_eq!(yt.provider, Provider::YouTube);
+        assert_eq!(
+            yt.frame_src,
+            "https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ?autoplay=1&start=42"
+        );
+        assert_eq!(
+            Embed::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+                .unwrap()
+                .thumbnail_filename()
+                .as_deref(),
+            Some("embed-youtube-dQw4w9WgXcQ.jpg")
+        );
+
+        let vimeo = Embed::parse("https://vimeo.com/76979871").unwrap();
+        assert_eq!(vimeo.provider, Provider::Vimeo);
+
+        let toot = Embed::parse("https://mastodon.social/@Gargron/109276767616591543").unwrap();
+        assert_eq!(toot.provider, Provider::Mastodon);
+        assert_eq!(
+            toot.frame_src,
+            "https://mastodon.social/@Gargron/109276767616591543/embed"
+        );
+        assert!(toot.thumbnail_filename().is_none());
+
+        let gist = Embed::parse("https://gist.github.com/octocat/6cad326836d38bd3a7ae").unwrap();
+        assert_eq!(
+            gist.frame_src,
+            "https://gist.github.com/octocat/6cad326836d38bd3a7ae.pibb"
+        );
+
+        let pen = Embed::parse("https://codepen.io/team/pen/abcDEF").unwrap();
+        assert_eq!(
+            pen.frame_src,
+            "https://codepen.io/team/embed/abcDEF?default-tab=result"
+        );
+
+        assert!(Embed::parse("https://example.com/some/page").is_none());
+        assert!(Embed::parse("https://www.youtube.com/watch?v=short").is_none());
+        assert!(Embed::parse("javascript:alert(1)").is_none());
+    }
+
+    #[test]
+    fn test_auto_embed_only_standalone_urls() {
+        let markdown = "Intro\n\nhttps://vimeo.com/76979871\n\nSee https://vimeo.com/1 inline.\n\n```\nhttps://vimeo.com/2\n```\n";
+        let output = auto_embed(markdown);
+        assert!(output.contains(r#"<figure class="embed embed-vimeo""#));
+        assert!(output.contains("See https://vimeo.com/1 inline."));
+        assert!(output.contains("```\nhttps://vimeo.com/2\n```"));
+        assert_eq!(output.matches("<figure").count(), 1);
+    }
+
+    #[test]
+    fn test_frame_sources_only_trust_known_shapes() {
+        let html = Embed::parse("https://youtu.be/dQw4w9WgXcQ")
+            .unwrap()
+            .render(Some("Demo"))
+            + &Embed::parse("https://fosstodon.org/@pebble/1234")
+                .unwrap()
+                .render(None)
+            + r#"<figure data-embed-src="https://evil.example/frame"></figure>"#;
+        assert_eq!(
+            frame_sources(&html),
+            vec![
+                "https://fosstodon.org".to_string(),
+                "https://www.youtube-nocookie.com".to_string()
+            ]
+        );
+        assert_eq!(embeds_in(&html).len(), 2);
+    }
+}
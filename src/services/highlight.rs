@@ -0,0 +1,394 @@
+//! Syntax highlighting for fenced code blocks.
+//!
+//! Code is highlighted with CSS classes rather than inline styles, so its
+//! colours come from a stylesheet generated from the configured light and
+//! dark syntect themes and follow the visitor's colour scheme.
+
+use crate::config::ContentConfig;
+use anyhow::{Context, Result};
+use std::fmt::Write;
+use std::path::Path;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Prefix of the token classes syntect emits (`hl-keyword`, `hl-string`, ...).
+pub const CLASS_PREFIX: &str = "hl-";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: CLASS_PREFIX,
+};
+
+/// Whether `class` is a token class produced by the highlighter.
+pub fn is_token_class(class: &str) -> bool {
+    class.len() > CLASS_PREFIX.len()
+        && class.starts_with(CLASS_PREFIX)
+        && class
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_+#.".contains(&b))
+}
+
+/// Whether `class` is a `language-*` class for a code element.
+pub fn is_language_class(class: &str) -> bool {
+    class
+        .strip_prefix("language-")
+        .is_some_and(|lang| !lang.is_empty() && lang.bytes().all(is_lang_byte))
+}
+
+fn is_lang_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-_+#.".contains(&b)
+}
+
+/// Options from a fence info string, e.g. ```` ```rust {3-5,8 linenos} ````.
+#[derive(Debug, Default, PartialEq)]
+pub struct FenceInfo {
+    pub lang: String,
+    /// Inclusive 1-based line ranges to highlight
+    pub highlighted: Vec<(usize, usize)>,
+    /// `Some(true)` for `linenos`, `Some(false)` for `nolinenos`, otherwise
+    /// the site default (`content.line_numbers`) applies
+    pub line_numbers: Option<bool>,
+}
+
+impl FenceInfo {
+    pub fn parse(info: &str) -> Self {
+        let info = info.trim();
+        let (lang, options) = match info.find('{') {
+            Some(start) => {
+                let rest = &info[start + 1..];
+                (&info[..start], rest.split('}').next().unwrap_or(rest))
+            }
+            None => (info, ""),
+        };
+
+        let mut parsed = FenceInfo {
+            lang: lang
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .next()
+                .unwrap_or("")
+                .to_string(),
+            ..Default::default()
+        };
+        for token in options
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|t| !t.is_empty())
+        {
+            match token {
+                "linenos" => parsed.line_numbers = Some(true),
+                "nolinenos" => parsed.line_numbers = Some(false),
+                _ => {
+                    if let Some(range) = parse_range(token) {
+                        parsed.highlighted.push(range);
+                    }
+                }
+            }
+        }
+        parsed
+    }
+
+    pub fn is_highlighted(&self, line: usize) -> bool {
+        self.highlighted
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&line))
+    }
+}
+
+fn parse_range(token: &str) -> Option<(usize, usize)> {
+    let (start, end) = token.split_once('-').unwrap_or((token, token));
+    let start: usize = start.trim().parse().ok()?;
+    let end: usize = end.trim().parse().ok()?;
+    if start == 0 || end < start {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// Highlight `code` as a `<pre class="code-block">` element with one
+/// `code-line` span per line.
+pub fn highlight(
+    syntax_set: &SyntaxSet,
+    code: &str,
+    info: &FenceInfo,
+) -> Result<String, syntect::Error> {
+    let syntax = syntax_set
+        .find_syntax_by_token(&info.lang)
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line)?;
+    }
+    Ok(code_block(&generator.finalize(), info))
+}
+
+/// Wrap already-escaped code lines in the `code-block` markup.
+pub fn code_block(html: &str, info: &FenceInfo) -> String {
+    let mut pre_class = String::from("code-block");
+    match info.line_numbers {
+        Some(true) => pre_class.push_str(" code-numbered"),
+        Some(false) => pre_class.push_str(" code-unnumbered"),
+        None => {}
+    }
+    let code_class = if !info.lang.is_empty() && info.lang.bytes().all(is_lang_byte) {
+        format!(r#" class="language-{}""#, info.lang)
+    } else {
+        String::new()
+    };
+    format!(
+        r#"<pre class="{}"><code{}>{}</code></pre>"#,
+        pre_class,
+        code_class,
+        wrap_lines(html, info)
+    )
+}
+
+/// Wrap each line in a `code-line` span. Token spans that continue past a
+/// line break (block comments, multi-line strings) are closed at the end of
+/// the line and reopened on the next, so every line is balanced.
+fn wrap_lines(html: &str, info: &FenceInfo) -> String {
+    let mut out = String::with_capacity(html.len() * 2);
+    let mut open: Vec<&str> = Vec::new();
+    let mut line = 0;
+    let mut in_line = false;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if !in_line {
+            // The generator closes spans still open at the end of the code
+            // after the final newline; those lines were already closed
+            if rest.trim_start_matches("</span>").is_empty() {
+                break;
+            }
+            line += 1;
+            out.push_str(if info.is_highlighted(line) {
+                r#"<span class="code-line code-line-highlight">"#
+            } else {
+                r#"<span class="code-line">"#
+            });
+            for tag in &open {
+                out.push_str(tag);
+            }
+            in_line = true;
+        }
+
+        if let Some(after) = rest.strip_prefix("</span>") {
+            open.pop();
+            out.push_str("</span>");
+            rest = after;
+        } else if rest.starts_with("<span") {
+            let end = rest.find('>').map_or(rest.len(), |i| i + 1);
+            open.push(&rest[..end]);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix('\n') {
+            for _ in 0..=open.len() {
+                out.push_str("</span>");
+            }
+            out.push('\n');
+            in_line = false;
+            rest = after;
+        } else {
+            let end = rest.find(['<', '\n']).unwrap_or(rest.len());
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+    if in_line {
+        for _ in 0..=open.len() {
+            out.push_str("</span>");
+        }
+    }
+    out
+}
+
+/// Load a highlighting theme: one of syntect's bundled themes by name, or a
+/// `.tmTheme` file.
+pub fn load_theme(name: &str) -> Result<Theme> {
+    if name.ends_with(".tmTheme") {
+        return ThemeSet::get_theme(Path::new(name))
+            .with_context(|| format!("Failed to load highlight theme {}", name));
+    }
+    let mut themes = ThemeSet::load_defaults().themes;
+    match themes.remove(name) {
+        Some(theme) => Ok(theme),
+        None => anyhow::bail!(
+            "Invalid highlight theme '{}'. Available themes: {} (or a path to a .tmTheme file)",
+            name,
+            themes.keys().cloned().collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// The stylesheet served at `/css/highlight.css`: token colours for the
+/// light theme, the dark theme under the same conditions as the site's dark
+/// colour scheme, and line numbers.
+pub fn stylesheet(config: &ContentConfig) -> Result<String> {
+    let light = load_theme(&config.highlight_theme)?;
+    let dark = load_theme(&config.highlight_theme_dark)?;
+
+    let mut css =
+        String::from("/* Generated by Pebble from the content.highlight_theme settings */\n");
+    let light_rules = theme_rules(&light)?;
+    let dark_rules = theme_rules(&dark)?;
+
+    css.push_str("@media not all and (prefers-color-scheme: dark) {\n");
+    push_scoped(&mut css, "html:not(.theme-dark)", &light_rules);
+    css.push_str("}\n");
+    push_scoped(&mut css, "html.theme-light", &light_rules);
+    css.push_str("@media (prefers-color-scheme: dark) {\n");
+    push_scoped(&mut css, "html:not(.theme-light)", &dark_rules);
+    css.push_str("}\n");
+    push_scoped(&mut css, "html.theme-dark", &dark_rules);
+
+    let numbered = if config.line_numbers {
+        ".code-block:not(.code-unnumbered)"
+    } else {
+        ".code-block.code-numbered"
+    };
+    let _ = write!(
+        css,
+        "{numbered} code {{ counter-reset: code-line; }}\n\
+         {numbered} .code-line::before {{ counter-increment: code-line; content: counter(code-line); \
+         display: inline-block; width: 2.5em; margin-right: 1em; padding-right: 0.5em; \
+         text-align: right; color: var(--text-muted); border-right: 1px solid var(--border); \
+         user-select: none; }}\n"
+    );
+    Ok(css)
+}
+
+/// A theme's rules as (selectors, declarations) pairs. The theme's own
+/// background is dropped so code blocks keep the site's background.
+fn theme_rules(theme: &Theme) -> Result<Vec<(Vec<String>, String)>> {
+    let generated = css_for_theme_with_class_style(theme, CLASS_STYLE)?;
+    let root = format!(".{}code", CLASS_PREFIX);
+
+    let mut rules = Vec::new();
+    let mut rest = generated.as_str();
+    while let Some(open) = rest.find('{') {
+        let close = match rest[open..].find('}') {
+            Some(i) => open + i,
+            None => break,
+        };
+        let selector = strip_comments(&rest[..open]);
+        let mut body = rest[open + 1..close].trim().to_string();
+        rest = &rest[close + 1..];
+
+        let selectors: Vec<String> = selector
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if selectors.is_empty() {
+            continue;
+        }
+        let selectors = if selectors == [root.as_str()] {
+            body = body
+                .lines()
+                .filter(|l| !l.trim_start().starts_with("background"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            vec![".code-block".to_string()]
+        } else {
+            selectors
+                .into_iter()
+                .map(|s| format!(".code-block {}", s))
+                .collect()
+        };
+        if !body.trim().is_empty() {
+            rules.push((
+                selectors,
+                body.split_whitespace().collect::<Vec<_>>().join(" "),
+            ));
+        }
+    }
+    Ok(rules)
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut out = String::new();
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = rest[start..]
+            .find("*/")
+            .map_or("", |end| &rest[start + end + 2..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+fn push_scoped(css: &mut String, scope: &str, rules: &[(Vec<String>, String)]) {
+    for (selectors, body) in rules {
+        let scoped: Vec<String> = selectors
+            .iter()
+            .map(|s| format!("{} {}", scope, s))
+            .collect();
+        let _ = writeln!(css, "{} {{ {} }}", scoped.join(", "), body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fence_info() {
+        let info = FenceInfo::parse("rust {3-5, 8 linenos}");
+        assert_eq!(info.lang, "rust");
+        assert_eq!(info.highlighted, vec![(3, 5), (8, 8)]);
+        assert_eq!(info.line_numbers, Some(true));
+        assert!(info.is_highlighted(4) && info.is_highlighted(8));
+        assert!(!info.is_highlighted(6));
+
+        let plain = FenceInfo::parse("python");
+        assert_eq!(plain.lang, "python");
+        assert!(plain.highlighted.is_empty());
+        assert_eq!(plain.line_numbers, None);
+
+        // Invalid ranges are ignored
+        assert!(FenceInfo::parse("{0-2 5-3 x}").highlighted.is_empty());
+    }
+
+    #[test]
+    fn test_lines_stay_balanced_across_multiline_tokens() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let code = "/* one\n   two */\nfn main() {}\n";
+        let html = highlight(&syntax_set, code, &FenceInfo::parse("rust {2}")).unwrap();
+
+        assert!(html.starts_with(r#"<pre class="code-block"><code class="language-rust">"#));
+        assert_eq!(html.matches(r#"class="code-line"#).count(), 3);
+        assert_eq!(html.matches("code-line-highlight").count(), 1);
+        assert_eq!(
+            html.matches("<span").count(),
+            html.matches("</span>").count()
+        );
+        for line in html.lines() {
+            assert_eq!(
+                line.matches("<span").count(),
+                line.matches("</span>").count()
+            );
+        }
+        assert!(html.contains("hl-comment"));
+        assert!(!html.contains("style="));
+    }
+
+    #[test]
+    fn test_stylesheet_covers_both_schemes() {
+        let mut config: ContentConfig = toml::from_str("").unwrap();
+        let css = stylesheet(&config).unwrap();
+        assert!(css.contains("@media (prefers-color-scheme: dark)"));
+        assert!(css.contains("html.theme-dark .code-block .hl-"));
+        assert!(css.contains("html.theme-light .code-block .hl-"));
+        assert!(css.contains(".code-block.code-numbered .code-line::before"));
+        assert!(!css.contains("background-color: #2b303b"));
+
+        config.line_numbers = true;
+        assert!(stylesheet(&config)
+            .unwrap()
+            .contains(".code-block:not(.code-unnumbered) .code-line::before"));
+
+        config.highlight_theme = "no-such-theme".to_string();
+        assert!(stylesheet(&config).is_err());
+    }
+}
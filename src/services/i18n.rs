@@ -0,0 +1,551 @@
+use crate::config::I18nConfig;
+use crate::models::{Content, ContentStatus, ContentTranslation, ContentType};
+use crate::Database;
+use anyhow::{bail, Context as _, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use rusqlite::OptionalExtension;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// UI strings for one language, keyed by message id.
+pub type Catalog = HashMap<String, String>;
+
+/// Catalogs shipped with Pebble. English is the fallback for any missing key.
+const BUILTIN_CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../../templates/i18n/en.toml")),
+    ("de", include_str!("../../templates/i18n/de.toml")),
+    ("es", include_str!("../../templates/i18n/es.toml")),
+    ("fr", include_str!("../../templates/i18n/fr.toml")),
+];
+
+/// Top-level path segments a language prefix would shadow.
+const RESERVED_PREFIXES: &[&str] = &["api", "js"];
+
+const MAX_GROUP_LENGTH: usize = 64;
+
+/// Check a language code has the `xx`, `xxx` or `xx-yyyy` shape used for
+/// route prefixes and `hreflang`, lowercase only so URLs stay canonical.
+pub fn is_valid_language_code(code: &str) -> bool {
+    let (primary, region) = match code.split_once('-') {
+        Some((p, r)) => (p, Some(r)),
+        None => (code, None),
+    };
+    let primary_ok =
+        (2..=3).contains(&primary.len()) && primary.bytes().all(|b| b.is_ascii_lowercase());
+    let region_ok = region.map_or(true, |r| {
+        (2..=8).contains(&r.len())
+            && r.bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+    });
+    primary_ok && region_ok && !RESERVED_PREFIXES.contains(&code)
+}
+
+/// Every language the site serves, the default first.
+pub fn site_languages(default_language: &str, config: &I18nConfig) -> Vec<String> {
+    std::iter::once(default_language.to_string())
+        .chain(config.languages.iter().cloned())
+        .collect()
+}
+
+/// Normalise a submitted language for storage: blank or the default language
+/// is stored as `None`, anything else must be one of the configured languages.
+pub fn normalize_language(
+    language: Option<&str>,
+    default_language: &str,
+    config: &I18nConfig,
+) -> Result<Option<String>> {
+    let language = language
+        .map(|l| l.trim().to_lowercase())
+        .unwrap_or_default();
+    if language.is_empty() || language == default_language {
+        return Ok(None);
+    }
+    if !config.is_additional(&language) {
+        bail!("'{}' is not one of the site's languages", language);
+    }
+    Ok(Some(language))
+}
+
+/// URL prefix for a language: empty for the default, `/<code>` otherwise.
+pub fn language_prefix(language: Option<&str>, default_language: &str) -> String {
+    match language {
+        Some(l) if l != default_language => format!("/{}", l),
+        _ => String::new(),
+    }
+}
+
+/// The language an item is served under: its own, while the site still lists
+/// it in `i18n.languages`, otherwise the default (`None`).
+pub fn route_language<'a>(language: Option<&'a str>, config: &I18nConfig) -> Option<&'a str> {
+    language.filter(|l| config.is_additional(l))
+}
+
+/// Public path of a post or page in its language.
+pub fn content_path(
+    content_type: ContentType,
+    slug: &str,
+    language: Option<&str>,
+    default_language: &str,
+) -> String {
+    let section = match content_type {
+        ContentType::Page => "pages",
+        _ => "posts",
+    };
+    format!(
+        "{}/{}/{}",
+        language_prefix(language, default_language),
+        section,
+        slug
+    )
+}
+
+/// Public path of a content item under the language it is served in.
+pub fn localized_content_path(
+    content: &Content,
+    default_language: &str,
+    config: &I18nConfig,
+) -> String {
+    content_path(
+        content.content_type,
+        &content.slug,
+        route_language(content.language.as_deref(), config),
+        default_language,
+    )
+}
+
+/// The language versions of a page for `hreflang` links and the language
+/// switcher: the item's own language and path first, then each published
+/// translation in a language the site serves.
+pub fn content_alternates(
+    db: &Database,
+    content: &Content,
+    default_language: &str,
+    config: &I18nConfig,
+) -> Vec<(String, String)> {
+    let own = route_language(content.language.as_deref(), config).unwrap_or(default_language);
+    let mut alternates = vec![(
+        own.to_string(),
+        localized_content_path(content, default_language, config),
+    )];
+    if content.translation_group.is_some() {
+        let translations =
+            list_translations(db, content.id, default_language, config).unwrap_or_default();
+        for t in translations {
+            if t.status == ContentStatus::Published
+                && (t.language == default_language || config.is_additional(&t.language))
+                && !alternates.iter().any(|(code, _)| *code == t.language)
+            {
+                alternates.push((t.language, t.path));
+            }
+        }
+    }
+    alternates
+}
+
+fn generate_group() -> String {
+    let mut bytes = [0u8; 12];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+struct GroupMember {
+    content_type: ContentType,
+    language: Option<String>,
+    group: Option<String>,
+}
+
+fn get_member(conn: &rusqlite::Connection, id: i64) -> Result<GroupMember> {
+    let member = conn
+        .query_row(
+            "SELECT content_type, language, translation_group FROM content WHERE id = ?",
+            [id],
+            |row| {
+                Ok(GroupMember {
+                    content_type: row
+                        .get::<_, String>(0)?
+                        .parse()
+                        .unwrap_or(ContentType::Post),
+                    language: row.get(1)?,
+                    group: row.get(2)?,
+                })
+            },
+        )
+        .optional()?;
+    member.ok_or_else(|| anyhow::anyhow!("Content not found"))
+}
+
+/// Fail if another member of `group` already uses `language`.
+fn ensure_language_free(
+    conn: &rusqlite::Connection,
+    group: &str,
+    language: Option<&str>,
+    id: i64,
+) -> Result<()> {
+    let taken: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM content WHERE translation_group = ? AND language IS ? AND id != ?)",
+        rusqlite::params![group, language, id],
+        |row| row.get(0),
+    )?;
+    if taken {
+        bail!("Another translation already uses this language");
+    }
+    Ok(())
+}
+
+/// A group with a single member links nothing, so drop it.
+fn prune_group(conn: &rusqlite::Connection, group: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE content SET translation_group = NULL WHERE translation_group = ?1
+         AND (SELECT COUNT(*) FROM content WHERE translation_group = ?1) < 2",
+        [group],
+    )?;
+    Ok(())
+}
+
+/// Set the language of a content item. `language` must already be normalised.
+pub fn set_content_language(db: &Database, id: i64, language: Option<&str>) -> Result<()> {
+    let conn = db.get()?;
+    let member = get_member(&conn, id)?;
+    if let Some(group) = &member.group {
+        ensure_language_free(&conn, group, language, id)?;
+    }
+    conn.execute(
+        "UPDATE content SET language = ? WHERE id = ?",
+        rusqlite::params![language, id],
+    )?;
+    Ok(())
+}
+
+/// Make `id` a translation of `translation_of`, joining (or starting) its
+/// translation group.
+pub fn link_translation(db: &Database, id: i64, translation_of: i64) -> Result<()> {
+    if id == translation_of {
+        bail!("Content cannot be a translation of itself");
+    }
+    let mut conn = db.get()?;
+    let tx = conn.transaction()?;
+    let member = get_member(&tx, id)?;
+    let original = get_member(&tx, translation_of)?;
+    if member.content_type != original.content_type {
+        bail!("Translations must be the same content type");
+    }
+    if member.content_type == ContentType::Snippet {
+        bail!("Snippets cannot have translations");
+    }
+    if member.content_type == ContentType::Entry {
+        bail!("Custom content type entries cannot have translations");
+    }
+
+    let group = match original.group {
+        Some(g) => g,
+        None => {
+            let g = generate_group();
+            tx.execute(
+                "UPDATE content SET translation_group = ? WHERE id = ?",
+                rusqlite::params![g, translation_of],
+            )?;
+            g
+        }
+    };
+    if member.group.as_deref() == Some(group.as_str()) {
+        return Ok(());
+    }
+    ensure_language_free(&tx, &group, member.language.as_deref(), id)?;
+    tx.execute(
+        "UPDATE content SET translation_group = ? WHERE id = ?",
+        rusqlite::params![group, id],
+    )?;
+    if let Some(old) = member.group {
+        prune_group(&tx, &old)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Remove a content item from its translation group.
+pub fn unlink_translation(db: &Database, id: i64) -> Result<()> {
+    let mut conn = db.get()?;
+    let tx = conn.transaction()?;
+    let member = get_member(&tx, id)?;
+    if let Some(group) = member.group {
+        tx.execute(
+            "UPDATE content SET translation_group = NULL WHERE id = ?",
+            [id],
+        )?;
+        prune_group(&tx, &group)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Assign a translation group token directly, as carried in exports.
+pub fn set_translation_group(db: &Database, id: i64, group: &str) -> Result<()> {
+    let group = group.trim();
+    if group.is_empty()
+        || group.len() > MAX_GROUP_LENGTH
+        || !group
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        bail!("Invalid translation group '{}'", group);
+    }
+    let conn = db.get()?;
+    let member = get_member(&conn, id)?;
+    ensure_language_free(&conn, group, member.language.as_deref(), id)?;
+    conn.execute(
+        "UPDATE content SET translation_group = ? WHERE id = ?",
+        rusqlite::params![group, id],
+    )?;
+    Ok(())
+}
+
+/// The other language versions of a content item, ordered by language.
+pub fn list_translations(
+    db: &Database,
+    id: i64,
+    default_language: &str,
+    config: &I18nConfig,
+) -> Result<Vec<ContentTranslation>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.slug, c.title, c.content_type, c.status, c.language
+         FROM content c
+         JOIN content self ON self.translation_group = c.translation_group
+         WHERE self.id = ?1 AND c.id != ?1
+         ORDER BY COALESCE(c.language, ''), c.id",
+    )?;
+    let translations = stmt
+        .query_map([id], |row| {
+            let slug: String = row.get(1)?;
+            let content_type: ContentType = row
+                .get::<_, String>(3)?
+                .parse()
+                .unwrap_or(ContentType::Post);
+            let language: Option<String> = row.get(5)?;
+            Ok(ContentTranslation {
+                id: row.get(0)?,
+                path: content_path(
+                    content_type,
+                    &slug,
+                    route_language(language.as_deref(), config),
+                    default_language,
+                ),
+                slug,
+                title: row.get(2)?,
+                content_type,
+                status: row
+                    .get::<_, String>(4)?
+                    .parse()
+                    .unwrap_or(ContentStatus::Draft),
+                language: language.unwrap_or_else(|| default_language.to_string()),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(translations)
+}
+
+fn parse_catalog(source: &str) -> Result<Catalog> {
+    Ok(toml::from_str(source)?)
+}
+
+fn builtin_catalog(code: &str) -> Option<Catalog> {
+    BUILTIN_CATALOGS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .and_then(|(_, src)| parse_catalog(src).ok())
+}
+
+/// Build the catalog for one language: English, then the built-in catalog
+/// for the primary language and exact code, then `<catalog_dir>/<code>.toml`.
+pub fn load_catalog(code: &str, catalog_dir: &Path) -> Result<Catalog> {
+    let mut catalog = builtin_catalog("en").unwrap_or_default();
+    if code != "en" {
+        catalog.remove("language_name");
+    }
+    let primary = code.split('-').next().unwrap_or(code);
+    for layer in [primary, code] {
+        if layer != "en" {
+            catalog.extend(builtin_catalog(layer).unwrap_or_default());
+        }
+    }
+
+    let custom = catalog_dir.join(format!("{}.toml", code));
+    if custom.exists() {
+        let source = std::fs::read_to_string(&custom)?;
+        let overrides = parse_catalog(&source)
+            .with_context(|| format!("Invalid message catalog {}", custom.display()))?;
+        catalog.extend(overrides);
+    }
+
+    catalog
+        .entry("language_name".to_string())
+        .or_insert_with(|| code.to_string());
+    Ok(catalog)
+}
+
+/// Catalogs for every language the site serves.
+pub fn load_catalogs(
+    default_language: &str,
+    config: &I18nConfig,
+) -> Result<HashMap<String, Catalog>> {
+    let dir = Path::new(&config.catalog_dir);
+    site_languages(default_language, config)
+        .into_iter()
+        .map(|code| load_catalog(&code, dir).map(|c| (code, c)))
+        .collect()
+}
+
+/// One entry of the language switcher or the `hreflang` alternates.
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageLink {
+    pub code: String,
+    pub name: String,
+    pub path: String,
+    pub current: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateContent;
+    use crate::services::markdown::MarkdownRenderer;
+    use crate::services::content;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    fn test_db() -> Database {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let db = Database::open_memory(&format!("i18n_test_{}", id)).unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    fn create(db: &Database, title: &str, content_type: ContentType) -> i64 {
+        content::create_content(
+            db,
+            &MarkdownRenderer::new(),
+            &[],
+            CreateContent {
+                title: title.to_string(),
+                slug: None,
+                content_type,
+                body_markdown: "Body".to_string(),
+                excerpt: None,
+                featured_image: None,
+                status: ContentStatus::Published,
+                scheduled_at: None,
+                tags: vec![],
+                metadata: None,
+                entry_type: None,
+                fields: None,
+            },
+            None,
+            200,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_language_codes_and_paths() {
+        assert!(is_valid_language_code("fr"));
+        assert!(is_valid_language_code("pt-br"));
+        assert!(!is_valid_language_code("FR"));
+        assert!(!is_valid_language_code("french"));
+        assert!(!is_valid_language_code("api"));
+        assert!(!is_valid_language_code("fr-"));
+
+        let config = I18nConfig {
+            languages: vec!["fr".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(normalize_language(Some("en"), "en", &config).unwrap(), None);
+        assert_eq!(
+            normalize_language(Some(" FR "), "en", &config).unwrap(),
+            Some("fr".to_string())
+        );
+        assert!(normalize_language(Some("de"), "en", &config).is_err());
+
+        assert_eq!(
+            content_path(ContentType::Post, "hello", None, "en"),
+            "/posts/hello"
+        );
+        assert_eq!(
+            content_path(ContentType::Page, "about", Some("fr"), "en"),
+            "/fr/pages/about"
+        );
+    }
+
+    #[test]
+    fn test_link_and_unlink_translations() {
+        let db = test_db();
+        let en = create(&db, "Hello", ContentType::Post);
+        let fr = create(&db, "Bonjour", ContentType::Post);
+        let de = create(&db, "Hallo", ContentType::Post);
+        let page = create(&db, "About", ContentType::Page);
+
+        set_content_language(&db, fr, Some("fr")).unwrap();
+        link_translation(&db, fr, en).unwrap();
+
+        let config = I18nConfig {
+            languages: vec!["fr".to_string(), "de".to_string()],
+            ..Default::default()
+        };
+        let translations = list_translations(&db, en, "en", &config).unwrap();
+        assert_eq!(translations.len(), 1);
+        assert_eq!(translations[0].language, "fr");
+        assert_eq!(translations[0].path, "/fr/posts/bonjour");
+
+        // de has no language yet, so it clashes with the default-language original
+        assert!(link_translation(&db, de, en).is_err());
+        set_content_language(&db, de, Some("de")).unwrap();
+        link_translation(&db, de, fr).unwrap();
+        assert_eq!(list_translations(&db, en, "en", &config).unwrap().len(), 2);
+
+        // Changing a member's language to one already in the group is rejected
+        assert!(set_content_language(&db, de, Some("fr")).is_err());
+        assert!(link_translation(&db, page, en).is_err());
+
+        unlink_translation(&db, de).unwrap();
+        unlink_translation(&db, fr).unwrap();
+        assert!(list_translations(&db, en, "en", &config)
+            .unwrap()
+            .is_empty());
+        let original = content::get_content_by_id(&db, en).unwrap().unwrap();
+        assert_eq!(original.content.translation_group, None);
+    }
+
+    #[test]
+    fn test_catalog_layers() {
+        let dir = std::env::temp_dir().join(format!(
+            "pebble_i18n_test_{}",
+            TEST_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("fr.toml"), "nav_posts = \"Billets\"\n").unwrap();
+        std::fs::write(dir.join("it.toml"), "nav_home = \"Inizio\"\n").unwrap();
+
+        let en = load_catalog("en", &dir).unwrap();
+        assert_eq!(en["nav_posts"], "Posts");
+        assert_eq!(en["language_name"], "English");
+
+        let fr = load_catalog("fr-ca", &dir).unwrap();
+        assert_eq!(fr["nav_home"], "Accueil");
+        assert_eq!(fr["language_name"], "Français");
+        let fr = load_catalog("fr", &dir).unwrap();
+        assert_eq!(fr["nav_posts"], "Billets");
+
+        // No built-in Italian: English strings, the override, and the code as its name
+        let it = load_catalog("it", &dir).unwrap();
+        assert_eq!(it["nav_home"], "Inizio");
+        assert_eq!(it["nav_tags"], "Tags");
+        assert_eq!(it["language_name"], "it");
+
+        std::fs::write(dir.join("de.toml"), "nav_home = [").unwrap();
+        assert!(load_catalog("de", &dir).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
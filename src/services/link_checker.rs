@@ -0,0 +1,751 @@
+//! External link checking: find the outbound links in published content,
+//! request each one (politely — one host at a time per worker, with a pause
+//! between requests and robots.txt respected) and record which are broken.
+//! Runs in the background while the server is up, from the admin, and with
+//! `pebble links check`.
+
+use crate::config::LinkCheckerConfig;
+use crate::models::{BrokenLinkReport, LinkCheck, LinkCheckSummary};
+use crate::services::webmention;
+use crate::Database;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Sent with every request; sites can address us in robots.txt as `pebble`.
+#[cfg_attr(not(feature = "webhooks"), allow(dead_code))]
+const USER_AGENT: &str = "Pebble-CMS/1.0 (link checker)";
+/// robots.txt `User-agent` values that address this checker specifically.
+const ROBOTS_AGENTS: &[&str] = &["pebble", "pebble-cms"];
+/// Longest robots.txt read; anything after this is ignored.
+#[cfg_attr(not(feature = "webhooks"), allow(dead_code))]
+const MAX_ROBOTS_BYTES: usize = 512 * 1024;
+
+/// Sites (by URL) with a check in progress, so the background task, the
+/// admin button and the CLI never check the same site twice at once.
+static RUNNING: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+fn running() -> std::sync::MutexGuard<'static, HashSet<String>> {
+    RUNNING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Held for the duration of a check; releases the site when dropped.
+pub struct RunGuard(String);
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        running().remove(&self.0);
+    }
+}
+
+/// Claim the checker for a site, or `None` if a check is already in progress.
+pub fn begin_run(site_url: &str) -> Option<RunGuard> {
+    running()
+        .insert(site_url.to_string())
+        .then(|| RunGuard(site_url.to_string()))
+}
+
+pub fn is_running(site_url: &str) -> bool {
+    running().contains(site_url)
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    /// Number of hosts checked at the same time
+    pub concurrency: usize,
+    /// Pause between requests to the same host
+    pub host_delay: Duration,
+    pub timeout: Duration,
+    pub respect_robots: bool,
+}
+
+impl CheckOptions {
+    pub fn from_config(config: &LinkCheckerConfig) -> Self {
+        Self {
+            concurrency: config.concurrency.max(1),
+            host_delay: Duration::from_millis(config.host_delay_ms),
+            timeout: Duration::from_secs(config.timeout_secs.max(1)),
+            respect_robots: config.respect_robots,
+        }
+    }
+}
+
+/// Counts from one run of the checker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckRun {
+    pub checked: usize,
+    pub broken: usize,
+    pub skipped: usize,
+}
+
+/// What happened when a URL was requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub status_code: Option<u16>,
+    pub broken: bool,
+    pub skipped: bool,
+    pub error: Option<String>,
+}
+
+impl Outcome {
+    pub fn from_status(status: u16) -> Self {
+        Self {
+            status_code: Some(status),
+            broken: is_broken_status(status),
+            skipped: false,
+            error: None,
+        }
+    }
+
+    pub fn failed(error: String) -> Self {
+        Self {
+            status_code: None,
+            broken: true,
+            skipped: false,
+            error: Some(error),
+        }
+    }
+
+    pub fn disallowed() -> Self {
+        Self {
+            status_code: None,
+            broken: false,
+            skipped: true,
+            error: Some("Disallowed by robots.txt".to_string()),
+        }
+    }
+}
+
+/// Client and server errors mean the link is broken, except the statuses
+/// sites commonly send to automated clients for pages that work fine in a
+/// browser: 401 and 403 (bot protection, logins) and 429 (rate limiting).
+pub fn is_broken_status(status: u16) -> bool {
+    status >= 400 && !matches!(status, 401 | 403 | 429)
+}
+
+// ----------------------------------------------------------------------------
+// Links in content
+// ----------------------------------------------------------------------------
+
+/// A published item and the external links in its rendered HTML.
+struct LinkedContent {
+    id: i64,
+    title: String,
+    slug: String,
+    content_type: String,
+    links: Vec<String>,
+}
+
+fn linked_content(db: &Database, site_url: &str) -> Result<Vec<LinkedContent>> {
+    let conn = db.get()?;
+    // Snippets are rendered into the items that use them, so their links are
+    // found there
+    let mut stmt = conn.prepare(
+        "SELECT id, title, slug, COALESCE(entry_type, content_type), body_html FROM content
+         WHERE status = 'published' AND content_type != 'snippet'
+         ORDER BY published_at DESC, id DESC",
+    )?;
+    let items = stmt
+        .query_map([], |row| {
+            let body_html: String = row.get(4)?;
+            Ok(LinkedContent {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                slug: row.get(2)?,
+                content_type: row.get(3)?,
+                links: webmention::external_links(&body_html, site_url),
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+/// Every distinct external link in published content.
+pub fn all_links(db: &Database, site_url: &str) -> Result<Vec<String>> {
+    let mut seen = HashSet::new();
+    let mut links = Vec::new();
+    for item in linked_content(db, site_url)? {
+        for link in item.links {
+            if seen.insert(link.clone()) {
+                links.push(link);
+            }
+        }
+    }
+    Ok(links)
+}
+
+/// Links that have never been checked, or not within `recheck_after_hours`.
+pub fn stale_links(db: &Database, site_url: &str, recheck_after_hours: u64) -> Result<Vec<String>> {
+    let fresh: HashSet<String> = {
+        let conn = db.get()?;
+        let mut stmt =
+            conn.prepare("SELECT url FROM link_checks WHERE checked_at > datetime('now', ?1)")?;
+        let fresh = stmt
+            .query_map([format!("-{} hours", recheck_after_hours)], |row| {
+                row.get(0)
+            })?
+            .collect::<std::result::Result<_, _>>()?;
+        fresh
+    };
+    Ok(all_links(db, site_url)?
+        .into_iter()
+        .filter(|l| !fresh.contains(l))
+        .collect())
+}
+
+// ----------------------------------------------------------------------------
+// Stored results
+// ----------------------------------------------------------------------------
+
+fn row_to_link_check(row: &rusqlite::Row) -> rusqlite::Result<LinkCheck> {
+    Ok(LinkCheck {
+        url: row.get(0)?,
+        status_code: row.get(1)?,
+        broken: row.get(2)?,
+        skipped: row.get(3)?,
+        error: row.get(4)?,
+        checked_at: row.get(5)?,
+    })
+}
+
+pub fn record(db: &Database, url: &str, outcome: &Outcome) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO link_checks (url, status_code, broken, skipped, error, checked_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP)
+         ON CONFLICT(url) DO UPDATE SET status_code = excluded.status_code, broken = excluded.broken,
+             skipped = excluded.skipped, error = excluded.error, checked_at = excluded.checked_at",
+        rusqlite::params![
+            url,
+            outcome.status_code,
+            outcome.broken,
+            outcome.skipped,
+            outcome.error
+        ],
+    )?;
+    Ok(())
+}
+
+fn load_checks(db: &Database) -> Result<HashMap<String, LinkCheck>> {
+    let conn = db.get()?;
+    let mut stmt = conn
+        .prepare("SELECT url, status_code, broken, skipped, error, checked_at FROM link_checks")?;
+    let checks = stmt
+        .query_map([], row_to_link_check)?
+        .map(|r| r.map(|c| (c.url.clone(), c)))
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(checks)
+}
+
+/// Forget results for links no longer in any published content.
+pub fn prune(db: &Database, site_url: &str) -> Result<usize> {
+    let current: HashSet<String> = all_links(db, site_url)?.into_iter().collect();
+    let conn = db.get()?;
+    let mut removed = 0;
+    for url in load_checks(db)?.into_keys() {
+        if !current.contains(&url) {
+            removed += conn.execute("DELETE FROM link_checks WHERE url = ?1", [&url])?;
+        }
+    }
+    Ok(removed)
+}
+
+/// Published content with at least one broken link, newest first.
+pub fn broken_report(db: &Database, site_url: &str) -> Result<Vec<BrokenLinkReport>> {
+    let checks = load_checks(db)?;
+    let reports = linked_content(db, site_url)?
+        .into_iter()
+        .filter_map(|item| {
+            let links: Vec<LinkCheck> = item
+                .links
+                .iter()
+                .filter_map(|l| checks.get(l))
+                .filter(|c| c.broken)
+                .cloned()
+                .collect();
+            (!links.is_empty()).then_some(BrokenLinkReport {
+                content_id: item.id,
+                title: item.title,
+                slug: item.slug,
+                content_type: item.content_type,
+                links,
+            })
+        })
+        .collect();
+    Ok(reports)
+}
+
+pub fn summary(db: &Database, site_url: &str) -> Result<LinkCheckSummary> {
+    let checks = load_checks(db)?;
+    let mut summary = LinkCheckSummary::default();
+    for url in all_links(db, site_url)? {
+        summary.total += 1;
+        let Some(check) = checks.get(&url) else {
+            continue;
+        };
+        summary.checked += 1;
+        summary.broken += check.broken as usize;
+        summary.skipped += check.skipped as usize;
+        if summary.last_checked.as_deref() < Some(check.checked_at.as_str()) {
+            summary.last_checked = Some(check.checked_at.clone());
+        }
+    }
+    Ok(summary)
+}
+
+// ----------------------------------------------------------------------------
+// robots.txt
+// ----------------------------------------------------------------------------
+
+/// The Allow/Disallow rules of a robots.txt that apply to this checker.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RobotsRules {
+    /// (allow, path pattern)
+    rules: Vec<(bool, String)>,
+}
+
+impl RobotsRules {
+    /// Parse robots.txt, keeping the groups addressed to Pebble or, if there
+    /// are none, the `*` groups.
+    pub fn parse(txt: &str) -> Self {
+        let mut specific = Vec::new();
+        let mut wildcard = Vec::new();
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        let mut has_specific_group = false;
+
+        for line in txt.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // A user-agent line after rules starts a new group
+                    if in_rules {
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    let agent = value.to_ascii_lowercase();
+                    has_specific_group |= ROBOTS_AGENTS.contains(&agent.as_str());
+                    agents.push(agent);
+                }
+                key @ ("allow" | "disallow") => {
+                    in_rules = true;
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = (key == "allow", value.to_string());
+                    if agents.iter().any(|a| ROBOTS_AGENTS.contains(&a.as_str())) {
+                        specific.push(rule.clone());
+                    }
+                    if agents.iter().any(|a| a == "*") {
+                        wildcard.push(rule);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            rules: if has_specific_group {
+                specific
+            } else {
+                wildcard
+            },
+        }
+    }
+
+    /// Whether a path (with query) may be requested. The longest matching
+    /// rule wins, and Allow wins a tie.
+    pub fn allows(&self, path: &str) -> bool {
+        let mut best: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if !robots_match(pattern, path) {
+                continue;
+            }
+            let len = pattern.len();
+            best = match best {
+                Some((best_len, best_allow))
+                    if best_len > len || (best_len == len && best_allow) =>
+                {
+                    Some((best_len, best_allow))
+                }
+                _ => Some((len, *allow)),
+            };
+        }
+        best.map(|(_, allow)| allow).unwrap_or(true)
+    }
+}
+
+/// Match a robots.txt path pattern: a prefix match where `*` matches any
+/// run of characters and a trailing `$` anchors the end.
+fn robots_match(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let is_last = i + 1 == parts.len();
+        if is_last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+// ----------------------------------------------------------------------------
+// Checking
+// ----------------------------------------------------------------------------
+
+#[cfg(feature = "webhooks")]
+fn http_client(options: &CheckOptions) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .connect_timeout(options.timeout.min(Duration::from_secs(5)))
+        .timeout(options.timeout)
+        .redirect(reqwest::redirect::Policy::limited(10))
+        .user_agent(USER_AGENT)
+        .build()?)
+}
+
+/// Fetch a host's robots.txt. A missing or unreadable file allows everything.
+#[cfg(feature = "webhooks")]
+async fn fetch_robots(client: &reqwest::Client, origin: &str) -> RobotsRules {
+    let Ok(mut resp) = client.get(format!("{}/robots.txt", origin)).send().await else {
+        return RobotsRules::default();
+    };
+    if !resp.status().is_success() {
+        return RobotsRules::default();
+    }
+    let mut body = Vec::new();
+    while let Ok(Some(chunk)) = resp.chunk().await {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_ROBOTS_BYTES {
+            break;
+        }
+    }
+    RobotsRules::parse(&String::from_utf8_lossy(&body))
+}
+
+/// Request a URL with HEAD, retrying with GET when HEAD fails, since plenty
+/// of servers answer HEAD with 404 or 405 for pages that exist.
+#[cfg(feature = "webhooks")]
+async fn check_url(client: &reqwest::Client, url: &str) -> Outcome {
+    if let Ok(resp) = client.head(url).send().await {
+        if resp.status().is_success() {
+            return Outcome::from_status(resp.status().as_u16());
+        }
+    }
+    // The body is never read; dropping the response closes the connection
+    match client.get(url).send().await {
+        Ok(resp) => Outcome::from_status(resp.status().as_u16()),
+        Err(e) if e.is_timeout() => Outcome::failed("Timed out".to_string()),
+        Err(e) if e.is_connect() => Outcome::failed(format!("Could not connect: {}", e)),
+        Err(e) => Outcome::failed(e.to_string()),
+    }
+}
+
+/// Check every URL in `urls` and record the results. URLs are grouped by
+/// host; up to `concurrency` hosts are checked at once, each one request at a
+/// time with `host_delay` between requests.
+#[cfg(feature = "webhooks")]
+pub async fn check_links(
+    db: &Database,
+    urls: &[String],
+    options: &CheckOptions,
+) -> Result<CheckRun> {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    let mut by_origin: BTreeMap<String, Vec<url::Url>> = BTreeMap::new();
+    for raw in urls {
+        let Ok(url) = url::Url::parse(raw) else {
+            continue;
+        };
+        by_origin
+            .entry(url.origin().ascii_serialization())
+            .or_default()
+            .push(url);
+    }
+
+    let client = http_client(options)?;
+    let permits = Arc::new(tokio::sync::Semaphore::new(options.concurrency.max(1)));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (origin, urls) in by_origin {
+        let db = db.clone();
+        let client = client.clone();
+        let permits = permits.clone();
+        let options = options.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await?;
+            let mut run = CheckRun::default();
+            let robots = if options.respect_robots {
+                let robots = fetch_robots(&client, &origin).await;
+                tokio::time::sleep(options.host_delay).await;
+                robots
+            } else {
+                RobotsRules::default()
+            };
+
+            for (i, url) in urls.iter().enumerate() {
+                let path = match url.query() {
+                    Some(q) => format!("{}?{}", url.path(), q),
+                    None => url.path().to_string(),
+                };
+                let outcome = if robots.allows(&path) {
+                    if i > 0 {
+                        tokio::time::sleep(options.host_delay).await;
+                    }
+                    check_url(&client, url.as_str()).await
+                } else {
+                    Outcome::disallowed()
+                };
+                record(&db, url.as_str(), &outcome)?;
+                run.checked += 1;
+                run.broken += outcome.broken as usize;
+                run.skipped += outcome.skipped as usize;
+            }
+            anyhow::Ok(run)
+        });
+    }
+
+    let mut total = CheckRun::default();
+    while let Some(result) = tasks.join_next().await {
+        let run = result??;
+        total.checked += run.checked;
+        total.broken += run.broken;
+        total.skipped += run.skipped;
+    }
+    Ok(total)
+}
+
+/// Check the site's external links: every link, or with `stale_only` just
+/// those not checked within `recheck_after_hours`. Results for links that
+/// are no longer in published content are dropped first.
+#[cfg(feature = "webhooks")]
+pub async fn run_check(
+    db: &Database,
+    site_url: &str,
+    config: &LinkCheckerConfig,
+    stale_only: bool,
+) -> Result<CheckRun> {
+    let Some(_guard) = begin_run(site_url) else {
+        anyhow::bail!("A link check is already running");
+    };
+    prune(db, site_url)?;
+    let urls = if stale_only {
+        stale_links(db, site_url, config.recheck_after_hours)?
+    } else {
+        all_links(db, site_url)?
+    };
+    check_links(db, &urls, &CheckOptions::from_config(config)).await
+}
+
+#[cfg(not(feature = "webhooks"))]
+pub async fn run_check(
+    _db: &Database,
+    _site_url: &str,
+    _config: &LinkCheckerConfig,
+    _stale_only: bool,
+) -> Result<CheckRun> {
+    anyhow::bail!("Link checking requires the webhooks feature")
+}
+
+/// Check every link in the background, e.g. from the admin's "Check now".
+pub fn spawn_check(db: &Database, site_url: String, config: LinkCheckerConfig) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        match run_check(&db, &site_url, &config, false).await {
+            Ok(run) => tracing::info!(
+                "Link check: {} link(s) checked, {} broken",
+                run.checked,
+                run.broken
+            ),
+            Err(e) => tracing::warn!("Link check failed: {}", e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ContentStatus, ContentType, CreateContent};
+    use crate::services::markdown::MarkdownRenderer;
+    use crate::services::content;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    const SITE_URL: &str = "https://blog.example.com";
+
+    fn setup_test_db() -> Database {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let db = Database::open_memory(&format!("link_checker_test_{}", id)).unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    fn create_post(db: &Database, slug: &str, body: &str, status: ContentStatus) -> i64 {
+        content::create_content(
+            db,
+            &MarkdownRenderer::new(),
+            &[],
+            CreateContent {
+                title: slug.to_string(),
+                slug: Some(slug.to_string()),
+                content_type: ContentType::Post,
+                body_markdown: body.to_string(),
+                status,
+                scheduled_at: None,
+                excerpt: None,
+                featured_image: None,
+                tags: vec![],
+                metadata: None,
+                entry_type: None,
+                fields: None,
+            },
+            None,
+            200,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_robots_rules() {
+        let robots = RobotsRules::parse(
+            "User-agent: *\nDisallow: /private\nAllow: /private/open\nDisallow: /*.pdf$\n\n\
+             User-agent: googlebot\nDisallow: /\n",
+        );
+        assert!(robots.allows("/"));
+        assert!(!robots.allows("/private/page"));
+        assert!(robots.allows("/private/open/page"));
+        assert!(!robots.allows("/files/report.pdf"));
+        assert!(robots.allows("/files/report.pdf?download=1"));
+
+        // A group naming Pebble replaces the wildcard group
+        let robots = RobotsRules::parse(
+            "User-agent: *\nDisallow: /\n\nUser-agent: Pebble\nDisallow: /admin # comment\n",
+        );
+        assert!(robots.allows("/posts/1"));
+        assert!(!robots.allows("/admin/login"));
+
+        assert!(RobotsRules::parse("User-agent: *\nDisallow:\n").allows("/anything"));
+    }
+
+    #[test]
+    fn test_stale_links_and_report() {
+        let db = setup_test_db();
+        let post = create_post(
+            &db,
+            "links",
+            "[a](https://a.example/one) [b](https://b.example/two#part) [self](https://blog.example.com/posts/x)",
+            ContentStatus::Published,
+        );
+        create_post(
+            &db,
+            "draft",
+            "[c](https://c.example/)",
+            ContentStatus::Draft,
+        );
+
+        assert_eq!(
+            all_links(&db, SITE_URL).unwrap(),
+            vec!["https://a.example/one", "https://b.example/two"]
+        );
+
+        record(&db, "https://a.example/one", &Outcome::from_status(200)).unwrap();
+        record(&db, "https://b.example/two", &Outcome::from_status(404)).unwrap();
+        record(&db, "https://gone.example/", &Outcome::from_status(404)).unwrap();
+        assert!(stale_links(&db, SITE_URL, 24).unwrap().is_empty());
+        assert_eq!(stale_links(&db, SITE_URL, 0).unwrap().len(), 2);
+
+        let report = broken_report(&db, SITE_URL).unwrap();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].content_id, post);
+        assert_eq!(report[0].links.len(), 1);
+        assert_eq!(report[0].links[0].status_code, Some(404));
+
+        assert_eq!(prune(&db, SITE_URL).unwrap(), 1);
+        let summary = summary(&db, SITE_URL).unwrap();
+        assert_eq!((summary.total, summary.checked, summary.broken), (2, 2, 1));
+
+        assert!(!is_broken_status(403));
+        assert!(is_broken_status(410));
+        assert!(is_broken_status(503));
+    }
+
+    #[cfg(feature = "webhooks")]
+    #[tokio::test]
+    async fn test_check_links_against_stub_server() {
+        use axum::http::{Method, StatusCode};
+        use axum::routing::{any, get};
+
+        let app = axum::Router::new()
+            .route(
+                "/robots.txt",
+                get(|| async { "User-agent: *\nDisallow: /private\n" }),
+            )
+            .route("/ok", get(|| async { "fine" }))
+            .route("/gone", get(|| async { StatusCode::GONE }))
+            .route(
+                "/no-head",
+                any(|method: Method| async move {
+                    if method == Method::HEAD {
+                        StatusCode::METHOD_NOT_ALLOWED
+                    } else {
+                        StatusCode::OK
+                    }
+                }),
+            )
+            .route("/private/page", get(|| async { "secret" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stub = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let db = setup_test_db();
+        let urls: Vec<String> = ["/ok", "/gone", "/no-head", "/missing", "/private/page"]
+            .iter()
+            .map(|p| format!("{}{}", stub, p))
+            .collect();
+        let options = CheckOptions {
+            concurrency: 2,
+            host_delay: Duration::ZERO,
+            timeout: Duration::from_secs(5),
+            respect_robots: true,
+        };
+        let run = check_links(&db, &urls, &options).await.unwrap();
+        assert_eq!(
+            run,
+            CheckRun {
+                checked: 5,
+                broken: 2,
+                skipped: 1
+            }
+        );
+
+        let checks = load_checks(&db).unwrap();
+        let status = |path: &str| checks[&format!("{}{}", stub, path)].clone();
+        assert!(!status("/ok").broken);
+        assert_eq!(status("/gone").status_code, Some(410));
+        assert!(status("/gone").broken);
+        assert_eq!(status("/no-head").status_code, Some(200));
+        assert_eq!(status("/missing").status_code, Some(404));
+        assert!(status("/private/page").skipped);
+        assert!(!status("/private/page").broken);
+    }
+}
@@ -0,0 +1,822 @@
+//! Content checks: problems in what authors write that rendering quietly
+//! tolerates, such as links to slugs that don't exist or images without alt
+//! text. Checks run when content is saved, from the editor's checks panel and
+//! across the whole site with `pebble check`.
+
+use crate::config::{Config, ContentTypeConfig};
+use crate::models::{Content, ContentStatus, ContentType};
+use crate::services::content;
+use crate::services::markdown::{self, ShortcodeProcessor};
+use crate::Database;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use regex::Regex;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Search engines cut titles off at around this many characters
+const MAX_SEO_TITLE_LENGTH: usize = 60;
+/// Search engines cut descriptions off at around this many characters
+const MAX_SEO_DESCRIPTION_LENGTH: usize = 160;
+
+static HTML_TAG_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)<(a|img)\b[^>]*>").expect("Invalid HTML tag regex pattern"));
+static HTML_ATTR_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\b(href|src|alt)\s*=\s*(?:"([^"]*)"|'([^']*)')"#)
+        .expect("Invalid HTML attribute regex pattern")
+});
+/// Suffixes of the WebP variants generated for each uploaded image
+static VARIANT_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:-\d+w|-thumb)?\.webp$").expect("Invalid variant regex pattern"));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Short name of the check that found the issue, e.g. "broken-link"
+    pub rule: String,
+    pub message: String,
+    /// Line of the Markdown body the issue points at, counting from 1
+    pub line: Option<usize>,
+}
+
+impl Issue {
+    fn new(severity: Severity, rule: &str, message: String, line: Option<usize>) -> Self {
+        Self {
+            severity,
+            rule: rule.to_string(),
+            message,
+            line,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckCounts {
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl CheckCounts {
+    pub fn of(issues: &[Issue]) -> Self {
+        let errors = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count();
+        Self {
+            errors,
+            warnings: issues.len() - errors,
+        }
+    }
+}
+
+/// What the checks look at: a saved content item or the editor's unsaved form.
+#[derive(Debug, Clone, Default)]
+pub struct Draft {
+    /// Id of the saved item, so it isn't reported as its own duplicate
+    pub id: Option<i64>,
+    pub content_type: ContentType,
+    pub title: String,
+    pub body_markdown: String,
+    /// Excerpt written by the author. Generated excerpts are sized by
+    /// `content.excerpt_length` and aren't checked.
+    pub excerpt: Option<String>,
+    pub meta_title: Option<String>,
+    pub meta_description: Option<String>,
+}
+
+impl Draft {
+    pub fn from_content(content: &Content, excerpt_length: usize) -> Self {
+        let metadata = |key: &str| {
+            content.metadata[key]
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+        };
+        Self {
+            id: Some(content.id),
+            content_type: content.content_type,
+            title: content.title.clone(),
+            body_markdown: content.body_markdown.clone(),
+            excerpt: authored_excerpt(
+                content.excerpt.as_deref(),
+                &content.body_markdown,
+                excerpt_length,
+            ),
+            meta_title: metadata("meta_title"),
+            meta_description: metadata("meta_description"),
+        }
+    }
+}
+
+/// `excerpt` unless it's empty or the one generated from `body`.
+pub fn authored_excerpt(
+    excerpt: Option<&str>,
+    body: &str,
+    excerpt_length: usize,
+) -> Option<String> {
+    excerpt
+        .map(str::trim)
+        .filter(|e| !e.is_empty() && *e != markdown::generate_excerpt(body, excerpt_length))
+        .map(String::from)
+}
+
+/// Issues found in one content item by [`check_site`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemReport {
+    pub id: i64,
+    pub slug: String,
+    pub title: String,
+    pub content_type: ContentType,
+    pub issues: Vec<Issue>,
+}
+
+/// Run every check against `draft`. Errors come first, then warnings, each in
+/// body order with issues about the whole item last.
+pub fn check(
+    db: &Database,
+    content_types: &[ContentTypeConfig],
+    draft: &Draft,
+) -> Result<Vec<Issue>> {
+    let scan = Scan::new(&draft.body_markdown);
+    let mut issues = Vec::new();
+
+    for (url, line) in &scan.links {
+        if let Some(issue) = check_link(db, content_types, url, *line)? {
+            issues.push(issue);
+        }
+    }
+    for (filename, line) in &scan.media {
+        if !media_exists(db, filename)? {
+            issues.push(Issue::new(
+                Severity::Error,
+                "missing-media",
+                format!("\"{}\" is not in the media library", filename),
+                Some(*line),
+            ));
+        }
+    }
+    for (src, line) in &scan.missing_alt {
+        issues.push(Issue::new(
+            Severity::Warning,
+            "missing-alt",
+            format!("Image \"{}\" has no alt text", src),
+            Some(*line),
+        ));
+    }
+    for (slug, line) in &scan.snippets {
+        if !snippet_exists(db, slug)? {
+            issues.push(Issue::new(
+                Severity::Error,
+                "unresolved-snippet",
+                format!("No snippet with slug \"{}\"", slug),
+                Some(*line),
+            ));
+        }
+    }
+
+    // Snippets are fragments of other pages, so page-level checks don't apply
+    if draft.content_type != ContentType::Snippet {
+        // The title is the page's h1, so the body should start at h2
+        let mut previous = 1;
+        for &(level, line) in &scan.headings {
+            if level > previous + 1 {
+                issues.push(Issue::new(
+                    Severity::Warning,
+                    "heading-skip",
+                    format!("Heading level jumps from h{} to h{}", previous, level),
+                    Some(line),
+                ));
+            }
+            previous = level;
+        }
+
+        issues.extend(check_duplicate_title(db, draft)?);
+
+        let (field, title) = match draft.meta_title.as_deref() {
+            Some(meta_title) => ("Meta title", meta_title),
+            None => ("Title", draft.title.as_str()),
+        };
+        let len = title.chars().count();
+        if len > MAX_SEO_TITLE_LENGTH {
+            issues.push(Issue::new(
+                Severity::Warning,
+                "title-length",
+                format!(
+                    "{} is {} characters; search results show about {}",
+                    field, len, MAX_SEO_TITLE_LENGTH
+                ),
+                None,
+            ));
+        }
+
+        let description = draft
+            .meta_description
+            .as_deref()
+            .map(|d| ("Meta description", d))
+            .or_else(|| draft.excerpt.as_deref().map(|e| ("Excerpt", e)));
+        if let Some((field, description)) = description {
+            let len = description.chars().count();
+            if len > MAX_SEO_DESCRIPTION_LENGTH {
+                issues.push(Issue::new(
+                    Severity::Warning,
+                    "description-length",
+                    format!(
+                        "{} is {} characters; search results show about {}",
+                        field, len, MAX_SEO_DESCRIPTION_LENGTH
+                    ),
+                    None,
+                ));
+            }
+        }
+    }
+
+    issues.sort_by_key(|i| (Reverse(i.severity), i.line.unwrap_or(usize::MAX)));
+    Ok(issues)
+}
+
+/// Store the latest results for a content item, replacing earlier ones.
+pub fn record(db: &Database, content_id: i64, issues: &[Issue]) -> Result<()> {
+    let counts = CheckCounts::of(issues);
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO content_checks (content_id, errors, warnings, issues, checked_at)
+         VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+         ON CONFLICT(content_id) DO UPDATE SET
+            errors = excluded.errors,
+            warnings = excluded.warnings,
+            issues = excluded.issues,
+            checked_at = excluded.checked_at",
+        rusqlite::params![
+            content_id,
+            counts.errors as i64,
+            counts.warnings as i64,
+            serde_json::to_string(issues)?
+        ],
+    )?;
+    Ok(())
+}
+
+/// Check a saved content item and record the results.
+pub fn check_saved(db: &Database, config: &Config, content_id: i64) -> Result<Vec<Issue>> {
+    let Some(item) = content::get_content_by_id(db, content_id)? else {
+        return Ok(Vec::new());
+    };
+    let draft = Draft::from_content(&item.content, config.content.excerpt_length);
+    let issues = check(db, &config.content_types, &draft)?;
+    record(db, content_id, &issues)?;
+    Ok(issues)
+}
+
+/// Run the checks after a save. Failing to check never fails the save itself.
+pub fn check_on_save(db: &Database, config: &Config, content_id: i64) -> CheckCounts {
+    match check_saved(db, config, content_id) {
+        Ok(issues) => CheckCounts::of(&issues),
+        Err(e) => {
+            tracing::warn!("Content checks failed for content {}: {}", content_id, e);
+            CheckCounts::default()
+        }
+    }
+}
+
+/// Recorded issue counts of every content item checked so far, by content id.
+pub fn recorded_counts(db: &Database) -> Result<HashMap<i64, CheckCounts>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT content_id, errors, warnings FROM content_checks WHERE errors > 0 OR warnings > 0",
+    )?;
+    let counts = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                CheckCounts {
+                    errors: row.get::<_, i64>(1)? as usize,
+                    warnings: row.get::<_, i64>(2)? as usize,
+                },
+            ))
+        })?
+        .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+    Ok(counts)
+}
+
+/// Check and record every content item, returning those with issues.
+pub fn check_site(db: &Database, config: &Config) -> Result<Vec<ItemReport>> {
+    let ids: Vec<i64> = {
+        let conn = db.get()?;
+        let mut stmt = conn.prepare("SELECT id FROM content ORDER BY content_type, slug")?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        ids
+    };
+
+    let mut reports = Vec::new();
+    for id in ids {
+        let Some(item) = content::get_content_by_id(db, id)? else {
+            continue;
+        };
+        let issues = check_saved(db, config, id)?;
+        if !issues.is_empty() {
+            reports.push(ItemReport {
+                id,
+                slug: item.content.slug,
+                title: item.content.title,
+                content_type: item.content.content_type,
+                issues,
+            });
+        }
+    }
+    Ok(reports)
+}
+
+/// Everything in a Markdown body the checks look up, with line numbers.
+#[derive(Debug, Default)]
+struct Scan {
+    /// Site-relative link targets
+    links: Vec<(String, usize)>,
+    /// Media library filenames
+    media: Vec<(String, usize)>,
+    /// Sources of images without alt text
+    missing_alt: Vec<(String, usize)>,
+    headings: Vec<(u8, usize)>,
+    snippets: Vec<(String, usize)>,
+}
+
+impl Scan {
+    fn new(body: &str) -> Self {
+        let mut scan = Self::default();
+        let line_of = |offset: usize| body[..offset].matches('\n').count() + 1;
+
+        let mut image: Option<(String, String, usize)> = None;
+        for (event, range) in Parser::new_ext(body, markdown::markdown_options()).into_offset_iter()
+        {
+            let line = line_of(range.start);
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    scan.headings.push((level as u8, line));
+                }
+                Event::Start(Tag::Link { dest_url, .. }) => scan.url(&dest_url, line),
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    scan.url(&dest_url, line);
+                    image = Some((dest_url.to_string(), String::new(), line));
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some((_, alt, _)) = image.as_mut() {
+                        alt.push_str(&text);
+                    }
+                }
+                Event::End(TagEnd::Image) => {
+                    if let Some((src, alt, line)) = image.take() {
+                        if alt.trim().is_empty() {
+                            scan.missing_alt.push((src, line));
+                        }
+                    }
+                }
+                Event::Html(html) | Event::InlineHtml(html) => scan.html(&html, line),
+                _ => {}
+            }
+        }
+
+        for tag in markdown::find_shortcodes(body) {
+            scan.shortcode(&tag, line_of(tag.offset));
+        }
+        scan
+    }
+
+    fn url(&mut self, url: &str, line: usize) {
+        let path = url.split(['?', '#']).next().unwrap_or("");
+        if !path.starts_with('/') || path.starts_with("//") {
+            return;
+        }
+        match path.strip_prefix("/media/") {
+            Some(filename) => self.media.push((filename.to_string(), line)),
+            None => self.links.push((path.to_string(), line)),
+        }
+    }
+
+    fn html(&mut self, html: &str, line: usize) {
+        for tag in HTML_TAG_REGEX.captures_iter(html) {
+            let mut attrs = HashMap::new();
+            for attr in HTML_ATTR_REGEX.captures_iter(&tag[0]) {
+                let value = attr
+                    .get(2)
+                    .or_else(|| attr.get(3))
+                    .map_or("", |m| m.as_str());
+                attrs.insert(attr[1].to_lowercase(), value.to_string());
+            }
+            if let Some(url) = attrs.get("href").or_else(|| attrs.get("src")) {
+                self.url(url, line);
+            }
+            // An explicit alt="" marks a decorative image, so only a missing
+            // attribute counts
+            if tag[1].eq_ignore_ascii_case("img") && !attrs.contains_key("alt") {
+                let src = attrs.get("src").cloned().unwrap_or_default();
+                self.missing_alt.push((src, line));
+            }
+        }
+    }
+
+    fn shortcode(&mut self, tag: &markdown::ShortcodeTag, line: usize) {
+        let src = tag.attrs.get("src").map(|s| s.as_str()).unwrap_or("");
+        let is_image = |src: &str| {
+            let ext = src.rsplit('.').next().unwrap_or("").to_lowercase();
+            matches!(
+                ext.as_str(),
+                "jpg" | "jpeg" | "png" | "gif" | "webp" | "svg"
+            )
+        };
+        let needs_alt = match tag.name.as_str() {
+            "image" | "img" | "figure" => true,
+            "media" => is_image(src),
+            "video" | "audio" | "gallery" => false,
+            "snippet" => {
+                if let Some(slug) = tag.attrs.get("slug") {
+                    self.snippets.push((slug.clone(), line));
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        let sources: Vec<&str> = if tag.name == "gallery" {
+            src.split(',').map(str::trim).collect()
+        } else {
+            vec![src]
+        };
+        for source in sources.into_iter().filter(|s| !s.is_empty()) {
+            if !source.contains("://") {
+                let filename = ShortcodeProcessor::normalize_src(source);
+                self.media.push((filename.to_string(), line));
+            }
+        }
+        let alt = tag.attrs.get("alt").map(|s| s.trim()).unwrap_or("");
+        if needs_alt && !src.is_empty() && alt.is_empty() {
+            self.missing_alt.push((src.to_string(), line));
+        }
+    }
+}
+
+/// What a site-relative path points at, if it's a route the checks know.
+enum LinkTarget<'a> {
+    Content {
+        content_type: ContentType,
+        entry_type: Option<&'a str>,
+        slug: &'a str,
+    },
+    Tag(&'a str),
+    Series(&'a str),
+}
+
+fn link_target<'a>(
+    path: &'a str,
+    content_types: &'a [ContentTypeConfig],
+) -> Option<LinkTarget<'a>> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let content = |content_type, slug| LinkTarget::Content {
+        content_type,
+        entry_type: None,
+        slug,
+    };
+    match segments.as_slice() {
+        ["posts", slug] | [_, "posts", slug] => Some(content(ContentType::Post, slug)),
+        ["pages", slug] | [_, "pages", slug] => Some(content(ContentType::Page, slug)),
+        ["tags", slug] => Some(LinkTarget::Tag(slug)),
+        ["series", slug] => Some(LinkTarget::Series(slug)),
+        [prefix, slug] => content_types
+            .iter()
+            .find(|def| def.prefix() == *prefix)
+            .map(|def| LinkTarget::Content {
+                content_type: ContentType::Entry,
+                entry_type: Some(&def.name),
+                slug,
+            }),
+        _ => None,
+    }
+}
+
+fn check_link(
+    db: &Database,
+    content_types: &[ContentTypeConfig],
+    path: &str,
+    line: usize,
+) -> Result<Option<Issue>> {
+    let broken = |message: String| {
+        Some(Issue::new(
+            Severity::Error,
+            "broken-link",
+            message,
+            Some(line),
+        ))
+    };
+    let conn = db.get()?;
+
+    let issue = match link_target(path, content_types) {
+        Some(LinkTarget::Content {
+            content_type,
+            entry_type,
+            slug,
+        }) => {
+            let found: Option<(String, Option<String>, String)> = conn
+                .query_row(
+                    "SELECT content_type, entry_type, status FROM content WHERE slug = ?1",
+                    [slug],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?;
+            let kind = entry_type.unwrap_or(match content_type {
+                ContentType::Post => "post",
+                _ => "page",
+            });
+            match found {
+                None => broken(format!("{} links to a {} that doesn't exist", path, kind)),
+                Some((found_type, found_entry_type, _))
+                    if found_type != content_type.to_string()
+                        || found_entry_type.as_deref() != entry_type =>
+                {
+                    broken(format!(
+                        "{} links to \"{}\", which is not a {}",
+                        path, slug, kind
+                    ))
+                }
+                Some((_, _, status)) if status != ContentStatus::Published.to_string() => {
+                    Some(Issue::new(
+                        Severity::Warning,
+                        "broken-link",
+                        format!("{} links to a {} that is {}", path, kind, status),
+                        Some(line),
+                    ))
+                }
+                Some(_) => None,
+            }
+        }
+        Some(LinkTarget::Tag(slug)) => {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM tags WHERE slug = ?1)",
+                [slug],
+                |row| row.get(0),
+            )?;
+            if exists {
+                None
+            } else {
+                broken(format!("{} links to a tag that doesn't exist", path))
+            }
+        }
+        Some(LinkTarget::Series(slug)) => {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM content_series WHERE slug = ?1)",
+                [slug],
+                |row| row.get(0),
+            )?;
+            if exists {
+                None
+            } else {
+                broken(format!("{} links to a series that doesn't exist", path))
+            }
+        }
+        None => None,
+    };
+    Ok(issue)
+}
+
+/// Whether `filename` is in the media library, either as uploaded or as one
+/// of the WebP variants generated from an upload.
+fn media_exists(db: &Database, filename: &str) -> Result<bool> {
+    let conn = db.get()?;
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM media WHERE filename = ?1)",
+        [filename],
+        |row| row.get(0),
+    )?;
+    if exists || !VARIANT_REGEX.is_match(filename) {
+        return Ok(exists);
+    }
+    let base = VARIANT_REGEX.replace(filename, "");
+    let exists = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM media WHERE substr(filename, 1, length(?1) + 1) = ?1 || '.')",
+        [base.as_ref()],
+        |row| row.get(0),
+    )?;
+    Ok(exists)
+}
+
+fn snippet_exists(db: &Database, slug: &str) -> Result<bool> {
+    Ok(matches!(
+        content::get_content_by_slug(db, slug)?,
+        Some(item) if item.content.content_type == ContentType::Snippet
+    ))
+}
+
+fn check_duplicate_title(db: &Database, draft: &Draft) -> Result<Option<Issue>> {
+    let title = draft.title.trim();
+    if title.is_empty() {
+        return Ok(None);
+    }
+    let conn = db.get()?;
+    // Translations of an item share its title by design
+    let duplicate: Option<(String, String)> = conn
+        .query_row(
+            "SELECT slug, content_type FROM content
+             WHERE title = ?1 COLLATE NOCASE
+               AND content_type != 'snippet'
+               AND id != ?2
+               AND (translation_group IS NULL OR translation_group IS NOT
+                    (SELECT translation_group FROM content WHERE id = ?2))
+             ORDER BY id LIMIT 1",
+            rusqlite::params![title, draft.id.unwrap_or(0)],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    Ok(duplicate.map(|(slug, content_type)| {
+        Issue::new(
+            Severity::Warning,
+            "duplicate-title",
+            format!("The {} \"{}\" has the same title", content_type, slug),
+            None,
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateContent;
+    use crate::services::markdown::MarkdownRenderer;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    fn setup_test_db() -> Database {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let db = Database::open_memory(&format!("lint_test_{}", id)).unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    fn create(
+        db: &Database,
+        title: &str,
+        slug: &str,
+        content_type: ContentType,
+        body: &str,
+    ) -> i64 {
+        content::create_content(
+            db,
+            &MarkdownRenderer::new(),
+            &[],
+            CreateContent {
+                title: title.to_string(),
+                slug: Some(slug.to_string()),
+                content_type,
+                body_markdown: body.to_string(),
+                status: ContentStatus::Published,
+                scheduled_at: None,
+                excerpt: None,
+                featured_image: None,
+                tags: vec![],
+                metadata: None,
+                entry_type: None,
+                fields: None,
+            },
+            None,
+            200,
+        )
+        .unwrap()
+    }
+
+    fn rules(issues: &[Issue]) -> Vec<&str> {
+        issues.iter().map(|i| i.rule.as_str()).collect()
+    }
+
+    #[test]
+    fn test_links_media_and_snippets() {
+        let db = setup_test_db();
+        create(&db, "Existing", "existing", ContentType::Post, "Hi");
+        create(&db, "Footer", "footer", ContentType::Snippet, "Bye");
+        db.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO media (filename, original_name, mime_type, size_bytes) VALUES ('abc.jpg', 'a.jpg', 'image/jpeg', 1)",
+                [],
+            )
+            .unwrap();
+
+        let draft = Draft {
+            title: "Links".to_string(),
+            body_markdown: [
+                "[ok](/posts/existing) and [gone](/posts/missing#top) and [page](/pages/existing)",
+                "",
+                "![A photo](/media/abc-800w.webp) [image src=\"nope.png\" alt=\"Nope\"]",
+                "",
+                "[snippet slug=\"footer\"] [snippet slug=\"header\"] [external](https://example.com/posts/x)",
+            ]
+            .join("\n"),
+            ..Default::default()
+        };
+        let issues = check(&db, &[], &draft).unwrap();
+
+        assert_eq!(
+            rules(&issues),
+            vec![
+                "broken-link",
+                "broken-link",
+                "missing-media",
+                "unresolved-snippet"
+            ]
+        );
+        assert!(issues.iter().all(|i| i.severity == Severity::Error));
+        assert!(issues[0].message.contains("/posts/missing"));
+        assert!(issues[1].message.contains("not a page"));
+        assert_eq!(issues[2].line, Some(3));
+        assert_eq!(issues[3].line, Some(5));
+    }
+
+    #[test]
+    fn test_alt_text_and_headings() {
+        let db = setup_test_db();
+        let draft = Draft {
+            title: "Structure".to_string(),
+            body_markdown: [
+                "### Too deep",
+                "",
+                "## Fine",
+                "",
+                "#### Skipped",
+                "",
+                "![](https://example.com/a.png) <img src=\"https://example.com/b.png\">",
+                "<img src=\"https://example.com/c.png\" alt=\"\"> [figure src=\"https://example.com/d.png\"]",
+            ]
+            .join("\n"),
+            ..Default::default()
+        };
+        let issues = check(&db, &[], &draft).unwrap();
+
+        assert_eq!(
+            rules(&issues),
+            vec![
+                "heading-skip",
+                "heading-skip",
+                "missing-alt",
+                "missing-alt",
+                "missing-alt"
+            ]
+        );
+        assert!(issues.iter().all(|i| i.severity == Severity::Warning));
+        assert_eq!(issues[0].message, "Heading level jumps from h1 to h3");
+        assert_eq!(issues[1].line, Some(5));
+        assert_eq!(issues[4].line, Some(8));
+
+        let snippet = Draft {
+            content_type: ContentType::Snippet,
+            ..draft
+        };
+        assert_eq!(check(&db, &[], &snippet).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_titles_and_recorded_counts() {
+        let db = setup_test_db();
+        let id = create(&db, "Hello World", "hello", ContentType::Post, "Body");
+        create(&db, "hello world", "hello-again", ContentType::Page, "Body");
+
+        let long = "x".repeat(MAX_SEO_DESCRIPTION_LENGTH + 1);
+        let draft = Draft {
+            id: Some(id),
+            title: "Hello World".to_string(),
+            excerpt: Some(long.clone()),
+            meta_title: Some(long),
+            ..Default::default()
+        };
+        let issues = check(&db, &[], &draft).unwrap();
+        assert_eq!(
+            rules(&issues),
+            vec!["duplicate-title", "title-length", "description-length"]
+        );
+        assert!(issues[0].message.contains("hello-again"));
+        assert!(issues[1].message.starts_with("Meta title"));
+
+        // A generated excerpt isn't the author's to shorten
+        let item = content::get_content_by_id(&db, id).unwrap().unwrap();
+        assert_eq!(Draft::from_content(&item.content, 200).excerpt, None);
+
+        record(&db, id, &issues).unwrap();
+        let counts = recorded_counts(&db).unwrap();
+        assert_eq!(
+            counts[&id],
+            CheckCounts {
+                errors: 0,
+                warnings: 3
+            }
+        );
+        record(&db, id, &[]).unwrap();
+        assert!(recorded_counts(&db).unwrap().is_empty());
+    }
+}
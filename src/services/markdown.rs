@@ -1,10 +1,16 @@
+use super::diagrams;
+use super::embeds;
+use super::highlight;
+use super::math;
+use super::shortcodes::{self, ShortcodeRegistry};
+use crate::models::TocEntry;
+use crate::Config;
 use ammonia::Builder;
 use once_cell::sync::Lazy;
 use pulldown_cmark::{html, Options, Parser};
 use regex::Regex;
-use std::collections::HashMap;
-use syntect::highlighting::ThemeSet;
-use syntect::html::highlighted_html_for_string;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use syntect::parsing::SyntaxSet;
 
 // Statically compiled regexes - avoids runtime panic and improves performance
@@ -14,6 +20,59 @@ static ATTR_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r#"(\w+)(?:="([^"]*)")?|(\w+)"#).expect("Invalid attribute regex pattern")
 });
 
+const CALLOUT_TYPES: &[&str] = &["note", "tip", "info", "warning", "danger"];
+
+/// Left by the `[toc]` shortcode and replaced with the table of contents once
+/// every heading has been seen. Comments are stripped by the sanitizer, so an
+/// unreplaced placeholder never reaches a page.
+const TOC_PLACEHOLDER: &str = "<!-- pebble:toc -->";
+
+pub(crate) fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_HEADING_ATTRIBUTES
+        | Options::ENABLE_MATH
+}
+
+fn parse_attributes(attrs_str: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+
+    for cap in ATTR_REGEX.captures_iter(attrs_str) {
+        if let Some(name) = cap.get(1) {
+            let value = cap.get(2).map(|m| m.as_str()).unwrap_or("true");
+            attrs.insert(name.as_str().to_string(), value.to_string());
+        } else if let Some(flag) = cap.get(3) {
+            attrs.insert(flag.as_str().to_string(), "true".to_string());
+        }
+    }
+
+    attrs
+}
+
+/// A shortcode tag found in Markdown source.
+#[derive(Debug, Clone)]
+pub struct ShortcodeTag {
+    pub name: String,
+    pub attrs: HashMap<String, String>,
+    /// Byte offset of the opening `[` in the source.
+    pub offset: usize,
+}
+
+/// Every opening shortcode tag in `markdown`, including ones nested in paired
+/// bodies. Link text such as `[label]` matches too; callers filter by name.
+pub fn find_shortcodes(markdown: &str) -> Vec<ShortcodeTag> {
+    SHORTCODE_REGEX
+        .captures_iter(markdown)
+        .map(|caps| ShortcodeTag {
+            name: caps[1].to_string(),
+            attrs: parse_attributes(caps.get(2).map(|m| m.as_str()).unwrap_or("")),
+            offset: caps.get(0).expect("capture 0 always exists").start(),
+        })
+        .collect()
+}
+
 /// Shortcode processor for embedding media and other dynamic content.
 ///
 /// Supported shortcodes:
@@ -22,7 +81,19 @@ static ATTR_REGEX: Lazy<Regex> = Lazy::new(|| {
 /// - `[video src="filename.mp4" controls]` - Embeds video player
 /// - `[audio src="filename.mp3" controls]` - Embeds audio player
 /// - `[gallery src="file1.jpg,file2.jpg,file3.jpg"]` - Embeds a gallery of images
-pub struct ShortcodeProcessor;
+/// - `[callout type="warning" title="..."]...[/callout]` - Highlighted box (alias `admonition`)
+/// - `[details summary="..."]...[/details]` - Collapsible section
+/// - `[figure src="chart.png" caption="..."]` - Image with a caption
+/// - `[button href="/contact"]Label[/button]` - Link styled as a button
+/// - `[embed url="https://youtu.be/..."]` - Click-to-load third-party embed
+/// - `[toc title="Contents"]` - Table of contents of the document's headings
+///
+/// Shortcodes that take a body use paired syntax, `[name ...]body[/name]`; the
+/// body is Markdown. Site templates in the processor's [`ShortcodeRegistry`]
+/// are tried first, so a site can replace any built-in.
+pub struct ShortcodeProcessor {
+    registry: Arc<ShortcodeRegistry>,
+}
 
 impl Default for ShortcodeProcessor {
     fn default() -> Self {
@@ -31,43 +102,107 @@ impl Default for ShortcodeProcessor {
 }
 
 impl ShortcodeProcessor {
+    /// A processor with only the built-in shortcodes.
     pub fn new() -> Self {
-        Self
+        Self::with_registry(Arc::new(ShortcodeRegistry::default()))
+    }
+
+    pub fn with_registry(registry: Arc<ShortcodeRegistry>) -> Self {
+        Self { registry }
     }
 
     /// Process all shortcodes in the content and return the processed content.
     pub fn process(&self, content: &str) -> String {
-        SHORTCODE_REGEX
-            .replace_all(content, |caps: &regex::Captures| {
-                let name = &caps[1];
-                let attrs_str = caps.get(2).map(|m| m.as_str()).unwrap_or("");
-                let attrs = self.parse_attributes(attrs_str);
-
-                match name {
-                    "media" => self.render_media(&attrs),
-                    "image" | "img" => self.render_image(&attrs),
-                    "video" => self.render_video(&attrs),
-                    "audio" => self.render_audio(&attrs),
-                    "gallery" => self.render_gallery(&attrs),
-                    _ => caps[0].to_string(), // Unknown shortcode, leave as-is
-                }
-            })
-            .to_string()
-    }
+        let mut output = String::with_capacity(content.len());
+        let mut rest = content;
+
+        while let Some(caps) = SHORTCODE_REGEX.captures(rest) {
+            let whole = caps.get(0).expect("capture 0 always exists");
+            output.push_str(&rest[..whole.start()]);
 
-    fn parse_attributes(&self, attrs_str: &str) -> HashMap<String, String> {
-        let mut attrs = HashMap::new();
+            let name = &caps[1];
+            let attrs_str = caps.get(2).map(|m| m.as_str()).unwrap_or("");
+            let attrs = parse_attributes(attrs_str);
 
-        for cap in ATTR_REGEX.captures_iter(attrs_str) {
-            if let Some(name) = cap.get(1) {
-                let value = cap.get(2).map(|m| m.as_str()).unwrap_or("true");
-                attrs.insert(name.as_str().to_string(), value.to_string());
-            } else if let Some(flag) = cap.get(3) {
-                attrs.insert(flag.as_str().to_string(), "true".to_string());
+            let after = &rest[whole.end()..];
+            let paired = if self.accepts_body(name) {
+                find_closing_tag(after, name)
+            } else {
+                None
+            };
+            let (body, remaining) = match paired {
+                Some((body, consumed)) => (Some(body), &after[consumed..]),
+                None => (None, after),
+            };
+
+            match self.render_shortcode(name, &attrs, body) {
+                Some(html) => output.push_str(&html),
+                None => output.push_str(whole.as_str()), // Unknown shortcode, leave as-is
             }
+            rest = remaining;
         }
 
-        attrs
+        output.push_str(rest);
+        output
+    }
+
+    fn accepts_body(&self, name: &str) -> bool {
+        self.registry.has_template(name)
+            || matches!(
+                name,
+                "callout" | "admonition" | "details" | "figure" | "button"
+            )
+    }
+
+    fn render_shortcode(
+        &self,
+        name: &str,
+        attrs: &HashMap<String, String>,
+        body: Option<&str>,
+    ) -> Option<String> {
+        if self.registry.has_template(name) {
+            let body = body.map(|b| (b, self.render_body(b)));
+            return Some(compact_html(&self.registry.render(name, attrs, body)));
+        }
+
+        let html = match name {
+            "media" => self.render_media(attrs),
+            "image" | "img" => self.render_image(attrs),
+            "video" => self.render_video(attrs),
+            "audio" => self.render_audio(attrs),
+            "gallery" => self.render_gallery(attrs),
+            "callout" | "admonition" => self.render_callout(attrs, body),
+            "details" => self.render_details(attrs, body),
+            "figure" => self.render_figure(attrs, body),
+            "button" => self.render_button(attrs, body),
+            "embed" => self.render_embed(attrs),
+            "toc" => self.render_toc(attrs),
+            _ => return None,
+        };
+        Some(html)
+    }
+
+    /// Render a shortcode body as Markdown, expanding nested shortcodes first.
+    fn render_body(&self, body: &str) -> String {
+        let processed = self.process(body.trim());
+        let mut html_output = String::new();
+        html::push_html(
+            &mut html_output,
+            Parser::new_ext(&processed, markdown_options()),
+        );
+        html_output
+    }
+
+    /// Render a one-line body without the wrapping paragraph.
+    fn render_inline_body(&self, body: &str) -> String {
+        let html = self.render_body(body);
+        let trimmed = html.trim();
+        trimmed
+            .strip_prefix("<p>")
+            .and_then(|s| s.strip_suffix("</p>"))
+            .filter(|s| !s.contains("<p>"))
+            .unwrap_or(trimmed)
+            .to_string()
     }
 
     fn render_media(&self, attrs: &HashMap<String, String>) -> String {
@@ -93,7 +228,7 @@ impl ShortcodeProcessor {
     }
 
     /// Normalize the src path by removing any leading /media/ prefix
-    fn normalize_src(src: &str) -> &str {
+    pub fn normalize_src(src: &str) -> &str {
         src.trim_start_matches("/media/")
             .trim_start_matches("media/")
     }
@@ -342,13 +477,192 @@ Your browser does not support the audio tag.
         gallery_html.push_str("\n</div>");
         gallery_html
     }
+
+    fn render_callout(&self, attrs: &HashMap<String, String>, body: Option<&str>) -> String {
+        let kind = attrs
+            .get("type")
+            .map(|t| t.to_lowercase())
+            .filter(|t| CALLOUT_TYPES.contains(&t.as_str()))
+            .unwrap_or_else(|| "note".to_string());
+        let title = attrs
+            .get("title")
+            .map(|t| format!(r#"<p class="callout-title">{}</p>"#, html_escape(t)))
+            .unwrap_or_default();
+        let content = body.map(|b| self.render_body(b)).unwrap_or_default();
+
+        compact_html(&format!(
+            r#"<div class="callout callout-{}">
+{}
+<div class="callout-body">
+{}
+</div>
+</div>"#,
+            kind, title, content
+        ))
+    }
+
+    fn render_details(&self, attrs: &HashMap<String, String>, body: Option<&str>) -> String {
+        let Some(body) = body else {
+            return "<!-- details shortcode: missing body, use [details]...[/details] -->"
+                .to_string();
+        };
+        let summary = attrs
+            .get("summary")
+            .map(|s| s.as_str())
+            .unwrap_or("Details");
+        let open = if attrs.contains_key("open") {
+            " open"
+        } else {
+            ""
+        };
+
+        compact_html(&format!(
+            r#"<details class="shortcode-details"{}>
+<summary>{}</summary>
+{}
+</details>"#,
+            open,
+            html_escape(summary),
+            self.render_body(body)
+        ))
+    }
+
+    fn render_figure(&self, attrs: &HashMap<String, String>, body: Option<&str>) -> String {
+        let Some(raw_src) = attrs.get("src") else {
+            return "<!-- figure shortcode: missing src attribute -->".to_string();
+        };
+        let src = if raw_src.starts_with("http://") || raw_src.starts_with("https://") {
+            raw_src.clone()
+        } else {
+            format!("/media/{}", Self::normalize_src(raw_src))
+        };
+        let alt = attrs.get("alt").map(|s| s.as_str()).unwrap_or("");
+        let width = attrs
+            .get("width")
+            .map(|w| format!(r#" width="{}""#, html_escape(w)))
+            .unwrap_or_default();
+        let caption = match body.filter(|b| !b.trim().is_empty()) {
+            Some(b) => self.render_inline_body(b),
+            None => attrs
+                .get("caption")
+                .map(|c| html_escape(c))
+                .unwrap_or_default(),
+        };
+        let caption = if caption.is_empty() {
+            String::new()
+        } else {
+            format!("\n<figcaption>{}</figcaption>", caption)
+        };
+
+        format!(
+            r#"<figure class="media-figure">
+<img src="{}" alt="{}" class="media-image" loading="lazy"{}>{}
+</figure>"#,
+            html_escape(&src),
+            html_escape(alt),
+            width,
+            caption
+        )
+    }
+
+    fn render_button(&self, attrs: &HashMap<String, String>, body: Option<&str>) -> String {
+        let Some(href) = attrs.get("href").or_else(|| attrs.get("url")) else {
+            return "<!-- button shortcode: missing href attribute -->".to_string();
+        };
+        let style = match attrs.get("style").map(|s| s.as_str()) {
+            Some("secondary") => "secondary",
+            _ => "primary",
+        };
+        let label = match body.filter(|b| !b.trim().is_empty()) {
+            Some(b) => self.render_inline_body(b),
+            None => html_escape(attrs.get("label").map(|s| s.as_str()).unwrap_or(href)),
+        };
+
+        format!(
+            r#"<a href="{}" class="shortcode-button shortcode-button-{}">{}</a>"#,
+            html_escape(href),
+            style,
+            label
+        )
+    }
+
+    /// The headings aren't known until the whole document is parsed, so this
+    /// leaves a placeholder for [`MarkdownRenderer::render_with_toc`] to fill.
+    fn render_toc(&self, attrs: &HashMap<String, String>) -> String {
+        let title = attrs
+            .get("title")
+            .map(|t| format!(r#"<p class="toc-title">{}</p>"#, html_escape(t)))
+            .unwrap_or_default();
+        format!(r#"<nav class="toc">{}{}</nav>"#, title, TOC_PLACEHOLDER)
+    }
+
+    fn render_embed(&self, attrs: &HashMap<String, String>) -> String {
+        let Some(url) = attrs.get("url").or_else(|| attrs.get("src")) else {
+            return "<!-- embed shortcode: missing url attribute -->".to_string();
+        };
+        match embeds::Embed::parse(url) {
+            Some(embed) => embed.render(attrs.get("title").map(|s| s.as_str())),
+            None => format!(
+                r#"<a href="{}">{}</a>"#,
+                html_escape(url),
+                html_escape(attrs.get("title").unwrap_or(url))
+            ),
+        }
+    }
+}
+
+/// Find the `[/name]` closing a paired shortcode, skipping nested pairs of the
+/// same name. Returns the body and the number of bytes consumed including the
+/// closing tag.
+fn find_closing_tag<'a>(text: &'a str, name: &str) -> Option<(&'a str, usize)> {
+    let open = format!("[{}", name);
+    let close = format!("[/{}]", name);
+    let mut depth = 0usize;
+    let mut pos = 0;
+
+    while let Some(offset) = text[pos..].find('[') {
+        let at = pos + offset;
+        let tail = &text[at..];
+        if tail.starts_with(&close) {
+            if depth == 0 {
+                return Some((&text[..at], at + close.len()));
+            }
+            depth -= 1;
+            pos = at + close.len();
+        } else if tail.starts_with(&open)
+            && matches!(tail[open.len()..].chars().next(), Some(' ' | ']'))
+        {
+            depth += 1;
+            pos = at + open.len();
+        } else {
+            pos = at + 1;
+        }
+    }
+
+    None
+}
+
+/// Drop blank lines so block output stays a single Markdown HTML block;
+/// a blank line would end the block and hand the rest back to the parser.
+fn compact_html(html: &str) -> String {
+    html.lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// HTML and table of contents from [`MarkdownRenderer::render_with_toc`].
+#[derive(Debug, Clone)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
 }
 
 pub struct MarkdownRenderer {
     syntax_set: SyntaxSet,
-    theme_set: ThemeSet,
     sanitizer: Builder<'static>,
     shortcode_processor: ShortcodeProcessor,
+    heading_anchors: bool,
 }
 
 impl Default for MarkdownRenderer {
@@ -358,7 +672,26 @@ impl Default for MarkdownRenderer {
 }
 
 impl MarkdownRenderer {
+    /// A renderer with only the built-in shortcodes and no heading anchors.
     pub fn new() -> Self {
+        Self::with_shortcodes(Arc::new(ShortcodeRegistry::default()))
+    }
+
+    /// A renderer for the site configured by `config`: its shortcodes
+    /// directory and `content.heading_anchors`.
+    pub fn for_site(config: &Config) -> anyhow::Result<Self> {
+        let registry = shortcodes::load_site_shortcodes(config)?;
+        Ok(Self::with_shortcodes(Arc::new(registry))
+            .with_heading_anchors(config.content.heading_anchors))
+    }
+
+    /// Add a permalink anchor to each heading.
+    pub fn with_heading_anchors(mut self, enabled: bool) -> Self {
+        self.heading_anchors = enabled;
+        self
+    }
+
+    pub fn with_shortcodes(registry: Arc<ShortcodeRegistry>) -> Self {
         let mut tags = ammonia::Builder::default().clone_tags();
         tags.insert("pre");
         tags.insert("code");
@@ -379,9 +712,47 @@ impl MarkdownRenderer {
         tags.insert("video");
         tags.insert("audio");
         tags.insert("iframe");
+        // Shortcode tags
+        tags.insert("details");
+        tags.insert("summary");
+        tags.insert("nav");
+        // MathML from `$...$` math
+        tags.extend([
+            "math",
+            "semantics",
+            "annotation",
+            "mrow",
+            "mi",
+            "mn",
+            "mo",
+            "mtext",
+            "mspace",
+            "msup",
+            "msub",
+            "msubsup",
+            "munder",
+            "mover",
+            "munderover",
+            "mfrac",
+            "msqrt",
+            "mroot",
+            "mtable",
+            "mtr",
+            "mtd",
+            "mstyle",
+            "merror",
+        ]);
+        // SVG from diagram blocks
+        tags.extend([
+            "svg", "g", "rect", "ellipse", "circle", "path", "line", "polygon", "polyline", "text",
+            "tspan",
+        ]);
 
         let mut attrs = ammonia::Builder::default().clone_tag_attributes();
-        attrs.insert("span", ["style"].iter().cloned().collect());
+        // Classes on spans and code elements are checked by the attribute
+        // filter below, which also accepts every highlighter token class
+        attrs.insert("span", ["class"].iter().cloned().collect());
+        attrs.insert("code", ["class"].iter().cloned().collect());
         attrs.insert(
             "input",
             ["type", "checked", "disabled"].iter().cloned().collect(),
@@ -430,73 +801,192 @@ impl MarkdownRenderer {
                 .collect(),
         );
         attrs.insert("div", ["style"].iter().cloned().collect());
+        attrs.insert("details", ["open"].iter().cloned().collect());
+        attrs
+            .entry("a")
+            .or_default()
+            .extend(["aria-hidden", "tabindex"]);
+        for (tag, allowed) in [
+            ("math", &["display"][..]),
+            ("annotation", &["encoding"]),
+            ("mi", &["mathvariant"]),
+            ("mtext", &["mathvariant"]),
+            (
+                "mo",
+                &["fence", "stretchy", "largeop", "movablelimits", "form"],
+            ),
+            ("mfrac", &["linethickness"]),
+            ("mover", &["accent"]),
+            ("munder", &["accentunder"]),
+            ("mstyle", &["displaystyle", "scriptlevel"]),
+            ("mspace", &["width"]),
+            ("mtable", &["columnalign", "rowspacing", "columnspacing"]),
+        ] {
+            attrs.insert(tag, allowed.iter().copied().collect());
+        }
+        const SVG_PAINT: &[&str] = &["fill", "stroke", "stroke-width", "stroke-dasharray"];
+        attrs.insert(
+            "svg",
+            ["viewBox", "width", "height", "role", "aria-label"]
+                .iter()
+                .cloned()
+                .collect(),
+        );
+        for (tag, allowed) in [
+            ("g", &[][..]),
+            ("rect", &["x", "y", "width", "height", "rx", "ry"]),
+            ("ellipse", &["cx", "cy", "rx", "ry"]),
+            ("circle", &["cx", "cy", "r"]),
+            ("path", &["d"]),
+            ("line", &["x1", "y1", "x2", "y2"]),
+            ("polygon", &["points"]),
+            ("polyline", &["points"]),
+            (
+                "text",
+                &[
+                    "x",
+                    "y",
+                    "dy",
+                    "text-anchor",
+                    "dominant-baseline",
+                    "font-size",
+                ],
+            ),
+            ("tspan", &["x", "y", "dy"]),
+        ] {
+            attrs.insert(tag, allowed.iter().chain(SVG_PAINT).copied().collect());
+        }
+        attrs.insert(
+            "figure",
+            ["data-embed-src", "data-embed-title"]
+                .iter()
+                .cloned()
+                .collect(),
+        );
 
         let mut sanitizer = Builder::default();
         sanitizer
             .tags(tags)
             .tag_attributes(attrs)
+            .add_allowed_classes("pre", &["code-block", "code-numbered", "code-unnumbered"])
             .add_allowed_classes(
-                "code",
+                "figure",
                 &[
-                    "language-rust",
-                    "language-python",
-                    "language-javascript",
-                    "language-typescript",
-                    "language-go",
-                    "language-c",
-                    "language-cpp",
-                    "language-java",
-                    "language-html",
-                    "language-css",
-                    "language-json",
-                    "language-yaml",
-                    "language-toml",
-                    "language-sql",
-                    "language-bash",
-                    "language-shell",
-                    "language-markdown",
+                    "media-figure",
+                    "media-pdf",
+                    "embed",
+                    "embed-youtube",
+                    "embed-vimeo",
+                    "embed-mastodon",
+                    "embed-gist",
+                    "embed-codepen",
+                    "diagram",
+                    "diagram-dot",
+                    "diagram-mermaid",
+                    "diagram-error",
                 ],
             )
-            .add_allowed_classes("pre", &["code-block"])
-            .add_allowed_classes("figure", &["media-figure", "media-pdf"])
-            .add_allowed_classes("img", &["media-image", "gallery-image"])
+            .add_allowed_classes("figcaption", &["embed-notice"])
+            .add_allowed_classes("img", &["media-image", "gallery-image", "embed-thumbnail"])
             .add_allowed_classes("video", &["media-video"])
             .add_allowed_classes("audio", &["media-audio"])
             .add_allowed_classes("iframe", &["media-pdf-embed"])
-            .add_allowed_classes("div", &["media-gallery"])
-            .add_allowed_classes("a", &["media-link", "gallery-item"])
+            .add_allowed_classes(
+                "div",
+                &[
+                    "media-gallery",
+                    "callout",
+                    "callout-note",
+                    "callout-tip",
+                    "callout-info",
+                    "callout-warning",
+                    "callout-danger",
+                    "callout-body",
+                ],
+            )
+            .add_allowed_classes("p", &["callout-title", "toc-title"])
+            .add_allowed_classes("nav", &["toc"])
+            .add_allowed_classes("details", &["shortcode-details"])
+            .add_allowed_classes(
+                "a",
+                &[
+                    "media-link",
+                    "gallery-item",
+                    "shortcode-button",
+                    "shortcode-button-primary",
+                    "shortcode-button-secondary",
+                    "embed-facade",
+                    "heading-anchor",
+                ],
+            )
             .link_rel(Some("noopener noreferrer"));
+        let mut span_classes: HashSet<&'static str> =
+            ["embed-play", "code-line", "code-line-highlight"]
+                .into_iter()
+                .collect();
+        // Classes written literally in site shortcode templates
+        for (tag, class) in registry.allowed_classes() {
+            if *tag == "span" {
+                span_classes.insert(class);
+            } else if *tag != "code" {
+                sanitizer.add_allowed_classes(*tag, std::iter::once(*class));
+            }
+        }
+        sanitizer.attribute_filter(move |element, attribute, value| {
+            let keep: fn(&HashSet<&str>, &str) -> bool = match (element, attribute) {
+                ("span", "class") => {
+                    |allowed, class| allowed.contains(class) || highlight::is_token_class(class)
+                }
+                ("code", "class") => |_, class| highlight::is_language_class(class),
+                _ => return Some(value.into()),
+            };
+            let classes: Vec<&str> = value
+                .split_ascii_whitespace()
+                .filter(|class| keep(&span_classes, class))
+                .collect();
+            if classes.is_empty() {
+                None
+            } else {
+                Some(classes.join(" ").into())
+            }
+        });
 
         Self {
             syntax_set: SyntaxSet::load_defaults_newlines(),
-            theme_set: ThemeSet::load_defaults(),
             sanitizer,
-            shortcode_processor: ShortcodeProcessor::new(),
+            shortcode_processor: ShortcodeProcessor::with_registry(registry),
+            heading_anchors: false,
         }
     }
 
     pub fn render(&self, markdown: &str) -> String {
-        // Process shortcodes first (before markdown parsing)
-        let processed = self.shortcode_processor.process(markdown);
+        self.render_with_toc(markdown).html
+    }
 
-        let options = Options::ENABLE_TABLES
-            | Options::ENABLE_FOOTNOTES
-            | Options::ENABLE_STRIKETHROUGH
-            | Options::ENABLE_TASKLISTS
-            | Options::ENABLE_HEADING_ATTRIBUTES;
+    /// Render Markdown to sanitized HTML, also returning its headings as a
+    /// nested table of contents.
+    pub fn render_with_toc(&self, markdown: &str) -> RenderedMarkdown {
+        // Process embeds and shortcodes first (before markdown parsing)
+        let processed = self
+            .shortcode_processor
+            .process(&embeds::auto_embed(markdown));
 
-        let parser = Parser::new_ext(&processed, options);
+        let parser = Parser::new_ext(&processed, markdown_options());
         let mut events: Vec<pulldown_cmark::Event> = Vec::new();
         let mut in_code_block = false;
         let mut code_lang = String::new();
         let mut code_content = String::new();
 
-        // Track heading state for adding IDs
-        let mut in_heading = false;
+        // Heading contents are buffered until the end of the heading, when
+        // its id can be derived from the full text
+        let mut heading: Option<(u8, Option<String>)> = None;
+        let mut heading_events = Vec::new();
         let mut heading_text = String::new();
+        let mut used_ids = HashSet::new();
+        let mut headings = Vec::new();
 
         for event in parser {
-            match event {
+            let event = match event {
                 pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(kind)) => {
                     in_code_block = true;
                     code_lang = match kind {
@@ -504,146 +994,109 @@ impl MarkdownRenderer {
                         _ => String::new(),
                     };
                     code_content.clear();
+                    continue;
                 }
                 pulldown_cmark::Event::End(pulldown_cmark::TagEnd::CodeBlock) => {
                     in_code_block = false;
-                    let highlighted = self.highlight_code(&code_content, &code_lang);
-                    events.push(pulldown_cmark::Event::Html(highlighted.into()));
+                    let lang = code_lang.split_whitespace().next().unwrap_or("");
+                    let html = if diagrams::is_diagram(lang) {
+                        render_diagram(lang, &code_content)
+                    } else {
+                        self.highlight_code(&code_content, &code_lang)
+                    };
+                    pulldown_cmark::Event::Html(html.into())
                 }
                 pulldown_cmark::Event::Text(text) if in_code_block => {
                     code_content.push_str(&text);
+                    continue;
+                }
+                pulldown_cmark::Event::InlineMath(tex) => {
+                    if heading.is_some() {
+                        heading_text.push_str(&tex);
+                    }
+                    pulldown_cmark::Event::InlineHtml(math::to_mathml(&tex, false).into())
                 }
-                // Handle headings to add ID attributes
+                pulldown_cmark::Event::DisplayMath(tex) => {
+                    pulldown_cmark::Event::Html(math::to_mathml(&tex, true).into())
+                }
+                event => event,
+            };
+
+            match event {
                 pulldown_cmark::Event::Start(pulldown_cmark::Tag::Heading {
-                    level,
-                    id,
-                    classes,
-                    attrs,
+                    level, id, ..
                 }) => {
-                    in_heading = true;
+                    heading = Some((level as u8, id.map(|id| id.to_string())));
                     heading_text.clear();
-                    // If the heading already has an ID from {#custom-id} syntax, use it
-                    if id.is_some() {
-                        events.push(pulldown_cmark::Event::Start(pulldown_cmark::Tag::Heading {
-                            level,
-                            id,
-                            classes,
-                            attrs,
-                        }));
-                        in_heading = false; // Don't process further, it already has an ID
-                    }
                 }
-                pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Heading(level)) => {
-                    if in_heading {
-                        // Generate slug from heading text
-                        let slug = slugify(&heading_text);
-                        let level_num = match level {
-                            pulldown_cmark::HeadingLevel::H1 => 1,
-                            pulldown_cmark::HeadingLevel::H2 => 2,
-                            pulldown_cmark::HeadingLevel::H3 => 3,
-                            pulldown_cmark::HeadingLevel::H4 => 4,
-                            pulldown_cmark::HeadingLevel::H5 => 5,
-                            pulldown_cmark::HeadingLevel::H6 => 6,
-                        };
-                        // Emit heading with ID as raw HTML
-                        let heading_html = format!(
-                            r#"<h{} id="{}">{}</h{}>"#,
-                            level_num,
-                            html_escape(&slug),
-                            html_escape(&heading_text),
-                            level_num
-                        );
-                        events.push(pulldown_cmark::Event::Html(heading_html.into()));
-                        in_heading = false;
+                pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Heading(_)) => {
+                    let Some((level, custom_id)) = heading.take() else {
+                        continue;
+                    };
+                    // A {#custom-id} is kept as written; generated ids get a
+                    // numeric suffix when an earlier heading has the same text
+                    let id = custom_id.unwrap_or_else(|| unique_id(&used_ids, &heading_text));
+                    used_ids.insert(id.clone());
+
+                    let mut inner = String::new();
+                    html::push_html(&mut inner, heading_events.drain(..));
+                    let anchor = if self.heading_anchors {
+                        format!(
+                            r##"<a class="heading-anchor" href="#{}" aria-hidden="true" tabindex="-1">#</a>"##,
+                            html_escape(&id)
+                        )
                     } else {
-                        events.push(pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Heading(
-                            level,
-                        )));
-                    }
+                        String::new()
+                    };
+                    let heading_html = format!(
+                        r#"<h{} id="{}">{}{}</h{}>"#,
+                        level,
+                        html_escape(&id),
+                        inner,
+                        anchor,
+                        level
+                    );
+                    events.push(pulldown_cmark::Event::Html(heading_html.into()));
+                    headings.push(TocEntry {
+                        level,
+                        text: heading_text.trim().to_string(),
+                        id,
+                        children: Vec::new(),
+                    });
                 }
-                pulldown_cmark::Event::Text(text) if in_heading => {
-                    heading_text.push_str(&text);
+                event if heading.is_some() => {
+                    if let pulldown_cmark::Event::Text(text) | pulldown_cmark::Event::Code(text) =
+                        &event
+                    {
+                        heading_text.push_str(text);
+                    }
+                    heading_events.push(event);
                 }
-                _ => events.push(event),
+                event => events.push(event),
             }
         }
 
+        let toc = nest_headings(headings);
         let mut html_output = String::new();
         html::push_html(&mut html_output, events.into_iter());
+        if html_output.contains(TOC_PLACEHOLDER) {
+            html_output = html_output.replace(TOC_PLACEHOLDER, &toc_html(&toc));
+        }
 
-        self.sanitizer.clean(&html_output).to_string()
-    }
-
-    fn highlight_code(&self, code: &str, lang: &str) -> String {
-        let syntax = self
-            .syntax_set
-            .find_syntax_by_token(lang)
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-
-        let theme = &self.theme_set.themes["base16-ocean.dark"];
-
-        match highlighted_html_for_string(code, &self.syntax_set, syntax, theme) {
-            Ok(html) => {
-                // syntect outputs <pre style="..."><span>...</span></pre>
-                // We need to strip the outer <pre> and just use the inner content
-                let inner = html
-                    .trim()
-                    .strip_prefix("<pre style=\"background-color:#2b303b;\">\n")
-                    .and_then(|s| s.strip_suffix("\n</pre>"))
-                    .or_else(|| {
-                        html.trim()
-                            .strip_prefix("<pre style=\"background-color:#2b303b;\">")
-                            .and_then(|s| s.strip_suffix("</pre>"))
-                    })
-                    .unwrap_or(&html);
-                format!(
-                    r#"<pre class="code-block"><code class="language-{}">{}</code></pre>"#,
-                    lang, inner
-                )
-            }
-            Err(_) => format!(
-                r#"<pre class="code-block"><code class="language-{}">{}</code></pre>"#,
-                lang,
-                html_escape(code)
-            ),
+        RenderedMarkdown {
+            html: self.sanitizer.clean(&html_output).to_string(),
+            toc,
         }
     }
 
+    fn highlight_code(&self, code: &str, info: &str) -> String {
+        let info = highlight::FenceInfo::parse(info);
+        highlight::highlight(&self.syntax_set, code, &info)
+            .unwrap_or_else(|_| highlight::code_block(&html_escape(code), &info))
+    }
+
     pub fn generate_excerpt(&self, markdown: &str, max_len: usize) -> String {
-        let text: String = markdown
-            .lines()
-            .filter(|line| {
-                let trimmed = line.trim();
-                !trimmed.is_empty()
-                    && !trimmed.starts_with('#')
-                    && !trimmed.starts_with("```")
-                    && !trimmed.starts_with('|')
-                    && !trimmed.starts_with("---")
-                    && !trimmed.starts_with("![")
-                    && !trimmed.starts_with("> ")
-            })
-            .collect::<Vec<_>>()
-            .join(" ");
-
-        let text = strip_markdown(&text);
-
-        let char_count = text.chars().count();
-        if char_count <= max_len {
-            text
-        } else {
-            let truncated: String = text.chars().take(max_len).collect();
-            let last_space_pos = truncated
-                .char_indices()
-                .rev()
-                .find(|(_, c)| *c == ' ')
-                .map(|(i, _)| i);
-
-            if let Some(pos) = last_space_pos {
-                format!("{}...", &truncated[..pos])
-            } else {
-                format!("{}...", truncated)
-            }
-        }
+        generate_excerpt(markdown, max_len)
     }
 
     /// Calculate estimated reading time in minutes based on word count.
@@ -666,6 +1119,73 @@ fn html_escape(s: &str) -> String {
         .replace('"', "&quot;")
 }
 
+/// Render a diagram block, falling back to its source with the reason when
+/// it cannot be laid out.
+fn render_diagram(lang: &str, source: &str) -> String {
+    diagrams::render(lang, source).unwrap_or_else(|err| {
+        format!(
+            r#"<figure class="diagram diagram-error">{}<figcaption>{}</figcaption></figure>"#,
+            highlight::code_block(&html_escape(source), &highlight::FenceInfo::default()),
+            html_escape(&err)
+        )
+    })
+}
+
+/// The slug of a heading's text, with `-1`, `-2`... appended if an earlier
+/// heading already uses it.
+fn unique_id(used: &HashSet<String>, text: &str) -> String {
+    let mut base = slugify(text);
+    if base.is_empty() {
+        base = "section".to_string();
+    }
+    if !used.contains(&base) {
+        return base;
+    }
+    let mut n = 1;
+    loop {
+        let id = format!("{}-{}", base, n);
+        if !used.contains(&id) {
+            return id;
+        }
+        n += 1;
+    }
+}
+
+/// Nest a flat list of headings so each holds the lower-level headings that
+/// follow it.
+fn nest_headings(headings: Vec<TocEntry>) -> Vec<TocEntry> {
+    fn insert(list: &mut Vec<TocEntry>, entry: TocEntry) {
+        match list.last_mut() {
+            Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+            _ => list.push(entry),
+        }
+    }
+
+    let mut toc = Vec::new();
+    for entry in headings {
+        insert(&mut toc, entry);
+    }
+    toc
+}
+
+fn toc_html(entries: &[TocEntry]) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+    let items: String = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                r##"<li><a href="#{}">{}</a>{}</li>"##,
+                html_escape(&entry.id),
+                html_escape(&entry.text),
+                toc_html(&entry.children)
+            )
+        })
+        .collect();
+    format!("<ul>{}</ul>", items)
+}
+
 /// Convert text to a URL-friendly slug for heading IDs
 fn slugify(text: &str) -> String {
     text.to_lowercase()
@@ -689,6 +1209,46 @@ fn slugify(text: &str) -> String {
         .join("-")
 }
 
+/// Plain-text excerpt of the first `max_len` characters of a Markdown body,
+/// cut at a word boundary.
+pub fn generate_excerpt(markdown: &str, max_len: usize) -> String {
+    let text: String = markdown
+        .lines()
+        .filter(|line| {
+            let trimmed = line.trim();
+            !trimmed.is_empty()
+                && !trimmed.starts_with('#')
+                && !trimmed.starts_with("```")
+                && !trimmed.starts_with('|')
+                && !trimmed.starts_with("---")
+                && !trimmed.starts_with("![")
+                && !trimmed.starts_with("> ")
+                && !trimmed.starts_with("[toc")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let text = strip_markdown(&text);
+
+    let char_count = text.chars().count();
+    if char_count <= max_len {
+        text
+    } else {
+        let truncated: String = text.chars().take(max_len).collect();
+        let last_space_pos = truncated
+            .char_indices()
+            .rev()
+            .find(|(_, c)| *c == ' ')
+            .map(|(i, _)| i);
+
+        if let Some(pos) = last_space_pos {
+            format!("{}...", &truncated[..pos])
+        } else {
+            format!("{}...", truncated)
+        }
+    }
+}
+
 fn strip_markdown(text: &str) -> String {
     let mut result = text.to_string();
 
@@ -819,4 +1379,14 @@ Command reference.
             output
         );
     }
+
+    #[test]
+    fn test_heading_anchors() {
+        let mut renderer = MarkdownRenderer::new();
+        renderer.heading_anchors = true;
+        let output = renderer.render("## Quick Start\n\n## Quick Start");
+        assert!(output.contains(
+            r##"<h2 id="quick-start-1">Quick Start<a class="heading-anchor" href="#quick-start-1" aria-hidden="true" tabindex="-1" rel="noopener noreferrer">#</a></h2>"##
+        ), "Output was: {}", output);
+    }
 }
@@ -0,0 +1,911 @@
+//! LaTeX math to MathML, rendered when content is saved so pages need no
+//! math JavaScript.
+//!
+//! Covers the commonly used part of LaTeX math: sub- and superscripts,
+//! fractions, roots, Greek letters and symbols, big operators, named
+//! functions, font and accent commands, `\left`/`\right` delimiters and the
+//! matrix, cases and aligned environments. An unknown command renders as an
+//! inline error rather than failing the whole formula.
+
+use std::fmt::Write;
+
+/// Render `tex` as a `<math>` element; `display` selects block layout (`$$`).
+pub fn to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = Parser {
+        chars: tex.chars().collect(),
+        pos: 0,
+        display,
+        variant: None,
+    };
+    let body = match parser.parse_expr(Stop::End) {
+        Ok(body) => body,
+        Err(e) => format!(
+            "<merror><mtext>{}: {}</mtext></merror>",
+            escape(&e),
+            escape(tex.trim())
+        ),
+    };
+    format!(
+        r#"<math{}><semantics><mrow>{}</mrow><annotation encoding="application/x-tex">{}</annotation></semantics></math>"#,
+        if display { r#" display="block""# } else { "" },
+        body,
+        escape(tex.trim())
+    )
+}
+
+/// Where an expression ends.
+#[derive(Clone, Copy, PartialEq)]
+enum Stop {
+    /// End of input
+    End,
+    /// A closing `}`
+    Brace,
+    /// A closing `]` (optional arguments)
+    Bracket,
+    /// `\right`
+    Right,
+    /// `&`, `\\` or `\end` inside an environment
+    Cell,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Ordinary,
+    /// A big operator or limit function; `limits` places scripts above and
+    /// below it in display math
+    LargeOp {
+        limits: bool,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Variant {
+    Normal,
+    Bold,
+    DoubleStruck,
+    Script,
+    Fraktur,
+    SansSerif,
+    Monospace,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+    variant: Option<Variant>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// The command at the current position, without consuming it.
+    fn peek_command(&self) -> Option<String> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        let rest = &self.chars[self.pos + 1..];
+        let letters: String = rest
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        if letters.is_empty() {
+            rest.first().map(|c| c.to_string())
+        } else {
+            Some(letters)
+        }
+    }
+
+    fn read_command(&mut self) -> String {
+        let name = self.peek_command().unwrap_or_default();
+        self.pos += 1 + name.chars().count();
+        name
+    }
+
+    fn parse_expr(&mut self, stop: Stop) -> Result<String, String> {
+        let mut out = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None if stop == Stop::End => break,
+                None => return Err("Unexpected end of formula".to_string()),
+                Some('}') if stop == Stop::Brace => break,
+                Some('}') => return Err("Unmatched }".to_string()),
+                Some(']') if stop == Stop::Bracket => break,
+                Some('&') if stop == Stop::Cell => break,
+                Some('&') => return Err("& outside an environment".to_string()),
+                Some('\\') => match self.peek_command().as_deref() {
+                    Some("right") if stop == Stop::Right => break,
+                    Some("right") => return Err("\\right without \\left".to_string()),
+                    Some("\\") | Some("end") if stop == Stop::Cell => break,
+                    Some("end") => return Err("\\end without \\begin".to_string()),
+                    Some("\\") => {
+                        self.read_command();
+                    }
+                    _ => out.push_str(&self.parse_scripted()?),
+                },
+                Some(_) => out.push_str(&self.parse_scripted()?),
+            }
+        }
+        Ok(out)
+    }
+
+    /// An atom with any sub/superscripts and primes attached to it.
+    fn parse_scripted(&mut self) -> Result<String, String> {
+        let (base, kind) = self.parse_atom()?;
+        let mut limits = matches!(kind, Kind::LargeOp { limits: true }) && self.display;
+        let mut sub = None;
+        let mut sup: Option<String> = None;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.parse_argument()?);
+                }
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.parse_argument()?);
+                }
+                Some('\'') => {
+                    let mut primes = String::new();
+                    while self.peek() == Some('\'') {
+                        self.pos += 1;
+                        primes.push('′');
+                    }
+                    let primes = format!("<mo>{}</mo>", primes);
+                    sup = Some(match sup {
+                        Some(existing) => format!("<mrow>{}{}</mrow>", primes, existing),
+                        None => primes,
+                    });
+                }
+                Some('\\') => match self.peek_command().as_deref() {
+                    Some("limits") => {
+                        self.read_command();
+                        limits = true;
+                    }
+                    Some("nolimits") => {
+                        self.read_command();
+                        limits = false;
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
+
+        let (under, over, both) = if limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base, sup),
+            (Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", both, base, sub, sup),
+        })
+    }
+
+    /// A single atom or a braced group, as taken by commands and scripts.
+    fn parse_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err("Missing argument".to_string()),
+            _ => Ok(self.parse_atom()?.0),
+        }
+    }
+
+    fn parse_group(&mut self, stop: Stop, close: char) -> Result<String, String> {
+        self.pos += 1;
+        let inner = self.parse_expr(stop)?;
+        if self.peek() != Some(close) {
+            return Err(format!("Missing {}", close));
+        }
+        self.pos += 1;
+        Ok(format!("<mrow>{}</mrow>", inner))
+    }
+
+    /// The raw text of a braced group, e.g. the argument of `\text`.
+    fn read_raw_group(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err("Expected {".to_string());
+        }
+        self.pos += 1;
+        let mut depth = 0;
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => {
+                    if let Some(next) = self.peek() {
+                        self.pos += 1;
+                        if "{}$%&#_ ".contains(next) {
+                            text.push(next);
+                        } else {
+                            text.push('\\');
+                            text.push(next);
+                        }
+                    }
+                }
+                '{' => {
+                    depth += 1;
+                    text.push(c);
+                }
+                '}' if depth == 0 => return Ok(text),
+                '}' => {
+                    depth -= 1;
+                    text.push(c);
+                }
+                _ => text.push(c),
+            }
+        }
+        Err("Missing }".to_string())
+    }
+
+    fn parse_atom(&mut self) -> Result<(String, Kind), String> {
+        self.skip_whitespace();
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err("Unexpected end of formula".to_string()),
+        };
+        let atom = match c {
+            '{' => self.parse_group(Stop::Brace, '}')?,
+            // A script with nothing before it attaches to an empty base
+            '^' | '_' => return Ok(("<mrow></mrow>".to_string(), Kind::Ordinary)),
+            '\\' => return self.parse_command(),
+            c if c.is_ascii_digit() || (c == '.' && self.next_is_digit()) => {
+                let mut number = String::new();
+                while let Some(d) = self.peek().filter(|d| d.is_ascii_digit() || *d == '.') {
+                    number.push(d);
+                    self.pos += 1;
+                }
+                let number: String = number.chars().map(|d| self.styled(d)).collect();
+                format!("<mn>{}</mn>", escape(&number))
+            }
+            c if c.is_alphabetic() => {
+                self.pos += 1;
+                self.identifier(c)
+            }
+            '~' => {
+                self.pos += 1;
+                r#"<mspace width="0.25em"></mspace>"#.to_string()
+            }
+            c => {
+                self.pos += 1;
+                match c {
+                    '-' => operator("−"),
+                    '*' => operator("∗"),
+                    c => operator(&c.to_string()),
+                }
+            }
+        };
+        Ok((atom, Kind::Ordinary))
+    }
+
+    fn next_is_digit(&self) -> bool {
+        self.chars
+            .get(self.pos + 1)
+            .is_some_and(|c| c.is_ascii_digit())
+    }
+
+    fn identifier(&self, c: char) -> String {
+        match self.variant {
+            Some(Variant::Normal) => format!(
+                r#"<mi mathvariant="normal">{}</mi>"#,
+                escape(&c.to_string())
+            ),
+            _ => format!("<mi>{}</mi>", escape(&self.styled(c).to_string())),
+        }
+    }
+
+    fn styled(&self, c: char) -> char {
+        match self.variant {
+            Some(variant) => styled_char(c, variant),
+            None => c,
+        }
+    }
+
+    fn with_variant(&mut self, variant: Variant) -> Result<String, String> {
+        let previous = self.variant.replace(variant);
+        let result = self.parse_argument();
+        self.variant = previous;
+        result
+    }
+
+    /// The delimiter after `\left`, `\right` or `\big`; `.` is an empty one.
+    fn read_delimiter(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let delimiter = match self.peek() {
+            Some('\\') => {
+                let name = self.read_command();
+                match name.as_str() {
+                    "{" | "lbrace" => "{".to_string(),
+                    "}" | "rbrace" => "}".to_string(),
+                    "|" | "Vert" => "‖".to_string(),
+                    "vert" => "|".to_string(),
+                    _ => match symbol(&name) {
+                        Some((text, _)) => text.to_string(),
+                        None => return Err(format!("Unknown delimiter \\{}", name)),
+                    },
+                }
+            }
+            Some('.') => {
+                self.pos += 1;
+                String::new()
+            }
+            Some(c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            None => return Err("Missing delimiter".to_string()),
+        };
+        Ok(delimiter)
+    }
+
+    fn parse_command(&mut self) -> Result<(String, Kind), String> {
+        let name = self.read_command();
+        let atom = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument()?;
+                let denominator = self.parse_argument()?;
+                let fraction = format!("<mfrac>{}{}</mfrac>", numerator, denominator);
+                match name.as_str() {
+                    "dfrac" | "cfrac" => {
+                        format!(r#"<mstyle displaystyle="true">{}</mstyle>"#, fraction)
+                    }
+                    "tfrac" => format!(r#"<mstyle displaystyle="false">{}</mstyle>"#, fraction),
+                    _ => fraction,
+                }
+            }
+            "binom" => {
+                let n = self.parse_argument()?;
+                let k = self.parse_argument()?;
+                format!(
+                    r#"<mrow><mo>(</mo><mfrac linethickness="0">{}{}</mfrac><mo>)</mo></mrow>"#,
+                    n, k
+                )
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    let index = self.parse_group(Stop::Bracket, ']')?;
+                    let radicand = self.parse_argument()?;
+                    format!("<mroot>{}{}</mroot>", radicand, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.parse_argument()?)
+                }
+            }
+            "left" => {
+                let open = self.read_delimiter()?;
+                let inner = self.parse_expr(Stop::Right)?;
+                self.read_command();
+                let close = self.read_delimiter()?;
+                format!("<mrow>{}{}{}</mrow>", fence(&open), inner, fence(&close))
+            }
+            "middle" => fence(&self.read_delimiter()?),
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl"
+            | "biggr" | "Biggl" | "Biggr" => operator(&self.read_delimiter()?),
+            "text" | "textrm" | "textup" | "mbox" | "textnormal" => {
+                format!("<mtext>{}</mtext>", escape(&self.read_raw_group()?))
+            }
+            "textit" => format!(
+                r#"<mtext mathvariant="italic">{}</mtext>"#,
+                escape(&self.read_raw_group()?)
+            ),
+            "textbf" => format!(
+                r#"<mtext mathvariant="bold">{}</mtext>"#,
+                escape(&self.read_raw_group()?)
+            ),
+            "operatorname" => {
+                let name = self.read_raw_group()?;
+                format!("<mi>{}</mi><mo>&#x2061;</mo>", escape(&name))
+            }
+            "mathrm" | "rm" => self.with_variant(Variant::Normal)?,
+            "mathbf" | "boldsymbol" | "bm" | "bf" => self.with_variant(Variant::Bold)?,
+            "mathit" => self.parse_argument()?,
+            "mathbb" => self.with_variant(Variant::DoubleStruck)?,
+            "mathcal" | "mathscr" => self.with_variant(Variant::Script)?,
+            "mathfrak" => self.with_variant(Variant::Fraktur)?,
+            "mathsf" => self.with_variant(Variant::SansSerif)?,
+            "mathtt" => self.with_variant(Variant::Monospace)?,
+            "begin" => self.parse_environment()?,
+            "displaystyle" | "textstyle" | "scriptstyle" | "!" | "nonumber" | "notag" => {
+                String::new()
+            }
+            "," | "thinspace" => space("0.1667em"),
+            ":" | ">" | "medspace" => space("0.2222em"),
+            ";" | "thickspace" => space("0.2778em"),
+            " " => space("0.25em"),
+            "quad" => space("1em"),
+            "qquad" => space("2em"),
+            "{" | "}" | "%" | "$" | "#" | "&" | "_" | "|" => {
+                let text = if name == "|" { "‖" } else { name.as_str() };
+                operator(text)
+            }
+            _ => {
+                if let Some(accent) = accent(&name) {
+                    let base = self.parse_argument()?;
+                    return Ok((accent.render(&base), Kind::Ordinary));
+                }
+                if let Some(text) = large_operator(&name) {
+                    let limits = !name.contains("int");
+                    return Ok((
+                        format!(r#"<mo largeop="true" movablelimits="false">{}</mo>"#, text),
+                        Kind::LargeOp { limits },
+                    ));
+                }
+                if LIMIT_FUNCTIONS.contains(&name.as_str()) {
+                    return Ok((format!("<mi>{}</mi>", name), Kind::LargeOp { limits: true }));
+                }
+                if FUNCTIONS.contains(&name.as_str()) {
+                    format!("<mi>{}</mi><mo>&#x2061;</mo>", name)
+                } else if let Some((text, is_operator)) = symbol(&name) {
+                    if is_operator {
+                        operator(text)
+                    } else if text.chars().count() == 1
+                        && text.chars().all(|c| c.is_uppercase())
+                        && name.chars().next().is_some_and(|c| c.is_uppercase())
+                    {
+                        // Capital Greek letters are upright
+                        format!(r#"<mi mathvariant="normal">{}</mi>"#, text)
+                    } else {
+                        format!("<mi>{}</mi>", text)
+                    }
+                } else {
+                    format!("<merror><mtext>\\{}</mtext></merror>", escape(&name))
+                }
+            }
+        };
+        Ok((atom, Kind::Ordinary))
+    }
+
+    fn parse_environment(&mut self) -> Result<String, String> {
+        let name = self.read_raw_group()?;
+        let (open, close, align) = match name.as_str() {
+            "matrix" | "smallmatrix" | "array" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some("left left")),
+            "aligned" | "align" | "align*" | "split" | "alignat" | "eqnarray" => {
+                ("", "", Some("right left"))
+            }
+            "gathered" | "gather" | "gather*" => ("", "", None),
+            other => return Err(format!("Unknown environment {}", other)),
+        };
+        if name == "array" || name == "alignat" {
+            // Column specification, e.g. {cc|c}
+            self.read_raw_group()?;
+        }
+
+        let mut rows: Vec<Vec<String>> = vec![Vec::new()];
+        loop {
+            let cell = self.parse_expr(Stop::Cell)?;
+            rows.last_mut().expect("at least one row").push(cell);
+            match self.peek() {
+                Some('&') => self.pos += 1,
+                _ => match self.read_command().as_str() {
+                    "\\" => rows.push(Vec::new()),
+                    "end" => {
+                        let end = self.read_raw_group()?;
+                        if end != name {
+                            return Err(format!("\\begin{{{}}} ended by \\end{{{}}}", name, end));
+                        }
+                        break;
+                    }
+                    _ => return Err(format!("Unterminated environment {}", name)),
+                },
+            }
+        }
+        // A trailing \\ leaves an empty last row
+        if rows.len() > 1 && rows.last().is_some_and(|r| r.iter().all(|c| c.is_empty())) {
+            rows.pop();
+        }
+
+        let mut table = String::from("<mtable");
+        if let Some(align) = align {
+            let _ = write!(table, r#" columnalign="{}""#, align);
+        }
+        table.push('>');
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                let _ = write!(table, "<mtd>{}</mtd>", cell);
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        if open.is_empty() && close.is_empty() {
+            Ok(table)
+        } else {
+            Ok(format!(
+                "<mrow>{}{}{}</mrow>",
+                fence(open),
+                table,
+                fence(close)
+            ))
+        }
+    }
+}
+
+fn operator(text: &str) -> String {
+    format!("<mo>{}</mo>", escape(text))
+}
+
+fn fence(text: &str) -> String {
+    if text.is_empty() {
+        String::new()
+    } else {
+        format!(r#"<mo fence="true" stretchy="true">{}</mo>"#, escape(text))
+    }
+}
+
+fn space(width: &str) -> String {
+    format!(r#"<mspace width="{}"></mspace>"#, width)
+}
+
+struct Accent {
+    mark: &'static str,
+    under: bool,
+    stretchy: bool,
+}
+
+impl Accent {
+    fn render(&self, base: &str) -> String {
+        let stretchy = if self.stretchy {
+            r#" stretchy="true""#
+        } else {
+            ""
+        };
+        if self.under {
+            format!(
+                r#"<munder accentunder="true">{}<mo{}>{}</mo></munder>"#,
+                base, stretchy, self.mark
+            )
+        } else {
+            format!(
+                r#"<mover accent="true">{}<mo{}>{}</mo></mover>"#,
+                base, stretchy, self.mark
+            )
+        }
+    }
+}
+
+fn accent(name: &str) -> Option<Accent> {
+    let (mark, under, stretchy) = match name {
+        "hat" => ("^", false, false),
+        "widehat" => ("^", false, true),
+        "tilde" => ("~", false, false),
+        "widetilde" => ("~", false, true),
+        "bar" => ("¯", false, false),
+        "overline" => ("‾", false, true),
+        "underline" => ("_", true, true),
+        "vec" => ("→", false, false),
+        "overrightarrow" => ("→", false, true),
+        "overleftarrow" => ("←", false, true),
+        "dot" => ("˙", false, false),
+        "ddot" => ("¨", false, false),
+        "acute" => ("´", false, false),
+        "grave" => ("`", false, false),
+        "breve" => ("˘", false, false),
+        "check" => ("ˇ", false, false),
+        "overbrace" => ("⏞", false, true),
+        "underbrace" => ("⏟", true, true),
+        _ => return None,
+    };
+    Some(Accent {
+        mark,
+        under,
+        stretchy,
+    })
+}
+
+fn large_operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "int" => "∫",
+        "iint" => "∬",
+        "iiint" => "∭",
+        "oint" => "∮",
+        "bigcup" => "⋃",
+        "bigcap" => "⋂",
+        "bigvee" => "⋁",
+        "bigwedge" => "⋀",
+        "bigoplus" => "⨁",
+        "bigotimes" => "⨂",
+        "bigsqcup" => "⨆",
+        _ => return None,
+    })
+}
+
+/// Functions whose scripts go below them in display math, like `\lim_{x\to0}`.
+const LIMIT_FUNCTIONS: &[&str] = &[
+    "lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "gcd", "Pr", "argmax", "argmin",
+];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "coth", "log", "ln", "lg", "exp", "dim", "ker", "deg", "arg", "hom", "mod",
+];
+
+/// A symbol command's text, and whether it is an operator (`<mo>`) rather
+/// than an identifier (`<mi>`).
+fn symbol(name: &str) -> Option<(&'static str, bool)> {
+    let identifier = match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "aleph" => "ℵ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "wp" => "℘",
+        "emptyset" | "varnothing" => "∅",
+        "imath" => "ı",
+        "jmath" => "ȷ",
+        _ => "",
+    };
+    if !identifier.is_empty() {
+        return Some((identifier, false));
+    }
+    let operator = match name {
+        "times" => "×",
+        "cdot" => "⋅",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "oplus" => "⊕",
+        "ominus" => "⊖",
+        "otimes" => "⊗",
+        "odot" => "⊙",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "neg" | "lnot" => "¬",
+        "cap" => "∩",
+        "cup" => "∪",
+        "setminus" => "∖",
+        "sqcup" => "⊔",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "ll" => "≪",
+        "gg" => "≫",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "prec" => "≺",
+        "succ" => "≻",
+        "preceq" => "⪯",
+        "succeq" => "⪰",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "supset" => "⊃",
+        "subseteq" => "⊆",
+        "supseteq" => "⊇",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "perp" => "⊥",
+        "parallel" => "∥",
+        "mid" => "∣",
+        "nmid" => "∤",
+        "angle" => "∠",
+        "triangle" => "△",
+        "to" | "rightarrow" => "→",
+        "gets" | "leftarrow" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" => "⇔",
+        "implies" => "⟹",
+        "impliedby" => "⟸",
+        "iff" => "⟺",
+        "mapsto" => "↦",
+        "longrightarrow" => "⟶",
+        "longleftarrow" => "⟵",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "lbrack" => "[",
+        "rbrack" => "]",
+        "backslash" => "∖",
+        "prime" => "′",
+        "degree" => "°",
+        "colon" => ":",
+        "vdash" => "⊢",
+        "models" => "⊨",
+        _ => return None,
+    };
+    Some((operator, true))
+}
+
+/// Map a letter or digit to its Unicode mathematical alphanumeric form.
+fn styled_char(c: char, variant: Variant) -> char {
+    // Letters missing from the mathematical alphanumeric block, which live
+    // in Letterlike Symbols instead
+    let exception = match (variant, c) {
+        (Variant::DoubleStruck, 'C') => Some('ℂ'),
+        (Variant::DoubleStruck, 'H') => Some('ℍ'),
+        (Variant::DoubleStruck, 'N') => Some('ℕ'),
+        (Variant::DoubleStruck, 'P') => Some('ℙ'),
+        (Variant::DoubleStruck, 'Q') => Some('ℚ'),
+        (Variant::DoubleStruck, 'R') => Some('ℝ'),
+        (Variant::DoubleStruck, 'Z') => Some('ℤ'),
+        (Variant::Script, 'B') => Some('ℬ'),
+        (Variant::Script, 'E') => Some('ℰ'),
+        (Variant::Script, 'F') => Some('ℱ'),
+        (Variant::Script, 'H') => Some('ℋ'),
+        (Variant::Script, 'I') => Some('ℐ'),
+        (Variant::Script, 'L') => Some('ℒ'),
+        (Variant::Script, 'M') => Some('ℳ'),
+        (Variant::Script, 'R') => Some('ℛ'),
+        (Variant::Script, 'e') => Some('ℯ'),
+        (Variant::Script, 'g') => Some('ℊ'),
+        (Variant::Script, 'o') => Some('ℴ'),
+        (Variant::Fraktur, 'C') => Some('ℭ'),
+        (Variant::Fraktur, 'H') => Some('ℌ'),
+        (Variant::Fraktur, 'I') => Some('ℑ'),
+        (Variant::Fraktur, 'R') => Some('ℜ'),
+        (Variant::Fraktur, 'Z') => Some('ℨ'),
+        _ => None,
+    };
+    if let Some(exception) = exception {
+        return exception;
+    }
+
+    let (letters, digits) = match variant {
+        Variant::Normal => return c,
+        Variant::Bold => (Some(0x1D400), Some(0x1D7CE)),
+        Variant::Script => (Some(0x1D49C), None),
+        Variant::Fraktur => (Some(0x1D504), None),
+        Variant::DoubleStruck => (Some(0x1D538), Some(0x1D7D8)),
+        Variant::SansSerif => (Some(0x1D5A0), Some(0x1D7E2)),
+        Variant::Monospace => (Some(0x1D670), Some(0x1D7F6)),
+    };
+    let code = match c {
+        'A'..='Z' => letters.map(|base| base + (c as u32 - 'A' as u32)),
+        'a'..='z' => letters.map(|base| base + 26 + (c as u32 - 'a' as u32)),
+        '0'..='9' => digits.map(|base| base + (c as u32 - '0' as u32)),
+        _ => None,
+    };
+    code.and_then(char::from_u32).unwrap_or(c)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_and_display_math() {
+        let inline = to_mathml(r"x^2 + \alpha_i", false);
+        assert!(inline.starts_with("<math><semantics>"));
+        assert!(inline.contains("<msup><mi>x</mi><mn>2</mn></msup>"));
+        assert!(inline.contains("<msub><mi>α</mi><mi>i</mi></msub>"));
+        assert!(inline
+            .contains(r#"<annotation encoding="application/x-tex">x^2 + \alpha_i</annotation>"#));
+
+        let display = to_mathml(r"\sum_{i=1}^{n} \frac{1}{i} \leq \sqrt[3]{n}", true);
+        assert!(display.starts_with(r#"<math display="block">"#));
+        assert!(display.contains("<munderover><mo"));
+        assert!(display.contains("<mfrac><mrow><mn>1</mn></mrow><mrow><mi>i</mi></mrow></mfrac>"));
+        assert!(display.contains("<mo>≤</mo>"));
+        assert!(display.contains("<mroot>"));
+    }
+
+    #[test]
+    fn test_environments_fonts_and_delimiters() {
+        let matrix = to_mathml(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}", true);
+        assert_eq!(matrix.matches("<mtr>").count(), 2);
+        assert_eq!(matrix.matches("<mtd>").count(), 4);
+        assert!(matrix.contains(r#"<mo fence="true" stretchy="true">(</mo>"#));
+
+        let sets = to_mathml(
+            r"x \in \mathbb{R}, \mathbf{v} = \left[ \vec{a} \right]",
+            false,
+        );
+        assert!(sets.contains("<mi>ℝ</mi>"));
+        assert!(sets.contains("<mi>𝐯</mi>"));
+        assert!(sets.contains(r#"<mover accent="true"><mrow><mi>a</mi></mrow><mo>→</mo></mover>"#));
+        assert!(sets.contains(r#"<mo fence="true" stretchy="true">]</mo>"#));
+
+        let cases = to_mathml(
+            r"f(x) = \begin{cases} 1 & x > 0 \\ 0 & \text{otherwise} \end{cases}",
+            true,
+        );
+        assert!(cases.contains(r#"columnalign="left left""#));
+        assert!(cases.contains("<mtext>otherwise</mtext>"));
+    }
+
+    #[test]
+    fn test_errors_are_escaped_and_contained() {
+        let unknown = to_mathml(r"a + \foo", false);
+        assert!(unknown.contains(r"<merror><mtext>\foo</mtext></merror>"));
+        assert!(unknown.contains("<mi>a</mi>"));
+
+        let unbalanced = to_mathml(r"\frac{1}{<script>", false);
+        assert!(unbalanced.contains("<merror>"));
+        assert!(!unbalanced.contains("<script>"));
+        assert!(unbalanced.contains("&lt;script&gt;"));
+    }
+}
@@ -0,0 +1,552 @@
+//! Prometheus metrics for `/metrics`. Requests, scheduled-publish runs and
+//! analytics ingestion are counted in process by [`Metrics`], one per site;
+//! database, webhook delivery and backup figures are read at scrape time.
+
+use crate::{Config, Database};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write as _};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Upper bounds of the request latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Counters kept by a running site.
+pub struct Metrics {
+    started: Instant,
+    requests: Mutex<BTreeMap<&'static str, RequestStats>>,
+    publish_successes: AtomicU64,
+    publish_failures: AtomicU64,
+    published: AtomicU64,
+    analytics_recorded: AtomicU64,
+    analytics_failed: AtomicU64,
+}
+
+#[derive(Default)]
+struct RequestStats {
+    /// Responses by status class: 1 for 1xx up to 5 for 5xx
+    statuses: BTreeMap<u16, u64>,
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: Mutex::new(BTreeMap::new()),
+            publish_successes: AtomicU64::new(0),
+            publish_failures: AtomicU64::new(0),
+            published: AtomicU64::new(0),
+            analytics_recorded: AtomicU64::new(0),
+            analytics_failed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_request(&self, path: &str, status: u16, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = requests.entry(route_class(path)).or_default();
+        *stats.statuses.entry(status / 100).or_default() += 1;
+        for (bucket, bound) in stats.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        stats.sum += seconds;
+        stats.count += 1;
+    }
+
+    /// A scheduled-publish run: the number of items it published, or `None`
+    /// when it failed.
+    pub fn record_publish_run(&self, published: Option<usize>) {
+        match published {
+            Some(count) => {
+                self.publish_successes.fetch_add(1, Ordering::Relaxed);
+                self.published.fetch_add(count as u64, Ordering::Relaxed);
+            }
+            None => {
+                self.publish_failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn record_analytics_event(&self, recorded: bool) {
+        let counter = if recorded {
+            &self.analytics_recorded
+        } else {
+            &self.analytics_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// All metrics in the Prometheus text exposition format.
+    pub fn render(&self, config: &Config, db: &Database) -> String {
+        let mut out = Exposition::default();
+
+        out.family("pebble_build_info", "gauge", "Pebble version");
+        out.sample(
+            "pebble_build_info",
+            &[("version", env!("CARGO_PKG_VERSION"))],
+            1,
+        );
+        out.family(
+            "pebble_uptime_seconds",
+            "gauge",
+            "Seconds since the site was started",
+        );
+        out.sample(
+            "pebble_uptime_seconds",
+            &[],
+            self.started.elapsed().as_secs(),
+        );
+
+        self.render_requests(&mut out);
+
+        out.family(
+            "pebble_scheduled_publish_runs_total",
+            "counter",
+            "Scheduled-publish runs by result",
+        );
+        out.sample(
+            "pebble_scheduled_publish_runs_total",
+            &[("result", "success")],
+            self.publish_successes.load(Ordering::Relaxed),
+        );
+        out.sample(
+            "pebble_scheduled_publish_runs_total",
+            &[("result", "failure")],
+            self.publish_failures.load(Ordering::Relaxed),
+        );
+        out.family(
+            "pebble_scheduled_published_total",
+            "counter",
+            "Items published by the scheduler",
+        );
+        out.sample(
+            "pebble_scheduled_published_total",
+            &[],
+            self.published.load(Ordering::Relaxed),
+        );
+
+        out.family(
+            "pebble_analytics_events_total",
+            "counter",
+            "Analytics events ingested, by result",
+        );
+        out.sample(
+            "pebble_analytics_events_total",
+            &[("result", "recorded")],
+            self.analytics_recorded.load(Ordering::Relaxed),
+        );
+        out.sample(
+            "pebble_analytics_events_total",
+            &[("result", "failed")],
+            self.analytics_failed.load(Ordering::Relaxed),
+        );
+
+        render_database(&mut out, config, db);
+        render_backups(&mut out, Path::new(&config.backup.directory));
+        out.0
+    }
+
+    fn render_requests(&self, out: &mut Exposition) {
+        let requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+
+        out.family(
+            "pebble_http_requests_total",
+            "counter",
+            "HTTP requests by route class and status class",
+        );
+        for (class, stats) in requests.iter() {
+            for (status, count) in &stats.statuses {
+                let status = format!("{}xx", status);
+                out.sample(
+                    "pebble_http_requests_total",
+                    &[("class", class), ("status", &status)],
+                    count,
+                );
+            }
+        }
+
+        out.family(
+            "pebble_http_request_duration_seconds",
+            "histogram",
+            "HTTP request latency by route class",
+        );
+        for (class, stats) in requests.iter() {
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                out.sample(
+                    "pebble_http_request_duration_seconds_bucket",
+                    &[("class", class), ("le", &bound.to_string())],
+                    count,
+                );
+            }
+            out.sample(
+                "pebble_http_request_duration_seconds_bucket",
+                &[("class", class), ("le", "+Inf")],
+                stats.count,
+            );
+            out.sample(
+                "pebble_http_request_duration_seconds_sum",
+                &[("class", class)],
+                stats.sum,
+            );
+            out.sample(
+                "pebble_http_request_duration_seconds_count",
+                &[("class", class)],
+                stats.count,
+            );
+        }
+    }
+}
+
+/// The route class a request path is counted under.
+pub fn route_class(path: &str) -> &'static str {
+    let prefixed = |prefix: &str| path == prefix || path.starts_with(&format!("{}/", prefix));
+    if prefixed("/admin") {
+        "admin"
+    } else if prefixed("/htmx") {
+        "htmx"
+    } else if prefixed("/api") {
+        "api"
+    } else if ["/static", "/media", "/og", "/js", "/css"]
+        .iter()
+        .any(|prefix| prefixed(prefix))
+        || path == "/favicon.ico"
+    {
+        "asset"
+    } else if path.ends_with("/feed.xml")
+        || path.ends_with("/feed.json")
+        || path == "/sitemap.xml"
+        || path == "/robots.txt"
+    {
+        "feed"
+    } else if path == "/health" || path == "/metrics" {
+        "internal"
+    } else {
+        "page"
+    }
+}
+
+/// Connection pool, SQLite file and webhook delivery figures.
+fn render_database(out: &mut Exposition, config: &Config, db: &Database) {
+    let pool = db.pool_state();
+    out.family(
+        "pebble_db_pool_connections",
+        "gauge",
+        "Database pool connections by state",
+    );
+    out.sample(
+        "pebble_db_pool_connections",
+        &[("state", "active")],
+        pool.connections - pool.idle_connections,
+    );
+    out.sample(
+        "pebble_db_pool_connections",
+        &[("state", "idle")],
+        pool.idle_connections,
+    );
+    out.family(
+        "pebble_db_pool_max_connections",
+        "gauge",
+        "Database pool size limit",
+    );
+    out.sample("pebble_db_pool_max_connections", &[], db.pool_max_size());
+
+    let path = Path::new(&config.database.path);
+    let file_size = |path: &Path| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    out.family(
+        "pebble_sqlite_file_bytes",
+        "gauge",
+        "Size of the database file",
+    );
+    out.sample("pebble_sqlite_file_bytes", &[], file_size(path));
+    out.family(
+        "pebble_sqlite_wal_bytes",
+        "gauge",
+        "Size of the write-ahead log",
+    );
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    out.sample("pebble_sqlite_wal_bytes", &[], file_size(Path::new(&wal)));
+
+    let figures = db.get().and_then(|conn| {
+        let pragma = |name: &str| -> rusqlite::Result<i64> {
+            conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))
+        };
+        let deliveries: (i64, i64) = conn.query_row(
+            "SELECT COALESCE(SUM(success != 0), 0), COALESCE(SUM(success = 0), 0) FROM webhook_deliveries",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((
+            pragma("page_count")?,
+            pragma("freelist_count")?,
+            pragma("page_size")?,
+            deliveries,
+        ))
+    });
+
+    out.family(
+        "pebble_db_up",
+        "gauge",
+        "Whether the database answered the scrape",
+    );
+    let (page_count, freelist, page_size, (delivered, failed)) = match figures {
+        Ok(figures) => {
+            out.sample("pebble_db_up", &[], 1);
+            figures
+        }
+        Err(e) => {
+            tracing::warn!("Metrics: could not query the database: {}", e);
+            out.sample("pebble_db_up", &[], 0);
+            return;
+        }
+    };
+    out.family(
+        "pebble_sqlite_pages",
+        "gauge",
+        "Database pages, in use and free",
+    );
+    out.sample(
+        "pebble_sqlite_pages",
+        &[("state", "used")],
+        page_count - freelist,
+    );
+    out.sample("pebble_sqlite_pages", &[("state", "free")], freelist);
+    out.family(
+        "pebble_sqlite_page_size_bytes",
+        "gauge",
+        "Database page size",
+    );
+    out.sample("pebble_sqlite_page_size_bytes", &[], page_size);
+
+    out.family(
+        "pebble_webhook_deliveries_total",
+        "counter",
+        "Webhook deliveries by result, after retries",
+    );
+    out.sample(
+        "pebble_webhook_deliveries_total",
+        &[("result", "success")],
+        delivered,
+    );
+    out.sample(
+        "pebble_webhook_deliveries_total",
+        &[("result", "failure")],
+        failed,
+    );
+}
+
+/// The number of backups in the backup directory and the age of the newest.
+fn render_backups(out: &mut Exposition, dir: &Path) {
+    let backups: Vec<SystemTime> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter(|e| {
+                    let name = e.file_name();
+                    let name = name.to_string_lossy();
+                    name.starts_with("pebble-backup-") && name.ends_with(".zip")
+                })
+                .filter_map(|e| e.metadata().and_then(|m| m.modified()).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    out.family("pebble_backups", "gauge", "Backups in the backup directory");
+    out.sample("pebble_backups", &[], backups.len());
+    if let Some(newest) = backups.into_iter().max() {
+        let age = SystemTime::now()
+            .duration_since(newest)
+            .unwrap_or_default()
+            .as_secs();
+        out.family(
+            "pebble_backup_age_seconds",
+            "gauge",
+            "Seconds since the newest backup",
+        );
+        out.sample("pebble_backup_age_seconds", &[], age);
+    }
+}
+
+/// Prometheus text format output.
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.0, "# HELP {} {}", name, help);
+        let _ = writeln!(self.0, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+                .collect();
+            let _ = write!(self.0, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.0, " {}", value);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Sample values by metric name and labels.
+    fn samples(text: &str) -> HashMap<String, f64> {
+        text.lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.rsplit_once(' '))
+            .filter_map(|(name, value)| Some((name.to_string(), value.parse().ok()?)))
+            .collect()
+    }
+
+    #[test]
+    fn test_route_class() {
+        assert_eq!(route_class("/"), "page");
+        assert_eq!(route_class("/posts/hello"), "page");
+        assert_eq!(route_class("/administrivia"), "page");
+        assert_eq!(route_class("/admin"), "admin");
+        assert_eq!(route_class("/admin/posts/1/edit"), "admin");
+        assert_eq!(route_class("/htmx/search"), "htmx");
+        assert_eq!(route_class("/api/v1/posts"), "api");
+        assert_eq!(route_class("/media/photo.webp"), "asset");
+        assert_eq!(route_class("/fr/feed.xml"), "feed");
+        assert_eq!(route_class("/sitemap.xml"), "feed");
+        assert_eq!(route_class("/health"), "internal");
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let dir = std::env::temp_dir().join(format!("pebble-metrics-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("pebble.db");
+        let db = Database::open(db_path.to_str().unwrap()).unwrap();
+        db.migrate().unwrap();
+        std::fs::write(dir.join("pebble-backup-20260101-000000.zip"), b"zip").unwrap();
+
+        let mut config: Config = toml::from_str(
+            r#"
+            [site]
+            title = "Test"
+            description = ""
+            url = "http://localhost:3000"
+            language = "en"
+            [server]
+            host = "127.0.0.1"
+            port = 3000
+            [database]
+            path = "pebble.db"
+            [content]
+            posts_per_page = 10
+            excerpt_length = 200
+            auto_excerpt = true
+            [media]
+            upload_dir = "media"
+            max_upload_size = "10MB"
+            [theme]
+            name = "default"
+            [auth]
+            session_lifetime = "7d"
+            "#,
+        )
+        .unwrap();
+        config.database.path = db_path.to_string_lossy().to_string();
+        config.backup.directory = dir.to_string_lossy().to_string();
+
+        let metrics = Metrics::new();
+        metrics.record_request("/posts/a", 200, Duration::from_millis(30));
+        metrics.record_request("/posts/b", 404, Duration::from_millis(3));
+        metrics.record_request("/admin", 303, Duration::from_secs(20));
+        metrics.record_publish_run(Some(2));
+        metrics.record_publish_run(None);
+        metrics.record_analytics_event(true);
+
+        let text = metrics.render(&config, &db);
+        let samples = samples(&text);
+        let get = |name: &str| {
+            *samples
+                .get(name)
+                .unwrap_or_else(|| panic!("{} missing", name))
+        };
+
+        assert_eq!(
+            get(r#"pebble_http_requests_total{class="page",status="2xx"}"#),
+            1.0
+        );
+        assert_eq!(
+            get(r#"pebble_http_requests_total{class="page",status="4xx"}"#),
+            1.0
+        );
+        assert_eq!(
+            get(r#"pebble_http_request_duration_seconds_bucket{class="page",le="0.005"}"#),
+            1.0
+        );
+        assert_eq!(
+            get(r#"pebble_http_request_duration_seconds_bucket{class="page",le="0.05"}"#),
+            2.0
+        );
+        assert_eq!(
+            get(r#"pebble_http_request_duration_seconds_bucket{class="admin",le="10"}"#),
+            0.0
+        );
+        assert_eq!(
+            get(r#"pebble_http_request_duration_seconds_bucket{class="admin",le="+Inf"}"#),
+            1.0
+        );
+        assert_eq!(
+            get(r#"pebble_http_request_duration_seconds_count{class="page"}"#),
+            2.0
+        );
+        assert_eq!(
+            get(r#"pebble_scheduled_publish_runs_total{result="success"}"#),
+            1.0
+        );
+        assert_eq!(
+            get(r#"pebble_scheduled_publish_runs_total{result="failure"}"#),
+            1.0
+        );
+        assert_eq!(get("pebble_scheduled_published_total"), 2.0);
+        assert_eq!(
+            get(r#"pebble_analytics_events_total{result="recorded"}"#),
+            1.0
+        );
+        assert_eq!(get("pebble_db_up"), 1.0);
+        assert!(get(r#"pebble_sqlite_pages{state="used"}"#) > 0.0);
+        assert_eq!(
+            get(r#"pebble_webhook_deliveries_total{result="failure"}"#),
+            0.0
+        );
+        assert_eq!(get("pebble_db_pool_max_connections"), 10.0);
+        assert_eq!(get("pebble_backups"), 1.0);
+        assert!(get("pebble_backup_age_seconds") < 60.0);
+        assert!(text.contains("# TYPE pebble_http_request_duration_seconds histogram"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
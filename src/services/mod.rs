@@ -1,18 +1,37 @@
+#[cfg(feature = "tls")]
+pub mod acme;
 pub mod analytics;
 pub mod api_token;
 pub mod audit;
 pub mod auth;
+pub mod comments;
 pub mod content;
+pub mod content_types;
 pub mod database;
+pub mod deploy_config;
+pub mod diagrams;
+pub mod embeds;
+pub mod highlight;
 pub mod html_to_markdown;
+pub mod i18n;
 pub mod image;
+pub mod link_checker;
+pub mod lint;
 pub mod markdown;
+pub mod math;
 pub mod media;
+pub mod metrics;
+pub mod newsletter;
 pub mod preview;
 pub mod search;
+pub mod seo;
 pub mod series;
 pub mod settings;
+pub mod shortcodes;
 pub mod slug;
+pub mod sync;
+pub mod social_card;
 pub mod tags;
 pub mod versions;
 pub mod webhook;
+pub mod webmention;
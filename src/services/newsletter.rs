@@ -0,0 +1,1065 @@
+//! Newsletter — double opt-in email subscribers, per-post sends over SMTP
+//! in batches, and a delivery log.
+
+use crate::config::NewsletterConfig;
+use crate::models::{NewsletterDelivery, NewsletterIssue, Subscriber, SubscriberStatus};
+use crate::Database;
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use url::Url;
+
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_NAME_LENGTH: usize = 100;
+
+const SUBSCRIBER_COLUMNS: &str = "id, email, name, status, confirm_token, unsubscribe_token, created_at, confirmed_at, unsubscribed_at";
+const ISSUE_COLUMNS: &str = "id, content_id, subject, status, recipient_count, sent_count, failed_count, created_by, created_at, completed_at";
+
+fn row_to_subscriber(row: &rusqlite::Row) -> rusqlite::Result<Subscriber> {
+    let status: String = row.get(3)?;
+    Ok(Subscriber {
+        id: row.get(0)?,
+        email: row.get(1)?,
+        name: row.get(2)?,
+        status: status.parse().unwrap_or_default(),
+        confirm_token: row.get(4)?,
+        unsubscribe_token: row.get(5)?,
+        created_at: row.get(6)?,
+        confirmed_at: row.get(7)?,
+        unsubscribed_at: row.get(8)?,
+    })
+}
+
+fn row_to_issue(row: &rusqlite::Row) -> rusqlite::Result<NewsletterIssue> {
+    Ok(NewsletterIssue {
+        id: row.get(0)?,
+        content_id: row.get(1)?,
+        subject: row.get(2)?,
+        status: row.get(3)?,
+        recipient_count: row.get(4)?,
+        sent_count: row.get(5)?,
+        failed_count: row.get(6)?,
+        created_by: row.get(7)?,
+        created_at: row.get(8)?,
+        completed_at: row.get(9)?,
+    })
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 24];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Check an email address is plausibly deliverable and return it trimmed.
+pub fn validate_email(email: &str) -> Result<String> {
+    let email = email.trim();
+    if email.is_empty() {
+        bail!("Email address is required");
+    }
+    if email.len() > MAX_EMAIL_LENGTH {
+        bail!("Email address is too long");
+    }
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+                && !email.contains(['<', '>', ',', ';', '"'])
+        }
+        None => false,
+    };
+    if !valid {
+        bail!("Please enter a valid email address");
+    }
+    Ok(email.to_string())
+}
+
+fn clean_name(name: Option<&str>) -> Option<String> {
+    name.map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(|n| n.chars().take(MAX_NAME_LENGTH).collect())
+}
+
+// ----------------------------------------------------------------------------
+// Subscribers
+// ----------------------------------------------------------------------------
+
+pub fn get_subscriber(db: &Database, id: i64) -> Result<Option<Subscriber>> {
+    let conn = db.get()?;
+    let subscriber = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM subscribers WHERE id = ?",
+                SUBSCRIBER_COLUMNS
+            ),
+            [id],
+            row_to_subscriber,
+        )
+        .ok();
+    Ok(subscriber)
+}
+
+fn get_subscriber_by(db: &Database, column: &str, value: &str) -> Result<Option<Subscriber>> {
+    let conn = db.get()?;
+    let subscriber = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM subscribers WHERE {} = ?",
+                SUBSCRIBER_COLUMNS, column
+            ),
+            [value],
+            row_to_subscriber,
+        )
+        .ok();
+    Ok(subscriber)
+}
+
+pub fn get_subscriber_by_unsubscribe_token(
+    db: &Database,
+    token: &str,
+) -> Result<Option<Subscriber>> {
+    get_subscriber_by(db, "unsubscribe_token", token)
+}
+
+/// Start a double opt-in subscription. Returns the subscriber when a
+/// confirmation email should be sent, or `None` if the address is already
+/// confirmed. Callers should respond identically in both cases so the form
+/// can't be used to discover who is subscribed.
+pub fn subscribe(db: &Database, email: &str, name: Option<&str>) -> Result<Option<Subscriber>> {
+    let email = validate_email(email)?;
+    let name = clean_name(name);
+
+    match get_subscriber_by(db, "email", &email)? {
+        Some(existing) if existing.status == SubscriberStatus::Confirmed => Ok(None),
+        Some(existing) => {
+            // Pending addresses keep their token so an earlier email still works
+            let token = existing
+                .confirm_token
+                .clone()
+                .unwrap_or_else(generate_token);
+            let conn = db.get()?;
+            conn.execute(
+                "UPDATE subscribers SET status = 'pending', confirm_token = ?, name = COALESCE(?, name), unsubscribed_at = NULL WHERE id = ?",
+                rusqlite::params![token, name, existing.id],
+            )?;
+            drop(conn);
+            get_subscriber(db, existing.id)
+        }
+        None => {
+            let conn = db.get()?;
+            conn.execute(
+                "INSERT INTO subscribers (email, name, status, confirm_token, unsubscribe_token) VALUES (?, ?, 'pending', ?, ?)",
+                rusqlite::params![email, name, generate_token(), generate_token()],
+            )?;
+            let id = conn.last_insert_rowid();
+            drop(conn);
+            get_subscriber(db, id)
+        }
+    }
+}
+
+/// Confirm a pending subscription from the link in the opt-in email.
+pub fn confirm_subscription(db: &Database, token: &str) -> Result<Option<Subscriber>> {
+    let subscriber = match get_subscriber_by(db, "confirm_token", token)? {
+        Some(s) if s.status == SubscriberStatus::Pending => s,
+        _ => return Ok(None),
+    };
+    let conn = db.get()?;
+    conn.execute(
+        "UPDATE subscribers SET status = 'confirmed', confirm_token = NULL, confirmed_at = CURRENT_TIMESTAMP WHERE id = ?",
+        [subscriber.id],
+    )?;
+    drop(conn);
+    get_subscriber(db, subscriber.id)
+}
+
+/// Unsubscribe using the per-subscriber token included in every email.
+pub fn unsubscribe(db: &Database, token: &str) -> Result<Option<Subscriber>> {
+    let subscriber = match get_subscriber_by_unsubscribe_token(db, token)? {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    if subscriber.status != SubscriberStatus::Unsubscribed {
+        let conn = db.get()?;
+        conn.execute(
+            "UPDATE subscribers SET status = 'unsubscribed', confirm_token = NULL, unsubscribed_at = CURRENT_TIMESTAMP WHERE id = ?",
+            [subscriber.id],
+        )?;
+    }
+    get_subscriber(db, subscriber.id)
+}
+
+/// Add a subscriber directly (admin or import), skipping opt-in. Returns
+/// `None` if the address already exists.
+pub fn add_subscriber(
+    db: &Database,
+    email: &str,
+    name: Option<&str>,
+    status: SubscriberStatus,
+) -> Result<Option<i64>> {
+    let email = validate_email(email)?;
+    if get_subscriber_by(db, "email", &email)?.is_some() {
+        return Ok(None);
+    }
+    let confirm_token = (status == SubscriberStatus::Pending).then(generate_token);
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO subscribers (email, name, status, confirm_token, unsubscribe_token, confirmed_at, unsubscribed_at)
+         VALUES (?, ?, ?, ?, ?,
+                 CASE WHEN ?3 = 'confirmed' THEN CURRENT_TIMESTAMP END,
+                 CASE WHEN ?3 = 'unsubscribed' THEN CURRENT_TIMESTAMP END)",
+        rusqlite::params![
+            email,
+            clean_name(name),
+            status.to_string(),
+            confirm_token,
+            generate_token(),
+        ],
+    )?;
+    Ok(Some(conn.last_insert_rowid()))
+}
+
+pub fn delete_subscriber(db: &Database, id: i64) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute("DELETE FROM subscribers WHERE id = ?", [id])?;
+    Ok(())
+}
+
+/// List subscribers, newest first, optionally filtered by status.
+pub fn list_subscribers(
+    db: &Database,
+    status: Option<SubscriberStatus>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<Subscriber>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM subscribers WHERE (?1 IS NULL OR status = ?1) ORDER BY created_at DESC, id DESC LIMIT ?2 OFFSET ?3",
+        SUBSCRIBER_COLUMNS
+    ))?;
+    let subscribers = stmt
+        .query_map(
+            rusqlite::params![status.map(|s| s.to_string()), limit as i64, offset as i64],
+            row_to_subscriber,
+        )?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(subscribers)
+}
+
+pub fn count_subscribers(db: &Database, status: Option<SubscriberStatus>) -> Result<i64> {
+    let conn = db.get()?;
+    let count = conn.query_row(
+        "SELECT COUNT(*) FROM subscribers WHERE (?1 IS NULL OR status = ?1)",
+        [status.map(|s| s.to_string())],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+// ----------------------------------------------------------------------------
+// CSV import/export
+// ----------------------------------------------------------------------------
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Split CSV text into records, honouring quoted fields (RFC 4180).
+fn parse_csv(data: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = data.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+        .into_iter()
+        .filter(|r| r.iter().any(|f| !f.trim().is_empty()))
+        .collect()
+}
+
+/// Export every subscriber as CSV (`email,name,status,created_at,confirmed_at,unsubscribed_at`).
+pub fn export_csv(db: &Database) -> Result<String> {
+    let count = count_subscribers(db, None)? as usize;
+    let subscribers = list_subscribers(db, None, count.max(1), 0)?;
+    let mut csv = String::from("email,name,status,created_at,confirmed_at,unsubscribed_at\n");
+    for s in subscribers.iter().rev() {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_field(&s.email),
+            csv_field(s.name.as_deref().unwrap_or_default()),
+            s.status,
+            s.created_at,
+            s.confirmed_at.as_deref().unwrap_or_default(),
+            s.unsubscribed_at.as_deref().unwrap_or_default(),
+        ));
+    }
+    Ok(csv)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub skipped: usize,
+    pub invalid: Vec<String>,
+}
+
+/// Import subscribers from CSV. A header row naming `email` (and optionally
+/// `name` and `status`) selects columns; without one, the columns are taken
+/// as email, name, status. Rows without a status are imported as confirmed,
+/// since the list owner is vouching for their consent. Existing addresses
+/// are left untouched.
+pub fn import_csv(db: &Database, data: &str) -> Result<ImportSummary> {
+    let mut records = parse_csv(data).into_iter().peekable();
+    let (mut email_col, mut name_col, mut status_col) = (0, Some(1), Some(2));
+    if let Some(header) = records.peek() {
+        let lower: Vec<String> = header.iter().map(|h| h.trim().to_lowercase()).collect();
+        if let Some(i) = lower
+            .iter()
+            .position(|h| h == "email" || h == "email_address")
+        {
+            email_col = i;
+            name_col = lower.iter().position(|h| h == "name");
+            status_col = lower.iter().position(|h| h == "status");
+            records.next();
+        }
+    }
+
+    let mut summary = ImportSummary::default();
+    for record in records {
+        let field = |col: Option<usize>| {
+            col.and_then(|i| record.get(i))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+        let email = field(Some(email_col)).unwrap_or_default();
+        let status = field(status_col)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(SubscriberStatus::Confirmed);
+        match add_subscriber(db, email, field(name_col), status) {
+            Ok(Some(_)) => summary.added += 1,
+            Ok(None) => summary.skipped += 1,
+            Err(_) => summary.invalid.push(email.to_string()),
+        }
+    }
+    Ok(summary)
+}
+
+// ----------------------------------------------------------------------------
+// Issues and delivery log
+// ----------------------------------------------------------------------------
+
+/// Record a new send of a post to all currently confirmed subscribers.
+pub fn create_issue(
+    db: &Database,
+    content_id: i64,
+    subject: &str,
+    created_by: Option<i64>,
+) -> Result<i64> {
+    let recipients = count_subscribers(db, Some(SubscriberStatus::Confirmed))?;
+    if recipients == 0 {
+        bail!("There are no confirmed subscribers to send to");
+    }
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO newsletter_issues (content_id, subject, recipient_count, created_by) VALUES (?, ?, ?, ?)",
+        rusqlite::params![content_id, subject, recipients, created_by],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_issue(db: &Database, id: i64) -> Result<Option<NewsletterIssue>> {
+    let conn = db.get()?;
+    let issue = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM newsletter_issues WHERE id = ?",
+                ISSUE_COLUMNS
+            ),
+            [id],
+            row_to_issue,
+        )
+        .ok();
+    Ok(issue)
+}
+
+pub fn list_issues(db: &Database, limit: usize) -> Result<Vec<NewsletterIssue>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM newsletter_issues ORDER BY created_at DESC, id DESC LIMIT ?",
+        ISSUE_COLUMNS
+    ))?;
+    let issues = stmt
+        .query_map([limit as i64], row_to_issue)?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(issues)
+}
+
+pub fn list_issues_for_content(db: &Database, content_id: i64) -> Result<Vec<NewsletterIssue>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM newsletter_issues WHERE content_id = ? ORDER BY created_at DESC, id DESC",
+        ISSUE_COLUMNS
+    ))?;
+    let issues = stmt
+        .query_map([content_id], row_to_issue)?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(issues)
+}
+
+pub fn list_deliveries(db: &Database, issue_id: i64) -> Result<Vec<NewsletterDelivery>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, issue_id, subscriber_id, email, success, error, sent_at
+         FROM newsletter_deliveries WHERE issue_id = ? ORDER BY id DESC",
+    )?;
+    let deliveries = stmt
+        .query_map([issue_id], |row| {
+            Ok(NewsletterDelivery {
+                id: row.get(0)?,
+                issue_id: row.get(1)?,
+                subscriber_id: row.get(2)?,
+                email: row.get(3)?,
+                success: row.get(4)?,
+                error: row.get(5)?,
+                sent_at: row.get(6)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(deliveries)
+}
+
+/// Confirmed subscribers who haven't yet received this issue. Retrying an
+/// interrupted or partly failed send picks up exactly these.
+pub fn pending_recipients(db: &Database, issue_id: i64) -> Result<Vec<Subscriber>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM subscribers WHERE status = 'confirmed' AND id NOT IN (
+             SELECT subscriber_id FROM newsletter_deliveries
+             WHERE issue_id = ? AND success = 1 AND subscriber_id IS NOT NULL
+         ) ORDER BY id",
+        SUBSCRIBER_COLUMNS
+    ))?;
+    let subscribers = stmt
+        .query_map([issue_id], row_to_subscriber)?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(subscribers)
+}
+
+#[cfg_attr(not(feature = "newsletter"), allow(dead_code))]
+fn log_delivery(
+    db: &Database,
+    issue_id: i64,
+    subscriber: &Subscriber,
+    error: Option<&str>,
+) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO newsletter_deliveries (issue_id, subscriber_id, email, success, error) VALUES (?, ?, ?, ?, ?)",
+        rusqlite::params![issue_id, subscriber.id, subscriber.email, error.is_none(), error],
+    )?;
+    Ok(())
+}
+
+fn set_issue_sending(db: &Database, issue_id: i64) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute(
+        "UPDATE newsletter_issues SET status = 'sending', completed_at = NULL WHERE id = ?",
+        [issue_id],
+    )?;
+    Ok(())
+}
+
+/// Recompute an issue's counters from its delivery log and mark it finished.
+pub fn finish_issue(db: &Database, issue_id: i64) -> Result<NewsletterIssue> {
+    let conn = db.get()?;
+    conn.execute(
+        "UPDATE newsletter_issues SET
+             sent_count = (SELECT COUNT(DISTINCT email) FROM newsletter_deliveries WHERE issue_id = ?1 AND success = 1),
+             failed_count = (SELECT COUNT(DISTINCT email) FROM newsletter_deliveries d WHERE d.issue_id = ?1 AND d.success = 0
+                             AND d.email NOT IN (SELECT email FROM newsletter_deliveries WHERE issue_id = ?1 AND success = 1)),
+             completed_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        [issue_id],
+    )?;
+    conn.execute(
+        "UPDATE newsletter_issues SET status = CASE WHEN sent_count = 0 AND failed_count > 0 THEN 'failed' ELSE 'sent' END WHERE id = ?",
+        [issue_id],
+    )?;
+    drop(conn);
+    get_issue(db, issue_id)?.ok_or_else(|| anyhow::anyhow!("Newsletter issue not found"))
+}
+
+// ----------------------------------------------------------------------------
+// Email
+// ----------------------------------------------------------------------------
+
+static TAG_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?is)<[a-z][a-z0-9]*(?:[^>"']|"[^"]*"|'[^']*')*>"#).unwrap());
+static URL_ATTR_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)(\s(href|src|srcset)\s*=\s*)(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+
+/// Rewrite root-relative `src`/`href`/`srcset` URLs against the site URL so
+/// images and links in a post keep working inside an email client.
+pub fn absolutize_urls(html: &str, site_url: &str) -> String {
+    let base = match Url::parse(&format!("{}/", site_url.trim_end_matches('/'))) {
+        Ok(base) => base,
+        Err(_) => return html.to_string(),
+    };
+    TAG_RE
+        .replace_all(html, |tag: &Captures| {
+            URL_ATTR_RE
+                .replace_all(&tag[0], |attr: &Captures| {
+                    let (quote, value) = match attr.get(3) {
+                        Some(value) => ('"', value.as_str()),
+                        None => ('\'', attr.get(4).map_or("", |m| m.as_str())),
+                    };
+                    let value = if attr[2].eq_ignore_ascii_case("srcset") {
+                        absolutize_srcset(&base, value)
+                    } else {
+                        absolutize_url(&base, value)
+                    };
+                    format!("{}{}{}{}", &attr[1], quote, value, quote)
+                })
+                .into_owned()
+        })
+        .into_owned()
+}
+
+/// Each `srcset` candidate is a URL followed by an optional width or density.
+fn absolutize_srcset(base: &Url, srcset: &str) -> String {
+    srcset
+        .split(',')
+        .map(str::trim)
+        .filter(|candidate| !candidate.is_empty())
+        .map(
+            |candidate| match candidate.split_once(char::is_whitespace) {
+                Some((url, descriptor)) => {
+                    format!("{} {}", absolutize_url(base, url), descriptor.trim())
+                }
+                None => absolutize_url(base, candidate),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Resolve a root-relative (`/x`) or protocol-relative (`//host/x`) URL;
+/// anything else is left as written.
+fn absolutize_url(base: &Url, url: &str) -> String {
+    if !url.starts_with('/') {
+        return url.to_string();
+    }
+    base.join(url)
+        .map(String::from)
+        .unwrap_or_else(|_| url.to_string())
+}
+
+/// A rendered message ready for delivery.
+#[derive(Debug, Clone)]
+pub struct OutgoingEmail {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    /// Added as `List-Unsubscribe` with one-click support (RFC 8058)
+    pub unsubscribe_url: Option<String>,
+}
+
+#[cfg(feature = "newsletter")]
+mod smtp {
+    use super::OutgoingEmail;
+    use crate::config::NewsletterConfig;
+    use anyhow::Result;
+    use lettre::message::header::{Header, HeaderName, HeaderValue};
+    use lettre::message::{Mailbox, MultiPart};
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+    #[derive(Clone)]
+    struct ListUnsubscribe(String);
+
+    impl Header for ListUnsubscribe {
+        fn name() -> HeaderName {
+            HeaderName::new_from_ascii_str("List-Unsubscribe")
+        }
+
+        fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(Self(s.to_string()))
+        }
+
+        fn display(&self) -> HeaderValue {
+            HeaderValue::new(Self::name(), self.0.clone())
+        }
+    }
+
+    #[derive(Clone)]
+    struct ListUnsubscribePost;
+
+    impl Header for ListUnsubscribePost {
+        fn name() -> HeaderName {
+            HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+        }
+
+        fn parse(_s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(Self)
+        }
+
+        fn display(&self) -> HeaderValue {
+            HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".to_string())
+        }
+    }
+
+    /// SMTP sender built from the `[newsletter]` config. One transport
+    /// (and its connection pool) is reused for a whole batch.
+    #[derive(Clone)]
+    pub struct Mailer {
+        transport: AsyncSmtpTransport<Tokio1Executor>,
+        from: Mailbox,
+        reply_to: Option<Mailbox>,
+    }
+
+    impl Mailer {
+        pub fn from_config(config: &NewsletterConfig) -> Result<Self> {
+            let from: Mailbox = config
+                .from_address
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid newsletter.from_address: {}", e))?;
+            let reply_to = match config.reply_to.as_deref().filter(|r| !r.trim().is_empty()) {
+                Some(r) => Some(
+                    r.parse()
+                        .map_err(|e| anyhow::anyhow!("Invalid newsletter.reply_to: {}", e))?,
+                ),
+                None => None,
+            };
+
+            let mut builder = match config.smtp_security.as_str() {
+                "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?,
+                "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                    config.smtp_host.as_str(),
+                ),
+                _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?,
+            }
+            .port(config.smtp_port)
+            .timeout(Some(std::time::Duration::from_secs(30)));
+            if let (Some(user), Some(pass)) = (&config.smtp_username, &config.smtp_password) {
+                builder = builder.credentials(Credentials::new(user.clone(), pass.clone()));
+            }
+
+            Ok(Self {
+                transport: builder.build(),
+                from,
+                reply_to,
+            })
+        }
+
+        pub async fn send(&self, email: &OutgoingEmail) -> Result<()> {
+            let to: Mailbox = email
+                .to
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid recipient: {}", e))?;
+            let mut builder = Message::builder()
+                .from(self.from.clone())
+                .to(to)
+                .subject(email.subject.clone());
+            if let Some(ref reply_to) = self.reply_to {
+                builder = builder.reply_to(reply_to.clone());
+            }
+            if let Some(ref url) = email.unsubscribe_url {
+                builder = builder
+                    .header(ListUnsubscribe(format!("<{}>", url)))
+                    .header(ListUnsubscribePost);
+            }
+            let message = builder.multipart(MultiPart::alternative_plain_html(
+                email.text.clone(),
+                email.html.clone(),
+            ))?;
+            self.transport.send(message).await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "newsletter")]
+pub use smtp::Mailer;
+
+/// Stand-in when the newsletter feature is disabled: every send fails.
+#[cfg(not(feature = "newsletter"))]
+#[derive(Clone)]
+pub struct Mailer;
+
+#[cfg(not(feature = "newsletter"))]
+impl Mailer {
+    pub fn from_config(_config: &NewsletterConfig) -> Result<Self> {
+        bail!("Pebble was built without the newsletter feature")
+    }
+
+    pub async fn send(&self, _email: &OutgoingEmail) -> Result<()> {
+        bail!("Pebble was built without the newsletter feature")
+    }
+}
+
+/// Issues currently being delivered by this process.
+static IN_FLIGHT: Lazy<Mutex<HashSet<i64>>> = Lazy::new(Default::default);
+
+/// Whether this process is still delivering an issue. An issue left in the
+/// `sending` state without being in flight was interrupted and can be retried.
+pub fn is_sending(issue_id: i64) -> bool {
+    IN_FLIGHT
+        .lock()
+        .map(|set| set.contains(&issue_id))
+        .unwrap_or(false)
+}
+
+struct InFlightGuard(i64);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Ok(mut set) = IN_FLIGHT.lock() {
+            set.remove(&self.0);
+        }
+    }
+}
+
+/// Deliver an issue to every confirmed subscriber who hasn't received it,
+/// `batch_size` messages at a time with `batch_delay_ms` between batches.
+/// Each attempt is written to the delivery log; returns the finished issue.
+pub async fn send_issue<F>(
+    db: &Database,
+    mailer: &Mailer,
+    config: &NewsletterConfig,
+    issue_id: i64,
+    render: F,
+) -> Result<NewsletterIssue>
+where
+    F: Fn(&Subscriber) -> Result<OutgoingEmail>,
+{
+    if !IN_FLIGHT
+        .lock()
+        .map(|mut set| set.insert(issue_id))
+        .unwrap_or(false)
+    {
+        bail!("Newsletter issue {} is already being sent", issue_id);
+    }
+    let _guard = InFlightGuard(issue_id);
+
+    set_issue_sending(db, issue_id)?;
+    let recipients = pending_recipients(db, issue_id)?;
+    let batch_size = config.batch_size.max(1);
+
+    for (i, batch) in recipients.chunks(batch_size).enumerate() {
+        if i > 0 && config.batch_delay_ms > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(config.batch_delay_ms)).await;
+        }
+        for subscriber in batch {
+            let result = match render(subscriber) {
+                Ok(email) => mailer.send(&email).await,
+                Err(e) => Err(e),
+            };
+            let error = result.err().map(|e| e.to_string());
+            if let Some(ref e) = error {
+                tracing::warn!(
+                    "Newsletter {} to {} failed: {}",
+                    issue_id,
+                    subscriber.email,
+                    e
+                );
+            }
+            log_delivery(db, issue_id, subscriber, error.as_deref())?;
+        }
+    }
+
+    let issue = finish_issue(db, issue_id)?;
+    tracing::info!(
+        "Newsletter {} finished: {} sent, {} failed",
+        issue_id,
+        issue.sent_count,
+        issue.failed_count
+    );
+    Ok(issue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::markdown::MarkdownRenderer;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    fn setup_test_db() -> Database {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let db = Database::open_memory(&format!("newsletter_test_{}", id)).unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    #[test]
+    fn test_absolutize_urls() {
+        let site = "https://blog.example.com/";
+        let abs = |html: &str| absolutize_urls(html, site);
+
+        assert_eq!(
+            abs(r#"<a href="/posts/hello">Hello</a> <img src='/media/a.png'>"#),
+            r#"<a href="https://blog.example.com/posts/hello">Hello</a> <img src='https://blog.example.com/media/a.png'>"#
+        );
+        assert_eq!(
+            abs(r#"<img src="//cdn.example.net/x.png">"#),
+            r#"<img src="https://cdn.example.net/x.png">"#
+        );
+        assert_eq!(
+            abs(
+                r#"<source srcset="/media/a-400.webp 400w, //cdn.example.net/b.webp 800w,https://img.example.org/c.webp 2x">"#
+            ),
+            r#"<source srcset="https://blog.example.com/media/a-400.webp 400w, https://cdn.example.net/b.webp 800w, https://img.example.org/c.webp 2x">"#
+        );
+
+        // Absolute, relative and in-page URLs, other attributes and text are left alone
+        let untouched = r##"<a href="https://other.example/x?a=1&amp;b=2">x</a> <a href="mailto:me@example.com">m</a> <a href="#notes">n</a> <img data-src="/lazy.png" alt="/alt" src="img.png"> src="/text""##;
+        assert_eq!(abs(untouched), untouched);
+    }
+
+    #[test]
+    fn test_double_opt_in_and_unsubscribe() {
+        let db = setup_test_db();
+
+        let pending = subscribe(&db, " reader@example.com ", Some("Reader"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.status, SubscriberStatus::Pending);
+        let token = pending.confirm_token.clone().unwrap();
+
+        // Subscribing again while pending re-sends the same token
+        let again = subscribe(&db, "READER@example.com", None).unwrap().unwrap();
+        assert_eq!(again.id, pending.id);
+        assert_eq!(again.confirm_token.as_deref(), Some(token.as_str()));
+
+        let confirmed = confirm_subscription(&db, &token).unwrap().unwrap();
+        assert_eq!(confirmed.status, SubscriberStatus::Confirmed);
+        assert!(confirm_subscription(&db, &token).unwrap().is_none());
+        assert!(subscribe(&db, "reader@example.com", None)
+            .unwrap()
+            .is_none());
+
+        let gone = unsubscribe(&db, &confirmed.unsubscribe_token)
+            .unwrap()
+            .unwrap();
+        assert_eq!(gone.status, SubscriberStatus::Unsubscribed);
+        assert_eq!(
+            count_subscribers(&db, Some(SubscriberStatus::Confirmed)).unwrap(),
+            0
+        );
+
+        assert!(subscribe(&db, "not-an-email", None).is_err());
+        assert!(subscribe(&db, "a@b", None).is_err());
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let db = setup_test_db();
+        let csv = "Email,Name,Status\nann@example.com,\"Smith, Ann\",\nbob@example.com,Bob,unsubscribed\nbad-address,,\nann@example.com,Dup,\n";
+        let summary = import_csv(&db, csv).unwrap();
+        assert_eq!(summary.added, 2);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.invalid, vec!["bad-address".to_string()]);
+        assert_eq!(
+            count_subscribers(&db, Some(SubscriberStatus::Confirmed)).unwrap(),
+            1
+        );
+
+        let exported = export_csv(&db).unwrap();
+        let rows = parse_csv(&exported);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1][0], "ann@example.com");
+        assert_eq!(rows[1][1], "Smith, Ann");
+        assert_eq!(rows[2][2], "unsubscribed");
+
+        // Headerless files are read as email,name
+        let summary = import_csv(&db, "cy@example.com,Cy\r\n").unwrap();
+        assert_eq!(summary.added, 1);
+    }
+
+    /// Minimal SMTP sink: accepts every message and records the DATA sections.
+    #[cfg(feature = "newsletter")]
+    async fn spawn_smtp_sink() -> (u16, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let messages: std::sync::Arc<std::sync::Mutex<Vec<String>>> = Default::default();
+        let sink = messages.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    break;
+                };
+                let sink = sink.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+                    let mut data: Option<String> = None;
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if let Some(ref mut body) = data {
+                            if line == "." {
+                                sink.lock().unwrap().push(std::mem::take(body));
+                                data = None;
+                                write.write_all(b"250 queued\r\n").await.unwrap();
+                            } else {
+                                body.push_str(&line);
+                                body.push('\n');
+                            }
+                            continue;
+                        }
+                        let command = line.to_ascii_uppercase();
+                        let reply: &[u8] = if command.starts_with("EHLO") {
+                            b"250 sink\r\n"
+                        } else if command.starts_with("DATA") {
+                            data = Some(String::new());
+                            b"354 go ahead\r\n"
+                        } else if command.starts_with("QUIT") {
+                            write.write_all(b"221 bye\r\n").await.unwrap();
+                            break;
+                        } else {
+                            b"250 ok\r\n"
+                        };
+                        write.write_all(reply).await.unwrap();
+                    }
+                });
+            }
+        });
+        (port, messages)
+    }
+
+    #[cfg(feature = "newsletter")]
+    #[tokio::test]
+    async fn test_send_issue_in_batches_to_smtp_sink() {
+        let db = setup_test_db();
+        for i in 0..5 {
+            add_subscriber(
+                &db,
+                &format!("reader{}@example.com", i),
+                None,
+                SubscriberStatus::Confirmed,
+            )
+            .unwrap();
+        }
+        add_subscriber(&db, "later@example.com", None, SubscriberStatus::Pending).unwrap();
+
+        let (port, messages) = spawn_smtp_sink().await;
+        let config = NewsletterConfig {
+            enabled: true,
+            from_address: "Blog <news@example.com>".to_string(),
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: port,
+            smtp_security: "none".to_string(),
+            batch_size: 2,
+            batch_delay_ms: 10,
+            ..Default::default()
+        };
+        let mailer = Mailer::from_config(&config).unwrap();
+
+        let content_id = crate::services::content::create_content(
+            &db,
+            &MarkdownRenderer::new(),
+            &[],
+            crate::models::CreateContent {
+                title: "Issue One".to_string(),
+                slug: None,
+                content_type: crate::models::ContentType::Post,
+                body_markdown: "Hello".to_string(),
+                status: crate::models::ContentStatus::Published,
+                scheduled_at: None,
+                excerpt: None,
+                featured_image: None,
+                tags: vec![],
+                metadata: None,
+                entry_type: None,
+                fields: None,
+            },
+            None,
+            200,
+        )
+        .unwrap();
+        let issue_id = create_issue(&db, content_id, "Issue One", None).unwrap();
+
+        let render = |s: &Subscriber| {
+            if s.email == "reader3@example.com" {
+                bail!("template failed");
+            }
+            Ok(OutgoingEmail {
+                to: s.email.clone(),
+                subject: "Issue One".to_string(),
+                html: "<p>Hello</p>".to_string(),
+                text: "Hello".to_string(),
+                unsubscribe_url: Some(format!(
+                    "https://blog.example.com/newsletter/unsubscribe/{}",
+                    s.unsubscribe_token
+                )),
+            })
+        };
+        let issue = send_issue(&db, &mailer, &config, issue_id, render)
+            .await
+            .unwrap();
+        assert_eq!(issue.recipient_count, 5);
+        assert_eq!(issue.sent_count, 4);
+        assert_eq!(issue.failed_count, 1);
+        assert_eq!(issue.status, "sent");
+
+        let received = messages.lock().unwrap().clone();
+        assert_eq!(received.len(), 4);
+        assert!(received[0]
+            .contains("List-Unsubscribe: <https://blog.example.com/newsletter/unsubscribe/"));
+        assert!(received[0].contains("Subject: Issue One"));
+
+        // Retrying only targets the subscriber that failed
+        assert_eq!(pending_recipients(&db, issue_id).unwrap().len(), 1);
+        assert_eq!(list_deliveries(&db, issue_id).unwrap().len(), 5);
+    }
+}
@@ -12,7 +12,7 @@ pub fn search_content(db: &Database, query: &str, limit: usize) -> Result<Vec<Co
         SELECT c.id, c.slug, c.title, c.content_type, c.excerpt, c.status, c.published_at, c.created_at
         FROM content c
         JOIN content_fts fts ON c.id = fts.rowid
-        WHERE content_fts MATCH ? AND c.status = 'published'
+        WHERE content_fts MATCH ? AND c.status = 'published' AND c.content_type != 'entry'
         ORDER BY rank
         LIMIT ?
         "#,
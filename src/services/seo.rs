@@ -0,0 +1,370 @@
+//! Search and social metadata for content pages: the values behind the
+//! canonical link, Open Graph and Twitter card tags, and the schema.org
+//! JSON-LD emitted by `base.html`.
+
+use crate::config::SiteConfig;
+use crate::models::{ContentType, ContentWithTags};
+use serde::Serialize;
+use serde_json::json;
+
+/// Search engines show at most this many characters of a headline.
+const MAX_HEADLINE_LENGTH: usize = 110;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeoMeta {
+    pub title: String,
+    pub description: String,
+    pub canonical_url: String,
+    pub noindex: bool,
+    /// Open Graph type: "article" for posts, "website" otherwise
+    pub og_type: &'static str,
+    /// `og:locale`, e.g. "en" or "pt_BR"
+    pub locale: String,
+    /// Absolute URL of the image shown when the page is shared
+    pub image: Option<String>,
+    pub twitter_card: &'static str,
+    pub published_time: Option<String>,
+    pub modified_time: Option<String>,
+    pub author: Option<String>,
+    pub tags: Vec<String>,
+    /// schema.org graph, already escaped for a `<script>` element
+    pub json_ld: String,
+}
+
+/// A step in the breadcrumb trail between the home page and the item.
+#[derive(Debug, Clone)]
+pub struct Crumb {
+    pub name: String,
+    pub path: String,
+}
+
+fn metadata_str<'a>(metadata: &'a serde_json::Value, key: &str) -> Option<&'a str> {
+    metadata
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+/// Whether an item asks search engines not to index it.
+pub fn is_noindex(metadata: &serde_json::Value) -> bool {
+    metadata
+        .get("noindex")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Absolute URL for an image given as a full URL, a site path, or the file
+/// name of an uploaded media item.
+pub fn absolute_image_url(base_url: &str, image: &str) -> String {
+    let base = base_url.trim_end_matches('/');
+    if image.starts_with("http://") || image.starts_with("https://") {
+        image.to_string()
+    } else if image.starts_with('/') {
+        format!("{}{}", base, image)
+    } else {
+        format!("{}/media/{}", base, image)
+    }
+}
+
+/// The image an item sets for sharing: its social image if one is set,
+/// otherwise its featured image.
+pub fn own_image(item: &ContentWithTags) -> Option<&str> {
+    metadata_str(&item.content.metadata, "social_image").or(item
+        .content
+        .featured_image
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty()))
+}
+
+/// ISO 8601 form of a stored timestamp, which may be SQLite's
+/// `YYYY-MM-DD HH:MM:SS` (always UTC) or RFC 3339 already.
+fn iso_datetime(stored: &str) -> String {
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(stored) {
+        return dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    }
+    match chrono::NaiveDateTime::parse_from_str(stored, "%Y-%m-%d %H:%M:%S") {
+        Ok(dt) => dt
+            .and_utc()
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        Err(_) => stored.to_string(),
+    }
+}
+
+/// `og:locale` form of a language code: "pt-br" becomes "pt_BR".
+fn og_locale(language: &str) -> String {
+    match language.split_once('-') {
+        Some((lang, region)) => format!("{}_{}", lang, region.to_ascii_uppercase()),
+        None => language.to_string(),
+    }
+}
+
+fn truncate_chars(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((i, _)) => s[..i].to_string(),
+        None => s.to_string(),
+    }
+}
+
+/// SEO metadata for a content item served at `path`. `base_url` is the
+/// site's public URL (or the `--base-url` of a static build), `crumbs`
+/// the breadcrumb steps between the home page and the item, and
+/// `fallback_image` the absolute URL shared when the item has no image of
+/// its own.
+pub fn content_seo(
+    item: &ContentWithTags,
+    site: &SiteConfig,
+    base_url: &str,
+    path: &str,
+    home_path: &str,
+    crumbs: &[Crumb],
+    fallback_image: Option<String>,
+) -> SeoMeta {
+    let content = &item.content;
+    let base = base_url.trim_end_matches('/');
+    let url = format!("{}{}", base, path);
+    let language = content.language.as_deref().unwrap_or(&site.language);
+
+    let title = metadata_str(&content.metadata, "meta_title")
+        .unwrap_or(&content.title)
+        .to_string();
+    let description = metadata_str(&content.metadata, "meta_description")
+        .or(content.excerpt.as_deref().filter(|e| !e.trim().is_empty()))
+        .unwrap_or(&site.description)
+        .to_string();
+    let canonical_url = metadata_str(&content.metadata, "canonical_url")
+        .map(str::to_string)
+        .unwrap_or_else(|| url.clone());
+    let image = own_image(item)
+        .map(|image| absolute_image_url(base_url, image))
+        .or(fallback_image);
+    let published_time = content.published_at.as_deref().map(iso_datetime);
+    let modified_time = Some(iso_datetime(&content.updated_at));
+    let author = item.author.as_ref().map(|a| a.username.clone());
+    let tags: Vec<String> = item.tags.iter().map(|t| t.name.clone()).collect();
+    let is_post = content.content_type == ContentType::Post;
+
+    let mut page = json!({
+        "@type": if is_post { "BlogPosting" } else { "WebPage" },
+        "@id": format!("{}#content", canonical_url),
+        "url": canonical_url,
+        "name": title,
+        "description": description,
+        "inLanguage": language,
+        "isPartOf": { "@type": "WebSite", "name": site.title, "url": format!("{}/", base) },
+        "dateModified": modified_time,
+    });
+    if let Some(ref published) = published_time {
+        page["datePublished"] = json!(published);
+    }
+    if let Some(ref image) = image {
+        page["image"] = json!([image]);
+    }
+    if is_post {
+        page["headline"] = json!(truncate_chars(&title, MAX_HEADLINE_LENGTH));
+        page["mainEntityOfPage"] = json!({ "@type": "WebPage", "@id": canonical_url });
+        page["publisher"] =
+            json!({ "@type": "Organization", "name": site.title, "url": format!("{}/", base) });
+        if let Some(ref author) = author {
+            page["author"] = json!({ "@type": "Person", "name": author });
+        }
+        if !tags.is_empty() {
+            page["keywords"] = json!(tags.join(", "));
+        }
+    }
+
+    let home_url = if home_path == "/" {
+        format!("{}/", base)
+    } else {
+        format!("{}{}", base, home_path)
+    };
+    let mut trail = vec![(site.title.clone(), home_url)];
+    trail.extend(
+        crumbs
+            .iter()
+            .map(|c| (c.name.clone(), format!("{}{}", base, c.path))),
+    );
+    trail.push((content.title.clone(), url));
+    let breadcrumbs = json!({
+        "@type": "BreadcrumbList",
+        "itemListElement": trail
+            .iter()
+            .enumerate()
+            .map(|(i, (name, item))| json!({
+                "@type": "ListItem",
+                "position": i + 1,
+                "name": name,
+                "item": item,
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    let graph = json!({
+        "@context": "https://schema.org",
+        "@graph": [page, breadcrumbs],
+    });
+    // `</script>` in a title must not end the element early
+    let json_ld = graph.to_string().replace("</", "<\\/");
+
+    SeoMeta {
+        title,
+        description,
+        canonical_url,
+        noindex: is_noindex(&content.metadata),
+        og_type: if is_post { "article" } else { "website" },
+        locale: og_locale(language),
+        twitter_card: if image.is_some() {
+            "summary_large_image"
+        } else {
+            "summary"
+        },
+        image,
+        published_time,
+        modified_time,
+        author,
+        tags,
+        json_ld,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Content, ContentStatus, Tag, UserSummary};
+
+    fn site() -> SiteConfig {
+        SiteConfig {
+            title: "My Blog".to_string(),
+            description: "A blog".to_string(),
+            url: "https://blog.example.com".to_string(),
+            language: "en".to_string(),
+        }
+    }
+
+    fn item(content_type: ContentType, metadata: serde_json::Value) -> ContentWithTags {
+        ContentWithTags {
+            content: Content {
+                id: 1,
+                slug: "hello".to_string(),
+                title: "Hello </script> World".to_string(),
+                content_type,
+                body_markdown: String::new(),
+                body_html: String::new(),
+                excerpt: Some("The excerpt".to_string()),
+                featured_image: Some("cover.jpg".to_string()),
+                status: ContentStatus::Published,
+                scheduled_at: None,
+                published_at: Some("2024-03-01T09:30:00Z".to_string()),
+                author_id: Some(1),
+                metadata,
+                created_at: "2024-03-01 09:00:00".to_string(),
+                updated_at: "2024-03-02 10:00:00".to_string(),
+                language: Some("pt-br".to_string()),
+                translation_group: None,
+                entry_type: None,
+                fields: serde_json::json!({}),
+                toc: Vec::new(),
+            },
+            tags: vec![Tag {
+                id: 1,
+                name: "Rust".to_string(),
+                slug: "rust".to_string(),
+                created_at: String::new(),
+            }],
+            author: Some(UserSummary {
+                id: 1,
+                username: "alice".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_post_seo_defaults() {
+        let post = item(ContentType::Post, serde_json::json!({}));
+        let crumbs = [Crumb {
+            name: "Posts".to_string(),
+            path: "/pt-br/posts".to_string(),
+        }];
+        let seo = content_seo(
+            &post,
+            &site(),
+            "https://blog.example.com/",
+            "/pt-br/posts/hello",
+            "/pt-br",
+            &crumbs,
+            None,
+        );
+
+        assert_eq!(seo.title, "Hello </script> World");
+        assert_eq!(seo.description, "The excerpt");
+        assert_eq!(
+            seo.canonical_url,
+            "https://blog.example.com/pt-br/posts/hello"
+        );
+        assert_eq!(
+            seo.image.as_deref(),
+            Some("https://blog.example.com/media/cover.jpg")
+        );
+        assert_eq!(seo.twitter_card, "summary_large_image");
+        assert_eq!(seo.og_type, "article");
+        assert_eq!(seo.locale, "pt_BR");
+        assert_eq!(seo.modified_time.as_deref(), Some("2024-03-02T10:00:00Z"));
+        assert!(!seo.noindex);
+
+        assert!(!seo.json_ld.contains("</script>"));
+        let graph: serde_json::Value = serde_json::from_str(&seo.json_ld).unwrap();
+        let posting = &graph["@graph"][0];
+        assert_eq!(posting["@type"], "BlogPosting");
+        assert_eq!(posting["author"]["name"], "alice");
+        assert_eq!(posting["datePublished"], "2024-03-01T09:30:00Z");
+        assert_eq!(posting["keywords"], "Rust");
+        let trail = graph["@graph"][1]["itemListElement"].as_array().unwrap();
+        assert_eq!(trail.len(), 3);
+        assert_eq!(trail[0]["item"], "https://blog.example.com/pt-br");
+        assert_eq!(trail[1]["name"], "Posts");
+        assert_eq!(trail[2]["position"], 3);
+    }
+
+    #[test]
+    fn test_page_seo_overrides() {
+        let page = item(
+            ContentType::Page,
+            serde_json::json!({
+                "meta_title": "About us",
+                "meta_description": "Who we are",
+                "canonical_url": "https://elsewhere.example/about",
+                "social_image": "https://cdn.example/card.png",
+                "noindex": true,
+            }),
+        );
+        let seo = content_seo(
+            &page,
+            &site(),
+            "https://blog.example.com",
+            "/pages/hello",
+            "/",
+            &[],
+            Some("https://blog.example.com/og/hello.png".to_string()),
+        );
+        assert_eq!(seo.title, "About us");
+        assert_eq!(seo.description, "Who we are");
+        assert_eq!(seo.canonical_url, "https://elsewhere.example/about");
+        assert_eq!(seo.image.as_deref(), Some("https://cdn.example/card.png"));
+        assert_eq!(seo.og_type, "website");
+        assert!(seo.noindex);
+
+        let graph: serde_json::Value = serde_json::from_str(&seo.json_ld).unwrap();
+        assert_eq!(graph["@graph"][0]["@type"], "WebPage");
+        assert!(graph["@graph"][0].get("author").is_none());
+        assert_eq!(
+            graph["@graph"][1]["itemListElement"][0]["item"],
+            "https://blog.example.com/"
+        );
+
+        assert_eq!(
+            absolute_image_url("https://blog.example.com", "/media/a.png"),
+            "https://blog.example.com/media/a.png"
+        );
+    }
+}
@@ -298,6 +298,7 @@ mod tests {
     use super::*;
     use crate::services::content;
     use crate::models::{ContentType, ContentStatus, CreateContent};
+    use crate::services::markdown::MarkdownRenderer;
     use std::sync::atomic::{AtomicU64, Ordering};
 
     static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
@@ -334,6 +335,8 @@ mod tests {
         for i in 1..=3 {
             let id = content::create_content(
                 &db,
+                &MarkdownRenderer::new(),
+                &[],
                 CreateContent {
                     title: format!("Part {}", i),
                     slug: Some(format!("part-{}", i)),
@@ -345,6 +348,8 @@ mod tests {
                     scheduled_at: None,
                     tags: vec![],
                     metadata: None,
+                    entry_type: None,
+                    fields: None,
                 },
                 None,
                 200,
@@ -391,6 +396,8 @@ mod tests {
         for i in 1..=3 {
             let id = content::create_content(
                 &db,
+                &MarkdownRenderer::new(),
+                &[],
                 CreateContent {
                     title: format!("Post {}", i),
                     slug: Some(format!("post-{}", i)),
@@ -402,6 +409,8 @@ mod tests {
                     scheduled_at: None,
                     tags: vec![],
                     metadata: None,
+                    entry_type: None,
+                    fields: None,
                 },
                 None,
                 200,
@@ -0,0 +1,529 @@
+//! Shortcode registry: the built-in shortcodes plus site-defined shortcodes
+//! loaded from Tera templates in the site's shortcodes directory.
+//!
+//! Each site loads its registry at startup and hands it to the
+//! [`MarkdownRenderer`](super::markdown::MarkdownRenderer) it renders with, so
+//! several sites in one process never see each other's shortcodes.
+
+use anyhow::{Context as _, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+use tera::Tera;
+
+use crate::Config;
+
+/// Tag and class names handed to the sanitizer, which only accepts 'static
+/// strings. Each distinct name is leaked once however often registries are
+/// reloaded.
+static INTERNED: Lazy<Mutex<HashSet<&'static str>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+static HEADER_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)^\s*\{#(.*?)#\}").expect("Invalid shortcode header regex"));
+static CLASS_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"<([a-zA-Z][a-zA-Z0-9]*)\b[^>]*?\sclass="([^"]*)""#)
+        .expect("Invalid shortcode class regex")
+});
+static NAME_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\w+$").expect("Invalid shortcode name regex"));
+
+/// Where a shortcode comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShortcodeSource {
+    Builtin,
+    Site,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShortcodeAttribute {
+    pub name: String,
+    pub description: String,
+    pub required: bool,
+}
+
+/// Reference documentation for one shortcode, shown on the admin shortcodes page.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShortcodeInfo {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub description: String,
+    pub attributes: Vec<ShortcodeAttribute>,
+    /// What the body between `[name]` and `[/name]` is used for, if the shortcode takes one
+    pub body: Option<String>,
+    pub example: String,
+    pub source: ShortcodeSource,
+}
+
+/// Site-defined shortcodes plus the class names their templates use, which
+/// the sanitizer must allow for the rendered output to keep its styling.
+#[derive(Default)]
+pub struct ShortcodeRegistry {
+    templates: Tera,
+    site: BTreeMap<String, ShortcodeInfo>,
+    allowed_classes: Vec<(&'static str, &'static str)>,
+}
+
+impl ShortcodeRegistry {
+    /// Load every `<name>.html` template in `dir`. A missing directory yields
+    /// an empty registry.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut sources = Vec::new();
+        if dir.is_dir() {
+            let entries = std::fs::read_dir(dir).with_context(|| {
+                format!("Failed to read shortcodes directory {}", dir.display())
+            })?;
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("html") {
+                    continue;
+                }
+                let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                let source = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read shortcode {}", path.display()))?;
+                sources.push((name.to_string(), source));
+            }
+        }
+        Self::from_templates(sources)
+    }
+
+    /// Build a registry from `(name, template source)` pairs.
+    pub fn from_templates(sources: Vec<(String, String)>) -> Result<Self> {
+        let mut registry = Self::default();
+        for (name, source) in sources {
+            if !NAME_REGEX.is_match(&name) {
+                tracing::warn!(
+                    "Skipping shortcode template '{}': names may only contain letters, digits and underscores",
+                    name
+                );
+                continue;
+            }
+            registry
+                .templates
+                .add_raw_template(&format!("{}.html", name), &source)
+                .with_context(|| format!("Invalid shortcode template '{}'", name))?;
+            registry.collect_classes(&source);
+            registry
+                .site
+                .insert(name.clone(), parse_header(&name, &source));
+        }
+        Ok(registry)
+    }
+
+    fn collect_classes(&mut self, source: &str) {
+        for caps in CLASS_REGEX.captures_iter(source) {
+            let tag = caps[1].to_lowercase();
+            for class in caps[2].split_whitespace() {
+                if class.contains('{') || class.contains('}') {
+                    continue;
+                }
+                if self
+                    .allowed_classes
+                    .iter()
+                    .any(|(t, c)| *t == tag && *c == class)
+                {
+                    continue;
+                }
+                self.allowed_classes.push((intern(&tag), intern(class)));
+            }
+        }
+    }
+
+    pub fn has_template(&self, name: &str) -> bool {
+        self.site.contains_key(name)
+    }
+
+    /// Site template names, sorted.
+    pub fn template_names(&self) -> Vec<&str> {
+        self.site.keys().map(|k| k.as_str()).collect()
+    }
+
+    /// `(tag, class)` pairs used literally in the site templates.
+    pub fn allowed_classes(&self) -> &[(&'static str, &'static str)] {
+        &self.allowed_classes
+    }
+
+    /// Render a site shortcode. `body` is the already rendered HTML body.
+    /// Errors become an HTML comment so a broken template never breaks a page.
+    pub fn render(
+        &self,
+        name: &str,
+        attrs: &HashMap<String, String>,
+        body: Option<(&str, String)>,
+    ) -> String {
+        let mut ctx = tera::Context::new();
+        for (key, value) in attrs {
+            ctx.insert(key.as_str(), value);
+        }
+        ctx.insert("attrs", attrs);
+        if let Some((raw, html)) = body {
+            ctx.insert("raw_body", raw);
+            ctx.insert("body", &html);
+        }
+
+        match self.templates.render(&format!("{}.html", name), &ctx) {
+            Ok(html) => html,
+            Err(e) => {
+                let detail = std::error::Error::source(&e)
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| e.to_string());
+                tracing::warn!("Failed to render shortcode '{}': {}", name, detail);
+                format!(
+                    "<!-- {} shortcode: {} -->",
+                    name,
+                    detail.replace("--", "- -")
+                )
+            }
+        }
+    }
+
+    /// Every available shortcode, sorted by name. Site templates replace
+    /// built-ins of the same name.
+    pub fn reference(&self) -> Vec<ShortcodeInfo> {
+        let mut all: BTreeMap<String, ShortcodeInfo> = builtin_shortcodes()
+            .into_iter()
+            .map(|info| (info.name.clone(), info))
+            .collect();
+        for (name, info) in &self.site {
+            all.insert(name.clone(), info.clone());
+        }
+        all.into_values().collect()
+    }
+}
+
+/// Read the leading `{# ... #}` comment of a site template:
+///
+/// ```text
+/// {#
+///   Highlighted aside with an optional title.
+///   @attr type Style of the box: note or warning
+///   @required title Heading shown above the body
+///   @body Markdown shown inside the box
+/// #}
+/// ```
+fn parse_header(name: &str, source: &str) -> ShortcodeInfo {
+    let mut description = Vec::new();
+    let mut attributes = Vec::new();
+    let mut body = None;
+
+    if let Some(header) = HEADER_REGEX.captures(source).and_then(|c| c.get(1)) {
+        for line in header
+            .as_str()
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+        {
+            if let Some(rest) = line.strip_prefix("@body") {
+                body = Some(rest.trim().to_string());
+            } else if let Some((required, rest)) = line
+                .strip_prefix("@attr ")
+                .map(|r| (false, r))
+                .or_else(|| line.strip_prefix("@required ").map(|r| (true, r)))
+            {
+                let rest = rest.trim();
+                let (attr, desc) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                attributes.push(ShortcodeAttribute {
+                    name: attr.to_string(),
+                    description: desc.trim().to_string(),
+                    required,
+                });
+            } else {
+                description.push(line.to_string());
+            }
+        }
+    }
+
+    let mut example = format!("[{}", name);
+    for attr in &attributes {
+        example.push_str(&format!(" {}=\"...\"", attr.name));
+    }
+    example.push(']');
+    if body.is_some() {
+        example.push_str(&format!("...[/{}]", name));
+    }
+
+    ShortcodeInfo {
+        name: name.to_string(),
+        aliases: Vec::new(),
+        description: description.join(" "),
+        attributes,
+        body,
+        example,
+        source: ShortcodeSource::Site,
+    }
+}
+
+fn intern(name: &str) -> &'static str {
+    let mut interned = INTERNED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(existing) = interned.get(name) {
+        return existing;
+    }
+    let leaked: &'static str = Box::leak(name.to_string().into_boxed_str());
+    interned.insert(leaked);
+    leaked
+}
+
+/// Load the site's `content.shortcodes_dir`.
+pub fn load_site_shortcodes(config: &Config) -> Result<ShortcodeRegistry> {
+    let registry = ShortcodeRegistry::load(Path::new(&config.content.shortcodes_dir))?;
+    let count = registry.site.len();
+    if count > 0 {
+        tracing::info!(
+            "Loaded {} site shortcode(s): {}",
+            count,
+            registry.template_names().join(", ")
+        );
+    }
+    Ok(registry)
+}
+
+fn attr(name: &str, description: &str, required: bool) -> ShortcodeAttribute {
+    ShortcodeAttribute {
+        name: name.to_string(),
+        description: description.to_string(),
+        required,
+    }
+}
+
+fn builtin(
+    name: &str,
+    aliases: &[&str],
+    description: &str,
+    attributes: Vec<ShortcodeAttribute>,
+    body: Option<&str>,
+    example: &str,
+) -> ShortcodeInfo {
+    ShortcodeInfo {
+        name: name.to_string(),
+        aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        description: description.to_string(),
+        attributes,
+        body: body.map(str::to_string),
+        example: example.to_string(),
+        source: ShortcodeSource::Builtin,
+    }
+}
+
+/// Reference entries for the shortcodes Pebble ships with.
+pub fn builtin_shortcodes() -> Vec<ShortcodeInfo> {
+    vec![
+        builtin(
+            "media",
+            &[],
+            "Embeds an uploaded file, choosing image, video, audio or PDF from its extension.",
+            vec![
+                attr("src", "Media filename", true),
+                attr("title", "Link text for other file types", false),
+            ],
+            None,
+            r#"[media src="photo.jpg"]"#,
+        ),
+        builtin(
+            "image",
+            &["img"],
+            "Responsive image with WebP variants. The alt text is shown as a caption.",
+            vec![
+                attr("src", "Media filename", true),
+                attr("alt", "Alternative text and caption", false),
+                attr("title", "Tooltip text", false),
+                attr("width", "Width in pixels", false),
+                attr("height", "Height in pixels", false),
+                attr("class", "CSS class (default media-image)", false),
+            ],
+            None,
+            r#"[image src="photo.jpg" alt="A sunset"]"#,
+        ),
+        builtin(
+            "video",
+            &[],
+            "Video player for an uploaded MP4 or WebM file.",
+            vec![
+                attr("src", "Media filename", true),
+                attr("poster", "Preview image filename", false),
+                attr("autoplay", "Start playing muted on load", false),
+                attr("loop", "Repeat when finished", false),
+                attr("muted", "Start muted", false),
+                attr("nocontrols", "Hide the player controls", false),
+                attr("class", "CSS class (default media-video)", false),
+            ],
+            None,
+            r#"[video src="clip.mp4" poster="clip.jpg"]"#,
+        ),
+        builtin(
+            "audio",
+            &[],
+            "Audio player for an uploaded MP3 or OGG file.",
+            vec![
+                attr("src", "Media filename", true),
+                attr("autoplay", "Start playing on load", false),
+                attr("loop", "Repeat when finished", false),
+                attr("nocontrols", "Hide the player controls", false),
+                attr("class", "CSS class (default media-audio)", false),
+            ],
+            None,
+            r#"[audio src="episode.mp3"]"#,
+        ),
+        builtin(
+            "gallery",
+            &[],
+            "Grid of image thumbnails linking to the full images.",
+            vec![
+                attr("src", "Comma-separated media filenames", true),
+                attr("columns", "Number of columns (default 3)", false),
+                attr("class", "CSS class (default media-gallery)", false),
+            ],
+            None,
+            r#"[gallery src="a.jpg,b.jpg,c.jpg" columns="3"]"#,
+        ),
+        builtin(
+            "snippet",
+            &[],
+            "Inserts a snippet's Markdown. Expanded when the content is saved.",
+            vec![attr("slug", "Slug of the snippet", true)],
+            None,
+            r#"[snippet slug="newsletter-signup"]"#,
+        ),
+        builtin(
+            "embed",
+            &[],
+            "Click-to-load embed for YouTube, Vimeo, Mastodon, GitHub Gist or CodePen. \
+             Nothing loads from the provider until the reader clicks. A supported URL \
+             on a line of its own is embedded automatically.",
+            vec![
+                attr("url", "Link to the video, post, gist or pen", true),
+                attr("title", "Accessible title and caption", false),
+            ],
+            None,
+            r#"[embed url="https://www.youtube.com/watch?v=dQw4w9WgXcQ" title="Launch talk"]"#,
+        ),
+        builtin(
+            "toc",
+            &[],
+            "Table of contents linking to every heading in the document.",
+            vec![attr("title", "Heading shown above the list", false)],
+            None,
+            r#"[toc title="Contents"]"#,
+        ),
+        builtin(
+            "callout",
+            &["admonition"],
+            "Highlighted box for notes, tips and warnings.",
+            vec![
+                attr("type", "note, tip, info, warning or danger (default note)", false),
+                attr("title", "Heading shown above the body", false),
+            ],
+            Some("Markdown shown inside the box"),
+            "[callout type=\"warning\" title=\"Heads up\"]\nBack up your database first.\n[/callout]",
+        ),
+        builtin(
+            "details",
+            &[],
+            "Collapsible section that readers can expand.",
+            vec![
+                attr("summary", "Text of the toggle (default \"Details\")", false),
+                attr("open", "Start expanded", false),
+            ],
+            Some("Markdown revealed when expanded"),
+            "[details summary=\"Show the full log\"]\nLong output here.\n[/details]",
+        ),
+        builtin(
+            "figure",
+            &[],
+            "Image with a caption. The caption may be given as an attribute or as the body.",
+            vec![
+                attr("src", "Media filename or absolute URL", true),
+                attr("alt", "Alternative text", false),
+                attr("caption", "Caption text", false),
+                attr("width", "Width in pixels", false),
+            ],
+            Some("Caption, with inline Markdown"),
+            r#"[figure src="chart.png" alt="Visitors per month"]Traffic doubled in *March*.[/figure]"#,
+        ),
+        builtin(
+            "button",
+            &[],
+            "Link styled as a button.",
+            vec![
+                attr("href", "Link target", true),
+                attr("label", "Button text, if no body is given", false),
+                attr("style", "primary or secondary (default primary)", false),
+            ],
+            Some("Button text, with inline Markdown"),
+            r#"[button href="/contact" style="secondary"]Get in touch[/button]"#,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_documents_attributes() {
+        let source = "{#\n  Highlighted aside.\n  @required title Heading text\n  @attr tone Colour\n  @body Inner text\n#}\n<aside class=\"aside\">{{ body | safe }}</aside>";
+        let registry =
+            ShortcodeRegistry::from_templates(vec![("aside".to_string(), source.to_string())])
+                .unwrap();
+        let info = registry
+            .reference()
+            .into_iter()
+            .find(|i| i.name == "aside")
+            .unwrap();
+        assert_eq!(info.description, "Highlighted aside.");
+        assert_eq!(info.attributes.len(), 2);
+        assert!(info.attributes[0].required);
+        assert_eq!(info.attributes[1].name, "tone");
+        assert_eq!(info.body.as_deref(), Some("Inner text"));
+        assert_eq!(
+            info.example,
+            "[aside title=\"...\" tone=\"...\"]...[/aside]"
+        );
+        assert_eq!(info.source, ShortcodeSource::Site);
+    }
+
+    #[test]
+    fn test_literal_classes_are_collected() {
+        let source = r#"<div class="card card-{{ tone }} wide"><span class="label">{{ title }}</span></div>"#;
+        let registry =
+            ShortcodeRegistry::from_templates(vec![("card".to_string(), source.to_string())])
+                .unwrap();
+        let classes = registry.allowed_classes();
+        assert!(classes.contains(&("div", "card")));
+        assert!(classes.contains(&("div", "wide")));
+        assert!(classes.contains(&("span", "label")));
+        assert!(!classes.iter().any(|(_, c)| c.contains('{')));
+
+        // Reloading hands out the same interned names instead of leaking new ones
+        let reloaded =
+            ShortcodeRegistry::from_templates(vec![("card".to_string(), source.to_string())])
+                .unwrap();
+        for ((tag, class), (tag2, class2)) in classes.iter().zip(reloaded.allowed_classes()) {
+            assert!(std::ptr::eq(*tag, *tag2));
+            assert!(std::ptr::eq(*class, *class2));
+        }
+    }
+
+    #[test]
+    fn test_site_template_overrides_builtin_and_invalid_template_fails() {
+        let registry = ShortcodeRegistry::from_templates(vec![(
+            "callout".to_string(),
+            "<div>{{ body | safe }}</div>".to_string(),
+        )])
+        .unwrap();
+        let callout = registry
+            .reference()
+            .into_iter()
+            .find(|i| i.name == "callout")
+            .unwrap();
+        assert_eq!(callout.source, ShortcodeSource::Site);
+
+        let broken =
+            ShortcodeRegistry::from_templates(vec![("broken".to_string(), "{% if %}".to_string())]);
+        assert!(broken.is_err());
+    }
+}
@@ -0,0 +1,525 @@
+//! Generated Open Graph images ("social cards") for published posts that
+//! have neither a social nor a featured image. Cards show the post title,
+//! site name and author in the site theme's colors and are cached under
+//! `<media dir>/og/`, keyed by everything drawn on them, so a changed title
+//! or theme produces a fresh card at the same `/og/<slug>.png` URL.
+
+use super::content;
+use super::seo;
+use crate::config::{Config, ThemeConfig};
+use crate::models::{ContentStatus, ContentType, ContentWithTags};
+use crate::Database;
+use ab_glyph::{point, Font, FontRef, GlyphId, PxScale, ScaleFont};
+use anyhow::{Context, Result};
+use image::codecs::png::PngEncoder;
+use image::{ImageEncoder, Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CARD_WIDTH: u32 = 1200;
+pub const CARD_HEIGHT: u32 = 630;
+
+/// Subdirectory of the media directory holding rendered cards.
+const CACHE_DIR: &str = "og";
+
+/// Bump when the layout changes so cached cards are redrawn.
+const LAYOUT_VERSION: u32 = 2;
+
+static FONT_DATA: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf");
+
+const PADDING: f32 = 96.0;
+const ACCENT_BAR_WIDTH: u32 = 16;
+const SITE_NAME_SIZE: f32 = 36.0;
+const AUTHOR_SIZE: f32 = 32.0;
+/// Title sizes tried from largest to smallest until the title fits.
+const TITLE_SIZES: [f32; 4] = [72.0, 64.0, 56.0, 48.0];
+const TITLE_TOP: f32 = 180.0;
+const TITLE_BOTTOM: f32 = 500.0;
+const LINE_HEIGHT: f32 = 1.2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub background: Rgb<u8>,
+    pub text: Rgb<u8>,
+    pub muted: Rgb<u8>,
+    pub accent: Rgb<u8>,
+}
+
+/// Light-mode background, text, muted text and primary colors of the
+/// built-in themes, matching `_themes.css`.
+fn theme_colors(name: &str) -> [&'static str; 4] {
+    match name {
+        "minimal" => ["#ffffff", "#0f172a", "#64748b", "#18181b"],
+        "magazine" => ["#ffffff", "#0f172a", "#64748b", "#b91c1c"],
+        "brutalist" => ["#ffffff", "#000000", "#666666", "#ff0000"],
+        "neon" => ["#0a0a0f", "#e0e0e0", "#707080", "#00ffff"],
+        "serif" => ["#faf8f5", "#3d2c1e", "#8b7a6a", "#c05a33"],
+        "ocean" => ["#f0f8ff", "#112d42", "#5f8aab", "#1e6fa1"],
+        "midnight" => ["#13111a", "#e8e4f0", "#6e6580", "#d4a853"],
+        "botanical" => ["#f9f7f3", "#2b2921", "#7d7968", "#2d6a4f"],
+        "monochrome" => ["#ffffff", "#1a1a1a", "#888888", "#1a1a1a"],
+        "coral" => ["#fffafa", "#2d1f1f", "#a08080", "#ef6461"],
+        "terminal" => ["#0c0c14", "#b8d4b8", "#506850", "#4af626"],
+        "nordic" => ["#fcfcfd", "#2c3440", "#8895a7", "#4a90d9"],
+        "sunset" => ["#fffcf8", "#2d1f10", "#a0855d", "#e67e22"],
+        "typewriter" => ["#f5f0e8", "#2c2416", "#8a7e6e", "#b44e4e"],
+        _ => ["#ffffff", "#0f172a", "#64748b", "#3b82f6"],
+    }
+}
+
+/// Parse a `#rgb` or `#rrggbb` color.
+fn parse_hex_color(value: &str) -> Option<Rgb<u8>> {
+    let hex = value.trim().strip_prefix('#')?;
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        3 => {
+            let mut rgb = [0u8; 3];
+            for (i, c) in hex.chars().enumerate() {
+                rgb[i] = channel(&c.to_string())? * 17;
+            }
+            Some(Rgb(rgb))
+        }
+        6 => Some(Rgb([
+            channel(hex.get(0..2)?)?,
+            channel(hex.get(2..4)?)?,
+            channel(hex.get(4..6)?)?,
+        ])),
+        _ => None,
+    }
+}
+
+impl Palette {
+    /// The theme's colors, with any custom theme colors taking precedence.
+    pub fn from_theme(theme: &ThemeConfig) -> Self {
+        let [background, text, muted, accent] = theme_colors(&theme.name);
+        let pick = |custom: &Option<String>, builtin: &str| {
+            custom
+                .as_deref()
+                .and_then(parse_hex_color)
+                .or_else(|| parse_hex_color(builtin))
+                .unwrap_or(Rgb([0, 0, 0]))
+        };
+        Self {
+            background: pick(&theme.custom.background_color, background),
+            text: pick(&theme.custom.text_color, text),
+            muted: pick(&theme.custom.text_muted, muted),
+            accent: pick(&theme.custom.primary_color, accent),
+        }
+    }
+}
+
+/// What is drawn on a card.
+#[derive(Debug, Clone)]
+pub struct Card {
+    pub title: String,
+    pub site_name: String,
+    pub author: Option<String>,
+}
+
+impl Card {
+    pub fn for_item(item: &ContentWithTags, site_name: &str) -> Self {
+        Self {
+            title: item.content.title.clone(),
+            site_name: site_name.to_string(),
+            author: item.author.as_ref().map(|a| a.username.clone()),
+        }
+    }
+
+    /// Cache key covering everything that affects the rendered image.
+    fn fingerprint(&self, palette: &Palette) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(LAYOUT_VERSION.to_le_bytes());
+        for part in [
+            self.title.as_str(),
+            self.site_name.as_str(),
+            self.author.as_deref().unwrap_or(""),
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        for color in [
+            palette.background,
+            palette.text,
+            palette.muted,
+            palette.accent,
+        ] {
+            hasher.update(color.0);
+        }
+        hex::encode(&hasher.finalize()[..8])
+    }
+}
+
+/// Whether an item is shared with a generated card: published posts
+/// without an image of their own.
+pub fn needs_card(item: &ContentWithTags) -> bool {
+    item.content.content_type == ContentType::Post
+        && item.content.status == ContentStatus::Published
+        && seo::own_image(item).is_none()
+}
+
+/// Site path of a post's card.
+pub fn card_path(slug: &str) -> String {
+    format!("/{}/{}.png", CACHE_DIR, slug)
+}
+
+/// Scale at which glyphs are `size` pixels to the em, the way font sizes
+/// are usually given (ab_glyph's `PxScale` is the ascent-to-descent height).
+fn em_scale(font: &FontRef, size: f32) -> PxScale {
+    let units_per_em = font.units_per_em().unwrap_or(font.height_unscaled());
+    PxScale::from(size * font.height_unscaled() / units_per_em)
+}
+
+/// Distance from the baseline to the top of the tallest glyphs, in pixels.
+fn ascent(font: &FontRef, size: f32) -> f32 {
+    font.as_scaled(em_scale(font, size)).ascent()
+}
+
+/// Glyphs of `text` with their pen positions relative to the start of the
+/// line, kerned pairwise.
+fn layout_glyphs(font: &FontRef, text: &str, size: f32) -> (Vec<(GlyphId, f32)>, f32) {
+    let scaled = font.as_scaled(em_scale(font, size));
+    let mut glyphs = Vec::new();
+    let mut pen = 0.0;
+    let mut previous: Option<GlyphId> = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            pen += scaled.kern(previous, id);
+        }
+        glyphs.push((id, pen));
+        pen += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    (glyphs, pen)
+}
+
+/// Width of `text` set at `size` pixels.
+fn text_width(font: &FontRef, text: &str, size: f32) -> f32 {
+    layout_glyphs(font, text, size).1
+}
+
+/// Draw `text` with its baseline starting at (`x`, `baseline`), blending
+/// each glyph's coverage over the image.
+fn draw_text(
+    font: &FontRef,
+    img: &mut RgbImage,
+    text: &str,
+    x: f32,
+    baseline: f32,
+    size: f32,
+    color: Rgb<u8>,
+) {
+    let scale = em_scale(font, size);
+    let (glyphs, _) = layout_glyphs(font, text, size);
+    for (id, offset) in glyphs {
+        let glyph = id.with_scale_and_position(scale, point(x + offset, baseline));
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i64 + gx as i64;
+            let py = bounds.min.y as i64 + gy as i64;
+            if px < 0 || py < 0 || px >= img.width() as i64 || py >= img.height() as i64 {
+                return;
+            }
+            let alpha = coverage.min(1.0);
+            let pixel = img.get_pixel_mut(px as u32, py as u32);
+            for channel in 0..3 {
+                let under = pixel[channel] as f32;
+                pixel[channel] = (under + (color[channel] as f32 - under) * alpha).round() as u8;
+            }
+        });
+    }
+}
+
+/// Break `text` into lines no wider than `max_width`, splitting words that
+/// do not fit on a line of their own.
+fn wrap_text(font: &FontRef, text: &str, size: f32, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if text_width(font, &candidate, size) <= max_width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            if text_width(font, &line, size) > max_width && line.chars().count() > 1 {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, c.to_string()));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Shorten `line` until it fits with a trailing ellipsis.
+fn ellipsize(font: &FontRef, line: &str, size: f32, max_width: f32) -> String {
+    let mut text = line.trim_end().to_string();
+    loop {
+        let candidate = format!("{}\u{2026}", text);
+        if text.is_empty() || text_width(font, &candidate, size) <= max_width {
+            return candidate;
+        }
+        text.pop();
+        text = text.trim_end().to_string();
+    }
+}
+
+/// Title lines and size: the largest size at which the whole title fits,
+/// or the smallest size with the overflow cut off.
+fn layout_title(font: &FontRef, title: &str, max_width: f32) -> (Vec<String>, f32) {
+    let smallest = TITLE_SIZES[TITLE_SIZES.len() - 1];
+    for size in TITLE_SIZES {
+        let max_lines = ((TITLE_BOTTOM - TITLE_TOP) / (size * LINE_HEIGHT)) as usize;
+        let mut lines = wrap_text(font, title, size, max_width);
+        if lines.len() <= max_lines {
+            return (lines, size);
+        }
+        if size == smallest {
+            lines.truncate(max_lines);
+            if let Some(last) = lines.last_mut() {
+                *last = ellipsize(font, last, size, max_width);
+            }
+            return (lines, size);
+        }
+    }
+    unreachable!("the smallest title size always returns")
+}
+
+/// Render a card as PNG.
+pub fn render(card: &Card, palette: &Palette) -> Result<Vec<u8>> {
+    let font = FontRef::try_from_slice(FONT_DATA).context("bundled card font is invalid")?;
+    let mut img = RgbImage::from_pixel(CARD_WIDTH, CARD_HEIGHT, palette.background);
+    for y in 0..CARD_HEIGHT {
+        for x in 0..ACCENT_BAR_WIDTH {
+            img.put_pixel(x, y, palette.accent);
+        }
+    }
+
+    let max_width = CARD_WIDTH as f32 - 2.0 * PADDING;
+    let site_name = match text_width(&font, &card.site_name, SITE_NAME_SIZE) > max_width {
+        true => ellipsize(&font, &card.site_name, SITE_NAME_SIZE, max_width),
+        false => card.site_name.clone(),
+    };
+    draw_text(
+        &font,
+        &mut img,
+        &site_name,
+        PADDING,
+        PADDING + ascent(&font, SITE_NAME_SIZE),
+        SITE_NAME_SIZE,
+        palette.accent,
+    );
+
+    let (lines, size) = layout_title(&font, &card.title, max_width);
+    let mut baseline = TITLE_TOP + ascent(&font, size);
+    for line in &lines {
+        draw_text(&font, &mut img, line, PADDING, baseline, size, palette.text);
+        baseline += size * LINE_HEIGHT;
+    }
+
+    if let Some(ref author) = card.author {
+        draw_text(
+            &font,
+            &mut img,
+            author,
+            PADDING,
+            CARD_HEIGHT as f32 - PADDING + AUTHOR_SIZE * 0.25,
+            AUTHOR_SIZE,
+            palette.muted,
+        );
+    }
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(
+        img.as_raw(),
+        CARD_WIDTH,
+        CARD_HEIGHT,
+        image::ExtendedColorType::Rgb8,
+    )?;
+    Ok(png)
+}
+
+/// Path of the item's card under `media_dir`, rendering it first if the
+/// cached copy is missing or out of date. Older cards for the item are removed.
+pub fn cached_card(
+    media_dir: &Path,
+    theme: &ThemeConfig,
+    site_name: &str,
+    item: &ContentWithTags,
+) -> Result<PathBuf> {
+    let card = Card::for_item(item, site_name);
+    let palette = Palette::from_theme(theme);
+    let dir = media_dir.join(CACHE_DIR);
+    let prefix = format!("{}-", item.content.id);
+    let filename = format!("{}{}.png", prefix, card.fingerprint(&palette));
+    let path = dir.join(&filename);
+    if path.exists() {
+        return Ok(path);
+    }
+
+    fs::create_dir_all(&dir)?;
+    let png = render(&card, &palette)?;
+    // A name of its own per render, so concurrent first requests for a card
+    // do not write to the same file; the leading dot keeps it out of the
+    // cleanup below
+    let tmp = dir.join(format!(".{}.{}.tmp", filename, uuid::Uuid::new_v4()));
+    fs::write(&tmp, png)?;
+    fs::rename(&tmp, &path)?;
+
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&prefix) && name != filename {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    Ok(path)
+}
+
+/// Render the card of a just-published (or re-saved) post in the background
+/// so the first share does not wait for it.
+pub fn spawn_render(db: &Database, config: &Config, media_dir: &Path, content_id: i64) {
+    if !config.social_cards.enabled {
+        return;
+    }
+    let item = match content::get_content_by_id(db, content_id) {
+        Ok(Some(item)) if needs_card(&item) => item,
+        _ => return,
+    };
+    let media_dir = media_dir.to_path_buf();
+    let theme = config.theme.clone();
+    let site_name = config.site.title.clone();
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = cached_card(&media_dir, &theme, &site_name, &item) {
+            tracing::warn!(
+                "Failed to render social card for {}: {}",
+                item.content.slug,
+                e
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CustomThemeOptions;
+    use crate::models::{Content, UserSummary};
+
+    #[test]
+    fn test_palette_and_wrapping() {
+        let mut theme = ThemeConfig {
+            name: "ocean".to_string(),
+            custom: CustomThemeOptions::default(),
+        };
+        assert_eq!(
+            Palette::from_theme(&theme).background,
+            Rgb([0xf0, 0xf8, 0xff])
+        );
+        theme.custom.primary_color = Some("#f00".to_string());
+        theme.custom.background_color = Some("not a color".to_string());
+        let palette = Palette::from_theme(&theme);
+        assert_eq!(palette.accent, Rgb([255, 0, 0]));
+        assert_eq!(palette.background, Rgb([0xf0, 0xf8, 0xff]));
+
+        let font = FontRef::try_from_slice(FONT_DATA).unwrap();
+        let width = text_width(&font, "Hello", 72.0);
+        assert!(width > 150.0 && width < 300.0, "width {}", width);
+
+        let lines = wrap_text(&font, "one two three four five six", 72.0, 400.0);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| text_width(&font, l, 72.0) <= 400.0));
+        assert_eq!(lines.join(" "), "one two three four five six");
+
+        let long_title = "word ".repeat(200);
+        let (lines, size) = layout_title(&font, &long_title, 1008.0);
+        assert_eq!(size, 48.0);
+        assert!(lines.last().unwrap().ends_with('\u{2026}'));
+    }
+
+    #[test]
+    fn test_render_and_cache() {
+        let dir =
+            std::env::temp_dir().join(format!("pebble_social_card_test_{}", std::process::id()));
+        let theme = ThemeConfig {
+            name: "default".to_string(),
+            custom: CustomThemeOptions::default(),
+        };
+        let mut item = ContentWithTags {
+            content: Content {
+                id: 7,
+                slug: "hello".to_string(),
+                title: "Hello, World".to_string(),
+                content_type: ContentType::Post,
+                body_markdown: String::new(),
+                body_html: String::new(),
+                excerpt: None,
+                featured_image: None,
+                status: ContentStatus::Published,
+                scheduled_at: None,
+                published_at: None,
+                author_id: Some(1),
+                metadata: serde_json::json!({}),
+                created_at: String::new(),
+                updated_at: String::new(),
+                language: None,
+                translation_group: None,
+                entry_type: None,
+                fields: serde_json::json!({}),
+                toc: Vec::new(),
+            },
+            tags: Vec::new(),
+            author: Some(UserSummary {
+                id: 1,
+                username: "alice".to_string(),
+            }),
+        };
+        assert!(needs_card(&item));
+
+        // Concurrent first requests each render to their own temporary file
+        let paths: Vec<PathBuf> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| cached_card(&dir, &theme, "My Blog", &item).unwrap()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(paths.windows(2).all(|w| w[0] == w[1]));
+        let entries: Vec<_> = fs::read_dir(dir.join(CACHE_DIR)).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        let first = cached_card(&dir, &theme, "My Blog", &item).unwrap();
+        assert_eq!(first, paths[0]);
+        let img = image::open(&first).unwrap().to_rgb8();
+        assert_eq!(img.dimensions(), (CARD_WIDTH, CARD_HEIGHT));
+        assert_eq!(*img.get_pixel(2, 2), Rgb([0x3b, 0x82, 0xf6]));
+        // Some title text was drawn in the text color
+        let text = Palette::from_theme(&theme).text;
+        assert!(img.pixels().any(|p| *p == text));
+
+        assert_eq!(cached_card(&dir, &theme, "My Blog", &item).unwrap(), first);
+        item.content.title = "A new title".to_string();
+        let second = cached_card(&dir, &theme, "My Blog", &item).unwrap();
+        assert_ne!(second, first);
+        assert!(!first.exists());
+
+        item.content.featured_image = Some("cover.jpg".to_string());
+        assert!(!needs_card(&item));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1298 @@
+//! Content sync between two Pebble sites, for `pebble sync push/pull`.
+//!
+//! The sending site describes everything that can be synced as a
+//! [`SiteSnapshot`]. The receiving site plans the sync by comparing that
+//! snapshot with its own, and with the hashes it recorded in `sync_state`
+//! the last time it received items from the same sender. The recorded hashes
+//! tell changes made at the source apart from edits made on the receiving
+//! site since, which are reported as conflicts and skipped unless forced.
+//!
+//! Items are matched by slug (content, snippets, tags and series), by
+//! filename (media) or by key (settings).
+
+use crate::models::{ContentStatus, ContentType, CreateContent, UpdateContent};
+use crate::services::audit::{self, AuditAction, AuditCategory, AuditContext, AuditLogBuilder};
+use crate::services::markdown::MarkdownRenderer;
+use crate::services::{content, media, series, settings, tags};
+use crate::{Config, Database};
+use anyhow::{bail, Context, Result};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Setting holding the id other sites know this one by. Settings starting
+/// with `sync_` belong to the site itself and are never synced.
+pub const SITE_ID_SETTING: &str = "sync_site_id";
+const LOCAL_SETTING_PREFIX: &str = "sync_";
+
+/// What an item is. The order is the order inserts and updates are applied
+/// in: tags and media before the content using them, snippets before the
+/// content embedding them and series after their posts. Deletes run in
+/// reverse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Tag,
+    Media,
+    Snippet,
+    Content,
+    Series,
+    Setting,
+}
+
+impl ItemKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tag => "tag",
+            Self::Media => "media",
+            Self::Snippet => "snippet",
+            Self::Content => "content",
+            Self::Series => "series",
+            Self::Setting => "setting",
+        }
+    }
+}
+
+/// Everything a site can sync, as sent to the other site.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteSnapshot {
+    pub site_id: String,
+    pub title: String,
+    pub url: String,
+    pub pebble_version: String,
+    pub tags: Vec<SyncTag>,
+    pub media: Vec<SyncMedia>,
+    /// Posts, pages, entries and snippets
+    pub content: Vec<SyncContent>,
+    pub series: Vec<SyncSeries>,
+    pub settings: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncTag {
+    pub slug: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncMedia {
+    pub filename: String,
+    pub original_name: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub alt_text: String,
+    pub webp_filename: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    /// The uploaded file and the variants generated from it (WebP,
+    /// thumbnail, responsive sizes)
+    pub files: Vec<MediaFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaFile {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncContent {
+    pub slug: String,
+    pub title: String,
+    pub content_type: ContentType,
+    pub entry_type: Option<String>,
+    pub body_markdown: String,
+    pub excerpt: Option<String>,
+    pub featured_image: Option<String>,
+    pub status: ContentStatus,
+    pub scheduled_at: Option<String>,
+    pub published_at: Option<String>,
+    pub metadata: serde_json::Value,
+    pub language: Option<String>,
+    pub translation_group: Option<String>,
+    pub fields: serde_json::Value,
+    /// Tag slugs, sorted
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncSeries {
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub status: String,
+    /// Slugs of the series' content, in order
+    pub items: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncOptions {
+    /// Also delete items that exist only on the receiving site
+    #[serde(default)]
+    pub delete: bool,
+    /// Apply conflicting changes instead of skipping them
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Body of the `plan` and `apply` sync API requests.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncRequest {
+    pub source: SiteSnapshot,
+    #[serde(default)]
+    pub options: SyncOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncAction {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl SyncAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Insert => "insert",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedChange {
+    pub kind: ItemKind,
+    pub key: String,
+    pub title: String,
+    pub action: SyncAction,
+    /// Why applying the change would overwrite or lose edits made on the
+    /// receiving site
+    pub conflict: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPlan {
+    pub options: SyncOptions,
+    /// Changes in apply order
+    pub changes: Vec<PlannedChange>,
+    pub unchanged: usize,
+    /// Items only on the receiving site, never received from the source,
+    /// that are kept because `delete` is off
+    pub kept: usize,
+}
+
+impl SyncPlan {
+    pub fn conflicts(&self) -> usize {
+        self.changes.iter().filter(|c| c.conflict.is_some()).count()
+    }
+
+    /// Whether `change` is applied: conflicts only when forced.
+    pub fn applies(&self, change: &PlannedChange) -> bool {
+        change.conflict.is_none() || self.options.force
+    }
+
+    /// Media files the receiving site needs staged before `apply`.
+    pub fn media_files<'a>(&self, source: &'a SiteSnapshot) -> Vec<&'a MediaFile> {
+        let wanted: BTreeSet<&str> = self
+            .changes
+            .iter()
+            .filter(|c| c.kind == ItemKind::Media && c.action != SyncAction::Delete)
+            .filter(|c| self.applies(c))
+            .map(|c| c.key.as_str())
+            .collect();
+        source
+            .media
+            .iter()
+            .filter(|m| wanted.contains(m.filename.as_str()))
+            .flat_map(|m| m.files.iter())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedChange {
+    pub change: PlannedChange,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub applied: Vec<PlannedChange>,
+    /// Conflicting changes left out
+    pub skipped: Vec<PlannedChange>,
+    pub failed: Vec<FailedChange>,
+    pub unchanged: usize,
+    pub kept: usize,
+}
+
+impl SyncReport {
+    pub fn count(&self, action: SyncAction) -> usize {
+        self.applied.iter().filter(|c| c.action == action).count()
+    }
+}
+
+/// The id this site is known by to the sites it syncs with, created on
+/// first use.
+pub fn site_id(db: &Database) -> Result<String> {
+    if let Some(id) = settings::get_setting(db, SITE_ID_SETTING)? {
+        return Ok(id);
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    settings::set_setting(db, SITE_ID_SETTING, &id)?;
+    Ok(id)
+}
+
+/// Give a copied site its own sync id, so the sites it syncs with stop
+/// treating it as the original.
+pub fn reset_site_id(db: &Database) -> Result<()> {
+    settings::delete_setting(db, SITE_ID_SETTING)
+}
+
+/// Describe everything this site can sync.
+pub fn snapshot(db: &Database, config: &Config) -> Result<SiteSnapshot> {
+    let site_id = site_id(db)?;
+    let conn = db.get()?;
+
+    let tags = conn
+        .prepare("SELECT slug, name FROM tags ORDER BY slug")?
+        .query_map([], |row| {
+            Ok(SyncTag {
+                slug: row.get(0)?,
+                name: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let upload_dir = Path::new(&config.media.upload_dir);
+    let mut files: Vec<String> = match std::fs::read_dir(upload_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+    let mut media_rows = conn.prepare(
+        "SELECT filename, original_name, mime_type, size_bytes, COALESCE(alt_text, ''), webp_filename, width, height
+         FROM media ORDER BY filename",
+    )?;
+    let media = media_rows
+        .query_map([], |row| {
+            Ok(SyncMedia {
+                filename: row.get(0)?,
+                original_name: row.get(1)?,
+                mime_type: row.get(2)?,
+                size_bytes: row.get(3)?,
+                alt_text: row.get(4)?,
+                webp_filename: row.get(5)?,
+                width: row.get(6)?,
+                height: row.get(7)?,
+                files: Vec::new(),
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter()
+        .map(|mut m| {
+            m.files = media_files(upload_dir, &files, &m.filename)?;
+            Ok(m)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut content_tags: HashMap<i64, Vec<String>> = HashMap::new();
+    let mut tag_rows = conn.prepare(
+        "SELECT ct.content_id, t.slug FROM content_tags ct JOIN tags t ON t.id = ct.tag_id ORDER BY t.slug",
+    )?;
+    for row in tag_rows.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get(1)?)))? {
+        let (id, slug) = row?;
+        content_tags.entry(id).or_default().push(slug);
+    }
+
+    let mut content_rows = conn.prepare(
+        "SELECT id, slug, title, content_type, entry_type, body_markdown, excerpt, featured_image, status,
+                scheduled_at, published_at, metadata, language, translation_group, fields
+         FROM content ORDER BY slug",
+    )?;
+    let content = content_rows
+        .query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            let content_type: String = row.get(3)?;
+            let status: String = row.get(8)?;
+            let metadata: Option<String> = row.get(11)?;
+            let fields: Option<String> = row.get(14)?;
+            Ok(SyncContent {
+                slug: row.get(1)?,
+                title: row.get(2)?,
+                content_type: content_type.parse().unwrap_or_default(),
+                entry_type: row.get(4)?,
+                body_markdown: row.get(5)?,
+                excerpt: row.get(6)?,
+                featured_image: row.get(7)?,
+                status: status.parse().unwrap_or_default(),
+                scheduled_at: row.get(9)?,
+                published_at: row.get(10)?,
+                metadata: parse_json(metadata.as_deref()),
+                language: row.get(12)?,
+                translation_group: row.get(13)?,
+                fields: parse_json(fields.as_deref()),
+                tags: content_tags.remove(&id).unwrap_or_default(),
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut series_rows = conn
+        .prepare("SELECT id, slug, title, description, status FROM content_series ORDER BY slug")?;
+    let mut item_rows = conn.prepare(
+        "SELECT c.slug FROM series_items si JOIN content c ON c.id = si.content_id
+         WHERE si.series_id = ? ORDER BY si.position",
+    )?;
+    let mut series = Vec::new();
+    for row in series_rows.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    })? {
+        let (id, slug, title, description, status) = row?;
+        let items = item_rows
+            .query_map([id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        series.push(SyncSeries {
+            slug,
+            title,
+            description: description.unwrap_or_default(),
+            status,
+            items,
+        });
+    }
+
+    let settings = conn
+        .prepare("SELECT key, value FROM settings ORDER BY key")?
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(String, String)>>>()?
+        .into_iter()
+        .filter(|(key, _)| !key.starts_with(LOCAL_SETTING_PREFIX))
+        .collect();
+
+    Ok(SiteSnapshot {
+        site_id,
+        title: config.site.title.clone(),
+        url: config.site.url.clone(),
+        pebble_version: env!("CARGO_PKG_VERSION").to_string(),
+        tags,
+        media,
+        content,
+        series,
+        settings,
+    })
+}
+
+fn parse_json(raw: Option<&str>) -> serde_json::Value {
+    raw.and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or_else(|| serde_json::json!({}))
+}
+
+/// The files in `files` belonging to the upload `filename`: the file itself
+/// and the variants named after it (`<stem>.webp`, `<stem>-thumb.webp`,
+/// `<stem>-800w.webp`, ...).
+fn media_files(upload_dir: &Path, files: &[String], filename: &str) -> Result<Vec<MediaFile>> {
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    let dotted = format!("{}.", stem);
+    let dashed = format!("{}-", stem);
+    files
+        .iter()
+        .filter(|name| *name == filename || name.starts_with(&dotted) || name.starts_with(&dashed))
+        .map(|name| {
+            let data = std::fs::read(upload_dir.join(name))
+                .with_context(|| format!("Failed to read media file {}", name))?;
+            Ok(MediaFile {
+                name: name.clone(),
+                size: data.len() as u64,
+                sha256: hex::encode(Sha256::digest(&data)),
+            })
+        })
+        .collect()
+}
+
+/// An item of a snapshot, reduced to what planning needs.
+struct Item {
+    title: String,
+    hash: String,
+}
+
+fn items(snapshot: &SiteSnapshot) -> Result<BTreeMap<(ItemKind, String), Item>> {
+    let mut items = BTreeMap::new();
+    let mut add = |kind: ItemKind, key: &str, title: &str, value: serde_json::Value| {
+        items.insert(
+            (kind, key.to_string()),
+            Item {
+                title: title.to_string(),
+                hash: hash(&value),
+            },
+        );
+    };
+    for tag in &snapshot.tags {
+        add(
+            ItemKind::Tag,
+            &tag.slug,
+            &tag.name,
+            serde_json::to_value(tag)?,
+        );
+    }
+    for m in &snapshot.media {
+        add(
+            ItemKind::Media,
+            &m.filename,
+            &m.original_name,
+            serde_json::to_value(m)?,
+        );
+    }
+    for c in &snapshot.content {
+        let kind = match c.content_type {
+            ContentType::Snippet => ItemKind::Snippet,
+            _ => ItemKind::Content,
+        };
+        add(kind, &c.slug, &c.title, serde_json::to_value(c)?);
+    }
+    for s in &snapshot.series {
+        add(
+            ItemKind::Series,
+            &s.slug,
+            &s.title,
+            serde_json::to_value(s)?,
+        );
+    }
+    for (key, value) in &snapshot.settings {
+        add(ItemKind::Setting, key, key, serde_json::json!(value));
+    }
+    Ok(items)
+}
+
+/// SHA-256 of a value's JSON with object keys sorted, so the hash does not
+/// depend on the order metadata keys were written in.
+fn hash(value: &serde_json::Value) -> String {
+    fn canonical(value: &serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let sorted: BTreeMap<&String, serde_json::Value> =
+                    map.iter().map(|(k, v)| (k, canonical(v))).collect();
+                serde_json::Value::Object(sorted.into_iter().map(|(k, v)| (k.clone(), v)).collect())
+            }
+            serde_json::Value::Array(values) => {
+                serde_json::Value::Array(values.iter().map(canonical).collect())
+            }
+            other => other.clone(),
+        }
+    }
+    hex::encode(Sha256::digest(canonical(value).to_string().as_bytes()))
+}
+
+/// The hashes recorded after the last sync from `peer_id`.
+fn recorded_hashes(db: &Database, peer_id: &str) -> Result<HashMap<(ItemKind, String), String>> {
+    let conn = db.get()?;
+    let rows = conn
+        .prepare("SELECT kind, item_key, hash FROM sync_state WHERE peer_id = ?")?
+        .query_map([peer_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(kind, key, hash)| {
+            let kind: ItemKind = serde_json::from_value(serde_json::json!(kind)).ok()?;
+            Some(((kind, key), hash))
+        })
+        .collect())
+}
+
+/// Work out what receiving `source` would change on this site.
+pub fn plan(
+    db: &Database,
+    config: &Config,
+    source: &SiteSnapshot,
+    options: &SyncOptions,
+) -> Result<SyncPlan> {
+    let target = snapshot(db, config)?;
+    if source.site_id == target.site_id {
+        bail!(
+            "Both sites have the same sync id ({}). Either they are the same site, or one was copied \
+             from the other; run `pebble sync reset-id` on the copy",
+            target.site_id
+        );
+    }
+
+    let recorded = recorded_hashes(db, &source.site_id)?;
+    let source_items = items(source)?;
+    let target_items = items(&target)?;
+    let keys: BTreeSet<&(ItemKind, String)> =
+        source_items.keys().chain(target_items.keys()).collect();
+
+    let mut plan = SyncPlan {
+        options: options.clone(),
+        changes: Vec::new(),
+        unchanged: 0,
+        kept: 0,
+    };
+    let mut deletes = Vec::new();
+    for key in keys {
+        let last = recorded.get(key);
+        let change = |action, title: &str, conflict: Option<&str>| PlannedChange {
+            kind: key.0,
+            key: key.1.clone(),
+            title: title.to_string(),
+            action,
+            conflict: conflict.map(str::to_string),
+        };
+        match (source_items.get(key), target_items.get(key)) {
+            (Some(s), Some(t)) if s.hash == t.hash => plan.unchanged += 1,
+            (Some(s), Some(t)) => {
+                let conflict = match last {
+                    None => Some("differs on both sites and was never synced"),
+                    Some(last) if *last != t.hash => {
+                        Some("changed on this site since the last sync")
+                    }
+                    Some(_) => None,
+                };
+                plan.changes
+                    .push(change(SyncAction::Update, &s.title, conflict));
+            }
+            (Some(s), None) => {
+                let conflict = last.map(|_| "deleted on this site since the last sync");
+                plan.changes
+                    .push(change(SyncAction::Insert, &s.title, conflict));
+            }
+            (None, Some(t)) => match last {
+                Some(last) if *last == t.hash => {
+                    deletes.push(change(SyncAction::Delete, &t.title, None))
+                }
+                Some(_) => deletes.push(change(
+                    SyncAction::Delete,
+                    &t.title,
+                    Some("changed on this site since the last sync"),
+                )),
+                None if options.delete => deletes.push(change(SyncAction::Delete, &t.title, None)),
+                None => plan.kept += 1,
+            },
+            (None, None) => {}
+        }
+    }
+    deletes.sort_by(|a, b| b.kind.cmp(&a.kind).then_with(|| a.key.cmp(&b.key)));
+    plan.changes.extend(deletes);
+    Ok(plan)
+}
+
+/// Where media files for a sync are staged before `apply`, next to the
+/// upload directory so they are never served.
+pub fn staging_dir(upload_dir: &Path) -> PathBuf {
+    let name = upload_dir
+        .file_name()
+        .map(|n| format!("{}-sync", n.to_string_lossy()))
+        .unwrap_or_else(|| "media-sync".to_string());
+    upload_dir.with_file_name(name)
+}
+
+/// Stage a media file for the next `apply`.
+pub fn stage_media_file(upload_dir: &Path, name: &str, data: &[u8]) -> Result<()> {
+    check_file_name(name)?;
+    let dir = staging_dir(upload_dir);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join(name), data)?;
+    Ok(())
+}
+
+/// Read a media file of this site for the other site to stage.
+pub fn read_media_file(upload_dir: &Path, name: &str) -> Result<Vec<u8>> {
+    check_file_name(name)?;
+    std::fs::read(upload_dir.join(name)).with_context(|| format!("Media file not found: {}", name))
+}
+
+fn check_file_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("Invalid media file name: {}", name);
+    }
+    Ok(())
+}
+
+/// Receive `source`: plan the sync, apply the changes that are not
+/// conflicts (or all of them with `force`), remember what was received and
+/// record the sync in the audit log. Media files must have been staged with
+/// [`stage_media_file`] first.
+pub fn apply(
+    db: &Database,
+    config: &Config,
+    renderer: &MarkdownRenderer,
+    source: &SiteSnapshot,
+    options: &SyncOptions,
+    audit_ctx: &AuditContext,
+) -> Result<SyncReport> {
+    let plan = plan(db, config, source, options)?;
+    let upload_dir = Path::new(&config.media.upload_dir);
+    let staging = staging_dir(upload_dir);
+    let source_items = items(source)?;
+
+    let mut report = SyncReport {
+        applied: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+        unchanged: plan.unchanged,
+        kept: plan.kept,
+    };
+    for change in &plan.changes {
+        if !plan.applies(change) {
+            report.skipped.push(change.clone());
+            continue;
+        }
+        let result = apply_change(db, config, renderer, source, change, &staging).and_then(|_| {
+            let hash = source_items
+                .get(&(change.kind, change.key.clone()))
+                .map(|i| i.hash.as_str());
+            record(db, &source.site_id, change.kind, &change.key, hash)
+        });
+        match result {
+            Ok(()) => report.applied.push(change.clone()),
+            Err(e) => report.failed.push(FailedChange {
+                change: change.clone(),
+                error: format!("{:#}", e),
+            }),
+        }
+    }
+
+    // Items already the same on both sites are the baseline for next time
+    let target_items = items(&snapshot(db, config)?)?;
+    for (key, item) in &source_items {
+        if target_items.get(key).is_some_and(|t| t.hash == item.hash) {
+            record(db, &source.site_id, key.0, &key.1, Some(&item.hash))?;
+        }
+    }
+    let _ = std::fs::remove_dir_all(&staging);
+
+    let mut builder = AuditLogBuilder::new(AuditAction::Sync, AuditCategory::System)
+        .entity_type_only("site")
+        .metadata_value("source", serde_json::json!(source.url))
+        .metadata_value("source_site_id", serde_json::json!(source.site_id))
+        .metadata_value(
+            "inserted",
+            serde_json::json!(report.count(SyncAction::Insert)),
+        )
+        .metadata_value(
+            "updated",
+            serde_json::json!(report.count(SyncAction::Update)),
+        )
+        .metadata_value(
+            "deleted",
+            serde_json::json!(report.count(SyncAction::Delete)),
+        )
+        .metadata_value("conflicts_skipped", serde_json::json!(report.skipped.len()))
+        .metadata_value("forced", serde_json::json!(options.force))
+        .changes(serde_json::json!(report.applied));
+    if !report.failed.is_empty() {
+        builder = builder.failure(&format!("{} change(s) failed", report.failed.len()));
+    }
+    if let Err(e) = audit::log(db, audit_ctx, builder) {
+        tracing::warn!("Failed to record the sync in the audit log: {}", e);
+    }
+
+    Ok(report)
+}
+
+fn record(
+    db: &Database,
+    peer_id: &str,
+    kind: ItemKind,
+    key: &str,
+    hash: Option<&str>,
+) -> Result<()> {
+    let conn = db.get()?;
+    match hash {
+        Some(hash) => conn.execute(
+            "INSERT INTO sync_state (peer_id, kind, item_key, hash, synced_at) VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+             ON CONFLICT(peer_id, kind, item_key) DO UPDATE SET hash = excluded.hash, synced_at = CURRENT_TIMESTAMP",
+            (peer_id, kind.as_str(), key, hash),
+        )?,
+        None => conn.execute(
+            "DELETE FROM sync_state WHERE peer_id = ? AND kind = ? AND item_key = ?",
+            (peer_id, kind.as_str(), key),
+        )?,
+    };
+    Ok(())
+}
+
+fn apply_change(
+    db: &Database,
+    config: &Config,
+    renderer: &MarkdownRenderer,
+    source: &SiteSnapshot,
+    change: &PlannedChange,
+    staging: &Path,
+) -> Result<()> {
+    let key = change.key.as_str();
+    let delete = change.action == SyncAction::Delete;
+    match change.kind {
+        ItemKind::Tag => {
+            let existing = tags::get_tag_by_slug(db, key)?;
+            if delete {
+                if let Some(tag) = existing {
+                    tags::delete_tag(db, tag.id)?;
+                }
+                return Ok(());
+            }
+            let tag = source
+                .tags
+                .iter()
+                .find(|t| t.slug == key)
+                .context("Tag missing from source")?;
+            match existing {
+                Some(existing) => tags::update_tag(db, existing.id, &tag.name, Some(&tag.slug)),
+                None => tags::create_tag(db, &tag.name, Some(&tag.slug)).map(|_| ()),
+            }
+        }
+        ItemKind::Media => {
+            let upload_dir = Path::new(&config.media.upload_dir);
+            if delete {
+                return delete_media(db, upload_dir, key);
+            }
+            let item = source
+                .media
+                .iter()
+                .find(|m| m.filename == key)
+                .context("Media missing from source")?;
+            apply_media(db, upload_dir, item, staging)
+        }
+        ItemKind::Snippet | ItemKind::Content => {
+            let existing: Option<i64> = db
+                .get()?
+                .query_row("SELECT id FROM content WHERE slug = ?", [key], |row| {
+                    row.get(0)
+                })
+                .optional()?;
+            if delete {
+                if let Some(id) = existing {
+                    content::delete_content(db, renderer, id)?;
+                }
+                return Ok(());
+            }
+            let item = source
+                .content
+                .iter()
+                .find(|c| c.slug == key)
+                .context("Content missing from source")?;
+            apply_content(db, config, renderer, item, existing)
+        }
+        ItemKind::Series => {
+            let existing = series::get_series_by_slug(db, key)?;
+            if delete {
+                if let Some(existing) = existing {
+                    series::delete_series(db, existing.id)?;
+                }
+                return Ok(());
+            }
+            let item = source
+                .series
+                .iter()
+                .find(|s| s.slug == key)
+                .context("Series missing from source")?;
+            apply_series(db, item, existing.map(|s| s.id))
+        }
+        ItemKind::Setting => {
+            if delete {
+                return settings::delete_setting(db, key);
+            }
+            let value = source
+                .settings
+                .get(key)
+                .context("Setting missing from source")?;
+            settings::set_setting(db, key, value)
+        }
+    }
+}
+
+/// Create or update a content item through the content service, so it is
+/// rendered with this site's snippets and versioned, then set the fields
+/// the service manages itself to the source's values.
+fn apply_content(
+    db: &Database,
+    config: &Config,
+    renderer: &MarkdownRenderer,
+    item: &SyncContent,
+    existing: Option<i64>,
+) -> Result<()> {
+    let id = match existing {
+        Some(id) => {
+            let input = UpdateContent {
+                title: Some(item.title.clone()),
+                body_markdown: Some(item.body_markdown.clone()),
+                fields: Some(item.fields.clone()),
+                ..Default::default()
+            };
+            content::update_content(
+                db,
+                renderer,
+                &config.content_types,
+                id,
+                input,
+                None,
+                config.content.version_retention,
+            )?;
+            id
+        }
+        None => {
+            let input = CreateContent {
+                title: item.title.clone(),
+                slug: Some(item.slug.clone()),
+                content_type: item.content_type,
+                body_markdown: item.body_markdown.clone(),
+                excerpt: item.excerpt.clone(),
+                featured_image: item.featured_image.clone(),
+                status: ContentStatus::Draft,
+                scheduled_at: None,
+                tags: Vec::new(),
+                metadata: Some(item.metadata.clone()),
+                entry_type: item.entry_type.clone(),
+                fields: Some(item.fields.clone()),
+            };
+            content::create_content(
+                db,
+                renderer,
+                &config.content_types,
+                input,
+                None,
+                config.content.excerpt_length,
+            )?
+        }
+    };
+
+    let mut conn = db.get()?;
+    let tx = conn.transaction()?;
+    tx.execute(
+        "UPDATE content SET content_type = ?, entry_type = ?, excerpt = ?, featured_image = ?, status = ?,
+                scheduled_at = ?, published_at = ?, metadata = ?, language = ?, translation_group = ?
+         WHERE id = ?",
+        rusqlite::params![
+            item.content_type.to_string(),
+            item.entry_type,
+            item.excerpt,
+            item.featured_image,
+            item.status.to_string(),
+            item.scheduled_at,
+            item.published_at,
+            serde_json::to_string(&item.metadata)?,
+            item.language,
+            item.translation_group,
+            id,
+        ],
+    )?;
+    tx.execute("DELETE FROM content_tags WHERE content_id = ?", [id])?;
+    for slug in &item.tags {
+        tx.execute(
+            "INSERT OR IGNORE INTO content_tags (content_id, tag_id) SELECT ?, id FROM tags WHERE slug = ?",
+            (id, slug),
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn apply_series(db: &Database, item: &SyncSeries, existing: Option<i64>) -> Result<()> {
+    let id = match existing {
+        Some(id) => {
+            series::update_series(
+                db,
+                id,
+                Some(&item.title),
+                Some(&item.slug),
+                Some(&item.description),
+                Some(&item.status),
+            )?;
+            id
+        }
+        None => series::create_series(
+            db,
+            &item.title,
+            Some(&item.slug),
+            &item.description,
+            &item.status,
+        )?,
+    };
+    db.get()?
+        .execute("DELETE FROM series_items WHERE series_id = ?", [id])?;
+    let conn = db.get()?;
+    for slug in &item.items {
+        let content_id: Option<i64> = conn
+            .query_row("SELECT id FROM content WHERE slug = ?", [slug], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        match content_id {
+            Some(content_id) => series::add_item_to_series(db, id, content_id)?,
+            None => tracing::warn!(
+                "Series '{}' lists '{}', which is not on this site",
+                item.slug,
+                slug
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Move the staged files of a media item into the upload directory, after
+/// checking them against the source's hashes, and write its database row.
+fn apply_media(db: &Database, upload_dir: &Path, item: &SyncMedia, staging: &Path) -> Result<()> {
+    for file in &item.files {
+        check_file_name(&file.name)?;
+        let staged = staging.join(&file.name);
+        let data = std::fs::read(&staged)
+            .with_context(|| format!("Media file {} was not staged", file.name))?;
+        if hex::encode(Sha256::digest(&data)) != file.sha256 {
+            bail!("Media file {} does not match the source", file.name);
+        }
+    }
+    std::fs::create_dir_all(upload_dir)?;
+    for file in &item.files {
+        std::fs::copy(staging.join(&file.name), upload_dir.join(&file.name))?;
+    }
+
+    db.get()?.execute(
+        "INSERT INTO media (filename, original_name, mime_type, size_bytes, alt_text, webp_filename, width, height)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(filename) DO UPDATE SET original_name = excluded.original_name, mime_type = excluded.mime_type,
+             size_bytes = excluded.size_bytes, alt_text = excluded.alt_text, webp_filename = excluded.webp_filename,
+             width = excluded.width, height = excluded.height",
+        rusqlite::params![
+            item.filename,
+            item.original_name,
+            item.mime_type,
+            item.size_bytes,
+            item.alt_text,
+            item.webp_filename,
+            item.width,
+            item.height,
+        ],
+    )?;
+    Ok(())
+}
+
+fn delete_media(db: &Database, upload_dir: &Path, filename: &str) -> Result<()> {
+    let id: Option<i64> = db
+        .get()?
+        .query_row(
+            "SELECT id FROM media WHERE filename = ?",
+            [filename],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(id) = id else {
+        return Ok(());
+    };
+    media::delete_media(db, upload_dir, id)?;
+
+    // delete_media leaves the responsive size variants behind
+    let files: Vec<String> = std::fs::read_dir(upload_dir)?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    for file in media_files(upload_dir, &files, filename)? {
+        std::fs::remove_file(upload_dir.join(&file.name))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(name: &str) -> (Database, Config) {
+        let db = Database::open_memory(&format!("sync_{}_{}", name, std::process::id())).unwrap();
+        db.migrate().unwrap();
+        let upload_dir = std::env::temp_dir()
+            .join(format!("pebble-sync-{}-{}", name, std::process::id()))
+            .join("media");
+        let config: Config = toml::from_str(&format!(
+            r#"
+[site]
+title = "{name}"
+description = ""
+url = "http://{name}.test"
+
+[server]
+host = "127.0.0.1"
+port = 3000
+
+[database]
+path = ":memory:"
+
+[content]
+
+[media]
+upload_dir = "{}"
+
+[theme]
+name = "default"
+
+[auth]
+"#,
+            upload_dir.display()
+        ))
+        .unwrap();
+        (db, config)
+    }
+
+    fn post(db: &Database, slug: &str, body: &str, tags: &[&str]) -> i64 {
+        content::create_content(
+            db,
+            &MarkdownRenderer::new(),
+            &[],
+            CreateContent {
+                title: slug.replace('-', " "),
+                slug: Some(slug.to_string()),
+                content_type: ContentType::Post,
+                body_markdown: body.to_string(),
+                excerpt: None,
+                featured_image: None,
+                status: ContentStatus::Published,
+                scheduled_at: None,
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                metadata: None,
+                entry_type: None,
+                fields: None,
+            },
+            None,
+            200,
+        )
+        .unwrap()
+    }
+
+    fn actions(plan: &SyncPlan) -> Vec<(ItemKind, &str, SyncAction, bool)> {
+        plan.changes
+            .iter()
+            .map(|c| (c.kind, c.key.as_str(), c.action, c.conflict.is_some()))
+            .collect()
+    }
+
+    #[test]
+    fn test_sync_inserts_updates_and_reports_conflicts() {
+        let (staging, staging_config) = site("staging");
+        let (production, production_config) = site("production");
+        let ctx = AuditContext::new();
+        let options = SyncOptions::default();
+
+        post(&staging, "hello", "First version", &["rust"]);
+        settings::set_setting(&staging, settings::HOMEPAGE_TITLE, "Welcome").unwrap();
+        post(&production, "about", "Written on production", &[]);
+
+        let source = snapshot(&staging, &staging_config).unwrap();
+        let plan = plan(&production, &production_config, &source, &options).unwrap();
+        assert_eq!(
+            actions(&plan),
+            vec![
+                (ItemKind::Tag, "rust", SyncAction::Insert, false),
+                (ItemKind::Content, "hello", SyncAction::Insert, false),
+                (
+                    ItemKind::Setting,
+                    "homepage_title",
+                    SyncAction::Insert,
+                    false
+                ),
+            ]
+        );
+        assert_eq!(plan.kept, 1);
+
+        let report = apply(
+            &production,
+            &production_config,
+            &MarkdownRenderer::new(),
+            &source,
+            &options,
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(report.applied.len(), 3);
+        assert!(report.failed.is_empty());
+        let synced = content::get_content_by_slug(&production, "hello")
+            .unwrap()
+            .unwrap();
+        assert_eq!(synced.content.status, ContentStatus::Published);
+        assert_eq!(synced.tags[0].slug, "rust");
+        assert!(synced.content.body_html.contains("First version"));
+
+        // A second sync has nothing to do
+        let plan = super::plan(&production, &production_config, &source, &options).unwrap();
+        assert!(plan.changes.is_empty());
+
+        // Edited on both sites: a conflict, skipped unless forced
+        let staging_id = content::get_content_by_slug(&staging, "hello")
+            .unwrap()
+            .unwrap()
+            .content
+            .id;
+        let update = |body: &str| UpdateContent {
+            body_markdown: Some(body.to_string()),
+            ..Default::default()
+        };
+        content::update_content(
+            &staging,
+            &MarkdownRenderer::new(),
+            &[],
+            staging_id,
+            update("Second version"),
+            None,
+            10,
+        )
+        .unwrap();
+        let source = snapshot(&staging, &staging_config).unwrap();
+        let plan = super::plan(&production, &production_config, &source, &options).unwrap();
+        assert_eq!(
+            actions(&plan),
+            vec![(ItemKind::Content, "hello", SyncAction::Update, false)]
+        );
+
+        content::update_content(
+            &production,
+            &MarkdownRenderer::new(),
+            &[],
+            synced.content.id,
+            update("Hotfix"),
+            None,
+            10,
+        )
+        .unwrap();
+        let report = apply(
+            &production,
+            &production_config,
+            &MarkdownRenderer::new(),
+            &source,
+            &options,
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].conflict.is_some());
+
+        let forced = SyncOptions {
+            force: true,
+            ..Default::default()
+        };
+        let report = apply(
+            &production,
+            &production_config,
+            &MarkdownRenderer::new(),
+            &source,
+            &forced,
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(report.count(SyncAction::Update), 1);
+        let synced = content::get_content_by_slug(&production, "hello")
+            .unwrap()
+            .unwrap();
+        assert_eq!(synced.content.body_markdown, "Second version");
+
+        let logged: i64 = production
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM audit_logs WHERE action = 'sync'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(logged, 3);
+    }
+
+    #[test]
+    fn test_sync_deletes_only_what_came_from_the_source() {
+        let (staging, staging_config) = site("staging-delete");
+        let (production, production_config) = site("production-delete");
+        let ctx = AuditContext::new();
+        let options = SyncOptions::default();
+
+        let id = post(&staging, "draft-idea", "Maybe", &[]);
+        post(&production, "legal", "Production only", &[]);
+        let source = snapshot(&staging, &staging_config).unwrap();
+        apply(
+            &production,
+            &production_config,
+            &MarkdownRenderer::new(),
+            &source,
+            &options,
+            &ctx,
+        )
+        .unwrap();
+
+        content::delete_content(&staging, &MarkdownRenderer::new(), id).unwrap();
+        let source = snapshot(&staging, &staging_config).unwrap();
+        let plan = plan(&production, &production_config, &source, &options).unwrap();
+        assert_eq!(
+            actions(&plan),
+            vec![(ItemKind::Content, "draft-idea", SyncAction::Delete, false)]
+        );
+        assert_eq!(plan.kept, 1);
+
+        let everything = SyncOptions {
+            delete: true,
+            ..Default::default()
+        };
+        let plan = super::plan(&production, &production_config, &source, &everything).unwrap();
+        assert_eq!(plan.changes.len(), 2);
+    }
+
+    #[test]
+    fn test_sync_copies_media_files() {
+        let (staging, staging_config) = site("staging-media");
+        let (production, production_config) = site("production-media");
+        let upload_dir = Path::new(&staging_config.media.upload_dir);
+        std::fs::create_dir_all(upload_dir).unwrap();
+        std::fs::write(upload_dir.join("abc.pdf"), b"%PDF-1.4").unwrap();
+        staging
+            .get()
+            .unwrap()
+            .execute(
+                "INSERT INTO media (filename, original_name, mime_type, size_bytes) VALUES ('abc.pdf', 'Menu.pdf', 'application/pdf', 8)",
+                [],
+            )
+            .unwrap();
+
+        let source = snapshot(&staging, &staging_config).unwrap();
+        let options = SyncOptions::default();
+        let plan = plan(&production, &production_config, &source, &options).unwrap();
+        let files = plan.media_files(&source);
+        assert_eq!(files.len(), 1);
+
+        let target_dir = Path::new(&production_config.media.upload_dir);
+        for file in files {
+            let data = read_media_file(upload_dir, &file.name).unwrap();
+            stage_media_file(target_dir, &file.name, &data).unwrap();
+        }
+        let report = apply(
+            &production,
+            &production_config,
+            &MarkdownRenderer::new(),
+            &source,
+            &options,
+            &AuditContext::new(),
+        )
+        .unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(
+            std::fs::read(target_dir.join("abc.pdf")).unwrap(),
+            b"%PDF-1.4"
+        );
+        assert!(!staging_dir(target_dir).exists());
+        assert!(stage_media_file(target_dir, "../escape", b"x").is_err());
+
+        for config in [&staging_config, &production_config] {
+            let _ = std::fs::remove_dir_all(Path::new(&config.media.upload_dir).parent().unwrap());
+        }
+    }
+}
@@ -119,7 +119,8 @@ pub fn get_posts_by_tag(
         r#"
         SELECT c.id, c.slug, c.title, c.content_type, c.body_markdown, c.body_html,
                c.excerpt, c.featured_image, c.status, c.scheduled_at, c.published_at,
-               c.author_id, c.metadata, c.created_at, c.updated_at
+               c.author_id, c.metadata, c.created_at, c.updated_at, c.language, c.translation_group,
+               c.entry_type, c.fields, c.toc
         FROM content c
         JOIN content_tags ct ON c.id = ct.content_id
         JOIN tags t ON ct.tag_id = t.id
@@ -156,6 +157,12 @@ pub fn get_posts_by_tag(
                 metadata,
                 created_at: row.get(13)?,
                 updated_at: row.get(14)?,
+                language: row.get(15)?,
+                translation_group: row.get(16)?,
+                entry_type: row.get(17)?,
+                fields: serde_json::from_str(&row.get::<_, String>(18)?)
+                    .unwrap_or(serde_json::json!({})),
+                toc: serde_json::from_str(&row.get::<_, String>(19)?).unwrap_or_default(),
             })
         })?
         .filter_map(|r| r.ok())
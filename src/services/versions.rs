@@ -3,6 +3,7 @@
 //! Provides functionality for tracking content revisions, viewing history,
 //! comparing versions, and restoring previous versions.
 
+use crate::services::markdown::MarkdownRenderer;
 use crate::Database;
 use anyhow::Result;
 use rusqlite::Connection;
@@ -260,6 +261,7 @@ pub fn get_version_by_number(
 /// Creates a backup version of current state first, then applies the old version.
 pub fn restore_version(
     db: &Database,
+    renderer: &MarkdownRenderer,
     content_id: i64,
     version_id: i64,
     user_id: Option<i64>,
@@ -277,6 +279,14 @@ pub fn restore_version(
     let mut conn = db.get()?;
     let tx = conn.transaction()?;
 
+    let snippet_slug: Option<String> = tx
+        .query_row(
+            "SELECT slug FROM content WHERE id = ?1 AND content_type = 'snippet'",
+            [content_id],
+            |row| row.get(0),
+        )
+        .ok();
+
     // Update the content with the old version's data
     tx.execute(
         r#"
@@ -296,15 +306,6 @@ pub fn restore_version(
         ],
     )?;
 
-    // Re-render the markdown to HTML
-    let renderer = crate::services::markdown::MarkdownRenderer::new();
-    let body_html = renderer.render(&version.body_markdown);
-
-    tx.execute(
-        "UPDATE content SET body_html = ?1 WHERE id = ?2",
-        rusqlite::params![body_html, content_id],
-    )?;
-
     // Restore tags
     tx.execute(
         "DELETE FROM content_tags WHERE content_id = ?1",
@@ -336,6 +337,16 @@ pub fn restore_version(
 
     tx.commit()?;
 
+    // Re-render the markdown to HTML, along with anything embedding a restored snippet
+    crate::services::content::rerender_content(db, renderer, content_id)?;
+    if let Some(previous_slug) = snippet_slug {
+        crate::services::content::rerender_dependents(
+            db,
+            renderer,
+            &[previous_slug.as_str(), version.slug.as_str()],
+        )?;
+    }
+
     tracing::info!(
         "Restored content {} to version {} (v{})",
         content_id,
@@ -117,6 +117,23 @@ pub fn list_deliveries(db: &Database, webhook_id: i64, limit: i64) -> Result<Vec
     Ok(deliveries)
 }
 
+/// Shared outbound HTTP client for webhook deliveries and Webmention fetches.
+/// Timeouts and a redirect cap keep a slow or misbehaving remote from tying
+/// up background tasks.
+#[cfg(feature = "webhooks")]
+pub fn http_client() -> reqwest::Client {
+    static CLIENT: once_cell::sync::Lazy<reqwest::Client> = once_cell::sync::Lazy::new(|| {
+        reqwest::Client::builder()
+            .connect_timeout(std::time::Duration::from_secs(5))
+            .timeout(std::time::Duration::from_secs(15))
+            .redirect(reqwest::redirect::Policy::limited(5))
+            .user_agent("Pebble-CMS/1.0")
+            .build()
+            .unwrap_or_default()
+    });
+    CLIENT.clone()
+}
+
 /// Fire webhooks for a given event. Sends HTTP POST to each matching active webhook.
 /// Uses HMAC-SHA256 to sign the payload if the webhook has a secret.
 /// Runs asynchronously via tokio::spawn — does not block the caller.
@@ -169,7 +186,7 @@ pub fn fire_webhooks(db: &Database, event: &str, payload: serde_json::Value) {
             while attempts < max_attempts && !success {
                 attempts += 1;
 
-                let client = reqwest::Client::new();
+                let client = http_client();
                 let mut request = client
                     .post(&webhook.url)
                     .header("Content-Type", "application/json")
@@ -0,0 +1,1448 @@
+//! Webmention and Pingback — accept mentions of our content from other sites
+//! (verified asynchronously by fetching the source), and notify the sites we
+//! link to when content is published.
+
+use crate::models::{
+    Content, ContentStatus, ContentType, MentionType, Webmention, WebmentionGroups, WebmentionSend,
+    WebmentionStatus, WebmentionWithContent,
+};
+use crate::services::{content, i18n};
+use crate::{Config, Database};
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+
+/// Largest source or target document read while verifying or discovering endpoints.
+#[cfg_attr(not(feature = "webhooks"), allow(dead_code))]
+const MAX_FETCH_BYTES: usize = 1024 * 1024;
+/// Cap on notifications sent per publish, so a link roundup can't stall the sender.
+const MAX_OUTBOUND_LINKS: usize = 50;
+const MAX_SUMMARY_LENGTH: usize = 280;
+const MAX_URL_LENGTH: usize = 2000;
+/// How far past the start of an h-card we look for its name, URL and photo.
+const AUTHOR_SCAN_BYTES: usize = 3000;
+
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<([a-z][a-z0-9]*)\b([^>]*)>").unwrap());
+static ATTR_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)([a-z_:][-a-z0-9_:.]*)\s*(?:=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#)
+        .unwrap()
+});
+static STRIP_TAGS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+static LINK_HEADER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<([^>]*)>((?:\s*;[^,;]*)*)").unwrap());
+static REL_PARAM_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)rel\s*=\s*(?:"([^"]*)"|([^\s;,]+))"#).unwrap());
+static XMLRPC_METHOD_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<methodName>\s*([^<]*?)\s*</methodName>").unwrap());
+static XMLRPC_VALUE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)<value>\s*(?:<string>([^<]*)</string>|([^<]*))\s*</value>").unwrap()
+});
+
+pub const PROTOCOL_WEBMENTION: &str = "webmention";
+pub const PROTOCOL_PINGBACK: &str = "pingback";
+
+/// XML-RPC fault codes from the Pingback 1.0 specification.
+pub const PINGBACK_FAULT_GENERIC: i32 = 0;
+pub const PINGBACK_FAULT_TARGET_INVALID: i32 = 33;
+
+const WEBMENTION_COLUMNS: &str = "w.id, w.content_id, w.source, w.target, w.protocol, w.mention_type, w.status, w.author_name, w.author_url, w.author_photo, w.summary, w.error, w.created_at, w.verified_at";
+
+fn row_to_webmention(row: &rusqlite::Row) -> rusqlite::Result<Webmention> {
+    let mention_type: String = row.get(5)?;
+    let status: String = row.get(6)?;
+    Ok(Webmention {
+        id: row.get(0)?,
+        content_id: row.get(1)?,
+        source: row.get(2)?,
+        target: row.get(3)?,
+        protocol: row.get(4)?,
+        mention_type: mention_type.parse().unwrap_or_default(),
+        status: status.parse().unwrap_or_default(),
+        author_name: row.get(7)?,
+        author_url: row.get(8)?,
+        author_photo: row.get(9)?,
+        summary: row.get(10)?,
+        error: row.get(11)?,
+        created_at: row.get(12)?,
+        verified_at: row.get(13)?,
+    })
+}
+
+// ----------------------------------------------------------------------------
+// Receiving
+// ----------------------------------------------------------------------------
+
+fn parse_http_url(raw: &str) -> Option<Url> {
+    let raw = raw.trim();
+    if raw.len() > MAX_URL_LENGTH {
+        return None;
+    }
+    Url::parse(raw)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https") && u.host_str().is_some())
+}
+
+/// Compare URLs ignoring fragments and a trailing slash.
+fn same_url(a: &Url, b: &Url) -> bool {
+    let normalize = |u: &Url| {
+        let mut u = u.clone();
+        u.set_fragment(None);
+        u.to_string().trim_end_matches('/').to_string()
+    };
+    normalize(a) == normalize(b)
+}
+
+/// Public URL of a post or page, including any language prefix, used as the
+/// `source` of outgoing mentions. Snippets have no public URL.
+pub fn content_url(config: &Config, content: &Content) -> Option<String> {
+    if content.content_type == ContentType::Snippet {
+        return None;
+    }
+    Some(format!(
+        "{}{}",
+        config.site.url.trim_end_matches('/'),
+        i18n::localized_content_path(content, &config.site.language, &config.i18n)
+    ))
+}
+
+/// Map a mention target to the published post or page it refers to.
+pub fn resolve_target(db: &Database, site_url: &str, target: &str) -> Result<i64> {
+    let site = Url::parse(site_url)
+        .map_err(|_| anyhow::anyhow!("Site URL is not configured correctly"))?;
+    let target =
+        parse_http_url(target).ok_or_else(|| anyhow::anyhow!("Target must be an http(s) URL"))?;
+    if target.host_str() != site.host_str()
+        || target.port_or_known_default() != site.port_or_known_default()
+    {
+        bail!("Target is not on this site");
+    }
+
+    // Content in additional languages lives under a /<lang> prefix
+    let path = target.path().trim_end_matches('/');
+    let path = match path.strip_prefix('/').and_then(|p| p.split_once('/')) {
+        Some((lang, _)) if i18n::is_valid_language_code(lang) => &path[lang.len() + 1..],
+        _ => path,
+    };
+    let (content_type, slug) = if let Some(slug) = path.strip_prefix("/posts/") {
+        (ContentType::Post, slug)
+    } else if let Some(slug) = path.strip_prefix("/pages/") {
+        (ContentType::Page, slug)
+    } else {
+        bail!("Target does not accept mentions");
+    };
+
+    match content::get_content_by_slug(db, slug)? {
+        Some(c)
+            if c.content.content_type == content_type
+                && c.content.status == ContentStatus::Published =>
+        {
+            Ok(c.content.id)
+        }
+        _ => bail!("Target does not exist"),
+    }
+}
+
+/// Validate an incoming mention and queue it for verification. Repeat
+/// notifications for the same source and target update the existing row, so
+/// a sender re-notifying after an edit triggers a fresh verification.
+pub fn receive_mention(
+    db: &Database,
+    site_url: &str,
+    source: &str,
+    target: &str,
+    protocol: &str,
+) -> Result<i64> {
+    let source_url =
+        parse_http_url(source).ok_or_else(|| anyhow::anyhow!("Source must be an http(s) URL"))?;
+    let target_url =
+        parse_http_url(target).ok_or_else(|| anyhow::anyhow!("Target must be an http(s) URL"))?;
+    if same_url(&source_url, &target_url) {
+        bail!("Source and target must be different");
+    }
+    let content_id = resolve_target(db, site_url, target)?;
+
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO webmentions (content_id, source, target, protocol) VALUES (?, ?, ?, ?)
+         ON CONFLICT(source, target) DO UPDATE SET content_id = excluded.content_id, protocol = excluded.protocol, status = 'pending'",
+        rusqlite::params![content_id, source.trim(), target.trim(), protocol],
+    )?;
+    let id = conn.query_row(
+        "SELECT id FROM webmentions WHERE source = ? AND target = ?",
+        [source.trim(), target.trim()],
+        |row| row.get(0),
+    )?;
+    Ok(id)
+}
+
+pub fn get_webmention(db: &Database, id: i64) -> Result<Option<Webmention>> {
+    let conn = db.get()?;
+    let mention = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM webmentions w WHERE w.id = ?",
+                WEBMENTION_COLUMNS
+            ),
+            [id],
+            row_to_webmention,
+        )
+        .ok();
+    Ok(mention)
+}
+
+/// Verified mentions of a content item, grouped by type for display.
+pub fn list_for_content(db: &Database, content_id: i64) -> Result<WebmentionGroups> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM webmentions w WHERE w.content_id = ? AND w.status = 'verified' ORDER BY w.created_at ASC, w.id ASC",
+        WEBMENTION_COLUMNS
+    ))?;
+    let mentions: Vec<Webmention> = stmt
+        .query_map([content_id], row_to_webmention)?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    let mut groups = WebmentionGroups {
+        total: mentions.len(),
+        ..Default::default()
+    };
+    for m in mentions {
+        match m.mention_type {
+            MentionType::Like => groups.likes.push(m),
+            MentionType::Repost => groups.reposts.push(m),
+            MentionType::Reply => groups.replies.push(m),
+            MentionType::Mention | MentionType::Bookmark => groups.mentions.push(m),
+        }
+    }
+    Ok(groups)
+}
+
+/// List received mentions, newest first, optionally filtered by status.
+pub fn list_webmentions(
+    db: &Database,
+    status: Option<WebmentionStatus>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<WebmentionWithContent>> {
+    let conn = db.get()?;
+    let status_filter = status.map(|s| s.to_string());
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, c.title, c.slug FROM webmentions w JOIN content c ON c.id = w.content_id
+         WHERE (?1 IS NULL OR w.status = ?1) ORDER BY w.created_at DESC, w.id DESC LIMIT ?2 OFFSET ?3",
+        WEBMENTION_COLUMNS
+    ))?;
+    let mentions = stmt
+        .query_map(
+            rusqlite::params![status_filter, limit as i64, offset as i64],
+            |row| {
+                Ok(WebmentionWithContent {
+                    webmention: row_to_webmention(row)?,
+                    content_title: row.get(14)?,
+                    content_slug: row.get(15)?,
+                })
+            },
+        )?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(mentions)
+}
+
+pub fn count_webmentions(db: &Database, status: Option<WebmentionStatus>) -> Result<i64> {
+    let conn = db.get()?;
+    let status_filter = status.map(|s| s.to_string());
+    let count = conn.query_row(
+        "SELECT COUNT(*) FROM webmentions WHERE (?1 IS NULL OR status = ?1)",
+        [status_filter],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+pub fn delete_webmention(db: &Database, id: i64) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute("DELETE FROM webmentions WHERE id = ?", [id])?;
+    Ok(())
+}
+
+/// What a fetched source page says about the target.
+#[derive(Debug, Clone, Default)]
+pub struct SourceAnalysis {
+    pub links_to_target: bool,
+    pub mention_type: MentionType,
+    pub author_name: Option<String>,
+    pub author_url: Option<String>,
+    pub author_photo: Option<String>,
+    pub summary: Option<String>,
+}
+
+pub fn mark_verified(db: &Database, id: i64, analysis: &SourceAnalysis) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute(
+        "UPDATE webmentions SET status = 'verified', mention_type = ?, author_name = ?, author_url = ?, author_photo = ?, summary = ?, error = NULL, verified_at = CURRENT_TIMESTAMP WHERE id = ?",
+        rusqlite::params![
+            analysis.mention_type.to_string(),
+            analysis.author_name,
+            analysis.author_url,
+            analysis.author_photo,
+            analysis.summary,
+            id,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn mark_rejected(db: &Database, id: i64, reason: &str) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute(
+        "UPDATE webmentions SET status = 'rejected', error = ? WHERE id = ? ",
+        rusqlite::params![reason, id],
+    )?;
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// HTML scanning
+// ----------------------------------------------------------------------------
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    ATTR_RE.captures_iter(attrs).find_map(|c| {
+        if !c[1].eq_ignore_ascii_case(name) {
+            return None;
+        }
+        let value = c
+            .get(2)
+            .or_else(|| c.get(3))
+            .or_else(|| c.get(4))
+            .map(|m| m.as_str())
+            .unwrap_or("");
+        Some(decode_entities(value.trim()))
+    })
+}
+
+fn has_token(attrs: &str, name: &str, token: &str) -> bool {
+    attr(attrs, name)
+        .map(|v| v.split_whitespace().any(|t| t.eq_ignore_ascii_case(token)))
+        .unwrap_or(false)
+}
+
+fn floor_boundary(s: &str, mut index: usize) -> usize {
+    index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Plain text following an opening tag, up to the first closing tag. Nested
+/// void elements such as `<img>` are skipped.
+fn text_after(html: &str, end_of_tag: usize) -> Option<String> {
+    let rest = &html[end_of_tag..];
+    let rest = &rest[..rest.find("</").unwrap_or(rest.len())];
+    let text = STRIP_TAGS_RE.replace_all(rest, "");
+    let text = decode_entities(&text.split_whitespace().collect::<Vec<_>>().join(" "));
+    (!text.is_empty()).then_some(text)
+}
+
+fn resolve_http(base: &Url, href: &str) -> Option<String> {
+    base.join(href)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .map(|u| u.to_string())
+}
+
+fn truncate_chars(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_string()
+    } else {
+        let truncated: String = s.chars().take(max - 1).collect();
+        format!("{}…", truncated.trim_end())
+    }
+}
+
+/// Inspect a source document: does it link to `target`, how (reply, like,
+/// repost, bookmark), and who wrote it (from the h-card, if any).
+pub fn analyze_source(html: &str, source_url: &Url, target: &str) -> SourceAnalysis {
+    let mut analysis = SourceAnalysis::default();
+    let target = match Url::parse(target) {
+        Ok(t) => t,
+        Err(_) => return analysis,
+    };
+
+    for caps in TAG_RE.captures_iter(html) {
+        let name = caps[1].to_ascii_lowercase();
+        let attrs = &caps[2];
+        let link = match name.as_str() {
+            "a" | "link" | "area" => attr(attrs, "href"),
+            "img" | "video" | "audio" | "source" => attr(attrs, "src"),
+            _ => None,
+        };
+        let Some(link) = link.and_then(|l| source_url.join(&l).ok()) else {
+            continue;
+        };
+        if !same_url(&link, &target) {
+            continue;
+        }
+        analysis.links_to_target = true;
+
+        let kind = if has_token(attrs, "class", "u-like-of") {
+            MentionType::Like
+        } else if has_token(attrs, "class", "u-repost-of") {
+            MentionType::Repost
+        } else if has_token(attrs, "class", "u-in-reply-to") {
+            MentionType::Reply
+        } else if has_token(attrs, "class", "u-bookmark-of") {
+            MentionType::Bookmark
+        } else {
+            MentionType::Mention
+        };
+        if analysis.mention_type == MentionType::Mention {
+            analysis.mention_type = kind;
+        }
+    }
+
+    if !analysis.links_to_target {
+        return analysis;
+    }
+
+    extract_author(html, source_url, &mut analysis);
+
+    // Replies and plain mentions carry a short excerpt of the source content
+    if matches!(
+        analysis.mention_type,
+        MentionType::Reply | MentionType::Mention
+    ) {
+        let content_tag = TAG_RE.captures_iter(html).find(|c| {
+            has_token(&c[2], "class", "e-content") || has_token(&c[2], "class", "p-content")
+        });
+        if let Some(tag) = content_tag {
+            let start = tag.get(0).map(|m| m.end()).unwrap_or(0);
+            let end = floor_boundary(html, start + MAX_SUMMARY_LENGTH * 8);
+            let text = STRIP_TAGS_RE.replace_all(&html[start..end], "");
+            let text = decode_entities(&text.split_whitespace().collect::<Vec<_>>().join(" "));
+            if !text.is_empty() {
+                analysis.summary = Some(truncate_chars(&text, MAX_SUMMARY_LENGTH));
+            }
+        }
+    }
+
+    analysis
+}
+
+fn extract_author(html: &str, source_url: &Url, analysis: &mut SourceAnalysis) {
+    let cards: Vec<_> = TAG_RE
+        .captures_iter(html)
+        .filter(|c| has_token(&c[2], "class", "h-card"))
+        .collect();
+    let card = cards
+        .iter()
+        .find(|c| has_token(&c[2], "class", "p-author"))
+        .or_else(|| cards.first());
+
+    let Some(card) = card else {
+        analysis.author_name = source_url.host_str().map(|h| h.to_string());
+        return;
+    };
+
+    let card_match = card.get(0).expect("whole match");
+    let card_attrs = &card[2];
+    let scan_end = floor_boundary(html, card_match.end() + AUTHOR_SCAN_BYTES);
+    let scope = &html[card_match.start()..scan_end];
+
+    for tag in TAG_RE.captures_iter(scope) {
+        let attrs = &tag[2];
+        let tag_end = tag.get(0).map(|m| m.end()).unwrap_or(0);
+        if analysis.author_name.is_none() && has_token(attrs, "class", "p-name") {
+            analysis.author_name = attr(attrs, "alt").or_else(|| text_after(scope, tag_end));
+        }
+        if analysis.author_url.is_none() && has_token(attrs, "class", "u-url") {
+            analysis.author_url = attr(attrs, "href").and_then(|h| resolve_http(source_url, &h));
+        }
+        if analysis.author_photo.is_none() && has_token(attrs, "class", "u-photo") {
+            analysis.author_photo = attr(attrs, "src").and_then(|s| resolve_http(source_url, &s));
+        }
+    }
+
+    // A bare `<a class="h-card" href="...">Name</a>` is its own name and URL
+    if analysis.author_name.is_none() {
+        analysis.author_name = text_after(html, card_match.end());
+    }
+    if analysis.author_url.is_none() {
+        analysis.author_url = attr(card_attrs, "href").and_then(|h| resolve_http(source_url, &h));
+    }
+    if analysis.author_name.is_none() {
+        analysis.author_name = source_url.host_str().map(|h| h.to_string());
+    }
+    analysis.author_name = analysis.author_name.take().map(|n| truncate_chars(&n, 100));
+}
+
+/// Where to send a notification for a target page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Webmention(Url),
+    Pingback(Url),
+}
+
+impl Endpoint {
+    pub fn url(&self) -> &Url {
+        match self {
+            Self::Webmention(u) | Self::Pingback(u) => u,
+        }
+    }
+
+    pub fn protocol(&self) -> &'static str {
+        match self {
+            Self::Webmention(_) => PROTOCOL_WEBMENTION,
+            Self::Pingback(_) => PROTOCOL_PINGBACK,
+        }
+    }
+}
+
+/// Discover a target's notification endpoint. Webmention is preferred (HTTP
+/// `Link` header, then `<link>`/`<a rel="webmention">`); Pingback
+/// (`X-Pingback` header, then `<link rel="pingback">`) is the fallback.
+pub fn discover_endpoint(
+    link_headers: &[String],
+    pingback_header: Option<&str>,
+    html: &str,
+    base: &Url,
+) -> Option<Endpoint> {
+    for header in link_headers {
+        for caps in LINK_HEADER_RE.captures_iter(header) {
+            let is_webmention = REL_PARAM_RE.captures_iter(&caps[2]).any(|rel| {
+                rel.get(1)
+                    .or_else(|| rel.get(2))
+                    .map(|m| {
+                        m.as_str()
+                            .split_whitespace()
+                            .any(|t| t.eq_ignore_ascii_case("webmention"))
+                    })
+                    .unwrap_or(false)
+            });
+            if is_webmention {
+                if let Ok(url) = base.join(caps[1].trim()) {
+                    return Some(Endpoint::Webmention(url));
+                }
+            }
+        }
+    }
+
+    let rel_link = |rel: &str| {
+        TAG_RE.captures_iter(html).find_map(|c| {
+            let name = c[1].to_ascii_lowercase();
+            if (name == "link" || name == "a") && has_token(&c[2], "rel", rel) {
+                attr(&c[2], "href").and_then(|h| base.join(&h).ok())
+            } else {
+                None
+            }
+        })
+    };
+
+    if let Some(url) = rel_link("webmention") {
+        return Some(Endpoint::Webmention(url));
+    }
+    if let Some(url) = pingback_header.and_then(|h| Url::parse(h.trim()).ok()) {
+        return Some(Endpoint::Pingback(url));
+    }
+    rel_link("pingback").map(Endpoint::Pingback)
+}
+
+/// Links in rendered content that Webmentions are sent to, capped at
+/// [`MAX_OUTBOUND_LINKS`].
+pub fn extract_outbound_links(body_html: &str, site_url: &str) -> Vec<String> {
+    let mut links = external_links(body_html, site_url);
+    links.truncate(MAX_OUTBOUND_LINKS);
+    links
+}
+
+/// External http(s) links in rendered content, in document order, without
+/// duplicates or fragments.
+pub fn external_links(body_html: &str, site_url: &str) -> Vec<String> {
+    let site_host = Url::parse(site_url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()));
+    let mut links: Vec<String> = Vec::new();
+
+    for caps in TAG_RE.captures_iter(body_html) {
+        if !caps[1].eq_ignore_ascii_case("a") {
+            continue;
+        }
+        let Some(mut url) = attr(&caps[2], "href").and_then(|h| parse_http_url(&h)) else {
+            continue;
+        };
+        if url.host_str().map(|h| h.to_string()) == site_host {
+            continue;
+        }
+        url.set_fragment(None);
+        let url = url.to_string();
+        if !links.contains(&url) {
+            links.push(url);
+        }
+    }
+    links
+}
+
+// ----------------------------------------------------------------------------
+// Pingback (XML-RPC)
+// ----------------------------------------------------------------------------
+
+/// Parse a `pingback.ping` XML-RPC call into its (source, target) arguments.
+pub fn parse_pingback_request(xml: &str) -> Option<(String, String)> {
+    let method = XMLRPC_METHOD_RE.captures(xml)?;
+    if method[1].trim() != "pingback.ping" {
+        return None;
+    }
+    let mut values = XMLRPC_VALUE_RE.captures_iter(xml).map(|c| {
+        let raw = c
+            .get(1)
+            .or_else(|| c.get(2))
+            .map(|m| m.as_str())
+            .unwrap_or("");
+        decode_entities(raw.trim())
+    });
+    let source = values.next()?;
+    let target = values.next()?;
+    Some((source, target))
+}
+
+pub fn pingback_request(source: &str, target: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\n<methodCall><methodName>pingback.ping</methodName><params><param><value><string>{}</string></value></param><param><value><string>{}</string></value></param></params></methodCall>",
+        quick_xml::escape::escape(source),
+        quick_xml::escape::escape(target)
+    )
+}
+
+pub fn pingback_success(message: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\n<methodResponse><params><param><value><string>{}</string></value></param></params></methodResponse>",
+        quick_xml::escape::escape(message)
+    )
+}
+
+pub fn pingback_fault(code: i32, message: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\n<methodResponse><fault><value><struct><member><name>faultCode</name><value><int>{}</int></value></member><member><name>faultString</name><value><string>{}</string></value></member></struct></value></fault></methodResponse>",
+        code,
+        quick_xml::escape::escape(message)
+    )
+}
+
+// ----------------------------------------------------------------------------
+// Sending
+// ----------------------------------------------------------------------------
+
+#[cfg_attr(not(feature = "webhooks"), allow(dead_code))]
+fn log_send(
+    db: &Database,
+    content_id: i64,
+    target: &str,
+    endpoint: Option<&Endpoint>,
+    response_status: Option<i32>,
+    success: bool,
+    error: Option<&str>,
+) -> Result<()> {
+    let conn = db.get()?;
+    conn.execute(
+        "INSERT INTO webmention_sends (content_id, target, endpoint, protocol, response_status, success, error) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        rusqlite::params![
+            content_id,
+            target,
+            endpoint.map(|e| e.url().to_string()),
+            endpoint.map(|e| e.protocol()),
+            response_status,
+            success,
+            error,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Recent outgoing notifications, newest first.
+pub fn list_sends(db: &Database, limit: usize) -> Result<Vec<WebmentionSend>> {
+    let conn = db.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.content_id, c.title, s.target, s.endpoint, s.protocol, s.response_status, s.success, s.error, s.sent_at
+         FROM webmention_sends s JOIN content c ON c.id = s.content_id
+         ORDER BY s.sent_at DESC, s.id DESC LIMIT ?",
+    )?;
+    let sends = stmt
+        .query_map([limit as i64], |row| {
+            Ok(WebmentionSend {
+                id: row.get(0)?,
+                content_id: row.get(1)?,
+                content_title: row.get(2)?,
+                target: row.get(3)?,
+                endpoint: row.get(4)?,
+                protocol: row.get(5)?,
+                response_status: row.get(6)?,
+                success: row.get(7)?,
+                error: row.get(8)?,
+                sent_at: row.get(9)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(sends)
+}
+
+/// Send notifications for the outbound links of a just-published (or
+/// re-saved) post or page, if the site has sending enabled.
+pub fn notify_published(db: &Database, config: &Config, content_id: i64) {
+    if !config.webmentions.enabled || !config.webmentions.send_on_publish {
+        return;
+    }
+    let item = match content::get_content_by_id(db, content_id) {
+        Ok(Some(item)) if item.content.status == ContentStatus::Published => item,
+        _ => return,
+    };
+    let Some(source) = content_url(config, &item.content) else {
+        return;
+    };
+    let links = extract_outbound_links(&item.content.body_html, &config.site.url);
+    if links.is_empty() {
+        return;
+    }
+    spawn_send(
+        db,
+        content_id,
+        source,
+        links,
+        config.webmentions.allow_private_sources,
+    );
+}
+
+#[cfg(feature = "webhooks")]
+struct FetchedPage {
+    status: u16,
+    final_url: Url,
+    content_type: String,
+    link_headers: Vec<String>,
+    pingback_header: Option<String>,
+    body: String,
+}
+
+/// Client for fetching mention sources, which anyone can name. Unless
+/// `allow_private` is set, hosts on loopback, private, link-local and other
+/// non-public addresses are refused, both for the source URL and for every
+/// redirect, so a mention cannot make the server probe its own network.
+#[cfg(feature = "webhooks")]
+fn source_client(allow_private: bool) -> reqwest::Client {
+    if allow_private {
+        return super::webhook::http_client();
+    }
+    static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+        reqwest::Client::builder()
+            .connect_timeout(std::time::Duration::from_secs(5))
+            .timeout(std::time::Duration::from_secs(15))
+            .redirect(reqwest::redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= 5 {
+                    attempt.error("too many redirects")
+                } else if let Err(e) = check_public_host(attempt.url()) {
+                    attempt.error(e.to_string())
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .dns_resolver(std::sync::Arc::new(PublicResolver))
+            .user_agent("Pebble-CMS/1.0")
+            .build()
+            .unwrap_or_default()
+    });
+    CLIENT.clone()
+}
+
+/// Refuse URLs whose host is a non-public IP address. Host names are
+/// checked when they are resolved, by [`PublicResolver`].
+#[cfg(feature = "webhooks")]
+fn check_public_host(url: &Url) -> Result<()> {
+    let ip = match url.host() {
+        Some(url::Host::Ipv4(ip)) => std::net::IpAddr::V4(ip),
+        Some(url::Host::Ipv6(ip)) => std::net::IpAddr::V6(ip),
+        _ => return Ok(()),
+    };
+    if !is_public_address(ip) {
+        bail!("{} is not a public address", ip);
+    }
+    Ok(())
+}
+
+/// DNS resolution that drops non-public addresses, failing when none remain.
+#[cfg(feature = "webhooks")]
+struct PublicResolver;
+
+#[cfg(feature = "webhooks")]
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<std::net::SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_address(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} does not resolve to a public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is a globally routable unicast address.
+#[cfg_attr(not(feature = "webhooks"), allow(dead_code))]
+fn is_public_address(ip: std::net::IpAddr) -> bool {
+    use std::net::IpAddr;
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                || a >= 240
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Refuse `url` if it names a non-public IP address, unless `allow_private`.
+#[cfg(feature = "webhooks")]
+fn check_url(url: &str, allow_private: bool) -> Result<()> {
+    match Url::parse(url) {
+        Ok(url) if !allow_private => check_public_host(&url),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "webhooks")]
+async fn fetch_page(url: &str, allow_private: bool) -> Result<FetchedPage> {
+    check_url(url, allow_private)?;
+    let mut resp = source_client(allow_private)
+        .get(url)
+        .header(reqwest::header::ACCEPT, "text/html, */*;q=0.5")
+        .send()
+        .await?;
+
+    let headers = resp.headers();
+    let link_headers = headers
+        .get_all(reqwest::header::LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok().map(String::from))
+        .collect();
+    let pingback_header = headers
+        .get("x-pingback")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let content_type = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let status = resp.status().as_u16();
+    let final_url = resp.url().clone();
+
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        let remaining = MAX_FETCH_BYTES - body.len();
+        body.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+        if body.len() >= MAX_FETCH_BYTES {
+            break;
+        }
+    }
+
+    Ok(FetchedPage {
+        status,
+        final_url,
+        content_type,
+        link_headers,
+        pingback_header,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// Fetch a mention's source and mark it verified or rejected. Sources that
+/// return 410 Gone, or no longer link to the target, are rejected so that
+/// deleted replies disappear from the post.
+/// `allow_private` lets sources on private networks be fetched; see
+/// `webmentions.allow_private_sources`.
+#[cfg(feature = "webhooks")]
+pub async fn verify_webmention(
+    db: &Database,
+    id: i64,
+    allow_private: bool,
+) -> Result<WebmentionStatus> {
+    let mention = get_webmention(db, id)?.ok_or_else(|| anyhow::anyhow!("Webmention not found"))?;
+
+    let fetched = fetch_page(&mention.source, allow_private).await;
+    let outcome = match fetched {
+        Ok(page) if page.status == 410 => Err("Source has been deleted".to_string()),
+        Ok(page) if !(200..300).contains(&page.status) => {
+            Err(format!("Source returned HTTP {}", page.status))
+        }
+        Ok(page) => {
+            let analysis = if page.content_type.is_empty() || page.content_type.contains("html") {
+                analyze_source(&page.body, &page.final_url, &mention.target)
+            } else {
+                SourceAnalysis {
+                    links_to_target: page.body.contains(&mention.target),
+                    ..Default::default()
+                }
+            };
+            if analysis.links_to_target {
+                Ok(analysis)
+            } else {
+                Err("Source does not link to target".to_string())
+            }
+        }
+        Err(e) => Err(format!("Could not fetch source: {}", e)),
+    };
+
+    match outcome {
+        Ok(analysis) => {
+            mark_verified(db, id, &analysis)?;
+            if let Ok(Some(item)) = content::get_content_by_id(db, mention.content_id) {
+                super::webhook::fire_webhooks(
+                    db,
+                    "webmention.received",
+                    serde_json::json!({
+                        "id": id,
+                        "type": analysis.mention_type.to_string(),
+                        "protocol": mention.protocol,
+                        "source": mention.source,
+                        "target": mention.target,
+                        "author_name": analysis.author_name,
+                        "author_url": analysis.author_url,
+                        "content": {
+                            "id": item.content.id,
+                            "title": item.content.title,
+                            "slug": item.content.slug,
+                        },
+                    }),
+                );
+            }
+            Ok(WebmentionStatus::Verified)
+        }
+        Err(reason) => {
+            mark_rejected(db, id, &reason)?;
+            Ok(WebmentionStatus::Rejected)
+        }
+    }
+}
+
+/// Verify a received mention in the background.
+#[cfg(feature = "webhooks")]
+pub fn spawn_verification(db: &Database, id: i64, allow_private: bool) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        match verify_webmention(&db, id, allow_private).await {
+            Ok(status) => tracing::info!("Webmention {} {}", id, status),
+            Err(e) => tracing::warn!("Webmention {} verification failed: {}", id, e),
+        }
+    });
+}
+
+/// No-op version when webhooks feature is disabled; mentions stay pending.
+#[cfg(not(feature = "webhooks"))]
+pub fn spawn_verification(_db: &Database, _id: i64, _allow_private: bool) {
+    // Outbound HTTP not available without the webhooks feature
+}
+
+/// Notify one target. Returns `Ok(None)` when the target advertises no endpoint.
+/// Targets and the endpoints they advertise are fetched like mention sources,
+/// so a linked page cannot point the server at its own network.
+#[cfg(feature = "webhooks")]
+async fn send_one(
+    source: &str,
+    target: &str,
+    allow_private: bool,
+) -> Result<Option<(Endpoint, i32)>> {
+    let page = fetch_page(target, allow_private).await?;
+    let endpoint = match discover_endpoint(
+        &page.link_headers,
+        page.pingback_header.as_deref(),
+        &page.body,
+        &page.final_url,
+    ) {
+        Some(e) => e,
+        None => return Ok(None),
+    };
+
+    check_url(endpoint.url().as_str(), allow_private)?;
+    let client = source_client(allow_private);
+    let request = match &endpoint {
+        Endpoint::Webmention(url) => client
+            .post(url.clone())
+            .form(&[("source", source), ("target", target)]),
+        Endpoint::Pingback(url) => client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "text/xml")
+            .body(pingback_request(source, target)),
+    };
+    let resp = request.send().await?;
+    let status = resp.status().as_u16() as i32;
+    if let Endpoint::Pingback(_) = endpoint {
+        // XML-RPC reports errors as faults inside a 200 response
+        let body = resp.text().await.unwrap_or_default();
+        if body.contains("<fault>") {
+            bail!(
+                "Pingback fault: {}",
+                STRIP_TAGS_RE
+                    .replace_all(&body, " ")
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+    }
+    Ok(Some((endpoint, status)))
+}
+
+/// Send Webmentions (or Pingbacks) from `source` to each target, recording
+/// every attempt where an endpoint was found. Returns the number accepted.
+#[cfg(feature = "webhooks")]
+pub async fn send_webmentions(
+    db: &Database,
+    content_id: i64,
+    source: &str,
+    targets: &[String],
+    allow_private: bool,
+) -> Result<usize> {
+    let mut accepted = 0;
+    for target in targets {
+        match send_one(source, target, allow_private).await {
+            Ok(Some((endpoint, status))) => {
+                let success = (200..300).contains(&status);
+                if success {
+                    accepted += 1;
+                }
+                log_send(
+                    db,
+                    content_id,
+                    target,
+                    Some(&endpoint),
+                    Some(status),
+                    success,
+                    None,
+                )?;
+            }
+            Ok(None) => {
+                tracing::debug!("No Webmention or Pingback endpoint for {}", target);
+            }
+            Err(e) => {
+                log_send(
+                    db,
+                    content_id,
+                    target,
+                    None,
+                    None,
+                    false,
+                    Some(&e.to_string()),
+                )?;
+            }
+        }
+    }
+    Ok(accepted)
+}
+
+#[cfg(feature = "webhooks")]
+fn spawn_send(
+    db: &Database,
+    content_id: i64,
+    source: String,
+    targets: Vec<String>,
+    allow_private: bool,
+) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        match send_webmentions(&db, content_id, &source, &targets, allow_private).await {
+            Ok(count) => tracing::info!(
+                "Webmentions for {}: {} of {} link(s) notified",
+                source,
+                count,
+                targets.len()
+            ),
+            Err(e) => tracing::warn!("Sending webmentions for {} failed: {}", source, e),
+        }
+    });
+}
+
+#[cfg(not(feature = "webhooks"))]
+fn spawn_send(
+    _db: &Database,
+    _content_id: i64,
+    _source: String,
+    _targets: Vec<String>,
+    _allow_private: bool,
+) {
+    // Outbound HTTP not available without the webhooks feature
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateContent;
+    use crate::services::markdown::MarkdownRenderer;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    const SITE_URL: &str = "https://blog.example.com";
+    const TARGET: &str = "https://blog.example.com/posts/mentioned-post";
+
+    fn setup_test_db() -> Database {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let db = Database::open_memory(&format!("webmention_test_{}", id)).unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    fn create_post(db: &Database) -> i64 {
+        content::create_content(
+            db,
+            &MarkdownRenderer::new(),
+            &[],
+            CreateContent {
+                title: "Mentioned Post".to_string(),
+                slug: Some("mentioned-post".to_string()),
+                content_type: ContentType::Post,
+                body_markdown: "Hello".to_string(),
+                status: ContentStatus::Published,
+                scheduled_at: None,
+                excerpt: None,
+                featured_image: None,
+                tags: vec![],
+                metadata: None,
+                entry_type: None,
+                fields: None,
+            },
+            None,
+            200,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_receive_mention_resolves_target() {
+        let db = setup_test_db();
+        let content_id = create_post(&db);
+
+        let id = receive_mention(
+            &db,
+            SITE_URL,
+            "https://other.example.org/notes/1",
+            TARGET,
+            PROTOCOL_WEBMENTION,
+        )
+        .unwrap();
+        let mention = get_webmention(&db, id).unwrap().unwrap();
+        assert_eq!(mention.content_id, content_id);
+        assert_eq!(mention.status, WebmentionStatus::Pending);
+
+        // Re-sending updates the existing mention rather than duplicating it
+        let again = receive_mention(
+            &db,
+            SITE_URL,
+            "https://other.example.org/notes/1",
+            TARGET,
+            PROTOCOL_WEBMENTION,
+        )
+        .unwrap();
+        assert_eq!(again, id);
+
+        assert!(receive_mention(
+            &db,
+            SITE_URL,
+            "https://a.example/",
+            "https://elsewhere.example/posts/mentioned-post",
+            PROTOCOL_WEBMENTION
+        )
+        .is_err());
+        assert!(receive_mention(
+            &db,
+            SITE_URL,
+            "https://a.example/",
+            "https://blog.example.com/posts/missing",
+            PROTOCOL_WEBMENTION
+        )
+        .is_err());
+        assert!(receive_mention(&db, SITE_URL, TARGET, TARGET, PROTOCOL_WEBMENTION).is_err());
+    }
+
+    #[test]
+    fn test_analyze_source_and_discover_endpoint() {
+        let source = Url::parse("https://alice.example/likes/42").unwrap();
+        let html = r#"<article class="h-entry">
+            <a class="p-author h-card" href="/"><img class="u-photo" src="/me.jpg" alt="">Alice</a>
+            <a class="u-like-of" href="https://blog.example.com/posts/mentioned-post/">liked this</a>
+        </article>"#;
+        let analysis = analyze_source(html, &source, TARGET);
+        assert!(analysis.links_to_target);
+        assert_eq!(analysis.mention_type, MentionType::Like);
+        assert_eq!(analysis.author_name.as_deref(), Some("Alice"));
+        assert_eq!(
+            analysis.author_url.as_deref(),
+            Some("https://alice.example/")
+        );
+        assert_eq!(
+            analysis.author_photo.as_deref(),
+            Some("https://alice.example/me.jpg")
+        );
+
+        assert!(
+            !analyze_source("<a href=\"https://x.example/\">x</a>", &source, TARGET)
+                .links_to_target
+        );
+
+        let base = Url::parse("https://bob.example/post").unwrap();
+        let header = vec![
+            r#"<https://bob.example/feed>; rel="alternate", </wm>; rel="webmention""#.to_string(),
+        ];
+        assert_eq!(
+            discover_endpoint(&header, None, "", &base),
+            Some(Endpoint::Webmention(
+                Url::parse("https://bob.example/wm").unwrap()
+            ))
+        );
+        let html = r#"<link rel="pingback" href="https://bob.example/xmlrpc"><a rel="nofollow webmention" href="endpoint">x</a>"#;
+        assert_eq!(
+            discover_endpoint(&[], None, html, &base),
+            Some(Endpoint::Webmention(
+                Url::parse("https://bob.example/endpoint").unwrap()
+            ))
+        );
+        assert_eq!(
+            discover_endpoint(
+                &[],
+                None,
+                r#"<link rel="pingback" href="https://bob.example/xmlrpc">"#,
+                &base
+            ),
+            Some(Endpoint::Pingback(
+                Url::parse("https://bob.example/xmlrpc").unwrap()
+            ))
+        );
+
+        let (src, tgt) =
+            parse_pingback_request(&pingback_request("https://a.example/?x=1&y=2", TARGET))
+                .unwrap();
+        assert_eq!(src, "https://a.example/?x=1&y=2");
+        assert_eq!(tgt, TARGET);
+    }
+
+    #[test]
+    fn test_is_public_address() {
+        let public = |ip: &str| is_public_address(ip.parse().unwrap());
+        assert!(public("93.184.216.34"));
+        assert!(public("2606:4700::1111"));
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[cfg(feature = "webhooks")]
+    async fn spawn_stub(app: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[cfg(feature = "webhooks")]
+    #[tokio::test]
+    async fn test_verify_and_send_against_stub_server() {
+        use axum::routing::{get, post};
+        use std::sync::{Arc, Mutex};
+
+        let db = setup_test_db();
+        let content_id = create_post(&db);
+
+        let received: Arc<Mutex<Vec<(String, String)>>> = Arc::default();
+        let recorder = received.clone();
+        let app = axum::Router::new()
+            .route(
+                "/reply",
+                get(|| async {
+                    axum::response::Html(format!(
+                        r#"<div class="h-entry"><span class="p-author h-card"><span class="p-name">Carol</span></span>
+                        <a class="u-in-reply-to" href="{}">In reply to</a>
+                        <div class="e-content">Great <b>post</b>, thanks!</div></div>"#,
+                        TARGET
+                    ))
+                }),
+            )
+            .route("/unrelated", get(|| async { axum::response::Html("<p>No links here</p>") }))
+            .route(
+                "/article",
+                get(|| async {
+                    (
+                        [(axum::http::header::LINK, "</endpoint>; rel=\"webmention\"")],
+                        axum::response::Html("<p>An article</p>"),
+                    )
+                }),
+            )
+            .route(
+                "/endpoint",
+                post(move |axum::Form(form): axum::Form<std::collections::HashMap<String, String>>| {
+                    let recorder = recorder.clone();
+                    async move {
+                        recorder.lock().unwrap().push((
+                            form.get("source").cloned().unwrap_or_default(),
+                            form.get("target").cloned().unwrap_or_default(),
+                        ));
+                        axum::http::StatusCode::ACCEPTED
+                    }
+                }),
+            );
+        let stub = spawn_stub(app).await;
+
+        // Incoming: a reply is verified with author and excerpt; a page without the link is rejected
+        let id = receive_mention(
+            &db,
+            SITE_URL,
+            &format!("{}/reply", stub),
+            TARGET,
+            PROTOCOL_WEBMENTION,
+        )
+        .unwrap();
+        assert_eq!(
+            verify_webmention(&db, id, true).await.unwrap(),
+            WebmentionStatus::Verified
+        );
+        let groups = list_for_content(&db, content_id).unwrap();
+        assert_eq!(groups.replies.len(), 1);
+        assert_eq!(groups.replies[0].author_name.as_deref(), Some("Carol"));
+        assert_eq!(
+            groups.replies[0].summary.as_deref(),
+            Some("Great post, thanks!")
+        );
+
+        // Re-sending a verified mention puts it back to pending until re-verified
+        receive_mention(
+            &db,
+            SITE_URL,
+            &format!("{}/reply", stub),
+            TARGET,
+            PROTOCOL_WEBMENTION,
+        )
+        .unwrap();
+        assert_eq!(
+            get_webmention(&db, id).unwrap().unwrap().status,
+            WebmentionStatus::Pending
+        );
+        assert_eq!(list_for_content(&db, content_id).unwrap().total, 0);
+
+        // Sources on the server's own network are refused unless allowed
+        assert_eq!(
+            verify_webmention(&db, id, false).await.unwrap(),
+            WebmentionStatus::Rejected
+        );
+        let error = get_webmention(&db, id).unwrap().unwrap().error.unwrap();
+        assert!(error.contains("not a public address"), "{}", error);
+        let by_name = receive_mention(
+            &db,
+            SITE_URL,
+            &format!("{}/reply", stub.replace("127.0.0.1", "localhost")),
+            TARGET,
+            PROTOCOL_WEBMENTION,
+        )
+        .unwrap();
+        assert_eq!(
+            verify_webmention(&db, by_name, false).await.unwrap(),
+            WebmentionStatus::Rejected
+        );
+        delete_webmention(&db, by_name).unwrap();
+        assert_eq!(
+            verify_webmention(&db, id, true).await.unwrap(),
+            WebmentionStatus::Verified
+        );
+
+        let bad = receive_mention(
+            &db,
+            SITE_URL,
+            &format!("{}/unrelated", stub),
+            TARGET,
+            PROTOCOL_WEBMENTION,
+        )
+        .unwrap();
+        assert_eq!(
+            verify_webmention(&db, bad, true).await.unwrap(),
+            WebmentionStatus::Rejected
+        );
+        assert_eq!(list_for_content(&db, content_id).unwrap().total, 1);
+
+        // Outgoing targets on the server's own network are refused by default
+        let target = format!("{}/article", stub);
+        let targets = std::slice::from_ref(&target);
+        assert_eq!(
+            send_webmentions(&db, content_id, TARGET, targets, false)
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(received.lock().unwrap().is_empty());
+        let sends = list_sends(&db, 10).unwrap();
+        assert_eq!(sends.len(), 1);
+        assert!(!sends[0].success);
+        assert!(sends[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("not a public address"));
+
+        // Outgoing: the endpoint advertised in the Link header receives source and target
+        let accepted = send_webmentions(&db, content_id, TARGET, targets, true)
+            .await
+            .unwrap();
+        assert_eq!(accepted, 1);
+        assert_eq!(
+            received.lock().unwrap().as_slice(),
+            &[(TARGET.to_string(), target.clone())]
+        );
+        let sends = list_sends(&db, 10).unwrap();
+        assert_eq!(sends.len(), 2);
+        let sent = sends.iter().find(|s| s.success).unwrap();
+        assert_eq!(sent.protocol.as_deref(), Some(PROTOCOL_WEBMENTION));
+    }
+}
@@ -117,6 +117,66 @@ mod tests {
             assert!(html.contains("language-rust"));
         }
 
+        #[test]
+        fn test_render_code_block_highlight_classes() {
+            let renderer = MarkdownRenderer::new();
+            let html = renderer.render(
+                "```haskell {2 linenos}\nmain :: IO ()\nmain = putStrLn \"hi\"\n```\n\n<span class=\"evil hl-x\" style=\"color:red\">x</span>",
+            );
+            assert!(html.contains(
+                r#"<pre class="code-block code-numbered"><code class="language-haskell">"#
+            ));
+            assert!(html.contains(r#"<span class="code-line code-line-highlight">"#));
+            assert!(html.contains("hl-source hl-haskell"));
+            assert!(!html.contains("style="));
+            assert!(!html.contains("evil"));
+        }
+
+        #[test]
+        fn test_render_math_and_diagrams() {
+            let renderer = MarkdownRenderer::new();
+            let html = renderer.render(
+                "Euler: $e^{i\\pi} + 1 = 0$\n\n$$\\frac{a}{b}$$\n\n```dot\ndigraph { a -> b }\n```\n\n```mermaid\npie\n```\n\n<svg onload=\"alert(1)\"><a href=\"javascript:x\">x</a></svg>",
+            );
+            assert!(html.contains("<math><semantics><mrow><msup><mi>e</mi>"));
+            assert!(html.contains(r#"<math display="block">"#));
+            assert!(html.contains("<mfrac>"));
+            assert!(html.contains(r#"<figure class="diagram diagram-dot"><svg viewBox="#));
+            assert!(html.contains("<ellipse "));
+            assert!(html.contains(r#"<figure class="diagram diagram-error">"#));
+            assert!(!html.contains("onload"));
+            assert!(!html.contains("javascript"));
+        }
+
+        #[test]
+        fn test_render_heading_ids_and_toc() {
+            let renderer = MarkdownRenderer::new();
+            let rendered = renderer.render_with_toc(
+                "[toc]\n\n## Setup\n\n### Install `pebble`\n\n## Setup\n\n## *Usage* {#use}\n\n#### Deep\n\n# Top",
+            );
+            let html = &rendered.html;
+            assert!(html.contains(r#"<h2 id="setup">Setup</h2>"#));
+            assert!(html.contains(r#"<h3 id="install-pebble">Install <code>pebble</code></h3>"#));
+            assert!(html.contains(r#"<h2 id="setup-1">Setup</h2>"#));
+            assert!(html.contains(r#"<h2 id="use"><em>Usage</em></h2>"#));
+            assert!(!html.contains("heading-anchor"));
+
+            let toc = &rendered.toc;
+            assert_eq!(toc.len(), 4);
+            assert_eq!(toc[0].children[0].text, "Install pebble");
+            assert_eq!(toc[2].id, "use");
+            assert_eq!(toc[2].children[0].level, 4);
+            assert_eq!(toc[3].level, 1);
+
+            assert!(html.contains(
+                r##"<nav class="toc"><ul><li><a href="#setup" rel="noopener noreferrer">Setup</a><ul><li><a href="#install-pebble""##
+            ));
+            assert_eq!(
+                renderer.generate_excerpt("[toc]\n\nIntro text", 50),
+                "Intro text"
+            );
+        }
+
         #[test]
         fn test_render_inline_code() {
             let renderer = MarkdownRenderer::new();
@@ -882,6 +942,8 @@ session_lifetime = "7d"
 #[cfg(test)]
 mod shortcode_tests {
     use crate::services::markdown::{MarkdownRenderer, ShortcodeProcessor};
+    use crate::services::shortcodes::ShortcodeRegistry;
+    use std::sync::Arc;
 
     #[test]
     fn test_shortcode_image_basic() {
@@ -936,4 +998,96 @@ Some text after."#;
             "Should have correct path"
         );
     }
+
+    #[test]
+    fn test_paired_callout_renders_markdown_body() {
+        let renderer = MarkdownRenderer::new();
+        let input = "[callout type=\"warning\" title=\"Heads up\"]\nBack up **first**.\n\nThen upgrade.\n[/callout]\n\nAfter.";
+        let output = renderer.render(input);
+        assert!(
+            output.contains(r#"<div class="callout callout-warning">"#),
+            "Output was: {}",
+            output
+        );
+        assert!(output.contains(r#"<p class="callout-title">Heads up</p>"#));
+        assert!(output.contains("<strong>first</strong>"));
+        assert!(output.contains("<p>Then upgrade.</p>"));
+        assert!(output.contains("<p>After.</p>"));
+        assert!(!output.contains("[/callout]"));
+    }
+
+    #[test]
+    fn test_nested_and_inline_shortcodes() {
+        let processor = ShortcodeProcessor::new();
+        let output = processor.process(
+            "[details summary=\"More\"]\n[callout]Inner[/callout]\n[/details] [button href=\"/contact\" style=\"secondary\"]Get *in* touch[/button]",
+        );
+        assert!(output.contains(r#"<details class="shortcode-details">"#));
+        assert!(output.contains("<summary>More</summary>"));
+        assert!(output.contains(r#"<div class="callout callout-note">"#));
+        assert!(output.contains(
+            r#"<a href="/contact" class="shortcode-button shortcode-button-secondary">Get <em>in</em> touch</a>"#
+        ));
+
+        // An unclosed pair renders without a body; unknown closers are left alone
+        let output = processor.process("[callout title=\"T\"] text [/note]");
+        assert!(output.contains("callout-title"));
+        assert!(output.contains("text [/note]"));
+    }
+
+    #[test]
+    fn test_figure_caption_from_body_or_attribute() {
+        let processor = ShortcodeProcessor::new();
+        let output =
+            processor.process(r#"[figure src="chart.png" alt="Chart"]Traffic in *March*[/figure]"#);
+        assert!(output.contains(r#"src="/media/chart.png""#));
+        assert!(output.contains("<figcaption>Traffic in <em>March</em></figcaption>"));
+
+        let output =
+            processor.process(r#"[figure src="https://example.com/a.jpg" caption="Remote"]"#);
+        assert!(output.contains(r#"src="https://example.com/a.jpg""#));
+        assert!(output.contains("<figcaption>Remote</figcaption>"));
+    }
+
+    #[test]
+    fn test_site_template_shortcode() {
+        let registry = ShortcodeRegistry::from_templates(vec![(
+            "note".to_string(),
+            r#"<aside class="site-note">{% if title %}<b>{{ title }}</b>{% endif %}
+{{ body | safe }}</aside>"#
+                .to_string(),
+        )])
+        .unwrap();
+        let renderer = MarkdownRenderer::with_shortcodes(Arc::new(registry));
+        let output = renderer.render("[note title=\"<Hi>\"]\nSome *text*\n[/note]");
+        assert!(
+            output.contains(r#"<aside class="site-note">"#),
+            "Output was: {}",
+            output
+        );
+        assert!(
+            output.contains("<b>&lt;Hi&gt;</b>"),
+            "Output was: {}",
+            output
+        );
+        assert!(output.contains("<em>text</em>"));
+    }
+
+    #[test]
+    fn test_embed_facade_survives_sanitizer() {
+        let renderer = MarkdownRenderer::new();
+        let output = renderer.render(
+            "Watch this:\n\nhttps://www.youtube.com/watch?v=dQw4w9WgXcQ\n\n[embed url=\"https://example.com/page\"]",
+        );
+        assert!(
+            output.contains(r#"<figure class="embed embed-youtube" data-embed-src="https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ?autoplay=1""#),
+            "Output was: {}",
+            output
+        );
+        assert!(output.contains(r#"class="embed-facade""#));
+        assert!(output.contains(r#"src="/media/embed-youtube-dQw4w9WgXcQ.jpg""#));
+        assert!(!output.contains("<iframe"));
+        // Unsupported providers fall back to a plain link
+        assert!(output.contains(r#"<a href="https://example.com/page""#));
+    }
 }
@@ -0,0 +1,372 @@
+//! The client address of a request. Behind a reverse proxy every connection
+//! comes from the proxy, so the address is taken from the one header the
+//! proxy sets, `server.client_ip_header` (`X-Forwarded-For` by default), but
+//! only as far back as the hops are proxies listed in
+//! `server.trusted_proxies`. Other forwarding headers are never read, since
+//! a proxy passes on whatever a client sent in the ones it does not set.
+//!
+//! `client_ip_middleware` works the address out once per request and stores
+//! it as a [`ClientIp`], which analytics, the audit log and the rate limiters
+//! all read.
+
+use super::state::AppState;
+use crate::config::ServerConfig;
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderName, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The client address, when it is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl ClientIp {
+    /// The address as a string, or `unknown`, for rate limiter keys and logs.
+    pub fn key(&self) -> String {
+        self.0
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// Read the address `client_ip_middleware` stored, falling back to the
+    /// peer address for routers served without it.
+    pub fn from_parts(parts: &Parts) -> Self {
+        parts
+            .extensions
+            .get::<ClientIp>()
+            .copied()
+            .unwrap_or_else(|| {
+                ClientIp(
+                    parts
+                        .extensions
+                        .get::<ConnectInfo<SocketAddr>>()
+                        .map(|ci| ci.0.ip()),
+                )
+            })
+    }
+}
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    fn from_request_parts<'life0, 'life1, 'async_trait>(
+        parts: &'life0 mut Parts,
+        _state: &'life1 S,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Self::Rejection>> + Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        let client_ip = Self::from_parts(parts);
+        Box::pin(async move { Ok(client_ip) })
+    }
+}
+
+/// An address range such as `10.0.0.0/8`, `::1/128` or a single address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = addr
+            .parse()
+            .with_context(|| format!("'{}' is not an IP address or CIDR range", s))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .with_context(|| format!("Invalid prefix length in '{}'", s))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // An IPv4 client reaching a dual-stack socket shows up as ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip,
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The proxies whose forwarding headers are believed.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<Cidr>);
+
+impl TrustedProxies {
+    pub fn parse(ranges: &[String]) -> Result<Self> {
+        ranges
+            .iter()
+            .map(|range| Cidr::parse(range))
+            .collect::<Result<Vec<_>>>()
+            .map(Self)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+/// How client addresses are read from forwarded requests: which peers are
+/// believed, and the header they record hops in. Built once from the
+/// `[server]` config.
+#[derive(Debug, Clone)]
+pub struct ClientIpResolver {
+    trusted: TrustedProxies,
+    header: HeaderName,
+}
+
+impl ClientIpResolver {
+    pub fn new(trusted: TrustedProxies, header: &str) -> Result<Self> {
+        let header = HeaderName::from_bytes(header.trim().as_bytes())
+            .ok()
+            .with_context(|| format!("'{}' is not a valid header name", header))?;
+        Ok(Self { trusted, header })
+    }
+
+    pub fn from_config(server: &ServerConfig) -> Result<Self> {
+        let trusted =
+            TrustedProxies::parse(&server.trusted_proxies).context("server.trusted_proxies")?;
+        Self::new(trusted, &server.client_ip_header).context("server.client_ip_header")
+    }
+
+    /// The client address of a request from `peer`: the peer itself unless
+    /// it is a trusted proxy, else the nearest forwarded hop that is not one.
+    pub fn resolve(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer = peer?;
+        if !self.trusted.contains(peer) {
+            return Some(peer);
+        }
+
+        let mut client = peer;
+        for hop in self.forwarded_hops(headers).into_iter().rev() {
+            // An obfuscated or garbled hop: the chain cannot be followed further
+            let Some(hop) = hop else {
+                break;
+            };
+            client = hop;
+            if !self.trusted.contains(hop) {
+                break;
+            }
+        }
+        Some(client)
+    }
+
+    /// The hops recorded in the configured header, nearest to the client
+    /// first. `Forwarded` is read as RFC 7239 `for=` elements; any other
+    /// header as a comma-separated address list, which covers
+    /// `X-Forwarded-For` and single-address headers such as `X-Real-IP`.
+    /// A hop that is not an address is `None`.
+    fn forwarded_hops(&self, headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+        let values: Vec<&str> = headers
+            .get_all(&self.header)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        if values.is_empty() {
+            return Vec::new();
+        }
+        let joined = values.join(",");
+
+        if self.header == axum::http::header::FORWARDED {
+            return joined
+                .split(',')
+                .map(|element| {
+                    element
+                        .split(';')
+                        .filter_map(|pair| pair.split_once('='))
+                        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                        .and_then(|(_, node)| parse_node(node))
+                })
+                .collect();
+        }
+        joined.split(',').map(parse_node).collect()
+    }
+}
+
+/// An address as proxies write it: `203.0.113.7`, `203.0.113.7:4711`,
+/// `2001:db8::1` or `"[2001:db8::1]:4711"`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+/// Store the request's [`ClientIp`] for the handlers and middleware after it.
+pub async fn client_ip_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ci| ci.0.ip());
+    let client_ip = ClientIp(state.client_ip.resolve(peer, request.headers()));
+    request.extensions_mut().insert(client_ip);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn resolver(ranges: &[&str], header: &str) -> ClientIpResolver {
+        let trusted =
+            TrustedProxies::parse(&ranges.iter().map(|r| r.to_string()).collect::<Vec<_>>())
+                .unwrap();
+        ClientIpResolver::new(trusted, header).unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains(ip("10.1.200.3")));
+        assert!(!net.contains(ip("10.2.0.1")));
+        assert!(net.contains(ip("::ffff:10.1.0.9")));
+        assert!(Cidr::parse("0.0.0.0/0")
+            .unwrap()
+            .contains(ip("203.0.113.7")));
+        assert!(Cidr::parse("::1").unwrap().contains(ip("::1")));
+        assert!(Cidr::parse("fd00::/8").unwrap().contains(ip("fd12::1")));
+        assert!(!Cidr::parse("fd00::/8").unwrap().contains(ip("10.0.0.1")));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("localhost").is_err());
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let proxies = resolver(&["127.0.0.0/8", "10.0.0.0/8"], "x-forwarded-for");
+        let mut headers = HeaderMap::new();
+
+        // Direct connections keep the peer address
+        assert_eq!(
+            proxies.resolve(Some(ip("198.51.100.2")), &headers),
+            Some(ip("198.51.100.2"))
+        );
+        assert_eq!(
+            proxies.resolve(Some(ip("127.0.0.1")), &headers),
+            Some(ip("127.0.0.1"))
+        );
+
+        // Headers from an untrusted peer are ignored
+        headers.insert("x-forwarded-for", "1.2.3.4".parse().unwrap());
+        assert_eq!(
+            proxies.resolve(Some(ip("198.51.100.2")), &headers),
+            Some(ip("198.51.100.2"))
+        );
+
+        // A spoofed first hop is skipped: the nearest untrusted hop wins
+        headers.insert(
+            "x-forwarded-for",
+            "1.2.3.4, 203.0.113.7, 10.0.0.5".parse().unwrap(),
+        );
+        assert_eq!(
+            proxies.resolve(Some(ip("127.0.0.1")), &headers),
+            Some(ip("203.0.113.7"))
+        );
+
+        // Headers the proxy does not set are passed through from the
+        // client, so a spoofed Forwarded or X-Real-IP is ignored
+        headers.insert("forwarded", "for=1.2.3.4".parse().unwrap());
+        headers.insert("x-real-ip", "1.2.3.5".parse().unwrap());
+        assert_eq!(
+            proxies.resolve(Some(ip("127.0.0.1")), &headers),
+            Some(ip("203.0.113.7"))
+        );
+
+        // Without the configured header the proxy is the client
+        headers.remove("x-forwarded-for");
+        assert_eq!(
+            proxies.resolve(Some(ip("127.0.0.1")), &headers),
+            Some(ip("127.0.0.1"))
+        );
+        assert_eq!(proxies.resolve(None, &headers), None);
+
+        let no_proxies = resolver(&[], "x-forwarded-for");
+        headers.insert("x-forwarded-for", "203.0.113.9".parse().unwrap());
+        assert_eq!(
+            no_proxies.resolve(Some(ip("127.0.0.1")), &headers),
+            Some(ip("127.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn test_client_ip_header() {
+        let trusted = ["127.0.0.0/8"];
+
+        // Forwarded may carry ports and brackets
+        let forwarded = resolver(&trusted, "Forwarded");
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.2.3.4".parse().unwrap());
+        headers.insert(
+            "forwarded",
+            "for=1.2.3.4, for=\"[2001:db8::7]:4711\";proto=https"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            forwarded.resolve(Some(ip("127.0.0.1")), &headers),
+            Some(ip("2001:db8::7"))
+        );
+
+        // An obfuscated hop stops the walk at the last trusted address
+        headers.insert("forwarded", "for=203.0.113.7, for=_hidden".parse().unwrap());
+        assert_eq!(
+            forwarded.resolve(Some(ip("127.0.0.1")), &headers),
+            Some(ip("127.0.0.1"))
+        );
+
+        let real_ip = resolver(&trusted, "x-real-ip");
+        headers.insert("x-real-ip", "203.0.113.9".parse().unwrap());
+        assert_eq!(
+            real_ip.resolve(Some(ip("127.0.0.1")), &headers),
+            Some(ip("203.0.113.9"))
+        );
+
+        assert!(ClientIpResolver::new(TrustedProxies::default(), "").is_err());
+        assert!(ClientIpResolver::new(TrustedProxies::default(), "x forwarded").is_err());
+    }
+}
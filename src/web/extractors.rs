@@ -1,15 +1,15 @@
 use crate::models::{ApiToken, User};
 use crate::services::audit::AuditContext;
 use crate::services::{api_token, auth};
+use crate::web::client_ip::ClientIp;
 use crate::web::state::AppState;
-use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::extract::FromRequestParts;
 use axum::http::header;
 use axum::http::request::Parts;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Json, Redirect, Response};
 use axum_extra::extract::CookieJar;
 use std::future::Future;
-use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 
@@ -123,24 +123,7 @@ where
         'life1: 'async_trait,
         Self: 'async_trait,
     {
-        let ip = parts
-            .headers
-            .get("x-forwarded-for")
-            .and_then(|v| v.to_str().ok())
-            .map(|s| s.split(',').next().unwrap_or(s).trim().to_string())
-            .or_else(|| {
-                parts
-                    .headers
-                    .get("x-real-ip")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_string())
-            })
-            .or_else(|| {
-                parts
-                    .extensions
-                    .get::<ConnectInfo<SocketAddr>>()
-                    .map(|ci| ci.0.ip().to_string())
-            });
+        let ip = ClientIp::from_parts(parts).0.map(|ip| ip.to_string());
 
         // Extract User Agent
         let user_agent = parts
@@ -1,6 +1,10 @@
-use crate::models::{ContentStatus, ContentType, CreateContent, UpdateContent, User, UserRole};
+use crate::config::{ContentTypeConfig, FieldType};
+use crate::models::{
+    CommentStatus, ContentStatus, ContentType, CreateContent, SubscriberStatus, UpdateContent,
+    User, UserRole, WebmentionStatus,
+};
 use crate::services::audit::{AuditAction, AuditCategory, AuditLogBuilder};
-use crate::services::{api_token, audit, auth, content, database, media, preview, series, settings, tags, webhook};
+use crate::services::{api_token, audit, auth, comments, content, content_types, database, embeds, i18n, link_checker, lint, media, newsletter, preview, series, settings, social_card, tags, webhook, webmention};
 use crate::web::error::AppResult;
 use crate::web::extractors::{AuditInfo, CurrentUser, HxRequest};
 use crate::web::state::AppState;
@@ -22,6 +26,12 @@ fn make_admin_context(state: &AppState, user: &User) -> Context {
     if config.theme.custom.has_customizations() {
         ctx.insert("theme_custom_css", &config.theme.custom.to_css_variables());
     }
+    let custom_types: Vec<_> = config
+        .content_types
+        .iter()
+        .map(|t| serde_json::json!({ "name": t.name, "label": t.label() }))
+        .collect();
+    ctx.insert("custom_types", &custom_types);
     ctx
 }
 
@@ -94,6 +104,7 @@ pub async fn posts(
     ctx.insert("posts", &posts);
     ctx.insert("page", &page);
     ctx.insert("total_pages", &total_pages);
+    ctx.insert("checks", &lint::recorded_counts(&state.db)?);
 
     let html = state.templates.render("admin/posts/index.html", &ctx)?;
     Ok(Html(html).into_response())
@@ -102,6 +113,7 @@ pub async fn posts(
 pub async fn new_post(
     State(state): State<Arc<AppState>>,
     CurrentUser(user): CurrentUser,
+    Query(query): Query<NewContentQuery>,
 ) -> AppResult<Response> {
     if let Err(e) = require_author_or_admin(&user) {
         return Ok(e);
@@ -114,6 +126,7 @@ pub async fn new_post(
     ctx.insert("all_tags", &all_tags);
     ctx.insert("is_new", &true);
     ctx.insert("content_type", "post");
+    insert_translation_context(&mut ctx, &state, ContentType::Post, None, query.translation_of)?;
 
     let html = state.templates.render("admin/posts/form.html", &ctx)?;
     Ok(Html(html).into_response())
@@ -133,6 +146,10 @@ pub struct ContentForm {
     meta_title: Option<String>,
     meta_description: Option<String>,
     canonical_url: Option<String>,
+    #[serde(default)]
+    social_image: Option<String>,
+    #[serde(default)]
+    noindex: Option<String>,
     // Custom code fields (for pages)
     #[serde(default)]
     custom_html: Option<String>,
@@ -142,6 +159,107 @@ pub struct ContentForm {
     custom_js: Option<String>,
     #[serde(default)]
     use_custom_code: Option<String>,
+    // Discussion (posts only)
+    #[serde(default)]
+    comments_enabled: Option<String>,
+    // Translations (only shown when i18n.languages is configured)
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    translation_of: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct NewContentQuery {
+    translation_of: Option<i64>,
+}
+
+/// Language and translation controls for the post and page forms. Nothing is
+/// inserted when the site has no additional languages, which hides them.
+fn insert_translation_context(
+    ctx: &mut Context,
+    state: &AppState,
+    content_type: ContentType,
+    current: Option<&crate::models::Content>,
+    translation_of: Option<i64>,
+) -> anyhow::Result<()> {
+    let config = state.config().clone();
+    if config.i18n.languages.is_empty() {
+        return Ok(());
+    }
+    let languages: Vec<serde_json::Value> =
+        i18n::site_languages(&config.site.language, &config.i18n)
+            .into_iter()
+            .map(|code| {
+                let name = state
+                    .catalogs
+                    .get(&code)
+                    .and_then(|c| c.get("language_name"))
+                    .cloned()
+                    .unwrap_or_else(|| code.clone());
+                serde_json::json!({ "code": code, "name": name })
+            })
+            .collect();
+
+    let translations = match current {
+        Some(c) => i18n::list_translations(&state.db, c.id, &config.site.language, &config.i18n)?,
+        None => Vec::new(),
+    };
+    let candidates: Vec<crate::models::ContentSummary> =
+        content::list_content(&state.db, Some(content_type), None, 500, 0)?
+            .into_iter()
+            .filter(|c| {
+                current.map_or(true, |cur| cur.id != c.id)
+                    && !translations.iter().any(|t| t.id == c.id)
+            })
+            .collect();
+
+    // A new translation defaults to the first language its group lacks.
+    let current_language = match (current, translation_of) {
+        (Some(c), _) => c.language.clone(),
+        (None, Some(id)) => {
+            let taken =
+                i18n::list_translations(&state.db, id, &config.site.language, &config.i18n)?;
+            let original = content::get_content_by_id(&state.db, id)?
+                .and_then(|c| c.content.language);
+            let original = original.as_deref().unwrap_or(&config.site.language);
+            i18n::site_languages(&config.site.language, &config.i18n)
+                .into_iter()
+                .find(|code| code != original && !taken.iter().any(|t| &t.language == code))
+        }
+        (None, None) => None,
+    };
+
+    ctx.insert("i18n_languages", &languages);
+    ctx.insert(
+        "current_language",
+        current_language.as_deref().unwrap_or(&config.site.language),
+    );
+    ctx.insert("translations", &translations);
+    ctx.insert("translation_candidates", &candidates);
+    if let Some(id) = translation_of {
+        ctx.insert("translation_of", &id);
+    }
+    Ok(())
+}
+
+/// Apply the language and "translation of" fields from a saved form.
+fn save_translation_fields(state: &AppState, id: i64, form: &ContentForm) -> anyhow::Result<()> {
+    let config = state.config().clone();
+    if config.i18n.languages.is_empty() {
+        return Ok(());
+    }
+    let language =
+        i18n::normalize_language(form.language.as_deref(), &config.site.language, &config.i18n)?;
+    i18n::set_content_language(&state.db, id, language.as_deref())?;
+    if let Some(other) = form
+        .translation_of
+        .as_deref()
+        .and_then(|s| s.trim().parse::<i64>().ok())
+    {
+        i18n::link_translation(&state.db, id, other)?;
+    }
+    Ok(())
 }
 
 fn build_seo_metadata(form: &ContentForm) -> serde_json::Value {
@@ -161,6 +279,21 @@ fn build_seo_metadata(form: &ContentForm) -> serde_json::Value {
             metadata["canonical_url"] = serde_json::json!(cu);
         }
     }
+    if let Some(ref si) = form.social_image {
+        if !si.trim().is_empty() {
+            metadata["social_image"] = serde_json::json!(si.trim());
+        }
+    }
+    if form.noindex.is_some() {
+        metadata["noindex"] = serde_json::json!(true);
+    }
+    metadata
+}
+
+fn build_post_metadata(form: &ContentForm) -> serde_json::Value {
+    let mut metadata = build_seo_metadata(form);
+    // Unchecked checkboxes are not submitted, so always store an explicit value
+    metadata["comments_enabled"] = serde_json::json!(form.comments_enabled.is_some());
     metadata
 }
 
@@ -221,15 +354,25 @@ pub async fn create_post(
         status: form.status.parse().unwrap_or(ContentStatus::Draft),
         scheduled_at: form.scheduled_at.clone().filter(|s| !s.is_empty()),
         tags,
-        metadata: Some(build_seo_metadata(&form)),
+        metadata: Some(build_post_metadata(&form)),
+        entry_type: None,
+        fields: None,
     };
 
+    let config = state.config();
     let content_id = content::create_content(
         &state.db,
+        &state.markdown,
+        &config.content_types,
         input,
         Some(user.id),
-        state.config().content.excerpt_length,
+        config.content.excerpt_length,
     )?;
+    drop(config);
+    save_translation_fields(&state, content_id, &form)?;
+
+    embeds::spawn_cache_thumbnails(&state.db, &state.media_dir, content_id);
+    lint::check_on_save(&state.db, &state.config(), content_id);
 
     // Fire webhooks
     if form.status == "published" {
@@ -238,6 +381,8 @@ pub async fn create_post(
             "content.published",
             serde_json::json!({ "id": content_id, "title": form.title, "type": "post" }),
         );
+        webmention::notify_published(&state.db, &state.config(), content_id);
+        social_card::spawn_render(&state.db, &state.config(), &state.media_dir, content_id);
     }
 
     // Audit log
@@ -277,6 +422,19 @@ pub async fn edit_post(
             ctx.insert("all_tags", &all_tags);
             ctx.insert("is_new", &false);
             ctx.insert("content_type", "post");
+            insert_translation_context(&mut ctx, &state, ContentType::Post, Some(&p.content), None)?;
+            let newsletter_enabled = state.config().newsletter.enabled;
+            ctx.insert("newsletter_enabled", &newsletter_enabled);
+            if newsletter_enabled {
+                ctx.insert(
+                    "newsletter_issues",
+                    &newsletter::list_issues_for_content(&state.db, id)?,
+                );
+                ctx.insert(
+                    "newsletter_recipients",
+                    &newsletter::count_subscribers(&state.db, Some(SubscriberStatus::Confirmed))?,
+                );
+            }
 
             let html = state.templates.render("admin/posts/form.html", &ctx)?;
             Ok(Html(html).into_response())
@@ -313,18 +471,25 @@ pub async fn update_post(
         status: Some(form.status.parse().unwrap_or(ContentStatus::Draft)),
         scheduled_at: form.scheduled_at.clone().filter(|s| !s.is_empty()),
         tags: Some(tags),
-        metadata: Some(build_seo_metadata(&form)),
+        metadata: Some(build_post_metadata(&form)),
+        fields: None,
     };
 
     let config = state.config();
     content::update_content(
         &state.db,
+        &state.markdown,
+        &config.content_types,
         id,
         input,
-        config.content.excerpt_length,
         Some(user.id),
         config.content.version_retention,
     )?;
+    drop(config);
+    save_translation_fields(&state, id, &form)?;
+
+    embeds::spawn_cache_thumbnails(&state.db, &state.media_dir, id);
+    lint::check_on_save(&state.db, &state.config(), id);
 
     // Fire webhooks
     webhook::fire_webhooks(
@@ -332,6 +497,8 @@ pub async fn update_post(
         "content.updated",
         serde_json::json!({ "id": id, "title": form.title, "type": "post" }),
     );
+    webmention::notify_published(&state.db, &state.config(), id);
+    social_card::spawn_render(&state.db, &state.config(), &state.media_dir, id);
 
     // Audit log
     audit_ctx.user_id = Some(user.id);
@@ -366,7 +533,7 @@ pub async fn delete_post(
         .map(|c| c.content.title)
         .unwrap_or_default();
 
-    content::delete_content(&state.db, id)?;
+    content::delete_content(&state.db, &state.markdown, id)?;
 
     // Fire webhooks
     webhook::fire_webhooks(
@@ -423,6 +590,7 @@ pub async fn pages(
     ctx.insert("pages", &pages);
     ctx.insert("page", &page);
     ctx.insert("total_pages", &total_pages);
+    ctx.insert("checks", &lint::recorded_counts(&state.db)?);
 
     let html = state.templates.render("admin/pages/index.html", &ctx)?;
     Ok(Html(html).into_response())
@@ -431,6 +599,7 @@ pub async fn pages(
 pub async fn new_page(
     State(state): State<Arc<AppState>>,
     CurrentUser(user): CurrentUser,
+    Query(query): Query<NewContentQuery>,
 ) -> AppResult<Response> {
     if let Err(e) = require_author_or_admin(&user) {
         return Ok(e);
@@ -440,6 +609,7 @@ pub async fn new_page(
     ctx.insert("content", &Option::<crate::models::ContentWithTags>::None);
     ctx.insert("is_new", &true);
     ctx.insert("content_type", "page");
+    insert_translation_context(&mut ctx, &state, ContentType::Page, None, query.translation_of)?;
 
     let html = state.templates.render("admin/pages/form.html", &ctx)?;
     Ok(Html(html).into_response())
@@ -467,14 +637,24 @@ pub async fn create_page(
         scheduled_at: form.scheduled_at.clone().filter(|s| !s.is_empty()),
         tags: vec![],
         metadata: Some(build_page_metadata(&form)),
+        entry_type: None,
+        fields: None,
     };
 
+    let config = state.config();
     let id = content::create_content(
         &state.db,
+        &state.markdown,
+        &config.content_types,
         input,
         Some(user.id),
-        state.config().content.excerpt_length,
+        config.content.excerpt_length,
     )?;
+    drop(config);
+    save_translation_fields(&state, id, &form)?;
+
+    embeds::spawn_cache_thumbnails(&state.db, &state.media_dir, id);
+    lint::check_on_save(&state.db, &state.config(), id);
 
     // Fire webhooks
     if form.status == "published" {
@@ -483,6 +663,7 @@ pub async fn create_page(
             "content.published",
             serde_json::json!({ "id": id, "title": form.title, "type": "page" }),
         );
+        webmention::notify_published(&state.db, &state.config(), id);
     }
 
     // Audit log
@@ -530,6 +711,7 @@ pub async fn edit_page(
             ctx.insert("content", &p);
             ctx.insert("is_new", &false);
             ctx.insert("content_type", "page");
+            insert_translation_context(&mut ctx, &state, ContentType::Page, Some(&p.content), None)?;
 
             let html = state.templates.render("admin/pages/form.html", &ctx)?;
             Ok(Html(html).into_response())
@@ -560,17 +742,24 @@ pub async fn update_page(
         scheduled_at: form.scheduled_at.clone().filter(|s| !s.is_empty()),
         tags: None,
         metadata: Some(build_page_metadata(&form)),
+        fields: None,
     };
 
     let config = state.config();
     content::update_content(
         &state.db,
+        &state.markdown,
+        &config.content_types,
         id,
         input,
-        config.content.excerpt_length,
         Some(user.id),
         config.content.version_retention,
     )?;
+    drop(config);
+    save_translation_fields(&state, id, &form)?;
+
+    embeds::spawn_cache_thumbnails(&state.db, &state.media_dir, id);
+    let checks = lint::check_on_save(&state.db, &state.config(), id);
 
     // Fire webhooks
     webhook::fire_webhooks(
@@ -578,6 +767,7 @@ pub async fn update_page(
         "content.updated",
         serde_json::json!({ "id": id, "title": form.title, "type": "page" }),
     );
+    webmention::notify_published(&state.db, &state.config(), id);
 
     // Audit log
     audit_ctx.user_id = Some(user.id);
@@ -595,8 +785,19 @@ pub async fn update_page(
 
     if is_htmx {
         let mut ctx = Context::new();
-        ctx.insert("message", "Page saved successfully");
-        ctx.insert("type", "success");
+        if checks.errors + checks.warnings > 0 {
+            ctx.insert(
+                "message",
+                &format!(
+                    "Page saved with {} error(s) and {} warning(s), see Content checks",
+                    checks.errors, checks.warnings
+                ),
+            );
+            ctx.insert("type", "warning");
+        } else {
+            ctx.insert("message", "Page saved successfully");
+            ctx.insert("type", "success");
+        }
         let html = state.templates.render("htmx/flash.html", &ctx)?;
         Ok(Html(html).into_response())
     } else {
@@ -620,7 +821,7 @@ pub async fn delete_page(
         .map(|c| c.content.title)
         .unwrap_or_default();
 
-    content::delete_content(&state.db, id)?;
+    content::delete_content(&state.db, &state.markdown, id)?;
 
     // Fire webhooks
     webhook::fire_webhooks(
@@ -657,6 +858,52 @@ pub async fn delete_page(
     }
 }
 
+#[derive(Deserialize)]
+pub struct UnlinkTranslationForm {
+    /// Content whose edit page to return to
+    back: i64,
+}
+
+/// Remove a post or page from its translation group.
+pub async fn unlink_translation(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Path(id): Path<i64>,
+    Form(form): Form<UnlinkTranslationForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    let item = match content::get_content_by_id(&state.db, id)? {
+        Some(item) => item,
+        None => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+    i18n::unlink_translation(&state.db, id)?;
+
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(AuditAction::Update, AuditCategory::Content)
+            .entity(
+                &item.content.content_type.to_string(),
+                id,
+                Some(&item.content.title),
+            )
+            .metadata_value("translation_unlinked", serde_json::json!(true)),
+    );
+
+    let section = match item.content.content_type {
+        ContentType::Page => "pages",
+        _ => "posts",
+    };
+    Ok(Redirect::to(&format!("/admin/{}/{}/edit", section, form.back)).into_response())
+}
+
 pub async fn media_page(
     State(state): State<Arc<AppState>>,
     CurrentUser(user): CurrentUser,
@@ -958,6 +1205,11 @@ pub async fn save_settings(
             excerpt_length: form.excerpt_length.clamp(1, 10000),
             auto_excerpt: form.auto_excerpt.is_some(),
             version_retention: current.content.version_retention,
+            shortcodes_dir: current.content.shortcodes_dir.clone(),
+            highlight_theme: current.content.highlight_theme.clone(),
+            highlight_theme_dark: current.content.highlight_theme_dark.clone(),
+            line_numbers: current.content.line_numbers,
+            heading_anchors: current.content.heading_anchors,
         },
         media: current.media.clone(),
         theme: crate::config::ThemeConfig {
@@ -987,6 +1239,15 @@ pub async fn save_settings(
         audit: current.audit.clone(),
         api: current.api.clone(),
         backup: current.backup.clone(),
+        comments: current.comments.clone(),
+        webmentions: current.webmentions.clone(),
+        newsletter: current.newsletter.clone(),
+        link_checker: current.link_checker.clone(),
+        social_cards: current.social_cards.clone(),
+        i18n: current.i18n.clone(),
+        tls: current.tls.clone(),
+        metrics: current.metrics.clone(),
+        content_types: current.content_types.clone(),
     };
 
     // Drop the read lock before updating
@@ -1498,8 +1759,7 @@ pub async fn post_version_view(
     }
 
     // Render the markdown for preview
-    let renderer = crate::services::markdown::MarkdownRenderer::new();
-    let body_html = renderer.render(&version.body_markdown);
+    let body_html = state.markdown.render(&version.body_markdown);
 
     let mut ctx = make_admin_context(&state, &user);
     ctx.insert("content", &content);
@@ -1528,7 +1788,7 @@ pub async fn post_version_restore(
         return Ok((StatusCode::NOT_FOUND, "Not a post").into_response());
     }
 
-    crate::services::versions::restore_version(&state.db, id, vid, Some(user.id))?;
+    crate::services::versions::restore_version(&state.db, &state.markdown, id, vid, Some(user.id))?;
 
     Ok(Redirect::to(&format!("/admin/posts/{}/edit", id)).into_response())
 }
@@ -1764,8 +2024,7 @@ pub async fn page_version_view(
     }
 
     // Render the markdown for preview
-    let renderer = crate::services::markdown::MarkdownRenderer::new();
-    let body_html = renderer.render(&version.body_markdown);
+    let body_html = state.markdown.render(&version.body_markdown);
 
     let mut ctx = make_admin_context(&state, &user);
     ctx.insert("content", &content);
@@ -1794,7 +2053,7 @@ pub async fn page_version_restore(
         return Ok((StatusCode::NOT_FOUND, "Not a page").into_response());
     }
 
-    crate::services::versions::restore_version(&state.db, id, vid, Some(user.id))?;
+    crate::services::versions::restore_version(&state.db, &state.markdown, id, vid, Some(user.id))?;
 
     Ok(Redirect::to(&format!("/admin/pages/{}/edit", id)).into_response())
 }
@@ -2056,6 +2315,16 @@ pub async fn snippets(
     }
 
     let snippets = content::list_content(&state.db, Some(ContentType::Snippet), None, 100, 0)?;
+    let use_counts = content::snippet_use_counts(&state.db)?;
+    let snippets: Vec<serde_json::Value> = snippets
+        .into_iter()
+        .map(|snippet| {
+            let used_by = use_counts.get(&snippet.slug).copied().unwrap_or(0);
+            let mut value = serde_json::to_value(&snippet).unwrap_or_default();
+            value["used_by"] = serde_json::json!(used_by);
+            value
+        })
+        .collect();
 
     let mut ctx = make_admin_context(&state, &user);
     ctx.insert("snippets", &snippets);
@@ -2076,6 +2345,7 @@ pub async fn new_snippet(
     ctx.insert("content", &Option::<crate::models::ContentWithTags>::None);
     ctx.insert("is_new", &true);
     ctx.insert("content_type", "snippet");
+    ctx.insert("used_by", &Vec::<content::SnippetUse>::new());
 
     let html = state.templates.render("admin/snippets/form.html", &ctx)?;
     Ok(Html(html).into_response())
@@ -2099,27 +2369,76 @@ pub async fn create_snippet(
 
     let input = CreateContent {
         title: form.title.clone(),
-        slug: form.slug.filter(|s| !s.is_empty()),
+        slug: form.slug.clone().filter(|s| !s.is_empty()),
         content_type: ContentType::Snippet,
-        body_markdown: form.body_markdown,
+        body_markdown: form.body_markdown.clone(),
         excerpt: None,
         featured_image: None,
         status: ContentStatus::Published,
         scheduled_at: None,
         tags: vec![],
         metadata: None,
+        entry_type: None,
+        fields: None,
     };
 
-    content::create_content(
+    let config = state.config();
+    let result = content::create_content(
         &state.db,
+        &state.markdown,
+        &config.content_types,
         input,
         Some(user.id),
-        state.config().content.excerpt_length,
-    )?;
+        config.content.excerpt_length,
+    );
+    drop(config);
+    match result {
+        Ok(id) => {
+            embeds::spawn_cache_thumbnails(&state.db, &state.media_dir, id);
+            lint::check_on_save(&state.db, &state.config(), id);
+        }
+        Err(e) => return render_snippet_form_error(&state, &user, None, &form, &e.to_string()),
+    }
 
     Ok(Redirect::to("/admin/snippets").into_response())
 }
 
+/// Show the snippet form again with the submitted values and what went
+/// wrong, e.g. a snippet that would include itself.
+fn render_snippet_form_error(
+    state: &AppState,
+    user: &User,
+    id: Option<i64>,
+    form: &SnippetForm,
+    error: &str,
+) -> AppResult<Response> {
+    let used_by = match id {
+        Some(id) => match content::get_content_by_id(&state.db, id)? {
+            Some(current) => content::list_snippet_uses(&state.db, &current.content.slug)?,
+            None => Vec::new(),
+        },
+        None => Vec::new(),
+    };
+    let mut ctx = make_admin_context(state, user);
+    ctx.insert(
+        "content",
+        &serde_json::json!({
+            "id": id,
+            "title": form.title,
+            "slug": form.slug,
+            "body_markdown": form.body_markdown,
+            "body_html": null,
+        }),
+    );
+    ctx.insert("is_new", &id.is_none());
+    ctx.insert("content_type", "snippet");
+    ctx.insert("used_by", &used_by);
+    ctx.insert("error", error);
+
+    let html = state.templates.render("admin/snippets/form.html", &ctx)?;
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, Html(html)).into_response())
+}
+
 pub async fn edit_snippet(
     State(state): State<Arc<AppState>>,
     CurrentUser(user): CurrentUser,
@@ -2133,10 +2452,12 @@ pub async fn edit_snippet(
 
     match snippet {
         Some(s) if s.content.content_type == ContentType::Snippet => {
+            let used_by = content::list_snippet_uses(&state.db, &s.content.slug)?;
             let mut ctx = make_admin_context(&state, &user);
             ctx.insert("content", &s);
             ctx.insert("is_new", &false);
             ctx.insert("content_type", "snippet");
+            ctx.insert("used_by", &used_by);
 
             let html = state.templates.render("admin/snippets/form.html", &ctx)?;
             Ok(Html(html).into_response())
@@ -2156,26 +2477,34 @@ pub async fn update_snippet(
     }
 
     let input = UpdateContent {
-        title: Some(form.title),
-        slug: form.slug,
-        body_markdown: Some(form.body_markdown),
+        title: Some(form.title.clone()),
+        slug: form.slug.clone(),
+        body_markdown: Some(form.body_markdown.clone()),
         excerpt: None,
         featured_image: None,
         status: Some(ContentStatus::Published),
         scheduled_at: None,
         tags: None,
         metadata: None,
+        fields: None,
     };
 
     let config = state.config();
-    content::update_content(
+    let result = content::update_content(
         &state.db,
+        &state.markdown,
+        &config.content_types,
         id,
         input,
-        config.content.excerpt_length,
         Some(user.id),
         config.content.version_retention,
-    )?;
+    );
+    drop(config);
+    if let Err(e) = result {
+        return render_snippet_form_error(&state, &user, Some(id), &form, &e.to_string());
+    }
+    embeds::spawn_cache_thumbnails(&state.db, &state.media_dir, id);
+    lint::check_on_save(&state.db, &state.config(), id);
 
     Ok(Redirect::to("/admin/snippets").into_response())
 }
@@ -2190,7 +2519,7 @@ pub async fn delete_snippet(
         return Ok(e);
     }
 
-    content::delete_content(&state.db, id)?;
+    content::delete_content(&state.db, &state.markdown, id)?;
 
     if is_htmx {
         Ok((
@@ -2206,163 +2535,1320 @@ pub async fn delete_snippet(
     }
 }
 
-// ============================================================================
-// Bulk Operations
-// ============================================================================
-
-#[derive(Deserialize)]
-pub struct BulkActionForm {
-    action: String,
-    #[serde(default)]
-    ids: String, // comma-separated content IDs
-}
-
-pub async fn bulk_action(
+pub async fn shortcodes(
     State(state): State<Arc<AppState>>,
     CurrentUser(user): CurrentUser,
-    AuditInfo(mut audit_ctx): AuditInfo,
-    Form(form): Form<BulkActionForm>,
 ) -> AppResult<Response> {
     if let Err(e) = require_author_or_admin(&user) {
         return Ok(e);
     }
 
-    let ids: Vec<i64> = form
-        .ids
-        .split(',')
-        .filter_map(|s| s.trim().parse::<i64>().ok())
-        .collect();
+    let shortcodes_dir = state.config().content.shortcodes_dir.clone();
 
-    if ids.is_empty() {
-        return Ok(Redirect::to("/admin/posts").into_response());
-    }
+    let mut ctx = make_admin_context(&state, &user);
+    ctx.insert("shortcodes", &state.shortcodes.reference());
+    ctx.insert("shortcodes_dir", &shortcodes_dir);
 
-    let action_label = form.action.clone();
+    let html = state.templates.render("admin/shortcodes.html", &ctx)?;
+    Ok(Html(html).into_response())
+}
 
-    match form.action.as_str() {
-        "publish" => {
-            for id in &ids {
-                let _ = content::update_content(
-                    &state.db,
-                    *id,
-                    UpdateContent {
-                        status: Some(ContentStatus::Published),
-                        ..Default::default()
-                    },
-                    state.config().content.excerpt_length,
-                    Some(user.id),
-                    state.config().content.version_retention,
-                );
-            }
-        }
-        "draft" => {
-            for id in &ids {
-                let _ = content::update_content(
-                    &state.db,
-                    *id,
-                    UpdateContent {
-                        status: Some(ContentStatus::Draft),
-                        ..Default::default()
-                    },
-                    state.config().content.excerpt_length,
-                    Some(user.id),
-                    state.config().content.version_retention,
-                );
-            }
-        }
-        "archive" => {
-            for id in &ids {
-                let _ = content::update_content(
-                    &state.db,
-                    *id,
-                    UpdateContent {
-                        status: Some(ContentStatus::Archived),
-                        ..Default::default()
-                    },
-                    state.config().content.excerpt_length,
-                    Some(user.id),
-                    state.config().content.version_retention,
-                );
-            }
-        }
-        "delete" => {
-            for id in &ids {
-                let _ = content::delete_content(&state.db, *id);
+// ============================================================================
+// Custom Content Type Handlers
+// ============================================================================
+
+fn find_content_type(state: &AppState, name: &str) -> Option<ContentTypeConfig> {
+    content_types::find(&state.config().content_types, name).cloned()
+}
+
+fn field_value_text(value: Option<&serde_json::Value>) -> String {
+    match value {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Null) | None => String::new(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Form controls for a type's fields, pre-filled with `values`. Reference
+/// fields get the items they can point at and media fields the library.
+fn entry_field_controls(
+    state: &AppState,
+    def: &ContentTypeConfig,
+    values: &serde_json::Value,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    let mut controls = Vec::new();
+    for field in &def.fields {
+        let choices: Vec<serde_json::Value> = match field.field_type {
+            FieldType::Reference => {
+                let target = field.to.as_deref().unwrap_or("post");
+                let items = match target.parse::<ContentType>() {
+                    Ok(content_type @ (ContentType::Post | ContentType::Page)) => {
+                        content::list_content(&state.db, Some(content_type), None, 500, 0)?
+                    }
+                    _ => content::list_entries(&state.db, target, None, 500, 0)?,
+                };
+                items
+                    .into_iter()
+                    .map(|item| serde_json::json!({ "value": item.slug, "label": item.title }))
+                    .collect()
             }
-        }
-        _ => {}
+            FieldType::Media => media::list_media(&state.db, 200, 0)?
+                .into_iter()
+                .map(|m| serde_json::json!({ "value": m.filename, "label": m.original_name }))
+                .collect(),
+            _ => Vec::new(),
+        };
+        controls.push(serde_json::json!({
+            "name": field.name,
+            "label": field.label(),
+            "type": field.field_type,
+            "required": field.required,
+            "help": field.help,
+            "options": field.options,
+            "value": field_value_text(values.get(&field.name)),
+            "choices": choices,
+        }));
     }
+    Ok(controls)
+}
 
-    // Audit log for bulk action
-    audit_ctx.user_id = Some(user.id);
-    audit_ctx.username = Some(user.username.clone());
-    audit_ctx.user_role = Some(format!("{:?}", user.role));
-    let _ = audit::log(
-        &state.db,
-        &audit_ctx,
-        AuditLogBuilder::new(AuditAction::Update, AuditCategory::Content)
-            .metadata_value("bulk_action", serde_json::json!(action_label))
-            .metadata_value("affected_ids", serde_json::json!(ids)),
-    );
+#[derive(Deserialize)]
+pub struct EntryForm {
+    title: String,
+    slug: Option<String>,
+    #[serde(default)]
+    body_markdown: String,
+    excerpt: Option<String>,
+    #[serde(default = "default_entry_status")]
+    status: String,
+    scheduled_at: Option<String>,
+    /// Field inputs, submitted as `field_<name>`
+    #[serde(flatten)]
+    extra: std::collections::HashMap<String, String>,
+}
 
-    Ok(Redirect::to("/admin/posts").into_response())
+fn default_entry_status() -> String {
+    "draft".to_string()
 }
 
-// ===== API Token Management =====
+impl EntryForm {
+    fn field_input(&self, def: &ContentTypeConfig) -> serde_json::Map<String, serde_json::Value> {
+        def.fields
+            .iter()
+            .filter_map(|field| {
+                let value = self.extra.get(&format!("field_{}", field.name))?;
+                Some((field.name.clone(), serde_json::Value::String(value.clone())))
+            })
+            .collect()
+    }
 
-pub async fn tokens(
+    fn as_entry(&self) -> serde_json::Value {
+        serde_json::json!({
+            "title": self.title,
+            "slug": self.slug,
+            "body_markdown": self.body_markdown,
+            "excerpt": self.excerpt,
+            "status": self.status,
+            "scheduled_at": self.scheduled_at,
+        })
+    }
+}
+
+fn render_entry_form(
+    state: &AppState,
+    user: &User,
+    def: &ContentTypeConfig,
+    content: Option<&crate::models::ContentWithTags>,
+    entry: serde_json::Value,
+    values: &serde_json::Value,
+    error: Option<&str>,
+) -> AppResult<Response> {
+    let mut ctx = make_admin_context(state, user);
+    ctx.insert("content", &content);
+    ctx.insert("is_new", &content.is_none());
+    ctx.insert("entry", &entry);
+    ctx.insert("content_type", &content_type_summary(def));
+    ctx.insert("field_controls", &entry_field_controls(state, def, values)?);
+    if let Some(error) = error {
+        ctx.insert("error", error);
+    }
+
+    let html = state.templates.render("admin/entries/form.html", &ctx)?;
+    let status = if error.is_some() {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Html(html)).into_response())
+}
+
+fn content_type_summary(def: &ContentTypeConfig) -> serde_json::Value {
+    serde_json::json!({
+        "name": def.name,
+        "label": def.label(),
+        "prefix": def.prefix(),
+    })
+}
+
+pub async fn type_entries(
     State(state): State<Arc<AppState>>,
     CurrentUser(user): CurrentUser,
+    Path(type_name): Path<String>,
+    Query(pagination): Query<AdminPagination>,
 ) -> AppResult<Response> {
-    if let Err(e) = require_admin(&user) {
+    if let Err(e) = require_author_or_admin(&user) {
         return Ok(e);
     }
+    let Some(def) = find_content_type(&state, &type_name) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let per_page = 50;
+    let page = pagination.page.max(1);
+    let offset = (page - 1) * per_page;
+    let entries = content::list_entries(&state.db, &def.name, None, per_page, offset)?;
+    let total = content::count_entries(&state.db, &def.name, None)?;
+    let total_pages = (total as usize).div_ceil(per_page);
 
-    let tokens = api_token::list_tokens(&state.db).unwrap_or_default();
     let mut ctx = make_admin_context(&state, &user);
-    ctx.insert("tokens", &tokens);
-    ctx.insert("new_token", &Option::<String>::None);
+    ctx.insert("content_type", &content_type_summary(&def));
+    ctx.insert("entries", &entries);
+    ctx.insert("page", &page);
+    ctx.insert("total_pages", &total_pages);
 
-    let html = state.templates.render("admin/tokens/index.html", &ctx)?;
+    let html = state.templates.render("admin/entries/index.html", &ctx)?;
     Ok(Html(html).into_response())
 }
 
-#[derive(Deserialize)]
-pub struct CreateTokenForm {
-    pub name: String,
-    pub permissions: Option<String>,
-    pub expires_days: Option<i64>,
+pub async fn new_entry(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    Path(type_name): Path<String>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+    let Some(def) = find_content_type(&state, &type_name) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let entry = serde_json::json!({ "status": "draft" });
+    render_entry_form(&state, &user, &def, None, entry, &serde_json::json!({}), None)
 }
 
-pub async fn create_token(
+pub async fn create_entry(
     State(state): State<Arc<AppState>>,
     CurrentUser(user): CurrentUser,
     AuditInfo(mut audit_ctx): AuditInfo,
-    Form(form): Form<CreateTokenForm>,
+    Path(type_name): Path<String>,
+    Form(form): Form<EntryForm>,
 ) -> AppResult<Response> {
-    if let Err(e) = require_admin(&user) {
+    if let Err(e) = require_author_or_admin(&user) {
         return Ok(e);
     }
+    let Some(def) = find_content_type(&state, &type_name) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
 
-    let permissions = form.permissions.as_deref().unwrap_or("read");
-    let expires_at = form.expires_days.and_then(|days| {
-        if days > 0 {
-            Some(
-                (chrono::Utc::now() + chrono::Duration::days(days))
-                    .format("%Y-%m-%dT%H:%M:%S")
-                    .to_string(),
-            )
-        } else {
-            None
+    let input_fields = form.field_input(&def);
+    let fields = match content::validate_entry_fields(&state.db, &def, &input_fields) {
+        Ok(fields) => fields,
+        Err(e) => {
+            let values = serde_json::Value::Object(input_fields);
+            return render_entry_form(
+                &state,
+                &user,
+                &def,
+                None,
+                form.as_entry(),
+                &values,
+                Some(&e.to_string()),
+            );
         }
-    });
+    };
 
-    let (raw_token, _token) = api_token::create_token(
-        &state.db,
-        &form.name,
-        permissions,
-        Some(user.id),
-        expires_at.as_deref(),
+    let input = CreateContent {
+        title: form.title.clone(),
+        slug: form.slug.clone().filter(|s| !s.is_empty()),
+        content_type: ContentType::Entry,
+        body_markdown: form.body_markdown.clone(),
+        excerpt: form.excerpt.clone().filter(|s| !s.is_empty()),
+        featured_image: None,
+        status: form.status.parse().unwrap_or(ContentStatus::Draft),
+        scheduled_at: form.scheduled_at.clone().filter(|s| !s.is_empty()),
+        tags: vec![],
+        metadata: None,
+        entry_type: Some(def.name.clone()),
+        fields: Some(fields),
+    };
+
+    let config = state.config();
+    let id = content::create_content(
+        &state.db,
+        &state.markdown,
+        &config.content_types,
+        input,
+        Some(user.id),
+        config.content.excerpt_length,
+    )?;
+    drop(config);
+
+    embeds::spawn_cache_thumbnails(&state.db, &state.media_dir, id);
+    lint::check_on_save(&state.db, &state.config(), id);
+
+    // Fire webhooks
+    if form.status == "published" {
+        webhook::fire_webhooks(
+            &state.db,
+            "content.published",
+            serde_json::json!({ "id": id, "title": form.title, "type": def.name }),
+        );
+    }
+
+    // Audit log
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(AuditAction::Create, AuditCategory::Content)
+            .entity("entry", id, Some(&form.title))
+            .metadata_value("content_type", serde_json::json!(def.name)),
+    );
+
+    Ok(Redirect::to(&format!("/admin/types/{}/{}/edit", def.name, id)).into_response())
+}
+
+pub async fn edit_entry(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    Path((type_name, id)): Path<(String, i64)>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+    let Some(def) = find_content_type(&state, &type_name) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    match content::get_content_by_id(&state.db, id)? {
+        Some(e) if e.content.entry_type.as_deref() == Some(def.name.as_str()) => {
+            let entry = serde_json::to_value(&e.content)?;
+            let values = e.content.fields.clone();
+            render_entry_form(&state, &user, &def, Some(&e), entry, &values, None)
+        }
+        _ => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+pub async fn update_entry(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Path((type_name, id)): Path<(String, i64)>,
+    Form(form): Form<EntryForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+    let Some(def) = find_content_type(&state, &type_name) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let current = match content::get_content_by_id(&state.db, id)? {
+        Some(e) if e.content.entry_type.as_deref() == Some(def.name.as_str()) => e,
+        _ => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+
+    let input_fields = form.field_input(&def);
+    let fields = match content::validate_entry_fields(&state.db, &def, &input_fields) {
+        Ok(fields) => fields,
+        Err(e) => {
+            let values = serde_json::Value::Object(input_fields);
+            return render_entry_form(
+                &state,
+                &user,
+                &def,
+                Some(&current),
+                form.as_entry(),
+                &values,
+                Some(&e.to_string()),
+            );
+        }
+    };
+
+    let input = UpdateContent {
+        title: Some(form.title.clone()),
+        slug: form.slug.clone().filter(|s| !s.is_empty()),
+        body_markdown: Some(form.body_markdown.clone()),
+        excerpt: form.excerpt.clone(),
+        featured_image: None,
+        status: Some(form.status.parse().unwrap_or(ContentStatus::Draft)),
+        scheduled_at: form.scheduled_at.clone().filter(|s| !s.is_empty()),
+        tags: None,
+        metadata: None,
+        fields: Some(fields),
+    };
+
+    let config = state.config();
+    content::update_content(
+        &state.db,
+        &state.markdown,
+        &config.content_types,
+        id,
+        input,
+        Some(user.id),
+        config.content.version_retention,
+    )?;
+    drop(config);
+
+    embeds::spawn_cache_thumbnails(&state.db, &state.media_dir, id);
+    lint::check_on_save(&state.db, &state.config(), id);
+
+    // Fire webhooks
+    webhook::fire_webhooks(
+        &state.db,
+        "content.updated",
+        serde_json::json!({ "id": id, "title": form.title, "type": def.name }),
+    );
+
+    // Audit log
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(AuditAction::Update, AuditCategory::Content)
+            .entity("entry", id, Some(&form.title))
+            .metadata_value("content_type", serde_json::json!(def.name)),
+    );
+
+    Ok(Redirect::to(&format!("/admin/types/{}/{}/edit", def.name, id)).into_response())
+}
+
+pub async fn delete_entry(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    HxRequest(is_htmx): HxRequest,
+    Path((type_name, id)): Path<(String, i64)>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+    let Some(def) = find_content_type(&state, &type_name) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let title = match content::get_content_by_id(&state.db, id)? {
+        Some(e) if e.content.entry_type.as_deref() == Some(def.name.as_str()) => e.content.title,
+        _ => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+
+    content::delete_content(&state.db, &state.markdown, id)?;
+
+    // Fire webhooks
+    webhook::fire_webhooks(
+        &state.db,
+        "content.deleted",
+        serde_json::json!({ "id": id, "title": title, "type": def.name }),
+    );
+
+    // Audit log
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(AuditAction::Delete, AuditCategory::Content)
+            .entity("entry", id, Some(&title))
+            .metadata_value("content_type", serde_json::json!(def.name)),
+    );
+
+    let list_url = format!("/admin/types/{}", def.name);
+    if is_htmx {
+        Ok((
+            [(header::HeaderName::from_static("hx-redirect"), list_url)],
+            "",
+        )
+            .into_response())
+    } else {
+        Ok(Redirect::to(&list_url).into_response())
+    }
+}
+
+// ============================================================================
+// Comment Moderation Handlers
+// ============================================================================
+
+#[derive(Deserialize)]
+pub struct CommentQueueQuery {
+    #[serde(default = "default_comment_tab")]
+    status: String,
+    #[serde(default = "default_admin_page")]
+    page: usize,
+}
+
+fn default_comment_tab() -> String {
+    "pending".to_string()
+}
+
+pub async fn comments_queue(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    Query(query): Query<CommentQueueQuery>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    // "all" (or anything unrecognised) shows every status
+    let status = query.status.parse::<CommentStatus>().ok();
+    let per_page = 50;
+    let page = query.page.max(1);
+    let offset = (page - 1) * per_page;
+    let queue = comments::list_comments(&state.db, status, per_page, offset)?;
+    let total = comments::count_comments(&state.db, status)?;
+    let total_pages = (total as usize).div_ceil(per_page);
+
+    let mut counts = std::collections::HashMap::new();
+    for s in [CommentStatus::Pending, CommentStatus::Approved, CommentStatus::Spam] {
+        counts.insert(s.to_string(), comments::count_comments(&state.db, Some(s))?);
+    }
+
+    let mut ctx = make_admin_context(&state, &user);
+    ctx.insert("comments", &queue);
+    ctx.insert("counts", &counts);
+    ctx.insert(
+        "current_status",
+        &status.map(|s| s.to_string()).unwrap_or_else(|| "all".to_string()),
+    );
+    ctx.insert("comments_enabled", &state.config().comments.enabled);
+    ctx.insert("page", &page);
+    ctx.insert("total_pages", &total_pages);
+
+    let html = state.templates.render("admin/comments/index.html", &ctx)?;
+    Ok(Html(html).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct CommentActionForm {
+    #[serde(default)]
+    tab: String,
+}
+
+impl CommentActionForm {
+    fn redirect(&self) -> Redirect {
+        match self.tab.parse::<CommentStatus>() {
+            Ok(s) => Redirect::to(&format!("/admin/comments?status={}", s)),
+            Err(_) => Redirect::to("/admin/comments?status=all"),
+        }
+    }
+}
+
+/// Apply a moderation decision (`None` deletes) and record it in the audit log.
+fn moderate_comment(
+    state: &AppState,
+    user: &User,
+    mut audit_ctx: audit::AuditContext,
+    id: i64,
+    status: Option<CommentStatus>,
+) -> anyhow::Result<()> {
+    let comment = comments::get_comment(&state.db, id)?
+        .ok_or_else(|| anyhow::anyhow!("Comment not found"))?;
+
+    let (action, detail) = match status {
+        Some(s) => {
+            comments::set_comment_status(&state.db, id, s)?;
+            (AuditAction::Update, format!("Marked comment as {}", s))
+        }
+        None => {
+            comments::delete_comment(&state.db, id)?;
+            (AuditAction::Delete, "Deleted comment".to_string())
+        }
+    };
+
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(action, AuditCategory::Content)
+            .entity("comment", id, Some(&comment.author_name))
+            .metadata_value("detail", serde_json::json!(detail))
+            .metadata_value("content_id", serde_json::json!(comment.content_id)),
+    );
+
+    Ok(())
+}
+
+pub async fn approve_comment(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(audit_ctx): AuditInfo,
+    Path(id): Path<i64>,
+    Form(form): Form<CommentActionForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    moderate_comment(&state, &user, audit_ctx, id, Some(CommentStatus::Approved))?;
+    Ok(form.redirect().into_response())
+}
+
+pub async fn spam_comment(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(audit_ctx): AuditInfo,
+    Path(id): Path<i64>,
+    Form(form): Form<CommentActionForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    moderate_comment(&state, &user, audit_ctx, id, Some(CommentStatus::Spam))?;
+    Ok(form.redirect().into_response())
+}
+
+pub async fn delete_comment(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(audit_ctx): AuditInfo,
+    Path(id): Path<i64>,
+    Form(form): Form<CommentActionForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    moderate_comment(&state, &user, audit_ctx, id, None)?;
+    Ok(form.redirect().into_response())
+}
+
+// ============================================================================
+// Webmention Handlers
+// ============================================================================
+
+#[derive(Deserialize)]
+pub struct WebmentionQuery {
+    #[serde(default)]
+    status: String,
+    #[serde(default = "default_admin_page")]
+    page: usize,
+}
+
+pub async fn webmentions(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    Query(query): Query<WebmentionQuery>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    let status = query.status.parse::<WebmentionStatus>().ok();
+    let per_page = 50;
+    let page = query.page.max(1);
+    let offset = (page - 1) * per_page;
+    let received = webmention::list_webmentions(&state.db, status, per_page, offset)?;
+    let total = webmention::count_webmentions(&state.db, status)?;
+    let total_pages = (total as usize).div_ceil(per_page);
+
+    let mut counts = std::collections::HashMap::new();
+    for s in [
+        WebmentionStatus::Verified,
+        WebmentionStatus::Pending,
+        WebmentionStatus::Rejected,
+    ] {
+        counts.insert(s.to_string(), webmention::count_webmentions(&state.db, Some(s))?);
+    }
+    let sent = webmention::list_sends(&state.db, 25)?;
+
+    let config = state.config();
+    let mut ctx = make_admin_context(&state, &user);
+    ctx.insert("webmentions", &received);
+    ctx.insert("sent", &sent);
+    ctx.insert("counts", &counts);
+    ctx.insert(
+        "current_status",
+        &status.map(|s| s.to_string()).unwrap_or_else(|| "all".to_string()),
+    );
+    ctx.insert("webmentions_config", &config.webmentions);
+    ctx.insert("page", &page);
+    ctx.insert("total_pages", &total_pages);
+
+    let html = state.templates.render("admin/webmentions/index.html", &ctx)?;
+    Ok(Html(html).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct WebmentionActionForm {
+    #[serde(default)]
+    tab: String,
+}
+
+pub async fn delete_webmention(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Path(id): Path<i64>,
+    Form(form): Form<WebmentionActionForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    if let Some(mention) = webmention::get_webmention(&state.db, id)? {
+        webmention::delete_webmention(&state.db, id)?;
+
+        audit_ctx.user_id = Some(user.id);
+        audit_ctx.username = Some(user.username.clone());
+        audit_ctx.user_role = Some(format!("{:?}", user.role));
+        let _ = audit::log(
+            &state.db,
+            &audit_ctx,
+            AuditLogBuilder::new(AuditAction::Delete, AuditCategory::Content)
+                .entity("webmention", id, Some(&mention.source))
+                .metadata_value("content_id", serde_json::json!(mention.content_id)),
+        );
+    }
+
+    let tab = form
+        .tab
+        .parse::<WebmentionStatus>()
+        .map(|s| s.to_string())
+        .unwrap_or_else(|_| "all".to_string());
+    Ok(Redirect::to(&format!("/admin/webmentions?status={}", tab)).into_response())
+}
+
+// ============================================================================
+// Link Checker Handlers
+// ============================================================================
+
+pub async fn links(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    let config = state.config();
+    let reports = link_checker::broken_report(&state.db, &config.site.url)?;
+    let summary = link_checker::summary(&state.db, &config.site.url)?;
+
+    let mut ctx = make_admin_context(&state, &user);
+    ctx.insert("reports", &reports);
+    ctx.insert("summary", &summary);
+    ctx.insert("running", &link_checker::is_running(&config.site.url));
+    ctx.insert("link_checker_config", &config.link_checker);
+    ctx.insert("webhooks_available", &cfg!(feature = "webhooks"));
+
+    let html = state.templates.render("admin/links/index.html", &ctx)?;
+    Ok(Html(html).into_response())
+}
+
+pub async fn check_links(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    let config = state.config();
+    if !link_checker::is_running(&config.site.url) {
+        link_checker::spawn_check(
+            &state.db,
+            config.site.url.clone(),
+            config.link_checker.clone(),
+        );
+    }
+    Ok(Redirect::to("/admin/links").into_response())
+}
+
+// ============================================================================
+// Newsletter Handlers
+// ============================================================================
+
+#[derive(Deserialize)]
+pub struct NewsletterAdminQuery {
+    #[serde(default)]
+    status: String,
+    #[serde(default = "default_admin_page")]
+    page: usize,
+    imported: Option<usize>,
+    skipped: Option<usize>,
+    invalid: Option<usize>,
+}
+
+fn newsletter_admin_context(
+    state: &AppState,
+    user: &User,
+    status: Option<SubscriberStatus>,
+    page: usize,
+) -> AppResult<Context> {
+    let per_page = 50;
+    let page = page.max(1);
+    let offset = (page - 1) * per_page;
+    let subscribers = newsletter::list_subscribers(&state.db, status, per_page, offset)?;
+    let total = newsletter::count_subscribers(&state.db, status)?;
+    let total_pages = (total as usize).div_ceil(per_page);
+
+    let mut counts = std::collections::HashMap::new();
+    for s in [
+        SubscriberStatus::Confirmed,
+        SubscriberStatus::Pending,
+        SubscriberStatus::Unsubscribed,
+    ] {
+        counts.insert(s.to_string(), newsletter::count_subscribers(&state.db, Some(s))?);
+    }
+
+    let mut ctx = make_admin_context(state, user);
+    ctx.insert("subscribers", &subscribers);
+    ctx.insert("counts", &counts);
+    ctx.insert(
+        "current_status",
+        &status.map(|s| s.to_string()).unwrap_or_else(|| "all".to_string()),
+    );
+    ctx.insert("issues", &newsletter::list_issues(&state.db, 25)?);
+    ctx.insert("newsletter_enabled", &state.config().newsletter.enabled);
+    ctx.insert("page", &page);
+    ctx.insert("total_pages", &total_pages);
+    Ok(ctx)
+}
+
+pub async fn newsletter(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    Query(query): Query<NewsletterAdminQuery>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    let status = query.status.parse::<SubscriberStatus>().ok();
+    let mut ctx = newsletter_admin_context(&state, &user, status, query.page)?;
+    if let Some(added) = query.imported {
+        ctx.insert(
+            "import_summary",
+            &serde_json::json!({
+                "added": added,
+                "skipped": query.skipped.unwrap_or(0),
+                "invalid": query.invalid.unwrap_or(0),
+            }),
+        );
+    }
+
+    let html = state.templates.render("admin/newsletter/index.html", &ctx)?;
+    Ok(Html(html).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SubscriberForm {
+    email: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+pub async fn add_subscriber_handler(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Form(form): Form<SubscriberForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    // Subscribers added by hand skip the opt-in email: the admin vouches for them
+    let error = match newsletter::add_subscriber(
+        &state.db,
+        &form.email,
+        form.name.as_deref(),
+        SubscriberStatus::Confirmed,
+    ) {
+        Ok(Some(id)) => {
+            audit_ctx.user_id = Some(user.id);
+            audit_ctx.username = Some(user.username.clone());
+            audit_ctx.user_role = Some(format!("{:?}", user.role));
+            let _ = audit::log(
+                &state.db,
+                &audit_ctx,
+                AuditLogBuilder::new(AuditAction::Create, AuditCategory::Content)
+                    .entity("subscriber", id, Some(form.email.trim())),
+            );
+            return Ok(Redirect::to("/admin/newsletter?status=confirmed").into_response());
+        }
+        Ok(None) => "That address is already on the list".to_string(),
+        Err(e) => e.to_string(),
+    };
+
+    let mut ctx = newsletter_admin_context(&state, &user, None, 1)?;
+    ctx.insert("error", &error);
+    ctx.insert("form_email", form.email.trim());
+    let html = state.templates.render("admin/newsletter/index.html", &ctx)?;
+    Ok((StatusCode::BAD_REQUEST, Html(html)).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SubscriberActionForm {
+    #[serde(default)]
+    tab: String,
+}
+
+pub async fn delete_subscriber_handler(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Path(id): Path<i64>,
+    Form(form): Form<SubscriberActionForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    if let Some(subscriber) = newsletter::get_subscriber(&state.db, id)? {
+        newsletter::delete_subscriber(&state.db, id)?;
+
+        audit_ctx.user_id = Some(user.id);
+        audit_ctx.username = Some(user.username.clone());
+        audit_ctx.user_role = Some(format!("{:?}", user.role));
+        let _ = audit::log(
+            &state.db,
+            &audit_ctx,
+            AuditLogBuilder::new(AuditAction::Delete, AuditCategory::Content)
+                .entity("subscriber", id, Some(&subscriber.email)),
+        );
+    }
+
+    let tab = form
+        .tab
+        .parse::<SubscriberStatus>()
+        .map(|s| s.to_string())
+        .unwrap_or_else(|_| "all".to_string());
+    Ok(Redirect::to(&format!("/admin/newsletter?status={}", tab)).into_response())
+}
+
+/// Download the subscriber list as CSV
+pub async fn export_subscribers(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+) -> AppResult<Response> {
+    if let Err(e) = require_admin(&user) {
+        return Ok(e);
+    }
+
+    let data = newsletter::export_csv(&state.db)?;
+
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(AuditAction::Export, AuditCategory::Content)
+            .entity_type_only("subscribers"),
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"subscribers.csv\"",
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// Import subscribers from an uploaded CSV file
+pub async fn import_subscribers(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    mut multipart: Multipart,
+) -> AppResult<Response> {
+    if let Err(e) = require_admin(&user) {
+        return Ok(e);
+    }
+
+    let mut data = None;
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() == Some("file") {
+            data = Some(String::from_utf8_lossy(&field.bytes().await?).into_owned());
+        }
+    }
+    let Some(data) = data else {
+        return Ok((StatusCode::BAD_REQUEST, "No CSV file uploaded").into_response());
+    };
+
+    let summary = newsletter::import_csv(&state.db, &data)?;
+
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(AuditAction::Create, AuditCategory::Content)
+            .entity_type_only("subscribers")
+            .metadata_value("imported", serde_json::json!(summary.added))
+            .metadata_value("skipped", serde_json::json!(summary.skipped))
+            .metadata_value("invalid", serde_json::json!(summary.invalid)),
+    );
+
+    Ok(Redirect::to(&format!(
+        "/admin/newsletter?imported={}&skipped={}&invalid={}",
+        summary.added,
+        summary.skipped,
+        summary.invalid.len()
+    ))
+    .into_response())
+}
+
+/// Render and deliver an issue in the background. Each subscriber gets their
+/// own copy so the unsubscribe link is personal.
+fn spawn_issue_send(state: Arc<AppState>, mailer: newsletter::Mailer, issue_id: i64) {
+    tokio::spawn(async move {
+        let config = state.config().clone();
+        let base = config.site.url.trim_end_matches('/').to_string();
+        let issue = match newsletter::get_issue(&state.db, issue_id) {
+            Ok(Some(issue)) => issue,
+            _ => return,
+        };
+        let post = issue
+            .content_id
+            .and_then(|id| content::get_content_by_id(&state.db, id).ok().flatten());
+        let Some(mut post) = post else {
+            tracing::warn!("Newsletter {}: post no longer exists", issue_id);
+            let _ = newsletter::finish_issue(&state.db, issue_id);
+            return;
+        };
+        post.content.body_html = newsletter::absolutize_urls(&post.content.body_html, &base);
+        let post_url = format!("{}/posts/{}", base, post.content.slug);
+
+        let render = |subscriber: &crate::models::Subscriber| {
+            let unsubscribe_url = format!(
+                "{}/newsletter/unsubscribe/{}",
+                base, subscriber.unsubscribe_token
+            );
+            let mut ctx = Context::new();
+            ctx.insert("site", &config.site);
+            ctx.insert("content", &post);
+            ctx.insert("post_url", &post_url);
+            ctx.insert("subscriber", subscriber);
+            ctx.insert("unsubscribe_url", &unsubscribe_url);
+            Ok(newsletter::OutgoingEmail {
+                to: subscriber.email.clone(),
+                subject: issue.subject.clone(),
+                html: state.templates.render("email/newsletter.html", &ctx)?,
+                text: state.templates.render("email/newsletter.txt", &ctx)?,
+                unsubscribe_url: Some(unsubscribe_url),
+            })
+        };
+
+        if let Err(e) =
+            newsletter::send_issue(&state.db, &mailer, &config.newsletter, issue_id, render).await
+        {
+            tracing::error!("Newsletter {} failed: {}", issue_id, e);
+        }
+    });
+}
+
+#[derive(Deserialize)]
+pub struct SendNewsletterForm {
+    #[serde(default)]
+    subject: String,
+}
+
+pub async fn send_post_newsletter(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Path(id): Path<i64>,
+    Form(form): Form<SendNewsletterForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    let config = state.config().newsletter.clone();
+    if !config.enabled {
+        return Ok((StatusCode::BAD_REQUEST, "The newsletter is not enabled").into_response());
+    }
+    let post = match content::get_content_by_id(&state.db, id)? {
+        Some(p) if p.content.content_type == ContentType::Post => p,
+        _ => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+    if post.content.status != ContentStatus::Published {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "Only published posts can be sent as a newsletter",
+        )
+            .into_response());
+    }
+
+    let mailer = match newsletter::Mailer::from_config(&config) {
+        Ok(m) => m,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    };
+    let subject = match form.subject.trim() {
+        "" => post.content.title.clone(),
+        s => s.to_string(),
+    };
+    let issue_id = match newsletter::create_issue(&state.db, id, &subject, Some(user.id)) {
+        Ok(issue_id) => issue_id,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    };
+
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(AuditAction::Create, AuditCategory::Content)
+            .entity("newsletter_issue", issue_id, Some(&subject))
+            .metadata_value("content_id", serde_json::json!(id)),
+    );
+
+    spawn_issue_send(state.clone(), mailer, issue_id);
+    Ok(Redirect::to(&format!("/admin/newsletter/issues/{}", issue_id)).into_response())
+}
+
+pub async fn newsletter_issue(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    let Some(issue) = newsletter::get_issue(&state.db, id)? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let post = match issue.content_id {
+        Some(content_id) => content::get_content_by_id(&state.db, content_id)?,
+        None => None,
+    };
+    let in_flight = newsletter::is_sending(id);
+
+    let mut ctx = make_admin_context(&state, &user);
+    ctx.insert("issue", &issue);
+    ctx.insert("post", &post);
+    ctx.insert("deliveries", &newsletter::list_deliveries(&state.db, id)?);
+    ctx.insert("in_flight", &in_flight);
+    ctx.insert(
+        "remaining",
+        &newsletter::pending_recipients(&state.db, id)?.len(),
+    );
+
+    let html = state.templates.render("admin/newsletter/issue.html", &ctx)?;
+    Ok(Html(html).into_response())
+}
+
+/// Resend an issue to confirmed subscribers who haven't received it yet,
+/// e.g. after delivery failures or an interrupted send.
+pub async fn retry_newsletter_issue(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    if newsletter::get_issue(&state.db, id)?.is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    if !newsletter::is_sending(id) {
+        let mailer = match newsletter::Mailer::from_config(&state.config().newsletter) {
+            Ok(m) => m,
+            Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+        };
+        spawn_issue_send(state.clone(), mailer, id);
+    }
+    Ok(Redirect::to(&format!("/admin/newsletter/issues/{}", id)).into_response())
+}
+
+// ============================================================================
+// Bulk Operations
+// ============================================================================
+
+#[derive(Deserialize)]
+pub struct BulkActionForm {
+    action: String,
+    #[serde(default)]
+    ids: String, // comma-separated content IDs
+}
+
+pub async fn bulk_action(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Form(form): Form<BulkActionForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_author_or_admin(&user) {
+        return Ok(e);
+    }
+
+    let ids: Vec<i64> = form
+        .ids
+        .split(',')
+        .filter_map(|s| s.trim().parse::<i64>().ok())
+        .collect();
+
+    if ids.is_empty() {
+        return Ok(Redirect::to("/admin/posts").into_response());
+    }
+
+    let action_label = form.action.clone();
+    let (content_types, version_retention) = {
+        let config = state.config();
+        (config.content_types.clone(), config.content.version_retention)
+    };
+
+    match form.action.as_str() {
+        "publish" => {
+            for id in &ids {
+                let _ = content::update_content(
+                    &state.db,
+                    &state.markdown,
+                    &content_types,
+                    *id,
+                    UpdateContent {
+                        status: Some(ContentStatus::Published),
+                        ..Default::default()
+                    },
+                    Some(user.id),
+                    version_retention,
+                );
+                webmention::notify_published(&state.db, &state.config(), *id);
+                social_card::spawn_render(&state.db, &state.config(), &state.media_dir, *id);
+            }
+        }
+        "draft" => {
+            for id in &ids {
+                let _ = content::update_content(
+                    &state.db,
+                    &state.markdown,
+                    &content_types,
+                    *id,
+                    UpdateContent {
+                        status: Some(ContentStatus::Draft),
+                        ..Default::default()
+                    },
+                    Some(user.id),
+                    version_retention,
+                );
+            }
+        }
+        "archive" => {
+            for id in &ids {
+                let _ = content::update_content(
+                    &state.db,
+                    &state.markdown,
+                    &content_types,
+                    *id,
+                    UpdateContent {
+                        status: Some(ContentStatus::Archived),
+                        ..Default::default()
+                    },
+                    Some(user.id),
+                    version_retention,
+                );
+            }
+        }
+        "delete" => {
+            for id in &ids {
+                let _ = content::delete_content(&state.db, &state.markdown, *id);
+            }
+        }
+        _ => {}
+    }
+
+    // Audit log for bulk action
+    audit_ctx.user_id = Some(user.id);
+    audit_ctx.username = Some(user.username.clone());
+    audit_ctx.user_role = Some(format!("{:?}", user.role));
+    let _ = audit::log(
+        &state.db,
+        &audit_ctx,
+        AuditLogBuilder::new(AuditAction::Update, AuditCategory::Content)
+            .metadata_value("bulk_action", serde_json::json!(action_label))
+            .metadata_value("affected_ids", serde_json::json!(ids)),
+    );
+
+    Ok(Redirect::to("/admin/posts").into_response())
+}
+
+// ===== API Token Management =====
+
+pub async fn tokens(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+) -> AppResult<Response> {
+    if let Err(e) = require_admin(&user) {
+        return Ok(e);
+    }
+
+    let tokens = api_token::list_tokens(&state.db).unwrap_or_default();
+    let mut ctx = make_admin_context(&state, &user);
+    ctx.insert("tokens", &tokens);
+    ctx.insert("new_token", &Option::<String>::None);
+
+    let html = state.templates.render("admin/tokens/index.html", &ctx)?;
+    Ok(Html(html).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct CreateTokenForm {
+    pub name: String,
+    pub permissions: Option<String>,
+    pub expires_days: Option<i64>,
+}
+
+pub async fn create_token(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(user): CurrentUser,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Form(form): Form<CreateTokenForm>,
+) -> AppResult<Response> {
+    if let Err(e) = require_admin(&user) {
+        return Ok(e);
+    }
+
+    let permissions = match form.permissions.as_deref() {
+        Some("sync") => "sync",
+        _ => "read",
+    };
+    let expires_at = form.expires_days.and_then(|days| {
+        if days > 0 {
+            Some(
+                (chrono::Utc::now() + chrono::Duration::days(days))
+                    .format("%Y-%m-%dT%H:%M:%S")
+                    .to_string(),
+            )
+        } else {
+            None
+        }
+    });
+
+    let (raw_token, _token) = api_token::create_token(
+        &state.db,
+        &form.name,
+        permissions,
+        Some(user.id),
+        expires_at.as_deref(),
     )?;
 
     // Audit log
@@ -2439,6 +3925,8 @@ pub struct WebhookForm {
     pub event_content_deleted: Option<String>,
     pub event_media_uploaded: Option<String>,
     pub event_media_deleted: Option<String>,
+    pub event_comment_created: Option<String>,
+    pub event_webmention_received: Option<String>,
     pub active: Option<String>,
 }
 
@@ -2463,6 +3951,12 @@ impl WebhookForm {
         if self.event_media_deleted.is_some() {
             events.push("media.deleted");
         }
+        if self.event_comment_created.is_some() {
+            events.push("comment.created");
+        }
+        if self.event_webmention_received.is_some() {
+            events.push("webmention.received");
+        }
         events.join(",")
     }
 }
@@ -1,6 +1,6 @@
 use crate::models::{ContentStatus, ContentType};
-use crate::services::{content, media, series, tags};
-use crate::web::extractors::ApiTokenAuth;
+use crate::services::{content, content_types, media, series, sync, tags};
+use crate::web::extractors::{ApiTokenAuth, AuditInfo};
 use crate::web::state::AppState;
 use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
@@ -149,6 +149,78 @@ pub async fn get_page(
     }
 }
 
+/// GET /api/v1/types
+pub async fn list_content_types(
+    State(state): State<Arc<AppState>>,
+    _auth: ApiTokenAuth,
+) -> Response {
+    let config = state.config();
+    let data: Vec<serde_json::Value> = config
+        .content_types
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "name": t.name,
+                "label": t.label(),
+                "prefix": t.prefix(),
+                "fields": t.fields,
+            })
+        })
+        .collect();
+    drop(config);
+    json_single(serde_json::json!(data)).into_response()
+}
+
+/// GET /api/v1/types/:type_name
+pub async fn list_entries_api(
+    State(state): State<Arc<AppState>>,
+    _auth: ApiTokenAuth,
+    Path(type_name): Path<String>,
+    Query(params): Query<PaginationParams>,
+) -> Response {
+    let config = state.config();
+    let default_size = config.api.default_page_size;
+    let max_size = config.api.max_page_size;
+    let known = content_types::find(&config.content_types, &type_name).is_some();
+    drop(config);
+    if !known {
+        return not_found("Content type not found");
+    }
+
+    let (page, per_page, offset) = paginate(params.page, params.per_page, default_size, max_size);
+    let total = content::count_entries(&state.db, &type_name, Some(ContentStatus::Published)).unwrap_or(0);
+
+    match content::list_published_entries(&state.db, &type_name, per_page, offset) {
+        Ok(entries) => json_envelope(serde_json::to_value(&entries).unwrap_or_default(), total, page, per_page).into_response(),
+        Err(e) => {
+            tracing::error!("API list_entries error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "Internal server error"}))).into_response()
+        }
+    }
+}
+
+/// GET /api/v1/types/:type_name/:slug
+pub async fn get_entry_api(
+    State(state): State<Arc<AppState>>,
+    _auth: ApiTokenAuth,
+    Path((type_name, slug)): Path<(String, String)>,
+) -> Response {
+    match content::get_content_by_slug(&state.db, &slug) {
+        Ok(Some(entry))
+            if entry.content.content_type == ContentType::Entry
+                && entry.content.entry_type.as_deref() == Some(type_name.as_str())
+                && entry.content.status == ContentStatus::Published =>
+        {
+            json_single(serde_json::to_value(&entry).unwrap_or_default()).into_response()
+        }
+        Ok(_) => not_found("Entry not found"),
+        Err(e) => {
+            tracing::error!("API get_entry error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "Internal server error"}))).into_response()
+        }
+    }
+}
+
 /// GET /api/v1/tags
 pub async fn list_tags(
     State(state): State<Arc<AppState>>,
@@ -272,3 +344,113 @@ pub async fn site_info(
     drop(config);
     json_single(data).into_response()
 }
+
+/// Sync endpoints change content, so they need a token created with the
+/// `sync` permission.
+fn sync_forbidden(auth: &ApiTokenAuth) -> Option<Response> {
+    if auth.0.permissions == "sync" {
+        return None;
+    }
+    let body = serde_json::json!({
+        "error": "Forbidden",
+        "message": "This endpoint requires a token with the sync permission",
+    });
+    Some((StatusCode::FORBIDDEN, Json(body)).into_response())
+}
+
+fn sync_error(e: anyhow::Error) -> Response {
+    let body = serde_json::json!({
+        "error": "Sync failed",
+        "message": format!("{:#}", e),
+    });
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+}
+
+/// GET /api/v1/sync/snapshot
+pub async fn sync_snapshot(State(state): State<Arc<AppState>>, auth: ApiTokenAuth) -> Response {
+    if let Some(forbidden) = sync_forbidden(&auth) {
+        return forbidden;
+    }
+    let config = state.config().clone();
+    match sync::snapshot(&state.db, &config) {
+        Ok(snapshot) => json_single(serde_json::to_value(&snapshot).unwrap_or_default()).into_response(),
+        Err(e) => {
+            tracing::error!("API sync_snapshot error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "Internal server error"}))).into_response()
+        }
+    }
+}
+
+/// GET /api/v1/sync/media/:filename
+pub async fn sync_media_download(
+    State(state): State<Arc<AppState>>,
+    auth: ApiTokenAuth,
+    Path(filename): Path<String>,
+) -> Response {
+    if let Some(forbidden) = sync_forbidden(&auth) {
+        return forbidden;
+    }
+    match sync::read_media_file(&state.media_dir, &filename) {
+        Ok(data) => ([(axum::http::header::CONTENT_TYPE, "application/octet-stream")], data).into_response(),
+        Err(_) => not_found("Media file not found"),
+    }
+}
+
+/// PUT /api/v1/sync/media/:filename
+pub async fn sync_media_upload(
+    State(state): State<Arc<AppState>>,
+    auth: ApiTokenAuth,
+    Path(filename): Path<String>,
+    body: axum::body::Bytes,
+) -> Response {
+    if let Some(forbidden) = sync_forbidden(&auth) {
+        return forbidden;
+    }
+    match sync::stage_media_file(&state.media_dir, &filename, &body) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => sync_error(e),
+    }
+}
+
+/// POST /api/v1/sync/plan
+pub async fn sync_plan(
+    State(state): State<Arc<AppState>>,
+    auth: ApiTokenAuth,
+    Json(request): Json<sync::SyncRequest>,
+) -> Response {
+    if let Some(forbidden) = sync_forbidden(&auth) {
+        return forbidden;
+    }
+    let config = state.config().clone();
+    match sync::plan(&state.db, &config, &request.source, &request.options) {
+        Ok(plan) => json_single(serde_json::to_value(&plan).unwrap_or_default()).into_response(),
+        Err(e) => sync_error(e),
+    }
+}
+
+/// POST /api/v1/sync/apply
+pub async fn sync_apply(
+    State(state): State<Arc<AppState>>,
+    auth: ApiTokenAuth,
+    AuditInfo(mut audit_ctx): AuditInfo,
+    Json(request): Json<sync::SyncRequest>,
+) -> Response {
+    if let Some(forbidden) = sync_forbidden(&auth) {
+        return forbidden;
+    }
+    audit_ctx.username = Some(format!("token:{}", auth.0.name));
+    let config = state.config().clone();
+    match sync::apply(
+        &state.db,
+        &config,
+        &state.markdown,
+        &request.source,
+        &request.options,
+        &audit_ctx,
+    ) {
+        Ok(report) => {
+            json_single(serde_json::to_value(&report).unwrap_or_default()).into_response()
+        }
+        Err(e) => sync_error(e),
+    }
+}
@@ -1,38 +1,19 @@
 use crate::models::UserRole;
 use crate::services::audit::{AuditAction, AuditCategory, AuditContext, AuditLogBuilder};
 use crate::services::{audit, auth};
+use crate::web::client_ip::ClientIp;
 use crate::web::error::AppResult;
 use crate::web::state::AppState;
-use axum::extract::{ConnectInfo, State};
+use axum::extract::State;
 use axum::http::{HeaderMap, StatusCode};
 use axum::response::{Html, IntoResponse, Redirect, Response};
 use axum::Form;
 use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
 use serde::Deserialize;
-use std::net::SocketAddr;
 use std::sync::Arc;
 use tera::Context;
 use time::Duration;
 
-fn get_client_ip(headers: &HeaderMap, addr: Option<SocketAddr>) -> String {
-    if let Some(forwarded) = headers.get("x-forwarded-for") {
-        if let Ok(s) = forwarded.to_str() {
-            if let Some(ip) = s.split(',').next() {
-                return ip.trim().to_string();
-            }
-        }
-    }
-
-    if let Some(real_ip) = headers.get("x-real-ip") {
-        if let Ok(s) = real_ip.to_str() {
-            return s.to_string();
-        }
-    }
-
-    addr.map(|a| a.ip().to_string())
-        .unwrap_or_else(|| "unknown".to_string())
-}
-
 fn get_csrf_cookie(jar: &CookieJar) -> Option<String> {
     jar.get("_csrf").map(|c| c.value().to_string())
 }
@@ -67,12 +48,12 @@ pub struct LoginForm {
 
 pub async fn login(
     State(state): State<Arc<AppState>>,
-    connect_info: Option<ConnectInfo<SocketAddr>>,
+    client_ip: ClientIp,
     headers: HeaderMap,
     jar: CookieJar,
     Form(form): Form<LoginForm>,
 ) -> AppResult<Response> {
-    let client_key = get_client_ip(&headers, connect_info.map(|c| c.0));
+    let client_key = client_ip.key();
     let csrf_cookie = get_csrf_cookie(&jar);
 
     let new_csrf = state.csrf.generate();
@@ -186,10 +167,10 @@ pub async fn login(
 pub async fn logout(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    connect_info: Option<ConnectInfo<SocketAddr>>,
+    client_ip: ClientIp,
     jar: CookieJar,
 ) -> AppResult<Response> {
-    let client_ip = get_client_ip(&headers, connect_info.map(|c| c.0));
+    let client_ip = client_ip.key();
     let user_agent = headers
         .get(axum::http::header::USER_AGENT)
         .and_then(|v| v.to_str().ok())
@@ -1,4 +1,4 @@
-use crate::services::{search, tags};
+use crate::services::{lint, search, tags};
 use crate::web::error::AppResult;
 use crate::web::extractors::CurrentUser;
 use crate::web::state::AppState;
@@ -28,6 +28,51 @@ pub async fn preview(
     Ok(Html(rendered))
 }
 
+#[derive(Deserialize)]
+pub struct CheckForm {
+    #[serde(default)]
+    content_id: Option<String>,
+    #[serde(default)]
+    content_type: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    body_markdown: String,
+    excerpt: Option<String>,
+    meta_title: Option<String>,
+    meta_description: Option<String>,
+}
+
+/// Content checks panel of the editor, run against the unsaved form.
+pub async fn check(
+    State(state): State<Arc<AppState>>,
+    CurrentUser(_user): CurrentUser,
+    Form(form): Form<CheckForm>,
+) -> AppResult<Html<String>> {
+    let config = state.config();
+    let non_empty = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+    let draft = lint::Draft {
+        id: form.content_id.and_then(|id| id.parse().ok()),
+        content_type: form.content_type.parse().unwrap_or_default(),
+        excerpt: lint::authored_excerpt(
+            form.excerpt.as_deref(),
+            &form.body_markdown,
+            config.content.excerpt_length,
+        ),
+        title: form.title,
+        body_markdown: form.body_markdown,
+        meta_title: non_empty(form.meta_title),
+        meta_description: non_empty(form.meta_description),
+    };
+    let issues = lint::check(&state.db, &config.content_types, &draft)?;
+
+    let mut ctx = Context::new();
+    ctx.insert("issues", &issues);
+
+    let rendered = state.templates.render("htmx/check.html", &ctx)?;
+    Ok(Html(rendered))
+}
+
 #[derive(Deserialize)]
 pub struct SearchQuery {
     q: Option<String>,
@@ -1,11 +1,22 @@
-use crate::models::{ContentType, User};
-use crate::services::{content, preview, search, series, settings, tags};
+use crate::models::{
+    CommentStatus, ContentStatus, ContentType, ContentWithTags, CreateComment, Subscriber, User,
+};
+use crate::config::ContentTypeConfig;
+use crate::services::{
+    comments, content, content_types, embeds, i18n, newsletter, preview, search, seo, series, settings, social_card, tags, webhook,
+    webmention,
+};
+use crate::web::client_ip::ClientIp;
 use crate::web::error::AppResult;
 use crate::web::extractors::OptionalUser;
+use crate::web::security::FrameSources;
 use crate::web::state::AppState;
+use crate::web::MetricsListener;
 use axum::extract::{Path, Query, State};
-use axum::http::{header, StatusCode};
-use axum::response::{Html, IntoResponse, Response};
+use axum::http::{header, HeaderMap, StatusCode, Uri};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use sha2::{Digest, Sha256};
 use serde::Deserialize;
 use std::sync::Arc;
 use tera::Context;
@@ -17,12 +28,110 @@ fn make_context(state: &AppState, user: &Option<User>) -> Context {
     ctx.insert("theme", &config.theme);
     ctx.insert("user", user);
     ctx.insert("production_mode", &state.production_mode);
+    ctx.insert("webmentions_enabled", &config.webmentions.enabled);
+    ctx.insert(
+        "pingback_enabled",
+        &(config.webmentions.enabled && config.webmentions.pingback),
+    );
+    ctx.insert("newsletter_enabled", &config.newsletter.enabled);
     if config.theme.custom.has_customizations() {
         ctx.insert("theme_custom_css", &config.theme.custom.to_css_variables());
     }
+    let default_language = config.site.language.clone();
+    drop(config);
+    state.localize(&mut ctx, &default_language, &[]);
+    ctx
+}
+
+/// Context for a page in `lang` (`None` for the default language), with the
+/// page's path in each language it exists in.
+fn localized_context(
+    state: &AppState,
+    user: &Option<User>,
+    lang: Option<&str>,
+    alternates: &[(String, String)],
+) -> Context {
+    let mut ctx = make_context(state, user);
+    let lang = lang
+        .map(str::to_string)
+        .unwrap_or_else(|| state.config().site.language.clone());
+    state.localize(&mut ctx, &lang, alternates);
     ctx
 }
 
+/// HTML response for a page showing `body_html`, allowing the frames its
+/// embeds load once the reader clicks them.
+fn content_response(html: String, body_html: &str) -> Response {
+    let mut response = Html(html).into_response();
+    let origins = embeds::frame_sources(body_html);
+    if !origins.is_empty() {
+        response.extensions_mut().insert(FrameSources(origins));
+    }
+    response
+}
+
+fn localized_not_found(
+    state: &AppState,
+    user: &Option<User>,
+    lang: Option<&str>,
+) -> AppResult<Response> {
+    let ctx = localized_context(state, user, lang, &[]);
+    let html = state.templates.render("public/404.html", &ctx)?;
+    Ok((StatusCode::NOT_FOUND, Html(html)).into_response())
+}
+
+/// Whether a route prefix is one of the site's additional languages.
+fn is_site_language(state: &AppState, lang: &str) -> bool {
+    state.config().i18n.is_additional(lang)
+}
+
+/// A listing path (`""` for the home page) in every language the site serves.
+fn listing_alternates(state: &AppState, path: &str) -> Vec<(String, String)> {
+    let config = state.config();
+    let default = config.site.language.as_str();
+    if config.i18n.languages.is_empty() {
+        return Vec::new();
+    }
+    i18n::site_languages(default, &config.i18n)
+        .into_iter()
+        .map(|code| {
+            let full = format!("{}{}", i18n::language_prefix(Some(&code), default), path);
+            let full = if full.is_empty() { "/".to_string() } else { full };
+            (code, full)
+        })
+        .collect()
+}
+
+/// Public path of a content item, with its language prefix.
+fn localized_path(state: &AppState, item: &ContentWithTags) -> String {
+    let config = state.config();
+    i18n::localized_content_path(&item.content, &config.site.language, &config.i18n)
+}
+
+fn content_alternates(state: &AppState, item: &ContentWithTags) -> Vec<(String, String)> {
+    let config = state.config();
+    i18n::content_alternates(
+        &state.db,
+        &item.content,
+        &config.site.language,
+        &config.i18n,
+    )
+}
+
+/// Permanent redirect to an item's canonical path when it was requested under
+/// another language prefix, keeping the query string.
+fn canonical_redirect(state: &AppState, item: &ContentWithTags, uri: &Uri) -> Option<Response> {
+    let path = localized_path(state, item);
+    if uri.path() == path {
+        return None;
+    }
+    let target = match uri.query() {
+        Some(q) => format!("{}?{}", path, q),
+        None => path,
+    };
+    Some(Redirect::permanent(&target).into_response())
+}
+
 const MAX_PAGE: usize = 10000;
 
 #[derive(Deserialize)]
@@ -42,108 +151,843 @@ fn clamp_page(page: usize) -> usize {
 pub async fn index(
     State(state): State<Arc<AppState>>,
     OptionalUser(user): OptionalUser,
-) -> AppResult<Html<String>> {
+) -> AppResult<Response> {
+    render_index(&state, &user, None)
+}
+
+/// Home page for an additional language: /:lang
+pub async fn localized_index(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Path(lang): Path<String>,
+) -> AppResult<Response> {
+    if !is_site_language(&state, &lang) {
+        return localized_not_found(&state, &user, None);
+    }
+    render_index(&state, &user, Some(&lang))
+}
+
+fn render_index(
+    state: &AppState,
+    user: &Option<User>,
+    lang: Option<&str>,
+) -> AppResult<Response> {
     let homepage_settings = settings::get_homepage_settings(&state.db).unwrap_or_default();
-    let config = state.config();
-    let posts = content::list_published_content(
+    let config = state.config().clone();
+    let posts = content::list_published_content_in_language(
         &state.db,
         ContentType::Post,
+        lang,
         config.content.posts_per_page,
         0,
     )?;
-    let pages = content::list_published_content(&state.db, ContentType::Page, 100, 0)?;
+    let pages =
+        content::list_published_content_in_language(&state.db, ContentType::Page, lang, 100, 0)?;
 
-    let mut ctx = make_context(&state, &user);
+    let mut ctx = localized_context(state, user, lang, &listing_alternates(state, ""));
     ctx.insert("posts", &posts);
     ctx.insert("pages", &pages);
     ctx.insert("homepage", &homepage_settings);
     ctx.insert("homepage_config", &config.homepage);
 
     let html = state.templates.render("public/index.html", &ctx)?;
-    Ok(Html(html))
+    Ok(Html(html).into_response())
 }
 
 pub async fn posts(
     State(state): State<Arc<AppState>>,
     OptionalUser(user): OptionalUser,
     Query(pagination): Query<Pagination>,
-) -> AppResult<Html<String>> {
+) -> AppResult<Response> {
+    render_posts(&state, &user, None, pagination.page)
+}
+
+/// Post listing for an additional language: /:lang/posts
+pub async fn localized_posts(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Path(lang): Path<String>,
+    Query(pagination): Query<Pagination>,
+) -> AppResult<Response> {
+    if !is_site_language(&state, &lang) {
+        return localized_not_found(&state, &user, None);
+    }
+    render_posts(&state, &user, Some(&lang), pagination.page)
+}
+
+fn render_posts(
+    state: &AppState,
+    user: &Option<User>,
+    lang: Option<&str>,
+    page: usize,
+) -> AppResult<Response> {
     let per_page = state.config().content.posts_per_page.max(1);
-    let page = clamp_page(pagination.page);
+    let page = clamp_page(page);
     let offset = page.saturating_sub(1).saturating_mul(per_page);
-    let posts = content::list_published_content(&state.db, ContentType::Post, per_page, offset)?;
-    let total = content::count_content(
+    let posts = content::list_published_content_in_language(
         &state.db,
-        Some(ContentType::Post),
-        Some(crate::models::ContentStatus::Published),
+        ContentType::Post,
+        lang,
+        per_page,
+        offset,
     )?;
+    let total = content::count_published_content_in_language(&state.db, ContentType::Post, lang)?;
     let total_pages = (total as usize + per_page - 1) / per_page;
 
-    let mut ctx = make_context(&state, &user);
+    let mut ctx = localized_context(state, user, lang, &listing_alternates(state, "/posts"));
     ctx.insert("posts", &posts);
     ctx.insert("page", &page);
     ctx.insert("total_pages", &total_pages);
 
     let html = state.templates.render("public/posts.html", &ctx)?;
-    Ok(Html(html))
+    Ok(Html(html).into_response())
+}
+
+#[derive(Deserialize, Default)]
+pub struct PostQuery {
+    comment: Option<String>,
+    reply_to: Option<i64>,
 }
 
 pub async fn post(
     State(state): State<Arc<AppState>>,
     OptionalUser(user): OptionalUser,
     Path(slug): Path<String>,
+    Query(query): Query<PostQuery>,
+    uri: Uri,
+) -> AppResult<Response> {
+    render_post(&state, &user, None, &slug, query, &uri)
+}
+
+/// A post in an additional language: /:lang/posts/:slug
+pub async fn localized_post(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Path((lang, slug)): Path<(String, String)>,
+    Query(query): Query<PostQuery>,
+    uri: Uri,
 ) -> AppResult<Response> {
-    let post = content::get_content_by_slug(&state.db, &slug)?;
+    if !is_site_language(&state, &lang) {
+        return localized_not_found(&state, &user, None);
+    }
+    render_post(&state, &user, Some(&lang), &slug, query, &uri)
+}
+
+fn render_post(
+    state: &AppState,
+    user: &Option<User>,
+    lang: Option<&str>,
+    slug: &str,
+    query: PostQuery,
+    uri: &Uri,
+) -> AppResult<Response> {
+    let post = content::get_content_by_slug(&state.db, slug)?;
 
     match post {
         Some(p)
             if p.content.content_type == ContentType::Post
-                && p.content.status == crate::models::ContentStatus::Published =>
+                && p.content.status == ContentStatus::Published =>
         {
-            let mut ctx = make_context(&state, &user);
-            // Series navigation (prev/next within a series)
-            if let Ok(Some(nav)) = series::get_series_navigation(&state.db, p.content.id) {
-                ctx.insert("series_nav", &nav);
+            if let Some(redirect) = canonical_redirect(state, &p, uri) {
+                return Ok(redirect);
+            }
+            let mut ctx = post_context(state, user, &p, query.reply_to);
+            if let Some(ref notice) = query.comment {
+                ctx.insert("comment_notice", notice);
             }
-            ctx.insert("content", &p);
 
             let html = state.templates.render("public/post.html", &ctx)?;
-            Ok(Html(html).into_response())
+            Ok(content_response(html, &p.content.body_html))
         }
-        _ => {
+        _ => localized_not_found(state, user, lang),
+    }
+}
+
+fn post_context(
+    state: &AppState,
+    user: &Option<User>,
+    post: &ContentWithTags,
+    reply_to: Option<i64>,
+) -> Context {
+    let alternates = content_alternates(state, post);
+    let mut ctx = localized_context(state, user, Some(&alternates[0].0), &alternates);
+    ctx.insert("content_path", &alternates[0].1);
+    let site_url = state.config().site.url.clone();
+    ctx.insert("seo", &state.content_seo(post, &site_url, &alternates[0].1));
+    // Series navigation (prev/next within a series)
+    if let Ok(Some(nav)) = series::get_series_navigation(&state.db, post.content.id) {
+        ctx.insert("series_nav", &nav);
+    }
+
+    let site_comments = state.config().comments.enabled;
+    let comments_enabled = comments::comments_enabled_for(&post.content.metadata, site_comments);
+    if site_comments {
+        let thread = comments::list_thread(&state.db, post.content.id).unwrap_or_default();
+        if let Some(parent) = reply_to.and_then(|id| thread.iter().find(|c| c.comment.id == id)) {
+            ctx.insert("reply_to", parent);
+        }
+        ctx.insert("comment_count", &thread.len());
+        ctx.insert("comments", &thread);
+    }
+    ctx.insert("comments_enabled", &comments_enabled);
+
+    if state.config().webmentions.enabled {
+        if let Ok(mentions) = webmention::list_for_content(&state.db, post.content.id) {
+            ctx.insert("webmentions", &mentions);
+        }
+    }
+    ctx.insert("content", post);
+    ctx
+}
+
+#[derive(Deserialize)]
+pub struct CommentForm {
+    author_name: String,
+    #[serde(default)]
+    author_url: Option<String>,
+    body: String,
+    #[serde(default)]
+    parent_id: Option<String>,
+    /// Honeypot field: hidden from humans, so any value means a bot filled it in
+    #[serde(default)]
+    website: Option<String>,
+}
+
+pub async fn post_comment(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    client_ip: ClientIp,
+    Path(slug): Path<String>,
+    Form(form): Form<CommentForm>,
+) -> AppResult<Response> {
+    let comments_config = state.config().comments.clone();
+    let post = content::get_content_by_slug(&state.db, &slug)?.filter(|p| {
+        p.content.content_type == ContentType::Post
+            && p.content.status == crate::models::ContentStatus::Published
+            && comments::comments_enabled_for(&p.content.metadata, comments_config.enabled)
+    });
+    let post = match post {
+        Some(p) => p,
+        None => {
             let ctx = make_context(&state, &user);
             let html = state.templates.render("public/404.html", &ctx)?;
-            Ok((StatusCode::NOT_FOUND, Html(html)).into_response())
+            return Ok((StatusCode::NOT_FOUND, Html(html)).into_response());
+        }
+    };
+
+    let post_path = localized_path(&state, &post);
+    let pending_redirect = format!("{}?comment=pending#comments", post_path);
+
+    if form.website.as_deref().is_some_and(|w| !w.is_empty()) {
+        tracing::debug!("Discarding comment on {} (honeypot filled)", slug);
+        return Ok(Redirect::to(&pending_redirect).into_response());
+    }
+
+    let client_key = format!("comment:{}", client_ip.key());
+    if !state.comment_rate_limiter.check(&client_key) {
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many comments. Please try again later.",
+        )
+            .into_response());
+    }
+    state.comment_rate_limiter.record_attempt(&client_key);
+
+    let parent_id = form
+        .parent_id
+        .as_deref()
+        .and_then(|s| s.trim().parse::<i64>().ok());
+    let status = if comments_config.require_approval {
+        CommentStatus::Pending
+    } else {
+        CommentStatus::Approved
+    };
+    let input = CreateComment {
+        content_id: post.content.id,
+        parent_id,
+        author_name: form.author_name.clone(),
+        author_url: form.author_url.clone(),
+        body_markdown: form.body.clone(),
+    };
+
+    let comment_id =
+        match comments::create_comment(&state.db, input, status, comments_config.max_depth) {
+            Ok(id) => id,
+            Err(e) => {
+                let mut ctx = post_context(&state, &user, &post, parent_id);
+                ctx.insert("comment_error", &e.to_string());
+                ctx.insert(
+                    "comment_form",
+                    &serde_json::json!({
+                        "author_name": form.author_name,
+                        "author_url": form.author_url,
+                        "body": form.body,
+                    }),
+                );
+                let html = state.templates.render("public/post.html", &ctx)?;
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    content_response(html, &post.content.body_html),
+                )
+                    .into_response());
+            }
+        };
+
+    let comment = comments::get_comment(&state.db, comment_id)?;
+    let in_reply_to = comment
+        .as_ref()
+        .and_then(|c| c.parent_id)
+        .and_then(|pid| comments::get_comment(&state.db, pid).ok().flatten())
+        .map(|p| serde_json::json!({ "id": p.id, "author_name": p.author_name }));
+    webhook::fire_webhooks(
+        &state.db,
+        "comment.created",
+        serde_json::json!({
+            "id": comment_id,
+            "status": status.to_string(),
+            "author_name": form.author_name.trim(),
+            "body": form.body,
+            "in_reply_to": in_reply_to,
+            "content": {
+                "id": post.content.id,
+                "title": post.content.title,
+                "slug": post.content.slug,
+            },
+        }),
+    );
+
+    if status == CommentStatus::Approved {
+        Ok(Redirect::to(&format!(
+            "{}?comment=posted#comment-{}",
+            post_path, comment_id
+        ))
+        .into_response())
+    } else {
+        Ok(Redirect::to(&pending_redirect).into_response())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WebmentionForm {
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    target: String,
+}
+
+/// Client key for rate limiting incoming Webmentions and Pingbacks.
+fn mention_client_key(client_ip: ClientIp) -> String {
+    format!("webmention:{}", client_ip.key())
+}
+
+/// Webmention receiver. Mentions are accepted (202) once the target checks
+/// out; the source is fetched and verified in the background.
+pub async fn receive_webmention(
+    State(state): State<Arc<AppState>>,
+    client_ip: ClientIp,
+    Form(form): Form<WebmentionForm>,
+) -> AppResult<Response> {
+    let config = state.config().clone();
+    if !config.webmentions.enabled {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let client_key = mention_client_key(client_ip);
+    if !state.webmention_rate_limiter.check(&client_key) {
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many webmentions. Please try again later.",
+        )
+            .into_response());
+    }
+    state.webmention_rate_limiter.record_attempt(&client_key);
+
+    match webmention::receive_mention(
+        &state.db,
+        &config.site.url,
+        &form.source,
+        &form.target,
+        webmention::PROTOCOL_WEBMENTION,
+    ) {
+        Ok(id) => {
+            webmention::spawn_verification(
+                &state.db,
+                id,
+                config.webmentions.allow_private_sources,
+            );
+            Ok((StatusCode::ACCEPTED, "Webmention accepted for verification").into_response())
+        }
+        Err(e) => Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    }
+}
+
+/// Pingback receiver (XML-RPC `pingback.ping`). Shares the Webmention
+/// verification pipeline; results are reported as XML-RPC faults.
+pub async fn receive_pingback(
+    State(state): State<Arc<AppState>>,
+    client_ip: ClientIp,
+    body: String,
+) -> AppResult<Response> {
+    let config = state.config().clone();
+    if !config.webmentions.enabled || !config.webmentions.pingback {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let xml = |body: String| ([(header::CONTENT_TYPE, "text/xml; charset=utf-8")], body);
+
+    let client_key = mention_client_key(client_ip);
+    if !state.webmention_rate_limiter.check(&client_key) {
+        return Ok(xml(webmention::pingback_fault(
+            webmention::PINGBACK_FAULT_GENERIC,
+            "Too many pingbacks. Please try again later.",
+        ))
+        .into_response());
+    }
+    state.webmention_rate_limiter.record_attempt(&client_key);
+
+    let Some((source, target)) = webmention::parse_pingback_request(&body) else {
+        return Ok(xml(webmention::pingback_fault(
+            webmention::PINGBACK_FAULT_GENERIC,
+            "Expected a pingback.ping call with source and target",
+        ))
+        .into_response());
+    };
+
+    let response = match webmention::receive_mention(
+        &state.db,
+        &config.site.url,
+        &source,
+        &target,
+        webmention::PROTOCOL_PINGBACK,
+    ) {
+        Ok(id) => {
+            webmention::spawn_verification(
+                &state.db,
+                id,
+                config.webmentions.allow_private_sources,
+            );
+            webmention::pingback_success("Pingback accepted for verification")
         }
+        Err(e) => {
+            webmention::pingback_fault(webmention::PINGBACK_FAULT_TARGET_INVALID, &e.to_string())
+        }
+    };
+    Ok(xml(response).into_response())
+}
+
+#[derive(Deserialize, Default)]
+pub struct NewsletterQuery {
+    status: Option<String>,
+}
+
+fn render_newsletter_page(
+    state: &AppState,
+    user: &Option<User>,
+    status: &str,
+) -> AppResult<String> {
+    let mut ctx = make_context(state, user);
+    ctx.insert("newsletter_status", status);
+    Ok(state.templates.render("public/newsletter.html", &ctx)?)
+}
+
+fn newsletter_not_found(state: &AppState, user: &Option<User>) -> AppResult<Response> {
+    let ctx = make_context(state, user);
+    let html = state.templates.render("public/404.html", &ctx)?;
+    Ok((StatusCode::NOT_FOUND, Html(html)).into_response())
+}
+
+/// Newsletter sign-up page, also used for the outcome of each step.
+pub async fn newsletter_page(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Query(query): Query<NewsletterQuery>,
+) -> AppResult<Response> {
+    if !state.config().newsletter.enabled {
+        return newsletter_not_found(&state, &user);
+    }
+    let status = query
+        .status
+        .as_deref()
+        .filter(|s| ["pending", "confirmed", "unsubscribed", "invalid"].contains(s))
+        .unwrap_or("form");
+    Ok(Html(render_newsletter_page(&state, &user, status)?).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SubscribeForm {
+    email: String,
+    #[serde(default)]
+    name: Option<String>,
+    /// Honeypot field: hidden from humans, so any value means a bot filled it in
+    #[serde(default)]
+    website: Option<String>,
+}
+
+/// Render the opt-in email for a pending subscriber and send it in the
+/// background. Failures are logged; the subscriber can simply sign up again.
+fn spawn_confirmation_email(state: Arc<AppState>, subscriber: Subscriber) {
+    let Some(token) = subscriber.confirm_token.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let config = state.config().clone();
+        let base = config.site.url.trim_end_matches('/').to_string();
+        let mut ctx = Context::new();
+        ctx.insert("site", &config.site);
+        ctx.insert("subscriber", &subscriber);
+        ctx.insert("confirm_url", &format!("{}/newsletter/confirm/{}", base, token));
+        let unsubscribe_url = format!(
+            "{}/newsletter/unsubscribe/{}",
+            base, subscriber.unsubscribe_token
+        );
+        ctx.insert("unsubscribe_url", &unsubscribe_url);
+
+        let result = async {
+            let email = newsletter::OutgoingEmail {
+                to: subscriber.email.clone(),
+                subject: format!("Confirm your subscription to {}", config.site.title),
+                html: state.templates.render("email/confirm.html", &ctx)?,
+                text: state.templates.render("email/confirm.txt", &ctx)?,
+                unsubscribe_url: None,
+            };
+            newsletter::Mailer::from_config(&config.newsletter)?
+                .send(&email)
+                .await
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to send newsletter confirmation to {}: {}",
+                subscriber.email,
+                e
+            );
+        }
+    });
+}
+
+pub async fn newsletter_subscribe(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    client_ip: ClientIp,
+    Form(form): Form<SubscribeForm>,
+) -> AppResult<Response> {
+    if !state.config().newsletter.enabled {
+        return newsletter_not_found(&state, &user);
+    }
+
+    let pending_redirect = "/newsletter?status=pending";
+
+    if form.website.as_deref().is_some_and(|w| !w.is_empty()) {
+        tracing::debug!("Discarding newsletter sign-up (honeypot filled)");
+        return Ok(Redirect::to(pending_redirect).into_response());
+    }
+
+    let client_key = format!("newsletter:{}", client_ip.key());
+    if !state.newsletter_rate_limiter.check(&client_key) {
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many sign-ups. Please try again later.",
+        )
+            .into_response());
+    }
+    state.newsletter_rate_limiter.record_attempt(&client_key);
+
+    match newsletter::subscribe(&state.db, &form.email, form.name.as_deref()) {
+        Ok(Some(subscriber)) => spawn_confirmation_email(state.clone(), subscriber),
+        // Already confirmed: respond the same way so the form can't be used
+        // to find out who is subscribed
+        Ok(None) => {}
+        Err(e) => {
+            let mut ctx = make_context(&state, &user);
+            ctx.insert("newsletter_status", "form");
+            ctx.insert("newsletter_error", &e.to_string());
+            ctx.insert("newsletter_email", form.email.trim());
+            let html = state.templates.render("public/newsletter.html", &ctx)?;
+            return Ok((StatusCode::BAD_REQUEST, Html(html)).into_response());
+        }
+    }
+
+    Ok(Redirect::to(pending_redirect).into_response())
+}
+
+pub async fn newsletter_confirm(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Path(token): Path<String>,
+) -> AppResult<Response> {
+    if !state.config().newsletter.enabled {
+        return newsletter_not_found(&state, &user);
+    }
+
+    let status = match newsletter::confirm_subscription(&state.db, &token)? {
+        Some(_) => "confirmed",
+        None => "invalid",
+    };
+    Ok(Redirect::to(&format!("/newsletter?status={}", status)).into_response())
+}
+
+/// Unsubscribe landing page. Shows a confirmation button rather than acting
+/// on GET, so link scanners in mail filters can't unsubscribe readers.
+pub async fn newsletter_unsubscribe_form(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Path(token): Path<String>,
+) -> AppResult<Response> {
+    if !state.config().newsletter.enabled {
+        return newsletter_not_found(&state, &user);
+    }
+
+    let Some(subscriber) = newsletter::get_subscriber_by_unsubscribe_token(&state.db, &token)?
+    else {
+        return Ok(Html(render_newsletter_page(&state, &user, "invalid")?).into_response());
+    };
+    let mut ctx = make_context(&state, &user);
+    ctx.insert("newsletter_status", "unsubscribe");
+    ctx.insert("subscriber", &subscriber);
+    ctx.insert("token", &token);
+    let html = state.templates.render("public/newsletter.html", &ctx)?;
+    Ok(Html(html).into_response())
+}
+
+/// Unsubscribe. Also the target of one-click `List-Unsubscribe-Post`
+/// requests from mail clients (RFC 8058).
+pub async fn newsletter_unsubscribe(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Path(token): Path<String>,
+) -> AppResult<Response> {
+    if !state.config().newsletter.enabled {
+        return newsletter_not_found(&state, &user);
     }
+
+    let status = match newsletter::unsubscribe(&state.db, &token)? {
+        Some(_) => "unsubscribed",
+        None => "invalid",
+    };
+    Ok(Redirect::to(&format!("/newsletter?status={}", status)).into_response())
 }
 
 pub async fn page(
     State(state): State<Arc<AppState>>,
     OptionalUser(user): OptionalUser,
     Path(slug): Path<String>,
+    uri: Uri,
+) -> AppResult<Response> {
+    render_page(&state, &user, None, &slug, &uri)
+}
+
+/// A page in an additional language: /:lang/pages/:slug
+pub async fn localized_page(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Path((lang, slug)): Path<(String, String)>,
+    uri: Uri,
 ) -> AppResult<Response> {
-    let page = content::get_content_by_slug(&state.db, &slug)?;
+    if !is_site_language(&state, &lang) {
+        return localized_not_found(&state, &user, None);
+    }
+    render_page(&state, &user, Some(&lang), &slug, &uri)
+}
+
+fn render_page(
+    state: &AppState,
+    user: &Option<User>,
+    lang: Option<&str>,
+    slug: &str,
+    uri: &Uri,
+) -> AppResult<Response> {
+    let page = content::get_content_by_slug(&state.db, slug)?;
 
     match page {
         Some(p)
             if p.content.content_type == ContentType::Page
-                && p.content.status == crate::models::ContentStatus::Published =>
+                && p.content.status == ContentStatus::Published =>
         {
-            let mut ctx = make_context(&state, &user);
+            if let Some(redirect) = canonical_redirect(state, &p, uri) {
+                return Ok(redirect);
+            }
+            let alternates = content_alternates(state, &p);
+            let mut ctx = localized_context(state, user, Some(&alternates[0].0), &alternates);
+            let site_url = state.config().site.url.clone();
+            ctx.insert("seo", &state.content_seo(&p, &site_url, &alternates[0].1));
             ctx.insert("content", &p);
 
             let html = state.templates.render("public/page.html", &ctx)?;
-            Ok(Html(html).into_response())
+            Ok(content_response(html, &p.content.body_html))
         }
-        _ => {
-            let ctx = make_context(&state, &user);
-            let html = state.templates.render("public/404.html", &ctx)?;
-            Ok((StatusCode::NOT_FOUND, Html(html)).into_response())
+        _ => localized_not_found(state, user, lang),
+    }
+}
+
+/// The custom content type whose prefix is the first segment of `uri`.
+fn content_type_for(state: &AppState, uri: &Uri) -> Option<ContentTypeConfig> {
+    let prefix = uri.path().trim_start_matches('/').split('/').next()?;
+    content_types::find_by_prefix(&state.config().content_types, prefix).cloned()
+}
+
+fn content_type_context(def: &ContentTypeConfig) -> serde_json::Value {
+    serde_json::json!({
+        "name": def.name,
+        "label": def.label(),
+        "prefix": def.prefix(),
+        "fields": def.fields,
+    })
+}
+
+fn entry_view(
+    state: &AppState,
+    def: &ContentTypeConfig,
+    entry: ContentWithTags,
+) -> content_types::EntryView {
+    let config = state.config().clone();
+    content_types::entry_view(&state.db, &config.content_types, def, entry, |c| {
+        i18n::localized_content_path(c, &config.site.language, &config.i18n)
+    })
+}
+
+/// Listing of a custom content type: /:prefix
+pub async fn entries(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Query(pagination): Query<Pagination>,
+    uri: Uri,
+) -> AppResult<Response> {
+    let def = match content_type_for(&state, &uri) {
+        Some(def) => def,
+        None => return localized_not_found(&state, &user, None),
+    };
+    let per_page = state.config().content.posts_per_page.max(1);
+    let page = clamp_page(pagination.page);
+    let offset = page.saturating_sub(1).saturating_mul(per_page);
+    let items = content::list_published_entries(&state.db, &def.name, per_page, offset)?;
+    let total = content::count_entries(&state.db, &def.name, Some(ContentStatus::Published))?;
+    let total_pages = (total as usize).div_ceil(per_page);
+    let entries: Vec<_> = items
+        .into_iter()
+        .map(|entry| entry_view(&state, &def, entry))
+        .collect();
+
+    let mut ctx = make_context(&state, &user);
+    ctx.insert("content_type", &content_type_context(&def));
+    ctx.insert("entries", &entries);
+    ctx.insert("page", &page);
+    ctx.insert("total_pages", &total_pages);
+
+    let html = state
+        .templates
+        .render(&content_types::list_template_name(&def), &ctx)?;
+    Ok(Html(html).into_response())
+}
+
+/// A single entry of a custom content type: /:prefix/:slug
+pub async fn entry(
+    State(state): State<Arc<AppState>>,
+    OptionalUser(user): OptionalUser,
+    Path(slug): Path<String>,
+    uri: Uri,
+) -> AppResult<Response> {
+    let def = match content_type_for(&state, &uri) {
+        Some(def) => def,
+        None => return localized_not_found(&state, &user, None),
+    };
+    match content::get_content_by_slug(&state.db, &slug)? {
+        Some(item)
+            if item.content.content_type == ContentType::Entry
+                && item.content.entry_type.as_deref() == Some(def.name.as_str())
+                && item.content.status == ContentStatus::Published =>
+        {
+            let view = entry_view(&state, &def, item.clone());
+            let mut ctx = make_context(&state, &user);
+            let site_url = state.config().site.url.clone();
+            ctx.insert(
+                "seo",
+                &state.content_seo(&item, &site_url, &def.entry_path(&item.content.slug)),
+            );
+            ctx.insert("content_type", &content_type_context(&def));
+            ctx.insert("content", &item);
+            ctx.insert("entry", &view);
+
+            let html = state
+                .templates
+                .render(&content_types::detail_template_name(&def), &ctx)?;
+            Ok(content_response(html, &item.content.body_html))
         }
+        _ => localized_not_found(&state, &user, None),
     }
 }
 
+/// RSS feed of a custom content type: /:prefix/feed.xml
+pub async fn entries_feed(State(state): State<Arc<AppState>>, uri: Uri) -> AppResult<Response> {
+    let def = match content_type_for(&state, &uri) {
+        Some(def) => def,
+        None => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+    let items = content::list_published_entries(&state.db, &def.name, 20, 0)?;
+    let config = state.config();
+    let site = &config.site;
+
+    let mut rss_items = String::new();
+    for item in &items {
+        let path = def.entry_path(&item.content.slug);
+        let pub_date = item
+            .content
+            .published_at
+            .as_deref()
+            .map(to_rfc822)
+            .unwrap_or_default();
+        rss_items.push_str(&format!(
+            r#"
+    <item>
+      <title>{}</title>
+      <link>{}{}</link>
+      <description><![CDATA[{}]]></description>
+      <pubDate>{}</pubDate>
+      <guid isPermaLink="true">{}{}</guid>
+    </item>"#,
+            html_escape(&item.content.title),
+            site.url,
+            path,
+            item.content.excerpt.as_deref().unwrap_or(""),
+            pub_date,
+            site.url,
+            path,
+        ));
+    }
+
+    let rss = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>{} — {}</title>
+    <link>{}/{}</link>
+    <description>{} on {}</description>
+    <language>{}</language>
+    <atom:link href="{}/{}/feed.xml" rel="self" type="application/rss+xml"/>
+    {}
+  </channel>
+</rss>"#,
+        html_escape(def.label()),
+        html_escape(&site.title),
+        site.url,
+        def.prefix(),
+        html_escape(def.label()),
+        html_escape(&site.title),
+        site.language,
+        site.url,
+        def.prefix(),
+        rss_items
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/rss+xml; charset=utf-8"),
+            (header::CACHE_CONTROL, "public, max-age=3600"),
+        ],
+        rss,
+    )
+        .into_response())
+}
+
 pub async fn tags_page(
     State(state): State<Arc<AppState>>,
     OptionalUser(user): OptionalUser,
@@ -218,9 +1062,26 @@ pub async fn search(
 }
 
 pub async fn rss_feed(State(state): State<Arc<AppState>>) -> AppResult<Response> {
-    let posts = content::list_published_content(&state.db, ContentType::Post, 20, 0)?;
+    render_rss_feed(&state, None)
+}
+
+/// RSS feed for an additional language: /:lang/feed.xml
+pub async fn localized_rss_feed(
+    State(state): State<Arc<AppState>>,
+    Path(lang): Path<String>,
+) -> AppResult<Response> {
+    if !is_site_language(&state, &lang) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    render_rss_feed(&state, Some(&lang))
+}
+
+fn render_rss_feed(state: &AppState, lang: Option<&str>) -> AppResult<Response> {
+    let posts =
+        content::list_published_content_in_language(&state.db, ContentType::Post, lang, 20, 0)?;
     let config = state.config();
     let site = &config.site;
+    let prefix = i18n::language_prefix(lang, &site.language);
 
     let mut items = String::new();
     for post in &posts {
@@ -234,17 +1095,19 @@ pub async fn rss_feed(State(state): State<Arc<AppState>>) -> AppResult<Response>
             r#"
     <item>
       <title>{}</title>
-      <link>{}/posts/{}</link>
+      <link>{}{}/posts/{}</link>
       <description><![CDATA[{}]]></description>
       <pubDate>{}</pubDate>
-      <guid isPermaLink="true">{}/posts/{}</guid>
+      <guid isPermaLink="true">{}{}/posts/{}</guid>
     </item>"#,
             html_escape(&post.content.title),
             site.url,
+            prefix,
             post.content.slug,
             post.content.excerpt.as_deref().unwrap_or(""),
             pub_date,
             site.url,
+            prefix,
             post.content.slug
         ));
     }
@@ -254,18 +1117,20 @@ pub async fn rss_feed(State(state): State<Arc<AppState>>) -> AppResult<Response>
 <rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
   <channel>
     <title>{}</title>
-    <link>{}</link>
+    <link>{}{}</link>
     <description>{}</description>
     <language>{}</language>
-    <atom:link href="{}/feed.xml" rel="self" type="application/rss+xml"/>
+    <atom:link href="{}{}/feed.xml" rel="self" type="application/rss+xml"/>
     {}
   </channel>
 </rss>"#,
         html_escape(&site.title),
         site.url,
+        prefix,
         html_escape(&site.description),
-        site.language,
+        lang.unwrap_or(&site.language),
         site.url,
+        prefix,
         items
     );
 
@@ -319,6 +1184,41 @@ pub async fn serve_media(
         .into_response())
 }
 
+/// Generated share image of a post without a featured or social image.
+pub async fn social_card(
+    State(state): State<Arc<AppState>>,
+    Path(filename): Path<String>,
+) -> AppResult<Response> {
+    let config = state.config().clone();
+    let Some(slug) = filename.strip_suffix(".png") else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if !config.social_cards.enabled {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let item = match content::get_content_by_slug(&state.db, slug)? {
+        Some(item) if social_card::needs_card(&item) => item,
+        _ => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+
+    // Rendering and the cache writes block, so keep them off the async workers
+    let media_dir = state.media_dir.clone();
+    let path = tokio::task::spawn_blocking(move || {
+        social_card::cached_card(&media_dir, &config.theme, &config.site.title, &item)
+    })
+    .await??;
+    let png = tokio::fs::read(&path).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "public, max-age=3600"),
+        ],
+        png,
+    )
+        .into_response())
+}
+
 pub async fn serve_js(
     State(state): State<Arc<AppState>>,
     Path(filename): Path<String>,
@@ -339,17 +1239,45 @@ pub async fn serve_js(
     }
 }
 
+pub async fn highlight_css(State(state): State<Arc<AppState>>) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "text/css; charset=utf-8"),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        state.highlight_css.clone(),
+    )
+        .into_response()
+}
+
 pub async fn json_feed(State(state): State<Arc<AppState>>) -> AppResult<Response> {
-    let posts = content::list_published_content(&state.db, ContentType::Post, 20, 0)?;
+    render_json_feed(&state, None)
+}
+
+/// JSON feed for an additional language: /:lang/feed.json
+pub async fn localized_json_feed(
+    State(state): State<Arc<AppState>>,
+    Path(lang): Path<String>,
+) -> AppResult<Response> {
+    if !is_site_language(&state, &lang) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    render_json_feed(&state, Some(&lang))
+}
+
+fn render_json_feed(state: &AppState, lang: Option<&str>) -> AppResult<Response> {
+    let posts =
+        content::list_published_content_in_language(&state.db, ContentType::Post, lang, 20, 0)?;
     let config = state.config();
     let site = &config.site;
+    let prefix = i18n::language_prefix(lang, &site.language);
 
     let items: Vec<serde_json::Value> = posts
         .iter()
         .map(|post| {
             serde_json::json!({
-                "id": format!("{}/posts/{}", site.url, post.content.slug),
-                "url": format!("{}/posts/{}", site.url, post.content.slug),
+                "id": format!("{}{}/posts/{}", site.url, prefix, post.content.slug),
+                "url": format!("{}{}/posts/{}", site.url, prefix, post.content.slug),
                 "title": post.content.title,
                 "content_html": post.content.body_html,
                 "summary": post.content.excerpt,
@@ -363,10 +1291,10 @@ pub async fn json_feed(State(state): State<Arc<AppState>>) -> AppResult<Response
     let feed = serde_json::json!({
         "version": "https://jsonfeed.org/version/1.1",
         "title": site.title,
-        "home_page_url": site.url,
-        "feed_url": format!("{}/feed.json", site.url),
+        "home_page_url": format!("{}{}", site.url, prefix),
+        "feed_url": format!("{}{}/feed.json", site.url, prefix),
         "description": site.description,
-        "language": site.language,
+        "language": lang.unwrap_or(&site.language),
         "items": items
     });
 
@@ -380,26 +1308,96 @@ pub async fn json_feed(State(state): State<Arc<AppState>>) -> AppResult<Response
         .into_response())
 }
 
+/// `xhtml:link` alternates for a sitemap entry that exists in several languages.
+fn sitemap_alternates(site_url: &str, alternates: &[(String, String)]) -> String {
+    if alternates.len() < 2 {
+        return String::new();
+    }
+    alternates
+        .iter()
+        .map(|(code, path)| {
+            format!(
+                "\n    <xhtml:link rel=\"alternate\" hreflang=\"{}\" href=\"{}{}\"/>",
+                code, site_url, path
+            )
+        })
+        .collect()
+}
+
 pub async fn sitemap(State(state): State<Arc<AppState>>) -> AppResult<Response> {
-    let posts = content::list_published_content(&state.db, ContentType::Post, 1000, 0)?;
-    let pages = content::list_published_content(&state.db, ContentType::Page, 100, 0)?;
+    let indexable = |item: &ContentWithTags| !seo::is_noindex(&item.content.metadata);
+    let posts: Vec<_> = content::list_published_content(&state.db, ContentType::Post, 1000, 0)?
+        .into_iter()
+        .filter(indexable)
+        .collect();
+    let pages: Vec<_> = content::list_published_content(&state.db, ContentType::Page, 100, 0)?
+        .into_iter()
+        .filter(indexable)
+        .collect();
     let tags_list = tags::list_tags_with_counts(&state.db)?;
+    let home_alternates = listing_alternates(&state, "");
     let config = state.config();
     let site = &config.site;
+    let default_language = site.language.as_str();
+
+    // Localized path of every item, and the language versions of each translation group
+    let located = |item: &ContentWithTags| {
+        (
+            i18n::route_language(item.content.language.as_deref(), &config.i18n)
+                .unwrap_or(default_language)
+                .to_string(),
+            i18n::localized_content_path(&item.content, default_language, &config.i18n),
+        )
+    };
+    let mut groups: std::collections::HashMap<&str, Vec<(String, String)>> =
+        std::collections::HashMap::new();
+    for item in posts.iter().chain(pages.iter()) {
+        if let Some(group) = item.content.translation_group.as_deref() {
+            groups.entry(group).or_default().push(located(item));
+        }
+    }
+    let alternates_for = |item: &ContentWithTags| {
+        item.content
+            .translation_group
+            .as_deref()
+            .and_then(|g| groups.get(g))
+            .map(|alts| sitemap_alternates(&site.url, alts))
+            .unwrap_or_default()
+    };
 
     let mut urls = String::new();
 
-    urls.push_str(&format!(
-        r#"  <url>
+    if home_alternates.is_empty() {
+        urls.push_str(&format!(
+            r#"  <url>
     <loc>{}</loc>
     <changefreq>daily</changefreq>
     <priority>1.0</priority>
   </url>
 "#,
-        site.url
-    ));
+            site.url
+        ));
+    } else {
+        let links = sitemap_alternates(&site.url, &home_alternates);
+        for (_, path) in &home_alternates {
+            let loc = if path == "/" {
+                site.url.clone()
+            } else {
+                format!("{}{}", site.url, path)
+            };
+            urls.push_str(&format!(
+                r#"  <url>
+    <loc>{}</loc>
+    <changefreq>daily</changefreq>
+    <priority>1.0</priority>{}
+  </url>
+"#,
+                loc, links
+            ));
+        }
+    }
 
-    for post in posts {
+    for post in &posts {
         let image_tag = post
             .content
             .featured_image
@@ -416,32 +1414,63 @@ pub async fn sitemap(State(state): State<Arc<AppState>>) -> AppResult<Response>
             .unwrap_or_default();
         urls.push_str(&format!(
             r#"  <url>
-    <loc>{}/posts/{}</loc>
+    <loc>{}{}</loc>
     <lastmod>{}</lastmod>
     <changefreq>weekly</changefreq>
-    <priority>0.8</priority>{}
+    <priority>0.8</priority>{}{}
   </url>
 "#,
             site.url,
-            post.content.slug,
+            located(post).1,
             post.content.updated_at,
+            alternates_for(post),
             image_tag
         ));
     }
 
-    for page in pages {
+    for page in &pages {
         urls.push_str(&format!(
             r#"  <url>
-    <loc>{}/pages/{}</loc>
+    <loc>{}{}</loc>
     <lastmod>{}</lastmod>
     <changefreq>monthly</changefreq>
+    <priority>0.6</priority>{}
+  </url>
+"#,
+            site.url,
+            located(page).1,
+            page.content.updated_at,
+            alternates_for(page)
+        ));
+    }
+
+    for def in &config.content_types {
+        urls.push_str(&format!(
+            r#"  <url>
+    <loc>{}/{}</loc>
+    <changefreq>weekly</changefreq>
     <priority>0.6</priority>
   </url>
 "#,
             site.url,
-            page.content.slug,
-            page.content.updated_at
+            def.prefix()
         ));
+        let entries =
+            content::list_published_entries(&state.db, &def.name, 1000, 0).unwrap_or_default();
+        for entry in entries.iter().filter(|e| indexable(e)) {
+            urls.push_str(&format!(
+                r#"  <url>
+    <loc>{}{}</loc>
+    <lastmod>{}</lastmod>
+    <changefreq>monthly</changefreq>
+    <priority>0.6</priority>
+  </url>
+"#,
+                site.url,
+                def.entry_path(&entry.content.slug),
+                entry.content.updated_at
+            ));
+        }
     }
 
     for tag in tags_list {
@@ -459,7 +1488,8 @@ pub async fn sitemap(State(state): State<Arc<AppState>>) -> AppResult<Response>
     let sitemap = format!(
         r#"<?xml version="1.0" encoding="UTF-8"?>
 <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
-        xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">
+        xmlns:image="http://www.google.com/schemas/sitemap-image/1.1"
+        xmlns:xhtml="http://www.w3.org/1999/xhtml">
 {}
 </urlset>"#,
         urls
@@ -523,6 +1553,7 @@ pub async fn tag_rss_feed(
 
     let mut items = String::new();
     for post in posts.iter().take(20) {
+        let path = i18n::localized_content_path(&post.content, &site.language, &config.i18n);
         let pub_date = post
             .content
             .published_at
@@ -533,19 +1564,19 @@ pub async fn tag_rss_feed(
             r#"
     <item>
       <title>{}</title>
-      <link>{}/posts/{}</link>
+      <link>{}{}</link>
       <description><![CDATA[{}]]></description>
       <pubDate>{}</pubDate>
-      <guid isPermaLink="true">{}/posts/{}</guid>
+      <guid isPermaLink="true">{}{}</guid>
       <category>{}</category>
     </item>"#,
             html_escape(&post.content.title),
             site.url,
-            post.content.slug,
+            path,
             post.content.excerpt.as_deref().unwrap_or(""),
             pub_date,
             site.url,
-            post.content.slug,
+            path,
             html_escape(&tag.name),
         ));
     }
@@ -623,6 +1654,44 @@ pub async fn robots_txt(State(state): State<Arc<AppState>>) -> Response {
         .into_response()
 }
 
+/// Prometheus metrics, when `metrics.enabled`. Served on `metrics.listen`
+/// when that is set, otherwise on the site itself; either way the bearer
+/// token is required when one is configured.
+pub async fn metrics(
+    State(state): State<Arc<AppState>>,
+    listener: Option<Extension<MetricsListener>>,
+    headers: HeaderMap,
+) -> Response {
+    let config = state.config();
+    let metrics = &config.metrics;
+    if !metrics.enabled || (metrics.listen.is_some() && listener.is_none()) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if let Some(token) = metrics.token.as_deref().filter(|t| !t.is_empty()) {
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or("");
+        // Compare digests so the comparison time says nothing about the token
+        if Sha256::digest(presented.as_bytes()) != Sha256::digest(token.as_bytes()) {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+            )
+                .into_response();
+        }
+    }
+
+    let body = state.metrics.render(&config, &state.db);
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
 /// Health check endpoint — returns DB status for reverse proxies and uptime monitors.
 pub async fn health(State(state): State<Arc<AppState>>) -> Response {
     match state.db.health_check() {
@@ -672,6 +1741,12 @@ pub async fn draft_preview(
     match item {
         Some(p) => {
             let mut ctx = make_context(&state, &user);
+            let path = localized_path(&state, &p);
+            let site_url = state.config().site.url.clone();
+            // Previews must never be indexed, whatever the item's own setting
+            let mut seo = state.content_seo(&p, &site_url, &path);
+            seo.noindex = true;
+            ctx.insert("seo", &seo);
             ctx.insert("content", &p);
             ctx.insert("is_preview", &true);
 
@@ -682,7 +1757,7 @@ pub async fn draft_preview(
             };
 
             let html = state.templates.render(template, &ctx)?;
-            Ok(Html(html).into_response())
+            Ok(content_response(html, &p.content.body_html))
         }
         None => {
             let ctx = make_context(&state, &user);
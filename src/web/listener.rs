@@ -0,0 +1,400 @@
+//! Where a server listens: a TCP address, a Unix domain socket
+//! (`unix:/run/pebble.sock`) or a socket passed in by systemd socket
+//! activation (`systemd`, or `systemd:<name>` to pick one of several).
+//!
+//! Connections over a Unix socket come from a reverse proxy on the same
+//! machine and carry no peer address, so they are given `127.0.0.1` as one:
+//! the default `server.trusted_proxies` then lets the proxy's forwarding
+//! header supply the client address (see `client_ip`).
+
+use anyhow::{bail, Context, Result};
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use tokio::net::TcpListener;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(String),
+    Unix(PathBuf),
+    /// A socket from systemd, by name (`FileDescriptorName=`) or index
+    Systemd(Option<String>),
+}
+
+impl ListenAddr {
+    pub fn parse(addr: &str) -> Result<Self> {
+        let addr = addr.trim();
+        if let Some(path) = addr.strip_prefix("unix:") {
+            if path.is_empty() {
+                bail!("Missing socket path in '{}'", addr);
+            }
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+        if addr == "systemd" {
+            return Ok(Self::Systemd(None));
+        }
+        if let Some(name) = addr.strip_prefix("systemd:") {
+            return Ok(Self::Systemd(Some(name.to_string())));
+        }
+        if !addr.contains(':') {
+            bail!(
+                "Invalid listen address '{}' (expected host:port, unix:/path or systemd)",
+                addr
+            );
+        }
+        Ok(Self::Tcp(addr.to_string()))
+    }
+
+    /// The listen address from `--listen`, else from config, else host and port.
+    pub fn resolve(
+        flag: Option<&str>,
+        config: Option<&str>,
+        host: &str,
+        port: u16,
+    ) -> Result<Self> {
+        match flag.or(config) {
+            Some(addr) => Self::parse(addr),
+            None => Ok(Self::Tcp(format!("{}:{}", host, port))),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "http://{}", addr),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            Self::Systemd(None) => write!(f, "systemd socket"),
+            Self::Systemd(Some(name)) => write!(f, "systemd socket '{}'", name),
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix {
+        listener: tokio::net::UnixListener,
+        /// Socket file to remove on shutdown, when this process created it
+        path: Option<PathBuf>,
+    },
+    /// A TCP listener serving HTTPS (`tls.enabled`)
+    #[cfg(feature = "tls")]
+    Tls {
+        listener: TcpListener,
+        acceptor: tokio_rustls::TlsAcceptor,
+    },
+}
+
+impl Listener {
+    /// Bind `addr`. `socket_mode` is the octal permission mode for a Unix
+    /// socket Pebble creates, e.g. `660`.
+    pub async fn bind(addr: &ListenAddr, socket_mode: Option<&str>) -> Result<Self> {
+        match addr {
+            ListenAddr::Tcp(addr) => {
+                Ok(Self::Tcp(TcpListener::bind(addr).await.with_context(
+                    || format!("Failed to listen on {}", addr),
+                )?))
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => bind_unix(path, socket_mode),
+            #[cfg(unix)]
+            ListenAddr::Systemd(name) => from_systemd(name.as_deref()),
+            #[cfg(not(unix))]
+            _ => {
+                let _ = socket_mode;
+                bail!("Unix sockets and socket activation are only supported on Unix")
+            }
+        }
+    }
+
+    /// Serve HTTPS instead of HTTP. Only TCP listeners can.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(self, acceptor: tokio_rustls::TlsAcceptor) -> Result<Self> {
+        match self {
+            Self::Tcp(listener) => Ok(Self::Tls { listener, acceptor }),
+            _ => bail!("HTTPS needs a TCP listener, not {}", self.describe()),
+        }
+    }
+
+    /// Whether the listener accepts connections on every network interface.
+    pub fn is_unspecified(&self) -> bool {
+        match self {
+            #[cfg(feature = "tls")]
+            Self::Tls { listener, .. } => listener
+                .local_addr()
+                .map(|addr| addr.ip().is_unspecified())
+                .unwrap_or(false),
+            Self::Tcp(listener) => listener
+                .local_addr()
+                .map(|addr| addr.ip().is_unspecified())
+                .unwrap_or(false),
+            #[cfg(unix)]
+            Self::Unix { .. } => false,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp(listener) => listener
+                .local_addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or_else(|_| "TCP socket".to_string()),
+            #[cfg(feature = "tls")]
+            Self::Tls { listener, .. } => listener
+                .local_addr()
+                .map(|addr| format!("https://{}", addr))
+                .unwrap_or_else(|_| "TLS socket".to_string()),
+            #[cfg(unix)]
+            Self::Unix { listener, .. } => listener
+                .local_addr()
+                .ok()
+                .and_then(|addr| addr.as_pathname().map(|p| format!("unix:{}", p.display())))
+                .unwrap_or_else(|| "Unix socket".to_string()),
+        }
+    }
+}
+
+/// Serve `app` on `listener` until `shutdown` completes.
+pub async fn serve(
+    listener: Listener,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    match listener {
+        Listener::Tcp(listener) => {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown)
+            .await?;
+        }
+        #[cfg(feature = "tls")]
+        Listener::Tls { listener, acceptor } => {
+            super::tls::serve(listener, acceptor, app, shutdown).await?;
+        }
+        #[cfg(unix)]
+        Listener::Unix { listener, path } => {
+            let app = app.layer(middleware::from_fn(local_peer));
+            let result = serve_unix(listener, app, shutdown).await;
+            if let Some(path) = path {
+                let _ = std::fs::remove_file(path);
+            }
+            result?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve_unix(
+    listener: tokio::net::UnixListener,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    use hyper::server::conn::http1;
+    use hyper_util::rt::TokioIo;
+    use hyper_util::server::graceful::GracefulShutdown;
+    use hyper_util::service::TowerToHyperService;
+
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        // Running out of file descriptors, for example; retry shortly
+                        tracing::warn!("Failed to accept connection: {}", e);
+                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let service = TowerToHyperService::new(app.clone());
+                let connection = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+                let connection = graceful.watch(connection);
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        tracing::debug!("Connection error: {}", e);
+                    }
+                });
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+/// Record a Unix socket peer as a local address.
+async fn local_peer(mut request: Request<Body>, next: Next) -> Response {
+    request.extensions_mut().insert(ConnectInfo(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        0,
+    )));
+    next.run(request).await
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path, socket_mode: Option<&str>) -> Result<Listener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    // A socket left behind by a server that did not shut down cleanly
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{} exists and is not a socket", path.display());
+        }
+        std::fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on unix:{}", path.display()))?;
+    if let Some(mode) = socket_mode {
+        let mode = parse_socket_mode(mode)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    Ok(Listener::Unix {
+        listener,
+        path: Some(path.to_path_buf()),
+    })
+}
+
+/// Parse an octal permission mode such as `660` or `0660`.
+pub fn parse_socket_mode(mode: &str) -> Result<u32> {
+    match u32::from_str_radix(mode.trim(), 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => bail!("Invalid socket mode '{}' (expected octal, e.g. 660)", mode),
+    }
+}
+
+/// Take over a listening socket passed in by systemd, TCP or Unix.
+#[cfg(unix)]
+fn from_systemd(name: Option<&str>) -> Result<Listener> {
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+    let fd = systemd_fd(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::env::var("LISTEN_FDNAMES").ok().as_deref(),
+        name,
+    )?;
+    claim_fd(fd)?;
+
+    // Safety: systemd passes the sockets as descriptors 3 and up, owned by
+    // this process, and claim_fd ensures each is taken over only once
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+    if tcp.local_addr().is_ok() {
+        tcp.set_nonblocking(true)?;
+        return Ok(Listener::Tcp(TcpListener::from_std(tcp)?));
+    }
+    let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+    unix.local_addr()
+        .context("The socket from systemd is neither a TCP nor a Unix socket")?;
+    unix.set_nonblocking(true)?;
+    Ok(Listener::Unix {
+        listener: tokio::net::UnixListener::from_std(unix)?,
+        path: None,
+    })
+}
+
+/// The descriptor of the socket `name` (a `FileDescriptorName=` or an
+/// index; the first socket when `None`) in the `LISTEN_*` variables.
+#[cfg(unix)]
+fn systemd_fd(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    listen_fdnames: Option<&str>,
+    name: Option<&str>,
+) -> Result<std::os::unix::io::RawFd> {
+    const FIRST_FD: i32 = 3;
+
+    let (Some(pid), Some(count)) = (listen_pid, listen_fds) else {
+        bail!("No sockets were passed in by systemd (LISTEN_FDS is not set)");
+    };
+    if pid.trim().parse::<u32>().ok() != Some(std::process::id()) {
+        bail!("The systemd sockets were passed to another process (LISTEN_PID does not match)");
+    }
+    let count: usize = count.trim().parse().context("Invalid LISTEN_FDS")?;
+
+    let index = match name {
+        None => 0,
+        Some(name) => match name.parse::<usize>() {
+            Ok(index) => index,
+            Err(_) => listen_fdnames
+                .unwrap_or_default()
+                .split(':')
+                .position(|n| n == name)
+                .with_context(|| format!("systemd passed no socket named '{}'", name))?,
+        },
+    };
+    if index >= count {
+        bail!(
+            "systemd passed {} socket(s), socket {} requested",
+            count,
+            index
+        );
+    }
+    Ok(FIRST_FD + index as i32)
+}
+
+#[cfg(unix)]
+fn claim_fd(fd: std::os::unix::io::RawFd) -> Result<()> {
+    static CLAIMED: std::sync::Mutex<Vec<i32>> = std::sync::Mutex::new(Vec::new());
+    let mut claimed = CLAIMED.lock().unwrap_or_else(|e| e.into_inner());
+    if claimed.contains(&fd) {
+        bail!("The same systemd socket is used for two listeners");
+    }
+    claimed.push(fd);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!(
+            ListenAddr::parse("127.0.0.1:3000").unwrap(),
+            ListenAddr::Tcp("127.0.0.1:3000".into())
+        );
+        assert_eq!(
+            ListenAddr::parse("unix:/run/pebble.sock").unwrap(),
+            ListenAddr::Unix("/run/pebble.sock".into())
+        );
+        assert_eq!(
+            ListenAddr::parse("systemd:admin").unwrap(),
+            ListenAddr::Systemd(Some("admin".into()))
+        );
+        assert!(ListenAddr::parse("unix:").is_err());
+        assert!(ListenAddr::parse("localhost").is_err());
+        assert_eq!(parse_socket_mode("0660").unwrap(), 0o660);
+        assert!(parse_socket_mode("999").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_systemd_fd() {
+        let pid = std::process::id().to_string();
+        let fd = |fds, names, name| systemd_fd(Some(&pid), Some(fds), names, name);
+        assert_eq!(fd("1", None, None).unwrap(), 3);
+        assert_eq!(fd("2", Some("web:admin"), Some("admin")).unwrap(), 4);
+        assert_eq!(fd("2", None, Some("1")).unwrap(), 4);
+        assert!(fd("1", None, Some("1")).is_err());
+        assert!(fd("2", Some("web:admin"), Some("metrics")).is_err());
+        assert!(systemd_fd(Some("1"), Some("1"), None, None).is_err());
+        assert!(systemd_fd(None, None, None, None).is_err());
+    }
+}
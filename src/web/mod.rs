@@ -1,30 +1,39 @@
+pub mod client_ip;
 mod error;
 mod extractors;
 mod handlers;
+pub mod listener;
 mod routes;
 pub mod security;
 mod state;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod vhost;
 
 pub use state::AppState;
 
+use client_ip::ClientIp;
+use listener::{ListenAddr, Listener};
+
 use crate::services::analytics::{
     extract_browser_family, extract_device_type, extract_referrer_domain, generate_session_hash,
     get_daily_salt, lookup_country, run_aggregation_job, Analytics, AnalyticsConfig,
     AnalyticsEvent,
 };
+use crate::config::ContentTypeConfig;
+use crate::services::metrics::Metrics;
 use crate::{Config, Database};
 use anyhow::Result;
 use axum::body::Body;
-use axum::extract::{ConnectInfo, State};
+use axum::extract::State;
 use axum::http::Request;
 use axum::middleware::{self, Next};
 use axum::response::Response;
-use axum::Router;
-use std::net::SocketAddr;
+use axum::routing::get;
+use axum::{Extension, Router};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::net::TcpListener;
 use tower_http::compression::CompressionLayer;
 use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
@@ -33,14 +42,18 @@ pub async fn serve(
     config: Config,
     config_path: PathBuf,
     db: Database,
-    addr: &str,
+    metrics: Arc<Metrics>,
+    listen: &ListenAddr,
     shutdown_rx: Option<tokio::sync::watch::Receiver<bool>>,
 ) -> Result<()> {
     let analytics_config = AnalyticsConfig::default();
     let analytics = Arc::new(Analytics::with_config(db.clone(), analytics_config));
 
-    let state =
-        AppState::new(config, config_path, db.clone(), false)?.with_analytics(analytics.clone());
+    let content_types = config.content_types.clone();
+    let metrics_listener = bind_metrics(&config).await?;
+    let state = AppState::new(config, config_path, db.clone(), false)?
+        .with_analytics(analytics.clone())
+        .with_metrics(metrics);
     let state = Arc::new(state);
 
     let analytics_aggregator = analytics.clone();
@@ -57,31 +70,13 @@ pub async fn serve(
         }
     });
 
-    let app = Router::new()
-        .merge(routes::public_routes())
-        .merge(routes::admin_routes())
-        .merge(routes::htmx_routes())
-        .merge(routes::api_routes())
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            security::write_rate_limit_middleware,
-        ))
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            analytics_middleware,
-        ))
-        .layer(middleware::from_fn(security::apply_security_headers))
-        .layer(CompressionLayer::new())
-        .layer(TimeoutLayer::with_status_code(axum::http::StatusCode::GATEWAY_TIMEOUT, std::time::Duration::from_secs(30)))
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+    let socket_mode = state.config().server.socket_mode.clone();
+    let metrics = serve_metrics(metrics_listener, state.clone(), shutdown_signal());
+    let app = site_app(state, &content_types);
 
-    let listener = TcpListener::bind(addr).await?;
-    let app = app.into_make_service_with_connect_info::<SocketAddr>();
-    tracing::info!("Server listening on {}", addr);
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    let listener = Listener::bind(listen, socket_mode.as_deref()).await?;
+    tracing::info!("Server listening on {}", listener.describe());
+    tokio::try_join!(listener::serve(listener, app, shutdown_signal()), metrics)?;
 
     // Signal background tasks to stop
     agg_handle.abort();
@@ -89,19 +84,24 @@ pub async fn serve(
     Ok(())
 }
 
+/// Serve a site read-only on `listen`. With `admin_listen`, the same process
+/// also serves the full site, admin included, on that second listener, so the
+/// admin can be kept off the public interface and reached over an SSH tunnel
+/// or VPN. Both listeners share one state.
 pub async fn serve_production(
     config: &Config,
     config_path: PathBuf,
-    host: &str,
-    port: u16,
+    db: Database,
+    metrics: Arc<Metrics>,
+    listen: &ListenAddr,
+    admin_listen: Option<&ListenAddr>,
 ) -> Result<()> {
-    let db = Database::open(&config.database.path)?;
-
     let analytics_config = AnalyticsConfig::default();
     let analytics = Arc::new(Analytics::with_config(db.clone(), analytics_config));
 
     let state = AppState::new(config.clone(), config_path, db.clone(), true)?
-        .with_analytics(analytics.clone());
+        .with_analytics(analytics.clone())
+        .with_metrics(metrics);
     let state = Arc::new(state);
 
     let analytics_aggregator = analytics.clone();
@@ -109,36 +109,172 @@ pub async fn serve_production(
         run_aggregation_job(analytics_aggregator).await;
     });
 
+    let socket_mode = config.server.socket_mode.as_deref();
+    let listener = Listener::bind(listen, socket_mode).await?;
+    let admin_listener = match admin_listen {
+        Some(admin_listen) => {
+            let listener = Listener::bind(admin_listen, socket_mode).await?;
+            if listener.is_unspecified() {
+                tracing::warn!(
+                    "Admin listener on {} accepts connections on every interface",
+                    admin_listen
+                );
+            }
+            Some(listener)
+        }
+        None => None,
+    };
+    let metrics_listener = bind_metrics(config).await?;
+
+    // One signal stops every listener and the HTTPS background tasks
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = stop_tx.send(true);
+    });
+
+    #[cfg(feature = "tls")]
+    let (listener, https) = if config.tls.enabled {
+        let https = tls::Https::start(config, stop_rx.clone()).await?;
+        (listener.with_tls(https.acceptor.clone())?, Some(https))
+    } else {
+        (listener, None)
+    };
+    #[cfg(not(feature = "tls"))]
+    if config.tls.enabled {
+        anyhow::bail!("HTTPS requires Pebble built with the 'tls' feature");
+    }
+
+    let stopped = |mut rx: tokio::sync::watch::Receiver<bool>| async move {
+        let _ = rx.wait_for(|stop| *stop).await;
+    };
+
+    let app = production_app(state.clone(), &config.content_types);
+    tracing::info!("Production server listening on {}", listener.describe());
+    let public = listener::serve(listener, app, stopped(stop_rx.clone()));
+    let metrics = serve_metrics(metrics_listener, state.clone(), stopped(stop_rx.clone()));
+
+    let admin = async {
+        match admin_listener {
+            Some(admin_listener) => {
+                let admin_app = site_app(state, &config.content_types);
+                tracing::info!("Admin server listening on {}", admin_listener.describe());
+                listener::serve(admin_listener, admin_app, stopped(stop_rx)).await
+            }
+            None => Ok(()),
+        }
+    };
+    tokio::try_join!(public, admin, metrics)?;
+
+    #[cfg(feature = "tls")]
+    if let Some(https) = https {
+        https.stopped().await;
+    }
+    agg_handle.abort();
+    tracing::info!("Production server shut down gracefully");
+    Ok(())
+}
+
+/// Marks requests that came in on `metrics.listen`, where `/metrics` is served.
+#[derive(Clone, Copy)]
+pub(crate) struct MetricsListener;
+
+/// Bind `metrics.listen` when metrics are served on their own address.
+async fn bind_metrics(config: &Config) -> Result<Option<Listener>> {
+    match (&config.metrics.listen, config.metrics.enabled) {
+        (Some(addr), true) => {
+            let listener =
+                Listener::bind(&ListenAddr::parse(addr)?, config.server.socket_mode.as_deref())
+                    .await?;
+            if listener.is_unspecified() && config.metrics.token.is_none() {
+                tracing::warn!(
+                    "Metrics listener on {} accepts connections on every interface without a token",
+                    addr
+                );
+            }
+            Ok(Some(listener))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Serve only `/metrics` on `listener`, if there is one.
+async fn serve_metrics(
+    listener: Option<Listener>,
+    state: Arc<AppState>,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let Some(listener) = listener else {
+        return Ok(());
+    };
     let app = Router::new()
+        .route("/metrics", get(handlers::public::metrics))
+        .layer(Extension(MetricsListener))
+        .with_state(state);
+    tracing::info!("Metrics listening on {}", listener.describe());
+    listener::serve(listener, app, shutdown).await
+}
+
+/// A site with public, admin, HTMX and API routes and their middleware.
+pub(crate) fn site_app(state: Arc<AppState>, content_types: &[ContentTypeConfig]) -> Router {
+    Router::new()
         .merge(routes::public_routes())
+        .merge(routes::content_type_routes(content_types))
+        .merge(routes::admin_routes())
+        .merge(routes::htmx_routes())
         .merge(routes::api_routes())
-        .merge(routes::production_fallback_routes())
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            security::write_rate_limit_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             analytics_middleware,
         ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            client_ip::client_ip_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            metrics_middleware,
+        ))
         .layer(middleware::from_fn(security::apply_security_headers))
         .layer(CompressionLayer::new())
         .layer(TimeoutLayer::with_status_code(axum::http::StatusCode::GATEWAY_TIMEOUT, std::time::Duration::from_secs(30)))
         .layer(TraceLayer::new_for_http())
-        .with_state(state);
-
-    let addr = format!("{}:{}", host, port);
-    let listener = TcpListener::bind(&addr).await?;
-    let app = app.into_make_service_with_connect_info::<SocketAddr>();
-    tracing::info!("Production server listening on {}", addr);
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+        .with_state(state)
+}
 
-    agg_handle.abort();
-    tracing::info!("Production server shut down gracefully");
-    Ok(())
+/// A read-only site without admin routes, as served by `pebble deploy`.
+pub(crate) fn production_app(state: Arc<AppState>, content_types: &[ContentTypeConfig]) -> Router {
+    Router::new()
+        .merge(routes::public_routes())
+        .merge(routes::content_type_routes(content_types))
+        .merge(routes::api_routes())
+        .merge(routes::production_fallback_routes())
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            analytics_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            client_ip::client_ip_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            metrics_middleware,
+        ))
+        .layer(middleware::from_fn(security::apply_security_headers))
+        .layer(CompressionLayer::new())
+        .layer(TimeoutLayer::with_status_code(axum::http::StatusCode::GATEWAY_TIMEOUT, std::time::Duration::from_secs(30)))
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
 }
 
 /// Listens for SIGTERM/SIGINT and returns when either is received.
 /// On Unix, also listens for SIGTERM. On all platforms, listens for Ctrl+C.
-async fn shutdown_signal() {
+pub(crate) async fn shutdown_signal() {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
             .await
@@ -166,9 +302,24 @@ async fn shutdown_signal() {
     }
 }
 
+/// Count the request and its latency for `/metrics`.
+async fn metrics_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    state
+        .metrics
+        .record_request(&path, response.status().as_u16(), start.elapsed());
+    response
+}
+
 async fn analytics_middleware(
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    client_ip: ClientIp,
     request: Request<Body>,
     next: Next,
 ) -> Response {
@@ -205,7 +356,7 @@ async fn analytics_middleware(
         .unwrap_or("")
         .to_string();
 
-    let ip = addr.ip().to_string();
+    let ip = client_ip.key();
 
     let response = next.run(request).await;
 
@@ -237,7 +388,9 @@ async fn analytics_middleware(
         };
 
         // Record event immediately for real-time analytics
-        if let Err(e) = analytics.record_event(&event) {
+        let recorded = analytics.record_event(&event);
+        state.metrics.record_analytics_event(recorded.is_ok());
+        if let Err(e) = recorded {
             tracing::error!("Failed to record analytics event: {}", e);
         }
     }
@@ -247,7 +400,13 @@ async fn analytics_middleware(
 
 fn should_skip_tracking(path: &str) -> bool {
     let skip_prefixes = ["/static", "/media", "/admin", "/api", "/htmx", "/_"];
-    let skip_exact = ["/robots.txt", "/favicon.ico", "/health", "/sitemap.xml"];
+    let skip_exact = [
+        "/robots.txt",
+        "/favicon.ico",
+        "/health",
+        "/metrics",
+        "/sitemap.xml",
+    ];
 
     skip_prefixes.iter().any(|p| path.starts_with(p))
         || skip_exact.contains(&path)
@@ -286,3 +445,85 @@ fn extract_content_info(path: &str, db: &Database) -> (Option<i64>, Option<Strin
     }
     (None, None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_prefix(prefix: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            [site]
+            title = "Test"
+            description = ""
+            url = "http://localhost:3000"
+            language = "en"
+            [server]
+            [database]
+            path = ":memory:"
+            [content]
+            [media]
+            upload_dir = "media"
+            [theme]
+            name = "default"
+            [auth]
+            [[content_types]]
+            name = "project"
+            prefix = "{}"
+            "#,
+            prefix
+        ))
+        .unwrap()
+    }
+
+    fn state(config: &Config) -> Arc<AppState> {
+        let db = Database::open_memory(&format!("web_{}", uuid::Uuid::new_v4())).unwrap();
+        db.migrate().unwrap();
+        Arc::new(AppState::new(config.clone(), PathBuf::from("pebble.toml"), db, false).unwrap())
+    }
+
+    /// Prefixes that collide with a built-in route make axum panic when the
+    /// routers are merged, so validation must refuse them first.
+    #[test]
+    fn test_reserved_prefixes_keep_the_router_buildable() {
+        let config = config_with_prefix("projects");
+        config.validate().unwrap();
+        let _ = site_app(state(&config), &config.content_types);
+        let _ = production_app(state(&config), &config.content_types);
+
+        for prefix in ["metrics", "og"] {
+            let config = config_with_prefix(prefix);
+            assert!(config.validate().is_err(), "{} was accepted", prefix);
+            let state = state(&config);
+            let built = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                site_app(state, &config.content_types)
+            }));
+            assert!(built.is_err(), "{} does not collide", prefix);
+        }
+    }
+
+    /// Sites hosted in one process each render with their own shortcodes and
+    /// heading anchor setting, whichever was loaded last.
+    #[test]
+    fn test_sites_render_with_their_own_settings() {
+        let dir = std::env::temp_dir().join(format!("pebble-web-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("badge.html"), r#"<span class="badge">{{ body }}</span>"#).unwrap();
+
+        let mut first = config_with_prefix("projects");
+        first.content.shortcodes_dir = dir.to_string_lossy().to_string();
+        first.content.heading_anchors = true;
+        let first = state(&first);
+        let second = state(&config_with_prefix("projects"));
+
+        let markdown = "## Intro\n\n[badge]New[/badge]";
+        let html = first.markdown.render(markdown);
+        assert!(html.contains(r#"class="heading-anchor""#), "{}", html);
+        assert!(html.contains(r#"<span class="badge">"#), "{}", html);
+        let html = second.markdown.render(markdown);
+        assert!(!html.contains("heading-anchor"), "{}", html);
+        assert!(!html.contains("badge\""), "{}", html);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
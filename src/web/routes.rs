@@ -1,5 +1,6 @@
 use super::handlers;
 use super::state::AppState;
+use crate::config::ContentTypeConfig;
 use axum::extract::DefaultBodyLimit;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
@@ -12,6 +13,26 @@ pub fn public_routes() -> Router<Arc<AppState>> {
         .route("/", get(handlers::public::index))
         .route("/posts", get(handlers::public::posts))
         .route("/posts/:slug", get(handlers::public::post))
+        .route(
+            "/posts/:slug/comments",
+            post(handlers::public::post_comment),
+        )
+        .route("/webmention", post(handlers::public::receive_webmention))
+        .route("/xmlrpc", post(handlers::public::receive_pingback))
+        .route("/newsletter", get(handlers::public::newsletter_page))
+        .route(
+            "/newsletter/subscribe",
+            post(handlers::public::newsletter_subscribe),
+        )
+        .route(
+            "/newsletter/confirm/:token",
+            get(handlers::public::newsletter_confirm),
+        )
+        .route(
+            "/newsletter/unsubscribe/:token",
+            get(handlers::public::newsletter_unsubscribe_form)
+                .post(handlers::public::newsletter_unsubscribe),
+        )
         .route("/pages/:slug", get(handlers::public::page))
         .route("/tags", get(handlers::public::tags))
         .route("/tags/:slug", get(handlers::public::tag))
@@ -25,13 +46,40 @@ pub fn public_routes() -> Router<Arc<AppState>> {
         )
         .route("/sitemap.xml", get(handlers::public::sitemap))
         .route("/media/:filename", get(handlers::public::serve_media))
+        .route("/og/:filename", get(handlers::public::social_card))
         .route("/js/:filename", get(handlers::public::serve_js))
+        .route("/css/highlight.css", get(handlers::public::highlight_css))
         .route("/robots.txt", get(handlers::public::robots_txt))
         .route("/health", get(handlers::public::health))
+        .route("/metrics", get(handlers::public::metrics))
         .route(
             "/preview/:token",
             get(handlers::public::draft_preview),
         )
+        // Additional languages (i18n.languages), e.g. /fr/posts/:slug
+        .route("/:lang", get(handlers::public::localized_index))
+        .route("/:lang/posts", get(handlers::public::localized_posts))
+        .route("/:lang/posts/:slug", get(handlers::public::localized_post))
+        .route("/:lang/pages/:slug", get(handlers::public::localized_page))
+        .route("/:lang/feed.xml", get(handlers::public::localized_rss_feed))
+        .route("/:lang/feed.json", get(handlers::public::localized_json_feed))
+}
+
+/// Listing, entry and feed routes for each custom content type. Prefixes come
+/// from config, so these are registered when the server starts.
+pub fn content_type_routes(types: &[ContentTypeConfig]) -> Router<Arc<AppState>> {
+    let mut router = Router::new();
+    for def in types {
+        let prefix = def.prefix();
+        router = router
+            .route(&format!("/{}", prefix), get(handlers::public::entries))
+            .route(
+                &format!("/{}/feed.xml", prefix),
+                get(handlers::public::entries_feed),
+            )
+            .route(&format!("/{}/:slug", prefix), get(handlers::public::entry));
+    }
+    router
 }
 
 pub fn admin_routes() -> Router<Arc<AppState>> {
@@ -51,6 +99,10 @@ pub fn admin_routes() -> Router<Arc<AppState>> {
             "/admin/posts/:id/delete",
             post(handlers::admin::delete_post),
         )
+        .route(
+            "/admin/posts/:id/translations/unlink",
+            post(handlers::admin::unlink_translation),
+        )
         // Post version routes
         .route(
             "/admin/posts/:id/versions",
@@ -77,6 +129,10 @@ pub fn admin_routes() -> Router<Arc<AppState>> {
             "/admin/pages/:id/delete",
             post(handlers::admin::delete_page),
         )
+        .route(
+            "/admin/pages/:id/translations/unlink",
+            post(handlers::admin::unlink_translation),
+        )
         // Page version routes
         .route(
             "/admin/pages/:id/versions",
@@ -167,6 +223,82 @@ pub fn admin_routes() -> Router<Arc<AppState>> {
             "/admin/snippets/:id/delete",
             post(handlers::admin::delete_snippet),
         )
+        // Shortcode reference
+        .route("/admin/shortcodes", get(handlers::admin::shortcodes))
+        // Custom content type routes
+        .route(
+            "/admin/types/:type_name",
+            get(handlers::admin::type_entries).post(handlers::admin::create_entry),
+        )
+        .route(
+            "/admin/types/:type_name/new",
+            get(handlers::admin::new_entry),
+        )
+        .route(
+            "/admin/types/:type_name/:id/edit",
+            get(handlers::admin::edit_entry),
+        )
+        .route(
+            "/admin/types/:type_name/:id",
+            post(handlers::admin::update_entry),
+        )
+        .route(
+            "/admin/types/:type_name/:id/delete",
+            post(handlers::admin::delete_entry),
+        )
+        // Comment moderation
+        .route("/admin/comments", get(handlers::admin::comments_queue))
+        .route(
+            "/admin/comments/:id/approve",
+            post(handlers::admin::approve_comment),
+        )
+        .route(
+            "/admin/comments/:id/spam",
+            post(handlers::admin::spam_comment),
+        )
+        .route(
+            "/admin/comments/:id/delete",
+            post(handlers::admin::delete_comment),
+        )
+        // Webmentions
+        .route("/admin/webmentions", get(handlers::admin::webmentions))
+        .route(
+            "/admin/webmentions/:id/delete",
+            post(handlers::admin::delete_webmention),
+        )
+        // External link checker
+        .route("/admin/links", get(handlers::admin::links))
+        .route("/admin/links/check", post(handlers::admin::check_links))
+        // Newsletter
+        .route("/admin/newsletter", get(handlers::admin::newsletter))
+        .route(
+            "/admin/newsletter/subscribers",
+            post(handlers::admin::add_subscriber_handler),
+        )
+        .route(
+            "/admin/newsletter/subscribers/:id/delete",
+            post(handlers::admin::delete_subscriber_handler),
+        )
+        .route(
+            "/admin/newsletter/export.csv",
+            get(handlers::admin::export_subscribers),
+        )
+        .route(
+            "/admin/newsletter/import",
+            post(handlers::admin::import_subscribers),
+        )
+        .route(
+            "/admin/newsletter/issues/:id",
+            get(handlers::admin::newsletter_issue),
+        )
+        .route(
+            "/admin/newsletter/issues/:id/retry",
+            post(handlers::admin::retry_newsletter_issue),
+        )
+        .route(
+            "/admin/posts/:id/newsletter",
+            post(handlers::admin::send_post_newsletter),
+        )
         // Bulk operations
         .route("/admin/bulk", post(handlers::admin::bulk_action))
         // API token management
@@ -203,6 +335,7 @@ pub fn admin_routes() -> Router<Arc<AppState>> {
 pub fn htmx_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/htmx/preview", post(handlers::htmx::preview))
+        .route("/htmx/check", post(handlers::htmx::check))
         .route("/htmx/search", get(handlers::htmx::search))
         .route(
             "/htmx/tags/autocomplete",
@@ -234,12 +367,34 @@ pub fn api_routes() -> Router<Arc<AppState>> {
         .route("/api/v1/posts/:slug", get(handlers::api::get_post))
         .route("/api/v1/pages", get(handlers::api::list_pages))
         .route("/api/v1/pages/:slug", get(handlers::api::get_page))
+        .route("/api/v1/types", get(handlers::api::list_content_types))
+        .route("/api/v1/types/:type_name", get(handlers::api::list_entries_api))
+        .route(
+            "/api/v1/types/:type_name/:slug",
+            get(handlers::api::get_entry_api),
+        )
         .route("/api/v1/tags", get(handlers::api::list_tags))
         .route("/api/v1/tags/:slug", get(handlers::api::get_tag))
         .route("/api/v1/series", get(handlers::api::list_series_api))
         .route("/api/v1/series/:slug", get(handlers::api::get_series_api))
         .route("/api/v1/media", get(handlers::api::list_media_api))
         .route("/api/v1/site", get(handlers::api::site_info))
+        // Sync, for `pebble sync push/pull`
+        .route("/api/v1/sync/snapshot", get(handlers::api::sync_snapshot))
+        .route(
+            "/api/v1/sync/media/:filename",
+            get(handlers::api::sync_media_download)
+                .put(handlers::api::sync_media_upload)
+                .layer(DefaultBodyLimit::max(100 * 1024 * 1024)),
+        )
+        .route(
+            "/api/v1/sync/plan",
+            post(handlers::api::sync_plan).layer(DefaultBodyLimit::max(64 * 1024 * 1024)),
+        )
+        .route(
+            "/api/v1/sync/apply",
+            post(handlers::api::sync_apply).layer(DefaultBodyLimit::max(64 * 1024 * 1024)),
+        )
 }
 
 async fn admin_not_available() -> impl IntoResponse {
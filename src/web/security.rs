@@ -1,6 +1,7 @@
+use crate::web::client_ip::ClientIp;
 use crate::web::state::AppState;
 use axum::body::Body;
-use axum::extract::{ConnectInfo, State};
+use axum::extract::State;
 use axum::http::header::HeaderValue;
 use axum::http::{header, Method, Request, Response, StatusCode};
 use axum::middleware::Next;
@@ -8,7 +9,6 @@ use axum::response::IntoResponse;
 use axum_extra::extract::CookieJar;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
-use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
@@ -26,6 +26,11 @@ static HEADER_CSP_PUBLIC: &str = "default-src 'self'; script-src 'self'; style-s
 // Admin pages: relaxed script-src to allow inline scripts in admin templates
 static HEADER_CSP_ADMIN: &str = "default-src 'self'; script-src 'self' 'unsafe-inline'; style-src 'self' 'unsafe-inline'; img-src 'self' data: blob:; font-src 'self'; frame-ancestors 'none'; base-uri 'self'; form-action 'self'; object-src 'none'";
 
+/// Response extension listing the third-party origins a page embeds. The
+/// security headers middleware adds them to the public CSP as `frame-src`.
+#[derive(Debug, Clone)]
+pub struct FrameSources(pub Vec<String>);
+
 pub struct RateLimiter {
     attempts: RwLock<HashMap<String, Vec<Instant>>>,
     max_attempts: usize,
@@ -135,12 +140,18 @@ pub async fn apply_security_headers(request: Request<Body>, next: Next) -> Respo
     headers.insert(header::STRICT_TRANSPORT_SECURITY, HEADER_HSTS.clone());
 
     if !headers.contains_key(header::CONTENT_SECURITY_POLICY) {
-        let csp = if is_admin {
-            HEADER_CSP_ADMIN
-        } else {
-            HEADER_CSP_PUBLIC
+        let frame_sources = response
+            .extensions()
+            .get::<FrameSources>()
+            .filter(|f| !f.0.is_empty())
+            .map(|f| f.0.join(" "));
+        let headers = response.headers_mut();
+        let csp = match (is_admin, frame_sources) {
+            (true, _) => HEADER_CSP_ADMIN.to_string(),
+            (false, Some(origins)) => format!("{}; frame-src {}", HEADER_CSP_PUBLIC, origins),
+            (false, None) => HEADER_CSP_PUBLIC.to_string(),
         };
-        if let Ok(val) = HeaderValue::from_str(csp) {
+        if let Ok(val) = HeaderValue::from_str(&csp) {
             headers.insert(header::CONTENT_SECURITY_POLICY, val);
         }
     }
@@ -152,7 +163,7 @@ pub async fn apply_security_headers(request: Request<Body>, next: Next) -> Respo
 /// Keyed by session cookie so legitimate multi-user setups aren't penalized.
 pub async fn write_rate_limit_middleware(
     State(state): State<Arc<AppState>>,
-    connect_info: Option<ConnectInfo<SocketAddr>>,
+    client_ip: ClientIp,
     request: Request<Body>,
     next: Next,
 ) -> Response<Body> {
@@ -173,12 +184,7 @@ pub async fn write_rate_limit_middleware(
     let key = cookies
         .get("session")
         .map(|c| format!("write:{}", c.value()))
-        .unwrap_or_else(|| {
-            let ip = connect_info
-                .map(|ci| ci.0.ip().to_string())
-                .unwrap_or_else(|| "unknown".to_string());
-            format!("write:{}", ip)
-        });
+        .unwrap_or_else(|| format!("write:{}", client_ip.key()));
 
     if !state.write_rate_limiter.check(&key) {
         return (
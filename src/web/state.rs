@@ -1,18 +1,28 @@
 use crate::services::analytics::Analytics;
+use crate::services::content_types;
+use crate::services::highlight;
+use crate::services::i18n::{self, Catalog, LanguageLink};
 use crate::services::markdown::MarkdownRenderer;
+use crate::services::metrics::Metrics;
+use crate::services::seo::{self, Crumb, SeoMeta};
+use crate::services::shortcodes::{self, ShortcodeRegistry};
+use crate::services::social_card;
+use crate::web::client_ip::ClientIpResolver;
 use crate::web::security::{CsrfManager, RateLimiter};
+use crate::models::{ContentType, ContentWithTags};
 use crate::{Config, Database};
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
-use tera::{Tera, Value};
+use tera::{Context, Tera, Value};
 
 pub struct AppState {
     pub config: RwLock<Config>,
     pub config_path: PathBuf,
     pub db: Database,
     pub templates: Tera,
+    /// Renders with this site's shortcodes and heading anchor setting.
     pub markdown: MarkdownRenderer,
     pub media_dir: PathBuf,
     pub production_mode: bool,
@@ -22,8 +32,27 @@ pub struct AppState {
     /// Rate limiter for all write endpoints (content create/update/delete, settings, tags, users).
     /// 30 write operations per 60 seconds, 5-minute lockout.
     pub write_rate_limiter: Arc<RateLimiter>,
+    /// Rate limiter for public comment submissions, keyed by client IP.
+    /// 5 comments per 10 minutes, 15-minute lockout.
+    pub comment_rate_limiter: Arc<RateLimiter>,
+    /// Rate limiter for incoming Webmentions and Pingbacks, keyed by client IP.
+    /// 20 notifications per 10 minutes, 15-minute lockout.
+    pub webmention_rate_limiter: Arc<RateLimiter>,
+    /// Rate limiter for public newsletter sign-ups, keyed by client IP.
+    /// 5 sign-ups per 10 minutes, 15-minute lockout.
+    pub newsletter_rate_limiter: Arc<RateLimiter>,
+    /// Trusted proxies and forwarding header, parsed from `[server]` at startup.
+    pub client_ip: ClientIpResolver,
     pub analytics: Option<Arc<Analytics>>,
+    /// Counters served at `/metrics`, shared with the site's background tasks.
+    pub metrics: Arc<Metrics>,
     pub static_assets: HashMap<String, &'static str>,
+    /// Code highlighting stylesheet generated from the configured themes.
+    pub highlight_css: String,
+    /// UI string catalogs for the default and additional languages, loaded at startup.
+    pub catalogs: HashMap<String, Catalog>,
+    /// This site's shortcodes, listed on the admin shortcodes page.
+    pub shortcodes: Arc<ShortcodeRegistry>,
 }
 
 impl AppState {
@@ -82,6 +111,10 @@ impl AppState {
                 "admin/pages/form.html",
                 include_str!("../../templates/admin/pages/form.html"),
             ),
+            (
+                "admin/translations.html",
+                include_str!("../../templates/admin/translations.html"),
+            ),
             (
                 "admin/media/index.html",
                 include_str!("../../templates/admin/media/index.html"),
@@ -106,6 +139,10 @@ impl AppState {
                 "public/posts.html",
                 include_str!("../../templates/public/posts.html"),
             ),
+            (
+                "public/toc.html",
+                include_str!("../../templates/public/toc.html"),
+            ),
             (
                 "public/post.html",
                 include_str!("../../templates/public/post.html"),
@@ -142,6 +179,10 @@ impl AppState {
                 "htmx/flash.html",
                 include_str!("../../templates/htmx/flash.html"),
             ),
+            (
+                "htmx/check.html",
+                include_str!("../../templates/htmx/check.html"),
+            ),
             (
                 "htmx/analytics_realtime.html",
                 include_str!("../../templates/htmx/analytics_realtime.html"),
@@ -194,10 +235,30 @@ impl AppState {
                 "admin/snippets/form.html",
                 include_str!("../../templates/admin/snippets/form.html"),
             ),
+            (
+                "admin/shortcodes.html",
+                include_str!("../../templates/admin/shortcodes.html"),
+            ),
             (
                 "public/series.html",
                 include_str!("../../templates/public/series.html"),
             ),
+            (
+                "public/entries.html",
+                include_str!("../../templates/public/entries.html"),
+            ),
+            (
+                "public/entry.html",
+                include_str!("../../templates/public/entry.html"),
+            ),
+            (
+                "admin/entries/index.html",
+                include_str!("../../templates/admin/entries/index.html"),
+            ),
+            (
+                "admin/entries/form.html",
+                include_str!("../../templates/admin/entries/form.html"),
+            ),
             (
                 "admin/tokens/index.html",
                 include_str!("../../templates/admin/tokens/index.html"),
@@ -210,15 +271,84 @@ impl AppState {
                 "admin/webhooks/deliveries.html",
                 include_str!("../../templates/admin/webhooks/deliveries.html"),
             ),
+            (
+                "admin/comments/index.html",
+                include_str!("../../templates/admin/comments/index.html"),
+            ),
+            (
+                "admin/webmentions/index.html",
+                include_str!("../../templates/admin/webmentions/index.html"),
+            ),
+            (
+                "admin/links/index.html",
+                include_str!("../../templates/admin/links/index.html"),
+            ),
+            (
+                "admin/newsletter/index.html",
+                include_str!("../../templates/admin/newsletter/index.html"),
+            ),
+            (
+                "admin/newsletter/issue.html",
+                include_str!("../../templates/admin/newsletter/issue.html"),
+            ),
+            (
+                "public/newsletter_form.html",
+                include_str!("../../templates/public/newsletter_form.html"),
+            ),
+            (
+                "public/newsletter.html",
+                include_str!("../../templates/public/newsletter.html"),
+            ),
+            (
+                "email/newsletter.html",
+                include_str!("../../templates/email/newsletter.html"),
+            ),
+            (
+                "email/newsletter.txt",
+                include_str!("../../templates/email/newsletter.txt"),
+            ),
+            (
+                "email/confirm.html",
+                include_str!("../../templates/email/confirm.html"),
+            ),
+            (
+                "email/confirm.txt",
+                include_str!("../../templates/email/confirm.txt"),
+            ),
         ])?;
 
+        // Site-provided templates for custom content types
+        for def in &config.content_types {
+            if let Some(path) = &def.list_template {
+                let source = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read list template {}", path))?;
+                templates.add_raw_template(&content_types::list_template_name(def), &source)?;
+            }
+            if let Some(path) = &def.detail_template {
+                let source = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read detail template {}", path))?;
+                templates.add_raw_template(&content_types::detail_template_name(def), &source)?;
+            }
+        }
+
+        let site_shortcodes = Arc::new(shortcodes::load_site_shortcodes(&config)?);
+        let markdown = MarkdownRenderer::with_shortcodes(site_shortcodes.clone())
+            .with_heading_anchors(config.content.heading_anchors);
+
+        let highlight_css = highlight::stylesheet(&config.content)?;
         let media_dir = PathBuf::from(&config.media.upload_dir);
+        let client_ip = ClientIpResolver::from_config(&config.server)?;
+        let catalogs = i18n::load_catalogs(&config.site.language, &config.i18n)?;
 
         let mut static_assets = HashMap::new();
         static_assets.insert(
             "theme.js".to_string(),
             include_str!("../../templates/js/theme.js"),
         );
+        static_assets.insert(
+            "embeds.js".to_string(),
+            include_str!("../../templates/js/embeds.js"),
+        );
         static_assets.insert(
             "admin.js".to_string(),
             include_str!("../../templates/js/admin.js"),
@@ -233,7 +363,7 @@ impl AppState {
             config_path,
             db,
             templates,
-            markdown: MarkdownRenderer::new(),
+            markdown,
             media_dir,
             production_mode,
             csrf: Arc::new(CsrfManager::default()),
@@ -248,11 +378,145 @@ impl AppState {
                 std::time::Duration::from_secs(60),
                 std::time::Duration::from_secs(300),
             )),
+            comment_rate_limiter: Arc::new(RateLimiter::new(
+                5,
+                std::time::Duration::from_secs(600),
+                std::time::Duration::from_secs(900),
+            )),
+            webmention_rate_limiter: Arc::new(RateLimiter::new(
+                20,
+                std::time::Duration::from_secs(600),
+                std::time::Duration::from_secs(900),
+            )),
+            newsletter_rate_limiter: Arc::new(RateLimiter::new(
+                5,
+                std::time::Duration::from_secs(600),
+                std::time::Duration::from_secs(900),
+            )),
+            client_ip,
             analytics: None,
+            metrics: Arc::new(Metrics::new()),
             static_assets,
+            highlight_css,
+            catalogs,
+            shortcodes: site_shortcodes,
         })
     }
 
+    /// Insert the language context for public templates: the current
+    /// language, its URL prefix and UI strings, the language switcher and the
+    /// `hreflang` alternates. `alternates` maps language codes to this page's
+    /// path in that language; the switcher sends other languages home.
+    pub fn localize(&self, ctx: &mut Context, lang: &str, alternates: &[(String, String)]) {
+        let config = self.config();
+        let default = config.site.language.as_str();
+        let link = |code: &str, path: String| LanguageLink {
+            code: code.to_string(),
+            name: self
+                .catalogs
+                .get(code)
+                .and_then(|c| c.get("language_name"))
+                .cloned()
+                .unwrap_or_else(|| code.to_string()),
+            path,
+            current: code == lang,
+        };
+
+        let languages: Vec<LanguageLink> = if config.i18n.languages.is_empty() {
+            Vec::new()
+        } else {
+            i18n::site_languages(default, &config.i18n)
+                .iter()
+                .map(|code| {
+                    let path = alternates
+                        .iter()
+                        .find(|(c, _)| c == code)
+                        .map(|(_, p)| p.clone())
+                        .unwrap_or_else(|| {
+                            let prefix = i18n::language_prefix(Some(code), default);
+                            if prefix.is_empty() {
+                                "/".to_string()
+                            } else {
+                                prefix
+                            }
+                        });
+                    link(code, path)
+                })
+                .collect()
+        };
+        let hreflang: Vec<LanguageLink> = if alternates.len() > 1 {
+            alternates
+                .iter()
+                .map(|(code, path)| link(code, path.clone()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let catalog = self
+            .catalogs
+            .get(lang)
+            .or_else(|| self.catalogs.get(default))
+            .cloned()
+            .unwrap_or_default();
+        let prefix = i18n::language_prefix(Some(lang), default);
+        let home = if prefix.is_empty() { "/" } else { prefix.as_str() };
+        ctx.insert("lang", lang);
+        ctx.insert("lang_home", home);
+        ctx.insert("lang_prefix", &prefix);
+        ctx.insert("i18n", &catalog);
+        ctx.insert("languages", &languages);
+        ctx.insert("alternates", &hreflang);
+    }
+
+    /// SEO metadata for a published item served at `path`. Breadcrumbs run
+    /// from the home page of the item's language through its listing page.
+    pub fn content_seo(&self, item: &ContentWithTags, base_url: &str, path: &str) -> SeoMeta {
+        let config = self.config();
+        let default = config.site.language.as_str();
+        let lang = i18n::route_language(item.content.language.as_deref(), &config.i18n)
+            .unwrap_or(default);
+        let prefix = i18n::language_prefix(Some(lang), default);
+        let home = if prefix.is_empty() { "/" } else { prefix.as_str() };
+
+        let crumbs: Vec<Crumb> = match item.content.content_type {
+            ContentType::Post => {
+                let name = self
+                    .catalogs
+                    .get(lang)
+                    .or_else(|| self.catalogs.get(default))
+                    .and_then(|c| c.get("nav_posts"))
+                    .cloned()
+                    .unwrap_or_else(|| "Posts".to_string());
+                vec![Crumb {
+                    name,
+                    path: format!("{}/posts", prefix),
+                }]
+            }
+            ContentType::Entry => item
+                .content
+                .entry_type
+                .as_deref()
+                .and_then(|name| config.content_types.iter().find(|t| t.name == name))
+                .map(|def| {
+                    vec![Crumb {
+                        name: def.label().to_string(),
+                        path: format!("/{}", def.prefix()),
+                    }]
+                })
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let card = (config.social_cards.enabled && social_card::needs_card(item)).then(|| {
+            format!(
+                "{}{}",
+                base_url.trim_end_matches('/'),
+                social_card::card_path(&item.content.slug)
+            )
+        });
+        seo::content_seo(item, &config.site, base_url, path, home, &crumbs, card)
+    }
+
     /// Get a read lock on the config
     pub fn config(&self) -> std::sync::RwLockReadGuard<'_, Config> {
         self.config.read().unwrap_or_else(|e| e.into_inner())
@@ -329,6 +593,11 @@ impl AppState {
         self.analytics = Some(analytics);
         self
     }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
 }
 
 fn format_date_filter(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
@@ -0,0 +1,452 @@
+//! HTTPS for `pebble deploy`, so a single server needs no reverse proxy.
+//! The certificate comes from `tls.cert_file`/`tls.key_file`, reloaded when
+//! the files change, or from an ACME certificate authority, renewed in the
+//! background before it expires. A plain HTTP listener on
+//! `tls.redirect_listen` answers ACME HTTP-01 challenges and redirects every
+//! other request to HTTPS.
+
+use super::listener::{self, ListenAddr, Listener};
+use crate::config::TlsConfig;
+use crate::services::acme::{self, Challenges};
+use crate::Config;
+use anyhow::{bail, Context, Result};
+use axum::extract::{ConnectInfo, Path};
+use axum::http::{Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect};
+use axum::routing::get;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+/// How often the certificate is checked for renewal or changed files.
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The certificate being served; replaced in place on renewal.
+#[derive(Debug, Default)]
+pub struct CertStore {
+    current: RwLock<Option<(Arc<CertifiedKey>, DateTime<Utc>)>>,
+}
+
+impl CertStore {
+    /// Serve the given PEM certificate chain and key from now on. Returns
+    /// when the certificate expires.
+    pub fn install(&self, cert_pem: &[u8], key_pem: &[u8]) -> Result<DateTime<Utc>> {
+        let certs = CertificateDer::pem_slice_iter(cert_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid certificate PEM")?;
+        let leaf = certs.first().context("No certificate in the PEM file")?;
+        let expires = der_not_after(leaf).context("Could not read the certificate's expiry")?;
+        let key = PrivateKeyDer::from_pem_slice(key_pem).context("Invalid private key PEM")?;
+        let key = rustls::crypto::ring::sign::any_supported_type(&key)
+            .map_err(|e| anyhow::anyhow!("Unsupported private key: {}", e))?;
+        let certified = Arc::new(CertifiedKey::new(certs, key));
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Some((certified, expires));
+        Ok(expires)
+    }
+
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|(_, expires)| *expires)
+    }
+}
+
+impl ResolvesServerCert for CertStore {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|(key, _)| key.clone())
+    }
+}
+
+/// When the first certificate in a PEM file expires.
+pub fn certificate_expiry(cert_pem: &[u8]) -> Result<DateTime<Utc>> {
+    let cert = CertificateDer::pem_slice_iter(cert_pem)
+        .next()
+        .context("No certificate in the PEM file")?
+        .context("Invalid certificate PEM")?;
+    der_not_after(&cert).context("Could not read the certificate's expiry")
+}
+
+/// The `notAfter` time of a DER certificate.
+fn der_not_after(der: &[u8]) -> Option<DateTime<Utc>> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+}
+
+/// HTTPS set up for a site, with its background tasks.
+pub struct Https {
+    pub acceptor: TlsAcceptor,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Https {
+    /// Load or start obtaining the certificate, start the redirect listener
+    /// and the renewal task. Everything stops when `stop` turns true.
+    pub async fn start(config: &Config, stop: watch::Receiver<bool>) -> Result<Self> {
+        let tls = &config.tls;
+        let store = Arc::new(CertStore::default());
+        let challenges = Challenges::default();
+        let mut tasks = Vec::new();
+
+        if !tls.redirect_listen.is_empty() {
+            let listener = Listener::bind(&ListenAddr::parse(&tls.redirect_listen)?, None).await?;
+            tracing::info!("Redirecting HTTP to HTTPS on {}", listener.describe());
+            let app = redirect_app(&config.site.url, challenges.clone());
+            let stopped = stopped(stop.clone());
+            tasks.push(tokio::spawn(async move {
+                if let Err(e) = listener::serve(listener, app, stopped).await {
+                    tracing::error!("HTTP redirect listener failed: {}", e);
+                }
+            }));
+        }
+
+        if tls.uses_acme() {
+            let domains = tls.domains(&config.site.url);
+            if let Some(cert) = acme::Storage::new(&tls.storage_dir).load(&domains) {
+                match store.install(cert.cert_pem.as_bytes(), cert.key_pem.as_bytes()) {
+                    Ok(expires) => {
+                        tracing::info!("Using stored certificate, valid until {}", expires)
+                    }
+                    Err(e) => tracing::warn!("Ignoring the stored certificate: {}", e),
+                }
+            }
+            tasks.push(tokio::spawn(manage_acme(
+                tls.clone(),
+                domains,
+                store.clone(),
+                challenges,
+                stop,
+            )));
+        } else {
+            let (Some(cert_file), Some(key_file)) = (tls.cert_file.clone(), tls.key_file.clone())
+            else {
+                bail!("tls.cert_file and tls.key_file must be set together");
+            };
+            let expires = install_files(&store, &cert_file, &key_file)?;
+            tracing::info!("Using certificate {}, valid until {}", cert_file, expires);
+            tasks.push(tokio::spawn(watch_files(
+                cert_file,
+                key_file,
+                store.clone(),
+                stop,
+            )));
+        }
+
+        let mut server_config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(store);
+        server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(server_config)),
+            tasks,
+        })
+    }
+
+    /// Wait for the background tasks after the stop signal.
+    pub async fn stopped(self) {
+        for task in self.tasks {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn stopped(mut stop: watch::Receiver<bool>) {
+    let _ = stop.wait_for(|stop| *stop).await;
+}
+
+fn install_files(store: &CertStore, cert_file: &str, key_file: &str) -> Result<DateTime<Utc>> {
+    let cert = std::fs::read(cert_file).with_context(|| format!("Failed to read {}", cert_file))?;
+    let key = std::fs::read(key_file).with_context(|| format!("Failed to read {}", key_file))?;
+    store
+        .install(&cert, &key)
+        .with_context(|| format!("Invalid certificate in {}", cert_file))
+}
+
+/// Reload the certificate files when they change, e.g. after certbot renewed them.
+async fn watch_files(
+    cert_file: String,
+    key_file: String,
+    store: Arc<CertStore>,
+    mut stop: watch::Receiver<bool>,
+) {
+    let modified = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last: Option<SystemTime> = modified(&cert_file);
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(FILE_CHECK_INTERVAL) => {}
+            _ = stop.wait_for(|stop| *stop) => return,
+        }
+        let current = modified(&cert_file);
+        if current == last {
+            continue;
+        }
+        last = current;
+        match install_files(&store, &cert_file, &key_file) {
+            Ok(expires) => tracing::info!(
+                "Reloaded certificate {}, valid until {}",
+                cert_file,
+                expires
+            ),
+            Err(e) => tracing::error!("Keeping the current certificate: {:#}", e),
+        }
+    }
+}
+
+/// Obtain a certificate when there is none or it is due for renewal, then
+/// check again twice a day. Failures are retried with a growing delay.
+async fn manage_acme(
+    config: TlsConfig,
+    domains: Vec<String>,
+    store: Arc<CertStore>,
+    challenges: Challenges,
+    mut stop: watch::Receiver<bool>,
+) {
+    let storage = acme::Storage::new(&config.storage_dir);
+    let renew_before = chrono::Duration::days(config.renew_days as i64);
+    let mut failures = 0u32;
+    loop {
+        let due = store
+            .expires()
+            .map_or(true, |expires| expires - renew_before <= Utc::now());
+        if due {
+            tracing::info!(
+                "Requesting a certificate for {} from {}",
+                domains.join(", "),
+                config.acme_directory
+            );
+            let result = async {
+                let cert = acme::obtain(&config, &domains, &challenges).await?;
+                let expires = store.install(cert.cert_pem.as_bytes(), cert.key_pem.as_bytes())?;
+                storage.save(&domains, &cert)?;
+                Ok::<_, anyhow::Error>(expires)
+            }
+            .await;
+            match result {
+                Ok(expires) => {
+                    failures = 0;
+                    tracing::info!("Installed a new certificate, valid until {}", expires);
+                }
+                Err(e) => {
+                    failures += 1;
+                    tracing::error!("Could not obtain a certificate: {:#}", e);
+                }
+            }
+        }
+
+        let delay = if failures > 0 {
+            Duration::from_secs(60 << failures.min(6))
+        } else {
+            CHECK_INTERVAL
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = stop.wait_for(|stop| *stop) => return,
+        }
+    }
+}
+
+/// The plain HTTP site: ACME challenge responses, and a permanent redirect
+/// to the HTTPS origin of `site.url` for everything else.
+fn redirect_app(site_url: &str, challenges: Challenges) -> Router {
+    let origin = match url::Url::parse(site_url) {
+        Ok(url) if url.scheme() == "https" => url.origin().ascii_serialization(),
+        Ok(url) => format!("https://{}", url.host_str().unwrap_or("localhost")),
+        Err(_) => "https://localhost".to_string(),
+    };
+
+    Router::new()
+        .route(
+            "/.well-known/acme-challenge/:token",
+            get(move |Path(token): Path<String>| async move {
+                let challenges = challenges.read().unwrap_or_else(|e| e.into_inner());
+                match challenges.get(&token) {
+                    Some(key_authorization) => key_authorization.clone().into_response(),
+                    None => StatusCode::NOT_FOUND.into_response(),
+                }
+            }),
+        )
+        .fallback(move |uri: Uri| async move {
+            let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+            Redirect::permanent(&format!("{}{}", origin, path))
+        })
+}
+
+/// Serve `app` over TLS on `listener` until `shutdown` completes.
+pub async fn serve(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    use hyper::body::Incoming;
+    use hyper::server::conn::http1;
+    use hyper_util::rt::TokioIo;
+    use hyper_util::server::graceful::GracefulShutdown;
+    use hyper_util::service::TowerToHyperService;
+
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Failed to accept connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let app = app.clone().map_request(move |mut request: Request<Incoming>| {
+                    request.extensions_mut().insert(ConnectInfo::<SocketAddr>(peer));
+                    request
+                });
+                let watcher = graceful.watcher();
+                tokio::spawn(async move {
+                    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(e)) => {
+                            tracing::debug!("TLS handshake with {} failed: {}", peer, e);
+                            return;
+                        }
+                        Err(_) => return,
+                    };
+                    let connection = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app));
+                    if let Err(e) = watcher.watch(connection).await {
+                        tracing::debug!("Connection error: {}", e);
+                    }
+                });
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_signed(days: i64) -> (String, String) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec!["example.test".to_string()]).unwrap();
+        params.not_after = time::OffsetDateTime::now_utc() + time::Duration::days(days);
+        let cert = params.self_signed(&key).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    #[test]
+    fn test_certificate_expiry() {
+        let (cert, key) = self_signed(40);
+        let expires = certificate_expiry(cert.as_bytes()).unwrap();
+        let days = (expires - Utc::now()).num_days();
+        assert!((39..=40).contains(&days), "{} days", days);
+
+        let store = CertStore::default();
+        assert!(store.expires().is_none());
+        assert_eq!(
+            store.install(cert.as_bytes(), key.as_bytes()).unwrap(),
+            expires
+        );
+        assert_eq!(store.expires(), Some(expires));
+
+        assert!(certificate_expiry(b"not a certificate").is_err());
+        assert!(store.install(cert.as_bytes(), b"no key").is_err());
+
+        // Dates from 2050 on are GeneralizedTime rather than UTCTime
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec!["example.test".to_string()]).unwrap();
+        params.not_after = rcgen::date_time_ymd(2061, 3, 4);
+        let far = params.self_signed(&key).unwrap();
+        assert_eq!(
+            der_not_after(far.der()).unwrap().to_rfc3339(),
+            "2061-03-04T00:00:00+00:00"
+        );
+
+        // Truncated and malformed DER
+        let der = far.der().to_vec();
+        assert!(der_not_after(&der[..der.len() / 2]).is_none());
+        assert!(der_not_after(&[0x30, 0x85, 1, 2, 3, 4, 5]).is_none());
+        assert!(der_not_after(&[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redirect_app() {
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        let challenges = Challenges::default();
+        challenges
+            .write()
+            .unwrap()
+            .insert("tok".to_string(), "tok.thumb".to_string());
+        let app = redirect_app("https://example.test/blog/", challenges);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/.well-known/acme-challenge/tok")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"tok.thumb");
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/.well-known/acme-challenge/other")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .oneshot(
+                Request::get("/posts/hello?x=1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()["location"],
+            "https://example.test/posts/hello?x=1"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Name-based virtual hosting: several sites served from one listener, each
+//! request routed by its `Host` header to the site's own router and state.
+
+use super::state::AppState;
+use crate::services::analytics::{run_aggregation_job, Analytics, AnalyticsConfig};
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tower::ServiceExt;
+
+/// A site loaded into the host, with the background tasks it owns. The
+/// tasks are stopped when the last reference to the site is dropped, i.e.
+/// once it has been removed and its in-flight requests have finished.
+pub struct HostedSite {
+    pub name: String,
+    /// Host names routed to this site, lowercase and without port
+    pub hosts: Vec<String>,
+    router: Router,
+    shutdown_tx: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl HostedSite {
+    /// Wrap a site's state in its router. `tasks` are the site's background
+    /// tasks, which must stop when `shutdown_tx` is signalled.
+    pub fn new(
+        name: String,
+        hosts: Vec<String>,
+        state: AppState,
+        shutdown_tx: watch::Sender<bool>,
+        mut tasks: Vec<JoinHandle<()>>,
+    ) -> Self {
+        let analytics = Arc::new(Analytics::with_config(
+            state.db.clone(),
+            AnalyticsConfig::default(),
+        ));
+        tasks.push(tokio::spawn(run_aggregation_job(analytics.clone())));
+
+        let state = state.with_analytics(analytics);
+        let content_types = state.config().content_types.clone();
+        let production = state.production_mode;
+        let state = Arc::new(state);
+        let router = if production {
+            super::production_app(state, &content_types)
+        } else {
+            super::site_app(state, &content_types)
+        };
+
+        Self {
+            name,
+            hosts,
+            router,
+            shutdown_tx,
+            tasks,
+        }
+    }
+}
+
+impl Drop for HostedSite {
+    fn drop(&mut self) {
+        let _ = self.shutdown_tx.send(true);
+        for task in &self.tasks {
+            task.abort();
+        }
+        tracing::info!("Stopped hosting site '{}'", self.name);
+    }
+}
+
+/// The sites currently served, looked up by host name.
+#[derive(Default)]
+pub struct VirtualHosts {
+    by_host: RwLock<HashMap<String, Arc<HostedSite>>>,
+}
+
+impl VirtualHosts {
+    /// Replace the served sites. A host claimed by more than one site goes
+    /// to the first one listed.
+    pub fn set_sites(&self, sites: &[Arc<HostedSite>]) {
+        let mut by_host = HashMap::new();
+        for site in sites {
+            for host in &site.hosts {
+                if let Some(first) = by_host.get(host) {
+                    let first: &Arc<HostedSite> = first;
+                    tracing::warn!(
+                        "Host '{}' is claimed by both '{}' and '{}'; serving it from '{}'",
+                        host,
+                        first.name,
+                        site.name,
+                        first.name
+                    );
+                    continue;
+                }
+                by_host.insert(host.clone(), site.clone());
+            }
+        }
+        *self.by_host.write().unwrap_or_else(|e| e.into_inner()) = by_host;
+    }
+
+    pub fn site_for_host(&self, host: &str) -> Option<Arc<HostedSite>> {
+        self.by_host
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(host)
+            .cloned()
+    }
+}
+
+/// The host a request was sent to: the `Host` header, or the URI authority
+/// for HTTP/2, lowercase and without port.
+fn request_host(request: &Request<Body>) -> Option<String> {
+    let raw = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| request.uri().authority().map(|a| a.as_str()))?;
+    let host = match raw.strip_prefix('[') {
+        // IPv6 literal, e.g. "[::1]:3000"
+        Some(rest) => rest.split(']').next()?,
+        None => raw.split(':').next()?,
+    };
+    Some(host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+async fn dispatch(State(hosts): State<Arc<VirtualHosts>>, request: Request<Body>) -> Response {
+    let Some(site) = request_host(&request).and_then(|host| hosts.site_for_host(&host)) else {
+        return (StatusCode::NOT_FOUND, "No site is served at this address").into_response();
+    };
+
+    match site.router.clone().oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
+
+/// Serve `hosts` on `addr` until SIGTERM or Ctrl+C.
+pub async fn serve(hosts: Arc<VirtualHosts>, addr: &str) -> Result<()> {
+    let app = Router::new().fallback(dispatch).with_state(hosts);
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Host listening on {}", addr);
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(super::shutdown_signal())
+    .await?;
+    tracing::info!("Host shut down gracefully");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_host() {
+        let request = |host: &str| {
+            Request::builder()
+                .uri("/posts")
+                .header(header::HOST, host)
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert_eq!(
+            request_host(&request("Blog.Example.com:8080")).as_deref(),
+            Some("blog.example.com")
+        );
+        assert_eq!(
+            request_host(&request("example.com.")).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(request_host(&request("[::1]:3000")).as_deref(), Some("::1"));
+
+        let http2 = Request::builder()
+            .uri("https://docs.example.com/")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_host(&http2).as_deref(), Some("docs.example.com"));
+    }
+}
@@ -0,0 +1,20 @@
+(function () {
+    var allowed = /^https:\/\/(www\.youtube-nocookie\.com|player\.vimeo\.com|gist\.github\.com|codepen\.io)\/|^https:\/\/[^\/]+\/@[^\/]+\/\d+\/embed$/;
+    document.addEventListener("click", function (e) {
+        var link = e.target.closest && e.target.closest(".embed-facade");
+        if (!link) return;
+        var figure = link.closest("figure.embed"),
+            src = figure && figure.getAttribute("data-embed-src");
+        if (!src || !allowed.test(src)) return;
+        e.preventDefault();
+        var frame = document.createElement("iframe");
+        frame.src = src;
+        frame.title = figure.getAttribute("data-embed-title") || "Embedded content";
+        frame.className = "embed-frame";
+        frame.setAttribute("allow", "autoplay; encrypted-media; fullscreen; picture-in-picture");
+        frame.setAttribute("allowfullscreen", "");
+        frame.setAttribute("referrerpolicy", "strict-origin-when-cross-origin");
+        link.replaceWith(frame);
+        figure.classList.add("embed-loaded");
+    });
+})();
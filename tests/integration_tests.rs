@@ -1,4 +1,5 @@
 use pebble_cms::models::{ContentStatus, ContentType, UserRole};
+use pebble_cms::services::markdown::MarkdownRenderer;
 use pebble_cms::services::{auth, content, database, search, settings, tags};
 use pebble_cms::Database;
 
@@ -13,6 +14,10 @@ fn create_test_db() -> Database {
     db
 }
 
+fn renderer() -> MarkdownRenderer {
+    MarkdownRenderer::new()
+}
+
 // Valid test passwords that meet requirements: 8+ chars, uppercase, lowercase, number
 const TEST_PASSWORD: &str = "Password123";
 const WRONG_PASSWORD: &str = "WrongPass456";
@@ -339,6 +344,8 @@ mod content_integration_tests {
             scheduled_at: None,
             tags: vec![],
             metadata: None,
+            entry_type: None,
+            fields: None,
         }
     }
 
@@ -347,7 +354,7 @@ mod content_integration_tests {
         let db = create_test_db();
 
         let input = create_test_post("Test Post");
-        let content_id = content::create_content(&db, input, None, 200).unwrap();
+        let content_id = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         assert!(content_id > 0);
     }
@@ -357,7 +364,7 @@ mod content_integration_tests {
         let db = create_test_db();
 
         let input = create_test_post("Test Post");
-        let content_id = content::create_content(&db, input, None, 200).unwrap();
+        let content_id = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         let post = content::get_content_by_id(&db, content_id)
             .unwrap()
@@ -371,7 +378,7 @@ mod content_integration_tests {
         let db = create_test_db();
 
         let input = create_test_post("My Blog Post");
-        content::create_content(&db, input, None, 200).unwrap();
+        content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         let post = content::get_content_by_slug(&db, "my-blog-post")
             .unwrap()
@@ -386,7 +393,7 @@ mod content_integration_tests {
 
         for i in 1..=5 {
             let input = create_test_post(&format!("Post {}", i));
-            content::create_content(&db, input, None, 200).unwrap();
+            content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
         }
 
         let posts = content::list_content(&db, Some(ContentType::Post), None, 10, 0).unwrap();
@@ -399,7 +406,7 @@ mod content_integration_tests {
 
         for i in 1..=10 {
             let input = create_test_post(&format!("Post {}", i));
-            content::create_content(&db, input, None, 200).unwrap();
+            content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
         }
 
         // First page
@@ -417,12 +424,12 @@ mod content_integration_tests {
 
         // Create draft post
         let draft = create_test_post("Draft Post");
-        content::create_content(&db, draft, None, 200).unwrap();
+        content::create_content(&db, &renderer(), &[], draft, None, 200).unwrap();
 
         // Create published post
         let mut published = create_test_post("Published Post");
         published.status = ContentStatus::Published;
-        content::create_content(&db, published, None, 200).unwrap();
+        content::create_content(&db, &renderer(), &[], published, None, 200).unwrap();
 
         let published_posts =
             content::list_published_content(&db, ContentType::Post, 10, 0).unwrap();
@@ -436,7 +443,7 @@ mod content_integration_tests {
 
         for i in 1..=3 {
             let input = create_test_post(&format!("Post {}", i));
-            content::create_content(&db, input, None, 200).unwrap();
+            content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
         }
 
         let count = content::count_content(&db, Some(ContentType::Post), None).unwrap();
@@ -450,13 +457,13 @@ mod content_integration_tests {
         // Create 2 drafts
         for i in 1..=2 {
             let input = create_test_post(&format!("Draft {}", i));
-            content::create_content(&db, input, None, 200).unwrap();
+            content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
         }
 
         // Create 1 published
         let mut published = create_test_post("Published");
         published.status = ContentStatus::Published;
-        content::create_content(&db, published, None, 200).unwrap();
+        content::create_content(&db, &renderer(), &[], published, None, 200).unwrap();
 
         let draft_count =
             content::count_content(&db, Some(ContentType::Post), Some(ContentStatus::Draft))
@@ -474,7 +481,7 @@ mod content_integration_tests {
         let db = create_test_db();
 
         let input = create_test_post("Original Title");
-        let content_id = content::create_content(&db, input, None, 200).unwrap();
+        let content_id = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         let update = pebble_cms::models::UpdateContent {
             title: Some("Updated Title".to_string()),
@@ -486,9 +493,10 @@ mod content_integration_tests {
             scheduled_at: None,
             tags: None,
             metadata: None,
+            fields: None,
         };
 
-        content::update_content(&db, content_id, update, 200, None, 50).unwrap();
+        content::update_content(&db, &renderer(), &[], content_id, update, None, 50).unwrap();
 
         let updated = content::get_content_by_id(&db, content_id)
             .unwrap()
@@ -501,9 +509,9 @@ mod content_integration_tests {
         let db = create_test_db();
 
         let input = create_test_post("To Delete");
-        let content_id = content::create_content(&db, input, None, 200).unwrap();
+        let content_id = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
-        content::delete_content(&db, content_id).unwrap();
+        content::delete_content(&db, &renderer(), content_id).unwrap();
 
         let deleted = content::get_content_by_id(&db, content_id).unwrap();
         assert!(deleted.is_none());
@@ -524,9 +532,11 @@ mod content_integration_tests {
             scheduled_at: None,
             tags: vec![],
             metadata: None,
+            entry_type: None,
+            fields: None,
         };
 
-        content::create_content(&db, input, None, 200).unwrap();
+        content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         let page = content::get_content_by_slug(&db, "about-us")
             .unwrap()
@@ -552,9 +562,11 @@ mod content_integration_tests {
             scheduled_at: None,
             tags: vec!["Rust".to_string(), "Programming".to_string()],
             metadata: None,
+            entry_type: None,
+            fields: None,
         };
 
-        let content_id = content::create_content(&db, input, None, 200).unwrap();
+        let content_id = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         let post = content::get_content_by_id(&db, content_id)
             .unwrap()
@@ -579,9 +591,11 @@ mod content_integration_tests {
             scheduled_at: None,
             tags: vec![],
             metadata: None,
+            entry_type: None,
+            fields: None,
         };
 
-        let content_id = content::create_content(&db, input, None, 100).unwrap();
+        let content_id = content::create_content(&db, &renderer(), &[], input, None, 100).unwrap();
 
         let post = content::get_content_by_id(&db, content_id)
             .unwrap()
@@ -606,9 +620,11 @@ mod content_integration_tests {
             scheduled_at: None,
             tags: vec![],
             metadata: None,
+            entry_type: None,
+            fields: None,
         };
 
-        let result = content::create_content(&db, input, None, 200);
+        let result = content::create_content(&db, &renderer(), &[], input, None, 200);
         assert!(result.is_err());
     }
 
@@ -627,10 +643,12 @@ mod content_integration_tests {
             status: ContentStatus::Draft,
             scheduled_at: None,
             tags: vec![],
-            metadata: None, // No metadata provided
+            metadata: None, // No metadata provided,
+            entry_type: None,
+            fields: None,
         };
 
-        let content_id = content::create_content(&db, input, None, 200).unwrap();
+        let content_id = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         let page = content::get_content_by_id(&db, content_id)
             .unwrap()
@@ -666,9 +684,11 @@ mod content_integration_tests {
             scheduled_at: None,
             tags: vec![],
             metadata: Some(custom_metadata),
+            entry_type: None,
+            fields: None,
         };
 
-        let content_id = content::create_content(&db, input, None, 200).unwrap();
+        let content_id = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         let page = content::get_content_by_id(&db, content_id)
             .unwrap()
@@ -678,6 +698,93 @@ mod content_integration_tests {
         assert_eq!(page.content.metadata["custom_html"], "<div>Hello</div>");
         assert_eq!(page.content.metadata["meta_title"], "Custom Title");
     }
+
+    fn create_snippet(db: &Database, slug: &str, body: &str) -> i64 {
+        let mut input = create_test_post(slug);
+        input.slug = Some(slug.to_string());
+        input.content_type = ContentType::Snippet;
+        input.body_markdown = body.to_string();
+        input.status = ContentStatus::Published;
+        content::create_content(db, &renderer(), &[], input, None, 200).unwrap()
+    }
+
+    fn update_body(db: &Database, id: i64, body: &str) -> anyhow::Result<()> {
+        let input = pebble_cms::models::UpdateContent {
+            title: None,
+            slug: None,
+            body_markdown: Some(body.to_string()),
+            excerpt: None,
+            featured_image: None,
+            status: None,
+            scheduled_at: None,
+            tags: None,
+            metadata: None,
+            fields: None,
+        };
+        content::update_content(db, &renderer(), &[], id, input, None, 0)
+    }
+
+    fn body_html(db: &Database, id: i64) -> String {
+        content::get_content_by_id(db, id)
+            .unwrap()
+            .unwrap()
+            .content
+            .body_html
+    }
+
+    #[test]
+    fn test_nested_snippets_rerender_dependents() {
+        let db = create_test_db();
+
+        let inner = create_snippet(&db, "inner", "Inner text");
+        create_snippet(&db, "outer", "Outer [snippet slug=\"inner\"]");
+        let mut input = create_test_post("Host");
+        input.body_markdown = "Host [snippet slug=\"outer\"]".to_string();
+        let host = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
+
+        assert!(body_html(&db, host).contains("Outer Inner text"));
+        let uses = content::list_snippet_uses(&db, "inner").unwrap();
+        assert_eq!(uses.len(), 2, "outer and the host both use inner");
+
+        update_body(&db, inner, "Changed").unwrap();
+        assert!(body_html(&db, host).contains("Outer Changed"));
+    }
+
+    #[test]
+    fn test_snippet_cycle_rejected() {
+        let db = create_test_db();
+
+        let a = create_snippet(&db, "a", "A [snippet slug=\"b\"]");
+        create_snippet(&db, "b", "B");
+        let err = update_body(&db, a, "A [snippet slug=\"a\"]").unwrap_err();
+        assert!(err.to_string().contains("include itself"));
+
+        let mut input = create_test_post("C");
+        input.slug = Some("c".to_string());
+        input.content_type = ContentType::Snippet;
+        input.body_markdown = "C [snippet slug=\"a\"]".to_string();
+        content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
+        let b = content::get_content_by_slug(&db, "b").unwrap().unwrap();
+        let err = update_body(&db, b.content.id, "B [snippet slug=\"c\"]").unwrap_err();
+        assert!(err.to_string().contains("b → c → a → b"));
+    }
+
+    #[test]
+    fn test_snippet_created_and_deleted_after_use() {
+        let db = create_test_db();
+
+        let mut input = create_test_post("Early");
+        input.body_markdown = "Before [snippet slug=\"later\"]".to_string();
+        let host = content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
+        assert!(body_html(&db, host).contains("[snippet slug"));
+
+        let snippet = create_snippet(&db, "later", "Now here");
+        assert!(body_html(&db, host).contains("Before Now here"));
+        assert_eq!(content::snippet_use_counts(&db).unwrap()["later"], 1);
+
+        content::delete_content(&db, &renderer(), snippet).unwrap();
+        assert!(body_html(&db, host).contains("[snippet slug"));
+    }
 }
 
 mod settings_integration_tests {
@@ -803,8 +910,10 @@ mod search_integration_tests {
             scheduled_at: None,
             tags: vec![],
             metadata: None,
+            entry_type: None,
+            fields: None,
         };
-        content::create_content(&db, input, None, 200).unwrap();
+        content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         // Rebuild FTS index
         search::rebuild_fts_index(&db).unwrap();
@@ -830,8 +939,10 @@ mod search_integration_tests {
             scheduled_at: None,
             tags: vec![],
             metadata: None,
+            entry_type: None,
+            fields: None,
         };
-        content::create_content(&db, input, None, 200).unwrap();
+        content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         search::rebuild_fts_index(&db).unwrap();
 
@@ -856,8 +967,10 @@ mod search_integration_tests {
                 scheduled_at: None,
                 tags: vec![],
                 metadata: None,
+                entry_type: None,
+                fields: None,
             };
-            content::create_content(&db, input, None, 200).unwrap();
+            content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
         }
 
         let indexed = search::rebuild_fts_index(&db).unwrap();
@@ -880,8 +993,10 @@ mod search_integration_tests {
             scheduled_at: None,
             tags: vec![],
             metadata: None,
+            entry_type: None,
+            fields: None,
         };
-        content::create_content(&db, input, None, 200).unwrap();
+        content::create_content(&db, &renderer(), &[], input, None, 200).unwrap();
 
         search::rebuild_fts_index(&db).unwrap();
 
@@ -931,3 +1046,80 @@ mod database_integration_tests {
         assert_eq!(results[0], "ok");
     }
 }
+
+mod deploy_config_integration_tests {
+    use pebble_cms::services::deploy_config::{self, DeploySite, DeployTarget};
+    use pebble_cms::Config;
+    use std::path::{Path, PathBuf};
+
+    /// Compare generated files with `tests/golden/deploy_config/<case>/`.
+    /// Run with `UPDATE_GOLDEN=1` to rewrite the golden files after an
+    /// intended change, then review the diff.
+    fn check_golden(case: &str, target: DeployTarget) {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden/deploy_config")
+            .join(case);
+        let config = Config::load(&dir.join("pebble.toml")).unwrap();
+        let site = DeploySite {
+            name: case.to_string(),
+            site_dir: PathBuf::from(format!("/srv/{}", case)),
+            binary: PathBuf::from("/usr/local/bin/pebble"),
+            user: "pebble".to_string(),
+        };
+
+        for file in deploy_config::generate(target, &config, &site).unwrap() {
+            // Keep the golden files stable across version bumps
+            let contents = file
+                .contents
+                .replace(env!("CARGO_PKG_VERSION"), "{version}");
+            let golden = dir.join(&file.name);
+            if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                std::fs::write(&golden, &contents).unwrap();
+                continue;
+            }
+            let expected = std::fs::read_to_string(&golden)
+                .unwrap_or_else(|_| panic!("missing golden file {}", golden.display()));
+            assert_eq!(contents, expected, "{} differs from its golden file", file.name);
+        }
+    }
+
+    #[test]
+    fn test_systemd_unit() {
+        check_golden("field-notes", DeployTarget::Systemd);
+        check_golden("wiki", DeployTarget::Systemd);
+    }
+
+    #[test]
+    fn test_caddyfile() {
+        check_golden("field-notes", DeployTarget::Caddy);
+        check_golden("wiki", DeployTarget::Caddy);
+    }
+
+    #[test]
+    fn test_nginx_conf() {
+        check_golden("field-notes", DeployTarget::Nginx);
+        check_golden("wiki", DeployTarget::Nginx);
+    }
+
+    #[test]
+    fn test_docker_files() {
+        check_golden("field-notes", DeployTarget::Docker);
+        check_golden("wiki", DeployTarget::Docker);
+    }
+
+    #[test]
+    fn test_listen_socket() {
+        // server.listen on a Unix socket, and on a socket from systemd
+        check_golden("journal", DeployTarget::Systemd);
+        check_golden("journal", DeployTarget::Caddy);
+        check_golden("journal", DeployTarget::Nginx);
+        check_golden("handbook", DeployTarget::Systemd);
+        check_golden("handbook", DeployTarget::Nginx);
+    }
+
+    #[test]
+    fn test_unknown_target() {
+        assert!("apache".parse::<DeployTarget>().is_err());
+        assert_eq!("nginx".parse::<DeployTarget>().unwrap(), DeployTarget::Nginx);
+    }
+}